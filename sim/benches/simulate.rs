@@ -0,0 +1,95 @@
+//! Throughput of the core simulation loop on synthetic workloads of several million accesses, for a few cache
+//! configurations. Run with `cargo bench --bench simulate`; `cargo bench --bench simulate -- --save-baseline <file>`
+//! records the accesses per second of every workload, and `-- --baseline <file> [--tolerance <percent>]` fails if
+//! any of them got slower than the recorded one by more than the tolerance (default 10%).
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::process::ExitCode;
+
+use sim::{format_bench, run_bench, CacheBuilder, Replacement, SimError, DEFAULT_BENCH_LENGTH};
+
+// Runs of each configuration, keeping the fastest time of each workload to smooth out noise
+const RUNS: usize = 3;
+
+// Configurations timed, named s:E:b and policy
+fn configurations() -> Vec<(&'static str, CacheBuilder)> {
+    let cache = |s, e, b| CacheBuilder::new().set_index_bits(s).associativity(e).block_bits(b);
+    vec![
+        ("5:1:5 lru", cache(5, 1, 5)),
+        ("6:8:6 lru", cache(6, 8, 6)),
+        ("6:8:6 plru", cache(6, 8, 6).replacement(Replacement::Plru)),
+        ("10:16:6 lru", cache(10, 16, 6)),
+    ]
+}
+
+// Accesses per second of every workload of every configuration, named "<configuration>/<workload>"
+fn measure() -> Result<BTreeMap<String, f64>, SimError> {
+    let mut throughputs = BTreeMap::new();
+    for (name, builder) in configurations() {
+        let mut best = run_bench(&builder, DEFAULT_BENCH_LENGTH)?;
+        for _ in 1..RUNS {
+            for (best, result) in best.iter_mut().zip(run_bench(&builder, DEFAULT_BENCH_LENGTH)?) {
+                if result.elapsed < best.elapsed {
+                    *best = result;
+                }
+            }
+        }
+        println!("{}\n{}", name, format_bench(&best));
+        for result in best {
+            throughputs.insert(format!("{}/{}", name, result.workload), result.accesses_per_second());
+        }
+    }
+    Ok(throughputs)
+}
+
+// Workloads slower than the baseline by more than tolerance percent, with their old and new throughputs
+fn regressions(baseline: &BTreeMap<String, f64>, throughputs: &BTreeMap<String, f64>, tolerance: f64) -> Vec<(String, f64, f64)> {
+    throughputs.iter()
+        .filter_map(|(name, &now)| baseline.get(name).map(|&before| (name.clone(), before, now)))
+        .filter(|&(_, before, now)| now < before * (1.0 - tolerance / 100.0))
+        .collect()
+}
+
+fn run(args: &[String]) -> Result<bool, SimError> {
+    let (mut save, mut baseline, mut tolerance) = (None, None, 10.0);
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let mut value = |option: &str| args.next().cloned().ok_or_else(|| SimError::ParseArgs(format!("{} needs a value", option)));
+        match arg.as_str() {
+            "--save-baseline" => save = Some(value(arg)?),
+            "--baseline" => baseline = Some(value(arg)?),
+            "--tolerance" => tolerance = value(arg)?.parse().map_err(|e| SimError::ParseArgs(format!("invalid value for --tolerance ({})", e)))?,
+            // cargo bench passes --bench, and a filter nothing here uses
+            _ => {}
+        }
+    }
+    let throughputs = measure()?;
+    if let Some(path) = save {
+        let json = serde_json::to_string_pretty(&throughputs).map_err(|err| SimError::Io(err.to_string()))?;
+        fs::write(&path, json).map_err(|err| SimError::Io(format!("failed to write {} ({})", path, err)))?;
+    }
+    let Some(path) = baseline else { return Ok(true) };
+    let text = fs::read_to_string(&path).map_err(|err| SimError::Io(format!("failed to read {} ({})", path, err)))?;
+    let baseline: BTreeMap<String, f64> = serde_json::from_str(&text).map_err(|err| SimError::Io(format!("invalid baseline {} ({})", path, err)))?;
+    let regressions = regressions(&baseline, &throughputs, tolerance);
+    for (name, before, now) in &regressions {
+        println!("regression: {} went from {:.0} to {:.0} accesses/s ({:+.1}%)", name, before, now, 100.0 * (now - before) / before);
+    }
+    if regressions.is_empty() {
+        println!("no workload is more than {}% slower than {}", tolerance, path);
+    }
+    Ok(regressions.is_empty())
+}
+
+fn main() -> ExitCode {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    match run(&args) {
+        Ok(true) => ExitCode::SUCCESS,
+        Ok(false) => ExitCode::FAILURE,
+        Err(err) => {
+            eprintln!("Error: {}", err);
+            ExitCode::from(2)
+        }
+    }
+}
@@ -0,0 +1,12 @@
+//! Parse arbitrary bytes as one line of each trace format, which may fail but must not panic.
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use sim::TraceFormat;
+
+fuzz_target!(|data: &[u8]| {
+    for format in ["lackey", "din", "generic:pc,op,addr,size,tid:csv"] {
+        let format: TraceFormat = format.parse().unwrap();
+        let _ = format.parse_bytes(data);
+    }
+});
@@ -0,0 +1,16 @@
+//! Read arbitrary bytes as a whole trace, text, binary or compressed, and simulate its accesses on a small cache.
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use sim::{Cache, TraceAccesses};
+
+fuzz_target!(|data: &[u8]| {
+    let accesses = match TraceAccesses::from_bytes(data) {
+        Ok(accesses) => accesses,
+        Err(_) => return,
+    };
+    let mut cache = Cache::new(2, 2, 3).unwrap();
+    for access in accesses.lenient(true).flatten() {
+        let _ = cache.simulate_access(access);
+    }
+});
@@ -0,0 +1,51 @@
+/* C interface of the sim cache simulator, implemented in sim/src/ffi.rs. */
+
+#ifndef SIM_H
+#define SIM_H
+
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+/* Version of this interface, compare it with sim_abi_version() */
+#define SIM_ABI_VERSION 1
+
+/* Return codes */
+#define SIM_OK 0
+#define SIM_ERR_NULL (-1)   /* a pointer argument was null */
+#define SIM_ERR_ACCESS (-2) /* the operation isn't 'L', 'S' or 'M', or the access can't be simulated */
+
+/* An opaque cache */
+typedef struct SimCache SimCache;
+
+/* Hits, misses and evictions, of one access or of a whole run */
+typedef struct SimCounts {
+    uint64_t hits;
+    uint64_t misses;
+    uint64_t evictions;
+} SimCounts;
+
+uint32_t sim_abi_version(void);
+
+/* Create an empty cache of 2^s sets of e lines of 2^b bytes, or NULL if the configuration is invalid */
+SimCache *sim_cache_new(uint32_t s, uint32_t e, uint32_t b);
+
+/* Release a cache; NULL is ignored */
+void sim_cache_free(SimCache *cache);
+
+/* Simulate an access of size bytes; result may be NULL */
+int sim_cache_access(SimCache *cache, char operation, uint64_t address, uint32_t size, SimCounts *result);
+
+/* Store the hits, misses and evictions so far */
+int sim_cache_stats(const SimCache *cache, SimCounts *stats);
+
+/* Clear the statistics, keeping the cache contents */
+int sim_cache_reset_stats(SimCache *cache);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
@@ -0,0 +1,135 @@
+//! Measuring how many accesses per second the simulator handles on synthetic workloads, to compare machines and
+//! catch performance regressions.
+
+use std::time::{Duration, Instant};
+
+use crate::cache::{CacheBuilder, DEFAULT_SEED};
+use crate::error::SimError;
+use crate::generate::{Generator, Pattern};
+use crate::trace::{simulate_accesses, MemoryAccess};
+
+/// Accesses simulated per workload unless another number is given.
+pub const DEFAULT_BENCH_LENGTH: usize = 2_000_000;
+
+/// Time taken to simulate one workload, with its statistics so that runs can be checked against each other.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BenchResult {
+    pub workload: &'static str,
+    pub accesses: usize,
+    pub elapsed: Duration,
+    pub hits: usize,
+    pub misses: usize,
+    pub evictions: usize,
+}
+
+impl BenchResult {
+    /// Simulated accesses per second, 0 if no time could be measured.
+    pub fn accesses_per_second(&self) -> f64 {
+        let seconds = self.elapsed.as_secs_f64();
+        if seconds == 0.0 { 0.0 } else { self.accesses as f64 / seconds }
+    }
+}
+
+/// Workloads of length accesses sized for a cache with the given capacity in bytes and block size: a sequential
+/// stream and a block-strided sweep over four times the capacity, which mostly miss, and random accesses and a
+/// pointer chase over twice the capacity, which mix hits and misses. A quarter of the accesses are stores.
+pub fn bench_workloads(capacity: u64, block_size: u64, length: usize, seed: u64) -> Vec<(&'static str, Generator)> {
+    let workload = |pattern, footprint, stride| Generator { pattern, footprint, length, access_size: 8, stride, write_ratio: 0.25, seed };
+    vec![
+        ("sequential", workload(Pattern::Sequential, 4 * capacity, 0)),
+        ("strided", workload(Pattern::Strided, 4 * capacity, block_size)),
+        ("random", workload(Pattern::Random, 2 * capacity, 0)),
+        ("pointer-chase", workload(Pattern::PointerChase, 2 * capacity, block_size.max(8))),
+    ]
+}
+
+/// Simulate each workload of length accesses on a fresh cache built from the builder, with the same random
+/// addresses on every run. The accesses are generated before the clock starts, so only the simulation loop is timed.
+pub fn run_bench(builder: &CacheBuilder, length: usize) -> Result<Vec<BenchResult>, SimError> {
+    if length == 0 {
+        return Err(SimError::Config("a benchmark needs at least one access per workload".to_string()));
+    }
+    let cache = builder.build()?;
+    let block_size = cache.block_size() as u64;
+    let capacity = (cache.set_count() * cache.associativity()) as u64 * block_size;
+    let mut results = Vec::new();
+    for (workload, generator) in bench_workloads(capacity, block_size, length, DEFAULT_SEED) {
+        let accesses: Vec<MemoryAccess> = generator.accesses()?.collect();
+        let mut cache = builder.build()?;
+        let start = Instant::now();
+        simulate_accesses(&mut cache, accesses.into_iter().map(Ok), 0)?;
+        let elapsed = start.elapsed();
+        results.push(BenchResult { workload, accesses: length, elapsed, hits: cache.hits(), misses: cache.misses(), evictions: cache.evictions() });
+    }
+    Ok(results)
+}
+
+/// A table of the time and throughput of each workload, with a total over all of them.
+pub fn format_bench(results: &[BenchResult]) -> String {
+    let mut text = format!("{:<14}{:>12}{:>12}{:>16}{:>11}\n", "workload", "accesses", "seconds", "accesses/s", "miss-rate");
+    for result in results {
+        let miss_rate = 100.0 * result.misses as f64 / (result.hits + result.misses).max(1) as f64;
+        text.push_str(&format!(
+            "{:<14}{:>12}{:>12.3}{:>16.0}{:>10.2}%\n",
+            result.workload, result.accesses, result.elapsed.as_secs_f64(), result.accesses_per_second(), miss_rate
+        ));
+    }
+    let total = BenchResult {
+        workload: "total",
+        accesses: results.iter().map(|result| result.accesses).sum(),
+        elapsed: results.iter().map(|result| result.elapsed).sum(),
+        hits: 0,
+        misses: 0,
+        evictions: 0,
+    };
+    text.push_str(&format!("{:<14}{:>12}{:>12.3}{:>16.0}\n", total.workload, total.accesses, total.elapsed.as_secs_f64(), total.accesses_per_second()));
+    text
+}
+
+#[cfg(test)]
+use crate::cache::Replacement;
+
+// Tests for run_bench function
+#[test]
+fn test_run_bench() {
+    let builder = CacheBuilder::new().set_index_bits(4).associativity(2).block_bits(4);
+    let results = run_bench(&builder, 1000).unwrap();
+    let workloads: Vec<&str> = results.iter().map(|result| result.workload).collect();
+    assert_eq!(workloads, ["sequential", "strided", "random", "pointer-chase"]);
+    for result in &results {
+        assert_eq!(result.accesses, 1000);
+        assert!(result.hits + result.misses >= 1000, "{:?}", result);
+    }
+    // Streams over four times the cache miss on every new block: once per two 8-byte accesses, or on every
+    // block-strided access
+    assert_eq!((results[0].hits, results[0].misses), (500, 500));
+    assert_eq!((results[1].hits, results[1].misses), (0, 1000));
+    assert!(results[2].hits > 0 && results[2].misses > 0);
+
+    // The workloads are the same from one run to the next, and only the random one tells LRU and FIFO apart
+    let misses = |results: &[BenchResult]| results.iter().map(|result| result.misses).collect::<Vec<usize>>();
+    assert_eq!(misses(&run_bench(&builder, 1000).unwrap()), misses(&results));
+    let fifo = run_bench(&builder.clone().replacement(Replacement::Fifo), 1000).unwrap();
+    assert_eq!((fifo[0].misses, fifo[1].misses, fifo[3].misses), (results[0].misses, results[1].misses, results[3].misses));
+}
+
+#[test]
+fn test_run_bench_errors() {
+    let builder = CacheBuilder::new().set_index_bits(4).associativity(2).block_bits(4);
+    assert!(matches!(run_bench(&builder, 0), Err(SimError::Config(_))));
+    assert!(matches!(run_bench(&CacheBuilder::new().set_index_bits(4).associativity(0), 10), Err(SimError::Config(_))));
+}
+
+// Tests for format_bench function
+#[test]
+fn test_format_bench() {
+    let result = |workload, millis, misses| BenchResult { workload, accesses: 1000, elapsed: Duration::from_millis(millis), hits: 1000 - misses, misses, evictions: 0 };
+    assert_eq!(
+        format_bench(&[result("sequential", 250, 500), result("random", 750, 100)]),
+        "workload          accesses     seconds      accesses/s  miss-rate\n\
+         sequential            1000       0.250            4000     50.00%\n\
+         random                1000       0.750            1333     10.00%\n\
+         total                 2000       1.000            2000\n"
+    );
+    assert_eq!(BenchResult { elapsed: Duration::ZERO, ..result("random", 0, 0) }.accesses_per_second(), 0.0);
+}
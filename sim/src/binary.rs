@@ -0,0 +1,148 @@
+//! Compact binary traces: a header followed by fixed-size records that read without any parsing.
+//!
+//! The header is the 8 bytes `SIMTRACE` and a version byte. Each record is an operation byte (`L`, `S` or `M`),
+//! the address as a little-endian u64 and the size as a little-endian u16. Thread ids aren't recorded.
+
+use std::io::{self, BufRead, Write};
+
+use crate::error::SimError;
+use crate::trace::MemoryAccess;
+use crate::writer::TraceWriter;
+
+/// Leading bytes of a binary trace.
+pub const BINARY_MAGIC: &[u8; 8] = b"SIMTRACE";
+
+/// Version of the binary trace format written by this crate.
+pub const BINARY_VERSION: u8 = 1;
+
+/// Bytes in each record of a binary trace.
+pub const RECORD_SIZE: usize = 11;
+
+/// Write the header that starts a binary trace.
+pub fn write_binary_header<W: Write>(writer: &mut W) -> io::Result<()> {
+    writer.write_all(BINARY_MAGIC)?;
+    writer.write_all(&[BINARY_VERSION])
+}
+
+/// Encode an access as a binary trace record. Sizes must fit in 16 bits.
+pub fn encode_record(access: &MemoryAccess) -> Result<[u8; RECORD_SIZE], SimError> {
+    if !matches!(access.operation, 'L' | 'S' | 'M' | 'A' | 'P' | 'F' | 'W' | 'K' | 'U') {
+        return Err(SimError::Trace(format!("invalid operation {}", access.operation)));
+    }
+    let size = u16::try_from(access.size).map_err(|_| SimError::Trace(format!("access size {} doesn't fit in a binary record", access.size)))?;
+    let mut record = [0; RECORD_SIZE];
+    record[0] = access.operation as u8;
+    record[1..9].copy_from_slice(&access.address.to_le_bytes());
+    record[9..].copy_from_slice(&size.to_le_bytes());
+    Ok(record)
+}
+
+/// Decode a binary trace record.
+pub fn decode_record(record: &[u8; RECORD_SIZE]) -> Result<MemoryAccess, SimError> {
+    let operation = match record[0] {
+        b'L' | b'S' | b'M' | b'A' | b'P' | b'F' | b'W' | b'K' | b'U' => record[0] as char,
+        byte => return Err(SimError::Trace(format!("invalid operation byte 0x{:02x}", byte))),
+    };
+    let address = u64::from_le_bytes(record[1..9].try_into().unwrap());
+    let size = u16::from_le_bytes(record[9..].try_into().unwrap()) as u32;
+    Ok(MemoryAccess { operation, address, thread: 0, size, pc: 0 })
+}
+
+// Consume the header if the stream is a binary trace, and report whether it is
+pub(crate) fn read_binary_header(reader: &mut dyn BufRead) -> io::Result<bool> {
+    let buffer = reader.fill_buf()?;
+    if !buffer.starts_with(BINARY_MAGIC) {
+        return Ok(false);
+    }
+    match buffer.get(BINARY_MAGIC.len()) {
+        Some(&BINARY_VERSION) => {
+            reader.consume(BINARY_MAGIC.len() + 1);
+            Ok(true)
+        }
+        Some(version) => Err(io::Error::new(io::ErrorKind::InvalidData, format!("unsupported binary trace version {}", version))),
+        None => Err(io::Error::new(io::ErrorKind::UnexpectedEof, "truncated binary trace header")),
+    }
+}
+
+// Read the next record, or None at the end of the trace
+pub(crate) fn read_record(reader: &mut dyn BufRead) -> io::Result<Option<[u8; RECORD_SIZE]>> {
+    if reader.fill_buf()?.is_empty() {
+        return Ok(None);
+    }
+    let mut record = [0; RECORD_SIZE];
+    reader.read_exact(&mut record).map_err(|err| match err.kind() {
+        io::ErrorKind::UnexpectedEof => io::Error::new(io::ErrorKind::UnexpectedEof, "truncated binary record"),
+        _ => err,
+    })?;
+    Ok(Some(record))
+}
+
+/// Write accesses as a binary trace and return how many were written, stopping at the first error.
+pub fn convert_accesses<I, W>(accesses: I, writer: &mut W) -> Result<usize, SimError>
+where
+    I: IntoIterator<Item = Result<MemoryAccess, SimError>>,
+    W: Write,
+{
+    let mut writer = TraceWriter::binary(writer);
+    for access in accesses {
+        writer.write_access(&access?)?;
+    }
+    let count = writer.accesses();
+    writer.finish()?;
+    Ok(count)
+}
+
+#[cfg(test)]
+use crate::trace::trace_accesses;
+
+// Tests for encode_record and decode_record functions
+#[test]
+fn test_record_round_trip() {
+    let access = MemoryAccess { operation: 'M', address: 0x7ff0_0000_1234, thread: 0, size: 8, pc: 0 };
+    let record = encode_record(&access).unwrap();
+    assert_eq!(record, [b'M', 0x34, 0x12, 0, 0, 0xf0, 0x7f, 0, 0, 8, 0]);
+    assert_eq!(decode_record(&record), Ok(access));
+    let prefetch = MemoryAccess { operation: 'P', ..access };
+    assert_eq!(decode_record(&encode_record(&prefetch).unwrap()), Ok(prefetch));
+    let flush = MemoryAccess { operation: 'W', address: 0, thread: 0, size: 0, pc: 0 };
+    assert_eq!(decode_record(&encode_record(&flush).unwrap()), Ok(flush));
+}
+
+#[test]
+fn test_encode_record_errors() {
+    assert!(encode_record(&MemoryAccess { operation: 'L', address: 0, thread: 0, size: 0x1_0000, pc: 0 }).is_err());
+    assert!(encode_record(&MemoryAccess { operation: 'X', address: 0, thread: 0, size: 1, pc: 0 }).is_err());
+    assert_eq!(decode_record(&[b'X', 0, 0, 0, 0, 0, 0, 0, 0, 1, 0]), Err(SimError::Trace("invalid operation byte 0x58".to_string())));
+}
+
+// Tests for read_binary_header function
+#[test]
+fn test_read_binary_header() {
+    let mut binary: &[u8] = b"SIMTRACE\x01L";
+    assert!(read_binary_header(&mut binary).unwrap());
+    assert_eq!(binary, b"L");
+    let mut text: &[u8] = b" L 10,1\n";
+    assert!(!read_binary_header(&mut text).unwrap());
+    assert_eq!(text, b" L 10,1\n");
+    let mut future: &[u8] = b"SIMTRACE\x02";
+    assert!(read_binary_header(&mut future).is_err());
+}
+
+// Tests for convert_accesses function
+#[test]
+fn test_convert_accesses() {
+    let path = std::env::temp_dir().join(format!("sim-convert-{}.bin", std::process::id()));
+    let mut output = Vec::new();
+    assert_eq!(convert_accesses(trace_accesses("../traces/yi.trace").unwrap(), &mut output), Ok(7));
+    assert_eq!(output.len(), BINARY_MAGIC.len() + 1 + 7 * RECORD_SIZE);
+    std::fs::write(&path, &output).unwrap();
+
+    let text: Vec<MemoryAccess> = trace_accesses("../traces/yi.trace").unwrap().map(Result::unwrap).collect();
+    let binary: Vec<MemoryAccess> = trace_accesses(path.to_str().unwrap()).unwrap().map(Result::unwrap).collect();
+    assert_eq!(binary, text);
+
+    std::fs::write(&path, &output[..output.len() - 1]).unwrap();
+    let mut accesses = trace_accesses(path.to_str().unwrap()).unwrap();
+    assert_eq!(accesses.nth(6), Some(Err(SimError::Trace("record 7: truncated binary record".to_string()))));
+    std::fs::remove_file(path).unwrap();
+}
@@ -0,0 +1,6105 @@
+//! Set-associative cache model with LRU, FIFO, random or tree-PLRU replacement and its optional extensions.
+
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet, VecDeque};
+use std::fmt;
+use std::ops::{Deref, DerefMut, RangeInclusive};
+
+use serde::{Deserialize, Serialize};
+
+use crate::dueling::{SetDueling, DUEL_LEADERS, PSEL_BITS};
+use crate::hawkeye::{Hawkeye, HawkeyeUndo};
+use crate::lirs::{Lirs, LirsUndo};
+use crate::ship::{SignatureTable, SignatureTableUndo, SHIP_REGION_BITS, SHIP_SIGNATURE_BITS};
+use crate::tinylfu::{TinyLfu, TinyLfuUndo, TINYLFU_ROWS};
+use crate::two_queue::{TwoQueue, TwoQueueUndo};
+use crate::error::SimError;
+use crate::logging::{log_enabled, log_event, LogLevel};
+use crate::rng::Rng;
+use crate::trace::MemoryAccess;
+
+#[derive(Clone, Serialize, Deserialize)]
+struct Line {
+    tag: Option<usize>,
+    is_valid: bool,
+    dirty: bool, // Stored to since it was filled
+    last_used: u64, // Access time of the last use, for LRU across sets in skewed mode
+    #[serde(default)]
+    filled_at: u64, // Lookup that filled the line, to measure its lifetime when it is evicted
+    #[serde(default)]
+    pinned: bool, // Never chosen for eviction
+    #[serde(default)]
+    spared: bool, // Pinned when the policy would have evicted it, and not hit since
+    #[serde(default)]
+    reused: bool, // Hit since it was filled, for the bypass predictor
+    #[serde(default)]
+    rrpv: u8, // Re-reference prediction value of the RRIP policies, the highest being evicted first
+    #[serde(default)]
+    signature: u16, // Signature the line was filled or last hit under, for the SHiP and Hawkeye policies
+    #[serde(default)]
+    protected: bool, // In the protected segment of SLRU, having been hit since it was filled, or in the Am queue of 2Q
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+struct Set {
+    lines: Vec<Line>,
+    access_order: VecDeque<usize>,
+    next_way: usize, // Round-robin pointer used when breaking ties
+    #[serde(default)]
+    stats: SetStats,
+    #[serde(default)]
+    plru: Vec<bool>, // Tree-PLRU bits by node in heap order, true pointing the victim to the upper half; empty for other policies
+}
+
+/// A simulated cache of 2^s sets with E lines each, counting hits, misses and evictions.
+#[derive(Serialize, Deserialize)]
+pub struct Cache {
+    sets: Vec<Set>,
+    hits: usize,
+    misses: usize,
+    evictions: usize,
+    #[serde(default)]
+    replacement: Replacement,
+    #[serde(default)]
+    insertion: Insertion,
+    tie_break: TieBreak,
+    rng: Rng,
+    set_bits: usize,
+    block_bits: usize,
+    skewed: bool,
+    clock: u64,
+    way_predictor: Option<WayPredictor>,
+    fast_hits: usize, // Hits found in the predicted way
+    slow_hits: usize, // Hits found only after checking the other ways
+    address_bits: usize,
+    oob_policy: OobPolicy,
+    oob_accesses: usize, // Accesses with an address above the modeled address width
+    #[serde(default)]
+    prefetch_hits: usize, // Prefetches of blocks already cached, not counted as hits
+    #[serde(default)]
+    prefetch_fills: usize, // Prefetches that brought a block in, not counted as misses
+    #[serde(default)]
+    uncacheable: Vec<RangeInclusive<u64>>, // Address ranges whose accesses bypass the cache
+    #[serde(default)]
+    uncached_accesses: usize,
+    #[serde(default)]
+    scratchpad: Option<RangeInclusive<u64>>, // Address range served by the scratchpad, which always hits
+    #[serde(default)]
+    scratchpad_ways: usize, // Ways of each set the scratchpad took from the cache
+    #[serde(default)]
+    scratchpad_accesses: usize,
+    #[serde(default)]
+    flushes: usize, // Flush records, of one line or of the whole cache
+    #[serde(default)]
+    flushed_lines: usize, // Valid lines the flushes invalidated
+    #[serde(default)]
+    flush_write_backs: usize, // Dirty lines among them
+    #[serde(default)]
+    dirty_evictions: usize, // Evicted lines that had to be written back
+    #[serde(default)]
+    pinned_hits: usize,
+    #[serde(default)]
+    spared_evictions: usize, // Misses that evicted another line than the pinned one the policy chose
+    #[serde(default)]
+    saved_misses: usize, // First hits on pinned lines after they were spared, which would have missed
+    #[serde(default)]
+    pin_bypasses: usize, // Misses left unfilled as every line they could fill was pinned
+    #[serde(default)]
+    locked_ways: Vec<WayLock>, // Ways whose lines are pinned as soon as they are filled
+    thread: usize, // Thread issuing the current access
+    #[serde(default)]
+    pc: u64, // Instruction making the current access, 0 if unknown
+    thread_stats: BTreeMap<usize, ThreadStats>,
+    way_partitions: HashMap<usize, Vec<usize>>, // Ways each thread may fill, threads without an entry may use all of them
+    banks: Option<Banks>,
+    #[serde(default)]
+    write_buffer: Option<WriteBuffer>,
+    #[serde(default)]
+    store_buffer: Option<StoreBuffer>,
+    #[serde(default)]
+    bypass: Option<BypassPredictor>,
+    #[serde(default)]
+    tinylfu: Option<TinyLfu>, // Admission filter of the misses that would evict
+    #[serde(default)]
+    dueling: Option<SetDueling>, // Set dueling between SRRIP and BRRIP for DRRIP
+    #[serde(default)]
+    adaptive: Option<Adaptive>, // Two policies the sets switch between at runtime, replacing the one above
+    #[serde(default)]
+    ship: Option<SignatureTable>, // Signature history counter table of the SHiP policies
+    #[serde(default)]
+    hawkeye: Option<Hawkeye>,
+    #[serde(default)]
+    slru: Option<Slru>,
+    #[serde(default)]
+    two_queue: Option<TwoQueue>,
+    #[serde(default)]
+    lirs: Option<Lirs>,
+    compression: Option<Compression>,
+    undo_depth: usize, // Number of recent accesses that can be undone
+    #[serde(skip)]
+    history: VecDeque<Undo>,
+    resets: usize, // Number of times the statistics were reset, so regions of interest can notice
+    roi_stats: Stats, // Statistics accumulated over all closed regions of interest
+    #[serde(default)]
+    miss_classifier: Option<MissClassifier>,
+    #[serde(default)]
+    hot_blocks: Option<HotBlocks>,
+    #[serde(default)]
+    lookups: u64, // Lookups simulated so far, never reset
+    #[serde(default)]
+    lifetimes: Option<BTreeMap<u64, usize>>, // Evictions by the lookups their line survived, if tracked
+    #[serde(skip)]
+    mute_log: bool, // The baseline of a compressed cache doesn't log the accesses it shadows
+}
+
+// State an access may overwrite, saved before it so the access can be undone
+struct Undo {
+    sets: Vec<(usize, Set)>,
+    hits: usize,
+    misses: usize,
+    evictions: usize,
+    fast_hits: usize,
+    slow_hits: usize,
+    oob_accesses: usize,
+    prefetch_hits: usize,
+    prefetch_fills: usize,
+    uncached_accesses: usize,
+    scratchpad_accesses: usize,
+    flushes: usize,
+    flushed_lines: usize,
+    flush_write_backs: usize,
+    dirty_evictions: usize,
+    pinned_hits: usize,
+    spared_evictions: usize,
+    saved_misses: usize,
+    pin_bypasses: usize,
+    clock: u64,
+    rng_state: u64,
+    thread_stats: BTreeMap<usize, ThreadStats>,
+    banks: Option<Banks>,
+    write_buffer: Option<WriteBuffer>,
+    store_buffer: Option<StoreBuffer>,
+    bypass: Option<BypassUndo>, // Counters of the regions of the access and of the lines in sets, entries of the blocks
+    tinylfu: Option<TinyLfuUndo>, // Counters of the blocks in blocks, or the whole sketch if it may be halved
+    dueling: Option<SetDueling>,
+    adaptive: Option<Adaptive>,
+    ship: Option<SignatureTableUndo>, // Counters of the signatures of the lines in sets and of the blocks' fills
+    hawkeye: Option<HawkeyeUndo>, // Samplers of the sets in sets and the counters the access may train
+    slru: Option<Slru>,
+    two_queue: Option<TwoQueueUndo>, // A1out queues of the sets in sets
+    lirs: Option<LirsUndo>, // LIRS state of the sets in sets
+    miss_classifier: Vec<MissClassifierUndo>, // Changes the lookups of the access made to the classifier, in order
+    hot_blocks: Vec<HotBlocksUndo>, // Entries the lookups of the access replaced, in order
+    lookups: u64,
+    lifetimes: Vec<u64>, // Lifetimes the evictions of the access added
+}
+
+// Highest re-reference prediction value of the RRIP policies, for lines expected to be reused last
+const RRPV_MAX: u8 = 3;
+
+// BRRIP fills one line in this many, on average, with the SRRIP value instead of the highest
+const BRRIP_SHORT_FILLS: usize = 32;
+
+// Most recent switches of an adaptive cache kept with the lookup they happened at
+const ADAPTIVE_SWITCH_POINTS: usize = 16;
+
+// BIP inserts one line in this many, on average, at the most recently used position
+const BIP_MRU_FILLS: usize = 32;
+
+// Segments a line is divided into when storing compressed blocks
+const SEGMENTS_PER_LINE: usize = 8;
+
+// Synthetic compression model: each set gets twice as many tags as lines, and a block fits as long as the
+// compressed sizes of the valid blocks don't exceed the set's data capacity
+#[derive(Serialize, Deserialize)]
+struct Compression {
+    compressibility: f64, // Fraction of blocks that compress to half a line
+    baseline: Box<Cache>, // Uncompressed cache fed the same accesses, to measure the miss-rate benefit
+}
+
+// Cache banks indexed by the low block bits, with consecutive accesses issued together in groups of issue_width
+#[derive(Clone, Serialize, Deserialize)]
+struct Banks {
+    count: usize,
+    issue_width: usize,
+    group_size: usize, // Accesses issued so far in the current cycle
+    group_accesses: Vec<usize>, // Accesses to each bank in the current cycle
+    cycles: usize, // Cycles needed, with conflicting accesses serialized
+    conflicts: usize, // Accesses that found their bank busy in the same cycle
+}
+
+impl Banks {
+    fn new(count: usize, issue_width: usize) -> Banks {
+        Banks {
+            count,
+            issue_width,
+            group_size: 0,
+            group_accesses: vec![0; count],
+            cycles: 0,
+            conflicts: 0,
+        }
+    }
+
+    // Issue an access to the bank of the block, starting a new cycle when the issue width is used up
+    fn issue(&mut self, block: u64) {
+        if self.group_size == self.issue_width {
+            self.group_size = 0;
+            self.group_accesses.iter_mut().for_each(|accesses| *accesses = 0);
+        }
+        let busiest = self.group_accesses.iter().copied().max().unwrap_or(0);
+        let bank = (block % self.count as u64) as usize;
+        self.group_accesses[bank] += 1;
+        if self.group_accesses[bank] > 1 {
+            self.conflicts += 1;
+        }
+        if self.group_accesses[bank] > busiest {
+            self.cycles += 1;
+        }
+        self.group_size += 1;
+    }
+}
+
+// Write-back buffer between the cache and memory: dirty evictions wait in it until memory takes them, one every
+// drain_interval lookups, and a miss to a block still waiting is served from it instead of memory
+#[derive(Clone, Serialize, Deserialize)]
+struct WriteBuffer {
+    capacity: usize,
+    drain_interval: u64,
+    blocks: VecDeque<u64>, // Block addresses waiting to be written, oldest first
+    last_drain: u64, // Lookup memory last took a block at, or the buffer was last empty
+    write_backs: usize, // Dirty evictions that entered the buffer
+    hits: usize, // Misses served from the buffer
+    stalls: usize, // Dirty evictions that found the buffer full and waited for its oldest block to be written
+}
+
+impl WriteBuffer {
+    fn new(capacity: usize, drain_interval: u64) -> WriteBuffer {
+        WriteBuffer { capacity, drain_interval, blocks: VecDeque::new(), last_drain: 0, write_backs: 0, hits: 0, stalls: 0 }
+    }
+
+    // Write the blocks memory has taken by the given lookup
+    fn drain(&mut self, now: u64) {
+        if self.blocks.is_empty() {
+            self.last_drain = now;
+        }
+        while !self.blocks.is_empty() && now >= self.last_drain + self.drain_interval {
+            self.blocks.pop_front();
+            self.last_drain += self.drain_interval;
+        }
+    }
+
+    // Queue a dirty block, stalling for the oldest one to be written if the buffer is full
+    fn push(&mut self, block: u64, now: u64) {
+        self.drain(now);
+        if self.blocks.len() == self.capacity {
+            self.blocks.pop_front();
+            self.last_drain = now;
+            self.stalls += 1;
+        }
+        self.blocks.push_back(block);
+        self.write_backs += 1;
+    }
+
+    // Take a block out of the buffer if it is still waiting there, returning whether it was
+    fn take(&mut self, block: u64, now: u64) -> bool {
+        self.drain(now);
+        match self.blocks.iter().position(|&waiting| waiting == block) {
+            Some(position) => {
+                self.blocks.remove(position);
+                self.hits += 1;
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+// Highest value of the reuse counters of the bypass predictor, which regions start at
+const BYPASS_COUNTER_MAX: u8 = 3;
+
+// Entries of the direct-mapped table of recently bypassed blocks, a newer block replacing the one in its entry
+const BYPASSED_BLOCKS: usize = 1024;
+
+// Bypass predictor: a saturating reuse counter for each region of addresses, lowered when a line of the region is
+// evicted without having been hit since its fill and raised when it was. Fills in regions whose counter is zero are
+// predicted dead and bypassed, and a recently bypassed block accessed again raises its counter back
+#[derive(Clone, Serialize, Deserialize)]
+struct BypassPredictor {
+    region_bits: usize,
+    counters: HashMap<u64, u8>, // Counters below the maximum, by region
+    bypassed: Vec<Option<u64>>, // Blocks whose fill was bypassed, by block modulo BYPASSED_BLOCKS, until accessed again
+    bypasses: usize, // Fills bypassed
+    reuses: usize, // Bypassed blocks accessed again, each a miss the bypass may have caused
+}
+
+// Counters of the regions and entries of the bypassed blocks an access may change, with the counts
+struct BypassUndo {
+    counters: Vec<(u64, Option<u8>)>,
+    bypassed: Vec<(usize, Option<u64>)>,
+    counts: [usize; 2], // Bypasses and reuses
+}
+
+impl BypassPredictor {
+    fn new(region_bits: usize) -> BypassPredictor {
+        BypassPredictor { region_bits, counters: HashMap::new(), bypassed: vec![None; BYPASSED_BLOCKS], bypasses: 0, reuses: 0 }
+    }
+
+    // Whether the fill of a missed block should be bypassed, counting the reuse of a block bypassed before
+    fn predict(&mut self, block: u64, address: u64) -> bool {
+        let entry = &mut self.bypassed[block as usize % BYPASSED_BLOCKS];
+        if *entry == Some(block) {
+            *entry = None;
+            self.reuses += 1;
+            self.train(address, true);
+        }
+        self.counters.get(&(address >> self.region_bits)) == Some(&0)
+    }
+
+    // Update the counter of the region of an evicted or reused block
+    fn train(&mut self, address: u64, reused: bool) {
+        let region = address >> self.region_bits;
+        let counter = self.counters.get(&region).copied().unwrap_or(BYPASS_COUNTER_MAX);
+        match if reused { counter + 1 } else { counter.saturating_sub(1) } {
+            BYPASS_COUNTER_MAX.. => self.counters.remove(&region),
+            counter => self.counters.insert(region, counter),
+        };
+    }
+
+    fn bypass(&mut self, block: u64) {
+        self.bypassed[block as usize % BYPASSED_BLOCKS] = Some(block);
+        self.bypasses += 1;
+    }
+
+    // Save the entries of some blocks and the counters of the regions of some addresses
+    fn save(&self, blocks: &[u64], addresses: impl Iterator<Item = u64>) -> BypassUndo {
+        let mut regions: Vec<u64> = addresses.map(|address| address >> self.region_bits).collect();
+        regions.sort();
+        regions.dedup();
+        let mut entries: Vec<usize> = blocks.iter().map(|&block| block as usize % BYPASSED_BLOCKS).collect();
+        entries.sort();
+        entries.dedup();
+        BypassUndo {
+            counters: regions.into_iter().map(|region| (region, self.counters.get(&region).copied())).collect(),
+            bypassed: entries.into_iter().map(|entry| (entry, self.bypassed[entry])).collect(),
+            counts: [self.bypasses, self.reuses],
+        }
+    }
+
+    fn restore(&mut self, undo: BypassUndo) {
+        for (region, counter) in undo.counters {
+            match counter {
+                Some(counter) => self.counters.insert(region, counter),
+                None => self.counters.remove(&region),
+            };
+        }
+        for (entry, block) in undo.bypassed {
+            self.bypassed[entry] = block;
+        }
+        [self.bypasses, self.reuses] = undo.counts;
+    }
+}
+
+// Segmented LRU: the number of lines of a set the protected segment holds, and the lines moved between segments
+#[derive(Clone, Serialize, Deserialize)]
+struct Slru {
+    protected_ways: usize,
+    promotions: usize, // Probationary lines hit, moving to the protected segment
+    demotions: usize, // Protected lines pushed back to the probationary segment by a promotion
+}
+
+// Adaptive replacement: leader sets duel for two policies sharing the state of the lines, and the follower sets use
+// whichever missed less, so the cache switches policy as the workload changes phase
+#[derive(Clone, Serialize, Deserialize)]
+struct Adaptive {
+    policies: [Replacement; 2],
+    dueling: SetDueling,
+    switch_points: VecDeque<(u64, Replacement)>, // Lookups the followers last switched at and the policy they took
+}
+
+// Store buffer in front of the cache: stores wait in it by block, so a store to a block already waiting coalesces
+// with it and a load of a waiting block is forwarded from it. A store to a new block retires the oldest one into the
+// cache when the buffer is full, and an atomic access retires them all first
+#[derive(Clone, Serialize, Deserialize)]
+struct StoreBuffer {
+    capacity: usize,
+    entries: VecDeque<(u64, usize)>, // Blocks stored to and the thread that stored them, oldest first
+    stores: usize, // Stores that entered the buffer, including coalesced ones
+    coalesced: usize, // Stores to a block already waiting
+    forwards: usize, // Loads served from the buffer
+}
+
+impl StoreBuffer {
+    fn new(capacity: usize) -> StoreBuffer {
+        StoreBuffer { capacity, entries: VecDeque::new(), stores: 0, coalesced: 0, forwards: 0 }
+    }
+
+    fn holds(&self, block: u64) -> bool {
+        self.entries.iter().any(|&(waiting, _)| waiting == block)
+    }
+
+    // Buffer a store to a block, returning the entry retired to make room for it
+    fn store(&mut self, block: u64, thread: usize) -> Option<(u64, usize)> {
+        self.stores += 1;
+        if self.holds(block) {
+            self.coalesced += 1;
+            return None;
+        }
+        let retired = if self.entries.len() == self.capacity { self.entries.pop_front() } else { None };
+        self.entries.push_back((block, thread));
+        retired
+    }
+}
+
+// Classifies misses into the 3Cs: compulsory on the first access to a block, capacity if a fully-associative
+// LRU cache with as many lines would also miss, and conflict otherwise
+#[derive(Clone, Serialize, Deserialize)]
+struct MissClassifier {
+    lines: usize, // Capacity of the fully-associative cache
+    seen: HashSet<u64>, // Blocks accessed so far
+    last_used: HashMap<u64, u64>, // Blocks in the fully-associative cache and the time of their last use
+    lru: BTreeMap<u64, u64>, // The same blocks by time of last use
+    clock: u64,
+    compulsory: usize,
+    capacity: usize,
+    conflict: usize,
+}
+
+// Class a miss was counted in
+#[derive(Clone, Copy)]
+enum MissClass {
+    Compulsory,
+    Capacity,
+    Conflict,
+}
+
+// Changes an access made to the classifier, put back when it is undone
+struct MissClassifierUndo {
+    block: u64,
+    first_touch: bool, // The block was added to the blocks seen
+    last_used: Option<u64>, // Previous use of the block, if it was in the fully-associative cache
+    evicted: Option<(u64, u64)>, // Last use and block the fully-associative cache evicted for it
+    class: Option<MissClass>,
+}
+
+impl MissClassifier {
+    fn new(lines: usize) -> MissClassifier {
+        MissClassifier {
+            lines,
+            seen: HashSet::new(),
+            last_used: HashMap::new(),
+            lru: BTreeMap::new(),
+            clock: 0,
+            compulsory: 0,
+            capacity: 0,
+            conflict: 0,
+        }
+    }
+
+    // Access a block in the fully-associative cache, classifying the access if the real cache missed, and return
+    // the changes it made
+    fn access(&mut self, block: u64, missed: bool) -> MissClassifierUndo {
+        self.clock += 1;
+        let first_touch = self.seen.insert(block);
+        let last_used = self.last_used.insert(block, self.clock);
+        if let Some(time) = last_used {
+            self.lru.remove(&time);
+        }
+        self.lru.insert(self.clock, block);
+        let mut evicted = None;
+        if self.lru.len() > self.lines {
+            evicted = self.lru.pop_first();
+            if let Some((_, block)) = evicted {
+                self.last_used.remove(&block);
+            }
+        }
+        let class = match (missed, first_touch, last_used.is_some()) {
+            (false, _, _) => None,
+            (true, true, _) => Some(MissClass::Compulsory),
+            (true, false, false) => Some(MissClass::Capacity),
+            (true, false, true) => Some(MissClass::Conflict),
+        };
+        match class {
+            Some(MissClass::Compulsory) => self.compulsory += 1,
+            Some(MissClass::Capacity) => self.capacity += 1,
+            Some(MissClass::Conflict) => self.conflict += 1,
+            None => {}
+        }
+        MissClassifierUndo { block, first_touch, last_used, evicted, class }
+    }
+
+    // Put back what an access changed, the last access first
+    fn restore(&mut self, undo: MissClassifierUndo) {
+        if undo.first_touch {
+            self.seen.remove(&undo.block);
+        }
+        self.lru.remove(&self.clock);
+        self.last_used.remove(&undo.block);
+        self.clock -= 1;
+        for (time, block) in undo.last_used.map(|time| (time, undo.block)).into_iter().chain(undo.evicted) {
+            self.last_used.insert(block, time);
+            self.lru.insert(time, block);
+        }
+        match undo.class {
+            Some(MissClass::Compulsory) => self.compulsory -= 1,
+            Some(MissClass::Capacity) => self.capacity -= 1,
+            Some(MissClass::Conflict) => self.conflict -= 1,
+            None => {}
+        }
+    }
+}
+
+// Access counts of the most accessed blocks, kept within a fixed number of entries with the Space-Saving
+// algorithm: a block that isn't tracked replaces the least counted one and inherits its count as an error bound
+#[derive(Clone, Serialize, Deserialize)]
+struct HotBlocks {
+    capacity: usize,
+    blocks: HashMap<u64, HotBlock>,
+    by_count: BTreeSet<(usize, u64)>, // (count, block) of every tracked block, least counted first
+}
+
+#[derive(Clone, Copy, Serialize, Deserialize)]
+struct HotBlock {
+    count: usize, // Lookups counted for the block, including the inherited ones
+    error: usize, // Inherited lookups, which belonged to blocks tracked before it
+    hits: usize,
+}
+
+// Entries a record replaced, put back when its access is undone
+struct HotBlocksUndo {
+    block: u64,
+    previous: Option<HotBlock>, // Entry of the recorded block before the record, None if it wasn't tracked
+    evicted: Option<(u64, HotBlock)>, // Block the record stopped tracking to make room, with its entry
+}
+
+impl HotBlocks {
+    fn new(capacity: usize) -> HotBlocks {
+        HotBlocks { capacity, blocks: HashMap::new(), by_count: BTreeSet::new() }
+    }
+
+    // Count the lookups and hits of an access to a block, returning the entries it replaced
+    fn record(&mut self, block: u64, lookups: usize, hits: usize) -> HotBlocksUndo {
+        let mut undo = HotBlocksUndo { block, previous: None, evicted: None };
+        let entry = match self.blocks.get_mut(&block) {
+            Some(entry) => {
+                self.by_count.remove(&(entry.count, block));
+                undo.previous = Some(*entry);
+                entry
+            }
+            None => {
+                let error = if self.blocks.len() < self.capacity {
+                    0
+                } else {
+                    let (count, evicted) = self.by_count.pop_first().unwrap();
+                    undo.evicted = self.blocks.remove(&evicted).map(|entry| (evicted, entry));
+                    count
+                };
+                self.blocks.entry(block).or_insert(HotBlock { count: error, error, hits: 0 })
+            }
+        };
+        entry.count += lookups;
+        entry.hits += hits;
+        self.by_count.insert((entry.count, block));
+        undo
+    }
+
+    // Put back the entries a record replaced
+    fn restore(&mut self, undo: HotBlocksUndo) {
+        if let Some(entry) = self.blocks.remove(&undo.block) {
+            self.by_count.remove(&(entry.count, undo.block));
+        }
+        for (block, entry) in undo.previous.map(|entry| (undo.block, entry)).into_iter().chain(undo.evicted) {
+            self.blocks.insert(block, entry);
+            self.by_count.insert((entry.count, block));
+        }
+    }
+}
+
+/// Lookups and hits of one block, as reported by [`Cache::hot_blocks`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BlockStats {
+    pub address: u64, // Address of the first byte of the block
+    pub lookups: usize,
+    pub hits: usize,
+}
+
+/// Distribution of the lifetimes of evicted lines: the lookups to the cache between a line's fill and its
+/// eviction.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LifetimeStats {
+    pub evictions: usize,
+    pub min: u64,
+    pub mean: f64,
+    pub p50: u64,
+    pub p90: u64,
+    pub p99: u64,
+    pub max: u64,
+}
+
+/// Blocks tracked by default when reporting the hottest blocks of a run.
+pub const HOT_BLOCK_CAPACITY: usize = 4096;
+
+/// Lookups between two blocks the write-back buffer drains to memory by default.
+pub const DEFAULT_DRAIN_INTERVAL: u64 = 10;
+
+/// Kind of a data access.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Op {
+    Load,
+    Store,
+    /// A load followed by a store to the same address
+    Modify,
+    /// A software prefetch, filling the cache without counting as a demand hit or miss
+    Prefetch,
+    /// An atomic read-modify-write, simulated as a modify in a single cache
+    Atomic,
+    /// A flush of the line holding the address, writing it back if dirty
+    Flush,
+    /// A flush of every line
+    FlushAll,
+    /// A pin of the line holding the address, filling it if needed, so that it is never evicted
+    Pin,
+    /// An unpin of the line holding the address
+    Unpin,
+}
+
+impl From<Op> for char {
+    fn from(op: Op) -> char {
+        match op {
+            Op::Load => 'L',
+            Op::Store => 'S',
+            Op::Modify => 'M',
+            Op::Prefetch => 'P',
+            Op::Atomic => 'A',
+            Op::Flush => 'F',
+            Op::FlushAll => 'W',
+            Op::Pin => 'K',
+            Op::Unpin => 'U',
+        }
+    }
+}
+
+impl TryFrom<char> for Op {
+    type Error = SimError;
+
+    fn try_from(operation: char) -> Result<Op, SimError> {
+        match operation {
+            'L' => Ok(Op::Load),
+            'S' => Ok(Op::Store),
+            'M' => Ok(Op::Modify),
+            'P' => Ok(Op::Prefetch),
+            'A' => Ok(Op::Atomic),
+            'F' => Ok(Op::Flush),
+            'W' => Ok(Op::FlushAll),
+            'K' => Ok(Op::Pin),
+            'U' => Ok(Op::Unpin),
+            _ => Err(SimError::Access(format!("unknown operation: {}", operation))),
+        }
+    }
+}
+
+/// What simulating one access did to the cache ('M' and 'A' accesses look the cache up twice).
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AccessResult {
+    pub access: MemoryAccess,
+    pub hits: usize,
+    pub misses: usize,
+    pub evictions: usize,
+    /// Blocks a prefetch found already cached
+    pub prefetch_hits: usize,
+    /// Blocks a prefetch brought into the cache
+    pub prefetch_fills: usize,
+}
+
+impl AccessResult {
+    /// Describe the access and its outcome, e.g. "M 20 miss eviction hit".
+    pub fn describe(&self) -> String {
+        let mut description = format!("{} {:x}", self.access.operation, self.access.address);
+        let outcomes = [
+            ("miss", self.misses),
+            ("prefetch-fill", self.prefetch_fills),
+            ("eviction", self.evictions),
+            ("hit", self.hits),
+            ("prefetch-hit", self.prefetch_hits),
+        ];
+        for (outcome, count) in outcomes {
+            for _ in 0..count {
+                description.push(' ');
+                description.push_str(outcome);
+            }
+        }
+        if self.hits + self.misses + self.prefetch_hits + self.prefetch_fills == 0 {
+            description.push_str(" skipped");
+        }
+        description
+    }
+}
+
+/// Hits, misses and evictions caused by one thread.
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+pub struct ThreadStats {
+    pub hits: usize,
+    pub misses: usize,
+    pub evictions: usize,
+}
+
+/// Hits, misses and evictions in one set.
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+pub struct SetStats {
+    pub hits: usize,
+    pub misses: usize,
+    pub evictions: usize,
+}
+
+/// Counters of a cache at one point of a run, or over a window of it.
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+pub struct Stats {
+    pub hits: usize,
+    pub misses: usize,
+    pub evictions: usize,
+    pub fast_hits: usize,
+    pub slow_hits: usize,
+    pub oob_accesses: usize,
+    pub bank_cycles: usize,
+    pub bank_conflicts: usize,
+    pub baseline_misses: usize, // Misses of the uncompressed baseline of a compressed cache
+    pub threads: BTreeMap<usize, ThreadStats>,
+    #[serde(default)]
+    pub compulsory_misses: usize,
+    #[serde(default)]
+    pub capacity_misses: usize,
+    #[serde(default)]
+    pub conflict_misses: usize,
+    #[serde(default)]
+    pub prefetch_hits: usize,
+    #[serde(default)]
+    pub prefetch_fills: usize,
+    #[serde(default)]
+    pub uncached_accesses: usize,
+    #[serde(default)]
+    pub scratchpad_accesses: usize,
+    #[serde(default)]
+    pub write_backs: usize, // Dirty evictions that entered the write-back buffer
+    #[serde(default)]
+    pub write_buffer_hits: usize,
+    #[serde(default)]
+    pub write_buffer_stalls: usize,
+    #[serde(default)]
+    pub buffered_stores: usize, // Stores that entered the store buffer
+    #[serde(default)]
+    pub coalesced_stores: usize,
+    #[serde(default)]
+    pub forwarded_loads: usize,
+    #[serde(default)]
+    pub bypassed_fills: usize,
+    #[serde(default)]
+    pub bypass_reuses: usize, // Bypassed blocks accessed again
+    #[serde(default)]
+    pub policy_switches: usize, // Times the DRRIP or adaptive followers changed policy
+    #[serde(default)]
+    pub distant_fills: usize, // Fills the SHiP policies predicted not to be reused, or Hawkeye cache-averse
+    #[serde(default)]
+    pub promotions: usize, // Lines moved to the protected segment of SLRU
+    #[serde(default)]
+    pub ghost_hits: usize, // Misses on a block the 2Q or LIRS policy still remembered after evicting it
+    #[serde(default)]
+    pub admitted_fills: usize, // Misses TinyLFU let replace their victim
+    #[serde(default)]
+    pub rejected_fills: usize, // Misses TinyLFU left unfilled, their block being no more frequent than the victim's
+    #[serde(default)]
+    pub flushes: usize,
+    #[serde(default)]
+    pub flushed_lines: usize,
+    #[serde(default)]
+    pub flush_write_backs: usize,
+    #[serde(default)]
+    pub pinned_hits: usize,
+    #[serde(default)]
+    pub spared_evictions: usize,
+    #[serde(default)]
+    pub saved_misses: usize,
+    #[serde(default)]
+    pub pin_bypasses: usize,
+}
+
+impl Stats {
+    /// Counts between an earlier snapshot of the same run and this one.
+    pub fn since(&self, earlier: &Stats) -> Stats {
+        let threads = self.threads.iter().map(|(&thread, stats)| {
+            let earlier = earlier.threads.get(&thread).copied().unwrap_or_default();
+            (thread, ThreadStats {
+                hits: stats.hits.saturating_sub(earlier.hits),
+                misses: stats.misses.saturating_sub(earlier.misses),
+                evictions: stats.evictions.saturating_sub(earlier.evictions),
+            })
+        }).collect();
+        Stats {
+            hits: self.hits.saturating_sub(earlier.hits),
+            misses: self.misses.saturating_sub(earlier.misses),
+            evictions: self.evictions.saturating_sub(earlier.evictions),
+            fast_hits: self.fast_hits.saturating_sub(earlier.fast_hits),
+            slow_hits: self.slow_hits.saturating_sub(earlier.slow_hits),
+            oob_accesses: self.oob_accesses.saturating_sub(earlier.oob_accesses),
+            bank_cycles: self.bank_cycles.saturating_sub(earlier.bank_cycles),
+            bank_conflicts: self.bank_conflicts.saturating_sub(earlier.bank_conflicts),
+            baseline_misses: self.baseline_misses.saturating_sub(earlier.baseline_misses),
+            threads,
+            compulsory_misses: self.compulsory_misses.saturating_sub(earlier.compulsory_misses),
+            capacity_misses: self.capacity_misses.saturating_sub(earlier.capacity_misses),
+            conflict_misses: self.conflict_misses.saturating_sub(earlier.conflict_misses),
+            prefetch_hits: self.prefetch_hits.saturating_sub(earlier.prefetch_hits),
+            prefetch_fills: self.prefetch_fills.saturating_sub(earlier.prefetch_fills),
+            uncached_accesses: self.uncached_accesses.saturating_sub(earlier.uncached_accesses),
+            scratchpad_accesses: self.scratchpad_accesses.saturating_sub(earlier.scratchpad_accesses),
+            write_backs: self.write_backs.saturating_sub(earlier.write_backs),
+            write_buffer_hits: self.write_buffer_hits.saturating_sub(earlier.write_buffer_hits),
+            write_buffer_stalls: self.write_buffer_stalls.saturating_sub(earlier.write_buffer_stalls),
+            buffered_stores: self.buffered_stores.saturating_sub(earlier.buffered_stores),
+            coalesced_stores: self.coalesced_stores.saturating_sub(earlier.coalesced_stores),
+            forwarded_loads: self.forwarded_loads.saturating_sub(earlier.forwarded_loads),
+            bypassed_fills: self.bypassed_fills.saturating_sub(earlier.bypassed_fills),
+            bypass_reuses: self.bypass_reuses.saturating_sub(earlier.bypass_reuses),
+            policy_switches: self.policy_switches.saturating_sub(earlier.policy_switches),
+            distant_fills: self.distant_fills.saturating_sub(earlier.distant_fills),
+            promotions: self.promotions.saturating_sub(earlier.promotions),
+            ghost_hits: self.ghost_hits.saturating_sub(earlier.ghost_hits),
+            admitted_fills: self.admitted_fills.saturating_sub(earlier.admitted_fills),
+            rejected_fills: self.rejected_fills.saturating_sub(earlier.rejected_fills),
+            flushes: self.flushes.saturating_sub(earlier.flushes),
+            flushed_lines: self.flushed_lines.saturating_sub(earlier.flushed_lines),
+            flush_write_backs: self.flush_write_backs.saturating_sub(earlier.flush_write_backs),
+            pinned_hits: self.pinned_hits.saturating_sub(earlier.pinned_hits),
+            spared_evictions: self.spared_evictions.saturating_sub(earlier.spared_evictions),
+            saved_misses: self.saved_misses.saturating_sub(earlier.saved_misses),
+            pin_bypasses: self.pin_bypasses.saturating_sub(earlier.pin_bypasses),
+        }
+    }
+
+    /// Add the counts of another window or run to these.
+    pub fn add(&mut self, other: &Stats) {
+        self.hits += other.hits;
+        self.misses += other.misses;
+        self.evictions += other.evictions;
+        self.fast_hits += other.fast_hits;
+        self.slow_hits += other.slow_hits;
+        self.oob_accesses += other.oob_accesses;
+        self.bank_cycles += other.bank_cycles;
+        self.bank_conflicts += other.bank_conflicts;
+        self.baseline_misses += other.baseline_misses;
+        self.compulsory_misses += other.compulsory_misses;
+        self.capacity_misses += other.capacity_misses;
+        self.conflict_misses += other.conflict_misses;
+        self.prefetch_hits += other.prefetch_hits;
+        self.prefetch_fills += other.prefetch_fills;
+        self.uncached_accesses += other.uncached_accesses;
+        self.scratchpad_accesses += other.scratchpad_accesses;
+        self.write_backs += other.write_backs;
+        self.write_buffer_hits += other.write_buffer_hits;
+        self.write_buffer_stalls += other.write_buffer_stalls;
+        self.buffered_stores += other.buffered_stores;
+        self.coalesced_stores += other.coalesced_stores;
+        self.forwarded_loads += other.forwarded_loads;
+        self.bypassed_fills += other.bypassed_fills;
+        self.bypass_reuses += other.bypass_reuses;
+        self.policy_switches += other.policy_switches;
+        self.distant_fills += other.distant_fills;
+        self.promotions += other.promotions;
+        self.ghost_hits += other.ghost_hits;
+        self.admitted_fills += other.admitted_fills;
+        self.rejected_fills += other.rejected_fills;
+        self.flushes += other.flushes;
+        self.flushed_lines += other.flushed_lines;
+        self.flush_write_backs += other.flush_write_backs;
+        self.pinned_hits += other.pinned_hits;
+        self.spared_evictions += other.spared_evictions;
+        self.saved_misses += other.saved_misses;
+        self.pin_bypasses += other.pin_bypasses;
+        for (&thread, stats) in &other.threads {
+            let total = self.threads.entry(thread).or_default();
+            total.hits += stats.hits;
+            total.misses += stats.misses;
+            total.evictions += stats.evictions;
+        }
+    }
+}
+
+/// Seed used for random tie-breaking unless another one is set.
+pub const DEFAULT_SEED: u64 = 0x2545_f491_4f6c_dd1d;
+
+/// Policy choosing the line a miss evicts from a full set.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default, Serialize, Deserialize)]
+pub enum Replacement {
+    /// The least recently used line
+    #[default]
+    Lru,
+    /// The line filled first, whatever its hits since
+    Fifo,
+    /// The most recently used line, which keeps most of a loop over more blocks than the set holds
+    Mru,
+    /// Segmented LRU: the least recently used line of the probationary segment lines are filled into, hits moving
+    /// lines to a protected segment whose least recently used line falls back when it is full
+    Slru,
+    /// 2Q: the oldest line of the A1in queue first-time misses are filled into while it holds more than its share,
+    /// otherwise the least recently used line of the Am queue, which misses on the blocks A1in last evicted fill
+    TwoQ,
+    /// Low inter-reference recency set: the oldest of the few lines holding HIR blocks, those not reused since the
+    /// least recently used of the LIR blocks that fill the other lines. A block reused more recently than that becomes
+    /// LIR in its place, evicted blocks being remembered as long as they could still do so
+    Lirs,
+    /// A random line, drawn with the seed of the cache
+    Random,
+    /// The line a binary tree of one bit per node points to, each access turning the bits on its path away from it
+    Plru,
+    /// Static re-reference interval prediction: a line with the highest 2-bit prediction value, lines being filled
+    /// with the second highest and hits resetting it to zero
+    Srrip,
+    /// Bimodal RRIP: as SRRIP, but filling lines with the highest value, except once every 32 fills on average
+    Brrip,
+    /// Dynamic RRIP: SRRIP or BRRIP, whichever missed less in the leader sets dueling for them
+    Drrip,
+    /// Signature-based hit prediction by program counter: SRRIP, but filling lines with the highest value when the
+    /// lines last filled by the same instruction were evicted without a hit
+    ShipPc,
+    /// Signature-based hit prediction by memory region: as ShipPc, with the 16 KiB region of the block as signature
+    ShipMem,
+    /// Hawkeye: lines loaded by instructions whose blocks Belady's optimal policy would have kept, as replayed on a
+    /// sample of the sets, are filled with the lowest value and the others with the highest, which is evicted first
+    Hawkeye,
+}
+
+impl Replacement {
+    // Whether the policy evicts by re-reference prediction values
+    fn is_rrip(self) -> bool {
+        matches!(self, Replacement::Srrip | Replacement::Brrip | Replacement::Drrip | Replacement::ShipPc | Replacement::ShipMem | Replacement::Hawkeye)
+    }
+
+    // Whether an adaptive cache can switch to and from the policy, which keeps no state beyond that of the lines
+    fn is_switchable(self) -> bool {
+        matches!(self, Replacement::Lru | Replacement::Fifo | Replacement::Mru | Replacement::Random | Replacement::Srrip | Replacement::Brrip)
+    }
+}
+
+impl std::str::FromStr for Replacement {
+    type Err = SimError;
+
+    fn from_str(value: &str) -> Result<Replacement, SimError> {
+        match value {
+            "lru" => Ok(Replacement::Lru),
+            "fifo" => Ok(Replacement::Fifo),
+            "mru" => Ok(Replacement::Mru),
+            "slru" => Ok(Replacement::Slru),
+            "2q" => Ok(Replacement::TwoQ),
+            "lirs" => Ok(Replacement::Lirs),
+            "random" => Ok(Replacement::Random),
+            "plru" => Ok(Replacement::Plru),
+            "srrip" => Ok(Replacement::Srrip),
+            "brrip" => Ok(Replacement::Brrip),
+            "drrip" => Ok(Replacement::Drrip),
+            "ship-pc" => Ok(Replacement::ShipPc),
+            "ship-mem" => Ok(Replacement::ShipMem),
+            "hawkeye" => Ok(Replacement::Hawkeye),
+            _ => Err(SimError::Config(format!("unknown replacement policy: {}", value))),
+        }
+    }
+}
+
+impl fmt::Display for Replacement {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Replacement::Lru => "lru",
+            Replacement::Fifo => "fifo",
+            Replacement::Mru => "mru",
+            Replacement::Slru => "slru",
+            Replacement::TwoQ => "2q",
+            Replacement::Lirs => "lirs",
+            Replacement::Random => "random",
+            Replacement::Plru => "plru",
+            Replacement::Srrip => "srrip",
+            Replacement::Brrip => "brrip",
+            Replacement::Drrip => "drrip",
+            Replacement::ShipPc => "ship-pc",
+            Replacement::ShipMem => "ship-mem",
+            Replacement::Hawkeye => "hawkeye",
+        })
+    }
+}
+
+/// Position in the recency order of the LRU and tree-PLRU policies a filled line takes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum Insertion {
+    /// The most recently used position, as any access
+    #[default]
+    Mru,
+    /// LRU insertion: the least recently used position, so a line not hit before the next miss is evicted by it
+    Lip,
+    /// Bimodal insertion: as LIP, but the most recently used position once every 32 fills on average
+    Bip,
+}
+
+impl std::str::FromStr for Insertion {
+    type Err = SimError;
+
+    fn from_str(value: &str) -> Result<Insertion, SimError> {
+        match value {
+            "mru" => Ok(Insertion::Mru),
+            "lip" => Ok(Insertion::Lip),
+            "bip" => Ok(Insertion::Bip),
+            _ => Err(SimError::Config(format!("unknown insertion policy: {}", value))),
+        }
+    }
+}
+
+impl fmt::Display for Insertion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Insertion::Mru => "mru",
+            Insertion::Lip => "lip",
+            Insertion::Bip => "bip",
+        })
+    }
+}
+
+/// Rule for choosing between several equally eligible lines.
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+pub enum TieBreak {
+    #[default]
+    Lowest,
+    Random,
+    RoundRobin,
+}
+
+impl std::str::FromStr for TieBreak {
+    type Err = SimError;
+
+    fn from_str(value: &str) -> Result<TieBreak, SimError> {
+        match value {
+            "lowest" => Ok(TieBreak::Lowest),
+            "random" => Ok(TieBreak::Random),
+            "round-robin" => Ok(TieBreak::RoundRobin),
+            _ => Err(SimError::Config(format!("unknown tie-break rule: {}", value))),
+        }
+    }
+}
+
+impl fmt::Display for TieBreak {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            TieBreak::Lowest => "lowest",
+            TieBreak::Random => "random",
+            TieBreak::RoundRobin => "round-robin",
+        })
+    }
+}
+
+/// Handling of addresses that don't fit in the modeled address width.
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+pub enum OobPolicy {
+    /// Drop the high bits and simulate the access
+    Mask,
+    /// Don't simulate the access
+    Skip,
+    /// Stop the simulation
+    #[default]
+    Error,
+}
+
+impl std::str::FromStr for OobPolicy {
+    type Err = SimError;
+
+    fn from_str(value: &str) -> Result<OobPolicy, SimError> {
+        match value {
+            "mask" => Ok(OobPolicy::Mask),
+            "skip" => Ok(OobPolicy::Skip),
+            "error" => Ok(OobPolicy::Error),
+            _ => Err(SimError::Config(format!("unknown out-of-range address policy: {}", value))),
+        }
+    }
+}
+
+impl fmt::Display for OobPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            OobPolicy::Mask => "mask",
+            OobPolicy::Skip => "skip",
+            OobPolicy::Error => "error",
+        })
+    }
+}
+
+// Number of low tag bits compared by the partial-tag way predictor
+const PARTIAL_TAG_BITS: usize = 4;
+
+/// Way predictor consulted before the full tag lookup of a set.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum WayPredictor {
+    /// Predict the most recently used way of the set
+    Mru,
+    /// Predict the first valid way whose low tag bits match
+    PartialTag,
+}
+
+impl std::str::FromStr for WayPredictor {
+    type Err = SimError;
+
+    fn from_str(value: &str) -> Result<WayPredictor, SimError> {
+        match value {
+            "mru" => Ok(WayPredictor::Mru),
+            "partial-tag" => Ok(WayPredictor::PartialTag),
+            _ => Err(SimError::Config(format!("unknown way predictor: {}", value))),
+        }
+    }
+}
+
+impl fmt::Display for WayPredictor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            WayPredictor::Mru => "mru",
+            WayPredictor::PartialTag => "partial-tag",
+        })
+    }
+}
+
+/// Ways locked against replacement, in every set or in a range of sets. A locked way keeps the first block that
+/// fills it, as if the block had been loaded and then locked.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WayLock {
+    pub ways: RangeInclusive<usize>,
+    pub sets: Option<RangeInclusive<usize>>, // All sets if None
+}
+
+/// Column names of the rows written by [`Cache::csv_row`].
+pub const CSV_HEADER: &str = "trace,warmup,s,E,b,policy,insertion,tie_break,skewed,way_predictor,address_bits,oob_policy,partitions,banks,issue_width,\
+compressibility,write_buffer,drain_interval,store_buffer,tinylfu,uncacheable,lock_ways,scratchpad,bypass,protected_ways,hits,misses,evictions,miss_rate,fast_hits,slow_hits,oob_accesses,bank_cycles,bank_conflicts,\
+baseline_misses,compulsory_misses,capacity_misses,conflict_misses,write_backs,write_buffer_hits,write_buffer_stalls,buffered_stores,\
+coalesced_stores,forwarded_loads,admitted_fills,rejected_fills,prefetch_hits,prefetch_fills,uncached_accesses,scratchpad_accesses,flushes,\
+flushed_lines,flush_write_backs,pinned_hits,spared_evictions,saved_misses,pin_bypasses,bypassed_fills,bypass_reuses,policy_switches,distant_fills,\
+promotions,ghost_hits,lifetime_min,lifetime_mean,lifetime_p50,lifetime_p90,lifetime_p99,lifetime_max";
+
+impl Cache {
+    /// Create an empty cache with 2^s sets of e lines and 2^b-byte blocks. Use [`CacheBuilder`] to configure anything more.
+    pub fn new(s: usize, e: usize, b: usize) -> Result<Cache, SimError> {
+        // The set index and block offset are cut out of 64-bit addresses
+        if s.checked_add(b).is_none_or(|bits| bits > 64) {
+            return Err(SimError::Config(format!("{} set index bits and {} block bits exceed a 64-bit address", s, b)));
+        }
+        // Calculate total cache size: 2^s * 2^b * E
+        match usize::checked_pow(2, s.try_into().unwrap()).and_then(|sets| {
+            usize::checked_pow(2, b.try_into().unwrap()).and_then(|blocks| {
+                sets.checked_mul(blocks).and_then(|sets_blocks| sets_blocks.checked_mul(e))})
+        }) {
+            Some(_size) => {
+                let mut sets = Vec::with_capacity(2usize.pow(s as u32));
+                for _ in 0..2usize.pow(s as u32) {
+                    let mut lines = Vec::with_capacity(e);
+                    for _ in 0..e {
+                        lines.push(Line { 
+                            tag: None, 
+                            is_valid: false,
+                            dirty: false,
+                            last_used: 0,
+                            filled_at: 0,
+                            pinned: false,
+                            spared: false,
+                            reused: false,
+                            rrpv: 0,
+                            signature: 0,
+                            protected: false,
+                        });
+                    }
+                    sets.push(Set { 
+                        lines, 
+                        access_order: VecDeque::new(),
+                        next_way: 0,
+                        stats: SetStats::default(),
+                        plru: Vec::new(),
+                    });
+                }
+                Ok(Cache { 
+                    sets, 
+                    hits: 0, 
+                    misses: 0, 
+                    evictions: 0,
+                    replacement: Replacement::Lru,
+                    insertion: Insertion::Mru,
+                    tie_break: TieBreak::Lowest,
+                    rng: Rng::new(DEFAULT_SEED),
+                    set_bits: s,
+                    block_bits: b,
+                    skewed: false,
+                    clock: 0,
+                    way_predictor: None,
+                    fast_hits: 0,
+                    slow_hits: 0,
+                    address_bits: 64,
+                    oob_policy: OobPolicy::Error,
+                    oob_accesses: 0,
+                    prefetch_hits: 0,
+                    prefetch_fills: 0,
+                    uncacheable: Vec::new(),
+                    uncached_accesses: 0,
+                    scratchpad: None,
+                    scratchpad_ways: 0,
+                    scratchpad_accesses: 0,
+                    flushes: 0,
+                    flushed_lines: 0,
+                    flush_write_backs: 0,
+                    dirty_evictions: 0,
+                    pinned_hits: 0,
+                    spared_evictions: 0,
+                    saved_misses: 0,
+                    pin_bypasses: 0,
+                    locked_ways: Vec::new(),
+                    thread: 0,
+                    pc: 0,
+                    thread_stats: BTreeMap::new(),
+                    way_partitions: HashMap::new(),
+                    banks: None,
+                    write_buffer: None,
+                    store_buffer: None,
+                    bypass: None,
+                    tinylfu: None,
+                    dueling: None,
+                    adaptive: None,
+                    ship: None,
+                    hawkeye: None,
+                    slru: None,
+                    two_queue: None,
+                    lirs: None,
+                    compression: None,
+                    undo_depth: 0,
+                    history: VecDeque::new(),
+                    resets: 0,
+                    roi_stats: Stats::default(),
+                    miss_classifier: None,
+                    hot_blocks: None,
+                    lookups: 0,
+                    lifetimes: None,
+                    mute_log: false,
+                })
+            }
+            None => {
+                Err(SimError::Config("cache size exceeds available space (overflow)".to_string()))
+            }
+        }
+    }
+
+    /// Number of sets.
+    pub fn set_count(&self) -> usize {
+        self.sets.len()
+    }
+
+    /// Number of lines in each set (a compressed cache holds up to twice as many tags).
+    pub fn associativity(&self) -> usize {
+        if self.compression.is_some() {
+            self.sets[0].lines.len() / 2
+        } else {
+            self.sets[0].lines.len()
+        }
+    }
+
+    /// Block size in bytes.
+    pub fn block_size(&self) -> usize {
+        1 << self.block_bits
+    }
+
+    /// Policy choosing the line a miss evicts.
+    pub fn replacement(&self) -> Replacement {
+        self.replacement
+    }
+
+    /// Position filled lines take in the recency order.
+    pub fn insertion(&self) -> Insertion {
+        self.insertion
+    }
+
+    /// Lines of each set the protected segment holds under SLRU.
+    pub fn protected_ways(&self) -> Option<usize> {
+        self.slru.as_ref().map(|slru| slru.protected_ways)
+    }
+
+    /// A1out ghost queues of a 2Q cache, with the misses they sent to Am.
+    pub fn two_queue(&self) -> Option<&TwoQueue> {
+        self.two_queue.as_ref()
+    }
+
+    /// TinyLFU admission filter of the cache, with the misses it admitted and rejected.
+    pub fn tinylfu(&self) -> Option<&TinyLfu> {
+        self.tinylfu.as_ref()
+    }
+
+    /// LIRS state of a LIRS cache: the recency stack and HIR queue of each set, and how its hits and misses went.
+    pub fn lirs(&self) -> Option<&Lirs> {
+        self.lirs.as_ref()
+    }
+
+    /// Whether each way indexes the sets with its own hash.
+    pub fn is_skewed(&self) -> bool {
+        self.skewed
+    }
+
+    /// Number of hits so far.
+    pub fn hits(&self) -> usize {
+        self.hits
+    }
+
+    /// Number of misses so far.
+    pub fn misses(&self) -> usize {
+        self.misses
+    }
+
+    /// Number of evictions so far.
+    pub fn evictions(&self) -> usize {
+        self.evictions
+    }
+
+    /// Number of prefetches of blocks that were already cached so far.
+    pub fn prefetch_hits(&self) -> usize {
+        self.prefetch_hits
+    }
+
+    /// Number of prefetches that brought a block into the cache so far.
+    pub fn prefetch_fills(&self) -> usize {
+        self.prefetch_fills
+    }
+
+    /// Number of dirty lines written back so far, evicted or flushed.
+    pub fn write_backs(&self) -> usize {
+        self.dirty_evictions + self.flush_write_backs
+    }
+
+    /// Pin the block of an address so that the replacement policy never evicts it, bringing it in like a prefetch
+    /// if it isn't cached. Returns whether the block is pinned: uncacheable blocks, and blocks whose lines are all
+    /// pinned already, can't be.
+    ///
+    /// ```
+    /// use sim::Cache;
+    ///
+    /// let mut cache = Cache::new(0, 2, 4).unwrap();
+    /// assert_eq!(cache.pin(0x0), Ok(true));
+    /// for address in [0x10, 0x20, 0x0] {
+    ///     cache.simulate_address('L', address).unwrap();
+    /// }
+    /// assert_eq!((cache.hits(), cache.misses()), (1, 2));
+    /// ```
+    pub fn pin(&mut self, address: u64) -> Result<bool, SimError> {
+        self.simulate_address('K', address)?;
+        Ok(self.locate(address).is_some_and(|(set_index, way)| self.sets[set_index].lines[way].pinned))
+    }
+
+    /// Unpin the block of an address, letting the replacement policy evict it again.
+    pub fn unpin(&mut self, address: u64) -> Result<(), SimError> {
+        self.simulate_address('U', address)
+    }
+
+    /// Number of lines pinned.
+    pub fn pinned_lines(&self) -> usize {
+        self.sets.iter().flat_map(|set| &set.lines).filter(|line| line.is_valid && line.pinned).count()
+    }
+
+    /// Number of accesses to uncacheable regions so far, which bypassed the cache.
+    pub fn uncached_accesses(&self) -> usize {
+        self.uncached_accesses
+    }
+
+    /// Set dueling state of a DRRIP or adaptive cache: the leader sets, the policy-selection counter and the policy
+    /// that won.
+    pub fn dueling(&self) -> Option<&SetDueling> {
+        self.dueling.as_ref().or(self.adaptive.as_ref().map(|adaptive| &adaptive.dueling))
+    }
+
+    /// Two policies an adaptive cache switches between, the first being used by the follower sets at the start.
+    pub fn adaptive_policies(&self) -> Option<[Replacement; 2]> {
+        self.adaptive.as_ref().map(|adaptive| adaptive.policies)
+    }
+
+    /// Latest switches of an adaptive cache, oldest first: the lookup the followers switched at and their new policy.
+    pub fn switch_points(&self) -> Vec<(u64, Replacement)> {
+        self.adaptive.as_ref().map_or_else(Vec::new, |adaptive| adaptive.switch_points.iter().copied().collect())
+    }
+
+    /// Signature history counter table of a SHiP cache, with the fills it predicted not to be reused.
+    pub fn ship(&self) -> Option<&SignatureTable> {
+        self.ship.as_ref()
+    }
+
+    /// Hawkeye state of a Hawkeye cache: how often OPT would have hit on the sampled sets, and the fills predicted
+    /// cache-averse.
+    pub fn hawkeye(&self) -> Option<&Hawkeye> {
+        self.hawkeye.as_ref()
+    }
+
+    /// Number of lookups served by the scratchpad so far, a modify or atomic counting as two like in the cache.
+    pub fn scratchpad_accesses(&self) -> usize {
+        self.scratchpad_accesses
+    }
+
+    /// Hits, misses and evictions of each thread seen so far.
+    pub fn thread_stats(&self) -> &BTreeMap<usize, ThreadStats> {
+        &self.thread_stats
+    }
+
+    /// Hits, misses and evictions of each set so far, by set index. In skewed mode an access counts in the
+    /// set of the way that held or received the block.
+    pub fn set_stats(&self) -> Vec<SetStats> {
+        self.sets.iter().map(|set| set.stats).collect()
+    }
+
+    // Mute the logging of the baseline of a compressed cache, which checkpoints don't record
+    pub(crate) fn mute_baseline_log(&mut self) {
+        if let Some(compression) = &mut self.compression {
+            compression.baseline.mute_log = true;
+        }
+    }
+
+    // Check that restored state has the shape the simulation relies on
+    pub(crate) fn check_shape(&self) -> Result<(), SimError> {
+        let ways = self.sets.first().map_or(0, |set| set.lines.len());
+        let consistent = self.set_bits < usize::BITS as usize
+            && self.sets.len() == 1 << self.set_bits
+            && ways > 0
+            && self.sets.iter().all(|set| set.lines.len() == ways && set.access_order.iter().all(|&way| way < ways))
+            && (self.replacement != Replacement::Plru || ways.is_power_of_two() && self.sets.iter().all(|set| set.plru.len() == ways - 1))
+            && (self.replacement == Replacement::Drrip) == self.dueling.as_ref().is_some_and(|dueling| dueling.sets() == self.sets.len())
+            && matches!(self.replacement, Replacement::ShipPc | Replacement::ShipMem) == self.ship.is_some()
+            && (self.replacement == Replacement::Hawkeye) == self.hawkeye.as_ref().is_some_and(|hawkeye| hawkeye.sets() == self.sets.len())
+            && (self.replacement == Replacement::Slru) == self.slru.as_ref().is_some_and(|slru| slru.protected_ways < ways.max(2))
+            && (self.replacement == Replacement::TwoQ) == self.two_queue.as_ref().is_some_and(|queues| queues.sets() == self.sets.len())
+            && (self.replacement == Replacement::Lirs) == self.lirs.as_ref().is_some_and(|lirs| lirs.sets() == self.sets.len())
+            && self.adaptive.as_ref().is_none_or(|adaptive| {
+                adaptive.dueling.sets() == self.sets.len() && adaptive.policies.iter().all(|policy| policy.is_switchable())
+            })
+            && self.ship.as_ref().map(SignatureTable::signatures).or(self.hawkeye.as_ref().map(Hawkeye::signatures))
+                .is_none_or(|signatures| self.sets.iter().flat_map(|set| &set.lines).all(|line| usize::from(line.signature) < signatures))
+            && self.banks.as_ref().is_none_or(|banks| banks.count.is_power_of_two() && banks.group_accesses.len() == banks.count)
+            && self.write_buffer.as_ref().is_none_or(|buffer| buffer.capacity > 0 && buffer.drain_interval > 0 && buffer.blocks.len() <= buffer.capacity)
+            && self.store_buffer.as_ref().is_none_or(|buffer| buffer.capacity > 0 && buffer.entries.len() <= buffer.capacity)
+            && self.compression.as_ref().is_none_or(|compression| compression.baseline.check_shape().is_ok());
+        if consistent {
+            Ok(())
+        } else {
+            Err(SimError::Config("inconsistent cache state in checkpoint".to_string()))
+        }
+    }
+
+    /// Current values of all counters.
+    pub fn snapshot_stats(&self) -> Stats {
+        Stats {
+            hits: self.hits,
+            misses: self.misses,
+            evictions: self.evictions,
+            fast_hits: self.fast_hits,
+            slow_hits: self.slow_hits,
+            oob_accesses: self.oob_accesses,
+            bank_cycles: self.banks.as_ref().map_or(0, |banks| banks.cycles),
+            bank_conflicts: self.banks.as_ref().map_or(0, |banks| banks.conflicts),
+            baseline_misses: self.compression.as_ref().map_or(0, |compression| compression.baseline.misses),
+            threads: self.thread_stats.clone(),
+            compulsory_misses: self.miss_classifier.as_ref().map_or(0, |classifier| classifier.compulsory),
+            capacity_misses: self.miss_classifier.as_ref().map_or(0, |classifier| classifier.capacity),
+            conflict_misses: self.miss_classifier.as_ref().map_or(0, |classifier| classifier.conflict),
+            prefetch_hits: self.prefetch_hits,
+            prefetch_fills: self.prefetch_fills,
+            uncached_accesses: self.uncached_accesses,
+            scratchpad_accesses: self.scratchpad_accesses,
+            write_backs: self.write_buffer.as_ref().map_or(0, |buffer| buffer.write_backs),
+            write_buffer_hits: self.write_buffer.as_ref().map_or(0, |buffer| buffer.hits),
+            write_buffer_stalls: self.write_buffer.as_ref().map_or(0, |buffer| buffer.stalls),
+            buffered_stores: self.store_buffer.as_ref().map_or(0, |buffer| buffer.stores),
+            coalesced_stores: self.store_buffer.as_ref().map_or(0, |buffer| buffer.coalesced),
+            forwarded_loads: self.store_buffer.as_ref().map_or(0, |buffer| buffer.forwards),
+            bypassed_fills: self.bypass.as_ref().map_or(0, |bypass| bypass.bypasses),
+            bypass_reuses: self.bypass.as_ref().map_or(0, |bypass| bypass.reuses),
+            policy_switches: self.dueling.as_ref().map_or(0, SetDueling::switches) + self.adaptive.as_ref().map_or(0, |adaptive| adaptive.dueling.switches()),
+            distant_fills: self.ship.as_ref().map_or(0, SignatureTable::distant_fills) + self.hawkeye.as_ref().map_or(0, Hawkeye::averse_fills),
+            promotions: self.slru.as_ref().map_or(0, |slru| slru.promotions),
+            ghost_hits: self.two_queue.as_ref().map_or(0, TwoQueue::ghost_hits) + self.lirs.as_ref().map_or(0, Lirs::ghost_hits),
+            admitted_fills: self.tinylfu.as_ref().map_or(0, TinyLfu::admitted),
+            rejected_fills: self.tinylfu.as_ref().map_or(0, TinyLfu::rejected),
+            flushes: self.flushes,
+            flushed_lines: self.flushed_lines,
+            flush_write_backs: self.flush_write_backs,
+            pinned_hits: self.pinned_hits,
+            spared_evictions: self.spared_evictions,
+            saved_misses: self.saved_misses,
+            pin_bypasses: self.pin_bypasses,
+        }
+    }
+
+    /// Zero all counters, including the region-of-interest totals, while keeping the cache contents warm.
+    /// Undo information is dropped, since stepping back would restore the old counters.
+    pub fn reset_stats(&mut self) {
+        self.hits = 0;
+        self.misses = 0;
+        self.evictions = 0;
+        self.fast_hits = 0;
+        self.slow_hits = 0;
+        self.oob_accesses = 0;
+        self.prefetch_hits = 0;
+        self.prefetch_fills = 0;
+        self.uncached_accesses = 0;
+        self.scratchpad_accesses = 0;
+        (self.flushes, self.flushed_lines, self.flush_write_backs, self.dirty_evictions) = (0, 0, 0, 0);
+        (self.pinned_hits, self.spared_evictions, self.saved_misses, self.pin_bypasses) = (0, 0, 0, 0);
+        self.thread_stats.clear();
+        self.sets.iter_mut().for_each(|set| set.stats = SetStats::default());
+        if let Some(banks) = &mut self.banks {
+            *banks = Banks::new(banks.count, banks.issue_width);
+        }
+        if let Some(buffer) = &mut self.write_buffer {
+            (buffer.write_backs, buffer.hits, buffer.stalls) = (0, 0, 0);
+        }
+        if let Some(buffer) = &mut self.store_buffer {
+            (buffer.stores, buffer.coalesced, buffer.forwards) = (0, 0, 0);
+        }
+        if let Some(bypass) = &mut self.bypass {
+            (bypass.bypasses, bypass.reuses) = (0, 0);
+        }
+        if let Some(tinylfu) = &mut self.tinylfu {
+            tinylfu.reset_stats();
+        }
+        if let Some(dueling) = &mut self.dueling {
+            dueling.reset_stats();
+        }
+        if let Some(adaptive) = &mut self.adaptive {
+            adaptive.dueling.reset_stats();
+            adaptive.switch_points.clear();
+        }
+        if let Some(ship) = &mut self.ship {
+            ship.reset_stats();
+        }
+        if let Some(hawkeye) = &mut self.hawkeye {
+            hawkeye.reset_stats();
+        }
+        if let Some(slru) = &mut self.slru {
+            (slru.promotions, slru.demotions) = (0, 0);
+        }
+        if let Some(two_queue) = &mut self.two_queue {
+            two_queue.reset_stats();
+        }
+        if let Some(lirs) = &mut self.lirs {
+            lirs.reset_stats();
+        }
+        if let Some(compression) = &mut self.compression {
+            compression.baseline.reset_stats();
+        }
+        if let Some(classifier) = &mut self.miss_classifier {
+            (classifier.compulsory, classifier.capacity, classifier.conflict) = (0, 0, 0);
+        }
+        if let Some(hot_blocks) = &mut self.hot_blocks {
+            *hot_blocks = HotBlocks::new(hot_blocks.capacity);
+        }
+        if let Some(lifetimes) = &mut self.lifetimes {
+            lifetimes.clear();
+        }
+        self.history.clear();
+        self.resets += 1;
+        self.roi_stats = Stats::default();
+    }
+
+    /// Check the consistency of the cache contents and counters, for tests and debugging: sets hold each tag at most
+    /// once, invalid lines are empty, the LRU order lists exactly the valid lines, and the per-set, per-thread and
+    /// miss-class counters add up to the totals. Returns the first inconsistency found.
+    pub fn check_invariants(&self) -> Result<(), SimError> {
+        let broken = |message: String| Err(SimError::Access(format!("invariant broken: {}", message)));
+        let ways = self.sets[0].lines.len();
+        let mut blocks = HashSet::new();
+        for (set_index, set) in self.sets.iter().enumerate() {
+            if set.lines.len() != ways {
+                return broken(format!("set {} has {} lines instead of {}", set_index, set.lines.len(), ways));
+            }
+            let mut tags = HashSet::new();
+            for (way, line) in set.lines.iter().enumerate() {
+                match (line.is_valid, line.tag) {
+                    (true, None) => return broken(format!("set {} way {} is valid without a tag", set_index, way)),
+                    (false, Some(_)) => return broken(format!("set {} way {} is invalid but keeps a tag", set_index, way)),
+                    (false, None) if line.dirty => return broken(format!("set {} way {} is invalid but dirty", set_index, way)),
+                    (false, None) if line.pinned => return broken(format!("set {} way {} is invalid but pinned", set_index, way)),
+                    (false, None) if line.protected => return broken(format!("set {} way {} is invalid but protected", set_index, way)),
+                    // Skewed lines hold whole block addresses, each of which may only be in one way across the sets
+                    (true, Some(tag)) if !(if self.skewed { blocks.insert(tag) } else { tags.insert(tag) }) => {
+                        return broken(format!("set {} holds tag {:#x} twice", set_index, tag));
+                    }
+                    _ if line.rrpv > RRPV_MAX => return broken(format!("set {} way {} has prediction value {}", set_index, way, line.rrpv)),
+                    _ => {}
+                }
+            }
+            // Skewed caches order lines by their last use instead
+            let mut order: Vec<usize> = set.access_order.iter().copied().collect();
+            order.sort_unstable();
+            let valid: Vec<usize> = if self.skewed { Vec::new() } else { (0..ways).filter(|&way| set.lines[way].is_valid).collect() };
+            if order != valid {
+                return broken(format!("set {} orders ways {:?} but holds valid ways {:?}", set_index, set.access_order, valid));
+            }
+            let protected = set.lines.iter().filter(|line| line.protected).count();
+            let protectable = if self.two_queue.is_some() { ways } else { self.slru.as_ref().map_or(0, |slru| slru.protected_ways) };
+            if protected > protectable {
+                return broken(format!("set {} protects {} lines", set_index, protected));
+            }
+            if let Some(lirs) = &self.lirs {
+                let mut resident: Vec<u64> = lirs.resident(set_index).collect();
+                resident.sort_unstable();
+                let mut held: Vec<u64> = set.lines.iter().filter(|line| line.is_valid).filter_map(|line| line.tag).map(|tag| tag as u64).collect();
+                held.sort_unstable();
+                if resident != held {
+                    return broken(format!("set {} holds tags {:x?} but LIRS has {:x?} resident", set_index, held, resident));
+                }
+            }
+            let plru_nodes = if self.replacement == Replacement::Plru { ways - 1 } else { 0 };
+            if set.plru.len() != plru_nodes {
+                return broken(format!("set {} has {} PLRU bits instead of {}", set_index, set.plru.len(), plru_nodes));
+            }
+            if self.compression.is_some() {
+                let used: usize = set.lines.iter().filter_map(|line| line.tag).map(|tag| self.compressed_size(set_index, tag)).sum();
+                if used > ways / 2 * SEGMENTS_PER_LINE {
+                    return broken(format!("set {} stores {} segments in {} lines", set_index, used, ways / 2));
+                }
+            }
+        }
+
+        let totals = (self.hits, self.misses, self.evictions);
+        let set_totals = self.sets.iter().fold((0, 0, 0), |(hits, misses, evictions), set| {
+            (hits + set.stats.hits, misses + set.stats.misses, evictions + set.stats.evictions)
+        });
+        if set_totals != totals {
+            return broken(format!("the sets count {:?} hits, misses and evictions but the cache {:?}", set_totals, totals));
+        }
+        let thread_totals = self.thread_stats.values().fold((0, 0, 0), |(hits, misses, evictions), stats| {
+            (hits + stats.hits, misses + stats.misses, evictions + stats.evictions)
+        });
+        if thread_totals != totals {
+            return broken(format!("the threads count {:?} hits, misses and evictions but the cache {:?}", thread_totals, totals));
+        }
+        if self.way_predictor.is_some() && self.fast_hits + self.slow_hits != self.hits {
+            return broken(format!("{} fast and {} slow hits out of {}", self.fast_hits, self.slow_hits, self.hits));
+        }
+        if let Some(classifier) = &self.miss_classifier {
+            let classified = classifier.compulsory + classifier.capacity + classifier.conflict;
+            if classified != self.misses {
+                return broken(format!("{} misses classified out of {}", classified, self.misses));
+            }
+        }
+        match &self.compression {
+            Some(compression) => compression.baseline.check_invariants(),
+            None => Ok(()),
+        }
+    }
+
+    /// Open a region of interest: accesses simulated through the guard are added to [`Cache::roi_stats`]
+    /// when it is dropped.
+    ///
+    /// ```
+    /// use sim::Cache;
+    ///
+    /// let mut cache = Cache::new(4, 2, 4).unwrap();
+    /// cache.simulate_address('L', 0x10).unwrap(); // Warm-up, not measured
+    /// {
+    ///     let mut roi = cache.roi();
+    ///     roi.simulate_address('L', 0x10).unwrap();
+    ///     roi.simulate_address('L', 0x20).unwrap();
+    /// }
+    /// assert_eq!((cache.roi_stats().hits, cache.roi_stats().misses), (1, 1));
+    /// ```
+    pub fn roi(&mut self) -> Roi<'_> {
+        let start = self.snapshot_stats();
+        let resets = self.resets;
+        Roi { cache: self, start, resets }
+    }
+
+    /// Statistics accumulated over all closed regions of interest.
+    pub fn roi_stats(&self) -> &Stats {
+        &self.roi_stats
+    }
+
+    /// Simulate an 'L', 'S', 'M', 'A' or 'P' access to the tag in a set, updating the cache and statistics. An 'A'
+    /// atomic read-modify-write is simulated as a modify, and a 'P' prefetch fills the cache like a load but counts
+    /// in the prefetch statistics instead of the hits and misses.
+    pub fn simulate_memory_access(&mut self, operation: char, set_index: usize, tag: usize) -> Result<(), SimError> {
+        match operation {
+            'L' | 'S' | 'P' => {
+                if set_index >= self.sets.len() {
+                    return Err(SimError::Access("failed to access cache set".to_string()));
+                }
+                self.lookups += 1;
+                if let Some(tinylfu) = self.tinylfu.as_mut().filter(|_| operation != 'P') {
+                    tinylfu.record((tag as u64) << self.set_bits | set_index as u64);
+                }
+
+                let predicted_way = self.predict_way(set_index, tag);
+                if let Some(hawkeye) = self.hawkeye.as_mut().filter(|_| operation != 'P') {
+                    hawkeye.train(set_index, tag as u64, hawkeye.signature(self.pc));
+                }
+
+                // If a valid line holds the tag, it's a hit
+                if let Some(index) = self.sets[set_index].lines.iter().position(|line| line.is_valid && line.tag == Some(tag)) {
+                    self.sets[set_index].lines[index].dirty |= operation == 'S';
+                    self.sets[set_index].lines[index].reused |= operation != 'P';
+                    self.sets[set_index].lines[index].rrpv = 0;
+                    if let Some(ship) = self.ship.as_mut().filter(|_| operation != 'P') {
+                        ship.record_hit(self.sets[set_index].lines[index].signature);
+                    }
+                    if let Some(hawkeye) = &self.hawkeye {
+                        // Hits predict again from the instruction making them
+                        let line = &mut self.sets[set_index].lines[index];
+                        line.signature = hawkeye.signature(self.pc);
+                        line.rrpv = if hawkeye.is_friendly(line.signature) { 0 } else { RRPV_MAX };
+                    }
+                    self.log(LogLevel::Trace, format_args!("hit set:{} way:{} tag:{:#x}", set_index, index, tag));
+                    self.record_hit(operation, set_index);
+                    self.record_pinned_hit(operation, set_index, index);
+                    if operation != 'P' {
+                        self.record_way_prediction(predicted_way, index);
+                        self.promote(set_index, index);
+                        if let Some(lirs) = &mut self.lirs {
+                            lirs.hit(set_index, tag as u64);
+                        }
+                    }
+                    self.update_access_order(set_index, index);
+                    return Ok(());
+                }
+
+                if self.compression.is_some() {
+                    return self.fill_compressed(operation, set_index, tag);
+                }
+                let bypass = self.predict_bypass(operation, set_index, tag);
+
+                // If there are empty lines, the tag has not been found - it's a miss and fill one of the empty lines
+                let empty_lines: Vec<usize> = self.sets[set_index].lines.iter().enumerate()
+                    .filter(|(index, line)| !line.is_valid && self.may_fill(*index))
+                    .map(|(index, _)| index)
+                    .collect();
+                if !empty_lines.is_empty() {
+                    let index = self.break_tie(set_index, &empty_lines);
+                    self.sets[set_index].lines[index].tag = Some(tag);
+                    self.sets[set_index].lines[index].is_valid = true;
+                    self.sets[set_index].lines[index].dirty = operation == 'S';
+                    self.sets[set_index].lines[index].filled_at = self.lookups;
+                    self.sets[set_index].lines[index].reused = false;
+                    self.sets[set_index].lines[index].protected = self.recall_ghost(set_index, tag);
+                    self.sets[set_index].lines[index].signature = self.fill_signature(set_index, tag);
+                    self.sets[set_index].lines[index].rrpv = self.insertion_rrpv(set_index, self.sets[set_index].lines[index].signature);
+                    self.sets[set_index].lines[index].pinned = self.is_locked(set_index, index);
+                    if let Some(lirs) = &mut self.lirs {
+                        lirs.fill(set_index, tag as u64);
+                    }
+                    self.log_fill(set_index, index);
+                    self.record_miss(operation, set_index);
+                    self.insert_access_order(set_index, index);
+                    return Ok(());
+                }
+
+                // A fill into an empty line displaces nothing, so only fills that would evict are bypassed
+                if bypass {
+                    self.record_bypass(operation, set_index, tag);
+                    return Ok(());
+                }
+
+                // If no hit happened and no empty line was found, evict the line the policy chooses - it's an eviction and update the line tag
+                if let Some(evict_index) = self.choose_unpinned_victim(set_index) {
+                    if !self.admit(operation, set_index, evict_index, tag) {
+                        self.record_rejection(operation, set_index, tag);
+                        return Ok(());
+                    }
+                    self.record_eviction(set_index, evict_index);
+                    self.age_rrpvs(set_index, evict_index);
+                    self.sets[set_index].lines[evict_index].tag = Some(tag);
+                    self.sets[set_index].lines[evict_index].dirty = operation == 'S';
+                    self.sets[set_index].lines[evict_index].filled_at = self.lookups;
+                    self.sets[set_index].lines[evict_index].reused = false;
+                    self.sets[set_index].lines[evict_index].protected = self.recall_ghost(set_index, tag);
+                    self.sets[set_index].lines[evict_index].signature = self.fill_signature(set_index, tag);
+                    self.sets[set_index].lines[evict_index].rrpv = self.insertion_rrpv(set_index, self.sets[set_index].lines[evict_index].signature);
+                    if let Some(lirs) = &mut self.lirs {
+                        lirs.fill(set_index, tag as u64);
+                    }
+                    self.log_fill(set_index, evict_index);
+                    self.record_miss(operation, set_index);
+                    self.insert_access_order(set_index, evict_index);
+                    return Ok(());
+                }
+                if self.sets[set_index].lines.iter().any(|line| line.pinned) {
+                    self.record_pin_bypass(operation, set_index);
+                    return Ok(());
+                }
+                Err(SimError::Access("eviction failed".to_string()))
+            }
+            'M' | 'A' => {
+                // Simulate Load operation followed by Store operation
+                self.simulate_memory_access('L', set_index, tag)?;
+                self.simulate_memory_access('S', set_index, tag)
+            }
+            _ => {
+                Err(SimError::Access(format!("unknown operation: {}", operation)))
+            }
+        }
+    }
+
+    /// Simulate one access from a trace and report its hits, misses and evictions.
+    pub fn simulate_access(&mut self, access: MemoryAccess) -> Result<AccessResult, SimError> {
+        let (hits, misses, evictions) = (self.hits, self.misses, self.evictions);
+        let (prefetch_hits, prefetch_fills) = (self.prefetch_hits, self.prefetch_fills);
+        self.thread = access.thread;
+        self.pc = access.pc;
+        if matches!(access.operation, 'K' | 'U') {
+            // Pins cover every block of their size, so that a trace can pin a whole region
+            let last_block = access.address.saturating_add(u64::from(access.size.max(1)) - 1) >> self.block_bits;
+            for block in (access.address >> self.block_bits)..=last_block {
+                self.simulate_address(access.operation, (block << self.block_bits).max(access.address))?;
+            }
+        } else {
+            self.simulate_address(access.operation, access.address)?;
+        }
+        Ok(AccessResult {
+            access,
+            hits: self.hits - hits,
+            misses: self.misses - misses,
+            evictions: self.evictions - evictions,
+            prefetch_hits: self.prefetch_hits - prefetch_hits,
+            prefetch_fills: self.prefetch_fills - prefetch_fills,
+        })
+    }
+
+    /// Simulate a size-byte access at an address. Accesses that straddle a block boundary look up every
+    /// block they cover, and the result adds up all of them.
+    ///
+    /// ```
+    /// use sim::{Cache, Op};
+    ///
+    /// let mut cache = Cache::new(4, 1, 4).unwrap();
+    /// assert_eq!(cache.access(Op::Load, 0x1c, 8).unwrap().misses, 2);
+    /// assert_eq!(cache.access(Op::Store, 0x20, 4).unwrap().hits, 1);
+    /// ```
+    pub fn access(&mut self, op: Op, address: u64, size: u32) -> Result<AccessResult, SimError> {
+        if size == 0 {
+            return Err(SimError::Access("access size must be at least 1".to_string()));
+        }
+        let (hits, misses, evictions) = (self.hits, self.misses, self.evictions);
+        let (prefetch_hits, prefetch_fills) = (self.prefetch_hits, self.prefetch_fills);
+        self.thread = 0;
+        self.pc = 0;
+        let first_block = address >> self.block_bits;
+        let last_block = match op {
+            Op::FlushAll => first_block,
+            _ => address.saturating_add(size as u64 - 1) >> self.block_bits,
+        };
+        for block in first_block..=last_block {
+            let block_address = if block == first_block { address } else { block << self.block_bits };
+            self.simulate_address(op.into(), block_address)?;
+        }
+        Ok(AccessResult {
+            access: MemoryAccess { operation: op.into(), address, thread: 0, size, pc: 0 },
+            hits: self.hits - hits,
+            misses: self.misses - misses,
+            evictions: self.evictions - evictions,
+            prefetch_hits: self.prefetch_hits - prefetch_hits,
+            prefetch_fills: self.prefetch_fills - prefetch_fills,
+        })
+    }
+
+    /// Lazily simulate a sequence of accesses, yielding the result of each one as it is simulated.
+    pub fn drive<'a, I>(&'a mut self, accesses: I) -> impl Iterator<Item = Result<AccessResult, SimError>> + 'a
+    where
+        I: IntoIterator<Item = MemoryAccess>,
+        I::IntoIter: 'a,
+    {
+        accesses.into_iter().map(move |access| self.simulate_access(access))
+    }
+
+    /// Decompose an address and simulate the access, using per-way set indexing in skewed mode.
+    pub fn simulate_address(&mut self, operation: char, address: u64) -> Result<(), SimError> {
+        if self.undo_depth > 0 {
+            self.save_undo(operation, address);
+        }
+        if matches!(operation, 'F' | 'W') {
+            return self.simulate_flush(operation, address);
+        }
+        if matches!(operation, 'K' | 'U') {
+            return self.simulate_pin(operation, address);
+        }
+        if self.uncacheable.iter().any(|region| region.contains(&address)) {
+            self.uncached_accesses += 1;
+            self.log(LogLevel::Debug, format_args!("uncached {} {:#x}", operation, address));
+            return Ok(());
+        }
+        if self.scratchpad.as_ref().is_some_and(|region| region.contains(&address)) {
+            // Prefetches have nothing to bring into the scratchpad
+            if operation != 'P' {
+                self.scratchpad_accesses += if matches!(operation, 'M' | 'A') { 2 } else { 1 };
+                self.log(LogLevel::Debug, format_args!("scratchpad {} {:#x}", operation, address));
+            }
+            return Ok(());
+        }
+        let address = match self.check_address_width(address)? {
+            Some(address) => address,
+            None => return Ok(()),
+        };
+        if self.store_buffer.is_some() && operation != 'P' {
+            self.simulate_buffered(operation, address)
+        } else {
+            self.look_up(operation, address)
+        }
+    }
+
+    // Flush the line holding an address, or every line for 'W', after retiring the buffered stores to them. Dirty
+    // lines are written back through the write-back buffer if there is one
+    fn simulate_flush(&mut self, operation: char, address: u64) -> Result<(), SimError> {
+        let address = if operation == 'W' {
+            address
+        } else {
+            match self.check_address_width(address)? {
+                Some(address) => address,
+                None => return Ok(()),
+            }
+        };
+        let block = address >> self.block_bits;
+        let retired: Vec<(u64, usize)> = match &mut self.store_buffer {
+            Some(buffer) => {
+                let (retired, kept): (Vec<_>, Vec<_>) = buffer.entries.drain(..).partition(|&(entry, _)| operation == 'W' || entry == block);
+                buffer.entries = kept.into();
+                retired
+            }
+            None => Vec::new(),
+        };
+        self.retire_stores(retired)?;
+        if let Some(compression) = &mut self.compression {
+            compression.baseline.simulate_address(operation, address)?;
+        }
+        self.flushes += 1;
+        let lines: Vec<(usize, usize)> = if operation == 'W' {
+            self.sets.iter().enumerate()
+                .flat_map(|(set_index, set)| set.lines.iter().enumerate().filter(|(_, line)| line.is_valid).map(move |(way, _)| (set_index, way)))
+                .collect()
+        } else {
+            self.locate(address).into_iter().collect()
+        };
+        self.log(LogLevel::Debug, format_args!("flush {} {:#x} lines:{}", operation, address, lines.len()));
+        for (set_index, way) in lines {
+            self.invalidate(set_index, way);
+        }
+        Ok(())
+    }
+
+    // Pin the block of an address, filling it like a prefetch if it isn't cached, or unpin it for 'U'. Uncacheable
+    // and scratchpad blocks, and blocks whose lines are all pinned, can't be pinned, and blocks in locked ways stay pinned
+    fn simulate_pin(&mut self, operation: char, address: u64) -> Result<(), SimError> {
+        if self.compression.is_some() {
+            return Err(SimError::Access("compressed caches can't pin lines".to_string()));
+        }
+        let address = match self.check_address_width(address)? {
+            Some(address) => address,
+            None => return Ok(()),
+        };
+        let uncacheable = self.uncacheable.iter().chain(&self.scratchpad).any(|region| region.contains(&address));
+        if operation == 'K' && !uncacheable && self.locate(address).is_none() {
+            self.look_up('P', address)?;
+        }
+        match self.locate(address) {
+            Some((set_index, way)) => {
+                let locked = self.is_locked(set_index, way);
+                let line = &mut self.sets[set_index].lines[way];
+                line.pinned = operation == 'K' || locked;
+                line.spared &= line.pinned;
+                self.log(LogLevel::Debug, format_args!("{} set:{} way:{}", if operation == 'K' { "pin" } else { "unpin" }, set_index, way));
+            }
+            None if operation == 'K' => self.log(LogLevel::Debug, format_args!("can't pin {:#x}", address)),
+            None => {}
+        }
+        Ok(())
+    }
+
+    // Empty a line, writing its block back if it is dirty
+    fn invalidate(&mut self, set_index: usize, way: usize) {
+        let line = &self.sets[set_index].lines[way];
+        self.flushed_lines += 1;
+        if line.dirty {
+            self.flush_write_backs += 1;
+            // Skewed lines keep the whole block address as tag
+            let tag = line.tag.unwrap_or_default() as u64;
+            let block = if self.skewed { tag } else { tag << self.set_bits | set_index as u64 };
+            let now = self.lookups;
+            if let Some(buffer) = &mut self.write_buffer {
+                buffer.push(block, now);
+            }
+        }
+        if let (Some(lirs), Some(tag)) = (&mut self.lirs, line.tag) {
+            lirs.evict(set_index, tag as u64);
+        }
+        let set = &mut self.sets[set_index];
+        set.lines[way] = Line { tag: None, is_valid: false, dirty: false, pinned: false, spared: false, reused: false, protected: false, ..set.lines[way] };
+        set.access_order.retain(|&order_way| order_way != way);
+    }
+
+    // Look the stores retired from the store buffer up, each as its own thread's store
+    fn retire_stores(&mut self, retired: Vec<(u64, usize)>) -> Result<(), SimError> {
+        let thread = self.thread;
+        for (retired_block, retired_thread) in retired {
+            self.thread = retired_thread;
+            self.look_up('S', retired_block << self.block_bits)?;
+        }
+        self.thread = thread;
+        Ok(())
+    }
+
+    // Pass an access through the store buffer. Only the loads it can't forward, the stores it retires and atomic
+    // accesses look the cache up, the retired stores counting in the result of the access that retired them
+    fn simulate_buffered(&mut self, operation: char, address: u64) -> Result<(), SimError> {
+        let block = address >> self.block_bits;
+        let thread = self.thread;
+        let buffer = self.store_buffer.as_mut().expect("buffered access without a store buffer");
+        let forwarded = matches!(operation, 'L' | 'M') && buffer.holds(block);
+        if forwarded {
+            buffer.forwards += 1;
+        }
+        let retired: Vec<(u64, usize)> = match operation {
+            'S' | 'M' => buffer.store(block, thread).into_iter().collect(),
+            'A' => buffer.entries.drain(..).collect(),
+            _ => Vec::new(),
+        };
+        if forwarded {
+            self.log(LogLevel::Debug, format_args!("store-buffer forward block:{:#x}", block));
+        }
+        self.retire_stores(retired)?;
+        match operation {
+            'L' | 'M' if !forwarded => self.look_up('L', address),
+            'A' => self.look_up('A', address),
+            _ => Ok(()),
+        }
+    }
+
+    // Look an address up in the cache and the models that watch its lookups
+    fn look_up(&mut self, operation: char, address: u64) -> Result<(), SimError> {
+        if let Some(banks) = &mut self.banks {
+            banks.issue(address >> self.block_bits);
+        }
+        if let Some(compression) = &mut self.compression {
+            compression.baseline.simulate_address(operation, address)?;
+        }
+        let (hits, misses, prefetch_fills) = (self.hits, self.misses, self.prefetch_fills);
+        if self.skewed {
+            let block = (address >> self.block_bits) as usize;
+            self.simulate_skewed_access(operation, block)?;
+        } else {
+            let (set_index, tag) = split_address(address, self.set_bits, self.block_bits)?;
+            self.simulate_memory_access(operation, set_index, tag)?;
+        }
+        if self.misses > misses || self.prefetch_fills > prefetch_fills {
+            self.refill_from_write_buffer(address);
+        }
+        // Lookups only happen within simulate_address, which saved the undo information of the access last
+        if let Some(classifier) = &mut self.miss_classifier {
+            let change = classifier.access(address >> self.block_bits, self.misses > misses);
+            if let Some(undo) = self.history.back_mut() {
+                undo.miss_classifier.push(change);
+            }
+        }
+        if let Some(hot_blocks) = self.hot_blocks.as_mut().filter(|_| operation != 'P') {
+            let change = hot_blocks.record(address >> self.block_bits, self.hits - hits + self.misses - misses, self.hits - hits);
+            if let Some(undo) = self.history.back_mut() {
+                undo.hot_blocks.push(change);
+            }
+        }
+        Ok(())
+    }
+
+    // Keep undo information for up to depth accesses, for this cache and the baseline of a compressed cache
+    fn set_undo_depth(&mut self, depth: usize) {
+        self.undo_depth = depth;
+        self.history.truncate(depth);
+        if let Some(compression) = &mut self.compression {
+            compression.baseline.set_undo_depth(depth);
+        }
+    }
+
+    // Save the state an access to the address may overwrite, dropping the oldest saved state beyond the undo depth.
+    // A full flush may overwrite every set
+    fn save_undo(&mut self, operation: char, address: u64) {
+        let address = if self.address_bits < 64 && self.oob_policy == OobPolicy::Mask {
+            address & ((1u64 << self.address_bits) - 1)
+        } else {
+            address
+        };
+        // Stores the access retires from the store buffer also look the cache up
+        let mut blocks = vec![address >> self.block_bits];
+        if let Some(buffer) = &self.store_buffer {
+            blocks.extend(buffer.entries.iter().map(|&(block, _)| block));
+        }
+        let mut set_indexes: Vec<usize> = if self.skewed {
+            // Skewed fills also move the round-robin pointer of set 0
+            let (cache, ways) = (&*self, self.sets[0].lines.len());
+            let mut set_indexes: Vec<usize> = blocks.iter().flat_map(|&block| (0..ways).map(move |way| cache.skew_index(block as usize, way))).collect();
+            set_indexes.push(0);
+            set_indexes
+        } else {
+            blocks.iter().filter_map(|&block| split_address(block << self.block_bits, self.set_bits, self.block_bits).ok()).map(|(set_index, _)| set_index).collect()
+        };
+        if operation == 'W' {
+            set_indexes = (0..self.sets.len()).collect();
+        }
+        set_indexes.sort();
+        set_indexes.dedup();
+
+        if self.history.len() == self.undo_depth {
+            self.history.pop_front();
+        }
+        // A modify or atomic access records its block twice
+        let tinylfu = self.tinylfu.as_ref().map(|tinylfu| tinylfu.save(&blocks, 2 * blocks.len()));
+        let two_queue = self.two_queue.as_ref().map(|queues| queues.save(&set_indexes));
+        let lirs = self.lirs.as_ref().map(|lirs| lirs.save(&set_indexes));
+        let bypass = self.bypass.as_ref().map(|bypass| {
+            // Reuses train the regions of the blocks looked up, and evictions those of the lines
+            let (set_bits, block_bits) = (self.set_bits, self.block_bits);
+            let lines = set_indexes.iter().flat_map(|&set_index| self.sets[set_index].lines.iter().filter_map(move |line| {
+                line.tag.filter(|_| line.is_valid).map(|tag| ((tag as u64) << set_bits | set_index as u64) << block_bits)
+            }));
+            bypass.save(&blocks, blocks.iter().map(|&block| block << block_bits).chain(lines))
+        });
+        let ship = self.ship.as_ref().map(|ship| {
+            // Hits and evictions train the signatures of the lines, including those the access fills
+            let fills = blocks.iter().filter_map(|&block| split_address(block << self.block_bits, self.set_bits, self.block_bits).ok())
+                .map(|(set_index, tag)| self.fill_signature(set_index, tag));
+            let signatures: Vec<u16> = set_indexes.iter().flat_map(|&set_index| self.sets[set_index].lines.iter().map(|line| line.signature))
+                .chain(fills).collect();
+            ship.save(&signatures)
+        });
+        let hawkeye = self.hawkeye.as_ref().map(|hawkeye| {
+            // Evictions train the signatures of the lines down, and a block looked up twice that of the access
+            let signatures: Vec<u16> = set_indexes.iter().flat_map(|&set_index| self.sets[set_index].lines.iter().map(|line| line.signature))
+                .chain([hawkeye.signature(self.pc)]).collect();
+            hawkeye.save(&set_indexes, &signatures)
+        });
+        self.history.push_back(Undo {
+            sets: set_indexes.into_iter().map(|set_index| (set_index, self.sets[set_index].clone())).collect(),
+            hits: self.hits,
+            misses: self.misses,
+            evictions: self.evictions,
+            fast_hits: self.fast_hits,
+            slow_hits: self.slow_hits,
+            oob_accesses: self.oob_accesses,
+            prefetch_hits: self.prefetch_hits,
+            prefetch_fills: self.prefetch_fills,
+            uncached_accesses: self.uncached_accesses,
+            scratchpad_accesses: self.scratchpad_accesses,
+            flushes: self.flushes,
+            flushed_lines: self.flushed_lines,
+            flush_write_backs: self.flush_write_backs,
+            dirty_evictions: self.dirty_evictions,
+            pinned_hits: self.pinned_hits,
+            spared_evictions: self.spared_evictions,
+            saved_misses: self.saved_misses,
+            pin_bypasses: self.pin_bypasses,
+            clock: self.clock,
+            rng_state: self.rng.state,
+            thread_stats: self.thread_stats.clone(),
+            banks: self.banks.clone(),
+            write_buffer: self.write_buffer.clone(),
+            bypass,
+            tinylfu,
+            dueling: self.dueling.clone(),
+            adaptive: self.adaptive.clone(),
+            ship,
+            hawkeye,
+            slru: self.slru.clone(),
+            two_queue,
+            lirs,
+            store_buffer: self.store_buffer.clone(),
+            miss_classifier: Vec::new(),
+            hot_blocks: Vec::new(),
+            lookups: self.lookups,
+            lifetimes: Vec::new(),
+        });
+    }
+
+    /// Undo the most recent access, returning false if no undo information is left.
+    pub fn step_back(&mut self) -> bool {
+        let undo = match self.history.pop_back() {
+            Some(undo) => undo,
+            None => return false,
+        };
+        for (set_index, set) in undo.sets {
+            self.sets[set_index] = set;
+        }
+        self.hits = undo.hits;
+        self.misses = undo.misses;
+        self.evictions = undo.evictions;
+        self.fast_hits = undo.fast_hits;
+        self.slow_hits = undo.slow_hits;
+        self.oob_accesses = undo.oob_accesses;
+        self.prefetch_hits = undo.prefetch_hits;
+        self.prefetch_fills = undo.prefetch_fills;
+        self.uncached_accesses = undo.uncached_accesses;
+        self.scratchpad_accesses = undo.scratchpad_accesses;
+        (self.flushes, self.flushed_lines, self.flush_write_backs) = (undo.flushes, undo.flushed_lines, undo.flush_write_backs);
+        self.dirty_evictions = undo.dirty_evictions;
+        (self.pinned_hits, self.spared_evictions, self.saved_misses, self.pin_bypasses) = (undo.pinned_hits, undo.spared_evictions, undo.saved_misses, undo.pin_bypasses);
+        self.clock = undo.clock;
+        self.rng.state = undo.rng_state;
+        self.thread_stats = undo.thread_stats;
+        self.banks = undo.banks;
+        self.write_buffer = undo.write_buffer;
+        if let (Some(bypass), Some(saved)) = (&mut self.bypass, undo.bypass) {
+            bypass.restore(saved);
+        }
+        if let (Some(tinylfu), Some(saved)) = (&mut self.tinylfu, undo.tinylfu) {
+            tinylfu.restore(saved);
+        }
+        self.dueling = undo.dueling;
+        self.adaptive = undo.adaptive;
+        if let (Some(ship), Some(saved)) = (&mut self.ship, undo.ship) {
+            ship.restore(saved);
+        }
+        if let (Some(hawkeye), Some(saved)) = (&mut self.hawkeye, undo.hawkeye) {
+            hawkeye.restore(saved);
+        }
+        self.slru = undo.slru;
+        if let (Some(queues), Some(saved)) = (&mut self.two_queue, undo.two_queue) {
+            queues.restore(saved);
+        }
+        if let (Some(lirs), Some(saved)) = (&mut self.lirs, undo.lirs) {
+            lirs.restore(saved);
+        }
+        self.store_buffer = undo.store_buffer;
+        if let Some(classifier) = &mut self.miss_classifier {
+            undo.miss_classifier.into_iter().rev().for_each(|change| classifier.restore(change));
+        }
+        if let Some(hot_blocks) = &mut self.hot_blocks {
+            undo.hot_blocks.into_iter().rev().for_each(|change| hot_blocks.restore(change));
+        }
+        self.lookups = undo.lookups;
+        if let Some(lifetimes) = &mut self.lifetimes {
+            for lifetime in undo.lifetimes {
+                if let Some(count) = lifetimes.get_mut(&lifetime) {
+                    *count -= 1;
+                    if *count == 0 {
+                        lifetimes.remove(&lifetime);
+                    }
+                }
+            }
+        }
+        if let Some(compression) = &mut self.compression {
+            compression.baseline.step_back();
+        }
+        true
+    }
+
+    // Apply the out-of-range policy to an address, returning None if the access should be skipped
+    fn check_address_width(&mut self, address: u64) -> Result<Option<u64>, SimError> {
+        if self.address_bits >= 64 || address >> self.address_bits == 0 {
+            return Ok(Some(address));
+        }
+        self.oob_accesses += 1;
+        self.log(LogLevel::Debug, format_args!("address {:#x} exceeds {} bits: {}", address, self.address_bits, self.oob_policy));
+        match self.oob_policy {
+            OobPolicy::Mask => Ok(Some(address & ((1u64 << self.address_bits) - 1))),
+            OobPolicy::Skip => Ok(None),
+            OobPolicy::Error => Err(SimError::Access(format!("address {:x} exceeds the {}-bit address width", address, self.address_bits))),
+        }
+    }
+
+    // Apply skewed-associative cache logic - way w of the block lives in set skew_index(block, w), and lines keep the whole block address as tag
+    fn simulate_skewed_access(&mut self, operation: char, block: usize) -> Result<(), SimError> {
+        match operation {
+            'L' | 'S' | 'P' => {
+                self.clock += 1;
+                self.lookups += 1;
+                let ways = self.sets[0].lines.len();
+                let set_indexes: Vec<usize> = (0..ways).map(|way| self.skew_index(block, way)).collect();
+
+                // If the line in any way holds the block, it's a hit
+                if let Some(way) = (0..ways).find(|&way| {
+                    let line = &self.sets[set_indexes[way]].lines[way];
+                    line.is_valid && line.tag == Some(block)
+                }) {
+                    self.log(LogLevel::Trace, format_args!("hit set:{} way:{} block:{:#x}", set_indexes[way], way, block));
+                    self.record_hit(operation, set_indexes[way]);
+                    self.record_pinned_hit(operation, set_indexes[way], way);
+                    self.sets[set_indexes[way]].lines[way].last_used = self.clock;
+                    self.sets[set_indexes[way]].lines[way].dirty |= operation == 'S';
+                    return Ok(());
+                }
+
+                // Otherwise it's a miss - fill an empty candidate line, or evict the least recently used candidate
+                let empty_ways: Vec<usize> = (0..ways).filter(|&way| !self.sets[set_indexes[way]].lines[way].is_valid && self.may_fill(way)).collect();
+                let way = if !empty_ways.is_empty() {
+                    // Candidates come from different sets, so skewed fills share the round-robin pointer of set 0
+                    self.break_tie(0, &empty_ways)
+                } else {
+                    let line = |way: usize| &self.sets[set_indexes[way]].lines[way];
+                    let choice = (0..ways).filter(|&way| self.may_fill(way)).min_by_key(|&way| line(way).last_used);
+                    let victim = (0..ways).filter(|&way| self.may_fill(way) && !line(way).pinned).min_by_key(|&way| line(way).last_used);
+                    if let Some(way) = choice.filter(|&way| line(way).pinned) {
+                        self.spare(set_indexes[way], way);
+                    }
+                    let Some(way) = victim else {
+                        if choice.is_some() {
+                            self.record_pin_bypass(operation, set_indexes[0]);
+                            return Ok(());
+                        }
+                        return Err(SimError::Access("eviction failed".to_string()));
+                    };
+                    self.record_eviction(set_indexes[way], way);
+                    way
+                };
+                let locked = self.is_locked(set_indexes[way], way);
+                let line = &mut self.sets[set_indexes[way]].lines[way];
+                line.tag = Some(block);
+                line.is_valid = true;
+                line.dirty = operation == 'S';
+                line.last_used = self.clock;
+                line.filled_at = self.lookups;
+                line.reused = false;
+                line.pinned = locked;
+                self.log_fill(set_indexes[way], way);
+                self.record_miss(operation, set_indexes[way]);
+                Ok(())
+            }
+            'M' | 'A' => {
+                // Simulate Load operation followed by Store operation
+                self.simulate_skewed_access('L', block)?;
+                self.simulate_skewed_access('S', block)
+            }
+            _ => {
+                Err(SimError::Access(format!("unknown operation: {}", operation)))
+            }
+        }
+    }
+
+    // Set index of a block in the given way: the top s bits of the block times an odd multiplier of the way's own, so
+    // every way hashes all the bits of the block differently
+    fn skew_index(&self, block: usize, way: usize) -> usize {
+        let s = self.set_bits;
+        if s == 0 {
+            return 0;
+        }
+        ((block as u64).wrapping_mul(skew_multiplier(way)) >> (64 - s)) as usize
+    }
+
+    // Give every set twice as many tags, holding blocks compressed with the given compressibility
+    fn enable_compression(&mut self, compressibility: f64) -> Result<(), SimError> {
+        let ways = self.sets[0].lines.len();
+        let baseline = Cache::new(self.set_bits, ways, self.block_bits)?;
+        for set in &mut self.sets {
+            for _ in 0..ways {
+                set.lines.push(Line { tag: None, is_valid: false, dirty: false, last_used: 0, filled_at: 0, pinned: false, spared: false, reused: false, rrpv: 0, signature: 0, protected: false });
+            }
+        }
+        self.compression = Some(Compression { compressibility, baseline: Box::new(baseline) });
+        self.mute_baseline_log();
+        Ok(())
+    }
+
+    // Compressed size of a block in segments - whether a block is compressible is derived from its address, so it never changes
+    fn compressed_size(&self, set_index: usize, tag: usize) -> usize {
+        let compressibility = self.compression.as_ref().map_or(0.0, |compression| compression.compressibility);
+        let block = ((tag as u64) << self.set_bits) | set_index as u64;
+
+        // SplitMix64 finalizer, mapped to a fraction in [0, 1)
+        let mut hash = block.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        hash = (hash ^ (hash >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        hash = (hash ^ (hash >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        hash ^= hash >> 31;
+        let fraction = (hash >> 11) as f64 / (1u64 << 53) as f64;
+
+        if fraction < compressibility { SEGMENTS_PER_LINE / 2 } else { SEGMENTS_PER_LINE }
+    }
+
+    // Miss in a compressed set - evict LRU lines until there is a free tag and enough data space for the block
+    fn fill_compressed(&mut self, operation: char, set_index: usize, tag: usize) -> Result<(), SimError> {
+        let size = self.compressed_size(set_index, tag);
+        let capacity = self.sets[set_index].lines.len() / 2 * SEGMENTS_PER_LINE;
+        loop {
+            let set = &self.sets[set_index];
+            let used: usize = set.lines.iter()
+                .filter(|line| line.is_valid)
+                .map(|line| self.compressed_size(set_index, line.tag.unwrap()))
+                .sum();
+            if set.lines.iter().any(|line| !line.is_valid) && used + size <= capacity {
+                break;
+            }
+            let evict_index = self.sets[set_index].access_order.pop_back().ok_or(SimError::Access("eviction failed".to_string()))?;
+            self.record_eviction(set_index, evict_index);
+            self.sets[set_index].lines[evict_index].is_valid = false;
+            self.sets[set_index].lines[evict_index].dirty = false;
+            self.sets[set_index].lines[evict_index].tag = None;
+        }
+
+        let empty_lines: Vec<usize> = self.sets[set_index].lines.iter().enumerate()
+            .filter(|(_, line)| !line.is_valid)
+            .map(|(index, _)| index)
+            .collect();
+        let index = self.break_tie(set_index, &empty_lines);
+        self.sets[set_index].lines[index].tag = Some(tag);
+        self.sets[set_index].lines[index].is_valid = true;
+        self.sets[set_index].lines[index].dirty = operation == 'S';
+        self.sets[set_index].lines[index].filled_at = self.lookups;
+        self.sets[set_index].lines[index].reused = false;
+        self.log_fill(set_index, index);
+        self.record_miss(operation, set_index);
+        self.update_access_order(set_index, index);
+        Ok(())
+    }
+
+    // Update the LRU order based on the accessed line
+    fn update_access_order(&mut self, set_index: usize, accessed_index: usize) {
+        let access_order = &mut self.sets[set_index].access_order;
+
+        if let Some(position) = access_order.iter().position(|&i| i == accessed_index) { 
+            access_order.remove(position); // Remove accessed_index if it exists
+        }
+        access_order.push_front(accessed_index); // Add accessed_index at the back
+        self.update_plru(set_index, accessed_index, true);
+    }
+
+    // Move a probationary line that was hit to the protected segment of SLRU. When that overflows the segment, its
+    // least recently used line goes back to the most recently used position of the probationary segment
+    fn promote(&mut self, set_index: usize, way: usize) {
+        let (Some(slru), set) = (&mut self.slru, &mut self.sets[set_index]) else {
+            return;
+        };
+        if set.lines[way].protected {
+            return;
+        }
+        set.lines[way].protected = true;
+        slru.promotions += 1;
+        if set.lines.iter().filter(|line| line.protected).count() <= slru.protected_ways {
+            return;
+        }
+        if let Some(demoted) = set.access_order.iter().rev().copied().find(|&other| other != way && set.lines[other].protected) {
+            set.lines[demoted].protected = false;
+            set.access_order.retain(|&other| other != demoted);
+            set.access_order.push_front(demoted);
+            slru.demotions += 1;
+        } else {
+            // A single-way segment can't hold the line
+            set.lines[way].protected = false;
+        }
+    }
+
+    // Whether a missing tag was evicted from A1in recently enough for A1out to remember it, filling it into Am
+    fn recall_ghost(&mut self, set_index: usize, tag: usize) -> bool {
+        self.two_queue.as_mut().is_some_and(|two_queue| two_queue.recall(set_index, tag as u64))
+    }
+
+    // Place a filled line in the recency order as the insertion policy does: LIP, and BIP but for its occasional
+    // fills at the most recently used position, make it the least recently used line, the PLRU bits pointing at it
+    fn insert_access_order(&mut self, set_index: usize, filled_index: usize) {
+        let mru = match self.insertion {
+            Insertion::Mru => true,
+            Insertion::Lip => false,
+            Insertion::Bip => self.rng.next_below(BIP_MRU_FILLS) == 0,
+        };
+        if mru {
+            return self.update_access_order(set_index, filled_index);
+        }
+        let access_order = &mut self.sets[set_index].access_order;
+        access_order.retain(|&way| way != filled_index);
+        access_order.push_back(filled_index);
+        self.update_plru(set_index, filled_index, false);
+    }
+
+    // Turn the PLRU bits on the path to the accessed line away from it, or towards it unless away
+    fn update_plru(&mut self, set_index: usize, accessed_index: usize, away: bool) {
+        let plru = &mut self.sets[set_index].plru;
+        let (mut node, mut first, mut size) = (0, 0, plru.len() + 1);
+        while size > 1 {
+            let half = size / 2;
+            let upper = accessed_index >= first + half;
+            plru[node] = upper != away;
+            (node, first, size) = if upper { (2 * node + 2, first + half, half) } else { (2 * node + 1, first, half) };
+        }
+    }
+
+    // Line of a full set the replacement policy evicts among its unpinned lines. When the policy would have evicted a
+    // pinned line, that line is spared
+    fn choose_unpinned_victim(&mut self, set_index: usize) -> Option<usize> {
+        if !self.sets[set_index].lines.iter().any(|line| line.pinned) {
+            return self.choose_victim(set_index, true);
+        }
+        let choice = self.choose_victim(set_index, true);
+        let victim = self.choose_victim(set_index, false);
+        if let Some(way) = choice.filter(|&way| self.sets[set_index].lines[way].pinned) {
+            self.spare(set_index, way);
+        }
+        victim
+    }
+
+    // Line of a full set the replacement policy evicts, out of the ways the current thread may fill, and out of the
+    // unpinned ones unless include_pinned
+    fn choose_victim(&mut self, set_index: usize, include_pinned: bool) -> Option<usize> {
+        let set = &self.sets[set_index];
+        let ways = set.lines.len();
+        let evictable = |way: usize| self.may_fill(way) && (include_pinned || !set.lines[way].pinned);
+        let fillable = |way: usize| set.lines[way].is_valid && evictable(way);
+        match self.set_replacement(set_index) {
+            Replacement::Lru => set.access_order.iter().rev().copied().find(|&way| evictable(way)),
+            Replacement::Mru => set.access_order.iter().copied().find(|&way| evictable(way)),
+            Replacement::Slru => {
+                // Protected lines are only evicted when no probationary one is left
+                let order = || set.access_order.iter().rev().copied().filter(|&way| evictable(way));
+                order().find(|&way| !set.lines[way].protected).or_else(|| order().next())
+            }
+            Replacement::TwoQ => {
+                // A1in gives up its oldest line while it holds more than its share of the set, otherwise Am its least
+                // recently used one, each standing in for the other when it has no line to evict
+                let in_lines = set.lines.iter().filter(|line| line.is_valid && !line.protected).count();
+                let oldest_in = (0..ways).filter(|&way| fillable(way) && !set.lines[way].protected).min_by_key(|&way| set.lines[way].filled_at);
+                let lru_am = set.access_order.iter().rev().copied().find(|&way| evictable(way) && set.lines[way].protected);
+                if in_lines > self.two_queue.as_ref().map_or(0, TwoQueue::in_ways) {
+                    oldest_in.or(lru_am)
+                } else {
+                    lru_am.or(oldest_in)
+                }
+            }
+            Replacement::Lirs => {
+                // Resident HIR blocks first, then LIR blocks from the bottom of the stack, whichever may be evicted
+                let mut victims = self.lirs.as_ref()?.victims(set_index);
+                victims.find_map(|tag| (0..ways).find(|&way| set.lines[way].tag == Some(tag as usize) && fillable(way)))
+            }
+            Replacement::Fifo => (0..ways).filter(|&way| fillable(way)).min_by_key(|&way| set.lines[way].filled_at),
+            Replacement::Random => {
+                let candidates: Vec<usize> = (0..ways).filter(|&way| fillable(way)).collect();
+                (!candidates.is_empty()).then(|| candidates[self.rng.next_below(candidates.len())])
+            }
+            Replacement::Srrip | Replacement::Brrip | Replacement::Drrip | Replacement::ShipPc | Replacement::ShipMem | Replacement::Hawkeye => {
+                // The first line with the highest value, which aging every line until one reaches the maximum finds
+                let candidates = (0..ways).filter(|&way| fillable(way));
+                candidates.rev().max_by_key(|&way| set.lines[way].rrpv)
+            }
+            Replacement::Plru => {
+                // Follow the bits from the root, turning away from halves without a line to fill
+                if !(0..ways).any(fillable) {
+                    return None;
+                }
+                let (mut node, mut first, mut size) = (0, 0, ways);
+                while size > 1 {
+                    let half = size / 2;
+                    let upper = (first + half..first + size).any(fillable) && (set.plru[node] || !(first..first + half).any(fillable));
+                    (node, first, size) = if upper { (2 * node + 2, first + half, half) } else { (2 * node + 1, first, half) };
+                }
+                Some(first)
+            }
+        }
+    }
+
+    // Policy replacing the lines of a set: the one of the cache, or the one an adaptive cache picked for the set
+    fn set_replacement(&self, set_index: usize) -> Replacement {
+        self.adaptive.as_ref().map_or(self.replacement, |adaptive| adaptive.policies[adaptive.dueling.policy(set_index)])
+    }
+
+    // Age the lines of a set as RRIP does until the victim reaches the highest prediction value. Hawkeye ages its
+    // lines at friendly fills instead
+    fn age_rrpvs(&mut self, set_index: usize, victim: usize) {
+        if !self.set_replacement(set_index).is_rrip() || self.hawkeye.is_some() {
+            return;
+        }
+        let lines = &mut self.sets[set_index].lines;
+        let age = RRPV_MAX - lines[victim].rrpv;
+        lines.iter_mut().for_each(|line| line.rrpv = (line.rrpv + age).min(RRPV_MAX));
+    }
+
+    // Prediction value a line of the set is filled with under the RRIP policies, SHiP and Hawkeye predicting from
+    // the signature of the fill
+    fn insertion_rrpv(&mut self, set_index: usize, signature: u16) -> u8 {
+        if let Some(ship) = &mut self.ship {
+            return if ship.record_fill(signature) { RRPV_MAX - 1 } else { RRPV_MAX };
+        }
+        if let Some(hawkeye) = &mut self.hawkeye {
+            if !hawkeye.record_fill(signature) {
+                return RRPV_MAX;
+            }
+            // A friendly fill ages the other friendly lines, which stay below the value of the averse ones
+            self.sets[set_index].lines.iter_mut().filter(|line| line.rrpv < RRPV_MAX - 1).for_each(|line| line.rrpv += 1);
+            return 0;
+        }
+        let bimodal = match self.set_replacement(set_index) {
+            Replacement::Brrip => true,
+            Replacement::Drrip => self.dueling.as_ref().is_some_and(|dueling| dueling.policy(set_index) == 1),
+            _ => false,
+        };
+        if bimodal && self.rng.next_below(BRRIP_SHORT_FILLS) != 0 { RRPV_MAX } else { RRPV_MAX - 1 }
+    }
+
+    // Signature a block filled into a set is trained under by the SHiP and Hawkeye policies: the instruction making
+    // the access, or for SHiP by memory region the region of the block
+    fn fill_signature(&self, set_index: usize, tag: usize) -> u16 {
+        if let Some(hawkeye) = &self.hawkeye {
+            return hawkeye.signature(self.pc);
+        }
+        match (&self.ship, self.replacement) {
+            (Some(ship), Replacement::ShipPc) => ship.signature(self.pc),
+            (Some(ship), _) => ship.signature(((tag as u64) << self.set_bits | set_index as u64) << self.block_bits >> SHIP_REGION_BITS),
+            (None, _) => 0,
+        }
+    }
+
+    // Pick one line out of several equally eligible candidates (given in increasing way order)
+    fn break_tie(&mut self, set_index: usize, candidates: &[usize]) -> usize {
+        let way = self.choose_way(set_index, candidates);
+        if candidates.len() > 1 {
+            self.log(LogLevel::Trace, format_args!("tie-break {} set:{} candidates:{:?} way:{}", self.tie_break, set_index, candidates, way));
+        }
+        way
+    }
+
+    // Apply the tie-break rule
+    fn choose_way(&mut self, set_index: usize, candidates: &[usize]) -> usize {
+        match self.tie_break {
+            TieBreak::Lowest => candidates[0],
+            TieBreak::Random => candidates[self.rng.next_below(candidates.len())],
+            TieBreak::RoundRobin => {
+                let set = &mut self.sets[set_index];
+                let chosen = candidates.iter().copied().find(|&way| way >= set.next_way).unwrap_or(candidates[0]);
+                set.next_way = (chosen + 1) % set.lines.len();
+                chosen
+            }
+        }
+    }
+
+    // Way the predictor expects the tag in, if way prediction is enabled
+    fn predict_way(&self, set_index: usize, tag: usize) -> Option<usize> {
+        let set = &self.sets[set_index];
+        match self.way_predictor? {
+            WayPredictor::Mru => set.access_order.front().copied(),
+            WayPredictor::PartialTag => {
+                let mask = (1usize << PARTIAL_TAG_BITS) - 1;
+                set.lines.iter().position(|line| line.is_valid && line.tag.is_some_and(|line_tag| line_tag & mask == tag & mask))
+            }
+        }
+    }
+
+    // Count a hit as fast if it was in the predicted way, slow otherwise
+    fn record_way_prediction(&mut self, predicted_way: Option<usize>, hit_way: usize) {
+        if self.way_predictor.is_none() {
+            return;
+        }
+        if predicted_way == Some(hit_way) {
+            self.fast_hits += 1;
+        } else {
+            self.slow_hits += 1;
+        }
+    }
+
+    // Whether the current thread's partition allows filling the given way
+    fn may_fill(&self, way: usize) -> bool {
+        match self.way_partitions.get(&self.thread) {
+            Some(ways) => ways.contains(&way),
+            None => true,
+        }
+    }
+
+    // Whether a way of a set is locked against replacement
+    fn is_locked(&self, set_index: usize, way: usize) -> bool {
+        self.locked_ways.iter().any(|lock| lock.ways.contains(&way) && lock.sets.as_ref().is_none_or(|sets| sets.contains(&set_index)))
+    }
+
+    // Log an event of this cache, unless it is muted
+    fn log(&self, level: LogLevel, message: fmt::Arguments) {
+        if !self.mute_log {
+            log_event(level, "cache", message);
+        }
+    }
+
+    // Log the fill of a line, once it holds its new block
+    fn log_fill(&self, set_index: usize, way: usize) {
+        if log_enabled(LogLevel::Debug) {
+            let line = &self.sets[set_index].lines[way];
+            let tag = line.tag.unwrap_or_default();
+            self.log(LogLevel::Debug, format_args!("fill set:{} way:{} tag:{:#x}{}", set_index, way, tag, if line.dirty { " dirty" } else { "" }));
+        }
+    }
+
+    // Increase cache hits count, or the prefetch hits count for a prefetch
+    fn record_hit(&mut self, operation: char, set_index: usize) {
+        if operation == 'P' {
+            self.prefetch_hits += 1;
+            return;
+        }
+        self.hits += 1;
+        self.thread_stats.entry(self.thread).or_default().hits += 1;
+        self.sets[set_index].stats.hits += 1;
+    }
+
+    // Count a demand hit on a pinned line, saving a miss if the line was spared since its last hit
+    fn record_pinned_hit(&mut self, operation: char, set_index: usize, way: usize) {
+        let line = &mut self.sets[set_index].lines[way];
+        if operation == 'P' || !line.pinned {
+            return;
+        }
+        self.pinned_hits += 1;
+        if line.spared {
+            line.spared = false;
+            self.saved_misses += 1;
+        }
+    }
+
+    // Keep a pinned line the replacement policy chose to evict
+    fn spare(&mut self, set_index: usize, way: usize) {
+        self.sets[set_index].lines[way].spared = true;
+        self.spared_evictions += 1;
+    }
+
+    // Count a miss that fills nothing, every line it could fill being pinned
+    fn record_pin_bypass(&mut self, operation: char, set_index: usize) {
+        self.log(LogLevel::Debug, format_args!("bypass set:{}: every line is pinned", set_index));
+        self.pin_bypasses += 1;
+        self.record_miss(operation, set_index);
+    }
+
+    // Increase cache misses count, or the prefetch fills count for a prefetch
+    fn record_miss(&mut self, operation: char, set_index: usize) {
+        if operation == 'P' {
+            self.prefetch_fills += 1;
+            return;
+        }
+        self.misses += 1;
+        self.thread_stats.entry(self.thread).or_default().misses += 1;
+        self.sets[set_index].stats.misses += 1;
+        if let Some(dueling) = &mut self.dueling {
+            dueling.record_miss(set_index);
+        }
+        if let Some(adaptive) = &mut self.adaptive {
+            let winner = adaptive.dueling.winner();
+            adaptive.dueling.record_miss(set_index);
+            if adaptive.dueling.winner() == winner {
+                return;
+            }
+            let policy = adaptive.policies[adaptive.dueling.winner()];
+            if adaptive.switch_points.len() == ADAPTIVE_SWITCH_POINTS {
+                adaptive.switch_points.pop_front();
+            }
+            adaptive.switch_points.push_back((self.lookups, policy));
+            self.log(LogLevel::Info, format_args!("switch to {} at lookup {}", policy, self.lookups));
+        }
+    }
+
+    // Increase cache evictions count, recording the lifetime of the line about to be evicted
+    fn record_eviction(&mut self, set_index: usize, way: usize) {
+        if log_enabled(LogLevel::Debug) {
+            let line = &self.sets[set_index].lines[way];
+            let tag = line.tag.unwrap_or_default();
+            self.log(LogLevel::Debug, format_args!("evict set:{} way:{} tag:{:#x}{}", set_index, way, tag, if line.dirty { " dirty" } else { "" }));
+        }
+        self.evictions += 1;
+        self.thread_stats.entry(self.thread).or_default().evictions += 1;
+        self.sets[set_index].stats.evictions += 1;
+        let line = &self.sets[set_index].lines[way];
+        // Skewed lines keep the whole block address as tag
+        let tag = line.tag.unwrap_or_default() as u64;
+        let block = if self.skewed { tag } else { tag << self.set_bits | set_index as u64 };
+        if let Some(bypass) = &mut self.bypass {
+            bypass.train(block << self.block_bits, line.reused);
+        }
+        if let Some(ship) = self.ship.as_mut().filter(|_| !line.reused) {
+            ship.record_dead_eviction(line.signature);
+        }
+        // Lines evicted from A1in are remembered by their tag, the set being that of the queue
+        if let Some(two_queue) = self.two_queue.as_mut().filter(|_| !line.protected) {
+            two_queue.remember(set_index, tag);
+        }
+        if let Some(lirs) = &mut self.lirs {
+            lirs.evict(set_index, tag);
+        }
+        // Evicting a friendly line means no averse one was left, so its instruction was wrongly trusted
+        if let Some(hawkeye) = self.hawkeye.as_mut().filter(|_| line.rrpv < RRPV_MAX) {
+            hawkeye.detrain(line.signature);
+        }
+        if line.dirty {
+            self.dirty_evictions += 1;
+            let now = self.lookups;
+            if let Some(buffer) = &mut self.write_buffer {
+                buffer.push(block, now);
+            }
+        }
+        if let Some(lifetimes) = &mut self.lifetimes {
+            // Lookups between the fill and the current one
+            let lifetime = self.lookups - self.sets[set_index].lines[way].filled_at - 1;
+            *lifetimes.entry(lifetime).or_default() += 1;
+            if let Some(undo) = self.history.back_mut() {
+                undo.lifetimes.push(lifetime);
+            }
+        }
+    }
+
+    // Whether the bypass predictor expects a demand miss's block not to be hit before its eviction
+    fn predict_bypass(&mut self, operation: char, set_index: usize, tag: usize) -> bool {
+        let block = (tag as u64) << self.set_bits | set_index as u64;
+        let block_bits = self.block_bits;
+        match &mut self.bypass {
+            Some(bypass) if operation != 'P' => bypass.predict(block, block << block_bits),
+            _ => false,
+        }
+    }
+
+    // Count a miss whose block isn't allocated, as predicted dead on fill
+    fn record_bypass(&mut self, operation: char, set_index: usize, tag: usize) {
+        self.log(LogLevel::Debug, format_args!("bypass set:{} tag:{:#x}: predicted dead", set_index, tag));
+        if let Some(bypass) = &mut self.bypass {
+            bypass.bypass((tag as u64) << self.set_bits | set_index as u64);
+        }
+        self.record_miss(operation, set_index);
+    }
+
+    // Whether TinyLFU lets a demand miss replace the line chosen to be evicted, its block being estimated to be
+    // accessed more often than the line's
+    fn admit(&mut self, operation: char, set_index: usize, way: usize, tag: usize) -> bool {
+        let victim = self.sets[set_index].lines[way].tag.unwrap_or_default();
+        let block = |tag: usize| (tag as u64) << self.set_bits | set_index as u64;
+        let (candidate, victim) = (block(tag), block(victim));
+        match &mut self.tinylfu {
+            Some(tinylfu) if operation != 'P' => tinylfu.admit(candidate, victim),
+            _ => true,
+        }
+    }
+
+    // Count a miss left unfilled as TinyLFU kept the line it would have evicted
+    fn record_rejection(&mut self, operation: char, set_index: usize, tag: usize) {
+        self.log(LogLevel::Debug, format_args!("reject set:{} tag:{:#x}: less frequent than the victim", set_index, tag));
+        self.record_miss(operation, set_index);
+    }
+
+    // Serve a miss from the write-back buffer if its block is still waiting there, refilling the line with the
+    // block's dirty data
+    fn refill_from_write_buffer(&mut self, address: u64) {
+        let (block, now) = (address >> self.block_bits, self.lookups);
+        if !self.write_buffer.as_mut().is_some_and(|buffer| buffer.take(block, now)) {
+            return;
+        }
+        self.log(LogLevel::Debug, format_args!("write-buffer hit block:{:#x}", block));
+        if let Some((set_index, way)) = self.locate(address) {
+            self.sets[set_index].lines[way].dirty = true;
+        }
+    }
+
+    /// Format cache statistics.
+    pub fn format_stats(&self) -> String {
+        let mut stats = format!("hits:{} misses:{} evictions:{}", self.hits, self.misses, self.evictions);
+        if self.way_predictor.is_some() {
+            let accuracy = if self.hits == 0 { 0.0 } else { 100.0 * self.fast_hits as f64 / self.hits as f64 };
+            stats.push_str(&format!("\nway-prediction: fast-hits:{} slow-hits:{} accuracy:{:.2}%", self.fast_hits, self.slow_hits, accuracy));
+        }
+        if self.address_bits < 64 {
+            stats.push_str(&format!("\nout-of-range-addresses:{}", self.oob_accesses));
+        }
+        if !self.uncacheable.is_empty() {
+            stats.push_str(&format!("\nuncached-accesses:{}", self.uncached_accesses));
+        }
+        if self.scratchpad.is_some() {
+            let accesses = self.scratchpad_accesses + self.hits + self.misses;
+            let share = if accesses == 0 { 0.0 } else { 100.0 * self.scratchpad_accesses as f64 / accesses as f64 };
+            stats.push_str(&format!(
+                "\nscratchpad-accesses:{} cache-accesses:{} scratchpad-share:{:.2}% scratchpad-ways:{}",
+                self.scratchpad_accesses, self.hits + self.misses, share, self.scratchpad_ways
+            ));
+        }
+        if self.flushes > 0 {
+            stats.push_str(&format!("\nflushes:{} flushed-lines:{} flush-write-backs:{}", self.flushes, self.flushed_lines, self.flush_write_backs));
+        }
+        let pinned_lines = self.pinned_lines();
+        if pinned_lines + self.pinned_hits > 0 {
+            stats.push_str(&format!(
+                "\npinned-lines:{} pinned-hits:{} spared-evictions:{} saved-misses:{} pin-bypasses:{}",
+                pinned_lines, self.pinned_hits, self.spared_evictions, self.saved_misses, self.pin_bypasses
+            ));
+        }
+        if self.prefetch_hits + self.prefetch_fills > 0 {
+            stats.push_str(&format!("\nprefetches:{} fills:{} already-cached:{}", self.prefetch_hits + self.prefetch_fills, self.prefetch_fills, self.prefetch_hits));
+        }
+        if let Some(banks) = &self.banks {
+            stats.push_str(&format!("\nbanks:{} cycles:{} bank-conflicts:{}", banks.count, banks.cycles, banks.conflicts));
+        }
+        if let Some(buffer) = &self.write_buffer {
+            stats.push_str(&format!("\nwrite-buffer: write-backs:{} hits:{} stalls:{}", buffer.write_backs, buffer.hits, buffer.stalls));
+        }
+        if let Some(buffer) = &self.store_buffer {
+            stats.push_str(&format!(
+                "\nstore-buffer: stores:{} coalesced:{} forwarded-loads:{} pending:{}",
+                buffer.stores, buffer.coalesced, buffer.forwards, buffer.entries.len()
+            ));
+        }
+        if let Some(dueling) = &self.dueling {
+            let [srrip_misses, brrip_misses] = dueling.leader_misses();
+            let winner = if dueling.winner() == 0 { Replacement::Srrip } else { Replacement::Brrip };
+            stats.push_str(&format!(
+                "\ndueling: srrip-leader-misses:{} brrip-leader-misses:{} psel:{} winner:{} switches:{}",
+                srrip_misses, brrip_misses, dueling.psel(), winner, dueling.switches()
+            ));
+        }
+        if let Some(adaptive) = &self.adaptive {
+            let [first, second] = adaptive.policies;
+            let [first_misses, second_misses] = adaptive.dueling.leader_misses();
+            let points = adaptive.switch_points.iter().map(|(lookup, policy)| format!("{}:{}", lookup, policy)).collect::<Vec<_>>();
+            stats.push_str(&format!(
+                "\nadaptive: {}-leader-misses:{} {}-leader-misses:{} psel:{} winner:{} switches:{} switch-points:{}",
+                first, first_misses, second, second_misses, adaptive.dueling.psel(), adaptive.policies[adaptive.dueling.winner()],
+                adaptive.dueling.switches(), if points.is_empty() { "-".to_string() } else { points.join(",") }
+            ));
+        }
+        if let Some(ship) = &self.ship {
+            stats.push_str(&format!("\nship: fills:{} distant-fills:{} dead-signatures:{}", ship.fills(), ship.distant_fills(), ship.dead_signatures()));
+        }
+        if let Some(slru) = &self.slru {
+            let protected = self.sets.iter().flat_map(|set| &set.lines).filter(|line| line.protected).count();
+            stats.push_str(&format!(
+                "\nslru: protected-ways:{} promotions:{} demotions:{} protected-lines:{}",
+                slru.protected_ways, slru.promotions, slru.demotions, protected
+            ));
+        }
+        if let Some(two_queue) = &self.two_queue {
+            let am_lines = self.sets.iter().flat_map(|set| &set.lines).filter(|line| line.protected).count();
+            stats.push_str(&format!(
+                "\n2q: a1in-ways:{} a1out-tags:{} remembered:{} ghost-hits:{} am-lines:{}",
+                two_queue.in_ways(), two_queue.out_tags(), two_queue.remembered(), two_queue.ghost_hits(), am_lines
+            ));
+        }
+        if let Some(lirs) = &self.lirs {
+            stats.push_str(&format!(
+                "\nlirs: lir-ways:{} lir-hits:{} hir-hits:{} ghost-hits:{} ghosts:{}",
+                lirs.lir_ways(), lirs.lir_hits(), lirs.hir_hits(), lirs.ghost_hits(), lirs.ghosts()
+            ));
+        }
+        if let Some(hawkeye) = &self.hawkeye {
+            stats.push_str(&format!(
+                "\nhawkeye: sampled-sets:{} sampled-accesses:{} opt-hits:{} fills:{} averse-fills:{}",
+                hawkeye.sampled_sets(), hawkeye.sampled_accesses(), hawkeye.opt_hits(), hawkeye.fills(), hawkeye.averse_fills()
+            ));
+        }
+        if let Some(tinylfu) = &self.tinylfu {
+            stats.push_str(&format!(
+                "\ntinylfu: admitted:{} rejected:{} counters:{}x{} resets:{}",
+                tinylfu.admitted(), tinylfu.rejected(), TINYLFU_ROWS, tinylfu.width(), tinylfu.resets()
+            ));
+        }
+        if let Some(bypass) = &self.bypass {
+            let dead_regions = bypass.counters.values().filter(|&&counter| counter == 0).count();
+            stats.push_str(&format!("\nbypass: bypassed-fills:{} reused:{} dead-regions:{}", bypass.bypasses, bypass.reuses, dead_regions));
+        }
+        if let Some(compression) = &self.compression {
+            let resident_lines = self.sets.iter().flat_map(|set| &set.lines).filter(|line| line.is_valid).count();
+            let physical_lines = self.sets.len() * self.sets[0].lines.len() / 2;
+            let baseline_misses = compression.baseline.misses;
+            let miss_reduction = if baseline_misses == 0 { 0.0 } else { 100.0 * (baseline_misses as f64 - self.misses as f64) / baseline_misses as f64 };
+            stats.push_str(&format!(
+                "\ncompression: resident-lines:{} effective-capacity:{:.2}x baseline-misses:{} miss-reduction:{:.2}%",
+                resident_lines, resident_lines as f64 / physical_lines as f64, baseline_misses, miss_reduction
+            ));
+        }
+        if self.lifetimes.is_some() {
+            match self.lifetime_stats() {
+                Some(lifetimes) => stats.push_str(&format!(
+                    "\nlifetimes: min:{} mean:{:.2} p50:{} p90:{} p99:{} max:{}",
+                    lifetimes.min, lifetimes.mean, lifetimes.p50, lifetimes.p90, lifetimes.p99, lifetimes.max
+                )),
+                None => stats.push_str("\nlifetimes: no evictions"),
+            }
+        }
+        if let Some(classifier) = &self.miss_classifier {
+            stats.push_str(&format!("\nmiss-classes: compulsory:{} capacity:{} conflict:{}", classifier.compulsory, classifier.capacity, classifier.conflict));
+        }
+        if self.thread_stats.len() > 1 || !self.way_partitions.is_empty() {
+            for (thread, thread_stats) in &self.thread_stats {
+                stats.push_str(&format!("\nthread {}: hits:{} misses:{} evictions:{}", thread, thread_stats.hits, thread_stats.misses, thread_stats.evictions));
+            }
+        }
+        stats
+    }
+
+    /// Describe the lines of a set and its LRU order.
+    pub fn describe_set(&self, set_index: usize) -> Option<String> {
+        self.set_state(set_index).map(|state| state.to_string())
+    }
+
+    /// Contents of a set: its lines and their LRU order.
+    pub fn set_state(&self, set_index: usize) -> Option<SetState> {
+        let set = self.sets.get(set_index)?;
+        let lines = set.lines.iter().enumerate().map(|(way, line)| LineState {
+            way,
+            valid: line.is_valid,
+            tag: if line.is_valid { line.tag } else { None },
+            dirty: line.is_valid && line.dirty,
+            pinned: line.is_valid && line.pinned,
+        }).collect();
+        let lru_order = if self.skewed {
+            // Skewed lines are ordered by their last use across all sets
+            let mut ways: Vec<usize> = (0..set.lines.len()).filter(|&way| set.lines[way].is_valid).collect();
+            ways.sort_by_key(|&way| std::cmp::Reverse(set.lines[way].last_used));
+            ways
+        } else {
+            set.access_order.iter().copied().collect()
+        };
+        Some(SetState { index: set_index, lines, lru_order })
+    }
+
+    /// Contents of every set, in index order.
+    pub fn set_states(&self) -> impl Iterator<Item = SetState> + '_ {
+        (0..self.sets.len()).filter_map(|set_index| self.set_state(set_index))
+    }
+
+    /// Set and way of the line holding the block of an address, or None if the block isn't cached.
+    pub fn locate(&self, address: u64) -> Option<(usize, usize)> {
+        let address = if self.address_bits >= 64 || address >> self.address_bits == 0 {
+            address
+        } else if self.oob_policy == OobPolicy::Mask {
+            address & ((1u64 << self.address_bits) - 1)
+        } else {
+            return None;
+        };
+        let holds = |set_index: usize, way: usize, tag: usize| {
+            let line = &self.sets[set_index].lines[way];
+            line.is_valid && line.tag == Some(tag)
+        };
+        if self.skewed {
+            let block = (address >> self.block_bits) as usize;
+            (0..self.sets[0].lines.len()).map(|way| (self.skew_index(block, way), way)).find(|&(set_index, way)| holds(set_index, way, block))
+        } else {
+            let (set_index, tag) = split_address(address, self.set_bits, self.block_bits).ok()?;
+            (0..self.sets[set_index].lines.len()).find(|&way| holds(set_index, way, tag)).map(|way| (set_index, way))
+        }
+    }
+
+    /// One CSV row with the run's trace and warm-up, the cache configuration and every statistic, in the columns
+    /// of [`CSV_HEADER`]. Options that aren't set leave their columns empty.
+    pub fn csv_row(&self, trace: &str, warmup: usize) -> String {
+        let mut partitions: Vec<_> = self.way_partitions.iter().collect();
+        partitions.sort();
+        let partitions = partitions.iter().map(|(thread, ways)| format!("{}:{}", thread, format_ways(ways))).collect::<Vec<_>>().join(",");
+        let accesses = self.hits + self.misses;
+        let miss_rate = if accesses == 0 { 0.0 } else { self.misses as f64 / accesses as f64 };
+        let optional = |value: Option<String>| value.unwrap_or_default();
+        let uncacheable = self.uncacheable.iter().map(format_address_range).collect::<Vec<_>>().join(",");
+        let locks = self.locked_ways.iter().map(|lock| match &lock.sets {
+            Some(sets) => format!("{}@{}", format_range(&lock.ways), format_range(sets)),
+            None => format_range(&lock.ways),
+        }).collect::<Vec<_>>().join(",");
+        let stats = self.snapshot_stats();
+        let lifetimes = self.lifetime_stats();
+        let lifetime = |value: fn(&LifetimeStats) -> String| optional(lifetimes.as_ref().map(value));
+        [
+            csv_field(trace),
+            warmup.to_string(),
+            self.set_bits.to_string(),
+            self.associativity().to_string(),
+            self.block_bits.to_string(),
+            self.adaptive.as_ref().map_or(self.replacement.to_string(), |adaptive| format!("{}/{}", adaptive.policies[0], adaptive.policies[1])),
+            self.insertion.to_string(),
+            self.tie_break.to_string(),
+            self.skewed.to_string(),
+            optional(self.way_predictor.map(|predictor| predictor.to_string())),
+            self.address_bits.to_string(),
+            self.oob_policy.to_string(),
+            csv_field(&partitions),
+            optional(self.banks.as_ref().map(|banks| banks.count.to_string())),
+            optional(self.banks.as_ref().map(|banks| banks.issue_width.to_string())),
+            optional(self.compression.as_ref().map(|compression| compression.compressibility.to_string())),
+            optional(self.write_buffer.as_ref().map(|buffer| buffer.capacity.to_string())),
+            optional(self.write_buffer.as_ref().map(|buffer| buffer.drain_interval.to_string())),
+            optional(self.store_buffer.as_ref().map(|buffer| buffer.capacity.to_string())),
+            self.tinylfu.is_some().to_string(),
+            csv_field(&uncacheable),
+            csv_field(&locks),
+            optional(self.scratchpad.as_ref().map(format_address_range)),
+            optional(self.bypass.as_ref().map(|bypass| (1u64 << bypass.region_bits).to_string())),
+            optional(self.slru.as_ref().map(|slru| slru.protected_ways.to_string())),
+            self.hits.to_string(),
+            self.misses.to_string(),
+            self.evictions.to_string(),
+            format!("{:.6}", miss_rate),
+            self.fast_hits.to_string(),
+            self.slow_hits.to_string(),
+            self.oob_accesses.to_string(),
+            optional(self.banks.as_ref().map(|banks| banks.cycles.to_string())),
+            optional(self.banks.as_ref().map(|banks| banks.conflicts.to_string())),
+            optional(self.compression.as_ref().map(|compression| compression.baseline.misses.to_string())),
+            optional(self.miss_classifier.as_ref().map(|classifier| classifier.compulsory.to_string())),
+            optional(self.miss_classifier.as_ref().map(|classifier| classifier.capacity.to_string())),
+            optional(self.miss_classifier.as_ref().map(|classifier| classifier.conflict.to_string())),
+            optional(self.write_buffer.as_ref().map(|buffer| buffer.write_backs.to_string())),
+            optional(self.write_buffer.as_ref().map(|buffer| buffer.hits.to_string())),
+            optional(self.write_buffer.as_ref().map(|buffer| buffer.stalls.to_string())),
+            optional(self.store_buffer.as_ref().map(|buffer| buffer.stores.to_string())),
+            optional(self.store_buffer.as_ref().map(|buffer| buffer.coalesced.to_string())),
+            optional(self.store_buffer.as_ref().map(|buffer| buffer.forwards.to_string())),
+            optional(self.tinylfu.as_ref().map(|tinylfu| tinylfu.admitted().to_string())),
+            optional(self.tinylfu.as_ref().map(|tinylfu| tinylfu.rejected().to_string())),
+            stats.prefetch_hits.to_string(),
+            stats.prefetch_fills.to_string(),
+            optional((!self.uncacheable.is_empty()).then(|| stats.uncached_accesses.to_string())),
+            optional(self.scratchpad.is_some().then(|| stats.scratchpad_accesses.to_string())),
+            stats.flushes.to_string(),
+            stats.flushed_lines.to_string(),
+            stats.flush_write_backs.to_string(),
+            stats.pinned_hits.to_string(),
+            stats.spared_evictions.to_string(),
+            stats.saved_misses.to_string(),
+            stats.pin_bypasses.to_string(),
+            optional(self.bypass.is_some().then(|| stats.bypassed_fills.to_string())),
+            optional(self.bypass.is_some().then(|| stats.bypass_reuses.to_string())),
+            optional((self.dueling.is_some() || self.adaptive.is_some()).then(|| stats.policy_switches.to_string())),
+            optional((self.ship.is_some() || self.hawkeye.is_some()).then(|| stats.distant_fills.to_string())),
+            optional(self.slru.is_some().then(|| stats.promotions.to_string())),
+            optional((self.two_queue.is_some() || self.lirs.is_some()).then(|| stats.ghost_hits.to_string())),
+            lifetime(|lifetimes| lifetimes.min.to_string()),
+            lifetime(|lifetimes| format!("{:.6}", lifetimes.mean)),
+            lifetime(|lifetimes| lifetimes.p50.to_string()),
+            lifetime(|lifetimes| lifetimes.p90.to_string()),
+            lifetime(|lifetimes| lifetimes.p99.to_string()),
+            lifetime(|lifetimes| lifetimes.max.to_string()),
+        ].join(",")
+    }
+
+    /// Format the hits, misses and evictions of every set that was accessed, one set per line, followed by how
+    /// many sets were used. Sets with many more misses than the rest show a pathological mapping.
+    pub fn format_set_stats(&self) -> String {
+        let mut lines: Vec<String> = self.sets.iter().enumerate()
+            .filter(|(_, set)| set.stats.hits + set.stats.misses > 0)
+            .map(|(index, set)| format!("set {}: hits:{} misses:{} evictions:{}", index, set.stats.hits, set.stats.misses, set.stats.evictions))
+            .collect();
+        lines.push(format!("sets-used:{}/{}", lines.len(), self.sets.len()));
+        lines.join("\n")
+    }
+
+    /// The n blocks looked up most often since the statistics were last reset, most looked up first, if the
+    /// cache tracks them. Counts are exact while fewer blocks than the tracking capacity were touched; beyond
+    /// that, blocks that were replaced and touched again only count their lookups since.
+    pub fn hot_blocks(&self, n: usize) -> Option<Vec<BlockStats>> {
+        let hot_blocks = self.hot_blocks.as_ref()?;
+        Some(hot_blocks.by_count.iter().rev().take(n).map(|&(_, block)| {
+            let entry = hot_blocks.blocks[&block];
+            BlockStats { address: block << self.block_bits, lookups: entry.count - entry.error, hits: entry.hits }
+        }).collect())
+    }
+
+    /// Distribution of the lifetimes of the lines evicted since the statistics were last reset, if the cache
+    /// tracks them and any line was evicted.
+    pub fn lifetime_stats(&self) -> Option<LifetimeStats> {
+        let lifetimes = self.lifetimes.as_ref()?;
+        let evictions: usize = lifetimes.values().sum();
+        if evictions == 0 {
+            return None;
+        }
+        // Nearest-rank percentile: the smallest lifetime at or above the given share of evictions
+        let percentile = |share: usize| {
+            let rank = (share * evictions).div_ceil(100).max(1);
+            let mut seen = 0;
+            lifetimes.iter().find(|(_, &count)| {
+                seen += count;
+                seen >= rank
+            }).map_or(0, |(&lifetime, _)| lifetime)
+        };
+        let total: f64 = lifetimes.iter().map(|(&lifetime, &count)| lifetime as f64 * count as f64).sum();
+        Some(LifetimeStats {
+            evictions,
+            min: *lifetimes.keys().next().unwrap(),
+            mean: total / evictions as f64,
+            p50: percentile(50),
+            p90: percentile(90),
+            p99: percentile(99),
+            max: *lifetimes.keys().next_back().unwrap(),
+        })
+    }
+
+    /// Format the n hottest blocks with their hit rates, one block per line.
+    pub fn format_hot_blocks(&self, n: usize) -> String {
+        let blocks = self.hot_blocks(n).unwrap_or_default();
+        blocks.iter().enumerate().map(|(rank, block)| {
+            let hit_rate = if block.lookups == 0 { 0.0 } else { 100.0 * block.hits as f64 / block.lookups as f64 };
+            format!("hot {}: block:{:x} lookups:{} hits:{} hit-rate:{:.2}%", rank + 1, block.address, block.lookups, block.hits, hit_rate)
+        }).collect::<Vec<String>>().join("\n")
+    }
+
+    /// Print cache statistics.
+    pub fn print_stats(&self) {
+        println!("{}", self.format_stats());
+    }
+}
+
+impl fmt::Display for Cache {
+    // Dump the geometry, statistics and every set holding valid lines
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "cache: {} sets x {} lines of {} bytes", self.set_count(), self.associativity(), self.block_size())?;
+        writeln!(f, "{}", self.format_stats())?;
+        for state in self.set_states().filter(|state| state.lines.iter().any(|line| line.valid)) {
+            writeln!(f, "{}", state)?;
+        }
+        Ok(())
+    }
+}
+
+/// Contents of one cache line.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LineState {
+    pub way: usize,
+    pub valid: bool,
+    /// Tag of the block held, None when the line is empty
+    pub tag: Option<usize>,
+    /// Whether the block was stored to since it was filled
+    pub dirty: bool,
+    /// Whether the line is pinned, never to be evicted
+    pub pinned: bool,
+}
+
+/// Contents of one cache set.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SetState {
+    pub index: usize,
+    pub lines: Vec<LineState>,
+    /// Ways holding a block, most recently used first
+    pub lru_order: Vec<usize>,
+}
+
+// Ways of a partition as a range such as 0-3 when they are contiguous, or joined with + otherwise
+fn format_ways(ways: &[usize]) -> String {
+    match (ways.first(), ways.last()) {
+        (Some(first), Some(last)) if ways.len() > 1 && ways.windows(2).all(|pair| pair[1] == pair[0] + 1) => format!("{}-{}", first, last),
+        _ => ways.iter().map(usize::to_string).collect::<Vec<_>>().join("+"),
+    }
+}
+
+// Format a range of ways or sets as "first-last", or a single number
+fn format_range(range: &RangeInclusive<usize>) -> String {
+    if range.start() == range.end() { range.start().to_string() } else { format!("{}-{}", range.start(), range.end()) }
+}
+
+// Format an address range as the options take it, "0xfirst-0xlast" or a single address
+fn format_address_range(range: &RangeInclusive<u64>) -> String {
+    if range.start() == range.end() { format!("{:#x}", range.start()) } else { format!("{:#x}-{:#x}", range.start(), range.end()) }
+}
+
+// Odd multiplier of the skewed index hash of a way: the way number scrambled by the SplitMix64 finalizer, so that the
+// multipliers of different ways share no pattern
+fn skew_multiplier(way: usize) -> u64 {
+    let mut z = (way as u64 + 1).wrapping_mul(0x9e37_79b9_7f4a_7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    (z ^ (z >> 31)) | 1
+}
+
+// Quote a CSV field if it holds a comma, quote or line break
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+impl fmt::Display for SetState {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let lines: Vec<String> = self.lines.iter().map(|line| match line.tag {
+            Some(tag) if line.dirty => format!("[{}] tag {:x} dirty{}", line.way, tag, if line.pinned { " pinned" } else { "" }),
+            Some(tag) => format!("[{}] tag {:x}{}", line.way, tag, if line.pinned { " pinned" } else { "" }),
+            None => format!("[{}] empty", line.way),
+        }).collect();
+        let order: Vec<String> = self.lru_order.iter().map(|way| way.to_string()).collect();
+        write!(f, "set {}: {} | LRU order (most recent first): {}", self.index, lines.join(" "), order.join(" "))
+    }
+}
+
+/// An open region of interest of a cache, measured from its creation until it is dropped.
+/// It dereferences to the cache, so anything that simulates accesses on a cache works on it.
+pub struct Roi<'a> {
+    cache: &'a mut Cache,
+    start: Stats,
+    resets: usize, // Resets of the cache when the region opened
+}
+
+impl Roi<'_> {
+    /// Counts since the region opened, or since the last reset of the statistics inside it.
+    pub fn stats(&self) -> Stats {
+        if self.cache.resets == self.resets {
+            self.cache.snapshot_stats().since(&self.start)
+        } else {
+            self.cache.snapshot_stats()
+        }
+    }
+}
+
+impl Deref for Roi<'_> {
+    type Target = Cache;
+
+    fn deref(&self) -> &Cache {
+        self.cache
+    }
+}
+
+impl DerefMut for Roi<'_> {
+    fn deref_mut(&mut self) -> &mut Cache {
+        self.cache
+    }
+}
+
+impl Drop for Roi<'_> {
+    fn drop(&mut self) {
+        let stats = self.stats();
+        self.cache.roi_stats.add(&stats);
+    }
+}
+
+/// Named configuration for a [`Cache`], validated when the cache is built.
+///
+/// ```
+/// use sim::{CacheBuilder, TieBreak};
+///
+/// let cache = CacheBuilder::new()
+///     .set_index_bits(4)
+///     .associativity(2)
+///     .block_bits(4)
+///     .tie_break(TieBreak::RoundRobin)
+///     .build()
+///     .unwrap();
+/// assert_eq!(cache.set_count(), 16);
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct CacheBuilder {
+    set_index_bits: usize,
+    associativity: usize,
+    block_bits: usize,
+    replacement: Replacement,
+    insertion: Insertion,
+    tie_break: TieBreak,
+    seed: u64,
+    skewed: bool,
+    way_predictor: Option<WayPredictor>,
+    address_bits: usize,
+    oob_policy: OobPolicy,
+    way_partitions: HashMap<usize, Vec<usize>>,
+    uncacheable: Vec<RangeInclusive<u64>>,
+    locked_ways: Vec<WayLock>,
+    scratchpad: Option<RangeInclusive<u64>>,
+    banks: Option<(usize, usize)>,
+    write_buffer: Option<(usize, u64)>,
+    store_buffer: Option<usize>,
+    bypass_region: Option<u64>,
+    tinylfu: bool,
+    protected_ways: Option<usize>,
+    adaptive: Option<[Replacement; 2]>,
+    compressibility: Option<f64>,
+    undo_depth: usize,
+    classify_misses: bool,
+    hot_block_capacity: Option<usize>,
+    track_lifetimes: bool,
+}
+
+impl Default for CacheBuilder {
+    fn default() -> CacheBuilder {
+        CacheBuilder {
+            set_index_bits: 0,
+            associativity: 1,
+            block_bits: 0,
+            replacement: Replacement::Lru,
+            insertion: Insertion::Mru,
+            tie_break: TieBreak::Lowest,
+            seed: DEFAULT_SEED,
+            skewed: false,
+            way_predictor: None,
+            address_bits: 64,
+            oob_policy: OobPolicy::Error,
+            way_partitions: HashMap::new(),
+            uncacheable: Vec::new(),
+            locked_ways: Vec::new(),
+            scratchpad: None,
+            banks: None,
+            write_buffer: None,
+            store_buffer: None,
+            bypass_region: None,
+            tinylfu: false,
+            protected_ways: None,
+            adaptive: None,
+            compressibility: None,
+            undo_depth: 0,
+            classify_misses: false,
+            hot_block_capacity: None,
+            track_lifetimes: false,
+        }
+    }
+}
+
+impl CacheBuilder {
+    /// Start from a single-set, direct-mapped cache of 1-byte blocks with no extensions enabled.
+    pub fn new() -> CacheBuilder {
+        CacheBuilder::default()
+    }
+
+    /// Number of set index bits s, giving 2^s sets.
+    pub fn set_index_bits(mut self, set_index_bits: usize) -> CacheBuilder {
+        self.set_index_bits = set_index_bits;
+        self
+    }
+
+    /// Number of lines E in each set.
+    pub fn associativity(mut self, associativity: usize) -> CacheBuilder {
+        self.associativity = associativity;
+        self
+    }
+
+    /// Number of block offset bits b, giving 2^b-byte blocks.
+    pub fn block_bits(mut self, block_bits: usize) -> CacheBuilder {
+        self.block_bits = block_bits;
+        self
+    }
+
+    /// Policy choosing the line a miss evicts, LRU by default.
+    pub fn replacement(mut self, replacement: Replacement) -> CacheBuilder {
+        self.replacement = replacement;
+        self
+    }
+
+    /// Position filled lines take in the recency order of the LRU and tree-PLRU policies.
+    pub fn insertion(mut self, insertion: Insertion) -> CacheBuilder {
+        self.insertion = insertion;
+        self
+    }
+
+    /// Rule for choosing between equally eligible lines.
+    pub fn tie_break(mut self, tie_break: TieBreak) -> CacheBuilder {
+        self.tie_break = tie_break;
+        self
+    }
+
+    /// Seed for random choices.
+    pub fn seed(mut self, seed: u64) -> CacheBuilder {
+        self.seed = seed;
+        self
+    }
+
+    /// Index each way with its own hash of the block address.
+    pub fn skewed(mut self, skewed: bool) -> CacheBuilder {
+        self.skewed = skewed;
+        self
+    }
+
+    /// Simulate a way predictor, reporting fast and slow hits.
+    pub fn way_predictor(mut self, way_predictor: Option<WayPredictor>) -> CacheBuilder {
+        self.way_predictor = way_predictor;
+        self
+    }
+
+    /// Modeled address width in bits, 64 by default.
+    pub fn address_bits(mut self, address_bits: usize) -> CacheBuilder {
+        self.address_bits = address_bits;
+        self
+    }
+
+    /// Handling of addresses above the modeled address width.
+    pub fn oob_policy(mut self, oob_policy: OobPolicy) -> CacheBuilder {
+        self.oob_policy = oob_policy;
+        self
+    }
+
+    /// Ways each thread may fill; threads without an entry may fill any way.
+    pub fn way_partitions(mut self, way_partitions: HashMap<usize, Vec<usize>>) -> CacheBuilder {
+        self.way_partitions = way_partitions;
+        self
+    }
+
+    /// Address ranges, such as memory-mapped devices, whose accesses bypass the cache and are only counted.
+    pub fn uncacheable(mut self, regions: Vec<RangeInclusive<u64>>) -> CacheBuilder {
+        self.uncacheable = regions;
+        self
+    }
+
+    /// Ways locked against replacement, which keep the first blocks that fill them.
+    pub fn locked_ways(mut self, locks: Vec<WayLock>) -> CacheBuilder {
+        self.locked_ways = locks;
+        self
+    }
+
+    /// Serve an address range from a scratchpad, whose accesses always hit. Its size, a multiple of the bytes of one
+    /// way across all sets, is taken from the cache's ways.
+    pub fn scratchpad(mut self, region: RangeInclusive<u64>) -> CacheBuilder {
+        self.scratchpad = Some(region);
+        self
+    }
+
+    // Ways of each set taken by the scratchpad, if its size is a whole number of them
+    fn scratchpad_ways(&self) -> Result<usize, SimError> {
+        let Some(region) = &self.scratchpad else {
+            return Ok(0);
+        };
+        if region.is_empty() {
+            return Err(SimError::Config(format!("scratchpad {:#x}-{:#x} ends before it starts", region.start(), region.end())));
+        }
+        let size = u128::from(region.end() - region.start()) + 1;
+        let way_size = 1u128 << (self.set_index_bits + self.block_bits);
+        if size % way_size != 0 {
+            return Err(SimError::Config(format!("scratchpad size {} is not a multiple of the {} bytes of a way", size, way_size)));
+        }
+        match usize::try_from(size / way_size) {
+            Ok(ways) if ways < self.associativity => Ok(ways),
+            _ => Err(SimError::Config("scratchpad takes every way of the cache".to_string())),
+        }
+    }
+
+    /// Split the cache into banks by low block bits, counting conflicts between accesses issued in the same cycle.
+    pub fn banks(mut self, count: usize, issue_width: usize) -> CacheBuilder {
+        self.banks = Some((count, issue_width));
+        self
+    }
+
+    /// Put a write-back buffer of the given number of blocks between the cache and memory, which writes a block
+    /// every drain_interval lookups. Misses to blocks still waiting in it are served from it.
+    pub fn write_buffer(mut self, entries: usize, drain_interval: u64) -> CacheBuilder {
+        self.write_buffer = Some((entries, drain_interval));
+        self
+    }
+
+    /// Put a store buffer of the given number of blocks in front of the cache, coalescing stores to the same block
+    /// and forwarding them to loads.
+    pub fn store_buffer(mut self, entries: usize) -> CacheBuilder {
+        self.store_buffer = Some(entries);
+        self
+    }
+
+    /// Bypass the fills predicted dead with a reuse counter for each region of the given size in bytes, a power of
+    /// two of at least a block.
+    pub fn bypass(mut self, region_size: u64) -> CacheBuilder {
+        self.bypass_region = Some(region_size);
+        self
+    }
+
+    /// Gate the misses that would evict with a TinyLFU frequency sketch, only filling those whose block is estimated
+    /// to be accessed more often than the victim's.
+    pub fn tinylfu(mut self, tinylfu: bool) -> CacheBuilder {
+        self.tinylfu = tinylfu;
+        self
+    }
+
+    /// Lines of each set the protected segment of SLRU holds, fewer than the ways. Half the ways by default.
+    pub fn protected_ways(mut self, ways: usize) -> CacheBuilder {
+        self.protected_ways = Some(ways);
+        self
+    }
+
+    /// Switch between two policies at runtime instead of using the replacement policy: leader sets duel for them and
+    /// the other sets follow whichever missed less. Both must be lru, fifo, mru, random, srrip or brrip.
+    pub fn adaptive(mut self, first: Replacement, second: Replacement) -> CacheBuilder {
+        self.adaptive = Some([first, second]);
+        self
+    }
+
+    /// Simulate a compressed cache where the given fraction of blocks take half a line.
+    pub fn compression(mut self, compressibility: f64) -> CacheBuilder {
+        self.compressibility = Some(compressibility);
+        self
+    }
+
+    /// Number of recent accesses that can be undone with [`Cache::step_back`].
+    pub fn undo_depth(mut self, undo_depth: usize) -> CacheBuilder {
+        self.undo_depth = undo_depth;
+        self
+    }
+
+    /// Classify misses as compulsory, capacity or conflict misses, simulating a fully-associative LRU cache of
+    /// the same size alongside.
+    pub fn classify_misses(mut self, classify_misses: bool) -> CacheBuilder {
+        self.classify_misses = classify_misses;
+        self
+    }
+
+    /// Count the lookups and hits of the most accessed blocks, tracking up to capacity blocks at a time.
+    pub fn track_hot_blocks(mut self, capacity: usize) -> CacheBuilder {
+        self.hot_block_capacity = Some(capacity);
+        self
+    }
+
+    /// Record how many lookups each line survived between its fill and its eviction.
+    pub fn track_lifetimes(mut self, track_lifetimes: bool) -> CacheBuilder {
+        self.track_lifetimes = track_lifetimes;
+        self
+    }
+
+    /// Check that the options are valid and can be combined.
+    pub fn validate(&self) -> Result<(), SimError> {
+        if self.associativity == 0 {
+            return Err(SimError::Config("associativity must be at least 1".to_string()));
+        }
+        if !(1..=64).contains(&self.address_bits) {
+            return Err(SimError::Config("address width must be between 1 and 64 bits".to_string()));
+        }
+        if self.set_index_bits.checked_add(self.block_bits).is_none_or(|bits| bits > self.address_bits) {
+            return Err(SimError::Config(format!(
+                "{} set index bits and {} block bits exceed the {}-bit address width",
+                self.set_index_bits, self.block_bits, self.address_bits
+            )));
+        }
+        // The ways the scratchpad leaves to the cache
+        let associativity = self.associativity - self.scratchpad_ways()?;
+        if self.way_partitions.values().flatten().any(|&way| way >= associativity) {
+            return Err(SimError::Config("way partition assigns a way beyond the lines in a set".to_string()));
+        }
+        if let Some(region) = self.uncacheable.iter().find(|region| region.is_empty()) {
+            return Err(SimError::Config(format!("uncacheable region {:#x}-{:#x} ends before it starts", region.start(), region.end())));
+        }
+        if self.locked_ways.iter().any(|lock| *lock.ways.end() >= associativity) {
+            return Err(SimError::Config("way lock locks a way beyond the lines in a set".to_string()));
+        }
+        let sets = 1usize.checked_shl(self.set_index_bits as u32).unwrap_or(0);
+        if self.locked_ways.iter().flat_map(|lock| lock.sets.as_ref()).any(|locked| *locked.end() >= sets) {
+            return Err(SimError::Config(format!("way lock locks a set beyond the {} sets", sets)));
+        }
+        if let Some((count, issue_width)) = self.banks {
+            if !count.is_power_of_two() {
+                return Err(SimError::Config("bank count must be a power of two".to_string()));
+            }
+            if issue_width == 0 {
+                return Err(SimError::Config("issue width must be at least 1".to_string()));
+            }
+        }
+        if let Some((entries, drain_interval)) = self.write_buffer {
+            if entries == 0 {
+                return Err(SimError::Config("write-back buffer needs at least one entry".to_string()));
+            }
+            if drain_interval == 0 {
+                return Err(SimError::Config("write-back buffer drain interval must be at least 1".to_string()));
+            }
+        }
+        if self.store_buffer == Some(0) {
+            return Err(SimError::Config("store buffer needs at least one entry".to_string()));
+        }
+        if let Some(region_size) = self.bypass_region {
+            if !region_size.is_power_of_two() || region_size.trailing_zeros() < self.block_bits as u32 {
+                return Err(SimError::Config("bypass region size must be a power of two of at least a block".to_string()));
+            }
+            if self.skewed || self.compressibility.is_some() {
+                return Err(SimError::Config("bypass prediction is not supported with a skewed or compressed cache".to_string()));
+            }
+        }
+        if self.tinylfu && (self.skewed || self.compressibility.is_some()) {
+            return Err(SimError::Config("TinyLFU admission is not supported with a skewed or compressed cache".to_string()));
+        }
+        if let Some(compressibility) = self.compressibility {
+            if !(0.0..=1.0).contains(&compressibility) {
+                return Err(SimError::Config("compressibility must be between 0 and 1".to_string()));
+            }
+            if self.skewed || !self.way_partitions.is_empty() {
+                return Err(SimError::Config("compression is not supported with a skewed or partitioned cache".to_string()));
+            }
+            if !self.locked_ways.is_empty() {
+                return Err(SimError::Config("compression is not supported with locked ways".to_string()));
+            }
+        }
+        if self.skewed && self.way_predictor.is_some() {
+            return Err(SimError::Config("way prediction is not supported with a skewed cache".to_string()));
+        }
+        if self.replacement != Replacement::Lru && (self.skewed || self.compressibility.is_some()) {
+            return Err(SimError::Config(format!("the {} policy is not supported with a skewed or compressed cache", self.replacement)));
+        }
+        if self.insertion != Insertion::Mru && !matches!(self.replacement, Replacement::Lru | Replacement::Plru) {
+            return Err(SimError::Config(format!("the {} insertion policy needs the lru or plru replacement policy", self.insertion)));
+        }
+        if self.insertion != Insertion::Mru && (self.skewed || self.compressibility.is_some()) {
+            return Err(SimError::Config(format!("the {} insertion policy is not supported with a skewed or compressed cache", self.insertion)));
+        }
+        if let Some(ways) = self.protected_ways {
+            if self.replacement != Replacement::Slru {
+                return Err(SimError::Config("a protected segment size needs the slru policy".to_string()));
+            }
+            if ways == 0 || ways >= associativity {
+                return Err(SimError::Config(format!("the protected segment must hold between 1 and {} lines", associativity.saturating_sub(1))));
+            }
+        }
+        if let Some([first, second]) = self.adaptive {
+            if self.replacement != Replacement::Lru || self.insertion != Insertion::Mru {
+                return Err(SimError::Config("adaptive switching is not supported with another replacement or insertion policy".to_string()));
+            }
+            if let Some(policy) = [first, second].into_iter().find(|policy| !policy.is_switchable()) {
+                return Err(SimError::Config(format!("adaptive switching does not support the {} policy", policy)));
+            }
+            if first == second {
+                return Err(SimError::Config("adaptive switching needs two different policies".to_string()));
+            }
+            if self.skewed || self.compressibility.is_some() {
+                return Err(SimError::Config("adaptive switching is not supported with a skewed or compressed cache".to_string()));
+            }
+        }
+        if self.replacement == Replacement::Plru && !associativity.is_power_of_two() {
+            return Err(SimError::Config("the plru policy needs a power-of-two associativity".to_string()));
+        }
+        if self.hot_block_capacity == Some(0) {
+            return Err(SimError::Config("hot block tracking needs a capacity of at least 1".to_string()));
+        }
+        Ok(())
+    }
+
+    /// Validate the options and build the cache.
+    pub fn build(&self) -> Result<Cache, SimError> {
+        self.validate()?;
+        let scratchpad_ways = self.scratchpad_ways()?;
+        let associativity = self.associativity - scratchpad_ways;
+        let mut cache = Cache::new(self.set_index_bits, associativity, self.block_bits)?;
+        cache.replacement = self.replacement;
+        cache.insertion = self.insertion;
+        if self.replacement == Replacement::Plru {
+            cache.sets.iter_mut().for_each(|set| set.plru = vec![false; associativity - 1]);
+        }
+        if self.replacement == Replacement::Drrip {
+            cache.dueling = Some(SetDueling::new(cache.set_count(), DUEL_LEADERS, PSEL_BITS));
+        }
+        if matches!(self.replacement, Replacement::ShipPc | Replacement::ShipMem) {
+            cache.ship = Some(SignatureTable::new(SHIP_SIGNATURE_BITS));
+        }
+        if self.replacement == Replacement::Hawkeye {
+            cache.hawkeye = Some(Hawkeye::new(cache.set_count(), associativity));
+        }
+        if self.replacement == Replacement::Lirs {
+            cache.lirs = Some(Lirs::new(cache.set_count(), associativity));
+        }
+        if self.replacement == Replacement::TwoQ {
+            cache.two_queue = Some(TwoQueue::new(cache.set_count(), associativity));
+        }
+        if let Some(policies) = self.adaptive {
+            cache.replacement = policies[0];
+            cache.adaptive = Some(Adaptive {
+                policies,
+                dueling: SetDueling::new(cache.set_count(), DUEL_LEADERS, PSEL_BITS),
+                switch_points: VecDeque::with_capacity(ADAPTIVE_SWITCH_POINTS),
+            });
+        }
+        if self.replacement == Replacement::Slru {
+            cache.slru = Some(Slru { protected_ways: self.protected_ways.unwrap_or(associativity / 2), promotions: 0, demotions: 0 });
+        }
+        cache.tie_break = self.tie_break;
+        cache.rng = Rng::new(self.seed);
+        cache.skewed = self.skewed;
+        cache.way_predictor = self.way_predictor;
+        cache.address_bits = self.address_bits;
+        cache.oob_policy = self.oob_policy;
+        cache.way_partitions = self.way_partitions.clone();
+        cache.uncacheable = self.uncacheable.clone();
+        cache.locked_ways = self.locked_ways.clone();
+        cache.scratchpad = self.scratchpad.clone();
+        cache.scratchpad_ways = scratchpad_ways;
+        cache.banks = self.banks.map(|(count, issue_width)| Banks::new(count, issue_width));
+        cache.write_buffer = self.write_buffer.map(|(entries, drain_interval)| WriteBuffer::new(entries, drain_interval));
+        cache.store_buffer = self.store_buffer.map(StoreBuffer::new);
+        cache.bypass = self.bypass_region.map(|region_size| BypassPredictor::new(region_size.trailing_zeros() as usize));
+        if self.tinylfu {
+            cache.tinylfu = Some(TinyLfu::new(cache.set_count() * associativity));
+        }
+        if let Some(compressibility) = self.compressibility {
+            cache.enable_compression(compressibility)?;
+        }
+        cache.set_undo_depth(self.undo_depth);
+        if self.classify_misses {
+            cache.miss_classifier = Some(MissClassifier::new(cache.set_count() * cache.associativity()));
+        }
+        cache.hot_blocks = self.hot_block_capacity.map(HotBlocks::new);
+        if self.track_lifetimes {
+            cache.lifetimes = Some(BTreeMap::new());
+        }
+        Ok(cache)
+    }
+}
+
+/// Parse comma-separated address ranges such as "0xfe000000-0xfeffffff,a0000-bffff", each a hexadecimal first
+/// and last address, or a single address.
+pub fn parse_address_ranges(value: &str) -> Result<Vec<RangeInclusive<u64>>, SimError> {
+    value.split(',').map(parse_address_range).collect()
+}
+
+/// Parse an address range such as "0xfe000000-0xfeffffff", a hexadecimal first and last address, or a single address.
+pub fn parse_address_range(range: &str) -> Result<RangeInclusive<u64>, SimError> {
+    let parse = |address: &str| {
+        let address = address.trim();
+        let digits = address.strip_prefix("0x").or_else(|| address.strip_prefix("0X")).unwrap_or(address);
+        u64::from_str_radix(digits, 16).map_err(|e| SimError::Config(format!("invalid address range {} ({})", range, e)))
+    };
+    let (first, last) = range.split_once('-').unwrap_or((range, range));
+    let (first, last) = (parse(first)?, parse(last)?);
+    if first > last {
+        return Err(SimError::Config(format!("address range {} ends before it starts", range)));
+    }
+    Ok(first..=last)
+}
+
+/// Parse comma-separated way locks such as "0-1,3@0-15", each a way or range of ways locked in every set, or
+/// followed by @ and the set or range of sets it is locked in.
+pub fn parse_way_locks(value: &str) -> Result<Vec<WayLock>, SimError> {
+    let parse = |lock: &str, range: &str| {
+        let (first, last) = range.split_once('-').unwrap_or((range, range));
+        let parse_number = |number: &str| {
+            number.trim().parse::<usize>().map_err(|e| SimError::Config(format!("invalid way lock {} ({})", lock, e)))
+        };
+        let (first, last) = (parse_number(first)?, parse_number(last)?);
+        if first > last {
+            return Err(SimError::Config(format!("way lock {} has a range that ends before it starts", lock)));
+        }
+        Ok(first..=last)
+    };
+    value.split(',').map(|lock| {
+        let (ways, sets) = match lock.split_once('@') {
+            Some((ways, sets)) => (ways, Some(sets)),
+            None => (lock, None),
+        };
+        Ok(WayLock { ways: parse(lock, ways)?, sets: sets.map(|sets| parse(lock, sets)).transpose()? })
+    }).collect()
+}
+
+/// Split an address into set index and tag: the s bits above the b block offset bits, and the bits above those.
+pub fn split_address(address: u64, s: usize, b: usize) -> Result<(usize, usize), SimError> {
+    if s.checked_add(b).is_none_or(|bits| bits > 64) {
+        return Err(SimError::Access(format!("{} set bits and {} block bits don't fit in a 64-bit address", s, b)));
+    }
+    // Shifting by the whole width of an address leaves nothing, where >> would overflow
+    let block = address.checked_shr(b as u32).unwrap_or(0);
+    let set_index = block & 1u64.checked_shl(s as u32).map_or(u64::MAX, |sets| sets - 1);
+    let tag = block.checked_shr(s as u32).unwrap_or(0);
+    Ok((set_index as usize, tag as usize))
+}
+
+#[cfg(test)]
+use crate::trace::simulate_tracefile;
+#[cfg(test)]
+use crate::lirs::LirsStatus;
+
+// Tests for split_address function
+#[test]
+fn test_split_address() {
+    assert_eq!(split_address(0x10, 4, 4), Ok((1, 0)));
+    assert_eq!(split_address(0x1234, 4, 4), Ok((3, 0x12)));
+    assert_eq!(split_address(0xffff_ffff_ffff_ffff, 8, 8), Ok((0xff, 0xffff_ffff_ffff)));
+    // Geometries wider than an address are rejected rather than sliced out of range
+    assert!(matches!(split_address(0x1234, 60, 8), Err(SimError::Access(_))));
+    assert!(matches!(split_address(0x1234, 0, 65), Err(SimError::Access(_))));
+    assert!(matches!(split_address(0x1234, usize::MAX, 1), Err(SimError::Access(_))));
+
+    // No set bits leave a single set, and no block bits a byte per block
+    assert_eq!(split_address(0x1234, 0, 4), Ok((0, 0x123)));
+    assert_eq!(split_address(0x1234, 4, 0), Ok((4, 0x123)));
+    assert_eq!(split_address(0x1234, 0, 0), Ok((0, 0x1234)));
+    // Geometries as wide as an address leave no tag
+    assert_eq!(split_address(0xffff_ffff_ffff_ffff, 60, 4), Ok((0x0fff_ffff_ffff_ffff, 0)));
+    assert_eq!(split_address(0xabcd_0000_0000_0000, 64, 0), Ok((0xabcd_0000_0000_0000, 0)));
+    assert_eq!(split_address(0xabcd_0000_0000_0000, 0, 64), Ok((0, 0)));
+
+    // A fully associative cache keeps any four blocks
+    let mut cache = Cache::new(0, 4, 4).unwrap();
+    for address in [0x0, 0x1000, 0xfff0, 0x10, 0x1004, 0xfff8] {
+        assert_eq!(cache.simulate_address('L', address), Ok(()));
+    }
+    assert_eq!((cache.hits, cache.misses, cache.evictions), (2, 4, 0));
+}
+
+// Test cache initilisation
+#[test]
+fn test_cache_new_valid_parameters() {
+    let s = 6;
+    let e = 2;
+    let b = 4;
+
+    match Cache::new(s, e, b) {
+        Ok(cache) => {
+            assert_eq!(cache.sets.len(), 64); 
+            for set in &cache.sets {
+                assert_eq!(set.lines.len(), e);
+
+                for line in &set.lines {
+                    assert!(!line.is_valid);
+                    assert_eq!(line.tag, None); 
+                }
+
+                assert_eq!(set.access_order.len(), 0); 
+            }
+        }
+        Err(err) => panic!("Error testing cache: {}", err),
+    }
+}
+
+#[test]
+fn test_cache_new_invalid_size() {
+    let s = 1000;
+    let e = 16;
+    let b = 64;
+    assert!(Cache::new(s, e, b).is_err());
+    assert_eq!(Cache::new(60, 1, 8).err(), Some(SimError::Config("60 set index bits and 8 block bits exceed a 64-bit address".to_string())));
+    assert!(matches!(Cache::new(usize::MAX, 1, 1), Err(SimError::Config(_))));
+}
+
+// Test for simulate_memory_access function
+#[test]
+fn test_simulate_memory_access_cache_hits() {
+    let mut cache = Cache::new(6, 2, 4).unwrap();
+
+    cache.sets[0].lines[0].is_valid = true;
+    cache.sets[0].lines[0].tag = Some(100);
+    cache.sets[0].access_order.push_back(0);
+
+    assert_eq!(cache.simulate_memory_access('L', 0, 100), Ok(()));
+    assert_eq!(cache.hits, 1);
+    assert_eq!(cache.misses, 0);
+    assert_eq!(cache.evictions, 0);
+
+    assert_eq!(cache.simulate_memory_access('S', 0, 100), Ok(()));
+    assert_eq!(cache.hits, 2);
+    assert_eq!(cache.misses, 0);
+    assert_eq!(cache.evictions, 0);
+
+    assert_eq!(cache.simulate_memory_access('M', 0, 100), Ok(()));
+    assert_eq!(cache.hits, 4);
+    assert_eq!(cache.misses, 0);
+    assert_eq!(cache.evictions, 0);
+}
+
+#[test]
+fn test_simulate_memory_access_atomic() {
+    // An atomic read-modify-write looks the cache up twice and leaves the line dirty, as a modify does
+    let mut cache = Cache::new(1, 1, 4).unwrap();
+    assert_eq!(cache.simulate_memory_access('A', 0, 1), Ok(()));
+    assert_eq!((cache.hits, cache.misses, cache.evictions), (1, 1, 0));
+    assert!(cache.sets[0].lines[0].dirty);
+    assert_eq!(cache.simulate_memory_access('A', 0, 2), Ok(()));
+    assert_eq!((cache.hits, cache.misses, cache.evictions), (2, 2, 1));
+
+    let mut skewed = CacheBuilder::new().set_index_bits(2).associativity(2).block_bits(4).skewed(true).build().unwrap();
+    assert_eq!(skewed.simulate_address('A', 0x40), Ok(()));
+    assert_eq!((skewed.hits, skewed.misses), (1, 1));
+}
+
+#[test]
+fn test_simulate_memory_access_cache_misses() {
+    let mut cache = Cache::new(6, 4, 4).unwrap();
+
+    assert_eq!(cache.simulate_memory_access('L', 0, 100), Ok(()));
+    assert_eq!(cache.hits, 0);
+    assert_eq!(cache.misses, 1);
+    assert_eq!(cache.evictions, 0);
+
+    assert_eq!(cache.simulate_memory_access('S', 0, 200), Ok(()));
+    assert_eq!(cache.hits, 0);
+    assert_eq!(cache.misses, 2);
+    assert_eq!(cache.evictions, 0);
+
+    assert_eq!(cache.simulate_memory_access('M', 0, 300), Ok(()));
+    assert_eq!(cache.hits, 1);
+    assert_eq!(cache.misses, 3);
+    assert_eq!(cache.evictions, 0);
+}
+
+#[test]
+fn test_simulate_memory_access_cache_evictions() {
+    let mut cache = Cache::new(6, 1, 4).unwrap();
+
+    cache.sets[0].lines[0].is_valid = true;
+    cache.sets[0].lines[0].tag = Some(100);
+    cache.sets[0].access_order.push_back(0);
+
+    assert_eq!(cache.simulate_memory_access('L', 0, 200), Ok(()));
+    assert_eq!(cache.hits, 0);
+    assert_eq!(cache.misses, 1);
+    assert_eq!(cache.evictions, 1);
+
+    assert_eq!(cache.simulate_memory_access('S', 0, 300), Ok(()));
+    assert_eq!(cache.hits, 0);
+    assert_eq!(cache.misses, 2);
+    assert_eq!(cache.evictions, 2);
+
+    assert_eq!(cache.simulate_memory_access('M', 0, 400), Ok(()));
+    assert_eq!(cache.hits, 1);
+    assert_eq!(cache.misses, 3);
+    assert_eq!(cache.evictions, 3);
+}
+
+#[test]
+fn test_simulate_memory_access_unknown_operation() {
+    let mut cache = Cache::new(6, 1, 4).unwrap();
+
+    assert_eq!(cache.simulate_memory_access('X', 0, 100), Err(SimError::Access("unknown operation: X".to_string())));
+}
+
+#[test]
+fn test_simulate_memory_access_prefetch() {
+    let mut cache = CacheBuilder::new().set_index_bits(1).associativity(1).block_bits(4).way_predictor(Some(WayPredictor::Mru)).build().unwrap();
+
+    // A prefetch fills the line without counting as a miss, and the demand access then hits
+    assert_eq!(cache.simulate_memory_access('P', 0, 1), Ok(()));
+    assert_eq!((cache.hits, cache.misses, cache.prefetch_fills, cache.prefetch_hits), (0, 0, 1, 0));
+    assert_eq!(cache.simulate_memory_access('L', 0, 1), Ok(()));
+    assert_eq!((cache.hits, cache.misses), (1, 0));
+
+    // Prefetching a cached block counts as a prefetch hit, and a prefetch can evict a line, which isn't dirty
+    assert_eq!(cache.simulate_memory_access('P', 0, 1), Ok(()));
+    assert_eq!(cache.simulate_memory_access('S', 1, 2), Ok(()));
+    assert_eq!(cache.simulate_memory_access('P', 1, 3), Ok(()));
+    assert!(!cache.sets[1].lines[0].dirty);
+    assert_eq!((cache.hits, cache.misses, cache.evictions, cache.prefetch_fills, cache.prefetch_hits), (1, 1, 1, 2, 1));
+    assert_eq!(cache.check_invariants(), Ok(()));
+    assert!(cache.format_stats().contains("\nprefetches:3 fills:2 already-cached:1"));
+
+    let mut skewed = CacheBuilder::new().set_index_bits(2).associativity(2).block_bits(4).skewed(true).build().unwrap();
+    assert_eq!(skewed.simulate_address('P', 0x40), Ok(()));
+    assert_eq!(skewed.simulate_address('L', 0x40), Ok(()));
+    assert_eq!((skewed.hits, skewed.misses, skewed.prefetch_fills), (1, 0, 1));
+}
+
+// Test for update_access_order function
+#[test]
+fn test_update_access_order() {
+    let mut cache = Cache::new(6, 2, 4).unwrap();
+
+    cache.update_access_order(0, 1);
+    assert_eq!(cache.sets[0].access_order, vec![1]);
+
+    cache.update_access_order(0, 2);
+    assert_eq!(cache.sets[0].access_order, vec![2, 1]);
+ 
+    cache.update_access_order(0, 1);
+    assert_eq!(cache.sets[0].access_order, vec![1, 2]);
+
+    cache.update_access_order(0, 3);
+    assert_eq!(cache.sets[0].access_order, vec![3, 1, 2]);
+    assert_eq!(cache.set_state(0).unwrap().lru_order, vec![3, 1, 2]);
+}
+
+// Tests for way prediction
+#[test]
+fn test_way_prediction_mru() {
+    let mut cache = Cache::new(1, 2, 4).unwrap();
+    cache.way_predictor = Some(WayPredictor::Mru);
+
+    assert_eq!(cache.simulate_memory_access('L', 0, 1), Ok(()));
+    assert_eq!(cache.simulate_memory_access('L', 0, 2), Ok(()));
+    assert_eq!(cache.simulate_memory_access('L', 0, 2), Ok(()));
+    assert_eq!(cache.simulate_memory_access('L', 0, 1), Ok(()));
+    assert_eq!(cache.simulate_memory_access('M', 0, 2), Ok(()));
+    assert_eq!((cache.hits, cache.fast_hits, cache.slow_hits), (4, 2, 2));
+    assert_eq!(cache.format_stats(), "hits:4 misses:2 evictions:0\nway-prediction: fast-hits:2 slow-hits:2 accuracy:50.00%");
+}
+
+#[test]
+fn test_way_prediction_partial_tag() {
+    let mut cache = Cache::new(1, 2, 4).unwrap();
+    cache.way_predictor = Some(WayPredictor::PartialTag);
+
+    // Tags 0x01 and 0x11 share their low bits, so the predictor picks the first of them
+    assert_eq!(cache.simulate_memory_access('L', 0, 0x01), Ok(()));
+    assert_eq!(cache.simulate_memory_access('L', 0, 0x11), Ok(()));
+    assert_eq!(cache.simulate_memory_access('L', 0, 0x01), Ok(()));
+    assert_eq!(cache.simulate_memory_access('L', 0, 0x11), Ok(()));
+    assert_eq!((cache.hits, cache.fast_hits, cache.slow_hits), (2, 1, 1));
+}
+
+#[test]
+fn test_way_prediction_disabled() {
+    let mut cache = Cache::new(1, 2, 4).unwrap();
+
+    assert_eq!(cache.simulate_memory_access('M', 0, 1), Ok(()));
+    assert_eq!((cache.hits, cache.fast_hits, cache.slow_hits), (1, 0, 0));
+    assert_eq!(cache.format_stats(), "hits:1 misses:1 evictions:0");
+}
+
+// Tests for uncacheable regions
+#[test]
+fn test_simulate_address_uncacheable() {
+    let mut cache = CacheBuilder::new().set_index_bits(1).associativity(1).block_bits(4).uncacheable(vec![0x100..=0x1ff, 0x400..=0x400]).build().unwrap();
+
+    // Accesses to the regions neither hit nor miss, nor disturb the cached lines
+    assert_eq!(cache.simulate_address('L', 0x0), Ok(()));
+    assert_eq!(cache.simulate_address('S', 0x100), Ok(()));
+    assert_eq!(cache.simulate_address('M', 0x1ff), Ok(()));
+    assert_eq!(cache.simulate_address('L', 0x400), Ok(()));
+    assert_eq!(cache.simulate_address('L', 0x0), Ok(()));
+    assert_eq!((cache.hits, cache.misses, cache.evictions, cache.uncached_accesses), (1, 1, 0, 3));
+    assert_eq!(cache.locate(0x100), None);
+    assert_eq!(cache.format_stats(), "hits:1 misses:1 evictions:0\nuncached-accesses:3");
+
+    // Each block of a straddling access is checked on its own
+    let result = cache.access(Op::Load, 0xf8, 16).unwrap();
+    assert_eq!((result.hits + result.misses, cache.uncached_accesses), (1, 4));
+    assert_eq!(result.describe(), "L f8 miss");
+    assert_eq!(cache.snapshot_stats().uncached_accesses, 4);
+    cache.reset_stats();
+    assert_eq!(cache.uncached_accesses, 0);
+}
+
+// Tests for parse_address_ranges function
+#[test]
+fn test_parse_address_ranges() {
+    assert_eq!(parse_address_ranges("0xfe000000-0xfeffffff"), Ok(vec![0xfe00_0000..=0xfeff_ffff]));
+    assert_eq!(parse_address_ranges("a0000-bffff, 0x400"), Ok(vec![0xa0000..=0xbffff, 0x400..=0x400]));
+    assert_eq!(parse_address_ranges("0-ffffffffffffffff"), Ok(vec![0..=u64::MAX]));
+    assert_eq!(parse_address_ranges("20-10"), Err(SimError::Config("address range 20-10 ends before it starts".to_string())));
+    assert_eq!(parse_address_range("0x1000-0x1fff"), Ok(0x1000..=0x1fff));
+    assert!(parse_address_range("0x1000-0x1fff,0x3000").is_err());
+    for invalid in ["", "10-", "-10", "10-20-30", "0xg", "10,,20"] {
+        assert!(matches!(parse_address_ranges(invalid), Err(SimError::Config(_))), "{}", invalid);
+    }
+}
+
+// Tests for parse_way_locks function
+#[test]
+fn test_parse_way_locks() {
+    assert_eq!(parse_way_locks("0-1"), Ok(vec![WayLock { ways: 0..=1, sets: None }]));
+    assert_eq!(
+        parse_way_locks("3@0-15, 2@7"),
+        Ok(vec![WayLock { ways: 3..=3, sets: Some(0..=15) }, WayLock { ways: 2..=2, sets: Some(7..=7) }])
+    );
+    assert_eq!(parse_way_locks("2-1"), Err(SimError::Config("way lock 2-1 has a range that ends before it starts".to_string())));
+    for invalid in ["", "a", "0@", "@1", "0-1-2", "0@1@2", "0,,1", "-1"] {
+        assert!(matches!(parse_way_locks(invalid), Err(SimError::Config(_))), "{}", invalid);
+    }
+}
+
+// Tests for out-of-range address handling
+#[test]
+fn test_simulate_address_out_of_range_mask() {
+    let mut cache = Cache::new(4, 1, 4).unwrap();
+    cache.address_bits = 16;
+    cache.oob_policy = OobPolicy::Mask;
+
+    assert_eq!(cache.simulate_address('L', 0x1_0010), Ok(()));
+    assert_eq!(cache.simulate_address('L', 0x0010), Ok(()));
+    assert_eq!(cache.simulate_address('L', 0xffff), Ok(()));
+    assert_eq!((cache.hits, cache.misses, cache.oob_accesses), (1, 2, 1));
+    assert_eq!(cache.format_stats(), "hits:1 misses:2 evictions:0\nout-of-range-addresses:1");
+}
+
+#[test]
+fn test_simulate_address_out_of_range_skip() {
+    let mut cache = Cache::new(4, 1, 4).unwrap();
+    cache.address_bits = 16;
+    cache.oob_policy = OobPolicy::Skip;
+
+    assert_eq!(cache.simulate_address('L', 0x1_0010), Ok(()));
+    assert_eq!(cache.simulate_address('M', 0xffff_0000), Ok(()));
+    assert_eq!(cache.simulate_address('L', 0x0010), Ok(()));
+    assert_eq!((cache.hits, cache.misses, cache.oob_accesses), (0, 1, 2));
+}
+
+#[test]
+fn test_simulate_address_out_of_range_error() {
+    let mut cache = Cache::new(4, 1, 4).unwrap();
+    cache.address_bits = 16;
+
+    assert_eq!(cache.simulate_address('L', 0x1_0010), Err(SimError::Access("address 10010 exceeds the 16-bit address width".to_string())));
+    assert_eq!(cache.simulate_address('L', 0xffff), Ok(()));
+    assert_eq!(cache.oob_accesses, 1);
+}
+
+#[test]
+fn test_simulate_address_full_width() {
+    let mut cache = Cache::new(4, 1, 4).unwrap();
+
+    assert_eq!(cache.simulate_address('L', u64::MAX), Ok(()));
+    assert_eq!(cache.oob_accesses, 0);
+    assert_eq!(cache.format_stats(), "hits:0 misses:1 evictions:0");
+}
+
+// Tests for way partitioning
+#[test]
+fn test_way_partitioning() {
+    let mut cache = Cache::new(1, 4, 4).unwrap();
+    cache.way_partitions = HashMap::from([(0, vec![0, 1]), (1, vec![2, 3])]);
+
+    // Thread 1 fills its own ways
+    cache.thread = 1;
+    assert_eq!(cache.simulate_memory_access('L', 0, 10), Ok(()));
+    assert_eq!(cache.simulate_memory_access('L', 0, 11), Ok(()));
+    assert_eq!(cache.sets[0].lines[2].tag, Some(10));
+    assert_eq!(cache.sets[0].lines[3].tag, Some(11));
+
+    // Thread 0 only evicts within ways 0 and 1
+    cache.thread = 0;
+    for tag in 0..3 {
+        assert_eq!(cache.simulate_memory_access('L', 0, tag), Ok(()));
+    }
+    assert_eq!(cache.sets[0].lines[0].tag, Some(2));
+    assert_eq!(cache.sets[0].lines[1].tag, Some(1));
+    assert_eq!(cache.sets[0].lines[2].tag, Some(10));
+    assert_eq!(cache.sets[0].lines[3].tag, Some(11));
+
+    // Lookups still hit in any way
+    assert_eq!(cache.simulate_memory_access('L', 0, 10), Ok(()));
+
+    assert_eq!(cache.thread_stats[&0], ThreadStats { hits: 1, misses: 3, evictions: 1 });
+    assert_eq!(cache.thread_stats[&1], ThreadStats { hits: 0, misses: 2, evictions: 0 });
+    assert_eq!(cache.format_stats(), "hits:1 misses:5 evictions:1\nthread 0: hits:1 misses:3 evictions:1\nthread 1: hits:0 misses:2 evictions:0");
+}
+
+#[test]
+fn test_way_partitioning_skewed() {
+    let mut cache = Cache::new(2, 2, 4).unwrap();
+    cache.skewed = true;
+    cache.way_partitions = HashMap::from([(0, vec![1])]);
+
+    // Blocks 0, 7 and 33 map to set 0 in both ways, but thread 0 may only use way 1
+    for address in [0x0, 0x70, 0x210, 0x210] {
+        assert_eq!(cache.simulate_address('L', address), Ok(()));
+    }
+    assert!(!cache.sets[0].lines[0].is_valid);
+    assert_eq!(cache.sets[0].lines[1].tag, Some(0x21));
+    assert_eq!((cache.hits, cache.misses, cache.evictions), (1, 3, 2));
+}
+
+#[test]
+fn test_thread_stats_single_thread() {
+    let mut cache = Cache::new(1, 1, 4).unwrap();
+
+    assert_eq!(cache.simulate_memory_access('M', 0, 1), Ok(()));
+    assert_eq!(cache.thread_stats[&0], ThreadStats { hits: 1, misses: 1, evictions: 0 });
+    assert_eq!(cache.format_stats(), "hits:1 misses:1 evictions:0");
+}
+
+// Tests for bank conflict counting
+#[test]
+fn test_banks_issue() {
+    let mut banks = Banks::new(4, 4);
+
+    // Blocks 0 and 4 share bank 0, 1 and 5 share bank 1
+    for block in [0, 1, 4, 2] {
+        banks.issue(block);
+    }
+    assert_eq!((banks.cycles, banks.conflicts), (2, 1));
+
+    for block in [5, 9, 13, 3] {
+        banks.issue(block);
+    }
+    assert_eq!((banks.cycles, banks.conflicts), (5, 3));
+
+    // Width 1 never conflicts
+    let mut banks = Banks::new(4, 1);
+    for block in [0, 4, 8] {
+        banks.issue(block);
+    }
+    assert_eq!((banks.cycles, banks.conflicts), (3, 0));
+}
+
+// Tests for WriteBuffer
+#[test]
+fn test_write_buffer() {
+    let mut buffer = WriteBuffer::new(2, 10);
+    buffer.push(0x1, 5);
+    buffer.push(0x2, 6);
+    // Full: the oldest block is written at once
+    buffer.push(0x3, 7);
+    assert_eq!((buffer.blocks.iter().copied().collect::<Vec<_>>(), buffer.stalls), (vec![0x2, 0x3], 1));
+    assert!(buffer.take(0x3, 8));
+    assert!(!buffer.take(0x1, 8));
+    // Memory takes a block every 10 lookups after the stall
+    assert!(!buffer.take(0x2, 17));
+    assert_eq!((buffer.write_backs, buffer.hits, buffer.blocks.len()), (3, 1, 0));
+    // An idle buffer doesn't save up drains
+    buffer.push(0x4, 100);
+    assert!(buffer.take(0x4, 109));
+}
+
+#[test]
+fn test_simulate_address_write_buffer() {
+    for skewed in [false, true] {
+        let mut cache = CacheBuilder::new().block_bits(4).skewed(skewed).write_buffer(2, 100).undo_depth(4).build().unwrap();
+        // The dirty block 0x0 is evicted into the buffer, and its next miss is served from it, dirty again
+        for (op, address) in [('S', 0x0), ('L', 0x10), ('L', 0x0)] {
+            cache.simulate_address(op, address).unwrap();
+        }
+        assert_eq!(cache.set_state(0).unwrap().lines[0], LineState { way: 0, valid: true, tag: Some(0), dirty: true, pinned: false });
+        let stats = cache.snapshot_stats();
+        assert_eq!((stats.misses, stats.write_backs, stats.write_buffer_hits, stats.write_buffer_stalls), (3, 1, 1, 0));
+        assert!(cache.step_back());
+        assert_eq!(cache.snapshot_stats().write_buffer_hits, 0);
+        assert!(cache.check_invariants().is_ok());
+    }
+
+    let mut cache = CacheBuilder::new().block_bits(4).write_buffer(1, 100).build().unwrap();
+    for (op, address) in [('S', 0x0), ('S', 0x10), ('S', 0x20), ('L', 0x0)] {
+        cache.simulate_address(op, address).unwrap();
+    }
+    // 0x0 was written to make room for 0x10, so its miss goes to memory, and 0x20 waits for 0x10 to be written
+    let stats = cache.snapshot_stats();
+    assert_eq!((stats.write_backs, stats.write_buffer_hits, stats.write_buffer_stalls), (3, 0, 2));
+    assert_eq!(cache.format_stats(), "hits:0 misses:4 evictions:3\nwrite-buffer: write-backs:3 hits:0 stalls:2");
+    cache.reset_stats();
+    assert_eq!(cache.format_stats(), "hits:0 misses:0 evictions:0\nwrite-buffer: write-backs:0 hits:0 stalls:0");
+}
+
+#[test]
+fn test_write_buffer_drains() {
+    // Memory takes a block every 2 lookups, so 0x0 is written before it misses again
+    let mut cache = CacheBuilder::new().block_bits(4).write_buffer(4, 2).build().unwrap();
+    for (op, address) in [('S', 0x0), ('L', 0x10), ('L', 0x20), ('L', 0x30), ('L', 0x0)] {
+        cache.simulate_address(op, address).unwrap();
+    }
+    assert_eq!(cache.snapshot_stats().write_buffer_hits, 0);
+    // Misses of a trace are served from a slow buffer, and never from one that doesn't exist
+    let mut buffered = CacheBuilder::new().set_index_bits(1).associativity(1).block_bits(4).write_buffer(8, 1000).build().unwrap();
+    simulate_tracefile(&mut buffered, "../traces/trans.trace").unwrap();
+    let mut plain = Cache::new(1, 1, 4).unwrap();
+    simulate_tracefile(&mut plain, "../traces/trans.trace").unwrap();
+    assert!(buffered.snapshot_stats().write_buffer_hits > 0);
+    assert_eq!((buffered.hits(), buffered.misses()), (plain.hits(), plain.misses()));
+    assert_eq!(plain.snapshot_stats().write_backs, 0);
+}
+
+// Tests for StoreBuffer
+#[test]
+fn test_store_buffer() {
+    let mut buffer = StoreBuffer::new(2);
+    assert_eq!(buffer.store(0x1, 0), None);
+    assert_eq!(buffer.store(0x2, 1), None);
+    assert_eq!(buffer.store(0x1, 0), None);
+    // Full: the oldest block is retired with its thread
+    assert_eq!(buffer.store(0x3, 0), Some((0x1, 0)));
+    assert!(buffer.holds(0x2) && buffer.holds(0x3) && !buffer.holds(0x1));
+    assert_eq!((buffer.stores, buffer.coalesced, buffer.forwards), (4, 1, 0));
+}
+
+#[test]
+fn test_simulate_address_store_buffer() {
+    for skewed in [false, true] {
+        let mut cache = CacheBuilder::new().set_index_bits(2).associativity(2).block_bits(4).skewed(skewed).store_buffer(2).undo_depth(4).build().unwrap();
+        // Stores to 0x0 coalesce and the load is forwarded, so nothing reaches the cache until 0x20 retires 0x0
+        for (op, address) in [('S', 0x0), ('S', 0x8), ('L', 0x4), ('S', 0x10)] {
+            cache.simulate_address(op, address).unwrap();
+        }
+        assert_eq!((cache.hits(), cache.misses()), (0, 0));
+        cache.simulate_address('S', 0x20).unwrap();
+        assert_eq!((cache.hits(), cache.misses()), (0, 1));
+        assert_eq!(cache.locate(0x0).map(|(set_index, way)| cache.sets[set_index].lines[way].dirty), Some(true));
+        let stats = cache.snapshot_stats();
+        assert_eq!((stats.buffered_stores, stats.coalesced_stores, stats.forwarded_loads), (4, 1, 1));
+        // Stepping back puts 0x0 back in the buffer and out of the cache
+        assert!(cache.step_back());
+        assert_eq!((cache.misses(), cache.locate(0x0)), (0, None));
+        assert!(cache.check_invariants().is_ok());
+    }
+
+    // An atomic access retires every waiting store before looking its own block up
+    let mut cache = CacheBuilder::new().set_index_bits(2).associativity(2).block_bits(4).store_buffer(4).build().unwrap();
+    for (op, address) in [('S', 0x0), ('M', 0x10), ('M', 0x10), ('A', 0x0)] {
+        cache.simulate_address(op, address).unwrap();
+    }
+    // The first modify loads 0x10 and the second is forwarded; the retired stores then miss 0x0 and hit 0x10, and the
+    // atomic access hits 0x0 twice
+    assert_eq!((cache.hits(), cache.misses()), (3, 2));
+    assert_eq!(
+        cache.format_stats(),
+        "hits:3 misses:2 evictions:0\nstore-buffer: stores:3 coalesced:1 forwarded-loads:1 pending:0"
+    );
+    cache.reset_stats();
+    assert_eq!(cache.snapshot_stats().buffered_stores, 0);
+    // Prefetches bypass the buffer
+    cache.simulate_address('P', 0x40).unwrap();
+    assert_eq!(cache.prefetch_fills(), 1);
+}
+
+#[test]
+fn test_simulate_access_store_buffer() {
+    // A retired store counts in the result of the access that retired it, with its own thread
+    let mut cache = CacheBuilder::new().set_index_bits(2).associativity(1).block_bits(4).store_buffer(1).build().unwrap();
+    let store = MemoryAccess { operation: 'S', address: 0x0, thread: 1, size: 4, pc: 0 };
+    assert_eq!(cache.simulate_access(store).unwrap().misses, 0);
+    let next = MemoryAccess { operation: 'S', address: 0x10, thread: 2, size: 4, pc: 0 };
+    assert_eq!(cache.simulate_access(next).unwrap().misses, 1);
+    assert_eq!(cache.thread_stats()[&1].misses, 1);
+    assert!(!cache.thread_stats().contains_key(&2));
+
+    // Coalescing keeps stores of a trace from looking the cache up
+    let mut buffered = CacheBuilder::new().set_index_bits(4).associativity(1).block_bits(4).store_buffer(8).build().unwrap();
+    simulate_tracefile(&mut buffered, "../traces/trans.trace").unwrap();
+    let mut plain = Cache::new(4, 1, 4).unwrap();
+    simulate_tracefile(&mut plain, "../traces/trans.trace").unwrap();
+    let stats = buffered.snapshot_stats();
+    assert!(stats.coalesced_stores > 0 && stats.forwarded_loads > 0);
+    assert!(buffered.hits() + buffered.misses() < plain.hits() + plain.misses());
+    assert!(buffered.check_invariants().is_ok());
+}
+
+#[test]
+fn test_simulate_address_banks() {
+    let mut cache = Cache::new(4, 1, 4).unwrap();
+    cache.banks = Some(Banks::new(2, 2));
+
+    for address in [0x00, 0x20, 0x10, 0x30, 0x00] {
+        assert_eq!(cache.simulate_address('L', address), Ok(()));
+    }
+    assert_eq!(cache.format_stats(), "hits:1 misses:4 evictions:0\nbanks:2 cycles:5 bank-conflicts:2");
+}
+
+// Tests for cache flushes
+#[test]
+fn test_simulate_address_flush() {
+    for skewed in [false, true] {
+        let mut cache = CacheBuilder::new().set_index_bits(2).associativity(2).block_bits(4).skewed(skewed).undo_depth(4).build().unwrap();
+        for (op, address) in [('S', 0x0), ('L', 0x10), ('F', 0x8), ('F', 0x0), ('F', 0x1000)] {
+            cache.simulate_address(op, address).unwrap();
+        }
+        // Only the first flush found the dirty line, the others count without emptying anything
+        assert_eq!((cache.hits(), cache.misses(), cache.evictions()), (0, 2, 0));
+        assert!(cache.locate(0x0).is_none() && cache.locate(0x10).is_some());
+        assert_eq!(cache.check_invariants(), Ok(()));
+        assert_eq!(cache.format_stats(), "hits:0 misses:2 evictions:0\nflushes:3 flushed-lines:1 flush-write-backs:1");
+        cache.simulate_address('L', 0x0).unwrap();
+        assert_eq!(cache.misses(), 3);
+
+        cache.simulate_address('W', 0).unwrap();
+        assert!(cache.set_states().all(|set| set.lines.iter().all(|line| !line.valid)));
+        assert_eq!(cache.snapshot_stats().flushed_lines, 3);
+        // Stepping back refills every line the full flush emptied
+        assert!(cache.step_back());
+        assert!(cache.locate(0x0).is_some() && cache.locate(0x10).is_some());
+        assert_eq!(cache.check_invariants(), Ok(()));
+        cache.reset_stats();
+        assert_eq!(cache.format_stats(), "hits:0 misses:0 evictions:0");
+    }
+}
+
+#[test]
+fn test_simulate_address_flush_buffers() {
+    let mut cache = CacheBuilder::new().set_index_bits(2).associativity(2).block_bits(4).store_buffer(2).write_buffer(2, 100).build().unwrap();
+    // The buffered store to 0x0 is retired before the flush, which writes the dirty line back, while 0x10 stays buffered
+    for (op, address) in [('S', 0x0), ('S', 0x10), ('F', 0x4)] {
+        cache.simulate_address(op, address).unwrap();
+    }
+    let stats = cache.snapshot_stats();
+    assert_eq!((stats.misses, stats.flush_write_backs, stats.write_backs), (1, 1, 1));
+    assert!(cache.locate(0x0).is_none() && cache.locate(0x10).is_none());
+    cache.simulate_address('W', 0).unwrap();
+    let stats = cache.snapshot_stats();
+    assert_eq!((stats.misses, stats.flushed_lines, stats.flush_write_backs, stats.write_backs), (2, 2, 2, 2));
+
+    // A full flush is one record whatever its size
+    let mut cache = Cache::new(2, 2, 4).unwrap();
+    cache.access(Op::FlushAll, 0, 64).unwrap();
+    assert_eq!(cache.snapshot_stats().flushes, 1);
+}
+
+// Tests for line pinning
+#[test]
+fn test_pin() {
+    let mut cache = CacheBuilder::new().set_index_bits(0).associativity(2).block_bits(4).undo_depth(3).build().unwrap();
+    assert_eq!(cache.pin(0x0), Ok(true));
+    assert_eq!((cache.misses(), cache.prefetch_fills()), (0, 1));
+    // LRU would evict 0x0 for 0x20, so 0x10 goes instead and the next load of 0x0 is a miss saved
+    for address in [0x10, 0x20, 0x0, 0x0] {
+        cache.simulate_address('L', address).unwrap();
+    }
+    assert_eq!((cache.hits(), cache.misses(), cache.evictions()), (2, 2, 1));
+    assert_eq!(cache.format_stats(), "hits:2 misses:2 evictions:1\n\
+        pinned-lines:1 pinned-hits:2 spared-evictions:1 saved-misses:1 pin-bypasses:0\nprefetches:1 fills:1 already-cached:0");
+
+    // With every line pinned, misses fill nothing
+    assert_eq!(cache.pin(0x30), Ok(true));
+    cache.simulate_address('S', 0x40).unwrap();
+    assert_eq!(cache.pin(0x50), Ok(false));
+    let stats = cache.snapshot_stats();
+    assert_eq!((stats.misses, stats.evictions, stats.pin_bypasses, stats.spared_evictions), (3, 2, 2, 3));
+    assert_eq!(cache.describe_set(0).unwrap(), "set 0: [0] tag 0 pinned [1] tag 3 pinned | LRU order (most recent first): 1 0");
+    assert_eq!(cache.check_invariants(), Ok(()));
+
+    // Undoing a pin unpins the line, and unpinning or flushing a line lets it go
+    assert!(cache.step_back() && cache.step_back());
+    assert_eq!(cache.pinned_lines(), 2);
+    assert!(cache.step_back());
+    assert_eq!((cache.pinned_lines(), cache.locate(0x30)), (1, None));
+    cache.unpin(0x0).unwrap();
+    assert_eq!(cache.pin(0x10), Ok(true));
+    cache.simulate_address('F', 0x10).unwrap();
+    assert_eq!(cache.pinned_lines(), 0);
+    assert_eq!(cache.check_invariants(), Ok(()));
+}
+
+#[test]
+fn test_pin_policies() {
+    for replacement in [Replacement::Lru, Replacement::Fifo, Replacement::Random, Replacement::Plru] {
+        let mut cache = CacheBuilder::new().set_index_bits(0).associativity(4).block_bits(4).replacement(replacement).build().unwrap();
+        assert_eq!(cache.pin(0x0), Ok(true));
+        for round in 0..2 {
+            for address in (1..8).map(|block| block << 4) {
+                cache.simulate_address('L', address + round).unwrap();
+            }
+        }
+        cache.simulate_address('L', 0x0).unwrap();
+        assert_eq!((cache.locate(0x0).is_some(), cache.snapshot_stats().pinned_hits), (true, 1), "{}", replacement);
+        assert_eq!(cache.check_invariants(), Ok(()));
+    }
+
+    let mut cache = CacheBuilder::new().set_index_bits(2).associativity(2).block_bits(4).skewed(true).build().unwrap();
+    assert_eq!(cache.pin(0x0), Ok(true));
+    for address in (1..64).map(|block| block << 4) {
+        cache.simulate_address('L', address).unwrap();
+    }
+    assert!(cache.locate(0x0).is_some());
+    assert_eq!(cache.check_invariants(), Ok(()));
+}
+
+#[test]
+fn test_pin_errors() {
+    let mut cache = CacheBuilder::new().set_index_bits(1).associativity(2).block_bits(4).uncacheable(vec![0x100..=0x1ff]).build().unwrap();
+    assert_eq!(cache.pin(0x100), Ok(false));
+    assert_eq!(cache.pinned_lines(), 0);
+    let mut compressed = CacheBuilder::new().set_index_bits(1).associativity(2).block_bits(4).compression(0.5).build().unwrap();
+    assert_eq!(compressed.pin(0x0), Err(SimError::Access("compressed caches can't pin lines".to_string())));
+
+    // A pin record covers every block of its size
+    let mut cache = Cache::new(2, 1, 4).unwrap();
+    cache.simulate_access(MemoryAccess { operation: 'K', address: 0x8, thread: 0, size: 0x20, pc: 0 }).unwrap();
+    assert_eq!(cache.pinned_lines(), 3);
+    cache.access(Op::Unpin, 0x10, 0x20).unwrap();
+    assert_eq!(cache.pinned_lines(), 1);
+}
+
+// Tests for way locking
+#[test]
+fn test_lock_ways() {
+    let lock = WayLock { ways: 0..=0, sets: Some(0..=0) };
+    let mut cache = CacheBuilder::new().set_index_bits(1).associativity(2).block_bits(4).locked_ways(vec![lock]).build().unwrap();
+    // Way 0 of set 0 keeps 0x0, while set 1 replaces its lines as usual
+    for address in [0x0, 0x20, 0x40, 0x0, 0x10, 0x30, 0x50] {
+        cache.simulate_address('L', address).unwrap();
+    }
+    let stats = cache.snapshot_stats();
+    assert_eq!((stats.hits, stats.misses, stats.evictions), (1, 6, 2));
+    assert_eq!((stats.pinned_hits, stats.spared_evictions, stats.saved_misses), (1, 1, 1));
+    assert_eq!((cache.locate(0x0), cache.locate(0x10)), (Some((0, 0)), None));
+
+    // Unpinning leaves a locked way pinned, but a flush empties it for the next block
+    cache.unpin(0x0).unwrap();
+    assert_eq!(cache.pinned_lines(), 1);
+    cache.simulate_address('F', 0x0).unwrap();
+    assert_eq!(cache.pinned_lines(), 0);
+    cache.simulate_address('L', 0x60).unwrap();
+    assert_eq!((cache.pinned_lines(), cache.locate(0x60)), (1, Some((0, 0))));
+    assert_eq!(cache.check_invariants(), Ok(()));
+
+    // With every way locked, misses past the first fills bypass the cache
+    let mut cache = CacheBuilder::new().associativity(2).block_bits(4).locked_ways(parse_way_locks("0-1").unwrap()).build().unwrap();
+    for address in [0x0, 0x10, 0x20, 0x0] {
+        cache.simulate_address('L', address).unwrap();
+    }
+    assert_eq!((cache.hits(), cache.misses(), cache.evictions(), cache.snapshot_stats().pin_bypasses), (1, 3, 0, 1));
+
+    let mut cache = CacheBuilder::new().set_index_bits(2).associativity(2).block_bits(4).skewed(true).locked_ways(parse_way_locks("0").unwrap()).build().unwrap();
+    for address in (0..64).map(|block| block << 4) {
+        cache.simulate_address('L', address).unwrap();
+    }
+    assert_eq!(cache.pinned_lines(), 4);
+    assert!(cache.locate(0x0).is_some());
+    assert_eq!(cache.check_invariants(), Ok(()));
+}
+
+// Tests for scratchpads
+#[test]
+fn test_scratchpad() {
+    // 64 bytes take two of the four 32-byte ways
+    let mut cache = CacheBuilder::new().set_index_bits(1).associativity(4).block_bits(4).scratchpad(0x1000..=0x103f).undo_depth(1).build().unwrap();
+    assert_eq!(cache.associativity(), 2);
+    for (operation, address) in [('L', 0x1000), ('S', 0x1010), ('M', 0x1020), ('P', 0x1000), ('L', 0x0), ('L', 0x0)] {
+        cache.simulate_address(operation, address).unwrap();
+    }
+    assert_eq!((cache.hits(), cache.misses(), cache.scratchpad_accesses()), (1, 1, 4));
+    assert_eq!(cache.format_stats(), "hits:1 misses:1 evictions:0\n\
+        scratchpad-accesses:4 cache-accesses:2 scratchpad-share:66.67% scratchpad-ways:2");
+    assert_eq!(cache.pin(0x1000), Ok(false));
+    assert_eq!(cache.locate(0x1000), None);
+
+    cache.simulate_address('A', 0x103c).unwrap();
+    assert_eq!(cache.snapshot_stats().scratchpad_accesses, 6);
+    assert!(cache.step_back());
+    assert_eq!(cache.scratchpad_accesses(), 4);
+    cache.reset_stats();
+    assert_eq!(cache.scratchpad_accesses(), 0);
+
+    let cache = CacheBuilder::new().set_index_bits(1).associativity(4).block_bits(4).replacement(Replacement::Plru).scratchpad(0x0..=0x3f).build().unwrap();
+    assert_eq!(cache.associativity(), 2);
+    assert_eq!(
+        CacheBuilder::new().associativity(4).block_bits(4).scratchpad(0x0..=0x17).validate(),
+        Err(SimError::Config("scratchpad size 24 is not a multiple of the 16 bytes of a way".to_string()))
+    );
+}
+
+// Tests for bypass prediction
+#[test]
+fn test_bypass() {
+    // 64-byte regions: three dead evictions from region 0 make the next fill from it bypass, and reusing the
+    // bypassed block brings the region back
+    let mut cache = CacheBuilder::new().associativity(2).block_bits(4).bypass(64).undo_depth(1).build().unwrap();
+    for address in [0x0, 0x10, 0x20, 0x30, 0x40, 0x50, 0x0] {
+        cache.simulate_address('L', address).unwrap();
+    }
+    assert_eq!((cache.locate(0x0), cache.snapshot_stats().bypassed_fills), (None, 1));
+    cache.simulate_address('L', 0x0).unwrap();
+    assert!(cache.locate(0x0).is_some());
+    assert_eq!(cache.format_stats(), "hits:0 misses:8 evictions:5\nbypass: bypassed-fills:1 reused:1 dead-regions:0");
+    assert!(cache.step_back());
+    assert_eq!(cache.format_stats(), "hits:0 misses:7 evictions:4\nbypass: bypassed-fills:1 reused:0 dead-regions:1");
+    cache.reset_stats();
+    assert_eq!((cache.snapshot_stats().bypassed_fills, cache.snapshot_stats().bypass_reuses), (0, 0));
+
+    // A stream through a direct-mapped cache stops displacing the block reused between its accesses
+    let accesses: Vec<u64> = (0..256).flat_map(|block| [0x10000, block << 4]).collect();
+    let mut plain = Cache::new(0, 1, 4).unwrap();
+    let mut bypassed = CacheBuilder::new().block_bits(4).bypass(4096).build().unwrap();
+    for &address in &accesses {
+        plain.simulate_address('L', address).unwrap();
+        bypassed.simulate_address('L', address).unwrap();
+    }
+    assert_eq!(plain.hits(), 0);
+    assert!(bypassed.hits() > 200);
+    assert_eq!(bypassed.check_invariants(), Ok(()));
+
+    // Only recently bypassed blocks are remembered, a block replacing the one sharing its entry
+    let mut predictor = BypassPredictor::new(6);
+    predictor.bypass(0x5);
+    predictor.bypass(0x5 + BYPASSED_BLOCKS as u64);
+    predictor.predict(0x5, 0x50);
+    assert_eq!(predictor.reuses, 0);
+    predictor.predict(0x5 + BYPASSED_BLOCKS as u64, 0x50);
+    assert_eq!((predictor.reuses, predictor.bypassed.len()), (1, BYPASSED_BLOCKS));
+}
+
+#[test]
+fn test_tinylfu_admission() {
+    // A and B are accessed five times each before a scan of blocks used once, none of which is estimated to be
+    // accessed more often than the line it would evict, so A and B are still there after the scan
+    let mut tags: Vec<usize> = (0..10).map(|tag| 0xa + tag % 2).collect();
+    tags.extend(0x100..0x120);
+    tags.extend([0xa, 0xb]);
+    let mut lru = CacheBuilder::new().associativity(2).build().unwrap();
+    // Sixteen sets size the sketch, only set 0 being accessed
+    let mut tinylfu = CacheBuilder::new().set_index_bits(4).associativity(2).tinylfu(true).undo_depth(1).build().unwrap();
+    simulate_tags(&mut lru, &tags);
+    simulate_tags(&mut tinylfu, &tags);
+    assert_eq!((lru.hits, tinylfu.hits, tinylfu.evictions), (8, 10, 0));
+    let stats = tinylfu.snapshot_stats();
+    assert_eq!((stats.admitted_fills, stats.rejected_fills, stats.misses), (0, 32, 34));
+    assert_eq!(tinylfu.format_stats(), "hits:10 misses:34 evictions:0\ntinylfu: admitted:0 rejected:32 counters:4x32 resets:0");
+
+    // A block accessed more often than the victim since replaces it
+    simulate_tags(&mut tinylfu, &[0x100; 6]);
+    tinylfu.simulate_address('L', 0x100 << 4).unwrap();
+    assert_eq!(tinylfu.evictions, 1);
+    assert_eq!(tinylfu.tinylfu().map(|filter| (filter.admitted(), filter.rejected())), Some((1, 38)));
+    assert!(tinylfu.step_back());
+    assert_eq!(tinylfu.tinylfu().map(|filter| (filter.admitted(), filter.rejected())), Some((0, 38)));
+    assert_eq!(tinylfu.check_invariants(), Ok(()));
+    tinylfu.reset_stats();
+    assert_eq!((tinylfu.snapshot_stats().admitted_fills, tinylfu.snapshot_stats().rejected_fills), (0, 0));
+
+    assert!(CacheBuilder::new().associativity(2).skewed(true).tinylfu(true).build().is_err());
+}
+
+// Tests for simulate_access and drive functions
+#[test]
+fn test_simulate_access_result() {
+    let mut cache = Cache::new(4, 1, 4).unwrap();
+    let load = MemoryAccess { operation: 'L', address: 0x10, thread: 0, size: 1, pc: 0 };
+    let modify = MemoryAccess { operation: 'M', address: 0x110, thread: 2, size: 4, pc: 0 };
+
+    assert_eq!(cache.simulate_access(load), Ok(AccessResult { access: load, hits: 0, misses: 1, evictions: 0, prefetch_hits: 0, prefetch_fills: 0 }));
+    assert_eq!(cache.simulate_access(modify), Ok(AccessResult { access: modify, hits: 1, misses: 1, evictions: 1, prefetch_hits: 0, prefetch_fills: 0 }));
+    assert_eq!(cache.thread, 2);
+    assert!(cache.simulate_access(MemoryAccess { operation: 'X', address: 0, thread: 0, size: 1, pc: 0 }).is_err());
+}
+
+#[test]
+fn test_drive() {
+    let mut cache = Cache::new(4, 1, 4).unwrap();
+    let accesses: Vec<MemoryAccess> = [0x10, 0x10, 0x20, 0x110, 0x10]
+        .iter()
+        .map(|&address| MemoryAccess { operation: 'L', address, thread: 0, size: 1, pc: 0 })
+        .collect();
+
+    let hit_addresses: Vec<u64> = cache.drive(accesses.clone())
+        .map(|result| result.unwrap())
+        .filter(|result| result.hits > 0)
+        .map(|result| result.access.address)
+        .collect();
+    assert_eq!(hit_addresses, vec![0x10]);
+    assert_eq!((cache.hits, cache.misses, cache.evictions), (1, 4, 2));
+
+    // Accesses are only simulated as the iterator is consumed
+    let mut cache = Cache::new(4, 1, 4).unwrap();
+    let before_first_eviction = cache.drive(accesses)
+        .take_while(|result| result.as_ref().is_ok_and(|result| result.evictions == 0))
+        .count();
+    assert_eq!(before_first_eviction, 3);
+    assert_eq!((cache.hits, cache.misses, cache.evictions), (1, 3, 1));
+}
+
+// Tests for access function
+#[test]
+fn test_access() {
+    let mut cache = Cache::new(4, 1, 4).unwrap();
+    let load = cache.access(Op::Load, 0x10, 4).unwrap();
+    assert_eq!(load, AccessResult { access: MemoryAccess { operation: 'L', address: 0x10, thread: 0, size: 4, pc: 0 }, hits: 0, misses: 1, evictions: 0, prefetch_hits: 0, prefetch_fills: 0 });
+    assert_eq!(cache.access(Op::Modify, 0x14, 4).unwrap().hits, 2);
+    assert_eq!(cache.access(Op::Store, 0x1f, 1).unwrap().hits, 1);
+
+    // Straddling a block boundary touches both blocks
+    let straddle = cache.access(Op::Load, 0x1c, 8).unwrap();
+    assert_eq!((straddle.hits, straddle.misses, straddle.evictions), (1, 1, 0));
+    assert_eq!((cache.hits, cache.misses), (4, 2));
+
+    // The last block of the address space
+    assert_eq!(cache.access(Op::Load, u64::MAX, 4).unwrap().misses, 1);
+    assert_eq!(cache.access(Op::Load, 0, 0), Err(SimError::Access("access size must be at least 1".to_string())));
+}
+
+#[test]
+fn test_op_conversions() {
+    for (op, operation) in [(Op::Load, 'L'), (Op::Store, 'S'), (Op::Modify, 'M'), (Op::Prefetch, 'P'), (Op::Atomic, 'A'), (Op::Flush, 'F'), (Op::FlushAll, 'W'), (Op::Pin, 'K'), (Op::Unpin, 'U')] {
+        assert_eq!(char::from(op), operation);
+        assert_eq!(Op::try_from(operation), Ok(op));
+    }
+    assert!(Op::try_from('X').is_err());
+}
+
+// Tests for state inspection
+#[test]
+fn test_set_state() {
+    let mut cache = Cache::new(1, 2, 4).unwrap();
+    cache.access(Op::Load, 0x00, 1).unwrap();
+    cache.access(Op::Store, 0x20, 1).unwrap();
+    assert_eq!(cache.set_state(0), Some(SetState {
+        index: 0,
+        lines: vec![
+            LineState { way: 0, valid: true, tag: Some(0), dirty: false, pinned: false },
+            LineState { way: 1, valid: true, tag: Some(1), dirty: true, pinned: false },
+        ],
+        lru_order: vec![1, 0],
+    }));
+    assert_eq!(cache.set_state(1).unwrap().lines[0], LineState { way: 0, valid: false, tag: None, dirty: false, pinned: false });
+    assert_eq!(cache.set_state(2), None);
+    assert_eq!(cache.set_states().count(), 2);
+}
+
+// Tests for locate method
+#[test]
+fn test_locate() {
+    let mut cache = Cache::new(1, 2, 4).unwrap();
+    cache.access(Op::Load, 0x00, 1).unwrap();
+    cache.access(Op::Store, 0x20, 1).unwrap();
+    assert_eq!(cache.locate(0x0c), Some((0, 0)));
+    assert_eq!(cache.locate(0x20), Some((0, 1)));
+    assert_eq!(cache.locate(0x10), None);
+    assert_eq!(cache.locate(0x40), None);
+
+    let mut cache = CacheBuilder::new().set_index_bits(1).associativity(1).block_bits(4).address_bits(8).oob_policy(OobPolicy::Mask).build().unwrap();
+    cache.access(Op::Load, 0x110, 1).unwrap();
+    assert_eq!(cache.locate(0x10), Some((1, 0)));
+    assert_eq!(cache.locate(0x210), Some((1, 0)));
+    let cache = CacheBuilder::new().set_index_bits(1).associativity(1).block_bits(4).address_bits(8).oob_policy(OobPolicy::Skip).build().unwrap();
+    assert_eq!(cache.locate(0x110), None);
+}
+
+#[test]
+fn test_dirty_bits() {
+    let mut cache = Cache::new(1, 1, 4).unwrap();
+    cache.access(Op::Load, 0x00, 1).unwrap();
+    assert!(!cache.set_state(0).unwrap().lines[0].dirty);
+    cache.access(Op::Modify, 0x00, 1).unwrap();
+    assert!(cache.set_state(0).unwrap().lines[0].dirty);
+    // A load filling the line over the dirty block leaves it clean
+    cache.access(Op::Load, 0x20, 1).unwrap();
+    assert!(!cache.set_state(0).unwrap().lines[0].dirty);
+    cache.access(Op::Store, 0x40, 1).unwrap();
+    assert!(cache.set_state(0).unwrap().lines[0].dirty);
+    // Only the dirty block 0x00 had to be written back when it was evicted
+    assert_eq!((cache.evictions(), cache.write_backs()), (2, 1));
+    cache.reset_stats();
+    assert_eq!(cache.write_backs(), 0);
+}
+
+#[test]
+fn test_set_state_skewed_and_compressed() {
+    let mut cache = CacheBuilder::new().set_index_bits(2).associativity(2).block_bits(4).skewed(true).build().unwrap();
+    cache.access(Op::Store, 0x00, 1).unwrap();
+    let (index, way) = cache.set_states()
+        .find_map(|state| state.lines.iter().find(|line| line.valid).map(|line| (state.index, line.way)))
+        .unwrap();
+    let state = cache.set_state(index).unwrap();
+    assert!(state.lines[way].dirty);
+    assert_eq!(state.lru_order, vec![way]);
+    assert_eq!(cache.locate(0x08), Some((index, way)));
+
+    let mut cache = CacheBuilder::new().set_index_bits(1).associativity(1).block_bits(4).compression(1.0).build().unwrap();
+    cache.access(Op::Store, 0x00, 1).unwrap();
+    cache.access(Op::Load, 0x20, 1).unwrap();
+    let state = cache.set_state(0).unwrap();
+    assert_eq!(state.lines.iter().filter(|line| line.dirty).count(), 1);
+    assert_eq!(state.lru_order.len(), 2);
+}
+
+#[test]
+fn test_display() {
+    let mut cache = Cache::new(2, 2, 4).unwrap();
+    cache.access(Op::Load, 0x10, 1).unwrap();
+    cache.access(Op::Modify, 0x50, 1).unwrap();
+    assert_eq!(cache.to_string(), "cache: 4 sets x 2 lines of 16 bytes\n\
+        hits:1 misses:2 evictions:0\n\
+        set 1: [0] tag 0 [1] tag 1 dirty | LRU order (most recent first): 1 0\n");
+}
+
+// Tests for the compressed cache
+#[test]
+fn test_compressed_size() {
+    let mut cache = Cache::new(2, 1, 4).unwrap();
+    cache.enable_compression(0.0).unwrap();
+    assert!((0..100).all(|tag| cache.compressed_size(1, tag) == SEGMENTS_PER_LINE));
+
+    cache.enable_compression(1.0).unwrap();
+    assert!((0..100).all(|tag| cache.compressed_size(1, tag) == SEGMENTS_PER_LINE / 2));
+
+    cache.enable_compression(0.5).unwrap();
+    let compressible = (0..1000).filter(|&tag| cache.compressed_size(1, tag) < SEGMENTS_PER_LINE).count();
+    assert!((400..600).contains(&compressible));
+    assert_eq!(cache.compressed_size(1, 7), cache.compressed_size(1, 7));
+}
+
+#[test]
+fn test_compression_fits_more_lines() {
+    // Every block compresses, so a 1-line set holds two blocks
+    let mut cache = Cache::new(1, 1, 4).unwrap();
+    cache.enable_compression(1.0).unwrap();
+    assert_eq!(cache.sets[0].lines.len(), 2);
+
+    for address in [0x00, 0x20, 0x00, 0x20, 0x40, 0x00] {
+        assert_eq!(cache.simulate_address('L', address), Ok(()));
+    }
+    assert_eq!((cache.hits, cache.misses, cache.evictions), (2, 4, 2));
+    assert_eq!(
+        cache.format_stats(),
+        "hits:2 misses:4 evictions:2\ncompression: resident-lines:2 effective-capacity:1.00x baseline-misses:6 miss-reduction:33.33%"
+    );
+}
+
+#[test]
+fn test_compression_evicts_until_block_fits() {
+    let mut cache = Cache::new(0, 2, 4).unwrap();
+    cache.enable_compression(0.5).unwrap();
+    let compressible: Vec<usize> = (0..).filter(|&tag| cache.compressed_size(0, tag) < SEGMENTS_PER_LINE).take(4).collect();
+    let incompressible = (0..).find(|&tag| cache.compressed_size(0, tag) == SEGMENTS_PER_LINE).unwrap();
+
+    // Four half-size blocks fill the two lines
+    for &tag in &compressible {
+        assert_eq!(cache.simulate_memory_access('L', 0, tag), Ok(()));
+    }
+    assert_eq!(cache.evictions, 0);
+
+    // An incompressible block needs two evictions to fit
+    assert_eq!(cache.simulate_memory_access('L', 0, incompressible), Ok(()));
+    assert_eq!(cache.evictions, 2);
+    let resident: Vec<Option<usize>> = cache.sets[0].lines.iter().filter(|line| line.is_valid).map(|line| line.tag).collect();
+    assert_eq!(resident.len(), 3);
+    assert!(!resident.contains(&Some(compressible[0])) && !resident.contains(&Some(compressible[1])));
+}
+
+// Tests for undo and debug mode
+#[test]
+fn test_step_back() {
+    let mut cache = Cache::new(2, 1, 4).unwrap();
+    cache.set_undo_depth(10);
+    cache.way_predictor = Some(WayPredictor::Mru);
+    cache.banks = Some(Banks::new(2, 2));
+
+    cache.simulate_address('L', 0x10).unwrap();
+    let set_before = cache.describe_set(1).unwrap();
+    let stats_before = cache.format_stats();
+
+    cache.simulate_address('M', 0x50).unwrap();
+    cache.simulate_address('L', 0x50).unwrap();
+    assert_ne!(cache.describe_set(1).unwrap(), set_before);
+
+    assert!(cache.step_back());
+    assert!(cache.step_back());
+    assert_eq!(cache.describe_set(1).unwrap(), set_before);
+    assert_eq!(cache.format_stats(), stats_before);
+    assert!(cache.step_back());
+    assert_eq!(cache.format_stats(), "hits:0 misses:0 evictions:0\nway-prediction: fast-hits:0 slow-hits:0 accuracy:0.00%\nbanks:2 cycles:0 bank-conflicts:0");
+    assert!(!cache.step_back());
+}
+
+// Simulate accesses, step back over the last steps of them and replay those, checking that stepping back restores
+// the state the cache had before them and that the replay ends where the first run did
+#[cfg(test)]
+fn assert_step_back_replays(builder: CacheBuilder, accesses: &[(char, u64)], steps: usize, state: fn(&Cache) -> String) {
+    let mut cache = builder.undo_depth(steps).build().unwrap();
+    let (before, replayed) = accesses.split_at(accesses.len() - steps);
+    for &(operation, address) in before {
+        cache.simulate_address(operation, address).unwrap();
+    }
+    let expected_before = state(&cache);
+    for &(operation, address) in replayed {
+        cache.simulate_address(operation, address).unwrap();
+    }
+    let expected_after = state(&cache);
+    for _ in 0..steps {
+        assert!(cache.step_back());
+    }
+    assert_eq!(state(&cache), expected_before);
+    assert_eq!(cache.check_invariants(), Ok(()));
+    for &(operation, address) in replayed {
+        cache.simulate_address(operation, address).unwrap();
+    }
+    assert_eq!(state(&cache), expected_after);
+}
+
+// Random loads and stores to a few blocks of each set
+#[cfg(test)]
+fn random_accesses(count: usize, blocks: usize, block_bits: usize) -> Vec<(char, u64)> {
+    let mut rng = Rng::new(7);
+    (0..count).map(|_| (if rng.next_below(4) == 0 { 'S' } else { 'L' }, (rng.next_below(blocks) as u64) << block_bits)).collect()
+}
+
+#[test]
+fn test_step_back_tinylfu() {
+    // Enough accesses for the sketch of 16 lines to be halved a few times
+    let builder = CacheBuilder::new().set_index_bits(2).associativity(4).block_bits(4).store_buffer(2).tinylfu(true);
+    let mut accesses = random_accesses(1000, 48, 4);
+    accesses.iter_mut().step_by(7).for_each(|access| access.0 = 'M');
+    assert_step_back_replays(builder, &accesses, 400, |cache| {
+        let filter = cache.tinylfu().unwrap();
+        let estimates = (0..48).map(|block| filter.estimate(block)).collect::<Vec<_>>();
+        format!("{} {} {:?}", cache.format_stats(), filter.resets(), estimates)
+    });
+}
+
+#[test]
+fn test_step_back_miss_classes() {
+    let builder = CacheBuilder::new().set_index_bits(2).associativity(2).block_bits(4).store_buffer(2).classify_misses(true);
+    assert_step_back_replays(builder, &random_accesses(400, 24, 4), 100, Cache::format_stats);
+}
+
+#[test]
+fn test_step_back_hot_blocks() {
+    let builder = CacheBuilder::new().set_index_bits(2).associativity(2).block_bits(4).store_buffer(2).track_hot_blocks(6);
+    assert_step_back_replays(builder, &random_accesses(400, 24, 4), 100, |cache| format!("{:?}", cache.hot_blocks(6)));
+}
+
+#[test]
+fn test_step_back_two_queue() {
+    let builder = CacheBuilder::new().set_index_bits(2).associativity(4).block_bits(4).replacement(Replacement::TwoQ);
+    assert_step_back_replays(builder, &random_accesses(400, 48, 4), 100, |cache| {
+        let queues = cache.two_queue().unwrap();
+        let ghosts = (0..cache.set_count()).map(|set| queues.ghosts(set).clone()).collect::<Vec<_>>();
+        format!("{} {:?}", cache.format_stats(), ghosts)
+    });
+}
+
+#[test]
+fn test_step_back_lirs() {
+    let builder = CacheBuilder::new().set_index_bits(2).associativity(4).block_bits(4).replacement(Replacement::Lirs);
+    assert_step_back_replays(builder, &random_accesses(400, 48, 4), 100, |cache| {
+        let lirs = cache.lirs().unwrap();
+        let stacks = (0..cache.set_count()).map(|set| {
+            let stack = lirs.stack(set).iter().map(|&tag| format!("{:x}:{:?}", tag, lirs.status(set, tag))).collect::<Vec<_>>();
+            format!("{:?} {:?}", stack, lirs.victims(set).collect::<Vec<_>>())
+        });
+        format!("{} {:?}", cache.format_stats(), stacks.collect::<Vec<_>>())
+    });
+}
+
+#[test]
+fn test_step_back_bypass() {
+    let builder = CacheBuilder::new().set_index_bits(1).associativity(2).block_bits(4).bypass(32);
+    assert_step_back_replays(builder, &random_accesses(400, 24, 4), 100, |cache| {
+        let sets = (0..cache.set_count()).map(|set| cache.describe_set(set).unwrap()).collect::<Vec<_>>();
+        format!("{} {:?}", cache.format_stats(), sets)
+    });
+}
+
+#[test]
+fn test_step_back_ship() {
+    for replacement in [Replacement::ShipPc, Replacement::ShipMem] {
+        let builder = CacheBuilder::new().set_index_bits(2).associativity(4).block_bits(10).replacement(replacement);
+        // Modifies hit the lines they fill, training the signatures of the fills
+        let accesses: Vec<_> = random_accesses(400, 96, 10).into_iter().map(|(op, address)| if op == 'S' { ('M', address) } else { (op, address) }).collect();
+        assert_step_back_replays(builder, &accesses, 100, |cache| format!("{} {:?}", cache.format_stats(), cache.ship()));
+    }
+    // Including the signature of a region no line was filled from before
+    let builder = CacheBuilder::new().associativity(2).block_bits(4).replacement(Replacement::ShipMem);
+    assert_step_back_replays(builder, &[('L', 0x0), ('M', 0x10_0000)], 1, |cache| format!("{:?}", cache.ship()));
+}
+
+#[test]
+fn test_step_back_hawkeye() {
+    let builder = CacheBuilder::new().set_index_bits(2).associativity(4).block_bits(4).replacement(Replacement::Hawkeye);
+    assert_step_back_replays(builder, &random_accesses(400, 48, 4), 100, |cache| format!("{} {:?}", cache.format_stats(), cache.hawkeye()));
+}
+
+#[test]
+fn test_step_back_bounded() {
+    let mut cache = Cache::new(2, 1, 4).unwrap();
+    cache.set_undo_depth(2);
+
+    for address in [0x10, 0x20, 0x30] {
+        cache.simulate_address('L', address).unwrap();
+    }
+    assert!(cache.step_back());
+    assert!(cache.step_back());
+    assert!(!cache.step_back());
+    assert_eq!(cache.misses, 1);
+
+    // Undo is off by default
+    let mut cache = Cache::new(2, 1, 4).unwrap();
+    cache.simulate_address('L', 0x10).unwrap();
+    assert!(!cache.step_back());
+}
+
+#[test]
+fn test_step_back_skewed_and_compressed() {
+    let mut cache = Cache::new(2, 2, 4).unwrap();
+    cache.skewed = true;
+    cache.tie_break = TieBreak::RoundRobin;
+    cache.set_undo_depth(4);
+    for address in [0x0, 0x40, 0x80] {
+        cache.simulate_address('L', address).unwrap();
+    }
+    let sets_before: Vec<String> = (0..4).map(|set_index| cache.describe_set(set_index).unwrap()).collect();
+    let next_way_before = cache.sets[0].next_way;
+    cache.simulate_address('L', 0xc0).unwrap();
+    assert!(cache.step_back());
+    assert_eq!((0..4).map(|set_index| cache.describe_set(set_index).unwrap()).collect::<Vec<String>>(), sets_before);
+    assert_eq!(cache.sets[0].next_way, next_way_before);
+
+    let mut cache = Cache::new(1, 1, 4).unwrap();
+    cache.enable_compression(1.0).unwrap();
+    cache.set_undo_depth(4);
+    for address in [0x00, 0x20, 0x40] {
+        cache.simulate_address('L', address).unwrap();
+    }
+    assert!(cache.step_back());
+    assert_eq!(cache.format_stats(), "hits:0 misses:2 evictions:0\ncompression: resident-lines:2 effective-capacity:1.00x baseline-misses:2 miss-reduction:0.00%");
+}
+
+#[test]
+fn test_describe_access_result() {
+    let access = MemoryAccess { operation: 'M', address: 0x20, thread: 0, size: 4, pc: 0 };
+    assert_eq!(AccessResult { access, hits: 1, misses: 1, evictions: 1, prefetch_hits: 0, prefetch_fills: 0 }.describe(), "M 20 miss eviction hit");
+    assert_eq!(AccessResult { access, hits: 2, misses: 0, evictions: 0, prefetch_hits: 0, prefetch_fills: 0 }.describe(), "M 20 hit hit");
+    assert_eq!(AccessResult { access, hits: 0, misses: 0, evictions: 0, prefetch_hits: 0, prefetch_fills: 0 }.describe(), "M 20 skipped");
+    let access = MemoryAccess { operation: 'P', ..access };
+    assert_eq!(AccessResult { access, hits: 0, misses: 0, evictions: 1, prefetch_hits: 0, prefetch_fills: 1 }.describe(), "P 20 prefetch-fill eviction");
+    assert_eq!(AccessResult { access, hits: 0, misses: 0, evictions: 0, prefetch_hits: 1, prefetch_fills: 0 }.describe(), "P 20 prefetch-hit");
+}
+
+// Tests for statistics reset and regions of interest
+#[test]
+fn test_snapshot_and_reset_stats() {
+    let mut cache = CacheBuilder::new().set_index_bits(1).associativity(1).block_bits(4).banks(2, 1).build().unwrap();
+    cache.simulate_address('L', 0x00).unwrap();
+    cache.simulate_address('L', 0x00).unwrap();
+    let before = cache.snapshot_stats();
+    assert_eq!((before.hits, before.misses, before.bank_cycles), (1, 1, 2));
+    assert_eq!(before.threads[&0], ThreadStats { hits: 1, misses: 1, evictions: 0 });
+
+    cache.simulate_address('L', 0x20).unwrap();
+    let window = cache.snapshot_stats().since(&before);
+    assert_eq!((window.hits, window.misses, window.evictions, window.bank_cycles), (0, 1, 1, 1));
+    assert_eq!(window.threads[&0], ThreadStats { hits: 0, misses: 1, evictions: 1 });
+
+    // Contents stay warm across a reset
+    cache.reset_stats();
+    assert_eq!(cache.snapshot_stats(), Stats::default());
+    cache.simulate_address('L', 0x20).unwrap();
+    assert_eq!((cache.hits(), cache.misses()), (1, 0));
+}
+
+#[test]
+fn test_reset_stats_drops_undo() {
+    let mut cache = CacheBuilder::new().set_index_bits(1).associativity(1).block_bits(4).undo_depth(4).build().unwrap();
+    cache.simulate_address('L', 0x00).unwrap();
+    cache.reset_stats();
+    assert!(!cache.step_back());
+}
+
+#[test]
+fn test_roi() {
+    let mut cache = Cache::new(1, 1, 4).unwrap();
+    cache.simulate_address('L', 0x00).unwrap();
+    {
+        let mut roi = cache.roi();
+        roi.simulate_address('L', 0x00).unwrap();
+        roi.simulate_address('L', 0x20).unwrap();
+        assert_eq!((roi.stats().hits, roi.stats().misses, roi.stats().evictions), (1, 1, 1));
+    }
+    cache.simulate_address('L', 0x00).unwrap();
+    {
+        let mut roi = cache.roi();
+        roi.simulate_address('L', 0x00).unwrap();
+    }
+    let total = cache.roi_stats();
+    assert_eq!((total.hits, total.misses, total.evictions), (2, 1, 1));
+    assert_eq!((cache.hits(), cache.misses()), (2, 3));
+}
+
+#[test]
+fn test_roi_reset_inside() {
+    let mut cache = Cache::new(1, 1, 4).unwrap();
+    cache.simulate_address('L', 0x00).unwrap();
+    cache.simulate_address('L', 0x00).unwrap();
+    {
+        let mut roi = cache.roi();
+        roi.simulate_address('L', 0x20).unwrap();
+        roi.reset_stats();
+        roi.simulate_address('L', 0x20).unwrap();
+    }
+    let total = cache.roi_stats();
+    assert_eq!((total.hits, total.misses, total.evictions), (1, 0, 0));
+}
+
+// Tests for per-set statistics
+#[test]
+fn test_set_stats() {
+    let mut cache = Cache::new(1, 1, 4).unwrap();
+    // Blocks 0, 2 and 4 all map to set 0, block 1 to set 1
+    for address in [0x0, 0x20, 0x0, 0x10, 0x40, 0x10] {
+        cache.simulate_address('L', address).unwrap();
+    }
+    assert_eq!(cache.set_stats(), vec![
+        SetStats { hits: 0, misses: 4, evictions: 3 },
+        SetStats { hits: 1, misses: 1, evictions: 0 },
+    ]);
+    assert_eq!(cache.format_set_stats(), "set 0: hits:0 misses:4 evictions:3\nset 1: hits:1 misses:1 evictions:0\nsets-used:2/2");
+
+    cache.reset_stats();
+    assert_eq!(cache.set_stats(), vec![SetStats::default(); 2]);
+    assert_eq!(cache.format_set_stats(), "sets-used:0/2");
+}
+
+#[test]
+fn test_set_stats_modes() {
+    let builders = [
+        CacheBuilder::new().set_index_bits(2).associativity(2).block_bits(4).skewed(true),
+        CacheBuilder::new().set_index_bits(2).associativity(2).block_bits(4).compression(0.5),
+        CacheBuilder::new().set_index_bits(2).associativity(2).block_bits(4).undo_depth(4),
+    ];
+    for builder in builders {
+        let mut cache = builder.build().unwrap();
+        for address in (0..40).map(|i| (i * 0x50) % 0x400) {
+            cache.simulate_address('M', address).unwrap();
+        }
+        let set_stats = cache.set_stats();
+        assert_eq!(set_stats.iter().map(|stats| stats.hits).sum::<usize>(), cache.hits());
+        assert_eq!(set_stats.iter().map(|stats| stats.misses).sum::<usize>(), cache.misses());
+        assert_eq!(set_stats.iter().map(|stats| stats.evictions).sum::<usize>(), cache.evictions());
+    }
+}
+
+#[test]
+fn test_set_stats_step_back() {
+    let mut cache = CacheBuilder::new().set_index_bits(1).associativity(1).block_bits(4).undo_depth(2).build().unwrap();
+    cache.simulate_address('L', 0x0).unwrap();
+    cache.simulate_address('L', 0x20).unwrap();
+    assert!(cache.step_back());
+    assert_eq!(cache.set_stats()[0], SetStats { hits: 0, misses: 1, evictions: 0 });
+}
+
+// Tests for miss classification
+#[test]
+fn test_classify_misses() {
+    // Two direct-mapped lines: blocks 0 and 2 share set 0
+    let mut cache = CacheBuilder::new().set_index_bits(1).associativity(1).block_bits(4).classify_misses(true).build().unwrap();
+    for address in [0x0, 0x20, 0x0] {
+        cache.simulate_address('L', address).unwrap();
+    }
+    let stats = cache.snapshot_stats();
+    assert_eq!((stats.compulsory_misses, stats.capacity_misses, stats.conflict_misses), (2, 0, 1));
+
+    // Three blocks cycling through two lines miss even in a fully-associative cache
+    for address in [0x10, 0x20, 0x0] {
+        cache.simulate_address('L', address).unwrap();
+    }
+    assert_eq!(cache.format_stats(), "hits:0 misses:6 evictions:4\nmiss-classes: compulsory:3 capacity:2 conflict:1");
+
+    // Blocks stay seen after a reset, so later misses aren't compulsory
+    cache.reset_stats();
+    cache.simulate_address('M', 0x20).unwrap();
+    let stats = cache.snapshot_stats();
+    assert_eq!((stats.misses, stats.compulsory_misses, stats.capacity_misses, stats.conflict_misses), (1, 0, 0, 1));
+}
+
+#[test]
+fn test_classify_misses_adds_up() {
+    let builders = [
+        CacheBuilder::new().set_index_bits(2).associativity(2).block_bits(3),
+        CacheBuilder::new().set_index_bits(4).associativity(1).block_bits(4),
+        CacheBuilder::new().set_index_bits(2).associativity(2).block_bits(3).skewed(true),
+        CacheBuilder::new().set_index_bits(2).associativity(2).block_bits(3).compression(0.5),
+    ];
+    for builder in builders {
+        let mut cache = builder.classify_misses(true).build().unwrap();
+        simulate_tracefile(&mut cache, "../traces/trans.trace").unwrap();
+        let stats = cache.snapshot_stats();
+        assert_eq!(stats.compulsory_misses + stats.capacity_misses + stats.conflict_misses, stats.misses);
+        assert!(stats.compulsory_misses > 0);
+    }
+
+    let mut cache = Cache::new(2, 2, 3).unwrap();
+    simulate_tracefile(&mut cache, "../traces/trans.trace").unwrap();
+    assert!(!cache.format_stats().contains("miss-classes"));
+    assert_eq!(cache.snapshot_stats().compulsory_misses, 0);
+}
+
+#[test]
+fn test_classify_misses_step_back() {
+    let mut cache = CacheBuilder::new().set_index_bits(1).associativity(1).block_bits(4).classify_misses(true).undo_depth(2).build().unwrap();
+    cache.simulate_address('L', 0x0).unwrap();
+    cache.simulate_address('L', 0x20).unwrap();
+    assert!(cache.step_back());
+    assert!(cache.step_back());
+    cache.simulate_address('L', 0x0).unwrap();
+    assert_eq!(cache.snapshot_stats().compulsory_misses, 1);
+}
+
+// Tests for hot block tracking
+#[test]
+fn test_hot_blocks() {
+    let mut cache = CacheBuilder::new().set_index_bits(1).associativity(1).block_bits(4).track_hot_blocks(8).build().unwrap();
+    for address in [0x0, 0x4, 0x20, 0x0, 0x10, 0x14, 0x18] {
+        cache.simulate_address('L', address).unwrap();
+    }
+    cache.simulate_address('M', 0x10).unwrap();
+    assert_eq!(cache.hot_blocks(2), Some(vec![
+        BlockStats { address: 0x10, lookups: 5, hits: 4 },
+        BlockStats { address: 0x0, lookups: 3, hits: 1 },
+    ]));
+    assert_eq!(cache.format_hot_blocks(3), "hot 1: block:10 lookups:5 hits:4 hit-rate:80.00%\n\
+        hot 2: block:0 lookups:3 hits:1 hit-rate:33.33%\n\
+        hot 3: block:20 lookups:1 hits:0 hit-rate:0.00%");
+
+    cache.reset_stats();
+    assert_eq!(cache.hot_blocks(2), Some(Vec::new()));
+    assert_eq!(Cache::new(1, 1, 4).unwrap().hot_blocks(2), None);
+    assert!(CacheBuilder::new().track_hot_blocks(0).build().is_err());
+}
+
+#[test]
+fn test_hot_blocks_bounded() {
+    // Two tracked blocks: a cold block replaces the least counted one and inherits its count as an error bound
+    let mut cache = CacheBuilder::new().set_index_bits(2).associativity(2).block_bits(4).track_hot_blocks(2).build().unwrap();
+    for address in [0x0, 0x0, 0x0, 0x10, 0x20, 0x20, 0x20, 0x20] {
+        cache.simulate_address('L', address).unwrap();
+    }
+    assert_eq!(cache.hot_blocks(5), Some(vec![
+        BlockStats { address: 0x20, lookups: 4, hits: 3 },
+        BlockStats { address: 0x0, lookups: 3, hits: 2 },
+    ]));
+
+    let mut cache = CacheBuilder::new().set_index_bits(4).associativity(1).block_bits(4).track_hot_blocks(HOT_BLOCK_CAPACITY).build().unwrap();
+    simulate_tracefile(&mut cache, "../traces/trans.trace").unwrap();
+    let blocks = cache.hot_blocks(usize::MAX).unwrap();
+    assert_eq!(blocks.iter().map(|block| block.lookups).sum::<usize>(), cache.hits() + cache.misses());
+    assert_eq!(blocks.iter().map(|block| block.hits).sum::<usize>(), cache.hits());
+}
+
+// Tests for eviction lifetimes
+#[test]
+fn test_lifetime_stats() {
+    let mut cache = CacheBuilder::new().set_index_bits(1).associativity(1).block_bits(4).track_lifetimes(true).build().unwrap();
+    assert_eq!(cache.lifetime_stats(), None);
+    assert_eq!(cache.format_stats(), "hits:0 misses:0 evictions:0\nlifetimes: no evictions");
+    // Block 0 survives 3 lookups before block 2 evicts it, which block 4 evicts at once
+    for address in [0x0, 0x10, 0x0, 0x10, 0x20, 0x40] {
+        cache.simulate_address('L', address).unwrap();
+    }
+    assert_eq!(cache.lifetime_stats(), Some(LifetimeStats { evictions: 2, min: 0, mean: 1.5, p50: 0, p90: 3, p99: 3, max: 3 }));
+    assert_eq!(cache.format_stats(), "hits:2 misses:4 evictions:2\nlifetimes: min:0 mean:1.50 p50:0 p90:3 p99:3 max:3");
+
+    cache.reset_stats();
+    assert_eq!(cache.lifetime_stats(), None);
+    assert_eq!(Cache::new(1, 1, 4).unwrap().lifetime_stats(), None);
+}
+
+#[test]
+fn test_lifetime_stats_modes() {
+    let builders = [
+        CacheBuilder::new().set_index_bits(2).associativity(2).block_bits(3),
+        CacheBuilder::new().set_index_bits(2).associativity(2).block_bits(3).skewed(true),
+        CacheBuilder::new().set_index_bits(2).associativity(2).block_bits(3).compression(0.5),
+    ];
+    for builder in builders {
+        let mut cache = builder.track_lifetimes(true).build().unwrap();
+        simulate_tracefile(&mut cache, "../traces/trans.trace").unwrap();
+        let lifetimes = cache.lifetime_stats().unwrap();
+        assert_eq!(lifetimes.evictions, cache.evictions());
+        assert!(lifetimes.min <= lifetimes.p50 && lifetimes.p50 <= lifetimes.p90 && lifetimes.p90 <= lifetimes.p99 && lifetimes.p99 <= lifetimes.max);
+        assert!(lifetimes.max < (cache.hits() + cache.misses()) as u64);
+    }
+}
+
+#[test]
+fn test_lifetime_stats_step_back() {
+    let mut cache = CacheBuilder::new().set_index_bits(1).associativity(1).block_bits(4).track_lifetimes(true).undo_depth(2).build().unwrap();
+    cache.simulate_address('L', 0x0).unwrap();
+    cache.simulate_address('L', 0x20).unwrap();
+    assert!(cache.step_back());
+    assert_eq!(cache.lifetime_stats(), None);
+    cache.simulate_address('L', 0x0).unwrap();
+    cache.simulate_address('L', 0x20).unwrap();
+    assert_eq!(cache.lifetime_stats().map(|lifetimes| lifetimes.max), Some(1));
+
+    let builder = CacheBuilder::new().set_index_bits(1).associativity(2).block_bits(4).track_lifetimes(true);
+    assert_step_back_replays(builder, &random_accesses(400, 12, 4), 100, |cache| format!("{} {:?}", cache.format_stats(), cache.lifetimes));
+}
+
+// Tests for CSV output
+#[test]
+fn test_csv_row() {
+    let mut cache = Cache::new(4, 2, 4).unwrap();
+    for address in [0x0, 0x8, 0x100] {
+        cache.simulate_address('L', address).unwrap();
+    }
+    let row = cache.csv_row("traces/yi.trace", 0);
+    assert_eq!(row, "traces/yi.trace,0,4,2,4,lru,mru,lowest,false,,64,error,,,,,,,,false,,,,,,1,2,0,0.666667,0,0,0,,,,,,,,,,,,,,,0,0,,,0,0,0,0,0,0,0,,,,,,,,,,,,");
+    assert_eq!(row.split(',').count(), CSV_HEADER.split(',').count());
+    // Configurations differing only in their insertion policy have rows of their own
+    let lip = CacheBuilder::new().set_index_bits(4).associativity(2).block_bits(4).insertion(Insertion::Lip).build().unwrap();
+    assert_eq!(lip.csv_row("traces/yi.trace", 0).split(',').nth(6), Some("lip"));
+
+    let mut partitions = HashMap::new();
+    partitions.insert(1, vec![2, 3]);
+    partitions.insert(0, vec![0, 1]);
+    let cache = CacheBuilder::new().set_index_bits(1).associativity(4).block_bits(2).tie_break(TieBreak::RoundRobin)
+        .way_partitions(partitions).banks(2, 4).build().unwrap();
+    assert_eq!(cache.csv_row("a,b.trace", 10), "\"a,b.trace\",10,1,4,2,lru,mru,round-robin,false,,64,error,\"0:0-1,1:2-3\",2,4,,,,,false,,,,,,0,0,0,0.000000,0,0,0,0,0,,,,,,,,,,,,,0,0,,,0,0,0,0,0,0,0,,,,,,,,,,,,");
+
+    // The write-back buffer fills its option and statistic columns
+    let mut cache = CacheBuilder::new().block_bits(4).write_buffer(2, 100).build().unwrap();
+    for (op, address) in [('S', 0x0), ('L', 0x10), ('L', 0x0)] {
+        cache.simulate_address(op, address).unwrap();
+    }
+    assert_eq!(cache.csv_row("t", 0), "t,0,0,1,4,lru,mru,lowest,false,,64,error,,,,,2,100,,false,,,,,,0,3,2,1.000000,0,0,0,,,,,,,1,1,0,,,,,,0,0,,,0,0,0,0,0,0,0,,,,,,,,,,,,");
+
+    // So does the store buffer, whose stores coalesce and forward loads before reaching the cache
+    let mut cache = CacheBuilder::new().set_index_bits(2).associativity(2).block_bits(4).store_buffer(2).build().unwrap();
+    for (op, address) in [('S', 0x0), ('S', 0x8), ('L', 0x4), ('S', 0x10), ('S', 0x20)] {
+        cache.simulate_address(op, address).unwrap();
+    }
+    assert_eq!(cache.csv_row("t", 0), "t,0,2,2,4,lru,mru,lowest,false,,64,error,,,,,,,2,false,,,,,,0,1,0,1.000000,0,0,0,,,,,,,,,,4,1,1,,,0,0,,,0,0,0,0,0,0,0,,,,,,,,,,,,");
+
+    // And the TinyLFU filter, which rejected the fill that would have evicted the block accessed twice
+    let mut cache = CacheBuilder::new().block_bits(4).tinylfu(true).build().unwrap();
+    for address in [0x0, 0x0, 0x10] {
+        cache.simulate_address('L', address).unwrap();
+    }
+    assert_eq!(cache.csv_row("t", 0), "t,0,0,1,4,lru,mru,lowest,false,,64,error,,,,,,,,true,,,,,,1,2,0,0.666667,0,0,0,,,,,,,,,,,,,0,1,0,0,,,0,0,0,0,0,0,0,,,,,,,,,,,,");
+
+    // The options and counters of the other features, and the lifetimes of the evicted lines
+    let mut cache = CacheBuilder::new().set_index_bits(1).associativity(4).block_bits(4).replacement(Replacement::Slru).protected_ways(2)
+        .uncacheable(vec![0x1000..=0x1fff, 0x3000..=0x3000]).locked_ways(vec![WayLock { ways: 0..=0, sets: Some(1..=1) }])
+        .scratchpad(0x8000..=0x801f).bypass(64).track_lifetimes(true).build().unwrap();
+    for (op, address) in [('L', 0x0), ('P', 0x20), ('L', 0x0), ('L', 0x1000), ('L', 0x8000), ('F', 0x0), ('L', 0x0),
+        ('L', 0x40), ('L', 0x80), ('L', 0xc0), ('K', 0x20), ('L', 0x20)] {
+        cache.simulate_address(op, address).unwrap();
+    }
+    assert_eq!(cache.csv_row("t", 0), "t,0,1,3,4,slru,mru,lowest,false,,64,error,,,,,,,,false,\"0x1000-0x1fff,0x3000\",0@1,0x8000-0x801f,64,2,\
+2,5,3,0.714286,0,0,0,,,,,,,,,,,,,,,0,2,1,1,1,1,0,1,0,0,0,0,0,,,2,,2,2.333333,2,3,3,3");
+}
+
+#[test]
+fn test_format_ways() {
+    assert_eq!(format_ways(&[0, 1, 2]), "0-2");
+    assert_eq!(format_ways(&[3]), "3");
+    assert_eq!(format_ways(&[0, 2]), "0+2");
+}
+
+#[test]
+fn test_policy_display() {
+    for tie_break in [TieBreak::Lowest, TieBreak::Random, TieBreak::RoundRobin] {
+        assert_eq!(tie_break.to_string().parse(), Ok(tie_break));
+    }
+    for oob_policy in [OobPolicy::Mask, OobPolicy::Skip, OobPolicy::Error] {
+        assert_eq!(oob_policy.to_string().parse(), Ok(oob_policy));
+    }
+    for predictor in [WayPredictor::Mru, WayPredictor::PartialTag] {
+        assert_eq!(predictor.to_string().parse(), Ok(predictor));
+    }
+    for replacement in [Replacement::Lru, Replacement::Fifo, Replacement::Random, Replacement::Plru] {
+        assert_eq!(replacement.to_string().parse(), Ok(replacement));
+    }
+    assert_eq!("lfu".parse::<Replacement>(), Err(SimError::Config("unknown replacement policy: lfu".to_string())));
+}
+
+// Tests for CacheBuilder
+#[test]
+fn test_cache_builder_defaults() {
+    let cache = CacheBuilder::new().set_index_bits(4).associativity(2).block_bits(4).build().unwrap();
+    assert_eq!(cache.sets.len(), 16);
+    assert_eq!(cache.sets[0].lines.len(), 2);
+    assert_eq!(cache.block_bits, 4);
+    assert_eq!(cache.replacement, Replacement::Lru);
+    assert!(cache.sets.iter().all(|set| set.plru.is_empty()));
+    assert_eq!(cache.tie_break, TieBreak::Lowest);
+    assert!(!cache.skewed);
+    assert_eq!(cache.address_bits, 64);
+    assert!(cache.banks.is_none());
+    assert!(cache.compression.is_none());
+    assert_eq!(cache.undo_depth, 0);
+}
+
+#[test]
+fn test_cache_builder_options() {
+    let cache = CacheBuilder::new()
+        .set_index_bits(2)
+        .associativity(4)
+        .block_bits(3)
+        .tie_break(TieBreak::Random)
+        .seed(9)
+        .way_predictor(Some(WayPredictor::Mru))
+        .address_bits(32)
+        .oob_policy(OobPolicy::Mask)
+        .banks(4, 2)
+        .compression(0.5)
+        .undo_depth(8)
+        .build()
+        .unwrap();
+    assert_eq!(cache.tie_break, TieBreak::Random);
+    assert_eq!(cache.rng.state, 9);
+    assert_eq!(cache.way_predictor, Some(WayPredictor::Mru));
+    assert_eq!((cache.address_bits, cache.oob_policy), (32, OobPolicy::Mask));
+    assert_eq!(cache.banks.as_ref().map(|banks| (banks.count, banks.issue_width)), Some((4, 2)));
+    assert_eq!(cache.sets[0].lines.len(), 8);
+    assert_eq!(cache.undo_depth, 8);
+    assert_eq!(cache.compression.as_ref().unwrap().baseline.undo_depth, 8);
+
+    let cache = CacheBuilder::new().associativity(4).way_partitions(HashMap::from([(0, vec![0, 1])])).build().unwrap();
+    assert_eq!(cache.way_partitions[&0], vec![0, 1]);
+}
+
+#[test]
+fn test_cache_builder_invalid() {
+    let base = CacheBuilder::new().set_index_bits(2).associativity(2).block_bits(2);
+    let invalid_builders = vec![
+        base.clone().associativity(0),
+        base.clone().address_bits(0),
+        base.clone().address_bits(65),
+        base.clone().way_partitions(HashMap::from([(0, vec![2])])),
+        base.clone().banks(3, 1),
+        base.clone().banks(4, 0),
+        base.clone().write_buffer(0, 1),
+        base.clone().write_buffer(4, 0),
+        base.clone().store_buffer(0),
+        base.clone().compression(1.5),
+        base.clone().compression(0.5).skewed(true),
+        base.clone().compression(0.5).way_partitions(HashMap::from([(0, vec![1])])),
+        base.clone().skewed(true).way_predictor(Some(WayPredictor::PartialTag)),
+        base.clone().set_index_bits(1000),
+        base.clone().set_index_bits(60).block_bits(5),
+        base.clone().set_index_bits(usize::MAX),
+        base.clone().address_bits(3),
+        base.clone().replacement(Replacement::Fifo).skewed(true),
+        base.clone().replacement(Replacement::Random).compression(0.5),
+        base.clone().replacement(Replacement::Plru).associativity(3),
+        base.clone().uncacheable(vec![0x10..=0x1f, RangeInclusive::new(0x20, 0x10)]),
+        base.clone().locked_ways(vec![WayLock { ways: 1..=2, sets: None }]),
+        base.clone().locked_ways(vec![WayLock { ways: 0..=0, sets: Some(2..=4) }]),
+        base.clone().locked_ways(vec![WayLock { ways: 0..=0, sets: None }]).compression(0.5),
+        base.clone().scratchpad(0x0..=0x1f),
+        base.clone().scratchpad(0x0..=0x7),
+        base.clone().scratchpad(RangeInclusive::new(0x10, 0x0)),
+        base.clone().scratchpad(0x0..=u64::MAX),
+        base.clone().associativity(4).scratchpad(0x0..=0x1f).locked_ways(vec![WayLock { ways: 2..=2, sets: None }]),
+        base.clone().associativity(4).scratchpad(0x0..=0xf).replacement(Replacement::Plru),
+        base.clone().bypass(48),
+        base.clone().bypass(2),
+        base.clone().bypass(64).skewed(true),
+    ];
+    for builder in invalid_builders {
+        assert!(matches!(builder.build(), Err(SimError::Config(_))));
+    }
+    assert!(base.validate().is_ok());
+    // Set index and block offset may take the whole address width
+    assert!(base.clone().address_bits(4).validate().is_ok());
+    assert_eq!(
+        base.clone().set_index_bits(20).block_bits(16).address_bits(32).validate(),
+        Err(SimError::Config("20 set index bits and 16 block bits exceed the 32-bit address width".to_string()))
+    );
+}
+
+// Tests for replacement policies
+#[cfg(test)]
+fn simulate_tags(cache: &mut Cache, tags: &[usize]) -> Vec<(usize, usize)> {
+    tags.iter().map(|&tag| {
+        let (hits, evictions) = (cache.hits, cache.evictions);
+        cache.simulate_memory_access('L', 0, tag).unwrap();
+        (cache.hits - hits, cache.evictions - evictions)
+    }).collect()
+}
+
+#[test]
+fn test_replacement_lru_and_fifo() {
+    // A is used again before C misses: LRU evicts B, FIFO evicts A, which was filled first
+    let mut lru = CacheBuilder::new().associativity(2).build().unwrap();
+    assert_eq!(simulate_tags(&mut lru, &[0xa, 0xb, 0xa, 0xc, 0xa]), [(0, 0), (0, 0), (1, 0), (0, 1), (1, 0)]);
+    let mut fifo = CacheBuilder::new().associativity(2).replacement(Replacement::Fifo).build().unwrap();
+    assert_eq!(simulate_tags(&mut fifo, &[0xa, 0xb, 0xa, 0xc, 0xa]), [(0, 0), (0, 0), (1, 0), (0, 1), (0, 1)]);
+    assert_eq!(fifo.sets[0].lines.iter().map(|line| line.tag).collect::<Vec<_>>(), [Some(0xc), Some(0xa)]);
+    assert_eq!(fifo.check_invariants(), Ok(()));
+}
+
+#[test]
+fn test_replacement_mru() {
+    // A is used again before C misses, so C evicts it, and A then evicts C
+    let mut mru = CacheBuilder::new().associativity(2).replacement(Replacement::Mru).build().unwrap();
+    assert_eq!(simulate_tags(&mut mru, &[0xa, 0xb, 0xa, 0xc, 0xa]), [(0, 0), (0, 0), (1, 0), (0, 1), (0, 1)]);
+    assert_eq!(mru.sets[0].lines.iter().map(|line| line.tag).collect::<Vec<_>>(), [Some(0xa), Some(0xb)]);
+
+    // A loop over one block more than the set holds never hits under LRU, while MRU keeps all but one of its blocks
+    let tags: Vec<usize> = (0..100).map(|tag| tag % 5).collect();
+    let mut lru = CacheBuilder::new().associativity(4).build().unwrap();
+    let mut mru = CacheBuilder::new().associativity(4).replacement(Replacement::Mru).build().unwrap();
+    simulate_tags(&mut lru, &tags);
+    simulate_tags(&mut mru, &tags);
+    assert_eq!((lru.hits, mru.hits), (0, 72));
+    assert_eq!(mru.check_invariants(), Ok(()));
+    assert_eq!(("mru".parse::<Replacement>().unwrap(), Replacement::Mru.to_string()), (Replacement::Mru, "mru".to_string()));
+}
+
+#[test]
+fn test_replacement_slru() {
+    // A and B are hit, so the scan of C to G only replaces probationary lines, while LRU lets it evict them
+    let tags = [0xa, 0xb, 0xa, 0xb, 0xc, 0xd, 0xe, 0xf, 0x10, 0xa, 0xb];
+    let mut lru = CacheBuilder::new().associativity(4).build().unwrap();
+    let mut slru = CacheBuilder::new().associativity(4).replacement(Replacement::Slru).build().unwrap();
+    simulate_tags(&mut lru, &tags);
+    simulate_tags(&mut slru, &tags);
+    assert_eq!((lru.hits, slru.hits, slru.protected_ways()), (2, 4, Some(2)));
+    assert_eq!(slru.snapshot_stats().promotions, 2);
+    assert_eq!(slru.check_invariants(), Ok(()));
+
+    // With a single protected line, promoting B demotes A to the most recently used probationary line, which is
+    // still the first probationary line to be evicted once C and D are filled
+    let mut slru = CacheBuilder::new().associativity(4).replacement(Replacement::Slru).protected_ways(1).build().unwrap();
+    assert_eq!(simulate_tags(&mut slru, &[0xa, 0xb, 0xa, 0xb, 0xc, 0xd, 0xe]).last(), Some(&(0, 1)));
+    assert_eq!(slru.sets[0].lines[0].tag, Some(0xe));
+    let segment = slru.slru.as_ref().unwrap();
+    assert_eq!((segment.promotions, segment.demotions), (2, 1));
+    assert_eq!(slru.check_invariants(), Ok(()));
+
+    assert_eq!(
+        CacheBuilder::new().associativity(4).protected_ways(2).build().err(),
+        Some(SimError::Config("a protected segment size needs the slru policy".to_string()))
+    );
+    for ways in [0, 4] {
+        assert!(CacheBuilder::new().associativity(4).replacement(Replacement::Slru).protected_ways(ways).build().is_err());
+    }
+    assert_eq!(("slru".parse::<Replacement>().unwrap(), Replacement::Slru.to_string()), (Replacement::Slru, "slru".to_string()));
+}
+
+#[test]
+fn test_replacement_two_queue() {
+    // A and B miss again while A1out remembers them, so they go to Am and the scan after them only cycles through
+    // A1in, while LRU lets the scan evict them
+    let tags = [0xa, 0xb, 0xc, 0xd, 0xe, 0xa, 0xb, 0xf, 0x10, 0x11, 0x12, 0xa, 0xb];
+    let mut lru = CacheBuilder::new().associativity(4).build().unwrap();
+    let mut two_q = CacheBuilder::new().associativity(4).replacement(Replacement::TwoQ).build().unwrap();
+    simulate_tags(&mut lru, &tags);
+    simulate_tags(&mut two_q, &tags);
+    assert_eq!((lru.hits, two_q.hits, two_q.snapshot_stats().ghost_hits), (0, 2, 2));
+    assert_eq!(two_q.two_queue().unwrap().ghosts(0), &[0xf, 0x10]);
+    assert_eq!(two_q.check_invariants(), Ok(()));
+
+    // Once A1in is down to its share of one line, the least recently used line of Am, A, is evicted instead and
+    // isn't remembered
+    assert_eq!(simulate_tags(&mut two_q, &[0x10, 0x11]), [(0, 1), (0, 1)]);
+    assert_eq!(two_q.sets[0].lines.iter().map(|line| (line.tag, line.protected)).collect::<Vec<_>>(), [
+        (Some(0x12), false), (Some(0x11), true), (Some(0xb), true), (Some(0x10), true)
+    ]);
+    assert!(two_q.two_queue().unwrap().ghosts(0).is_empty());
+    assert_eq!(two_q.check_invariants(), Ok(()));
+    assert_eq!(("2q".parse::<Replacement>().unwrap(), Replacement::TwoQ.to_string()), (Replacement::TwoQ, "2q".to_string()));
+}
+
+#[test]
+fn test_replacement_lirs() {
+    // A loop over one block more than the set holds: LRU never hits, while the three LIR blocks stay resident and
+    // the other two take turns in the HIR line
+    let tags: Vec<usize> = (0..100).map(|tag| tag % 5).collect();
+    let mut lru = CacheBuilder::new().associativity(4).build().unwrap();
+    let mut lirs = CacheBuilder::new().associativity(4).replacement(Replacement::Lirs).build().unwrap();
+    simulate_tags(&mut lru, &tags);
+    simulate_tags(&mut lirs, &tags);
+    assert_eq!((lru.hits, lirs.hits), (0, 57));
+    let state = lirs.lirs().unwrap();
+    assert_eq!((0..3).map(|tag| state.status(0, tag)).collect::<Vec<_>>(), [Some(LirsStatus::Lir); 3]);
+    assert_eq!(lirs.check_invariants(), Ok(()));
+
+    // D and E, evicted as HIR blocks, miss again while still in the stack and become LIR, each demoting the bottom
+    // LIR block, A and then B, which the next miss evicts
+    let mut lirs = CacheBuilder::new().associativity(4).replacement(Replacement::Lirs).build().unwrap();
+    assert_eq!(simulate_tags(&mut lirs, &[0xa, 0xb, 0xc, 0xd, 0xe, 0xd, 0xf, 0xe, 0x10]), [
+        (0, 0), (0, 0), (0, 0), (0, 0), (0, 1), (0, 1), (0, 1), (0, 1), (0, 1)
+    ]);
+    assert_eq!(lirs.snapshot_stats().ghost_hits, 2);
+    assert_eq!(lirs.sets[0].lines.iter().map(|line| line.tag).collect::<Vec<_>>(), [Some(0xe), Some(0x10), Some(0xc), Some(0xd)]);
+    assert_eq!(lirs.check_invariants(), Ok(()));
+    assert_eq!(("lirs".parse::<Replacement>().unwrap(), Replacement::Lirs.to_string()), (Replacement::Lirs, "lirs".to_string()));
+}
+
+#[test]
+fn test_replacement_plru() {
+    let mut plru = CacheBuilder::new().associativity(4).replacement(Replacement::Plru).build().unwrap();
+    simulate_tags(&mut plru, &[0xa, 0xb, 0xc, 0xd]);
+    // The last access, to way 3, points the root to the lower half and its node to way 2
+    assert_eq!(plru.sets[0].plru, [false, false, false]);
+    // After touching B, the tree points to C although A is the least recently used line, which LRU evicts
+    assert_eq!(simulate_tags(&mut plru, &[0xb, 0xe, 0xa]), [(1, 0), (0, 1), (1, 0)]);
+    assert_eq!(plru.sets[0].lines[2].tag, Some(0xe));
+    let mut lru = CacheBuilder::new().associativity(4).build().unwrap();
+    assert_eq!(simulate_tags(&mut lru, &[0xa, 0xb, 0xc, 0xd, 0xb, 0xe, 0xa]).last(), Some(&(0, 1)));
+
+    // A thread limited to the lower half evicts from it whatever the tree points to
+    let mut plru = CacheBuilder::new().associativity(4).replacement(Replacement::Plru)
+        .way_partitions(HashMap::from([(1, vec![0, 1])])).build().unwrap();
+    simulate_tags(&mut plru, &[0xa, 0xb, 0xc, 0xd]);
+    plru.thread = 1;
+    simulate_tags(&mut plru, &[0xb, 0xe]);
+    assert_eq!(plru.sets[0].lines[0].tag, Some(0xe));
+    assert_eq!(plru.check_invariants(), Ok(()));
+}
+
+#[test]
+fn test_replacement_random() {
+    let random = || CacheBuilder::new().associativity(4).replacement(Replacement::Random).seed(5).build().unwrap();
+    let tags: Vec<usize> = (0..64).map(|tag| tag % 9).collect();
+    let (mut first, mut second) = (random(), random());
+    assert_eq!(simulate_tags(&mut first, &tags), simulate_tags(&mut second, &tags));
+    assert_eq!(first.evictions, first.misses - 4);
+    // The victim isn't always the LRU line
+    let mut lru = CacheBuilder::new().associativity(4).build().unwrap();
+    simulate_tags(&mut lru, &tags);
+    assert_ne!(first.hits, lru.hits);
+}
+
+#[test]
+fn test_insertion_lip_and_bip() {
+    // Three blocks cycling through two lines: LRU insertion keeps the first one, which each hit makes MRU again
+    let tags: Vec<usize> = (0..30).map(|tag| 0xa + tag % 3).collect();
+    let mut mru = CacheBuilder::new().associativity(2).build().unwrap();
+    let mut lip = CacheBuilder::new().associativity(2).insertion(Insertion::Lip).build().unwrap();
+    simulate_tags(&mut mru, &tags);
+    simulate_tags(&mut lip, &tags);
+    assert_eq!((mru.hits, lip.hits, lip.insertion()), (0, 9, Insertion::Lip));
+    assert_eq!(lip.sets[0].access_order, [0, 1]);
+    assert_eq!(lip.check_invariants(), Ok(()));
+
+    // BIP now and then inserts at MRU, so it adapts when the working set changes, which LIP never does: LIP keeps
+    // the first block of the first working set, leaving one line for the two of the second
+    let first: Vec<usize> = (0..30).map(|tag| 0xa + tag % 3).collect();
+    let second: Vec<usize> = (0..200).map(|tag| 0x10 + tag % 2).collect();
+    let mut lip = CacheBuilder::new().associativity(2).insertion(Insertion::Lip).build().unwrap();
+    let mut bip = CacheBuilder::new().associativity(2).insertion(Insertion::Bip).build().unwrap();
+    simulate_tags(&mut lip, &first);
+    simulate_tags(&mut bip, &first);
+    let (lip_hits, bip_hits) = (lip.hits, bip.hits);
+    simulate_tags(&mut lip, &second);
+    simulate_tags(&mut bip, &second);
+    assert_eq!((lip_hits, bip_hits, lip.hits - lip_hits, bip.hits - bip_hits), (9, 9, 0, 152));
+
+    // Under tree-PLRU, the bits are left pointing at the filled line, so F replaces E, which was never hit
+    let mut plru = CacheBuilder::new().associativity(4).replacement(Replacement::Plru).insertion(Insertion::Lip).build().unwrap();
+    assert_eq!(
+        simulate_tags(&mut plru, &[0xa, 0xb, 0xc, 0xd, 0xe, 0xa, 0xf, 0xa, 0xb]),
+        [(0, 0), (0, 0), (0, 0), (0, 0), (0, 1), (1, 0), (0, 1), (1, 0), (1, 0)]
+    );
+    assert_eq!(plru.check_invariants(), Ok(()));
+
+    for replacement in [Replacement::Fifo, Replacement::Random, Replacement::Srrip] {
+        assert_eq!(
+            CacheBuilder::new().replacement(replacement).insertion(Insertion::Bip).build().err(),
+            Some(SimError::Config("the bip insertion policy needs the lru or plru replacement policy".to_string()))
+        );
+    }
+    assert!(CacheBuilder::new().associativity(2).skewed(true).insertion(Insertion::Lip).build().is_err());
+    assert_eq!(("bip".parse::<Insertion>().unwrap(), Insertion::Lip.to_string()), (Insertion::Bip, "lip".to_string()));
+    assert!("lru".parse::<Insertion>().is_err());
+}
+
+#[test]
+fn test_replacement_srrip() {
+    // Filled lines are predicted far, so the hit on A protects it from the two misses that follow
+    let mut srrip = CacheBuilder::new().associativity(4).replacement(Replacement::Srrip).build().unwrap();
+    assert_eq!(simulate_tags(&mut srrip, &[0xa, 0xb, 0xc, 0xd, 0xa, 0xe, 0xf]), [(0, 0), (0, 0), (0, 0), (0, 0), (1, 0), (0, 1), (0, 1)]);
+    assert_eq!(srrip.sets[0].lines.iter().map(|line| (line.tag, line.rrpv)).collect::<Vec<_>>(), [(Some(0xa), 1), (Some(0xe), 2), (Some(0xf), 2), (Some(0xd), 3)]);
+    assert_eq!(srrip.check_invariants(), Ok(()));
+
+    // A cyclic working set one line too large thrashes SRRIP, while BRRIP keeps most of it
+    let tags: Vec<usize> = (0..200).map(|tag| tag % 5).collect();
+    let mut brrip = CacheBuilder::new().associativity(4).replacement(Replacement::Brrip).build().unwrap();
+    let mut srrip = CacheBuilder::new().associativity(4).replacement(Replacement::Srrip).build().unwrap();
+    simulate_tags(&mut brrip, &tags);
+    simulate_tags(&mut srrip, &tags);
+    assert_eq!(srrip.hits, 0);
+    assert!(brrip.hits > 100);
+    assert_eq!(brrip.check_invariants(), Ok(()));
+}
+
+#[test]
+fn test_replacement_drrip() {
+    // Each of the 64 sets cycles through five blocks, which the BRRIP leaders handle better
+    let mut drrip = CacheBuilder::new().set_index_bits(6).associativity(4).block_bits(4).replacement(Replacement::Drrip).undo_depth(1).build().unwrap();
+    for address in (0..50 * 5 * 64).map(|block| (block % (5 * 64)) << 4) {
+        drrip.simulate_address('L', address).unwrap();
+    }
+    let dueling = drrip.dueling().unwrap();
+    let [srrip_misses, brrip_misses] = dueling.leader_misses();
+    assert!(srrip_misses > brrip_misses);
+    // The counter wavers while cold misses hit both kinds of leaders alike
+    assert_eq!((dueling.winner(), drrip.snapshot_stats().policy_switches), (1, dueling.switches()));
+    assert!(dueling.switches() > 0);
+    assert!(drrip.format_stats().ends_with(&format!(
+        "dueling: srrip-leader-misses:{} brrip-leader-misses:{} psel:{} winner:brrip switches:{}",
+        srrip_misses, brrip_misses, dueling.psel(), dueling.switches()
+    )));
+    assert_eq!(drrip.check_invariants(), Ok(()));
+
+    // Followers do as well as the BRRIP leaders
+    let mut brrip = CacheBuilder::new().set_index_bits(6).associativity(4).block_bits(4).replacement(Replacement::Brrip).build().unwrap();
+    let mut srrip = CacheBuilder::new().set_index_bits(6).associativity(4).block_bits(4).replacement(Replacement::Srrip).build().unwrap();
+    for address in (0..50 * 5 * 64).map(|block| (block % (5 * 64)) << 4) {
+        brrip.simulate_address('L', address).unwrap();
+        srrip.simulate_address('L', address).unwrap();
+    }
+    assert!(drrip.hits() > srrip.hits() && drrip.hits() <= brrip.hits() + 100);
+
+    let psel = drrip.dueling().unwrap().psel();
+    drrip.reset_stats();
+    assert_eq!((drrip.dueling().unwrap().psel(), drrip.dueling().unwrap().switches()), (psel, 0));
+    assert!(CacheBuilder::new().associativity(4).replacement(Replacement::Lru).build().unwrap().dueling().is_none());
+}
+
+#[test]
+fn test_adaptive_replacement() {
+    // Each of the 64 sets first cycles through five blocks, which the MRU leaders handle better, then slides over
+    // blocks four at a time, which only the LRU leaders keep
+    let cycle = (0..40 * 5 * 64).map(|block| (block % (5 * 64)) << 4);
+    let slide = (0..200u64).flat_map(|step| (0..64).flat_map(move |set| (0..4).rev().map(move |back| (0x10_0000 + (step.saturating_sub(back) * 64 + set)) << 4)));
+    let builder = CacheBuilder::new().set_index_bits(6).associativity(4).block_bits(4);
+    let mut adaptive = builder.clone().adaptive(Replacement::Lru, Replacement::Mru).undo_depth(1).build().unwrap();
+    assert_eq!((adaptive.adaptive_policies(), adaptive.replacement()), (Some([Replacement::Lru, Replacement::Mru]), Replacement::Lru));
+    for address in cycle.clone() {
+        adaptive.simulate_address('L', address).unwrap();
+    }
+    // The counter wavers while cold misses hit both kinds of leaders alike, then settles on MRU
+    let cycle_hits = adaptive.hits();
+    assert_eq!((adaptive.dueling().unwrap().winner(), adaptive.switch_points().last().map(|point| point.1)), (1, Some(Replacement::Mru)));
+    assert!(adaptive.switch_points().iter().all(|&(lookup, _)| lookup < 400));
+    for address in slide.clone() {
+        adaptive.simulate_address('L', address).unwrap();
+    }
+    // Only the latest switches are kept, the last one back to LRU once the slide began
+    let points = adaptive.switch_points();
+    let dueling = adaptive.dueling().unwrap();
+    assert_eq!((points.len(), points.last().map(|point| point.1), dueling.winner()), (ADAPTIVE_SWITCH_POINTS, Some(Replacement::Lru), 0));
+    assert!(points.last().unwrap().0 > 40 * 5 * 64);
+    assert_eq!(adaptive.snapshot_stats().policy_switches, dueling.switches());
+    let [lru_misses, mru_misses] = dueling.leader_misses();
+    let points = points.iter().map(|(lookup, policy)| format!("{}:{}", lookup, policy)).collect::<Vec<_>>().join(",");
+    assert!(adaptive.format_stats().ends_with(&format!(
+        "adaptive: lru-leader-misses:{} mru-leader-misses:{} psel:{} winner:lru switches:{} switch-points:{}",
+        lru_misses, mru_misses, dueling.psel(), dueling.switches(), points
+    )));
+    assert_eq!(adaptive.csv_row("t", 0).split(',').nth(5), Some("lru/mru"));
+    assert_eq!(adaptive.check_invariants(), Ok(()));
+
+    // LRU hits nothing in the cycle and the three reused blocks of each step of the slide, which MRU mostly misses
+    let mut lru = builder.clone().build().unwrap();
+    let mut mru = builder.clone().replacement(Replacement::Mru).build().unwrap();
+    for address in cycle.chain(slide) {
+        lru.simulate_address('L', address).unwrap();
+        mru.simulate_address('L', address).unwrap();
+    }
+    assert!(cycle_hits > 6000 && adaptive.hits() > 3 * mru.hits() && lru.hits() == 3 * 64 * 200);
+
+    // A miss in a leader set that switches the followers is undone with its switch point
+    let mut adaptive = builder.clone().adaptive(Replacement::Srrip, Replacement::Brrip).undo_depth(1).build().unwrap();
+    adaptive.simulate_address('L', 63 << 4).unwrap();
+    assert_eq!((adaptive.dueling().unwrap().winner(), adaptive.switch_points()), (1, vec![(1, Replacement::Brrip)]));
+    assert!(adaptive.step_back());
+    assert_eq!((adaptive.dueling().unwrap().winner(), adaptive.switch_points()), (0, vec![]));
+    assert_eq!(adaptive.check_invariants(), Ok(()));
+    adaptive.simulate_address('L', 63 << 4).unwrap();
+    adaptive.reset_stats();
+    assert!(adaptive.switch_points().is_empty());
+    assert_eq!(adaptive.snapshot_stats().policy_switches, 0);
+
+    assert!(builder.clone().adaptive(Replacement::Lru, Replacement::Lru).build().is_err());
+    assert!(builder.clone().adaptive(Replacement::Lru, Replacement::Plru).build().is_err());
+    assert!(builder.clone().adaptive(Replacement::Drrip, Replacement::Lru).build().is_err());
+    assert!(builder.clone().adaptive(Replacement::Lru, Replacement::Fifo).replacement(Replacement::Random).build().is_err());
+    assert!(builder.clone().adaptive(Replacement::Lru, Replacement::Fifo).insertion(Insertion::Lip).build().is_err());
+    assert!(builder.clone().adaptive(Replacement::Lru, Replacement::Fifo).skewed(true).build().is_err());
+    assert!(CacheBuilder::new().associativity(4).build().unwrap().adaptive_policies().is_none());
+}
+
+#[test]
+fn test_replacement_ship() {
+    // Two blocks loaded by one instruction are reused across a stream of six blocks loaded by another, which ages
+    // them out of SRRIP. SHiP learns that the stream's lines are never hit and fills them to be evicted first
+    let accesses: Vec<MemoryAccess> = (0..50).flat_map(|round| {
+        let hot = (0..2).map(|block| MemoryAccess { operation: 'L', address: block << 4, thread: 0, size: 1, pc: 0x400100 });
+        let stream = (0..6).map(move |block| MemoryAccess { operation: 'L', address: 0x10_0000 + ((round * 6 + block) << 4), thread: 0, size: 1, pc: 0x400200 });
+        hot.chain(stream)
+    }).collect();
+    let simulate = |replacement| {
+        let mut cache = CacheBuilder::new().associativity(4).block_bits(4).replacement(replacement).undo_depth(1).build().unwrap();
+        for &access in &accesses {
+            cache.simulate_access(access).unwrap();
+        }
+        cache
+    };
+    let srrip = simulate(Replacement::Srrip);
+    let mut ship_pc = simulate(Replacement::ShipPc);
+    assert_eq!((srrip.hits(), ship_pc.hits()), (0, 96));
+    let ship = ship_pc.ship().unwrap();
+    assert_eq!((ship.fills(), ship.distant_fills(), ship.dead_signatures()), (304, 294, 1));
+    assert!(!ship.predicts_reuse(ship.signature(0x400200)) && ship.predicts_reuse(ship.signature(0x400100)));
+    assert_eq!(ship_pc.snapshot_stats().distant_fills, 294);
+    assert!(ship_pc.format_stats().ends_with("\nship: fills:304 distant-fills:294 dead-signatures:1"));
+    assert_eq!(ship_pc.check_invariants(), Ok(()));
+
+    // Stepping back restores the table, and resetting keeps what it learnt
+    ship_pc.simulate_access(MemoryAccess { operation: 'L', address: 0x20_0000, thread: 0, size: 1, pc: 0x400200 }).unwrap();
+    assert_eq!(ship_pc.ship().unwrap().distant_fills(), 295);
+    assert!(ship_pc.step_back());
+    assert_eq!(ship_pc.ship().unwrap().distant_fills(), 294);
+    ship_pc.reset_stats();
+    assert_eq!((ship_pc.ship().unwrap().fills(), ship_pc.ship().unwrap().dead_signatures()), (0, 1));
+
+    // By region, the hot blocks and the stream fall in different 16 KiB regions, whatever instruction loads them
+    let ship_mem = simulate(Replacement::ShipMem);
+    assert_eq!(ship_mem.hits(), 96);
+    let ship = ship_mem.ship().unwrap();
+    assert!(!ship.predicts_reuse(ship.signature(0x10_0000 >> SHIP_REGION_BITS)) && ship.predicts_reuse(ship.signature(0)));
+    assert!(CacheBuilder::new().replacement(Replacement::Drrip).build().unwrap().ship().is_none());
+    assert_eq!(("ship-pc".parse::<Replacement>().unwrap(), Replacement::ShipMem.to_string()), (Replacement::ShipPc, "ship-mem".to_string()));
+}
+
+#[test]
+fn test_replacement_hawkeye() {
+    // Two blocks loaded by one instruction are reused across a stream of six loaded by another, and OPT would keep
+    // the two: Hawkeye learns to fill the stream's lines as cache-averse, getting close to OPT's hits
+    let accesses: Vec<MemoryAccess> = (0..50).flat_map(|round| {
+        let hot = (0..2).map(|block| MemoryAccess { operation: 'L', address: block << 4, thread: 0, size: 1, pc: 0x400100 });
+        let stream = (0..6).map(move |block| MemoryAccess { operation: 'L', address: 0x10_0000 + ((round * 6 + block) << 4), thread: 0, size: 1, pc: 0x400200 });
+        hot.chain(stream)
+    }).collect();
+    let mut hawkeye = CacheBuilder::new().associativity(4).block_bits(4).replacement(Replacement::Hawkeye).undo_depth(1).build().unwrap();
+    for &access in &accesses {
+        hawkeye.simulate_access(access).unwrap();
+    }
+    let state = hawkeye.hawkeye().unwrap();
+    assert_eq!((hawkeye.hits(), state.opt_hits(), state.sampled_accesses()), (95, 98, 400));
+    assert!(state.is_friendly(state.signature(0x400100)) && !state.is_friendly(state.signature(0x400200)));
+    assert_eq!((state.fills(), state.averse_fills(), hawkeye.snapshot_stats().distant_fills), (305, 297, 297));
+    assert!(hawkeye.format_stats().ends_with("\nhawkeye: sampled-sets:1 sampled-accesses:400 opt-hits:98 fills:305 averse-fills:297"));
+    assert_eq!(hawkeye.check_invariants(), Ok(()));
+
+    // Stepping back restores the occupancy vectors and the predictor, and resetting keeps them
+    hawkeye.simulate_access(MemoryAccess { operation: 'L', address: 0x0, thread: 0, size: 1, pc: 0x400100 }).unwrap();
+    assert_eq!(hawkeye.hawkeye().unwrap().sampled_accesses(), 401);
+    assert!(hawkeye.step_back());
+    assert_eq!(hawkeye.hawkeye().unwrap().sampled_accesses(), 400);
+    hawkeye.reset_stats();
+    assert_eq!((hawkeye.hawkeye().unwrap().opt_hits(), hawkeye.hawkeye().unwrap().fills()), (0, 0));
+    assert!(hawkeye.hawkeye().unwrap().is_friendly(hawkeye.hawkeye().unwrap().signature(0x400100)));
+
+    // A cyclic working set one and a half times the cache thrashes LRU, while OPT keeps part of it
+    let mut lru = CacheBuilder::new().associativity(4).block_bits(4).build().unwrap();
+    let mut hawkeye = CacheBuilder::new().associativity(4).block_bits(4).replacement(Replacement::Hawkeye).build().unwrap();
+    for block in 0..50 * 6 {
+        let access = MemoryAccess { operation: 'L', address: (block % 6) << 4, thread: 0, size: 1, pc: 0x400300 };
+        lru.simulate_access(access).unwrap();
+        hawkeye.simulate_access(access).unwrap();
+    }
+    assert_eq!((lru.hits(), hawkeye.hits(), hawkeye.hawkeye().unwrap().opt_hits()), (0, 125, 196));
+
+    // Larger caches only sample some of their sets
+    let cache = CacheBuilder::new().set_index_bits(8).associativity(4).replacement(Replacement::Hawkeye).build().unwrap();
+    assert_eq!((cache.hawkeye().unwrap().sampled_sets(), cache.hawkeye().unwrap().sets()), (64, 256));
+    assert!(CacheBuilder::new().replacement(Replacement::ShipPc).build().unwrap().hawkeye().is_none());
+    assert_eq!("hawkeye".parse::<Replacement>().unwrap(), Replacement::Hawkeye);
+}
+
+// Tests for break_tie function
+#[test]
+fn test_break_tie_lowest() {
+    let mut cache = Cache::new(1, 4, 4).unwrap();
+
+    for tag in 0..4 {
+        assert_eq!(cache.simulate_memory_access('L', 0, tag), Ok(()));
+        assert_eq!(cache.sets[0].lines[tag].tag, Some(tag));
+    }
+}
+
+#[test]
+fn test_break_tie_round_robin() {
+    let mut cache = Cache::new(1, 4, 4).unwrap();
+    cache.tie_break = TieBreak::RoundRobin;
+
+    assert_eq!(cache.break_tie(0, &[1, 3]), 1);
+    assert_eq!(cache.break_tie(0, &[0, 1, 3]), 3);
+    assert_eq!(cache.break_tie(0, &[0, 1, 2]), 0);
+    assert_eq!(cache.break_tie(0, &[0, 1, 2]), 1);
+    assert_eq!(cache.sets[0].next_way, 2);
+    assert_eq!(cache.sets[1].next_way, 0);
+}
+
+#[test]
+fn test_break_tie_random() {
+    let mut cache = Cache::new(1, 8, 4).unwrap();
+    cache.tie_break = TieBreak::Random;
+    cache.rng = Rng::new(42);
+
+    let mut chosen = Vec::new();
+    for tag in 0..8 {
+        assert_eq!(cache.simulate_memory_access('L', 0, tag), Ok(()));
+        chosen.push(cache.sets[0].access_order[0]);
+    }
+    assert_eq!(cache.misses, 8);
+    assert_eq!(cache.evictions, 0);
+    chosen.sort();
+    assert_eq!(chosen, (0..8).collect::<Vec<usize>>());
+
+    // The same seed makes the same choices
+    let mut first = Cache::new(1, 8, 4).unwrap();
+    let mut second = Cache::new(1, 8, 4).unwrap();
+    first.tie_break = TieBreak::Random;
+    second.tie_break = TieBreak::Random;
+    for tag in 0..8 {
+        first.simulate_memory_access('L', 0, tag).unwrap();
+        second.simulate_memory_access('L', 0, tag).unwrap();
+        assert_eq!(first.sets[0].access_order, second.sets[0].access_order);
+    }
+}
+
+// Tests for skewed-associative mode
+#[test]
+fn test_skew_index() {
+    let cache = Cache::new(4, 8, 4).unwrap();
+    assert_eq!((0..4).map(|way| cache.skew_index(0x12, way)).collect::<Vec<_>>(), [0xe, 0xc, 0x7, 0x7]);
+    // Any two ways put about one block in 16 in the same set, as independent hashes would
+    for way in 0..8 {
+        assert!((0..1024).all(|block| cache.skew_index(block, way) < 16));
+        for other in 0..way {
+            let shared = (0..1024).filter(|&block| cache.skew_index(block, way) == cache.skew_index(block, other)).count();
+            assert!((32..=96).contains(&shared), "ways {} and {} share {} sets", other, way, shared);
+        }
+    }
+
+    // Every way has its own hash, even with two sets
+    let cache = Cache::new(1, 4, 4).unwrap();
+    let hashes: Vec<Vec<usize>> = (0..4).map(|way| (0..16).map(|block| cache.skew_index(block, way)).collect()).collect();
+    assert!((0..4).all(|way| (0..way).all(|other| hashes[way] != hashes[other])));
+
+    let cache = Cache::new(0, 4, 4).unwrap();
+    assert_eq!(cache.skew_index(0x12, 1), 0);
+}
+
+#[test]
+fn test_simulate_address_skewed_spreads_conflicts() {
+    // Blocks 0, 4 and 8 share set 0 in a conventional 2-way cache and thrash it
+    let mut cache = Cache::new(2, 2, 4).unwrap();
+    for _ in 0..2 {
+        for address in [0x0, 0x40, 0x80] {
+            assert_eq!(cache.simulate_address('L', address), Ok(()));
+        }
+    }
+    assert_eq!((cache.hits, cache.misses, cache.evictions), (0, 6, 4));
+
+    // Each way places them in different sets when skewed
+    let mut cache = Cache::new(2, 2, 4).unwrap();
+    cache.skewed = true;
+    for _ in 0..2 {
+        for address in [0x0, 0x40, 0x80] {
+            assert_eq!(cache.simulate_address('L', address), Ok(()));
+        }
+    }
+    assert_eq!((cache.hits, cache.misses, cache.evictions), (3, 3, 0));
+}
+
+#[test]
+fn test_simulate_address_skewed_evicts_lru() {
+    // Blocks 0, 7 and 33 map to set 0 in both ways
+    let mut cache = Cache::new(2, 2, 4).unwrap();
+    cache.skewed = true;
+
+    assert_eq!(cache.simulate_address('L', 0x0), Ok(()));
+    assert_eq!(cache.simulate_address('L', 0x70), Ok(()));
+    assert_eq!(cache.simulate_address('L', 0x0), Ok(()));
+    assert_eq!((cache.hits, cache.misses, cache.evictions), (1, 2, 0));
+
+    assert_eq!(cache.simulate_address('L', 0x210), Ok(()));
+    assert_eq!(cache.sets[0].lines[1].tag, Some(0x21));
+    assert_eq!(cache.simulate_address('M', 0x70), Ok(()));
+    assert_eq!(cache.sets[0].lines[0].tag, Some(0x7));
+    assert_eq!((cache.hits, cache.misses, cache.evictions), (2, 4, 2));
+
+    assert_eq!(cache.simulate_address('X', 0x0), Err(SimError::Access("unknown operation: X".to_string())));
+}
+
+// Tests for check_invariants function
+#[test]
+fn test_check_invariants_detects_corruption() {
+    let mut cache = Cache::new(1, 2, 4).unwrap();
+    for address in [0x00, 0x20, 0x40, 0x10] {
+        cache.simulate_address('L', address).unwrap();
+    }
+    assert_eq!(cache.check_invariants(), Ok(()));
+
+    let mut duplicate = Cache::new(1, 2, 4).unwrap();
+    for address in [0x00, 0x20] {
+        duplicate.simulate_address('L', address).unwrap();
+    }
+    duplicate.sets[0].lines[1].tag = duplicate.sets[0].lines[0].tag;
+    assert_eq!(duplicate.check_invariants(), Err(SimError::Access("invariant broken: set 0 holds tag 0x0 twice".to_string())));
+
+    let mut unordered = Cache::new(1, 2, 4).unwrap();
+    unordered.simulate_address('L', 0x00).unwrap();
+    unordered.sets[0].access_order.push_back(1);
+    assert!(unordered.check_invariants().unwrap_err().message().contains("orders ways [0, 1] but holds valid ways [0]"));
+
+    let mut miscounted = Cache::new(1, 2, 4).unwrap();
+    miscounted.simulate_address('L', 0x00).unwrap();
+    miscounted.hits += 1;
+    assert!(miscounted.check_invariants().unwrap_err().message().contains("the sets count (0, 1, 0)"));
+
+    let mut stale = Cache::new(1, 2, 4).unwrap();
+    stale.sets[1].lines[0].dirty = true;
+    assert!(stale.check_invariants().unwrap_err().message().contains("set 1 way 0 is invalid but dirty"));
+}
+
+#[test]
+fn test_check_invariants_random_accesses() {
+    // Random configurations and access sequences, small enough to force conflicts, checked after every access
+    for seed in 1..=300 {
+        let mut rng = Rng::new(seed);
+        let (s, e, b) = (1 + rng.next_below(3), 1 + rng.next_below(4), 1 + rng.next_below(3));
+        let mut builder = CacheBuilder::new().set_index_bits(s).associativity(e).block_bits(b)
+            .tie_break([TieBreak::Lowest, TieBreak::Random, TieBreak::RoundRobin][rng.next_below(3)])
+            .seed(seed)
+            .classify_misses(rng.next_below(2) == 0)
+            .undo_depth(8);
+        // Skewed caches don't predict ways or compress, compressed caches aren't partitioned, and both only replace LRU
+        let skewed = rng.next_below(4) == 0;
+        let compressed = !skewed && rng.next_below(5) == 0;
+        builder = builder.skewed(skewed);
+        if !skewed {
+            builder = builder.way_predictor([None, Some(WayPredictor::Mru), Some(WayPredictor::PartialTag)][rng.next_below(3)]);
+        }
+        if compressed {
+            builder = builder.compression(0.5);
+        } else if e > 1 && rng.next_below(4) == 0 {
+            builder = builder.way_partitions(HashMap::from([(1, vec![0]), (2, (1..e).collect())]));
+        }
+        if !skewed && !compressed {
+            let policies = [Replacement::Lru, Replacement::Fifo, Replacement::Random, Replacement::Slru, Replacement::TwoQ, Replacement::Lirs, Replacement::Plru];
+            builder = builder.replacement(policies[rng.next_below(if e.is_power_of_two() { 7 } else { 6 })]);
+        }
+        let mut cache = builder.build().unwrap();
+        for step in 0..300 {
+            let access = MemoryAccess {
+                operation: ['L', 'S', 'M'][rng.next_below(3)],
+                address: rng.next_below(1 << (s + b + 3)) as u64,
+                thread: rng.next_below(3),
+                size: 1,
+                pc: 0,
+            };
+            cache.simulate_access(access).unwrap();
+            match rng.next_below(50) {
+                0 => {
+                    cache.step_back();
+                }
+                1 => cache.reset_stats(),
+                _ => {}
+            }
+            if let Err(err) = cache.check_invariants() {
+                panic!("seed {} step {} (s={} E={} b={}): {}", seed, step, s, e, b, err);
+            }
+        }
+    }
+}
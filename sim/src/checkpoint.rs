@@ -0,0 +1,115 @@
+//! Saving a cache's state to disk and restoring it, to resume long runs or reuse a warmed-up cache.
+
+use std::fs;
+
+use serde::{Deserialize, Serialize};
+
+use crate::cache::Cache;
+use crate::error::SimError;
+
+/// Version of the checkpoint format, bumped whenever the saved state changes.
+pub const CHECKPOINT_VERSION: u32 = 1;
+
+#[derive(Serialize)]
+struct CheckpointRef<'a> {
+    version: u32,
+    cache: &'a Cache,
+}
+
+#[derive(Deserialize)]
+struct Checkpoint {
+    version: u32,
+    cache: Cache,
+}
+
+impl Cache {
+    /// Serialize the cache's contents, configuration and statistics. Undo history is not saved.
+    pub fn to_checkpoint(&self) -> Result<String, SimError> {
+        serde_json::to_string(&CheckpointRef { version: CHECKPOINT_VERSION, cache: self })
+            .map_err(|err| SimError::Io(format!("failed to serialize checkpoint ({})", err)))
+    }
+
+    /// Restore a cache serialized by [`Cache::to_checkpoint`].
+    pub fn from_checkpoint(checkpoint: &str) -> Result<Cache, SimError> {
+        let checkpoint: Checkpoint = serde_json::from_str(checkpoint)
+            .map_err(|err| SimError::Config(format!("invalid checkpoint ({})", err)))?;
+        if checkpoint.version != CHECKPOINT_VERSION {
+            return Err(SimError::Config(format!(
+                "checkpoint version {} is not supported (expected {})",
+                checkpoint.version, CHECKPOINT_VERSION
+            )));
+        }
+        checkpoint.cache.check_shape()?;
+        let mut cache = checkpoint.cache;
+        cache.mute_baseline_log();
+        Ok(cache)
+    }
+
+    /// Save the cache to a checkpoint file.
+    pub fn save_checkpoint(&self, path: &str) -> Result<(), SimError> {
+        fs::write(path, self.to_checkpoint()?).map_err(|err| SimError::Io(format!("failed to write checkpoint {} ({})", path, err)))
+    }
+
+    /// Load a cache from a checkpoint file.
+    pub fn load_checkpoint(path: &str) -> Result<Cache, SimError> {
+        let checkpoint = fs::read_to_string(path).map_err(|err| SimError::Io(format!("failed to read checkpoint {} ({})", path, err)))?;
+        Cache::from_checkpoint(&checkpoint)
+    }
+}
+
+// Tests for checkpoint and restore
+#[cfg(test)]
+use crate::cache::{CacheBuilder, Op, TieBreak};
+
+#[test]
+fn test_checkpoint_round_trip() {
+    let mut cache = CacheBuilder::new().set_index_bits(2).associativity(2).block_bits(4).tie_break(TieBreak::Random).seed(3).banks(2, 2).build().unwrap();
+    for address in [0x00, 0x40, 0x80, 0x10, 0x00] {
+        cache.access(Op::Store, address, 1).unwrap();
+    }
+    let mut restored = Cache::from_checkpoint(&cache.to_checkpoint().unwrap()).unwrap();
+    assert_eq!(restored.to_string(), cache.to_string());
+    assert_eq!(restored.snapshot_stats(), cache.snapshot_stats());
+
+    // Both continue identically, including random tie-breaking
+    for address in [0xc0, 0x100, 0x140, 0x40, 0x1c0] {
+        assert_eq!(restored.access(Op::Load, address, 1), cache.access(Op::Load, address, 1));
+    }
+    assert_eq!(restored.to_string(), cache.to_string());
+}
+
+#[test]
+fn test_checkpoint_compressed_and_skewed() {
+    let mut cache = CacheBuilder::new().set_index_bits(2).associativity(2).block_bits(4).compression(0.5).build().unwrap();
+    cache.access(Op::Load, 0x40, 1).unwrap();
+    let restored = Cache::from_checkpoint(&cache.to_checkpoint().unwrap()).unwrap();
+    assert_eq!(restored.format_stats(), cache.format_stats());
+
+    let mut cache = CacheBuilder::new().set_index_bits(2).associativity(2).block_bits(4).skewed(true).build().unwrap();
+    cache.access(Op::Load, 0x40, 1).unwrap();
+    let mut restored = Cache::from_checkpoint(&cache.to_checkpoint().unwrap()).unwrap();
+    assert_eq!(restored.access(Op::Load, 0x40, 1).unwrap().hits, 1);
+}
+
+#[test]
+fn test_checkpoint_file() {
+    let path = std::env::temp_dir().join(format!("sim-checkpoint-{}.json", std::process::id()));
+    let path = path.to_str().unwrap();
+    let mut cache = Cache::new(4, 1, 4).unwrap();
+    cache.access(Op::Load, 0x10, 1).unwrap();
+    cache.save_checkpoint(path).unwrap();
+    let mut restored = Cache::load_checkpoint(path).unwrap();
+    fs::remove_file(path).unwrap();
+    assert_eq!(restored.access(Op::Load, 0x10, 1).unwrap().hits, 1);
+    assert!(matches!(Cache::load_checkpoint(path), Err(SimError::Io(_))));
+}
+
+#[test]
+fn test_checkpoint_invalid() {
+    let checkpoint = Cache::new(1, 1, 4).unwrap().to_checkpoint().unwrap();
+    assert!(matches!(Cache::from_checkpoint("not json"), Err(SimError::Config(_))));
+    let future = checkpoint.replacen(&format!("\"version\":{}", CHECKPOINT_VERSION), "\"version\":99", 1);
+    assert!(matches!(Cache::from_checkpoint(&future), Err(SimError::Config(_))));
+    let inconsistent = checkpoint.replacen("\"set_bits\":1", "\"set_bits\":3", 1);
+    assert!(matches!(Cache::from_checkpoint(&inconsistent), Err(SimError::Config(_))));
+}
@@ -0,0 +1,198 @@
+//! Shell completion scripts for the command line, generated from a description of its subcommands and options so
+//! they list the same flags and values the parser accepts.
+
+use std::fmt;
+
+use crate::error::SimError;
+
+/// Shell a completion script is written for.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Shell {
+    Bash,
+    Zsh,
+    Fish,
+}
+
+impl std::str::FromStr for Shell {
+    type Err = SimError;
+
+    fn from_str(value: &str) -> Result<Shell, SimError> {
+        match value {
+            "bash" => Ok(Shell::Bash),
+            "zsh" => Ok(Shell::Zsh),
+            "fish" => Ok(Shell::Fish),
+            _ => Err(SimError::Config(format!("unknown shell: {}", value))),
+        }
+    }
+}
+
+impl fmt::Display for Shell {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Shell::Bash => "bash",
+            Shell::Zsh => "zsh",
+            Shell::Fish => "fish",
+        })
+    }
+}
+
+/// Value an option takes, for completing it.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum OptionValue {
+    /// A flag without a value
+    None,
+    /// A value that can't be completed, such as a number
+    Any,
+    /// A file path
+    File,
+    /// One of a fixed set of values
+    OneOf(&'static [&'static str]),
+}
+
+/// A command line to complete: the command, its subcommands, and its options with "-" or "--" and the values they take.
+pub struct CommandLine<'a> {
+    pub command: &'a str,
+    pub subcommands: &'a [&'a str],
+    pub options: &'a [(String, OptionValue)],
+}
+
+/// Completion script of a command line for a shell.
+pub fn completion_script(shell: Shell, command_line: &CommandLine) -> String {
+    match shell {
+        Shell::Bash => bash_script(command_line),
+        Shell::Zsh => zsh_script(command_line),
+        Shell::Fish => fish_script(command_line),
+    }
+}
+
+// A bash completion function, registered with complete -F
+fn bash_script(command_line: &CommandLine) -> String {
+    let function = format!("_{}", command_line.command.replace('-', "_"));
+    let mut cases = String::new();
+    for (option, value) in command_line.options {
+        let reply = match value {
+            OptionValue::None => continue,
+            OptionValue::Any => "return".to_string(),
+            OptionValue::File => "COMPREPLY=($(compgen -f -- \"$cur\")); return".to_string(),
+            OptionValue::OneOf(values) => format!("COMPREPLY=($(compgen -W \"{}\" -- \"$cur\")); return", values.join(" ")),
+        };
+        cases.push_str(&format!("        {}) {};;\n", option, reply));
+    }
+    let options: Vec<&str> = command_line.options.iter().map(|(option, _)| option.as_str()).collect();
+    format!(
+        "{function}() {{\n\
+        \x20   local cur=\"${{COMP_WORDS[COMP_CWORD]}}\" prev=\"${{COMP_WORDS[COMP_CWORD-1]}}\"\n\
+        \x20   case \"$prev\" in\n\
+        {cases}\
+        \x20   esac\n\
+        \x20   if [[ $COMP_CWORD -eq 1 && $cur != -* ]]; then\n\
+        \x20       COMPREPLY=($(compgen -W \"{subcommands}\" -- \"$cur\"))\n\
+        \x20   else\n\
+        \x20       COMPREPLY=($(compgen -W \"{options}\" -- \"$cur\"))\n\
+        \x20   fi\n\
+        }}\n\
+        complete -F {function} {command}\n",
+        subcommands = command_line.subcommands.join(" "),
+        options = options.join(" "),
+        command = command_line.command,
+    )
+}
+
+// A zsh completion function using _arguments, to install as _<command> in $fpath
+fn zsh_script(command_line: &CommandLine) -> String {
+    let mut script = format!("#compdef {}\n\n_arguments \\\n", command_line.command);
+    script.push_str(&format!("    '1::subcommand:({})' \\\n", command_line.subcommands.join(" ")));
+    for (option, value) in command_line.options {
+        let spec = match value {
+            OptionValue::None => option.clone(),
+            OptionValue::Any => format!("{}:value: ", option),
+            OptionValue::File => format!("*{}:file:_files", option),
+            OptionValue::OneOf(values) => format!("{}:value:({})", option, values.join(" ")),
+        };
+        script.push_str(&format!("    '{}' \\\n", spec));
+    }
+    script.push_str("    '*::file:_files'\n");
+    script
+}
+
+// fish complete commands, one per option
+fn fish_script(command_line: &CommandLine) -> String {
+    let command = command_line.command;
+    let mut script = format!("complete -c {} -f\n", command);
+    script.push_str(&format!("complete -c {} -n __fish_use_subcommand -a '{}'\n", command, command_line.subcommands.join(" ")));
+    for (option, value) in command_line.options {
+        let name = match option.strip_prefix("--") {
+            Some(name) => format!("-l {}", name),
+            None => format!("-s {}", option.trim_start_matches('-')),
+        };
+        let value = match value {
+            OptionValue::None => String::new(),
+            OptionValue::Any => " -x".to_string(),
+            OptionValue::File => " -r -F".to_string(),
+            OptionValue::OneOf(values) => format!(" -x -a '{}'", values.join(" ")),
+        };
+        script.push_str(&format!("complete -c {} {}{}\n", command, name, value));
+    }
+    script
+}
+
+#[cfg(test)]
+fn test_command_line(options: &[(String, OptionValue)]) -> CommandLine<'_> {
+    CommandLine { command: "sim", subcommands: &["convert", "gen"], options }
+}
+
+#[cfg(test)]
+fn test_options() -> Vec<(String, OptionValue)> {
+    vec![
+        ("-s".to_string(), OptionValue::Any),
+        ("-t".to_string(), OptionValue::File),
+        ("--tie-break".to_string(), OptionValue::OneOf(&["lowest", "random"])),
+        ("--skewed".to_string(), OptionValue::None),
+    ]
+}
+
+// Tests for Shell
+#[test]
+fn test_shell() {
+    for shell in [Shell::Bash, Shell::Zsh, Shell::Fish] {
+        assert_eq!(shell.to_string().parse::<Shell>(), Ok(shell));
+    }
+    assert_eq!("powershell".parse::<Shell>(), Err(SimError::Config("unknown shell: powershell".to_string())));
+}
+
+// Tests for completion_script function
+#[test]
+fn test_completion_script_bash() {
+    let options = test_options();
+    let script = completion_script(Shell::Bash, &test_command_line(&options));
+    assert!(script.starts_with("_sim() {\n"));
+    assert!(script.contains("        -s) return;;\n"));
+    assert!(script.contains("        -t) COMPREPLY=($(compgen -f -- \"$cur\")); return;;\n"));
+    assert!(script.contains("        --tie-break) COMPREPLY=($(compgen -W \"lowest random\" -- \"$cur\")); return;;\n"));
+    assert!(!script.contains("--skewed)"));
+    assert!(script.contains("compgen -W \"convert gen\""));
+    assert!(script.contains("compgen -W \"-s -t --tie-break --skewed\""));
+    assert!(script.ends_with("complete -F _sim sim\n"));
+}
+
+#[test]
+fn test_completion_script_zsh() {
+    let options = test_options();
+    let script = completion_script(Shell::Zsh, &test_command_line(&options));
+    assert_eq!(
+        script,
+        "#compdef sim\n\n_arguments \\\n    '1::subcommand:(convert gen)' \\\n    '-s:value: ' \\\n    '*-t:file:_files' \\\n    \
+        '--tie-break:value:(lowest random)' \\\n    '--skewed' \\\n    '*::file:_files'\n"
+    );
+}
+
+#[test]
+fn test_completion_script_fish() {
+    let options = test_options();
+    let script = completion_script(Shell::Fish, &test_command_line(&options));
+    assert_eq!(
+        script,
+        "complete -c sim -f\ncomplete -c sim -n __fish_use_subcommand -a 'convert gen'\ncomplete -c sim -s s -x\n\
+        complete -c sim -s t -r -F\ncomplete -c sim -l tie-break -x -a 'lowest random'\ncomplete -c sim -l skewed\n"
+    );
+}
@@ -0,0 +1,695 @@
+//! Configuration files describing a cache or a hierarchy of cache levels, in TOML or JSON.
+//!
+//! ```toml
+//! memory_latency = 200  # cycles, for the average memory access time
+//! cores = 4  # optional: private copies of the first level for each core
+//! snoop_filter = true  # optional: count the snoops a directory of the private levels spares
+//!
+//! [[level]]
+//! name = "L1"
+//! size = "32K"  # or s = 6, the set index bits
+//! E = 8
+//! b = 6
+//! latency = 4
+//!
+//! [[level]]
+//! name = "L2"
+//! size = "2M"
+//! E = 16
+//! b = 6
+//! latency = 12
+//! tie_break = "random"
+//! lock_ways = "0@0-15"  # optional: ways locked against replacement, as for --lock-ways
+//! slices = 4  # optional: address-hashed slices, each ring hop to one taking slice_latency cycles
+//! slice_latency = 2
+//!
+//! [dram]  # optional row-buffer model behind the last level
+//! banks = 8
+//! row_size = 8192
+//!
+//! [memory_controller]  # optional bandwidth and queue contention
+//! bandwidth = 16  # bytes per cycle
+//! queue_depth = 8
+//! ```
+//!
+//! With `cores`, or several traces replayed together, the levels marked `private = true` are copied for each core and
+//! the rest are shared; by default only the first level is private. Private levels come before the shared ones.
+//!
+//! Only the subset of TOML such files need is read: comments, `[table]` and `[[array]]` headers, and
+//! `key = value` lines whose values are strings, integers, floats or booleans.
+
+use serde::Deserialize;
+use serde_json::{Map, Value};
+
+use crate::cache::{parse_address_range, parse_address_ranges, parse_way_locks, CacheBuilder, DEFAULT_SEED};
+use crate::controller::MemoryController;
+use crate::dram::{Dram, RowLatencies};
+use crate::error::SimError;
+use crate::nuca::{SliceHash, Slices};
+
+/// One level of a configuration file, with the options of [`CacheBuilder`] it supports.
+#[derive(Debug, Clone, PartialEq, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct LevelConfig {
+    pub name: Option<String>,
+    pub s: Option<usize>,
+    /// Capacity in bytes, instead of s
+    pub size: Option<ByteSize>,
+    #[serde(rename = "E")]
+    pub e: usize,
+    pub b: usize,
+    /// Cycles to look the level up, for the average memory access time
+    pub latency: Option<u64>,
+    /// Replacement policy, as for --policy: lru (default), fifo, mru, slru, 2q, lirs, random, plru or one of the RRIP-based ones
+    pub policy: Option<String>,
+    /// Position of filled lines in the recency order: mru (default), lip or bip
+    pub insertion: Option<String>,
+    /// Lines of each set in the protected segment of slru
+    pub protected_ways: Option<usize>,
+    /// Two policies switched between at runtime instead of policy, as for --adaptive: "lru,brrip"
+    pub adaptive: Option<String>,
+    pub tie_break: Option<String>,
+    pub seed: Option<u64>,
+    #[serde(default)]
+    pub skewed: bool,
+    pub way_predict: Option<String>,
+    pub addr_bits: Option<usize>,
+    pub oob_addr: Option<String>,
+    /// Address ranges that bypass the cache, as for --uncacheable
+    pub uncacheable: Option<String>,
+    /// Ways locked against replacement, as for --lock-ways
+    pub lock_ways: Option<String>,
+    /// Address range served by a scratchpad taking ways from the level, as for --scratchpad
+    pub scratchpad: Option<String>,
+    /// Region size of the bypass predictor, as for --bypass
+    pub bypass: Option<ByteSize>,
+    /// Whether a TinyLFU frequency sketch gates the fills that would evict, as for --tinylfu
+    #[serde(default)]
+    pub tinylfu: bool,
+    pub banks: Option<usize>,
+    pub issue_width: Option<usize>,
+    pub compress: Option<f64>,
+    /// Whether each core has a copy of the level rather than sharing it
+    pub private: Option<bool>,
+    /// Address-hashed slices the level is split into, a power of two
+    pub slices: Option<usize>,
+    /// Hash picking the slice of a block: xor (default) or bits
+    pub slice_hash: Option<String>,
+    /// Cycles of each ring hop from a core to the slice of its access
+    pub slice_latency: Option<u64>,
+}
+
+/// A number of bytes, as an integer or a string with a K, M or G suffix such as "32K".
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(untagged)]
+pub enum ByteSize {
+    Bytes(u64),
+    Text(String),
+}
+
+impl ByteSize {
+    pub fn bytes(&self) -> Result<u64, SimError> {
+        match self {
+            ByteSize::Bytes(bytes) => Ok(*bytes),
+            ByteSize::Text(text) => parse_bytes(text).map_err(|err| SimError::Config(format!("invalid size {} ({})", text, err))),
+        }
+    }
+}
+
+impl LevelConfig {
+    /// Set index bits of the level, given as s or worked out from its size.
+    pub fn set_index_bits(&self) -> Result<usize, SimError> {
+        let size = match (self.s, &self.size) {
+            (Some(s), None) => return Ok(s),
+            (None, Some(size)) => size.bytes()?,
+            (Some(_), Some(_)) => return Err(SimError::Config("s and size are alternatives".to_string())),
+            (None, None) => return Err(SimError::Config("missing s or size".to_string())),
+        };
+        let set_size = (self.e as u64).checked_shl(self.b as u32).filter(|&set_size| set_size > 0 && set_size >> self.b == self.e as u64);
+        match set_size {
+            Some(set_size) if size % set_size == 0 && (size / set_size).is_power_of_two() => Ok((size / set_size).trailing_zeros() as usize),
+            _ => Err(SimError::Config(format!("size {} isn't a power-of-two number of sets of E = {} blocks of 2^{} bytes", size, self.e, self.b))),
+        }
+    }
+
+    /// Slices of the level, if it is split into slices.
+    pub fn slices(&self) -> Result<Option<Slices>, SimError> {
+        let Some(count) = self.slices else {
+            if self.slice_hash.is_some() || self.slice_latency.is_some() {
+                return Err(SimError::Config("slice_hash and slice_latency require slices".to_string()));
+            }
+            return Ok(None);
+        };
+        let hash = match &self.slice_hash {
+            Some(hash) => hash.parse()?,
+            None => SliceHash::default(),
+        };
+        Slices::new(count, hash, self.set_index_bits()?, self.b, self.slice_latency).map(Some)
+    }
+
+    /// Builder of the cache the level describes.
+    pub fn builder(&self) -> Result<CacheBuilder, SimError> {
+        let mut builder = CacheBuilder::new()
+            .set_index_bits(self.set_index_bits()?)
+            .associativity(self.e)
+            .block_bits(self.b)
+            .seed(self.seed.unwrap_or(DEFAULT_SEED))
+            .skewed(self.skewed)
+            .address_bits(self.addr_bits.unwrap_or(64));
+        if let Some(policy) = &self.policy {
+            builder = builder.replacement(policy.parse()?);
+        }
+        if let Some(insertion) = &self.insertion {
+            builder = builder.insertion(insertion.parse()?);
+        }
+        if let Some(ways) = self.protected_ways {
+            builder = builder.protected_ways(ways);
+        }
+        if let Some(adaptive) = &self.adaptive {
+            let policies = adaptive.split(',').map(str::parse).collect::<Result<Vec<_>, _>>()?;
+            let [first, second] = policies[..] else {
+                return Err(SimError::Config("adaptive takes two policies".to_string()));
+            };
+            builder = builder.adaptive(first, second);
+        }
+        if let Some(tie_break) = &self.tie_break {
+            builder = builder.tie_break(tie_break.parse()?);
+        }
+        if let Some(way_predict) = &self.way_predict {
+            builder = builder.way_predictor(Some(way_predict.parse()?));
+        }
+        if let Some(oob_addr) = &self.oob_addr {
+            builder = builder.oob_policy(oob_addr.parse()?);
+        }
+        if let Some(uncacheable) = &self.uncacheable {
+            builder = builder.uncacheable(parse_address_ranges(uncacheable)?);
+        }
+        if let Some(lock_ways) = &self.lock_ways {
+            builder = builder.locked_ways(parse_way_locks(lock_ways)?);
+        }
+        if let Some(scratchpad) = &self.scratchpad {
+            builder = builder.scratchpad(parse_address_range(scratchpad)?);
+        }
+        if let Some(bypass) = &self.bypass {
+            builder = builder.bypass(bypass.bytes()?);
+        }
+        builder = builder.tinylfu(self.tinylfu);
+        if let Some(banks) = self.banks {
+            builder = builder.banks(banks, self.issue_width.unwrap_or(1));
+        } else if self.issue_width.is_some() {
+            return Err(SimError::Config("issue_width requires banks".to_string()));
+        }
+        if let Some(compress) = self.compress {
+            builder = builder.compression(compress);
+        }
+        builder.validate()?;
+        Ok(builder)
+    }
+}
+
+/// The DRAM behind the last level of a configuration file, with the options of [`Dram`].
+#[derive(Debug, Clone, PartialEq, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct DramConfig {
+    pub banks: usize,
+    /// Bytes per row
+    pub row_size: u64,
+    /// Row buffer policy: open (default) or closed
+    pub policy: Option<String>,
+    /// Cycles of a row hit, an access to an idle bank and a row conflict, for the average memory access time
+    pub row_hit_latency: Option<u64>,
+    pub row_empty_latency: Option<u64>,
+    pub row_conflict_latency: Option<u64>,
+}
+
+impl DramConfig {
+    /// The empty DRAM the section describes.
+    pub fn dram(&self) -> Result<Dram, SimError> {
+        let policy = match &self.policy {
+            Some(policy) => policy.parse()?,
+            None => Default::default(),
+        };
+        let dram = Dram::new(self.banks, self.row_size, policy)?;
+        match (self.row_hit_latency, self.row_empty_latency, self.row_conflict_latency) {
+            (Some(hit), Some(empty), Some(conflict)) => Ok(dram.with_latencies(RowLatencies { hit, empty, conflict })),
+            (None, None, None) => Ok(dram),
+            _ => Err(SimError::Config("row_hit_latency, row_empty_latency and row_conflict_latency go together".to_string())),
+        }
+    }
+}
+
+/// The memory controller behind the last level of a configuration file, with the options of [`MemoryController`].
+#[derive(Debug, Clone, PartialEq, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct MemoryControllerConfig {
+    /// Bytes transferred per cycle
+    pub bandwidth: f64,
+    /// Requests the queue holds before the core stalls
+    pub queue_depth: usize,
+}
+
+impl MemoryControllerConfig {
+    /// The idle controller the section describes, transferring blocks of the given size.
+    pub fn controller(&self, block_size: u64) -> Result<MemoryController, SimError> {
+        MemoryController::new(self.bandwidth, block_size, self.queue_depth)
+    }
+}
+
+/// A configuration file: one or more cache levels, the first one looked up first, and optionally the DRAM and the
+/// memory controller behind them.
+#[derive(Debug, Clone, PartialEq, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct HierarchyConfig {
+    #[serde(rename = "level")]
+    pub levels: Vec<LevelConfig>,
+    /// Cycles to reach memory after missing in every level
+    pub memory_latency: Option<u64>,
+    /// Cores with their own copies of the private levels
+    pub cores: Option<usize>,
+    /// Track the blocks of the cores' private levels in a snoop filter
+    #[serde(default)]
+    pub snoop_filter: bool,
+    pub dram: Option<DramConfig>,
+    pub memory_controller: Option<MemoryControllerConfig>,
+}
+
+impl HierarchyConfig {
+    /// Parse a TOML configuration.
+    pub fn from_toml(text: &str) -> Result<HierarchyConfig, SimError> {
+        HierarchyConfig::from_value(parse_toml(text)?)
+    }
+
+    /// Parse a JSON configuration with the same keys.
+    pub fn from_json(text: &str) -> Result<HierarchyConfig, SimError> {
+        HierarchyConfig::from_value(serde_json::from_str(text).map_err(|err| SimError::Config(err.to_string()))?)
+    }
+
+    /// Read a configuration file, as JSON if its name ends in .json and as TOML otherwise.
+    pub fn load(path: &str) -> Result<HierarchyConfig, SimError> {
+        let text = std::fs::read_to_string(path).map_err(|err| SimError::Config(format!("failed to read {} ({})", path, err)))?;
+        let config = if path.ends_with(".json") { HierarchyConfig::from_json(&text) } else { HierarchyConfig::from_toml(&text) };
+        config.map_err(|err| SimError::Config(format!("{}: {}", path, err)))
+    }
+
+    /// Whether the configuration is a single cache, which runs like the flags it replaces, rather than a hierarchy.
+    pub fn is_single_cache(&self) -> bool {
+        self.levels.len() == 1 && self.dram.is_none() && self.memory_controller.is_none() && self.cores.is_none()
+    }
+
+    /// Number of levels, from the first one, that each core has a copy of: those marked private, by default only
+    /// the first level.
+    pub fn private_levels(&self) -> usize {
+        self.levels.iter().enumerate().take_while(|&(index, level)| level.private.unwrap_or(index == 0)).count()
+    }
+
+    /// Bytes per block of the last level, which memory transfers.
+    pub fn block_size(&self) -> u64 {
+        self.levels.last().map_or(1, |level| 1 << level.b)
+    }
+
+    // Check the levels once they are deserialized
+    fn from_value(value: Value) -> Result<HierarchyConfig, SimError> {
+        let config: HierarchyConfig = serde_json::from_value(value).map_err(|err| SimError::Config(err.to_string()))?;
+        if config.levels.is_empty() {
+            return Err(SimError::Config("no cache level".to_string()));
+        }
+        for (index, level) in config.levels.iter().enumerate() {
+            level.builder().and_then(|_| level.slices()).map_err(|err| SimError::Config(format!("level {}: {}", index + 1, err)))?;
+        }
+        if config.levels.iter().skip(config.private_levels()).any(|level| level.private == Some(true)) {
+            return Err(SimError::Config("private levels must come before the shared ones".to_string()));
+        }
+        match config.cores {
+            Some(0) => return Err(SimError::Config("at least one core is needed".to_string())),
+            Some(_) if config.private_levels() == 0 => return Err(SimError::Config("cores need a private first level".to_string())),
+            _ => {}
+        }
+        if let Some(dram) = &config.dram {
+            dram.dram().map_err(|err| SimError::Config(format!("dram: {}", err)))?;
+        }
+        if let Some(controller) = &config.memory_controller {
+            controller.controller(config.block_size()).map_err(|err| SimError::Config(format!("memory_controller: {}", err)))?;
+        }
+        Ok(config)
+    }
+}
+
+/// Parse a byte count with an optional K, M or G suffix, such as "32K".
+pub fn parse_bytes(value: &str) -> Result<u64, String> {
+    let (digits, unit) = match value.char_indices().last() {
+        Some((i, 'K' | 'k')) => (&value[..i], 1 << 10),
+        Some((i, 'M' | 'm')) => (&value[..i], 1 << 20),
+        Some((i, 'G' | 'g')) => (&value[..i], 1 << 30),
+        _ => (value, 1),
+    };
+    let count = digits.parse::<u64>().map_err(|e| e.to_string())?;
+    count.checked_mul(unit).ok_or_else(|| "number too large".to_string())
+}
+
+/// Parse the subset of TOML that configuration files use into a JSON value.
+pub fn parse_toml(text: &str) -> Result<Value, SimError> {
+    let mut root = Map::new();
+    // Header of the table the following keys go into: its name, and whether it is an array of tables
+    let mut table: Option<(String, bool)> = None;
+    for (index, line) in text.lines().enumerate() {
+        let error = |message: String| SimError::Config(format!("line {}: {}", index + 1, message));
+        let line = strip_comment(line).trim();
+        if line.is_empty() {
+            continue;
+        }
+        if let Some(name) = line.strip_prefix("[[").and_then(|rest| rest.strip_suffix("]]")) {
+            let name = bare_key(name.trim()).map_err(error)?;
+            match root.entry(name.to_string()).or_insert_with(|| Value::Array(Vec::new())) {
+                Value::Array(tables) => tables.push(Value::Object(Map::new())),
+                _ => return Err(error(format!("{} is not an array of tables", name))),
+            }
+            table = Some((name.to_string(), true));
+        } else if let Some(name) = line.strip_prefix('[').and_then(|rest| rest.strip_suffix(']')) {
+            let name = bare_key(name.trim()).map_err(error)?;
+            if root.insert(name.to_string(), Value::Object(Map::new())).is_some() {
+                return Err(error(format!("duplicate table {}", name)));
+            }
+            table = Some((name.to_string(), false));
+        } else {
+            let (key, value) = line.split_once('=').ok_or_else(|| error("expected key = value".to_string()))?;
+            let key = bare_key(key.trim()).map_err(error)?;
+            let value = parse_value(value.trim()).map_err(error)?;
+            let entries = match &table {
+                None => &mut root,
+                Some((name, is_array)) => match (root.get_mut(name), is_array) {
+                    (Some(Value::Array(tables)), true) => tables.last_mut().and_then(Value::as_object_mut).unwrap(),
+                    (Some(Value::Object(entries)), false) => entries,
+                    _ => unreachable!("the table was created by its header"),
+                },
+            };
+            if entries.insert(key.to_string(), value).is_some() {
+                return Err(error(format!("duplicate key {}", key)));
+            }
+        }
+    }
+    Ok(Value::Object(root))
+}
+
+// Remove a comment, unless the # is inside a string
+fn strip_comment(line: &str) -> &str {
+    let mut quote = None;
+    let mut escaped = false;
+    for (index, character) in line.char_indices() {
+        match (quote, character) {
+            (Some('"'), '\\') if !escaped => {
+                escaped = true;
+                continue;
+            }
+            (Some(open), _) if character == open && !escaped => quote = None,
+            (None, '"' | '\'') => quote = Some(character),
+            (None, '#') => return &line[..index],
+            _ => {}
+        }
+        escaped = false;
+    }
+    line
+}
+
+fn bare_key(key: &str) -> Result<&str, String> {
+    if !key.is_empty() && key.chars().all(|character| character.is_ascii_alphanumeric() || character == '_' || character == '-') {
+        Ok(key)
+    } else {
+        Err(format!("invalid key {:?}", key))
+    }
+}
+
+// Parse a string, integer, float or boolean
+fn parse_value(value: &str) -> Result<Value, String> {
+    if let Some(literal) = value.strip_prefix('\'').and_then(|rest| rest.strip_suffix('\'')) {
+        return Ok(Value::String(literal.to_string()));
+    }
+    if let Some(basic) = value.strip_prefix('"').and_then(|rest| rest.strip_suffix('"')) {
+        let mut string = String::new();
+        let mut characters = basic.chars();
+        while let Some(character) = characters.next() {
+            if character != '\\' {
+                string.push(character);
+                continue;
+            }
+            string.push(match characters.next() {
+                Some('n') => '\n',
+                Some('t') => '\t',
+                Some('"') => '"',
+                Some('\\') => '\\',
+                escape => return Err(format!("unsupported escape \\{}", escape.map(String::from).unwrap_or_default())),
+            });
+        }
+        return Ok(Value::String(string));
+    }
+    match value {
+        "true" => return Ok(Value::Bool(true)),
+        "false" => return Ok(Value::Bool(false)),
+        _ => {}
+    }
+    let digits = value.replace('_', "");
+    let integer = match digits.strip_prefix("0x") {
+        Some(hex) => u64::from_str_radix(hex, 16).ok().map(Value::from),
+        None => digits.parse::<i64>().ok().map(Value::from),
+    };
+    integer
+        .or_else(|| digits.parse::<f64>().ok().filter(|float| float.is_finite()).map(Value::from))
+        .ok_or_else(|| format!("unsupported value {}", value))
+}
+
+#[cfg(test)]
+use serde_json::json;
+#[cfg(test)]
+use crate::cache::{Insertion, Replacement, WayLock};
+#[cfg(test)]
+use crate::dram::RowPolicy;
+
+// Tests for parse_bytes function
+#[test]
+fn test_parse_bytes() {
+    assert_eq!(parse_bytes("64"), Ok(64));
+    assert_eq!(parse_bytes("32K"), Ok(32 << 10));
+    assert_eq!(parse_bytes("2m"), Ok(2 << 20));
+    assert_eq!(parse_bytes("1G"), Ok(1 << 30));
+    assert!(parse_bytes("K").is_err());
+    assert!(parse_bytes("1T").is_err());
+    assert_eq!(parse_bytes("17179869184G"), Err("number too large".to_string()));
+}
+
+// Tests for parse_toml function
+#[test]
+fn test_parse_toml() {
+    let text = "# A comment\n\
+        top = 1_000 # trailing comment\n\
+        \n\
+        [table]\n\
+        name = \"a # b\"\n\
+        raw = 'c:\\path'\n\
+        [[items]]\n\
+        x = 0x10\n\
+        on = true\n\
+        [[items]]\n\
+        x = -2\n\
+        ratio = 0.5\n\
+        escaped = \"say \\\"hi\\\"\"\n";
+    assert_eq!(parse_toml(text), Ok(json!({
+        "top": 1000,
+        "table": { "name": "a # b", "raw": "c:\\path" },
+        "items": [{ "x": 16, "on": true }, { "x": -2, "ratio": 0.5, "escaped": "say \"hi\"" }],
+    })));
+}
+
+#[test]
+fn test_parse_toml_errors() {
+    assert_eq!(parse_toml("a = 1\na = 2"), Err(SimError::Config("line 2: duplicate key a".to_string())));
+    assert_eq!(parse_toml("[t]\n[t]"), Err(SimError::Config("line 2: duplicate table t".to_string())));
+    assert_eq!(parse_toml("[t]\n[[t]]"), Err(SimError::Config("line 2: t is not an array of tables".to_string())));
+    assert_eq!(parse_toml("just words"), Err(SimError::Config("line 1: expected key = value".to_string())));
+    assert_eq!(parse_toml("a = [1, 2]"), Err(SimError::Config("line 1: unsupported value [1, 2]".to_string())));
+    assert!(parse_toml("a b = 1").is_err());
+    assert!(parse_toml("a = \"\\u0041\"").is_err());
+}
+
+// Tests for HierarchyConfig
+#[test]
+fn test_hierarchy_config() {
+    let text = "memory_latency = 100\n\
+        [[level]]\n\
+        name = \"L1\"\n\
+        s = 4\n\
+        E = 2\n\
+        b = 4\n\
+        latency = 2\n\
+        [[level]]\n\
+        s = 6\n\
+        E = 4\n\
+        b = 4\n\
+        policy = \"plru\"\n\
+        tie_break = \"random\"\n\
+        seed = 7\n\
+        uncacheable = \"0xfe000000-0xfeffffff\"\n\
+        lock_ways = \"3@0-15\"\n";
+    let config = HierarchyConfig::from_toml(text).unwrap();
+    assert_eq!(config.memory_latency, Some(100));
+    assert_eq!(config.levels.len(), 2);
+    assert_eq!(config.levels[0].name.as_deref(), Some("L1"));
+    assert_eq!(config.levels[0].builder(), Ok(CacheBuilder::new().set_index_bits(4).associativity(2).block_bits(4)));
+    assert_eq!(
+        config.levels[1].builder(),
+        Ok(CacheBuilder::new().set_index_bits(6).associativity(4).block_bits(4).replacement(Replacement::Plru).tie_break("random".parse().unwrap()).seed(7)
+            .uncacheable(vec![0xfe00_0000..=0xfeff_ffff]).locked_ways(vec![WayLock { ways: 3..=3, sets: Some(0..=15) }]))
+    );
+
+    let json = r#"{"level": [{"s": 4, "E": 2, "b": 4, "latency": 2, "name": "L1"}, {"s": 6, "E": 4, "b": 4, "policy": "plru", "tie_break": "random", "seed": 7, "uncacheable": "0xfe000000-0xfeffffff", "lock_ways": "3@0-15"}], "memory_latency": 100}"#;
+    assert_eq!(HierarchyConfig::from_json(json), Ok(config));
+}
+
+#[test]
+fn test_hierarchy_config_errors() {
+    let level = "[[level]]\ns = 4\nE = 2\nb = 4\n";
+    assert!(HierarchyConfig::from_toml(level).is_ok());
+    assert_eq!(HierarchyConfig::from_toml("memory_latency = 1"), Err(SimError::Config("missing field `level`".to_string())));
+    assert_eq!(HierarchyConfig::from_json(r#"{"level": []}"#), Err(SimError::Config("no cache level".to_string())));
+    assert!(HierarchyConfig::from_toml(&format!("{}prefetcher = \"next-line\"\n", level)).unwrap_err().message().contains("unknown field `prefetcher`"));
+    assert_eq!(
+        HierarchyConfig::from_toml(&format!("{}policy = \"lfu\"\n", level)),
+        Err(SimError::Config("level 1: unknown replacement policy: lfu".to_string()))
+    );
+    assert!(HierarchyConfig::from_toml("[[level]]\ns = 4\nE = 0\nb = 4\n").is_err());
+    assert!(HierarchyConfig::from_toml(&format!("{}issue_width = 2\n", level)).is_err());
+    assert!(HierarchyConfig::from_toml(&format!("{}tie_break = \"highest\"\n", level)).is_err());
+    assert_eq!(HierarchyConfig::from_toml(&format!("{}insertion = \"bip\"\n", level)).unwrap().levels[0].builder(), Ok(CacheBuilder::new().set_index_bits(4).associativity(2).block_bits(4).insertion(Insertion::Bip)));
+    assert!(HierarchyConfig::from_toml(&format!("{}insertion = \"lru\"\n", level)).is_err());
+    assert!(HierarchyConfig::from_toml(&format!("{}insertion = \"lip\"\npolicy = \"fifo\"\n", level)).is_err());
+    assert_eq!(
+        HierarchyConfig::from_toml(&format!("{}policy = \"slru\"\nprotected_ways = 1\n", level)).unwrap().levels[0].builder(),
+        Ok(CacheBuilder::new().set_index_bits(4).associativity(2).block_bits(4).replacement(Replacement::Slru).protected_ways(1))
+    );
+    assert!(HierarchyConfig::from_toml(&format!("{}policy = \"slru\"\nprotected_ways = 2\n", level)).is_err());
+    assert_eq!(
+        HierarchyConfig::from_toml(&format!("{}adaptive = \"lru,brrip\"\n", level)).unwrap().levels[0].builder(),
+        Ok(CacheBuilder::new().set_index_bits(4).associativity(2).block_bits(4).adaptive(Replacement::Lru, Replacement::Brrip))
+    );
+    assert!(HierarchyConfig::from_toml(&format!("{}adaptive = \"lru\"\n", level)).is_err());
+    assert!(HierarchyConfig::from_toml(&format!("{}adaptive = \"lru,mru\"\npolicy = \"fifo\"\n", level)).is_err());
+    assert!(HierarchyConfig::from_toml(&format!("{}uncacheable = \"fff-100\"\n", level)).is_err());
+    assert!(HierarchyConfig::from_toml(&format!("{}lock_ways = \"2\"\n", level)).is_err());
+    assert!(HierarchyConfig::from_toml(&format!("{}lock_ways = \"0@16\"\n", level)).is_err());
+    assert!(HierarchyConfig::from_toml(&format!("{}scratchpad = \"0-ff\"\n", level)).is_ok());
+    assert!(HierarchyConfig::from_toml(&format!("{}scratchpad = \"0-1ff\"\n", level)).is_err());
+    assert!(HierarchyConfig::from_toml(&format!("{}scratchpad = \"0-7f\"\n", level)).is_err());
+    assert_eq!(HierarchyConfig::from_toml(&format!("{}bypass = \"4K\"\n", level)).unwrap().levels[0].builder(), Ok(CacheBuilder::new().set_index_bits(4).associativity(2).block_bits(4).bypass(4096)));
+    assert!(HierarchyConfig::from_toml(&format!("{}bypass = 8\n", level)).is_err());
+    assert_eq!(HierarchyConfig::from_toml(&format!("{}tinylfu = true\n", level)).unwrap().levels[0].builder(), Ok(CacheBuilder::new().set_index_bits(4).associativity(2).block_bits(4).tinylfu(true)));
+    assert!(HierarchyConfig::from_toml(&format!("{}tinylfu = true\nskewed = true\n", level)).is_err());
+}
+
+#[test]
+fn test_hierarchy_config_size() {
+    let config = HierarchyConfig::from_toml("[[level]]\nsize = \"32K\"\nE = 8\nb = 6\n[[level]]\nsize = 2097152\nE = 16\nb = 6\n").unwrap();
+    assert_eq!(config.levels[0].set_index_bits(), Ok(6));
+    assert_eq!(config.levels[1].builder(), Ok(CacheBuilder::new().set_index_bits(11).associativity(16).block_bits(6)));
+
+    assert_eq!(
+        HierarchyConfig::from_toml("[[level]]\nsize = \"48K\"\nE = 8\nb = 6\n"),
+        Err(SimError::Config("level 1: size 49152 isn't a power-of-two number of sets of E = 8 blocks of 2^6 bytes".to_string()))
+    );
+    assert_eq!(HierarchyConfig::from_toml("[[level]]\ns = 4\nsize = 1024\nE = 4\nb = 4\n"), Err(SimError::Config("level 1: s and size are alternatives".to_string())));
+    assert_eq!(HierarchyConfig::from_toml("[[level]]\nE = 4\nb = 4\n"), Err(SimError::Config("level 1: missing s or size".to_string())));
+    assert!(HierarchyConfig::from_toml("[[level]]\nsize = \"1T\"\nE = 4\nb = 4\n").is_err());
+    assert!(HierarchyConfig::from_toml("[[level]]\nsize = 1024\nE = 0\nb = 4\n").is_err());
+    assert!(HierarchyConfig::from_toml("[[level]]\nsize = 1024\nE = 4\nb = 70\n").is_err());
+}
+
+#[test]
+fn test_hierarchy_config_cores() {
+    let levels = "[[level]]\ns = 4\nE = 2\nb = 4\n[[level]]\ns = 5\nE = 2\nb = 4\n[[level]]\ns = 6\nE = 4\nb = 4\n";
+    let config = HierarchyConfig::from_toml(&format!("cores = 4\n{}", levels)).unwrap();
+    assert_eq!((config.cores, config.private_levels()), (Some(4), 1));
+    assert!(!config.is_single_cache());
+    assert!(!HierarchyConfig::from_toml("cores = 2\n[[level]]\ns = 4\nE = 2\nb = 4\n").unwrap().is_single_cache());
+    // Private L1 and L2 in front of a shared L3
+    let private = "[[level]]\ns = 4\nE = 2\nb = 4\n[[level]]\ns = 5\nE = 2\nb = 4\nprivate = true\n[[level]]\ns = 6\nE = 4\nb = 4\n";
+    assert_eq!(HierarchyConfig::from_toml(&format!("cores = 2\n{}", private)).unwrap().private_levels(), 2);
+    assert!(HierarchyConfig::from_toml(&format!("cores = 2\nsnoop_filter = true\n{}", levels)).unwrap().snoop_filter);
+
+    assert_eq!(HierarchyConfig::from_toml(&format!("cores = 0\n{}", levels)), Err(SimError::Config("at least one core is needed".to_string())));
+    assert_eq!(
+        HierarchyConfig::from_toml(&format!("{}private = true\n", levels)),
+        Err(SimError::Config("private levels must come before the shared ones".to_string()))
+    );
+    assert_eq!(
+        HierarchyConfig::from_toml("cores = 2\n[[level]]\ns = 4\nE = 2\nb = 4\nprivate = false\n"),
+        Err(SimError::Config("cores need a private first level".to_string()))
+    );
+}
+
+#[test]
+fn test_hierarchy_config_slices() {
+    let level = "[[level]]\ns = 4\nE = 2\nb = 4\n";
+    let config = HierarchyConfig::from_toml(&format!("{}slices = 8\nslice_hash = \"bits\"\nslice_latency = 3\n", level)).unwrap();
+    let slices = config.levels[0].slices().unwrap().unwrap();
+    assert_eq!((slices.count(), slices.hash(), slices.hop_cycles(4, 0)), (8, SliceHash::Bits, 12));
+    assert_eq!(HierarchyConfig::from_toml(level).unwrap().levels[0].slices(), Ok(None));
+
+    assert_eq!(
+        HierarchyConfig::from_toml(&format!("{}slices = 32\n", level)),
+        Err(SimError::Config("level 1: slices must be a power of two no larger than the 16 sets, not 32".to_string()))
+    );
+    assert_eq!(
+        HierarchyConfig::from_toml(&format!("{}slice_latency = 2\n", level)),
+        Err(SimError::Config("level 1: slice_hash and slice_latency require slices".to_string()))
+    );
+    assert!(HierarchyConfig::from_toml(&format!("{}slices = 4\nslice_hash = \"crc\"\n", level)).is_err());
+}
+
+#[test]
+fn test_hierarchy_config_dram() {
+    let level = "[[level]]\ns = 4\nE = 2\nb = 4\n";
+    let config = HierarchyConfig::from_toml(&format!("{}[dram]\nbanks = 8\nrow_size = 2048\npolicy = \"closed\"\n", level)).unwrap();
+    let dram = config.dram.as_ref().unwrap().dram().unwrap();
+    assert_eq!((dram.banks(), dram.row_size(), dram.policy()), (8, 2048, RowPolicy::Closed));
+    assert_eq!(dram.cycles(), None);
+    assert!(!config.is_single_cache());
+    assert!(HierarchyConfig::from_toml(level).unwrap().is_single_cache());
+
+    let latencies = "row_hit_latency = 20\nrow_empty_latency = 40\nrow_conflict_latency = 60\n";
+    let config = HierarchyConfig::from_toml(&format!("{}[dram]\nbanks = 4\nrow_size = 1024\n{}", level, latencies)).unwrap();
+    assert_eq!(config.dram.unwrap().dram().unwrap().cycles(), Some(0));
+
+    for dram in ["banks = 3\nrow_size = 1024\n", "banks = 4\nrow_size = 1024\npolicy = \"adaptive\"\n", "banks = 4\nrow_size = 1024\nrow_hit_latency = 20\n", "banks = 4\n"] {
+        let err = HierarchyConfig::from_toml(&format!("{}[dram]\n{}", level, dram)).unwrap_err();
+        assert!(matches!(err, SimError::Config(_)), "{}", dram);
+    }
+    assert_eq!(
+        HierarchyConfig::from_toml(&format!("{}[dram]\nbanks = 3\nrow_size = 1024\n", level)),
+        Err(SimError::Config("dram: DRAM banks must be a power of two, not 3".to_string()))
+    );
+}
+
+#[test]
+fn test_hierarchy_config_memory_controller() {
+    let levels = "[[level]]\ns = 4\nE = 2\nb = 4\n[[level]]\ns = 6\nE = 4\nb = 6\n";
+    let config = HierarchyConfig::from_toml(&format!("{}[memory_controller]\nbandwidth = 16\nqueue_depth = 4\n", levels)).unwrap();
+    assert_eq!(config.block_size(), 64);
+    let controller = config.memory_controller.as_ref().unwrap().controller(config.block_size()).unwrap();
+    assert_eq!((controller.transfer_cycles(), controller.queue_depth()), (4, 4));
+    assert!(!HierarchyConfig::from_toml("[[level]]\ns = 4\nE = 2\nb = 4\n[memory_controller]\nbandwidth = 0.5\nqueue_depth = 1\n").unwrap().is_single_cache());
+
+    assert_eq!(
+        HierarchyConfig::from_toml(&format!("{}[memory_controller]\nbandwidth = 16\nqueue_depth = 0\n", levels)),
+        Err(SimError::Config("memory_controller: memory controller queue depth must be at least 1".to_string()))
+    );
+    assert!(HierarchyConfig::from_toml(&format!("{}[memory_controller]\nbandwidth = -2\nqueue_depth = 4\n", levels)).is_err());
+    assert!(HierarchyConfig::from_toml(&format!("{}[memory_controller]\nqueue_depth = 4\n", levels)).is_err());
+}
+
+#[test]
+fn test_hierarchy_config_load() {
+    let path = std::env::temp_dir().join(format!("sim-config-{}.json", std::process::id()));
+    std::fs::write(&path, r#"{"level": [{"s": 4, "E": 2, "b": 4}]}"#).unwrap();
+    assert_eq!(HierarchyConfig::load(path.to_str().unwrap()).unwrap().levels[0].s, Some(4));
+    std::fs::write(&path, "not json").unwrap();
+    assert!(HierarchyConfig::load(path.to_str().unwrap()).unwrap_err().message().starts_with(path.to_str().unwrap()));
+    std::fs::remove_file(&path).unwrap();
+    assert!(HierarchyConfig::load("/nonexistent/config.toml").is_err());
+}
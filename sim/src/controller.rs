@@ -0,0 +1,189 @@
+//! A memory controller with a finite bandwidth and request queue, so that bursts of misses wait for each other.
+//!
+//! The core is taken to issue one access per cycle without waiting for its misses, as an out-of-order core
+//! overlapping them would, until the controller's queue is full: the next miss then stalls the core until the
+//! oldest request completes. The controller transfers one block at a time, so a request waits in the queue for
+//! the blocks of the requests before it. The fixed latency of memory comes on top of this, as in the average memory
+//! access time of a hierarchy.
+
+use std::collections::VecDeque;
+
+use crate::error::SimError;
+
+/// Counts of the requests a memory controller served.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct ControllerStats {
+    pub requests: usize,
+    pub queued: usize, // Requests that waited for the transfers of earlier ones
+    pub queue_cycles: u64, // Cycles requests spent waiting in the queue
+    pub stalls: usize, // Requests that found the queue full
+    pub stall_cycles: u64, // Cycles the core stalled on a full queue
+    pub max_occupancy: usize, // Most requests in the queue at once, counting the one being transferred
+}
+
+/// The request queue of a memory controller, and the time of the core that fills it.
+///
+/// ```
+/// use sim::MemoryController;
+///
+/// // A 64-byte block every 8 cycles: two misses in a row make the second one wait
+/// let mut controller = MemoryController::new(8.0, 64, 4).unwrap();
+/// assert_eq!(controller.request(), 0);
+/// controller.tick();
+/// assert_eq!(controller.request(), 7);
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct MemoryController {
+    transfer_cycles: u64, // Cycles to transfer a block
+    queue_depth: usize,
+    now: u64, // Cycle of the core
+    busy_until: u64, // Cycle the last queued transfer completes
+    pending: VecDeque<u64>, // Completion cycles of the requests in the queue, oldest first
+    stats: ControllerStats,
+}
+
+impl MemoryController {
+    /// Create an idle controller transferring bandwidth bytes per cycle of blocks of block_size bytes, with room for
+    /// queue_depth requests.
+    pub fn new(bandwidth: f64, block_size: u64, queue_depth: usize) -> Result<MemoryController, SimError> {
+        if !(bandwidth.is_finite() && bandwidth > 0.0) {
+            return Err(SimError::Config(format!("memory bandwidth must be a positive number of bytes per cycle, not {}", bandwidth)));
+        }
+        if queue_depth == 0 {
+            return Err(SimError::Config("memory controller queue depth must be at least 1".to_string()));
+        }
+        let transfer_cycles = ((block_size as f64 / bandwidth).ceil() as u64).max(1);
+        Ok(MemoryController { transfer_cycles, queue_depth, now: 0, busy_until: 0, pending: VecDeque::new(), stats: ControllerStats::default() })
+    }
+
+    /// Cycles to transfer a block.
+    pub fn transfer_cycles(&self) -> u64 {
+        self.transfer_cycles
+    }
+
+    /// Number of requests the queue holds.
+    pub fn queue_depth(&self) -> usize {
+        self.queue_depth
+    }
+
+    /// Counts so far.
+    pub fn stats(&self) -> ControllerStats {
+        self.stats
+    }
+
+    /// Clear the counts, keeping the queued requests.
+    pub fn reset_stats(&mut self) {
+        self.stats = ControllerStats::default();
+    }
+
+    /// Advance the core by the cycle of one access.
+    pub fn tick(&mut self) {
+        self.now += 1;
+    }
+
+    /// Queue a request for a block at the current cycle, and return the cycles it is delayed by the requests before
+    /// it: stalling the core while the queue is full, then waiting for the transfers ahead of it.
+    pub fn request(&mut self) -> u64 {
+        self.retire();
+        let mut stall = 0;
+        if self.pending.len() == self.queue_depth {
+            let free = self.pending.pop_front().expect("full queue without requests");
+            stall = free - self.now;
+            self.now = free;
+            self.retire();
+            self.stats.stalls += 1;
+            self.stats.stall_cycles += stall;
+        }
+        let start = self.busy_until.max(self.now);
+        let wait = start - self.now;
+        self.busy_until = start + self.transfer_cycles;
+        self.pending.push_back(self.busy_until);
+        self.stats.requests += 1;
+        if wait > 0 {
+            self.stats.queued += 1;
+            self.stats.queue_cycles += wait;
+        }
+        self.stats.max_occupancy = self.stats.max_occupancy.max(self.pending.len());
+        stall + wait
+    }
+
+    // Take the requests completed by the current cycle out of the queue
+    fn retire(&mut self) {
+        while self.pending.front().is_some_and(|&completion| completion <= self.now) {
+            self.pending.pop_front();
+        }
+    }
+
+    /// Cycles requests were delayed by contention: their waits in the queue and the stalls on a full queue.
+    pub fn delay_cycles(&self) -> u64 {
+        self.stats.queue_cycles + self.stats.stall_cycles
+    }
+
+    /// Format the counts on one line, with the mean delay of a request.
+    pub fn format_stats(&self) -> String {
+        let stats = &self.stats;
+        let mean_delay = if stats.requests == 0 { 0.0 } else { self.delay_cycles() as f64 / stats.requests as f64 };
+        format!(
+            "requests:{} queued:{} queue-cycles:{} stalls:{} stall-cycles:{} max-occupancy:{} mean-delay:{:.2} cycles",
+            stats.requests, stats.queued, stats.queue_cycles, stats.stalls, stats.stall_cycles, stats.max_occupancy, mean_delay
+        )
+    }
+}
+
+// Tests for MemoryController
+#[test]
+fn test_memory_controller_queue() {
+    // 4 cycles per block, room for 2 requests
+    let mut controller = MemoryController::new(16.0, 64, 2).unwrap();
+    assert_eq!(controller.transfer_cycles(), 4);
+    // A burst of three misses in the same cycle: the second waits for the first, and the third finds the queue
+    // full, stalling until the first completes at cycle 4 and then waiting for the second until cycle 8
+    assert_eq!(controller.request(), 0);
+    assert_eq!(controller.request(), 4);
+    assert_eq!(controller.request(), 4 + 4);
+    assert_eq!(controller.stats(), ControllerStats { requests: 3, queued: 2, queue_cycles: 8, stalls: 1, stall_cycles: 4, max_occupancy: 2 });
+    assert_eq!(controller.delay_cycles(), 12);
+
+    // Once the queue drains, a request doesn't wait
+    for _ in 0..20 {
+        controller.tick();
+    }
+    assert_eq!(controller.request(), 0);
+
+    controller.reset_stats();
+    assert_eq!(controller.stats(), ControllerStats::default());
+}
+
+#[test]
+fn test_memory_controller_spaced_requests() {
+    // Misses spaced by the transfer time never wait
+    let mut controller = MemoryController::new(1.0, 4, 1).unwrap();
+    for _ in 0..10 {
+        assert_eq!(controller.request(), 0);
+        for _ in 0..4 {
+            controller.tick();
+        }
+    }
+    assert_eq!(controller.delay_cycles(), 0);
+    // A fraction of a cycle per block still takes a cycle
+    assert_eq!(MemoryController::new(128.0, 64, 1).unwrap().transfer_cycles(), 1);
+    assert_eq!(MemoryController::new(0.5, 64, 1).unwrap().transfer_cycles(), 128);
+}
+
+#[test]
+fn test_memory_controller_errors() {
+    for bandwidth in [0.0, -1.0, f64::NAN, f64::INFINITY] {
+        assert!(matches!(MemoryController::new(bandwidth, 64, 4), Err(SimError::Config(_))), "{}", bandwidth);
+    }
+    assert!(matches!(MemoryController::new(8.0, 64, 0), Err(SimError::Config(_))));
+}
+
+// Tests for MemoryController::format_stats
+#[test]
+fn test_format_controller_stats() {
+    let mut controller = MemoryController::new(16.0, 64, 2).unwrap();
+    assert_eq!(controller.format_stats(), "requests:0 queued:0 queue-cycles:0 stalls:0 stall-cycles:0 max-occupancy:0 mean-delay:0.00 cycles");
+    controller.request();
+    controller.request();
+    assert_eq!(controller.format_stats(), "requests:2 queued:1 queue-cycles:4 stalls:0 stall-cycles:0 max-occupancy:2 mean-delay:2.00 cycles");
+}
@@ -0,0 +1,271 @@
+//! A local web dashboard serving the statistics of a run over HTTP while it is simulated.
+//!
+//! `GET /` returns a page that polls `GET /stats` for the statistics as JSON and charts the miss rate
+//! over the accesses simulated so far.
+
+use std::io::{BufRead, BufReader, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::thread::{self, JoinHandle};
+
+use serde::Serialize;
+
+use crate::cache::{Cache, Stats};
+use crate::error::SimError;
+use crate::trace::MemoryAccess;
+
+/// Accesses simulated between two updates of the dashboard.
+pub const PUBLISH_INTERVAL: usize = 10_000;
+
+// Points of history kept for the chart, beyond which every other point is dropped
+const HISTORY_POINTS: usize = 512;
+
+const DASHBOARD_PAGE: &str = r#"<!DOCTYPE html>
+<html>
+<head>
+<meta charset="utf-8">
+<title>sim dashboard</title>
+<style>
+body { font-family: sans-serif; margin: 2em; }
+table { border-collapse: collapse; margin-bottom: 1em; }
+td { padding: 2px 12px 2px 0; }
+canvas { border: 1px solid #ccc; }
+</style>
+</head>
+<body>
+<h1>sim <span id="state">running</span></h1>
+<table id="stats"></table>
+<canvas id="chart" width="720" height="240"></canvas>
+<p>Miss rate of each interval (red) and of the whole run so far (blue), by accesses simulated.</p>
+<script>
+async function refresh() {
+  const progress = await (await fetch('/stats')).json();
+  const stats = progress.stats;
+  const lookups = stats.hits + stats.misses;
+  const rows = [['accesses', progress.accesses], ['hits', stats.hits], ['misses', stats.misses],
+    ['evictions', stats.evictions], ['miss rate', lookups ? (100 * stats.misses / lookups).toFixed(2) + '%' : '-']];
+  document.getElementById('stats').innerHTML = rows.map(([name, value]) => '<tr><td>' + name + '</td><td>' + value + '</td></tr>').join('');
+  document.getElementById('state').textContent = progress.done ? 'done' : 'running';
+  draw(progress.history);
+  if (!progress.done) setTimeout(refresh, 1000);
+}
+function draw(history) {
+  const canvas = document.getElementById('chart');
+  const context = canvas.getContext('2d');
+  context.clearRect(0, 0, canvas.width, canvas.height);
+  if (history.length === 0) return;
+  const end = history[history.length - 1].accesses || 1;
+  const line = (color, rate) => {
+    context.strokeStyle = color;
+    context.beginPath();
+    history.forEach((point, i) => {
+      const x = canvas.width * point.accesses / end;
+      const y = canvas.height * (1 - rate(point, history[i - 1]));
+      i === 0 ? context.moveTo(x, y) : context.lineTo(x, y);
+    });
+    context.stroke();
+  };
+  const rate = (hits, misses) => hits + misses ? misses / (hits + misses) : 0;
+  line('#d03030', (point, previous) => previous ? rate(point.hits - previous.hits, point.misses - previous.misses) : rate(point.hits, point.misses));
+  line('#3050d0', point => rate(point.hits, point.misses));
+}
+refresh();
+</script>
+</body>
+</html>
+"#;
+
+/// Hits and misses after some accesses of a run.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct HistoryPoint {
+    pub accesses: usize,
+    pub hits: usize,
+    pub misses: usize,
+}
+
+/// What the dashboard shows: the statistics so far, and how the hits and misses grew.
+#[derive(Debug, Clone, PartialEq, Default, Serialize)]
+pub struct Progress {
+    pub accesses: usize,
+    pub done: bool,
+    pub stats: Stats,
+    pub history: Vec<HistoryPoint>,
+}
+
+/// An HTTP server on a background thread showing the statistics a run publishes to it.
+pub struct Dashboard {
+    address: SocketAddr,
+    progress: Arc<Mutex<Progress>>,
+    server: JoinHandle<()>,
+}
+
+impl Dashboard {
+    /// Listen on an address such as 127.0.0.1:8080 and start serving the dashboard.
+    pub fn serve(address: &str) -> Result<Dashboard, SimError> {
+        let listener = TcpListener::bind(address).map_err(|err| SimError::Io(format!("failed to listen on {} ({})", address, err)))?;
+        let address = listener.local_addr().map_err(|err| SimError::Io(format!("failed to listen on {} ({})", address, err)))?;
+        let progress = Arc::new(Mutex::new(Progress::default()));
+        let shared = Arc::clone(&progress);
+        let server = thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                // A client hanging up early only loses its own response
+                let _ = handle_request(stream, &shared);
+            }
+        });
+        Ok(Dashboard { address, progress, server })
+    }
+
+    /// Address the dashboard listens on.
+    pub fn address(&self) -> SocketAddr {
+        self.address
+    }
+
+    /// Publish the statistics of a cache after some accesses of the run.
+    pub fn publish(&self, cache: &Cache, accesses: usize) {
+        let stats = cache.snapshot_stats();
+        let mut progress = lock(&self.progress);
+        if progress.history.len() == HISTORY_POINTS {
+            let mut index = 0;
+            progress.history.retain(|_| {
+                index += 1;
+                index % 2 == 0
+            });
+        }
+        progress.history.push(HistoryPoint { accesses, hits: stats.hits, misses: stats.misses });
+        progress.accesses = accesses;
+        progress.stats = stats;
+    }
+
+    /// Publish the final statistics of the run.
+    pub fn finish(&self, cache: &Cache, accesses: usize) {
+        self.publish(cache, accesses);
+        lock(&self.progress).done = true;
+    }
+
+    /// What the dashboard currently shows.
+    pub fn progress(&self) -> Progress {
+        lock(&self.progress).clone()
+    }
+
+    /// Keep serving the dashboard until the process is interrupted.
+    pub fn wait(self) {
+        let _ = self.server.join();
+    }
+}
+
+// Lock the progress, which stays consistent even if a thread panicked while holding it
+fn lock(progress: &Mutex<Progress>) -> MutexGuard<'_, Progress> {
+    progress.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+// Answer one HTTP request with the page, the statistics as JSON, or an error
+fn handle_request(stream: TcpStream, progress: &Mutex<Progress>) -> std::io::Result<()> {
+    let mut reader = BufReader::new(&stream);
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+    // Consume the headers so closing the connection doesn't reset it before the response is read
+    let mut header = String::new();
+    while reader.read_line(&mut header)? > 0 && !header.trim().is_empty() {
+        header.clear();
+    }
+
+    let mut parts = request_line.split_whitespace();
+    let (status, content_type, body) = match (parts.next(), parts.next()) {
+        (Some("GET"), Some("/")) => ("200 OK", "text/html; charset=utf-8", DASHBOARD_PAGE.to_string()),
+        (Some("GET"), Some("/stats")) => {
+            let json = serde_json::to_string(&*lock(progress)).map_err(std::io::Error::other)?;
+            ("200 OK", "application/json", json)
+        }
+        (Some("GET"), _) => ("404 Not Found", "text/plain", "not found\n".to_string()),
+        _ => ("405 Method Not Allowed", "text/plain", "only GET is supported\n".to_string()),
+    };
+    let mut stream = &stream;
+    write!(
+        stream,
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nCache-Control: no-store\r\nConnection: close\r\n\r\n{}",
+        status, content_type, body.len(), body
+    )?;
+    stream.flush()
+}
+
+/// Simulate accesses as they are read, publishing the statistics to the dashboard every [`PUBLISH_INTERVAL`]
+/// accesses and marking the run done at the end. Returns the number of accesses simulated, stopping at the
+/// first error.
+pub fn simulate_accesses_live<I>(cache: &mut Cache, accesses: I, dashboard: &Dashboard) -> Result<usize, SimError>
+where
+    I: IntoIterator<Item = Result<MemoryAccess, SimError>>,
+{
+    let mut count = 0;
+    for access in accesses {
+        cache.simulate_access(access?)?;
+        count += 1;
+        if count % PUBLISH_INTERVAL == 0 {
+            dashboard.publish(cache, count);
+        }
+    }
+    dashboard.finish(cache, count);
+    Ok(count)
+}
+
+#[cfg(test)]
+use std::io::Read;
+#[cfg(test)]
+use crate::trace::trace_accesses;
+
+// Send a request to the dashboard and return the whole response
+#[cfg(test)]
+fn request(dashboard: &Dashboard, request_line: &str) -> String {
+    let mut stream = TcpStream::connect(dashboard.address()).unwrap();
+    write!(stream, "{}\r\nHost: localhost\r\n\r\n", request_line).unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).unwrap();
+    response
+}
+
+// Tests for Dashboard
+#[test]
+fn test_dashboard_serves_stats() {
+    let dashboard = Dashboard::serve("127.0.0.1:0").unwrap();
+    let mut cache = Cache::new(4, 1, 4).unwrap();
+    let count = simulate_accesses_live(&mut cache, trace_accesses("../traces/yi.trace").unwrap(), &dashboard).unwrap();
+    assert_eq!(count, 7);
+
+    let response = request(&dashboard, "GET /stats HTTP/1.1");
+    assert!(response.starts_with("HTTP/1.1 200 OK\r\nContent-Type: application/json\r\n"));
+    let body = response.split("\r\n\r\n").nth(1).unwrap();
+    let progress: serde_json::Value = serde_json::from_str(body).unwrap();
+    assert_eq!(progress["accesses"], 7);
+    assert_eq!(progress["done"], true);
+    assert_eq!(progress["stats"]["hits"], 4);
+    assert_eq!(progress["stats"]["misses"], 5);
+    assert_eq!(progress["history"][0]["misses"], 5);
+
+    let page = request(&dashboard, "GET / HTTP/1.1");
+    assert!(page.starts_with("HTTP/1.1 200 OK\r\nContent-Type: text/html"));
+    assert!(page.contains("fetch('/stats')"));
+    assert!(request(&dashboard, "GET /missing HTTP/1.1").starts_with("HTTP/1.1 404 Not Found"));
+    assert!(request(&dashboard, "POST /stats HTTP/1.1").starts_with("HTTP/1.1 405 Method Not Allowed"));
+}
+
+#[test]
+fn test_dashboard_history() {
+    let dashboard = Dashboard::serve("127.0.0.1:0").unwrap();
+    let cache = Cache::new(4, 1, 4).unwrap();
+    for accesses in 1..=HISTORY_POINTS + 1 {
+        dashboard.publish(&cache, accesses);
+    }
+    let progress = dashboard.progress();
+    assert!(!progress.done);
+    assert_eq!(progress.accesses, HISTORY_POINTS + 1);
+    // The full history was thinned to every other point before the last one was added
+    assert_eq!(progress.history.len(), HISTORY_POINTS / 2 + 1);
+    assert_eq!(progress.history[0].accesses, 2);
+    assert_eq!(progress.history.last().unwrap().accesses, HISTORY_POINTS + 1);
+}
+
+#[test]
+fn test_dashboard_serve_error() {
+    let dashboard = Dashboard::serve("127.0.0.1:0").unwrap();
+    assert!(matches!(Dashboard::serve(&dashboard.address().to_string()), Err(SimError::Io(_))));
+    assert!(Dashboard::serve("not an address").is_err());
+}
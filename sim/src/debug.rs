@@ -0,0 +1,287 @@
+//! Interactive stepping through a trace, with bounded undo, as a command prompt or a redrawn view of the sets.
+
+use std::io::{BufRead, Write};
+
+use crate::cache::{AccessResult, Cache, SetState};
+use crate::error::SimError;
+use crate::trace::MemoryAccess;
+#[cfg(test)]
+use crate::{cache::CacheBuilder, trace::read_accesses};
+
+/// Accesses the debugger can step back over unless another depth is given.
+pub const DEFAULT_UNDO_DEPTH: usize = 100;
+
+const DEBUG_HELP: &str = "Commands: n(ext) or empty line, b(ack), c(ontinue), p(rint) <set>, s(tats), h(elp), q(uit)";
+
+const TUI_HELP: &str = "n(ext) or empty line, b(ack), c(ontinue), q(uit)";
+
+// ANSI escapes to clear the terminal, and to color hits, fills and evictions
+const CLEAR_SCREEN: &str = "\x1b[H\x1b[2J";
+const GREEN: &str = "\x1b[32m";
+const YELLOW: &str = "\x1b[33m";
+const RED: &str = "\x1b[31m";
+const BOLD: &str = "\x1b[1m";
+const RESET: &str = "\x1b[0m";
+
+// Write debugger output
+fn emit<W: Write>(output: &mut W, text: &str) -> Result<(), SimError> {
+    output.write_all(text.as_bytes()).and_then(|_| output.flush()).map_err(|err| SimError::Io(format!("failed to write output ({})", err)))
+}
+
+/// Step through accesses reading debugger commands from input, stepping back over up to the cache's undo depth.
+pub fn debug_accesses<R: BufRead, W: Write>(cache: &mut Cache, accesses: &[MemoryAccess], input: R, output: &mut W) -> Result<(), SimError> {
+    let mut position = 0;
+    let mut commands = input.lines();
+    emit(output, &format!("{}\n", DEBUG_HELP))?;
+    loop {
+        emit(output, &format!("({}/{}) ", position, accesses.len()))?;
+        let command = match commands.next() {
+            Some(Ok(command)) => command,
+            _ => break,
+        };
+        let words: Vec<&str> = command.split_whitespace().collect();
+        let response = match words.first().copied() {
+            None | Some("n") | Some("next") => match accesses.get(position) {
+                Some(&access) => {
+                    let result = cache.simulate_access(access)?;
+                    position += 1;
+                    result.describe()
+                }
+                None => "end of trace".to_string(),
+            },
+            Some("b") | Some("back") => {
+                if cache.step_back() {
+                    position -= 1;
+                    let access = accesses[position];
+                    format!("undid {} {:x}", access.operation, access.address)
+                } else {
+                    "no history left to step back".to_string()
+                }
+            }
+            Some("c") | Some("continue") => {
+                for &access in &accesses[position..] {
+                    cache.simulate_access(access)?;
+                }
+                position = accesses.len();
+                "end of trace".to_string()
+            }
+            Some("p") | Some("print") => match words.get(1).and_then(|set_index| set_index.parse().ok()).and_then(|set_index| cache.describe_set(set_index)) {
+                Some(description) => description,
+                None => format!("usage: print <set index below {}>", cache.set_count()),
+            },
+            Some("s") | Some("stats") => cache.format_stats(),
+            Some("h") | Some("help") => DEBUG_HELP.to_string(),
+            Some("q") | Some("quit") => break,
+            Some(command) => format!("unknown command: {}\n{}", command, DEBUG_HELP),
+        };
+        emit(output, &format!("{}\n", response))?;
+    }
+    emit(output, "\n")
+}
+
+// What the last access did to the line holding its block
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Outcome {
+    Hit,
+    Fill,
+    Eviction(Option<usize>), // Tag of the evicted block
+}
+
+impl Outcome {
+    fn color(&self) -> &'static str {
+        match self {
+            Outcome::Hit => GREEN,
+            Outcome::Fill => YELLOW,
+            Outcome::Eviction(_) => RED,
+        }
+    }
+}
+
+// Line an access landed in, and what it did there
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Highlight {
+    set_index: usize,
+    way: usize,
+    outcome: Outcome,
+}
+
+// Simulate an access and find the line it hit, filled or evicted
+fn step(cache: &mut Cache, access: MemoryAccess) -> Result<(AccessResult, Option<Highlight>), SimError> {
+    let before: Vec<SetState> = cache.set_states().collect();
+    let result = cache.simulate_access(access)?;
+    let highlight = cache.locate(access.address).map(|(set_index, way)| {
+        let outcome = if result.evictions > 0 {
+            Outcome::Eviction(before[set_index].lines[way].tag)
+        } else if result.misses > 0 {
+            Outcome::Fill
+        } else {
+            Outcome::Hit
+        };
+        Highlight { set_index, way, outcome }
+    });
+    Ok((result, highlight))
+}
+
+// Draw the statistics, the outcome of the last command and the contents of every set holding a block
+fn render_screen(cache: &Cache, position: usize, total: usize, message: &str, highlight: Option<Highlight>) -> String {
+    let mut screen = String::from(CLEAR_SCREEN);
+    screen.push_str(&format!("access {}/{} hits:{} misses:{} evictions:{}\n", position, total, cache.hits(), cache.misses(), cache.evictions()));
+    match highlight {
+        Some(highlight) => screen.push_str(&format!("{}{}{}\n\n", highlight.outcome.color(), message, RESET)),
+        None => screen.push_str(&format!("{}\n\n", message)),
+    }
+    let mut empty_sets = 0;
+    for state in cache.set_states() {
+        let current = highlight.filter(|highlight| highlight.set_index == state.index);
+        if current.is_none() && state.lines.iter().all(|line| !line.valid) {
+            empty_sets += 1;
+            continue;
+        }
+        let lines: Vec<String> = state.lines.iter().map(|line| {
+            let mut text = match line.tag {
+                Some(tag) if line.dirty => format!("[{}] tag {:x} dirty", line.way, tag),
+                Some(tag) => format!("[{}] tag {:x}", line.way, tag),
+                None => format!("[{}] empty", line.way),
+            };
+            if line.pinned {
+                text.push_str(" pinned");
+            }
+            match current.filter(|highlight| highlight.way == line.way) {
+                Some(Highlight { outcome: Outcome::Eviction(Some(evicted)), .. }) => {
+                    text = format!("{}{} (evicted {:x}){}", RED, text, evicted, RESET);
+                }
+                Some(highlight) => text = format!("{}{}{}", highlight.outcome.color(), text, RESET),
+                None => {}
+            }
+            text
+        }).collect();
+        let order: Vec<String> = state.lru_order.iter().map(|way| way.to_string()).collect();
+        let label = if current.is_some() { format!("{}> set {}{}", BOLD, state.index, RESET) } else { format!("  set {}", state.index) };
+        screen.push_str(&format!("{}: {} | LRU: {}\n", label, lines.join(" "), order.join(" ")));
+    }
+    if empty_sets > 0 {
+        screen.push_str(&format!("  ({} empty sets not shown)\n", empty_sets));
+    }
+    screen.push_str(&format!("\n{}\n> ", TUI_HELP));
+    screen
+}
+
+/// Step through accesses like [`debug_accesses`], but redraw the contents of every set holding a block after
+/// each command, coloring the line the last access hit (green), filled (yellow) or evicted a block from (red).
+pub fn tui_accesses<R: BufRead, W: Write>(cache: &mut Cache, accesses: &[MemoryAccess], input: R, output: &mut W) -> Result<(), SimError> {
+    let mut position = 0;
+    let mut message = String::new();
+    let mut highlight = None;
+    let mut commands = input.lines();
+    loop {
+        emit(output, &render_screen(cache, position, accesses.len(), &message, highlight))?;
+        let command = match commands.next() {
+            Some(Ok(command)) => command,
+            _ => break,
+        };
+        highlight = None;
+        message = match command.split_whitespace().next() {
+            None | Some("n") | Some("next") => match accesses.get(position) {
+                Some(&access) => {
+                    let (result, landed) = step(cache, access)?;
+                    position += 1;
+                    highlight = landed;
+                    result.describe()
+                }
+                None => "end of trace".to_string(),
+            },
+            Some("b") | Some("back") => {
+                if cache.step_back() {
+                    position -= 1;
+                    let access = accesses[position];
+                    format!("undid {} {:x}", access.operation, access.address)
+                } else {
+                    "no history left to step back".to_string()
+                }
+            }
+            Some("c") | Some("continue") => {
+                for &access in &accesses[position..] {
+                    cache.simulate_access(access)?;
+                }
+                position = accesses.len();
+                "end of trace".to_string()
+            }
+            Some("q") | Some("quit") => break,
+            Some(command) => format!("unknown command: {}", command),
+        };
+    }
+    emit(output, "\n")
+}
+// Tests for debug_accesses function
+#[test]
+fn test_debug_accesses() {
+    let mut cache = CacheBuilder::new().set_index_bits(4).associativity(1).block_bits(4).undo_depth(10).build().unwrap();
+    let accesses = read_accesses("../traces/yi.trace").unwrap();
+    let input = "n\n\np 1\nb\nb\nb\nx\nc\nn\ns\nq\n";
+    let mut output = Vec::new();
+
+    assert_eq!(debug_accesses(&mut cache, &accesses, input.as_bytes(), &mut output), Ok(()));
+    let output = String::from_utf8(output).unwrap();
+    let responses: Vec<&str> = output.lines().skip(1).collect();
+    assert_eq!(responses, vec![
+        "(0/7) L 10 miss",
+        "(1/7) M 20 miss hit",
+        "(2/7) set 1: [0] tag 0 | LRU order (most recent first): 0",
+        "(2/7) undid M 20",
+        "(1/7) undid L 10",
+        "(0/7) no history left to step back",
+        "(0/7) unknown command: x",
+        DEBUG_HELP,
+        "(0/7) end of trace",
+        "(7/7) end of trace",
+        "(7/7) hits:4 misses:5 evictions:3",
+        "(7/7) ",
+    ]);
+}
+
+// Tests for step function
+#[test]
+fn test_step() {
+    let mut cache = Cache::new(1, 1, 4).unwrap();
+    let load = |address| MemoryAccess { operation: 'L', address, thread: 0, size: 1, pc: 0 };
+    let fill = Highlight { set_index: 1, way: 0, outcome: Outcome::Fill };
+    assert_eq!(step(&mut cache, load(0x10)).unwrap().1, Some(fill));
+    assert_eq!(step(&mut cache, load(0x14)).unwrap().1, Some(Highlight { outcome: Outcome::Hit, ..fill }));
+    assert_eq!(step(&mut cache, load(0x30)).unwrap().1, Some(Highlight { outcome: Outcome::Eviction(Some(0)), ..fill }));
+    let (result, highlight) = step(&mut cache, MemoryAccess { operation: 'M', address: 0x00, thread: 0, size: 1, pc: 0 }).unwrap();
+    assert_eq!(result.describe(), "M 0 miss hit");
+    assert_eq!(highlight, Some(Highlight { set_index: 0, way: 0, outcome: Outcome::Fill }));
+}
+
+// Tests for render_screen function
+#[test]
+fn test_render_screen() {
+    let mut cache = Cache::new(2, 2, 4).unwrap();
+    cache.simulate_access(MemoryAccess { operation: 'S', address: 0x10, thread: 0, size: 1, pc: 0 }).unwrap();
+    let highlight = Highlight { set_index: 1, way: 0, outcome: Outcome::Eviction(Some(0x2)) };
+    let screen = render_screen(&cache, 1, 7, "S 10 miss eviction", Some(highlight));
+    assert_eq!(screen, format!(
+        "{}access 1/7 hits:0 misses:1 evictions:0\n{}S 10 miss eviction{}\n\n{}> set 1{}: {}[0] tag 0 dirty (evicted 2){} [1] empty | LRU: 0\n  (3 empty sets not shown)\n\n{}\n> ",
+        CLEAR_SCREEN, RED, RESET, BOLD, RESET, RED, RESET, TUI_HELP
+    ));
+
+    let screen = render_screen(&cache, 1, 7, "undid L 0", None);
+    assert!(screen.contains("\nundid L 0\n\n  set 1: [0] tag 0 dirty [1] empty | LRU: 0\n"));
+}
+
+// Tests for tui_accesses function
+#[test]
+fn test_tui_accesses() {
+    let mut cache = CacheBuilder::new().set_index_bits(4).associativity(1).block_bits(4).undo_depth(10).build().unwrap();
+    let accesses = read_accesses("../traces/yi.trace").unwrap();
+    let mut output = Vec::new();
+    assert_eq!(tui_accesses(&mut cache, &accesses, "n\n\nb\nx\nc\nq\n".as_bytes(), &mut output), Ok(()));
+    let output = String::from_utf8(output).unwrap();
+    let screens: Vec<&str> = output.split(CLEAR_SCREEN).skip(1).collect();
+    assert_eq!(screens.len(), 6);
+    assert!(screens[1].starts_with(&format!("access 1/7 hits:0 misses:1 evictions:0\n{}L 10 miss{}", YELLOW, RESET)));
+    assert!(screens[2].contains(&format!("{}M 20 miss hit{}", YELLOW, RESET)));
+    assert!(screens[3].starts_with("access 1/7 hits:0 misses:1 evictions:0\nundid M 20\n"));
+    assert!(screens[4].contains("unknown command: x"));
+    assert!(screens[5].starts_with("access 7/7 hits:4 misses:5 evictions:3\nend of trace\n"));
+}
@@ -0,0 +1,132 @@
+//! Built-in experiments that generate their own accesses, for teaching without tracefiles.
+
+use crate::cache::{CacheBuilder, Stats};
+use crate::error::SimError;
+use crate::trace::MemoryAccess;
+
+/// Matrix size used by the locality demo unless another one is given.
+pub const DEFAULT_DEMO_N: usize = 512;
+
+/// Element size in bytes used by the locality demo unless another one is given.
+pub const DEFAULT_DEMO_ELEMENT_SIZE: usize = 8;
+
+// Loads of every element of an n x n row-major matrix at address 0, in row-major or column-major order
+fn traversal(n: usize, element_size: usize, column_major: bool) -> impl Iterator<Item = MemoryAccess> {
+    (0..n * n).map(move |step| {
+        let (row, column) = if column_major { (step % n, step / n) } else { (step / n, step % n) };
+        MemoryAccess { operation: 'L', address: ((row * n + column) * element_size) as u64, thread: 0, size: element_size as u32, pc: 0 }
+    })
+}
+
+// Simulate one traversal on a fresh cache
+fn run_traversal(builder: &CacheBuilder, n: usize, element_size: usize, column_major: bool) -> Result<Stats, SimError> {
+    let mut cache = builder.build()?;
+    for result in cache.drive(traversal(n, element_size, column_major)) {
+        result?;
+    }
+    Ok(cache.snapshot_stats())
+}
+
+/// Traverse an n x n matrix of element_size-byte elements row by row and column by column on caches
+/// built from the builder, and explain the difference in misses with the reuse distance of each order.
+pub fn locality_demo(builder: &CacheBuilder, n: usize, element_size: usize) -> Result<String, SimError> {
+    if n == 0 || element_size == 0 {
+        return Err(SimError::Config("matrix size and element size must be at least 1".to_string()));
+    }
+    if n.checked_mul(n).and_then(|elements| elements.checked_mul(element_size)).is_none_or(|bytes| bytes as u64 > u32::MAX as u64) {
+        return Err(SimError::Config("matrix does not fit in a 4 GiB address space".to_string()));
+    }
+    let cache = builder.build()?;
+    let (sets, associativity, block_size) = (cache.set_count(), cache.associativity(), cache.block_size());
+    let lines = sets * associativity;
+    let row_bytes = n * element_size;
+
+    let row_major = run_traversal(builder, n, element_size, false)?;
+    let column_major = run_traversal(builder, n, element_size, true)?;
+
+    // Reuse distance: distinct other blocks touched between two uses of the same block
+    let elements_per_block = (block_size / element_size).max(1);
+    let row_distance = 0;
+    let column_distance = if row_bytes >= block_size { n - 1 } else { row_distance };
+    // Sets the blocks of one column map to, and how many of those blocks they can hold
+    let mut column_sets: Vec<usize> = (0..n).map(|row| (row * row_bytes / block_size) % sets).collect();
+    column_sets.sort();
+    column_sets.dedup();
+    let column_capacity = column_sets.len() * associativity;
+
+    let accesses = n * n;
+    let miss_rate = |stats: &Stats| 100.0 * stats.misses as f64 / accesses as f64;
+    let mut report = format!(
+        "Locality demo: {0}x{0} matrix of {1}-byte elements ({2} loads per traversal)\n\
+         Cache: {3} sets x {4} lines of {5} bytes ({6} lines, {7} bytes)\n\n",
+        n, element_size, accesses, sets, associativity, block_size, lines, lines * block_size
+    );
+    report.push_str(&format!("{:<16}{:>14}{:>14}\n", "", "row-major", "column-major"));
+    report.push_str(&format!("{:<16}{:>14}{:>14}\n", "hits", row_major.hits, column_major.hits));
+    report.push_str(&format!("{:<16}{:>14}{:>14}\n", "misses", row_major.misses, column_major.misses));
+    report.push_str(&format!("{:<16}{:>14}{:>14}\n", "evictions", row_major.evictions, column_major.evictions));
+    report.push_str(&format!("{:<16}{:>13.2}%{:>13.2}%\n", "miss rate", miss_rate(&row_major), miss_rate(&column_major)));
+    report.push_str(&format!("{:<16}{:>14}{:>14}\n\n", "reuse distance", row_distance, column_distance));
+
+    report.push_str(&format!(
+        "Row-major: consecutive loads are {} bytes apart, so each {}-byte block serves {} loads in a row \
+         (reuse distance {}): only the first load of each block misses.\n",
+        element_size, block_size, elements_per_block, row_distance
+    ));
+    if column_distance == row_distance {
+        report.push_str(&format!(
+            "Column-major: a whole row fits in one block ({} bytes), so column order reuses blocks just as closely.\n",
+            row_bytes
+        ));
+    } else if column_distance < column_capacity {
+        report.push_str(&format!(
+            "Column-major: consecutive loads are {} bytes apart, so each load touches a new block, and a block is only \
+             reused one column later, after {} other blocks (reuse distance {}). A column's {} blocks map to {} of the \
+             {} sets, which hold {} lines, so they stay cached until the next column reuses them.\n",
+            row_bytes, column_distance, column_distance, n, column_sets.len(), sets, column_capacity
+        ));
+    } else {
+        report.push_str(&format!(
+            "Column-major: consecutive loads are {} bytes apart, so each load touches a new block, and a block is only \
+             reused one column later, after {} other blocks (reuse distance {}). A column's {} blocks map to {} of the \
+             {} sets, which hold only {} lines, so each block is evicted before it is reused.\n",
+            row_bytes, column_distance, column_distance, n, column_sets.len(), sets, column_capacity
+        ));
+    }
+    Ok(report)
+}
+
+// Tests for locality_demo function
+#[test]
+fn test_traversal() {
+    let addresses: Vec<u64> = traversal(2, 4, false).map(|access| access.address).collect();
+    assert_eq!(addresses, vec![0, 4, 8, 12]);
+    let addresses: Vec<u64> = traversal(2, 4, true).map(|access| access.address).collect();
+    assert_eq!(addresses, vec![0, 8, 4, 12]);
+}
+
+#[test]
+fn test_locality_demo_conflicts() {
+    let builder = CacheBuilder::new().set_index_bits(4).associativity(2).block_bits(4);
+    let report = locality_demo(&builder, 16, 8).unwrap();
+    assert!(report.contains(&format!("{:<16}{:>14}{:>14}\n", "misses", 128, 256)));
+    assert!(report.contains(&format!("{:<16}{:>14}{:>14}\n", "reuse distance", 0, 15)));
+    assert!(report.contains("map to 2 of the 16 sets, which hold only 4 lines"));
+}
+
+#[test]
+fn test_locality_demo_fits() {
+    let builder = CacheBuilder::new().set_index_bits(4).associativity(8).block_bits(4);
+    let report = locality_demo(&builder, 4, 8).unwrap();
+    assert!(report.contains(&format!("{:<16}{:>14}{:>14}\n", "misses", 8, 8)));
+    assert!(report.contains("so they stay cached until the next column reuses them"));
+}
+
+#[test]
+fn test_locality_demo_invalid() {
+    let builder = CacheBuilder::new().set_index_bits(4).associativity(2).block_bits(4);
+    assert!(locality_demo(&builder, 0, 8).is_err());
+    assert!(locality_demo(&builder, 16, 0).is_err());
+    assert!(locality_demo(&builder, 1 << 20, 8).is_err());
+    assert!(locality_demo(&builder.associativity(0), 16, 8).is_err());
+}
@@ -0,0 +1,241 @@
+//! A DRAM row-buffer model behind the last cache level, classifying the accesses that reach memory as row hits,
+//! row misses to an idle bank or row conflicts, the locality a flat memory latency hides.
+//!
+//! Addresses are interleaved a row at a time: the low bits select the byte within a row, the next ones the bank
+//! and the rest the row. Each bank has a row buffer holding its open row, which the open-row policy keeps open
+//! after an access and the closed-row policy closes.
+
+use std::fmt;
+
+use crate::error::SimError;
+
+/// What a bank does with its row buffer after an access.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum RowPolicy {
+    /// Keep the row open, so that the next access to it is a row hit and one to another row a conflict
+    #[default]
+    Open,
+    /// Close the row, so that every access opens its row in an idle bank
+    Closed,
+}
+
+impl std::str::FromStr for RowPolicy {
+    type Err = SimError;
+
+    fn from_str(value: &str) -> Result<RowPolicy, SimError> {
+        match value {
+            "open" => Ok(RowPolicy::Open),
+            "closed" => Ok(RowPolicy::Closed),
+            _ => Err(SimError::Config(format!("unknown row policy: {}", value))),
+        }
+    }
+}
+
+impl fmt::Display for RowPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            RowPolicy::Open => "open",
+            RowPolicy::Closed => "closed",
+        })
+    }
+}
+
+/// Outcome of an access to DRAM.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RowOutcome {
+    /// The row was already open in its bank
+    Hit,
+    /// The bank had no open row, so the row was opened
+    Empty,
+    /// The bank had another row open, which was closed before opening the row
+    Conflict,
+}
+
+/// Counts of the accesses a DRAM served, by outcome.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct DramStats {
+    pub row_hits: usize,
+    pub row_empty: usize,
+    pub row_conflicts: usize,
+}
+
+impl DramStats {
+    /// Number of accesses served.
+    pub fn accesses(&self) -> usize {
+        self.row_hits + self.row_empty + self.row_conflicts
+    }
+}
+
+/// Cycles each outcome of an access to DRAM takes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RowLatencies {
+    pub hit: u64,
+    pub empty: u64,
+    pub conflict: u64,
+}
+
+/// The banks of a DRAM and the rows open in their row buffers, which start idle.
+///
+/// ```
+/// use sim::{Dram, RowOutcome, RowPolicy};
+///
+/// let mut dram = Dram::new(8, 8192, RowPolicy::Open).unwrap();
+/// assert_eq!(dram.access(0x0), RowOutcome::Empty);
+/// assert_eq!(dram.access(0x40), RowOutcome::Hit);
+/// // Eight banks of 8 KiB rows later, the same bank holds the next row
+/// assert_eq!(dram.access(0x10000), RowOutcome::Conflict);
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct Dram {
+    row_bits: u32,
+    bank_bits: u32,
+    policy: RowPolicy,
+    open_rows: Vec<Option<u64>>, // Row open in each bank
+    latencies: Option<RowLatencies>,
+    stats: DramStats,
+}
+
+impl Dram {
+    /// Create a DRAM of the given number of banks and bytes per row, both powers of two.
+    pub fn new(banks: usize, row_size: u64, policy: RowPolicy) -> Result<Dram, SimError> {
+        if !banks.is_power_of_two() {
+            return Err(SimError::Config(format!("DRAM banks must be a power of two, not {}", banks)));
+        }
+        if !row_size.is_power_of_two() {
+            return Err(SimError::Config(format!("DRAM row size must be a power of two, not {}", row_size)));
+        }
+        let (row_bits, bank_bits) = (row_size.trailing_zeros(), banks.trailing_zeros());
+        if row_bits + bank_bits >= 64 {
+            return Err(SimError::Config("DRAM banks times row size must be below 2^64 bytes".to_string()));
+        }
+        Ok(Dram { row_bits, bank_bits, policy, open_rows: vec![None; banks], latencies: None, stats: DramStats::default() })
+    }
+
+    /// Give the cycles each outcome takes, for the average memory access time.
+    pub fn with_latencies(self, latencies: RowLatencies) -> Dram {
+        Dram { latencies: Some(latencies), ..self }
+    }
+
+    /// Number of banks.
+    pub fn banks(&self) -> usize {
+        self.open_rows.len()
+    }
+
+    /// Bytes per row.
+    pub fn row_size(&self) -> u64 {
+        1 << self.row_bits
+    }
+
+    /// Row buffer policy.
+    pub fn policy(&self) -> RowPolicy {
+        self.policy
+    }
+
+    /// Counts so far.
+    pub fn stats(&self) -> DramStats {
+        self.stats
+    }
+
+    /// Clear the counts, keeping the open rows.
+    pub fn reset_stats(&mut self) {
+        self.stats = DramStats::default();
+    }
+
+    /// Bank and row of an address.
+    pub fn locate(&self, address: u64) -> (usize, u64) {
+        let bank = (address >> self.row_bits) & ((1 << self.bank_bits) - 1);
+        (bank as usize, address >> (self.row_bits + self.bank_bits))
+    }
+
+    /// Access the row holding an address.
+    pub fn access(&mut self, address: u64) -> RowOutcome {
+        let (bank, row) = self.locate(address);
+        let outcome = match self.open_rows[bank] {
+            Some(open) if open == row => RowOutcome::Hit,
+            Some(_) => RowOutcome::Conflict,
+            None => RowOutcome::Empty,
+        };
+        match outcome {
+            RowOutcome::Hit => self.stats.row_hits += 1,
+            RowOutcome::Empty => self.stats.row_empty += 1,
+            RowOutcome::Conflict => self.stats.row_conflicts += 1,
+        }
+        self.open_rows[bank] = match self.policy {
+            RowPolicy::Open => Some(row),
+            RowPolicy::Closed => None,
+        };
+        outcome
+    }
+
+    /// Cycles the accesses so far took, if the latencies are known.
+    pub fn cycles(&self) -> Option<u64> {
+        let latencies = self.latencies?;
+        Some(latencies.hit * self.stats.row_hits as u64 + latencies.empty * self.stats.row_empty as u64 + latencies.conflict * self.stats.row_conflicts as u64)
+    }
+
+    /// Format the counts on one line, with the share of accesses that hit an open row.
+    pub fn format_stats(&self) -> String {
+        let accesses = self.stats.accesses();
+        let row_hit_rate = if accesses == 0 { 0.0 } else { 100.0 * self.stats.row_hits as f64 / accesses as f64 };
+        format!(
+            "accesses:{} row-hits:{} row-empty:{} row-conflicts:{} row-hit-rate:{:.2}%",
+            accesses, self.stats.row_hits, self.stats.row_empty, self.stats.row_conflicts, row_hit_rate
+        )
+    }
+}
+
+// Tests for RowPolicy
+#[test]
+fn test_parse_row_policy() {
+    for policy in [RowPolicy::Open, RowPolicy::Closed] {
+        assert_eq!(policy.to_string().parse(), Ok(policy));
+    }
+    assert_eq!("adaptive".parse::<RowPolicy>(), Err(SimError::Config("unknown row policy: adaptive".to_string())));
+}
+
+// Tests for Dram
+#[test]
+fn test_dram_open_rows() {
+    let mut dram = Dram::new(4, 1024, RowPolicy::Open).unwrap();
+    assert_eq!(dram.locate(0x1234), (0, 1));
+    assert_eq!(dram.locate(0x0c00), (3, 0));
+    let outcomes: Vec<RowOutcome> = [0x0, 0x3ff, 0x400, 0x1000, 0x0, 0x404].iter().map(|&address| dram.access(address)).collect();
+    assert_eq!(outcomes, vec![RowOutcome::Empty, RowOutcome::Hit, RowOutcome::Empty, RowOutcome::Conflict, RowOutcome::Conflict, RowOutcome::Hit]);
+    assert_eq!(dram.stats(), DramStats { row_hits: 2, row_empty: 2, row_conflicts: 2 });
+    assert_eq!(dram.cycles(), None);
+
+    dram.reset_stats();
+    assert_eq!(dram.stats().accesses(), 0);
+    // Resetting the counts keeps the rows open
+    assert_eq!(dram.access(0x0), RowOutcome::Hit);
+}
+
+#[test]
+fn test_dram_closed_rows() {
+    let mut dram = Dram::new(4, 1024, RowPolicy::Closed).unwrap().with_latencies(RowLatencies { hit: 20, empty: 40, conflict: 60 });
+    for address in [0x0, 0x8, 0x1000] {
+        assert_eq!(dram.access(address), RowOutcome::Empty);
+    }
+    assert_eq!(dram.cycles(), Some(120));
+}
+
+#[test]
+fn test_dram_errors() {
+    assert!(matches!(Dram::new(3, 1024, RowPolicy::Open), Err(SimError::Config(_))));
+    assert!(matches!(Dram::new(0, 1024, RowPolicy::Open), Err(SimError::Config(_))));
+    assert!(matches!(Dram::new(4, 1000, RowPolicy::Open), Err(SimError::Config(_))));
+    assert!(matches!(Dram::new(1 << 10, 1 << 54, RowPolicy::Open), Err(SimError::Config(_))));
+    let dram = Dram::new(1, 1 << 13, RowPolicy::Open).unwrap();
+    assert_eq!((dram.banks(), dram.row_size(), dram.locate(0x4000)), (1, 8192, (0, 2)));
+}
+
+// Tests for Dram::format_stats
+#[test]
+fn test_format_dram_stats() {
+    let mut dram = Dram::new(2, 64, RowPolicy::Open).unwrap();
+    assert_eq!(dram.format_stats(), "accesses:0 row-hits:0 row-empty:0 row-conflicts:0 row-hit-rate:0.00%");
+    for address in [0x0, 0x8, 0x10, 0x80] {
+        dram.access(address);
+    }
+    assert_eq!(dram.format_stats(), "accesses:4 row-hits:2 row-empty:1 row-conflicts:1 row-hit-rate:50.00%");
+}
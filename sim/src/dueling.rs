@@ -0,0 +1,159 @@
+//! Set dueling: a few leader sets of a cache always use one of two policies, and a saturating policy-selection
+//! counter (PSEL) comparing their misses picks the policy of all the other, follower, sets.
+//!
+//! The sets are split into as many constituencies as there are leaders of each policy, and each constituency has one
+//! leader of each policy, chosen by complementary offsets so the leaders spread over the index bits. Any adaptive
+//! policy choosing between two behaviours per set can duel with it.
+
+use serde::{Deserialize, Serialize};
+
+/// Leader sets of each policy in caches with enough sets.
+pub const DUEL_LEADERS: usize = 32;
+
+/// Bits of the policy-selection counter.
+pub const PSEL_BITS: u32 = 10;
+
+/// Part a set plays in set dueling.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DuelRole {
+    /// Always uses the first policy
+    FirstLeader,
+    /// Always uses the second policy
+    SecondLeader,
+    /// Uses the policy the counter picks
+    Follower,
+}
+
+/// Leader sets and policy-selection counter of two dueling policies, with the misses of the leaders and the number
+/// of times the followers switched policy.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SetDueling {
+    sets: usize,
+    constituency: usize, // Sets sharing one leader of each policy
+    psel: u32, // Raised by misses in the first policy's leaders, lowered by misses in the second's
+    psel_max: u32,
+    leader_misses: [usize; 2],
+    switches: usize,
+}
+
+impl SetDueling {
+    /// Duel over a power-of-two number of sets, with up to leaders leader sets of each policy and a counter of
+    /// psel_bits bits. Small caches get one leader of each policy per four sets, and caches of fewer than four sets
+    /// have no followers. The followers start with the first policy.
+    pub fn new(sets: usize, leaders: usize, psel_bits: u32) -> SetDueling {
+        let leaders = leaders.min(sets / 4).max(1);
+        let psel_max = (1 << psel_bits) - 1;
+        SetDueling { sets, constituency: (sets / leaders).max(1), psel: psel_max / 2, psel_max, leader_misses: [0; 2], switches: 0 }
+    }
+
+    /// Part a set plays: in each constituency, the set at the constituency's number is the first policy's leader and
+    /// the set at its complement the second's.
+    pub fn role(&self, set_index: usize) -> DuelRole {
+        let (number, offset) = (set_index / self.constituency % self.constituency, set_index % self.constituency);
+        if offset == number {
+            DuelRole::FirstLeader
+        } else if offset == self.constituency - 1 - number {
+            DuelRole::SecondLeader
+        } else {
+            DuelRole::Follower
+        }
+    }
+
+    /// Policy a set uses: 0 for the first, 1 for the second.
+    pub fn policy(&self, set_index: usize) -> usize {
+        match self.role(set_index) {
+            DuelRole::FirstLeader => 0,
+            DuelRole::SecondLeader => 1,
+            DuelRole::Follower => self.winner(),
+        }
+    }
+
+    /// Policy the followers use, the one whose leaders missed less lately: 0 for the first, 1 for the second.
+    pub fn winner(&self) -> usize {
+        usize::from(self.psel > self.psel_max / 2)
+    }
+
+    /// Count a miss in a set, moving the counter if the set is a leader.
+    pub fn record_miss(&mut self, set_index: usize) {
+        let winner = self.winner();
+        match self.role(set_index) {
+            DuelRole::FirstLeader => {
+                self.leader_misses[0] += 1;
+                self.psel = (self.psel + 1).min(self.psel_max);
+            }
+            DuelRole::SecondLeader => {
+                self.leader_misses[1] += 1;
+                self.psel = self.psel.saturating_sub(1);
+            }
+            DuelRole::Follower => return,
+        }
+        if self.winner() != winner {
+            self.switches += 1;
+        }
+    }
+
+    /// Number of sets dueling.
+    pub fn sets(&self) -> usize {
+        self.sets
+    }
+
+    /// Current value of the policy-selection counter.
+    pub fn psel(&self) -> u32 {
+        self.psel
+    }
+
+    /// Misses in the leaders of the first and second policies.
+    pub fn leader_misses(&self) -> [usize; 2] {
+        self.leader_misses
+    }
+
+    /// Times the followers changed policy.
+    pub fn switches(&self) -> usize {
+        self.switches
+    }
+
+    /// Zero the miss and switch counts, keeping the counter and so the policy the followers use.
+    pub fn reset_stats(&mut self) {
+        self.leader_misses = [0; 2];
+        self.switches = 0;
+    }
+}
+
+// Tests for SetDueling::role
+#[test]
+fn test_set_dueling_roles() {
+    // 256 sets: 32 constituencies of 8 sets
+    let dueling = SetDueling::new(256, DUEL_LEADERS, PSEL_BITS);
+    let roles: Vec<DuelRole> = (0..256).map(|set| dueling.role(set)).collect();
+    assert_eq!(roles.iter().filter(|&&role| role == DuelRole::FirstLeader).count(), 32);
+    assert_eq!(roles.iter().filter(|&&role| role == DuelRole::SecondLeader).count(), 32);
+    assert_eq!((dueling.role(0), dueling.role(7), dueling.role(9), dueling.role(14)), (DuelRole::FirstLeader, DuelRole::SecondLeader, DuelRole::FirstLeader, DuelRole::SecondLeader));
+    assert_eq!(dueling.role(1), DuelRole::Follower);
+
+    // Small caches get a leader of each policy per four sets, and tiny ones have no followers
+    let dueling = SetDueling::new(16, DUEL_LEADERS, PSEL_BITS);
+    assert_eq!((0..16).filter(|&set| dueling.role(set) == DuelRole::Follower).count(), 8);
+    let dueling = SetDueling::new(2, DUEL_LEADERS, PSEL_BITS);
+    assert_eq!((dueling.role(0), dueling.role(1)), (DuelRole::FirstLeader, DuelRole::SecondLeader));
+    assert_eq!(SetDueling::new(1, DUEL_LEADERS, PSEL_BITS).role(0), DuelRole::FirstLeader);
+}
+
+// Tests for SetDueling::record_miss
+#[test]
+fn test_set_dueling_counter() {
+    let mut dueling = SetDueling::new(8, 1, 2);
+    assert_eq!((dueling.psel(), dueling.winner(), dueling.policy(1)), (1, 0, 0));
+    // Misses in the first policy's leader hand the followers to the second policy, saturating the counter
+    for _ in 0..4 {
+        dueling.record_miss(0);
+    }
+    dueling.record_miss(1);
+    assert_eq!((dueling.psel(), dueling.winner(), dueling.policy(1), dueling.policy(0)), (3, 1, 1, 0));
+    assert_eq!((dueling.leader_misses(), dueling.switches()), ([4, 0], 1));
+    for _ in 0..2 {
+        dueling.record_miss(7);
+    }
+    assert_eq!((dueling.psel(), dueling.winner(), dueling.switches()), (1, 0, 2));
+    dueling.reset_stats();
+    assert_eq!((dueling.psel(), dueling.leader_misses(), dueling.switches()), (1, [0, 0], 0));
+}
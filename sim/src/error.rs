@@ -0,0 +1,66 @@
+//! Error type of the simulator library.
+
+use std::fmt;
+
+/// What went wrong, by kind, so callers can match on it rather than on the message.
+#[derive(Debug, Clone, PartialEq)]
+pub enum SimError {
+    /// Invalid command-line arguments
+    ParseArgs(String),
+    /// A tracefile that can't be opened or read
+    TraceRead(String),
+    /// A malformed line, or record, in a tracefile
+    Trace(String),
+    /// An invalid cache configuration
+    Config(String),
+    /// An access the cache can't simulate
+    Access(String),
+    /// A failure reading debugger commands or writing output
+    Io(String),
+}
+
+impl SimError {
+    /// The message without the kind of error.
+    pub fn message(&self) -> &str {
+        match self {
+            SimError::ParseArgs(message)
+            | SimError::TraceRead(message)
+            | SimError::Trace(message)
+            | SimError::Config(message)
+            | SimError::Access(message)
+            | SimError::Io(message) => message,
+        }
+    }
+
+    /// The kind of error as a short name, such as "trace-parse", for machine-readable reports.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            SimError::ParseArgs(_) => "args",
+            SimError::TraceRead(_) => "trace-read",
+            SimError::Trace(_) => "trace-parse",
+            SimError::Config(_) => "config",
+            SimError::Access(_) => "access",
+            SimError::Io(_) => "io",
+        }
+    }
+}
+
+impl fmt::Display for SimError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.message())
+    }
+}
+
+impl std::error::Error for SimError {}
+
+// Tests for SimError
+#[test]
+fn test_sim_error() {
+    let err = SimError::Config("bank count must be a power of two".to_string());
+    assert_eq!(err.message(), "bank count must be a power of two");
+    assert_eq!(err.to_string(), "bank count must be a power of two");
+    assert_eq!(err.kind(), "config");
+    assert_eq!(SimError::TraceRead("No such file or directory".to_string()).kind(), "trace-read");
+    let err: Box<dyn std::error::Error> = Box::new(SimError::Trace("invalid memory access format".to_string()));
+    assert_eq!(err.to_string(), "invalid memory access format");
+}
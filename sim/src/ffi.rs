@@ -0,0 +1,155 @@
+//! C interface for embedding the simulator, e.g. in a Pin tool that feeds accesses as they happen instead of
+//! writing a trace. `include/sim.h` declares these functions; link against the cdylib or staticlib build.
+//!
+//! A cache is an opaque pointer from `sim_cache_new`, released with `sim_cache_free`. Functions that can fail
+//! return `SIM_OK` or a negative error code.
+
+use std::os::raw::{c_char, c_int};
+
+use crate::cache::{Cache, CacheBuilder, Op};
+
+/// Version of the C interface, raised whenever a function or struct of it changes.
+pub const SIM_ABI_VERSION: u32 = 1;
+
+/// The call succeeded.
+pub const SIM_OK: c_int = 0;
+
+/// A pointer argument was null.
+pub const SIM_ERR_NULL: c_int = -1;
+
+/// The operation isn't 'L', 'S', 'M', 'A', 'P', 'F', 'W', 'K' or 'U', or the cache can't simulate the access.
+pub const SIM_ERR_ACCESS: c_int = -2;
+
+/// Hits, misses and evictions, of one access or of a whole run.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct SimCounts {
+    pub hits: u64,
+    pub misses: u64,
+    pub evictions: u64,
+}
+
+/// Version of the C interface the library implements.
+#[no_mangle]
+pub extern "C" fn sim_abi_version() -> u32 {
+    SIM_ABI_VERSION
+}
+
+/// Create an empty cache of 2^s sets of e lines of 2^b bytes, or return null if the configuration is invalid.
+#[no_mangle]
+pub extern "C" fn sim_cache_new(s: u32, e: u32, b: u32) -> *mut Cache {
+    match CacheBuilder::new().set_index_bits(s as usize).associativity(e as usize).block_bits(b as usize).build() {
+        Ok(cache) => Box::into_raw(Box::new(cache)),
+        Err(_) => std::ptr::null_mut(),
+    }
+}
+
+/// Release a cache. Null is ignored.
+///
+/// # Safety
+///
+/// `cache` must be null or a pointer returned by `sim_cache_new` that hasn't been freed yet.
+#[no_mangle]
+pub unsafe extern "C" fn sim_cache_free(cache: *mut Cache) {
+    if !cache.is_null() {
+        drop(Box::from_raw(cache));
+    }
+}
+
+/// Simulate an access of `size` bytes, storing its hits, misses and evictions in `result` unless it is null.
+///
+/// # Safety
+///
+/// `cache` must be a live pointer from `sim_cache_new`, and `result` null or valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn sim_cache_access(cache: *mut Cache, operation: c_char, address: u64, size: u32, result: *mut SimCounts) -> c_int {
+    let cache = match cache.as_mut() {
+        Some(cache) => cache,
+        None => return SIM_ERR_NULL,
+    };
+    let op = match Op::try_from(operation as u8 as char) {
+        Ok(op) => op,
+        Err(_) => return SIM_ERR_ACCESS,
+    };
+    match cache.access(op, address, size) {
+        Ok(access) => {
+            if let Some(result) = result.as_mut() {
+                *result = SimCounts { hits: access.hits as u64, misses: access.misses as u64, evictions: access.evictions as u64 };
+            }
+            SIM_OK
+        }
+        Err(_) => SIM_ERR_ACCESS,
+    }
+}
+
+/// Store the hits, misses and evictions so far in `stats`.
+///
+/// # Safety
+///
+/// `cache` must be a live pointer from `sim_cache_new`, and `stats` valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn sim_cache_stats(cache: *const Cache, stats: *mut SimCounts) -> c_int {
+    match (cache.as_ref(), stats.as_mut()) {
+        (Some(cache), Some(stats)) => {
+            *stats = SimCounts { hits: cache.hits() as u64, misses: cache.misses() as u64, evictions: cache.evictions() as u64 };
+            SIM_OK
+        }
+        _ => SIM_ERR_NULL,
+    }
+}
+
+/// Clear the statistics, keeping the cache contents, e.g. at the end of a warm-up.
+///
+/// # Safety
+///
+/// `cache` must be null or a live pointer from `sim_cache_new`.
+#[no_mangle]
+pub unsafe extern "C" fn sim_cache_reset_stats(cache: *mut Cache) -> c_int {
+    match cache.as_mut() {
+        Some(cache) => {
+            cache.reset_stats();
+            SIM_OK
+        }
+        None => SIM_ERR_NULL,
+    }
+}
+
+// Tests for the C interface
+#[test]
+fn test_ffi_cache() {
+    assert_eq!(sim_abi_version(), SIM_ABI_VERSION);
+    let cache = sim_cache_new(4, 1, 4);
+    assert!(!cache.is_null());
+    let mut result = SimCounts::default();
+    unsafe {
+        assert_eq!(sim_cache_access(cache, b'L' as c_char, 0x10, 1, &mut result), SIM_OK);
+        assert_eq!(result, SimCounts { hits: 0, misses: 1, evictions: 0 });
+        assert_eq!(sim_cache_access(cache, b'M' as c_char, 0x110, 1, &mut result), SIM_OK);
+        assert_eq!(result, SimCounts { hits: 1, misses: 1, evictions: 1 });
+        assert_eq!(sim_cache_access(cache, b'S' as c_char, 0x110, 1, std::ptr::null_mut()), SIM_OK);
+        assert_eq!(sim_cache_access(cache, b'X' as c_char, 0x10, 1, &mut result), SIM_ERR_ACCESS);
+
+        let mut stats = SimCounts::default();
+        assert_eq!(sim_cache_stats(cache, &mut stats), SIM_OK);
+        assert_eq!(stats, SimCounts { hits: 2, misses: 2, evictions: 1 });
+        assert_eq!(sim_cache_reset_stats(cache), SIM_OK);
+        assert_eq!(sim_cache_stats(cache, &mut stats), SIM_OK);
+        assert_eq!(stats, SimCounts::default());
+        sim_cache_free(cache);
+    }
+}
+
+#[test]
+fn test_ffi_errors() {
+    assert!(sim_cache_new(4, 0, 4).is_null());
+    let mut stats = SimCounts::default();
+    unsafe {
+        assert_eq!(sim_cache_access(std::ptr::null_mut(), b'L' as c_char, 0, 1, &mut stats), SIM_ERR_NULL);
+        assert_eq!(sim_cache_stats(std::ptr::null(), &mut stats), SIM_ERR_NULL);
+        assert_eq!(sim_cache_reset_stats(std::ptr::null_mut()), SIM_ERR_NULL);
+        let cache = sim_cache_new(4, 1, 4);
+        assert_eq!(sim_cache_stats(cache, std::ptr::null_mut()), SIM_ERR_NULL);
+        sim_cache_free(cache);
+        sim_cache_free(std::ptr::null_mut());
+    }
+}
@@ -0,0 +1,364 @@
+//! Synthetic traces with simple access patterns and matrix kernels, for testing policies and teaching without
+//! Valgrind.
+
+use std::ops::Range;
+
+use crate::error::SimError;
+use crate::rng::Rng;
+use crate::trace::MemoryAccess;
+
+/// Access size in bytes of generated traces unless another one is given.
+pub const DEFAULT_GEN_ACCESS_SIZE: u32 = 8;
+
+/// Order in which a generated trace visits its footprint.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Pattern {
+    /// Consecutive accesses, wrapping around at the end of the footprint
+    Sequential,
+    /// Accesses to uniformly random aligned addresses in the footprint
+    Random,
+    /// Accesses stride bytes apart, wrapping around at the end of the footprint
+    Strided,
+    /// A walk along a random cycle through every node of the footprint, as a linked list would be traversed.
+    /// Nodes are stride bytes apart, or access_size bytes if stride is 0. Each address depends on the previous
+    /// one, so no prefetcher can anticipate it and only the footprint decides the hit rate
+    PointerChase,
+}
+
+impl std::str::FromStr for Pattern {
+    type Err = SimError;
+
+    fn from_str(value: &str) -> Result<Pattern, SimError> {
+        match value {
+            "sequential" => Ok(Pattern::Sequential),
+            "random" => Ok(Pattern::Random),
+            "strided" => Ok(Pattern::Strided),
+            "pointer-chase" => Ok(Pattern::PointerChase),
+            _ => Err(SimError::Config(format!("unknown access pattern: {}", value))),
+        }
+    }
+}
+
+/// Shape of a synthetic trace: length accesses of access_size bytes within footprint bytes from address 0,
+/// a write_ratio share of them stores and the rest loads.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Generator {
+    pub pattern: Pattern,
+    pub footprint: u64,
+    pub length: usize,
+    pub access_size: u32,
+    /// Bytes between accesses of the strided pattern, or between the nodes of a pointer chase
+    pub stride: u64,
+    pub write_ratio: f64,
+    /// Seed of the random addresses and of the choice between loads and stores
+    pub seed: u64,
+}
+
+impl Generator {
+    /// Check that the accesses fit in the footprint and the write ratio is a share.
+    pub fn validate(&self) -> Result<(), SimError> {
+        if self.access_size == 0 || self.footprint < self.access_size as u64 {
+            return Err(SimError::Config("footprint must hold at least one access of at least 1 byte".to_string()));
+        }
+        if self.pattern == Pattern::Strided && self.stride == 0 {
+            return Err(SimError::Config("stride must be at least 1 byte".to_string()));
+        }
+        if self.pattern == Pattern::PointerChase && self.stride != 0 && (self.stride < self.access_size as u64 || self.footprint < self.stride) {
+            return Err(SimError::Config("pointer-chase nodes must hold an access and fit in the footprint".to_string()));
+        }
+        if !(0.0..=1.0).contains(&self.write_ratio) {
+            return Err(SimError::Config("write ratio must be between 0 and 1".to_string()));
+        }
+        Ok(())
+    }
+
+    /// Generate the accesses lazily.
+    pub fn accesses(&self) -> Result<impl Iterator<Item = MemoryAccess>, SimError> {
+        self.validate()?;
+        let generator = *self;
+        let size = generator.access_size as u64;
+        let slots = generator.footprint / size;
+        let mut rng = Rng::new(generator.seed);
+        let (spacing, chain) = match generator.pattern {
+            Pattern::PointerChase => {
+                let spacing = if generator.stride == 0 { size } else { generator.stride };
+                (spacing, random_cycle((generator.footprint / spacing) as usize, &mut rng))
+            }
+            _ => (size, Vec::new()),
+        };
+        let mut node = 0;
+        Ok((0..generator.length as u64).map(move |step| {
+            // Addresses are aligned to the access size, so that every access stays inside the footprint
+            let slot = match generator.pattern {
+                Pattern::Sequential => step % slots,
+                Pattern::Random => rng.next_below(slots as usize) as u64,
+                Pattern::Strided => step.wrapping_mul(generator.stride) % (slots * size) / size,
+                Pattern::PointerChase => {
+                    let current = node;
+                    node = chain[node];
+                    current as u64
+                }
+            };
+            let address = slot * spacing;
+            let store = generator.write_ratio > 0.0 && (rng.next_u64() as f64 / u64::MAX as f64) < generator.write_ratio;
+            MemoryAccess { operation: if store { 'S' } else { 'L' }, address, thread: 0, size: generator.access_size, pc: 0 }
+        }))
+    }
+}
+
+// Successor of every node of a single random cycle through n nodes: the nodes are shuffled and linked in order
+fn random_cycle(n: usize, rng: &mut Rng) -> Vec<usize> {
+    let mut order: Vec<usize> = (0..n).collect();
+    for i in (1..n).rev() {
+        order.swap(i, rng.next_below(i + 1));
+    }
+    let mut next = vec![0; n];
+    for (i, &node) in order.iter().enumerate() {
+        next[node] = order[(i + 1) % n];
+    }
+    next
+}
+
+/// Matrix computation whose accesses a [`MatrixKernel`] generates.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Kernel {
+    /// B = A transposed: each element is loaded from A and stored into B
+    Transpose,
+    /// C = A x B in i, j, k order: C[i][j] is loaded, the products of row i of A and column j of B are
+    /// accumulated, and C[i][j] is stored
+    Multiply,
+}
+
+impl std::str::FromStr for Kernel {
+    type Err = SimError;
+
+    fn from_str(value: &str) -> Result<Kernel, SimError> {
+        match value {
+            "transpose" => Ok(Kernel::Transpose),
+            "multiply" => Ok(Kernel::Multiply),
+            _ => Err(SimError::Config(format!("unknown matrix kernel: {}", value))),
+        }
+    }
+}
+
+/// Accesses of a kernel over n x n row-major matrices of element_size-byte elements, laid out one after the
+/// other from address 0 (A, then B, then C). With a block factor the loops are tiled into block x block tiles,
+/// as in cachelab's blocked transpose; without one the kernel is naive.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MatrixKernel {
+    pub kernel: Kernel,
+    pub n: usize,
+    pub block: Option<usize>,
+    pub element_size: u32,
+}
+
+impl MatrixKernel {
+    /// Check that the matrices aren't empty and the tiles fit in them.
+    pub fn validate(&self) -> Result<(), SimError> {
+        if self.n == 0 || self.element_size == 0 {
+            return Err(SimError::Config("matrix size and element size must be at least 1".to_string()));
+        }
+        if self.block.is_some_and(|block| block == 0 || block > self.n) {
+            return Err(SimError::Config("block factor must be between 1 and the matrix size".to_string()));
+        }
+        Ok(())
+    }
+
+    /// Generate the accesses lazily.
+    pub fn accesses(&self) -> Result<Box<dyn Iterator<Item = MemoryAccess>>, SimError> {
+        self.validate()?;
+        let MatrixKernel { kernel, n, block, element_size } = *self;
+        // A naive kernel is a blocked one with a single tile
+        let block = block.unwrap_or(n);
+        let element = move |matrix: usize, row: usize, column: usize, operation: char| MemoryAccess {
+            operation,
+            address: ((matrix * n * n + row * n + column) * element_size as usize) as u64,
+            thread: 0,
+            size: element_size,
+            pc: 0,
+        };
+        let (a, b, c) = (0, 1, 2);
+        Ok(match kernel {
+            Kernel::Transpose => Box::new(tiles(n, block).flat_map(move |rows| {
+                tiles(n, block).flat_map(move |columns| {
+                    rows.clone().flat_map(move |i| columns.clone().flat_map(move |j| [element(a, i, j, 'L'), element(b, j, i, 'S')]))
+                })
+            })),
+            Kernel::Multiply => Box::new(tiles(n, block).flat_map(move |rows| {
+                tiles(n, block).flat_map(move |columns| {
+                    let rows = rows.clone();
+                    tiles(n, block).flat_map(move |inner| {
+                        let columns = columns.clone();
+                        rows.clone().flat_map(move |i| {
+                            let inner = inner.clone();
+                            columns.clone().flat_map(move |j| {
+                                let products = inner.clone().flat_map(move |k| [element(a, i, k, 'L'), element(b, k, j, 'L')]);
+                                std::iter::once(element(c, i, j, 'L')).chain(products).chain(std::iter::once(element(c, i, j, 'S')))
+                            })
+                        })
+                    })
+                })
+            })),
+        })
+    }
+}
+
+// Index ranges of the tiles along one dimension of an n x n matrix
+fn tiles(n: usize, block: usize) -> impl Iterator<Item = Range<usize>> {
+    (0..n).step_by(block).map(move |start| start..(start + block).min(n))
+}
+
+#[cfg(test)]
+fn generator(pattern: Pattern) -> Generator {
+    Generator { pattern, footprint: 64, length: 10, access_size: 8, stride: 24, write_ratio: 0.0, seed: 1 }
+}
+
+#[cfg(test)]
+fn addresses(generator: Generator) -> Vec<u64> {
+    generator.accesses().unwrap().map(|access| access.address).collect()
+}
+
+// Tests for Pattern
+#[test]
+fn test_pattern_from_str() {
+    assert_eq!("sequential".parse(), Ok(Pattern::Sequential));
+    assert_eq!("random".parse(), Ok(Pattern::Random));
+    assert_eq!("strided".parse(), Ok(Pattern::Strided));
+    assert_eq!("pointer-chase".parse(), Ok(Pattern::PointerChase));
+    assert_eq!("zigzag".parse::<Pattern>(), Err(SimError::Config("unknown access pattern: zigzag".to_string())));
+}
+
+// Tests for Generator
+#[test]
+fn test_generator_sequential() {
+    assert_eq!(addresses(generator(Pattern::Sequential)), vec![0, 8, 16, 24, 32, 40, 48, 56, 0, 8]);
+    let accesses: Vec<MemoryAccess> = generator(Pattern::Sequential).accesses().unwrap().collect();
+    assert!(accesses.iter().all(|access| access.operation == 'L' && access.size == 8));
+}
+
+#[test]
+fn test_generator_strided() {
+    assert_eq!(addresses(generator(Pattern::Strided)), vec![0, 24, 48, 8, 32, 56, 16, 40, 0, 24]);
+    assert_eq!(addresses(Generator { stride: 12, ..generator(Pattern::Strided) }), vec![0, 8, 24, 32, 48, 56, 8, 16, 32, 40]);
+}
+
+#[test]
+fn test_generator_random() {
+    let random = generator(Pattern::Random);
+    let first = addresses(random);
+    assert_eq!(first, addresses(random));
+    assert_ne!(first, addresses(Generator { seed: 2, ..random }));
+    assert!(first.iter().all(|&address| address < 64 && address % 8 == 0));
+}
+
+#[test]
+fn test_generator_pointer_chase() {
+    // Every node is visited once per lap, in the same order each lap
+    let chase = Generator { length: 16, stride: 0, ..generator(Pattern::PointerChase) };
+    let walk = addresses(chase);
+    assert_eq!(walk[0], 0);
+    let mut lap = walk[..8].to_vec();
+    lap.sort();
+    assert_eq!(lap, vec![0, 8, 16, 24, 32, 40, 48, 56]);
+    assert_eq!(walk[..8], walk[8..]);
+    assert_ne!(walk[..8], [0, 8, 16, 24, 32, 40, 48, 56]);
+
+    let spaced = Generator { footprint: 256, stride: 64, ..chase };
+    assert!(addresses(spaced).iter().all(|&address| address % 64 == 0 && address < 256));
+    assert!(Generator { stride: 4, ..chase }.validate().is_err());
+    assert!(Generator { stride: 128, ..chase }.validate().is_err());
+}
+
+// Tests for random_cycle function
+#[test]
+fn test_random_cycle() {
+    let mut rng = Rng::new(3);
+    for n in [1, 2, 10, 1000] {
+        let next = random_cycle(n, &mut rng);
+        let mut node = 0;
+        let mut seen = vec![false; n];
+        for _ in 0..n {
+            assert!(!seen[node]);
+            seen[node] = true;
+            node = next[node];
+        }
+        assert_eq!(node, 0);
+    }
+}
+
+#[test]
+fn test_generator_write_ratio() {
+    let count_stores = |write_ratio| {
+        Generator { length: 1000, write_ratio, ..generator(Pattern::Sequential) }.accesses().unwrap()
+            .filter(|access| access.operation == 'S').count()
+    };
+    assert_eq!(count_stores(0.0), 0);
+    assert_eq!(count_stores(1.0), 1000);
+    assert!((250..350).contains(&count_stores(0.3)));
+}
+
+#[test]
+fn test_generator_validate() {
+    assert!(Generator { footprint: 4, ..generator(Pattern::Sequential) }.validate().is_err());
+    assert!(Generator { access_size: 0, ..generator(Pattern::Sequential) }.validate().is_err());
+    assert!(Generator { stride: 0, ..generator(Pattern::Strided) }.validate().is_err());
+    assert!(Generator { stride: 0, ..generator(Pattern::Random) }.validate().is_ok());
+    assert!(Generator { write_ratio: 1.5, ..generator(Pattern::Random) }.validate().is_err());
+    assert!(Generator { write_ratio: -0.1, ..generator(Pattern::Random) }.accesses().is_err());
+}
+
+// Tests for Kernel
+#[test]
+fn test_kernel_from_str() {
+    assert_eq!("transpose".parse(), Ok(Kernel::Transpose));
+    assert_eq!("multiply".parse(), Ok(Kernel::Multiply));
+    assert_eq!("lu".parse::<Kernel>(), Err(SimError::Config("unknown matrix kernel: lu".to_string())));
+}
+
+// Tests for MatrixKernel
+#[cfg(test)]
+fn kernel_trace(kernel: MatrixKernel) -> Vec<(char, u64)> {
+    kernel.accesses().unwrap().map(|access| (access.operation, access.address)).collect()
+}
+
+#[test]
+fn test_matrix_kernel_transpose() {
+    let naive = MatrixKernel { kernel: Kernel::Transpose, n: 2, block: None, element_size: 4 };
+    assert_eq!(kernel_trace(naive), vec![('L', 0), ('S', 16), ('L', 4), ('S', 24), ('L', 8), ('S', 20), ('L', 12), ('S', 28)]);
+
+    let naive = MatrixKernel { n: 4, ..naive };
+    let blocked = MatrixKernel { block: Some(2), ..naive };
+    let (naive, blocked) = (kernel_trace(naive), kernel_trace(blocked));
+    assert_eq!(blocked.len(), 32);
+    assert_eq!(blocked[..8], [('L', 0), ('S', 64), ('L', 4), ('S', 80), ('L', 16), ('S', 68), ('L', 20), ('S', 84)]);
+    let (mut naive_sorted, mut blocked_sorted) = (naive.clone(), blocked.clone());
+    naive_sorted.sort();
+    blocked_sorted.sort();
+    assert_eq!(naive_sorted, blocked_sorted);
+    assert_ne!(naive, blocked);
+}
+
+#[test]
+fn test_matrix_kernel_multiply() {
+    let naive = MatrixKernel { kernel: Kernel::Multiply, n: 2, block: None, element_size: 8 };
+    let trace = kernel_trace(naive);
+    assert_eq!(trace.len(), 4 * (2 + 2 * 2));
+    // C[0][0] = A[0][0] * B[0][0] + A[0][1] * B[1][0]
+    assert_eq!(trace[..6], [('L', 64), ('L', 0), ('L', 32), ('L', 8), ('L', 48), ('S', 64)]);
+
+    // Tiling the k loop loads and stores C once per tile
+    let blocked = MatrixKernel { n: 4, block: Some(2), ..naive };
+    let trace = kernel_trace(blocked);
+    assert_eq!(trace.len(), 16 * 2 * (2 + 2 * 2));
+    assert_eq!(trace.iter().filter(|(operation, _)| *operation == 'S').count(), 32);
+}
+
+#[test]
+fn test_matrix_kernel_validate() {
+    let kernel = MatrixKernel { kernel: Kernel::Transpose, n: 4, block: Some(2), element_size: 4 };
+    assert!(kernel.validate().is_ok());
+    assert!(MatrixKernel { n: 0, ..kernel }.validate().is_err());
+    assert!(MatrixKernel { element_size: 0, ..kernel }.validate().is_err());
+    assert!(MatrixKernel { block: Some(0), ..kernel }.validate().is_err());
+    assert!(MatrixKernel { block: Some(5), ..kernel }.accesses().is_err());
+}
@@ -0,0 +1,324 @@
+//! Hawkeye replacement: OPTgen replays the accesses of a few sampled sets to work out which of them Belady's
+//! optimal policy would have hit, and a predictor learns from it which instructions load lines worth keeping.
+//!
+//! OPTgen keeps, for each sampled set, the occupancy of the set by lines OPT would still be holding at each of its
+//! recent accesses. When a block comes back within the window, OPT would have kept it if the set had room at every
+//! access in between, which then counts the block as occupying them. The instruction that last loaded the block is
+//! trained up on such OPT hits and down on OPT misses, and the lines it fills are then cache-friendly or
+//! cache-averse.
+
+use std::collections::{HashMap, VecDeque};
+
+use serde::{Deserialize, Serialize};
+
+use crate::ship::fold_signature;
+
+/// Sets sampled by OPTgen in caches with enough sets, every set being sampled in smaller ones.
+pub const HAWKEYE_SAMPLED_SETS: usize = 64;
+
+/// Accesses of a sampled set OPTgen looks back over, as a multiple of the ways.
+pub const OPTGEN_WINDOW_FACTOR: usize = 8;
+
+/// Bits of the signatures of the instructions the predictor tells apart.
+pub const HAWKEYE_SIGNATURE_BITS: u32 = 11;
+
+/// Highest value of the 3-bit counters of the predictor, signatures being cache-friendly from halfway up.
+pub const HAWKEYE_COUNTER_MAX: u8 = 7;
+
+/// Occupancy vector of one sampled set: which of its recent accesses Belady's optimal policy would have hit.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct OptGen {
+    ways: usize,
+    window: usize,
+    occupancy: VecDeque<usize>, // Lines OPT holds at each access in the window, oldest first
+    blocks: VecDeque<u64>, // Block of each access in the window, oldest first
+    last_access: HashMap<u64, (u64, u16)>, // Time and signature of the last access to each block within the window
+    time: u64, // Accesses to the set so far
+    accesses: usize,
+    hits: usize,
+}
+
+impl OptGen {
+    /// Occupancy vector of a set of ways lines, looking back over OPTGEN_WINDOW_FACTOR times as many accesses.
+    pub fn new(ways: usize) -> OptGen {
+        let window = OPTGEN_WINDOW_FACTOR * ways;
+        OptGen {
+            ways,
+            window,
+            occupancy: VecDeque::with_capacity(window + 1),
+            blocks: VecDeque::with_capacity(window + 1),
+            last_access: HashMap::new(),
+            time: 0,
+            accesses: 0,
+            hits: 0,
+        }
+    }
+
+    /// Replay an access to a block under a signature, returning the signature of the block's previous access and
+    /// whether OPT would have kept the block since, or None for the first access to the block within the window.
+    pub fn access(&mut self, block: u64, signature: u16) -> Option<(u16, bool)> {
+        let now = self.time;
+        let previous = self.last_access.insert(block, (now, signature));
+        self.accesses += 1;
+        let outcome = previous.map(|(then, previous_signature)| {
+            let start = self.occupancy.len() - (now - then) as usize;
+            let hit = self.occupancy.range(start..).all(|&lines| lines < self.ways);
+            if hit {
+                self.occupancy.range_mut(start..).for_each(|lines| *lines += 1);
+                self.hits += 1;
+            }
+            (previous_signature, hit)
+        });
+        self.occupancy.push_back(0);
+        self.blocks.push_back(block);
+        if self.occupancy.len() > self.window {
+            self.occupancy.pop_front();
+            // The block's last access leaving the window, OPTgen can no longer tell whether it would be kept
+            let expired = now - self.window as u64;
+            if let Some(block) = self.blocks.pop_front().filter(|block| self.last_access.get(block).is_some_and(|&(then, _)| then == expired)) {
+                self.last_access.remove(&block);
+            }
+        }
+        self.time += 1;
+        outcome
+    }
+
+    /// Blocks accessed within the window.
+    pub fn tracked_blocks(&self) -> usize {
+        self.last_access.len()
+    }
+
+    /// Accesses replayed so far.
+    pub fn accesses(&self) -> usize {
+        self.accesses
+    }
+
+    /// Accesses replayed so far that OPT would have hit.
+    pub fn hits(&self) -> usize {
+        self.hits
+    }
+}
+
+/// Occupancy vectors of some sampled sets and predictor counters of a Hawkeye cache, with its fill counts, saved so
+/// that the accesses to those sets can be undone.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct HawkeyeUndo {
+    samplers: Vec<(usize, OptGen)>,
+    counters: Vec<(u16, u8)>,
+    fills: [usize; 2], // Fills and cache-averse fills
+}
+
+/// OPTgen over the sampled sets of a cache and the predictor it trains, with the fills the predictor found
+/// cache-averse.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Hawkeye {
+    stride: usize, // Sets between two sampled ones
+    samplers: Vec<OptGen>,
+    counters: Vec<u8>,
+    fills: usize,
+    averse_fills: usize,
+}
+
+impl Hawkeye {
+    /// Hawkeye over a power-of-two number of sets of ways lines, every signature starting cache-friendly.
+    pub fn new(sets: usize, ways: usize) -> Hawkeye {
+        let stride = (sets / HAWKEYE_SAMPLED_SETS).max(1);
+        Hawkeye {
+            stride,
+            samplers: (0..sets / stride).map(|_| OptGen::new(ways)).collect(),
+            counters: vec![HAWKEYE_COUNTER_MAX / 2 + 1; 1 << HAWKEYE_SIGNATURE_BITS],
+            fills: 0,
+            averse_fills: 0,
+        }
+    }
+
+    /// Signature of the instruction at a program counter.
+    pub fn signature(&self, pc: u64) -> u16 {
+        fold_signature(pc, HAWKEYE_SIGNATURE_BITS)
+    }
+
+    /// Number of sets of the cache.
+    pub fn sets(&self) -> usize {
+        self.stride * self.samplers.len()
+    }
+
+    /// Number of sets OPTgen samples.
+    pub fn sampled_sets(&self) -> usize {
+        self.samplers.len()
+    }
+
+    /// Replay a demand access to a block of a set if the set is sampled, training the signature of the block's
+    /// previous access with what OPT would have done.
+    pub fn train(&mut self, set_index: usize, block: u64, signature: u16) {
+        if !set_index.is_multiple_of(self.stride) {
+            return;
+        }
+        if let Some((previous_signature, hit)) = self.samplers[set_index / self.stride].access(block, signature) {
+            let counter = &mut self.counters[usize::from(previous_signature)];
+            *counter = if hit { (*counter + 1).min(HAWKEYE_COUNTER_MAX) } else { counter.saturating_sub(1) };
+        }
+    }
+
+    /// Whether the lines loaded by a signature are predicted to be kept by OPT.
+    pub fn is_friendly(&self, signature: u16) -> bool {
+        self.counters[usize::from(signature)] > HAWKEYE_COUNTER_MAX / 2
+    }
+
+    /// Count a fill by a signature, returning whether it is cache-friendly.
+    pub fn record_fill(&mut self, signature: u16) -> bool {
+        let friendly = self.is_friendly(signature);
+        self.fills += 1;
+        if !friendly {
+            self.averse_fills += 1;
+        }
+        friendly
+    }
+
+    /// Train a signature down when a line it filled is evicted while still predicted cache-friendly, no
+    /// cache-averse line being left to evict instead.
+    pub fn detrain(&mut self, signature: u16) {
+        let counter = &mut self.counters[usize::from(signature)];
+        *counter = counter.saturating_sub(1);
+    }
+
+    /// Number of signatures the predictor tells apart.
+    pub fn signatures(&self) -> usize {
+        self.counters.len()
+    }
+
+    /// Accesses OPTgen replayed so far, over all sampled sets.
+    pub fn sampled_accesses(&self) -> usize {
+        self.samplers.iter().map(OptGen::accesses).sum()
+    }
+
+    /// Replayed accesses OPT would have hit.
+    pub fn opt_hits(&self) -> usize {
+        self.samplers.iter().map(OptGen::hits).sum()
+    }
+
+    /// Fills counted so far.
+    pub fn fills(&self) -> usize {
+        self.fills
+    }
+
+    /// Fills counted so far that were cache-averse.
+    pub fn averse_fills(&self) -> usize {
+        self.averse_fills
+    }
+
+    /// Zero the counts, keeping the occupancy vectors and what the predictor learnt.
+    pub fn reset_stats(&mut self) {
+        (self.fills, self.averse_fills) = (0, 0);
+        self.samplers.iter_mut().for_each(|sampler| (sampler.accesses, sampler.hits) = (0, 0));
+    }
+
+    /// Save the occupancy vectors of the sampled sets among some sets, the counters of their blocks' signatures and
+    /// of the signatures of the lines that may be evicted, and the fill counts, for [`Hawkeye::restore`].
+    pub(crate) fn save(&self, sets: &[usize], line_signatures: &[u16]) -> HawkeyeUndo {
+        let samplers: Vec<(usize, OptGen)> = sets.iter().filter(|&&set| set.is_multiple_of(self.stride))
+            .map(|&set| (set / self.stride, self.samplers[set / self.stride].clone())).collect();
+        let mut signatures: Vec<u16> = samplers.iter().flat_map(|(_, sampler)| sampler.last_access.values().map(|&(_, signature)| signature))
+            .chain(line_signatures.iter().copied()).collect();
+        signatures.sort();
+        signatures.dedup();
+        HawkeyeUndo {
+            samplers,
+            counters: signatures.into_iter().map(|signature| (signature, self.counters[usize::from(signature)])).collect(),
+            fills: [self.fills, self.averse_fills],
+        }
+    }
+
+    /// Put back the state saved by [`Hawkeye::save`], undoing the accesses to its sets since.
+    pub(crate) fn restore(&mut self, undo: HawkeyeUndo) {
+        for (index, sampler) in undo.samplers {
+            self.samplers[index] = sampler;
+        }
+        for (signature, counter) in undo.counters {
+            self.counters[usize::from(signature)] = counter;
+        }
+        [self.fills, self.averse_fills] = undo.fills;
+    }
+}
+
+// Tests for OptGen::access
+#[test]
+fn test_optgen_access() {
+    let mut optgen = OptGen::new(2);
+    // A B C A B C in two lines: OPT keeps A and B over C, and then has no room left for C
+    for (signature, block) in [0xa, 0xb, 0xc].into_iter().enumerate() {
+        assert_eq!(optgen.access(block, signature as u16), None);
+    }
+    assert_eq!(optgen.access(0xa, 3), Some((0, true)));
+    assert_eq!(optgen.access(0xb, 4), Some((1, true)));
+    assert_eq!(optgen.access(0xc, 5), Some((2, false)));
+    assert_eq!((optgen.accesses(), optgen.hits()), (6, 2));
+    assert_eq!(optgen.occupancy, [1, 2, 2, 1, 0, 0]);
+
+    // Blocks are forgotten once their last access leaves the window of 16 accesses, their reuse being a first access
+    let mut optgen = OptGen::new(2);
+    optgen.access(0xa, 1);
+    for block in 0..15 {
+        optgen.access(0x100 + block, 2);
+    }
+    assert_eq!(optgen.access(0xa, 1), Some((1, true)));
+    for block in 0..16 {
+        optgen.access(0x100 + block, 2);
+    }
+    assert_eq!(optgen.access(0xa, 1), None);
+    assert_eq!((optgen.occupancy.len(), optgen.tracked_blocks()), (16, 16));
+}
+
+// Tests for Hawkeye::train
+#[test]
+fn test_hawkeye_training() {
+    let mut hawkeye = Hawkeye::new(256, 4);
+    assert_eq!((hawkeye.sampled_sets(), hawkeye.signatures()), (64, 2048));
+    assert_eq!(hawkeye.signature(0x400a10), 0x210);
+    // Sets between the sampled ones aren't replayed
+    hawkeye.train(1, 0xa, 5);
+    hawkeye.train(1, 0xa, 5);
+    assert_eq!(hawkeye.sampled_accesses(), 0);
+
+    // A signature whose blocks OPT keeps stays friendly, one whose blocks it drops turns averse
+    for round in 0..4 {
+        hawkeye.train(4, 0xa, 5);
+        hawkeye.train(4, 0x100 + round, 6);
+    }
+    assert_eq!((hawkeye.sampled_accesses(), hawkeye.opt_hits()), (8, 3));
+    assert!(hawkeye.is_friendly(5));
+    for block in 0..40 {
+        hawkeye.train(8, block % 20, 6);
+    }
+    assert!(!hawkeye.is_friendly(6));
+    assert_eq!((hawkeye.record_fill(5), hawkeye.record_fill(6)), (true, false));
+    assert_eq!((hawkeye.fills(), hawkeye.averse_fills()), (2, 1));
+
+    // Evicting friendly lines trains their signature down
+    for _ in 0..HAWKEYE_COUNTER_MAX {
+        hawkeye.detrain(5);
+    }
+    assert!(!hawkeye.is_friendly(5));
+    hawkeye.reset_stats();
+    assert_eq!((hawkeye.fills(), hawkeye.sampled_accesses(), hawkeye.opt_hits()), (0, 0, 0));
+    assert_eq!(Hawkeye::new(16, 4).sampled_sets(), 16);
+}
+
+// Tests for Hawkeye::save and Hawkeye::restore
+#[test]
+fn test_hawkeye_restore() {
+    let mut hawkeye = Hawkeye::new(4, 2);
+    hawkeye.train(0, 0xa, 5);
+    hawkeye.train(1, 0xb, 6);
+    // The signature of the access itself is saved too, as a modify trains it when it looks its block up again
+    let undo = hawkeye.save(&[0], &[6, 7]);
+    let saved = hawkeye.clone();
+    // OPT drops A after B, C and D took both lines, training 5 down, and the eviction of a line of 7 trains 7 down
+    for block in [0xb, 0xc, 0xd, 0xb, 0xc, 0xd, 0xa] {
+        hawkeye.train(0, block, 6);
+    }
+    hawkeye.detrain(7);
+    hawkeye.record_fill(5);
+    assert!(!hawkeye.is_friendly(5) && !hawkeye.is_friendly(7));
+    hawkeye.restore(undo);
+    assert_eq!(hawkeye, saved);
+}
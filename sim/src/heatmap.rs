@@ -0,0 +1,99 @@
+//! SVG heatmaps of the misses of every set, to spot conflict hot spots across the index space.
+
+use std::fmt::Write;
+
+use crate::cache::{Cache, SetStats};
+use crate::error::SimError;
+
+// Side of the square drawn for each set, in pixels
+const CELL_SIZE: usize = 12;
+
+// Height of the caption under the grid, in pixels
+const CAPTION_HEIGHT: usize = 24;
+
+/// Draw the misses of each set as an SVG grid of squares, row-major from the top left, shaded from white (no
+/// misses) to red (the most misses of any set). Sets that were never accessed are grey, and hovering over a
+/// square shows the counts of its set.
+pub fn set_heatmap_svg(set_stats: &[SetStats]) -> String {
+    // Powers of two of sets make a square grid, or one twice as wide as it is tall
+    let columns = 1usize << (set_stats.len().max(1).next_power_of_two().trailing_zeros().div_ceil(2));
+    let rows = set_stats.len().div_ceil(columns).max(1);
+    let width = columns * CELL_SIZE;
+    let height = rows * CELL_SIZE + CAPTION_HEIGHT;
+    let max_misses = set_stats.iter().map(|stats| stats.misses).max().unwrap_or(0);
+
+    let mut svg = String::new();
+    let _ = writeln!(svg, r#"<svg xmlns="http://www.w3.org/2000/svg" width="{}" height="{}" viewBox="0 0 {} {}">"#, width, height, width, height);
+    let _ = writeln!(svg, "<title>Misses per set</title>");
+    for (index, stats) in set_stats.iter().enumerate() {
+        let fill = if stats.hits + stats.misses == 0 {
+            "#e0e0e0".to_string()
+        } else {
+            let shade = 255 - (255 * stats.misses).checked_div(max_misses).unwrap_or(0);
+            format!("#ff{:02x}{:02x}", shade, shade)
+        };
+        let _ = writeln!(
+            svg,
+            r#"<rect x="{}" y="{}" width="{}" height="{}" fill="{}"><title>set {}: hits:{} misses:{} evictions:{}</title></rect>"#,
+            index % columns * CELL_SIZE, index / columns * CELL_SIZE, CELL_SIZE, CELL_SIZE, fill,
+            index, stats.hits, stats.misses, stats.evictions
+        );
+    }
+    let _ = writeln!(
+        svg,
+        r#"<text x="2" y="{}" font-family="sans-serif" font-size="11">{} sets, max misses {}</text>"#,
+        rows * CELL_SIZE + CAPTION_HEIGHT / 2 + 4, set_stats.len(), max_misses
+    );
+    svg.push_str("</svg>\n");
+    svg
+}
+
+/// Write the per-set miss heatmap of a cache to an SVG file.
+pub fn write_set_heatmap(cache: &Cache, path: &str) -> Result<(), SimError> {
+    std::fs::write(path, set_heatmap_svg(&cache.set_stats())).map_err(|err| SimError::Io(format!("failed to write {} ({})", path, err)))
+}
+
+#[cfg(test)]
+use crate::trace::simulate_tracefile;
+
+// Tests for set_heatmap_svg function
+#[test]
+fn test_set_heatmap_svg() {
+    let set_stats = [
+        SetStats { hits: 3, misses: 4, evictions: 2 },
+        SetStats { hits: 1, misses: 1, evictions: 0 },
+        SetStats::default(),
+        SetStats { hits: 5, misses: 0, evictions: 0 },
+    ];
+    let svg = set_heatmap_svg(&set_stats);
+    assert!(svg.starts_with(r#"<svg xmlns="http://www.w3.org/2000/svg" width="24" height="48" viewBox="0 0 24 48">"#));
+    assert!(svg.contains(r##"<rect x="0" y="0" width="12" height="12" fill="#ff0000"><title>set 0: hits:3 misses:4 evictions:2</title></rect>"##));
+    assert!(svg.contains(r##"<rect x="12" y="0" width="12" height="12" fill="#ffc0c0"><title>set 1: hits:1 misses:1 evictions:0</title></rect>"##));
+    assert!(svg.contains(r##"<rect x="0" y="12" width="12" height="12" fill="#e0e0e0">"##));
+    assert!(svg.contains(r##"<rect x="12" y="12" width="12" height="12" fill="#ffffff">"##));
+    assert!(svg.contains(">4 sets, max misses 4</text>"));
+    assert!(svg.ends_with("</svg>\n"));
+}
+
+#[test]
+fn test_set_heatmap_svg_shape() {
+    // 32 sets make 8 columns of 4 rows
+    let svg = set_heatmap_svg(&[SetStats::default(); 32]);
+    assert!(svg.contains(r#"width="96" height="72""#));
+    assert_eq!(svg.matches("<rect").count(), 32);
+    assert!(set_heatmap_svg(&[SetStats::default()]).contains(r#"width="12" height="36""#));
+}
+
+// Tests for write_set_heatmap function
+#[test]
+fn test_write_set_heatmap() {
+    let path = std::env::temp_dir().join(format!("sim-heatmap-{}.svg", std::process::id()));
+    let mut cache = Cache::new(4, 1, 4).unwrap();
+    simulate_tracefile(&mut cache, "../traces/trans.trace").unwrap();
+    write_set_heatmap(&cache, path.to_str().unwrap()).unwrap();
+    let svg = std::fs::read_to_string(&path).unwrap();
+    assert_eq!(svg, set_heatmap_svg(&cache.set_stats()));
+    assert_eq!(svg.matches("<rect").count(), 16);
+    std::fs::remove_file(path).unwrap();
+    assert!(write_set_heatmap(&cache, "/nonexistent/heatmap.svg").is_err());
+}
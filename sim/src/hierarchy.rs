@@ -0,0 +1,421 @@
+//! Several cache levels looked up in order: each level only sees the misses of the level before it.
+//!
+//! Every miss fetches the block from the next level, as a load, and fills it on the way back (write-allocate).
+//! Levels are neither inclusive nor exclusive. The dirty lines a level evicts or flushes are counted as write-back
+//! traffic to the level below, but aren't written into it. The misses of the last level go to memory, modeled as a DRAM with row buffers and behind a memory controller whose
+//! queue delays bursts of misses if the configuration describes them.
+
+use crate::cache::Cache;
+use crate::config::HierarchyConfig;
+use crate::controller::MemoryController;
+use crate::dram::Dram;
+use crate::error::SimError;
+use crate::nuca::Slices;
+use crate::trace::MemoryAccess;
+
+/// One cache level, the cycles it takes to look up and the slices it is split into.
+pub struct Level {
+    pub name: String,
+    pub cache: Cache,
+    pub latency: Option<u64>,
+    pub slices: Option<Slices>,
+}
+
+impl Level {
+    /// Look an access up, in the slice of its address for a sliced level. Returns the blocks the level misses or
+    /// a prefetch fills, and the cycles the access's core took to reach the slice.
+    pub fn look_up(&mut self, access: MemoryAccess) -> Result<(usize, u64), SimError> {
+        let Some(slices) = &mut self.slices else {
+            return self.cache.simulate_access(access).map(|result| (result.misses + result.prefetch_fills, 0));
+        };
+        let slice = slices.slice(access.address);
+        let result = self.cache.simulate_access(MemoryAccess { address: slices.map(access.address), ..access })?;
+        slices.record(slice, access.thread, result.hits, result.misses);
+        Ok((result.misses + result.prefetch_fills, slices.hop_cycles(slice, access.thread)))
+    }
+
+    /// Apply a flush or pin record to the level, on the slice holding its line. Flushes and pins aren't lookups, so
+    /// they take no hop cycles.
+    pub fn maintain(&mut self, access: MemoryAccess) -> Result<(), SimError> {
+        let address = self.slices.as_ref().map_or(access.address, |slices| slices.map(access.address));
+        self.cache.simulate_access(MemoryAccess { address, ..access }).map(|_| ())
+    }
+
+    /// Clear the statistics of the level and its slices, keeping its contents.
+    pub fn reset_stats(&mut self) {
+        self.cache.reset_stats();
+        if let Some(slices) = &mut self.slices {
+            slices.reset_stats();
+        }
+    }
+}
+
+// Fetch the blocks an access missed from the given levels in order, as loads or prefetches. Returns the misses of the
+// last one and the cycles the levels with a latency took.
+pub(crate) fn fetch_levels(levels: &mut [Level], access: MemoryAccess, mut misses: usize) -> Result<(usize, u64), SimError> {
+    let fetch = MemoryAccess { operation: if access.operation == 'P' { 'P' } else { 'L' }, ..access };
+    let mut cycles = 0;
+    for level in levels {
+        if misses == 0 {
+            break;
+        }
+        cycles += level.latency.unwrap_or(0) * misses as u64;
+        let mut next_misses = 0;
+        for _ in 0..misses {
+            let (level_misses, hop_cycles) = level.look_up(fetch)?;
+            next_misses += level_misses;
+            cycles += hop_cycles;
+        }
+        misses = next_misses;
+    }
+    Ok((misses, cycles))
+}
+
+/// A hierarchy of cache levels in front of memory.
+pub struct Hierarchy {
+    pub levels: Vec<Level>,
+    pub memory_latency: Option<u64>,
+    pub dram: Option<Dram>,
+    pub controller: Option<MemoryController>,
+}
+
+impl Hierarchy {
+    /// Build the empty levels a configuration describes, named L1, L2... unless they have names.
+    pub fn new(config: &HierarchyConfig) -> Result<Hierarchy, SimError> {
+        let levels = config.levels.iter().enumerate().map(|(index, level)| {
+            Ok(Level {
+                name: level.name.clone().unwrap_or_else(|| format!("L{}", index + 1)),
+                cache: level.builder()?.build()?,
+                latency: level.latency,
+                slices: level.slices()?,
+            })
+        }).collect::<Result<Vec<_>, SimError>>()?;
+        let dram = config.dram.as_ref().map(|dram| dram.dram()).transpose()?;
+        let controller = config.memory_controller.as_ref().map(|controller| controller.controller(config.block_size())).transpose()?;
+        Ok(Hierarchy { levels, memory_latency: config.memory_latency, dram, controller })
+    }
+
+    /// Simulate an access on the first level, fetching each block it misses from the levels below, and from the
+    /// DRAM after the last one through the memory controller's queue. Each access takes a cycle of the controller's
+    /// clock. A prefetch that fills a level is passed on to the next one as a prefetch. Flushes empty the line, or
+    /// everything for 'W', from every level, and pins pin it in every level.
+    pub fn simulate_access(&mut self, access: MemoryAccess) -> Result<(), SimError> {
+        if matches!(access.operation, 'F' | 'W' | 'K' | 'U') {
+            return self.levels.iter_mut().try_for_each(|level| level.maintain(access));
+        }
+        let misses = match self.levels.first_mut() {
+            Some(level) => level.look_up(access)?.0,
+            None => return Ok(()),
+        };
+        self.fetch(1, access, misses)?;
+        self.tick();
+        Ok(())
+    }
+
+    /// Fetch the blocks an access missed from the level at index `first` and the ones below it, and from memory,
+    /// without advancing the memory controller's clock. Returns the cycles the fetches took, counting the levels and
+    /// memory with a latency and the delays of the controller's queue.
+    pub fn fetch(&mut self, first: usize, access: MemoryAccess, misses: usize) -> Result<u64, SimError> {
+        let first = first.min(self.levels.len());
+        let (misses, mut cycles) = fetch_levels(&mut self.levels[first..], access, misses)?;
+        cycles += self.memory_latency.unwrap_or(0) * misses as u64;
+        if let Some(dram) = &mut self.dram {
+            for _ in 0..misses {
+                dram.access(access.address);
+            }
+        }
+        if let Some(controller) = &mut self.controller {
+            for _ in 0..misses {
+                cycles += controller.request();
+            }
+        }
+        Ok(cycles)
+    }
+
+    /// Advance the memory controller's clock by the cycle of one access.
+    pub fn tick(&mut self) {
+        if let Some(controller) = &mut self.controller {
+            controller.tick();
+        }
+    }
+
+    /// Simulate accesses as they are read, resetting the statistics of every level after the first warmup
+    /// accesses, and stopping at the first error.
+    pub fn simulate_accesses<I>(&mut self, accesses: I, warmup: usize) -> Result<(), SimError>
+    where
+        I: IntoIterator<Item = Result<MemoryAccess, SimError>>,
+    {
+        let mut accesses = accesses.into_iter();
+        if warmup > 0 {
+            for access in accesses.by_ref().take(warmup) {
+                self.simulate_access(access?)?;
+            }
+            self.reset_stats();
+        }
+        for access in accesses {
+            self.simulate_access(access?)?;
+        }
+        Ok(())
+    }
+
+    /// Clear the statistics of every level and of the DRAM, keeping their contents.
+    pub fn reset_stats(&mut self) {
+        for level in &mut self.levels {
+            level.reset_stats();
+        }
+        if let Some(dram) = &mut self.dram {
+            dram.reset_stats();
+        }
+        if let Some(controller) = &mut self.controller {
+            controller.reset_stats();
+        }
+    }
+
+    /// Average memory access time in cycles: the lookups of every level and the trips to memory, per lookup of
+    /// the first level. None unless every level and memory have a latency, the row latencies of a DRAM standing
+    /// for the memory latency, plus the delays of the memory controller's queue.
+    pub fn amat(&self) -> Option<f64> {
+        let first = self.levels.first()?;
+        let lookups = first.cache.hits() + first.cache.misses();
+        if lookups == 0 {
+            return None;
+        }
+        let mut cycles = 0;
+        for level in &self.levels {
+            cycles += level.latency? * (level.cache.hits() + level.cache.misses()) as u64;
+            cycles += level.slices.as_ref().map_or(0, Slices::total_hop_cycles);
+        }
+        cycles += match &self.dram {
+            Some(dram) => dram.cycles()?,
+            None => self.memory_latency? * self.levels.last()?.cache.misses() as u64,
+        };
+        cycles += self.controller.as_ref().map_or(0, MemoryController::delay_cycles);
+        Some(cycles as f64 / lookups as f64)
+    }
+
+    /// One statistics line per level, prefixed with its name and followed by the traffic it sends to the level below
+    /// (demand misses, prefetch fills and write-backs) and its slices' lines, then the DRAM's and the memory
+    /// controller's, and the average memory access time if known.
+    pub fn format_stats(&self) -> String {
+        let mut lines = Vec::new();
+        for level in &self.levels {
+            let cache = &level.cache;
+            let lookups = cache.hits() + cache.misses();
+            let miss_rate = if lookups == 0 { 0.0 } else { 100.0 * cache.misses() as f64 / lookups as f64 };
+            lines.push(format!("{}: hits:{} misses:{} evictions:{} miss-rate:{:.2}%", level.name, cache.hits(), cache.misses(), cache.evictions(), miss_rate));
+            lines.push(format!("{} traffic: demand:{} prefetch:{} write-backs:{}", level.name, cache.misses(), cache.prefetch_fills(), cache.write_backs()));
+            if let Some(slices) = &level.slices {
+                lines.extend(slices.format_stats(&level.name));
+            }
+        }
+        if let Some(dram) = &self.dram {
+            lines.push(format!("DRAM: {}", dram.format_stats()));
+        }
+        if let Some(controller) = &self.controller {
+            lines.push(format!("memory-controller: {}", controller.format_stats()));
+        }
+        if let Some(amat) = self.amat() {
+            lines.push(format!("amat:{:.2} cycles", amat));
+        }
+        lines.join("\n")
+    }
+}
+
+#[cfg(test)]
+use crate::dram::DramStats;
+#[cfg(test)]
+use crate::trace::{simulate_accesses, trace_accesses};
+
+#[cfg(test)]
+const TWO_LEVELS: &str = "memory_latency = 100\n\
+    [[level]]\ns = 1\nE = 1\nb = 4\nlatency = 1\n\
+    [[level]]\nname = \"LLC\"\ns = 4\nE = 2\nb = 4\nlatency = 10\n";
+
+// Tests for Hierarchy
+#[test]
+fn test_hierarchy() {
+    let mut hierarchy = Hierarchy::new(&HierarchyConfig::from_toml(TWO_LEVELS).unwrap()).unwrap();
+    assert_eq!(hierarchy.levels[0].name, "L1");
+    assert_eq!(hierarchy.levels[1].name, "LLC");
+    // 0x00 and 0x20 conflict in the first level but not in the second
+    for address in [0x00, 0x20, 0x00, 0x20] {
+        hierarchy.simulate_access(MemoryAccess { operation: 'S', address, thread: 0, size: 1, pc: 0 }).unwrap();
+    }
+    assert_eq!((hierarchy.levels[0].cache.hits(), hierarchy.levels[0].cache.misses()), (0, 4));
+    assert_eq!((hierarchy.levels[1].cache.hits(), hierarchy.levels[1].cache.misses()), (2, 2));
+    // 4 lookups of 1 cycle, 4 of 10 cycles and 2 trips to memory of 100 cycles
+    assert_eq!(hierarchy.amat(), Some((4.0 + 40.0 + 200.0) / 4.0));
+    // Every store dirtied the line it filled, so each of the first level's evictions is a write-back
+    assert_eq!(hierarchy.format_stats(), "L1: hits:0 misses:4 evictions:3 miss-rate:100.00%\n\
+        L1 traffic: demand:4 prefetch:0 write-backs:3\n\
+        LLC: hits:2 misses:2 evictions:0 miss-rate:50.00%\n\
+        LLC traffic: demand:2 prefetch:0 write-backs:0\n\
+        amat:61.00 cycles");
+}
+
+#[test]
+fn test_hierarchy_single_level_matches_cache() {
+    let config = HierarchyConfig::from_toml("[[level]]\ns = 4\nE = 1\nb = 4\n").unwrap();
+    let mut hierarchy = Hierarchy::new(&config).unwrap();
+    hierarchy.simulate_accesses(trace_accesses("../traces/trans.trace").unwrap(), 10).unwrap();
+    let mut cache = Cache::new(4, 1, 4).unwrap();
+    simulate_accesses(&mut cache, trace_accesses("../traces/trans.trace").unwrap(), 10).unwrap();
+    assert_eq!(hierarchy.levels[0].cache.format_stats(), cache.format_stats());
+    // Without latencies there is no average access time
+    assert_eq!(hierarchy.amat(), None);
+    assert!(hierarchy.format_stats().starts_with("L1: "));
+}
+
+#[test]
+fn test_hierarchy_lower_levels_see_misses() {
+    let mut hierarchy = Hierarchy::new(&HierarchyConfig::from_toml(TWO_LEVELS).unwrap()).unwrap();
+    hierarchy.simulate_accesses(trace_accesses("../traces/long.trace").unwrap(), 0).unwrap();
+    let (first, second) = (&hierarchy.levels[0].cache, &hierarchy.levels[1].cache);
+    assert_eq!(second.hits() + second.misses(), first.misses());
+    assert!(hierarchy.amat().unwrap() > 1.0);
+}
+
+#[test]
+fn test_hierarchy_slices() {
+    let sliced = |hash: &str| format!("{}slices = 4\nslice_hash = \"{}\"\nslice_latency = 2\n", TWO_LEVELS, hash);
+    // Blocks 0x100 apart share a set without slices, and the XOR hash spreads them over the four slices
+    let mut hierarchy = Hierarchy::new(&HierarchyConfig::from_toml(&sliced("xor")).unwrap()).unwrap();
+    for address in [0x000, 0x100, 0x200, 0x300, 0x000, 0x100, 0x200, 0x300] {
+        hierarchy.simulate_access(MemoryAccess { operation: 'L', address, thread: 0, size: 1, pc: 0 }).unwrap();
+    }
+    assert_eq!((hierarchy.levels[1].cache.hits(), hierarchy.levels[1].cache.misses()), (4, 4));
+    // Slices 1, 2 and 3 are 1, 2 and 1 hops from the core: 8 hops of 2 cycles over the two rounds
+    assert_eq!(hierarchy.amat(), Some((8.0 + 80.0 + 16.0 + 400.0) / 8.0));
+    assert!(hierarchy.format_stats().contains("LLC: hits:4 misses:4 evictions:0 miss-rate:50.00%\n\
+        LLC traffic: demand:4 prefetch:0 write-backs:0\n\
+        LLC slices:4 hash:xor imbalance:1.00 hop-cycles:16\n\
+        LLC slice 0: hits:1 misses:1 miss-rate:50.00% hops:0\n\
+        LLC slice 1: hits:1 misses:1 miss-rate:50.00% hops:2\n"));
+    hierarchy.reset_stats();
+    assert_eq!(hierarchy.levels[1].slices.as_ref().unwrap().total_hop_cycles(), 0);
+
+    // Slicing by the set index bits leaves the level as it was, and sends the stride to a single slice
+    let mut hierarchy = Hierarchy::new(&HierarchyConfig::from_toml(&sliced("bits")).unwrap()).unwrap();
+    hierarchy.simulate_accesses(trace_accesses("../traces/long.trace").unwrap(), 0).unwrap();
+    let mut unsliced = Hierarchy::new(&HierarchyConfig::from_toml(TWO_LEVELS).unwrap()).unwrap();
+    unsliced.simulate_accesses(trace_accesses("../traces/long.trace").unwrap(), 0).unwrap();
+    assert_eq!(hierarchy.levels[1].cache.format_stats(), unsliced.levels[1].cache.format_stats());
+    let slices = hierarchy.levels[1].slices.as_ref().unwrap();
+    assert_eq!(slices.stats().iter().map(|stats| stats.hits + stats.misses).sum::<usize>(), unsliced.levels[0].cache.misses());
+}
+
+#[test]
+fn test_hierarchy_dram() {
+    let config = format!("{}[dram]\nbanks = 2\nrow_size = 64\nrow_hit_latency = 20\nrow_empty_latency = 40\nrow_conflict_latency = 60\n", TWO_LEVELS);
+    let mut hierarchy = Hierarchy::new(&HierarchyConfig::from_toml(&config).unwrap()).unwrap();
+    // 0x00 and 0x10 share a row, 0x80 is another row of the same bank and 0x40 is in the other bank
+    for address in [0x00, 0x10, 0x80, 0x40, 0x00] {
+        hierarchy.simulate_access(MemoryAccess { operation: 'L', address, thread: 0, size: 1, pc: 0 }).unwrap();
+    }
+    let dram = hierarchy.dram.as_ref().unwrap();
+    assert_eq!(dram.stats(), DramStats { row_hits: 1, row_empty: 2, row_conflicts: 1 });
+    assert_eq!(dram.stats().accesses(), hierarchy.levels[1].cache.misses());
+    // 5 lookups of 1 cycle, 5 of 10 cycles (0x00 hits the second level) and the row latencies instead of memory_latency
+    assert_eq!(hierarchy.amat(), Some((5.0 + 50.0 + 20.0 + 80.0 + 60.0) / 5.0));
+    assert!(hierarchy.format_stats().contains("\nDRAM: accesses:4 row-hits:1 row-empty:2 row-conflicts:1 row-hit-rate:25.00%\namat:"));
+
+    hierarchy.reset_stats();
+    assert_eq!(hierarchy.dram.as_ref().unwrap().stats().accesses(), 0);
+}
+
+#[test]
+fn test_hierarchy_dram_single_level() {
+    // A single level with a DRAM behind it sends the DRAM every miss
+    let config = HierarchyConfig::from_toml("[[level]]\ns = 4\nE = 1\nb = 4\n[dram]\nbanks = 8\nrow_size = 1024\n").unwrap();
+    let mut hierarchy = Hierarchy::new(&config).unwrap();
+    hierarchy.simulate_accesses(trace_accesses("../traces/trans.trace").unwrap(), 0).unwrap();
+    let dram = hierarchy.dram.as_ref().unwrap();
+    assert_eq!(dram.stats().accesses(), hierarchy.levels[0].cache.misses());
+    assert!(dram.stats().row_hits > 0);
+    // Without latencies there is no average access time
+    assert_eq!(hierarchy.amat(), None);
+}
+
+#[test]
+fn test_hierarchy_memory_controller() {
+    // A 16-byte block of the second level takes 4 cycles at 4 bytes per cycle
+    let config = format!("{}[memory_controller]\nbandwidth = 4\nqueue_depth = 2\n", TWO_LEVELS);
+    let mut hierarchy = Hierarchy::new(&HierarchyConfig::from_toml(&config).unwrap()).unwrap();
+    // Two misses in consecutive cycles: the second waits 3 cycles for the first
+    for address in [0x00, 0x100] {
+        hierarchy.simulate_access(MemoryAccess { operation: 'L', address, thread: 0, size: 1, pc: 0 }).unwrap();
+    }
+    let controller = hierarchy.controller.as_ref().unwrap();
+    assert_eq!((controller.stats().requests, controller.delay_cycles()), (2, 3));
+    // 2 lookups of 1 cycle, 2 of 10 cycles, 2 trips to memory of 100 cycles and the wait
+    assert_eq!(hierarchy.amat(), Some((2.0 + 20.0 + 200.0 + 3.0) / 2.0));
+    assert!(hierarchy.format_stats().contains("\nmemory-controller: requests:2 queued:1 queue-cycles:3 "));
+
+    hierarchy.reset_stats();
+    assert_eq!(hierarchy.controller.as_ref().unwrap().stats().requests, 0);
+}
+
+#[test]
+fn test_hierarchy_memory_controller_contention() {
+    // The less bandwidth, the longer misses wait, and the higher the average access time
+    let amat = |bandwidth: f64| {
+        let config = format!("{}[memory_controller]\nbandwidth = {}\nqueue_depth = 4\n", TWO_LEVELS, bandwidth);
+        let mut hierarchy = Hierarchy::new(&HierarchyConfig::from_toml(&config).unwrap()).unwrap();
+        hierarchy.simulate_accesses(trace_accesses("../traces/long.trace").unwrap(), 0).unwrap();
+        let controller = hierarchy.controller.as_ref().unwrap();
+        assert_eq!(controller.stats().requests, hierarchy.levels[1].cache.misses());
+        hierarchy.amat().unwrap()
+    };
+    let (fast, slow) = (amat(64.0), amat(0.5));
+    assert!(slow > fast, "{} {}", slow, fast);
+}
+
+#[test]
+fn test_hierarchy_prefetch() {
+    let mut hierarchy = Hierarchy::new(&HierarchyConfig::from_toml(TWO_LEVELS).unwrap()).unwrap();
+    hierarchy.simulate_access(MemoryAccess { operation: 'P', address: 0x40, thread: 0, size: 1, pc: 0 }).unwrap();
+    hierarchy.simulate_access(MemoryAccess { operation: 'P', address: 0x40, thread: 0, size: 1, pc: 0 }).unwrap();
+    let (first, second) = (&hierarchy.levels[0].cache, &hierarchy.levels[1].cache);
+    assert_eq!((first.misses(), first.prefetch_fills(), first.prefetch_hits()), (0, 1, 1));
+    assert_eq!((second.misses(), second.prefetch_fills()), (0, 1));
+    assert!(hierarchy.format_stats().ends_with("L1 traffic: demand:0 prefetch:1 write-backs:0\n\
+        LLC: hits:0 misses:0 evictions:0 miss-rate:0.00%\n\
+        LLC traffic: demand:0 prefetch:1 write-backs:0"));
+    hierarchy.simulate_access(MemoryAccess { operation: 'L', address: 0x40, thread: 0, size: 1, pc: 0 }).unwrap();
+    assert_eq!(hierarchy.levels[0].cache.hits(), 1);
+}
+
+#[test]
+fn test_hierarchy_flush() {
+    let mut hierarchy = Hierarchy::new(&HierarchyConfig::from_toml(&format!("{}slices = 4\n", TWO_LEVELS)).unwrap()).unwrap();
+    for (operation, address) in [('S', 0x40), ('L', 0x10), ('F', 0x40), ('L', 0x40)] {
+        hierarchy.simulate_access(MemoryAccess { operation, address, thread: 0, size: 1, pc: 0 }).unwrap();
+    }
+    // The flushed block misses both levels again, the second one in the same slice
+    let (first, second) = (hierarchy.levels[0].cache.snapshot_stats(), hierarchy.levels[1].cache.snapshot_stats());
+    assert_eq!((first.misses, first.flushes, first.flushed_lines, first.flush_write_backs), (3, 1, 1, 1));
+    assert_eq!((second.misses, second.flushes, second.flushed_lines, second.flush_write_backs), (3, 1, 1, 0));
+    // The dirty line the flush wrote back is the first level's only write-back
+    assert_eq!((hierarchy.levels[0].cache.write_backs(), hierarchy.levels[1].cache.write_backs()), (1, 0));
+    let slices = hierarchy.levels[1].slices.as_ref().unwrap();
+    assert_eq!(slices.stats().iter().map(|stats| stats.hits + stats.misses).sum::<usize>(), 3);
+
+    hierarchy.simulate_access(MemoryAccess { operation: 'W', address: 0, thread: 0, size: 0, pc: 0 }).unwrap();
+    assert!(hierarchy.levels.iter().all(|level| level.cache.set_states().all(|set| set.lines.iter().all(|line| !line.valid))));
+    assert_eq!(hierarchy.levels[1].cache.snapshot_stats().flushed_lines, 3);
+}
+
+#[test]
+fn test_hierarchy_pin() {
+    let mut hierarchy = Hierarchy::new(&HierarchyConfig::from_toml(TWO_LEVELS).unwrap()).unwrap();
+    hierarchy.simulate_access(MemoryAccess { operation: 'K', address: 0x0, thread: 0, size: 32, pc: 0 }).unwrap();
+    assert_eq!((hierarchy.levels[0].cache.pinned_lines(), hierarchy.levels[1].cache.pinned_lines()), (2, 2));
+    // 0x20 and 0x30 can't evict the pinned lines of the first level, so they miss it without filling it
+    for address in [0x20, 0x30, 0x00, 0x10] {
+        hierarchy.simulate_access(MemoryAccess { operation: 'L', address, thread: 0, size: 1, pc: 0 }).unwrap();
+    }
+    let first = hierarchy.levels[0].cache.snapshot_stats();
+    assert_eq!((first.hits, first.misses, first.pinned_hits, first.saved_misses, first.pin_bypasses), (2, 2, 2, 2, 2));
+    hierarchy.simulate_access(MemoryAccess { operation: 'U', address: 0x10, thread: 0, size: 1, pc: 0 }).unwrap();
+    assert_eq!((hierarchy.levels[0].cache.pinned_lines(), hierarchy.levels[1].cache.pinned_lines()), (1, 1));
+}
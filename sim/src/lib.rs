@@ -0,0 +1,158 @@
+//! sim: a cache memory simulator.
+//!
+//! Simulates a set-associative cache with LRU, FIFO, random or tree-PLRU replacement over memory access traces in the
+//! Valgrind-Lackey format, counting hits, misses and evictions.
+//!
+//! ```no_run
+//! use sim::{simulate_tracefile, Cache};
+//!
+//! let mut cache = Cache::new(4, 2, 4).unwrap();
+//! simulate_tracefile(&mut cache, "traces/yi.trace").unwrap();
+//! println!("{}", cache.format_stats());
+//! ```
+
+pub mod bench;
+pub mod binary;
+pub mod cache;
+pub mod checkpoint;
+pub mod completions;
+pub mod config;
+pub mod controller;
+pub mod dashboard;
+pub mod debug;
+pub mod demo;
+pub mod dram;
+pub mod dueling;
+pub mod error;
+pub mod ffi;
+pub mod generate;
+pub mod hawkeye;
+pub mod heatmap;
+pub mod hierarchy;
+pub mod lirs;
+pub mod logging;
+pub mod multicore;
+pub mod nuca;
+pub mod paging;
+pub mod policies;
+pub mod progress;
+pub mod reference;
+pub mod remap;
+pub mod repl;
+mod rng;
+pub mod reuse;
+pub mod sampling;
+pub mod service;
+pub mod sharing;
+pub mod ship;
+pub mod snoop;
+pub mod sweep;
+pub mod tinylfu;
+pub mod trace;
+pub mod two_queue;
+pub mod web;
+pub mod working_set;
+pub mod writer;
+
+pub use bench::{bench_workloads, format_bench, run_bench, BenchResult, DEFAULT_BENCH_LENGTH};
+pub use binary::{convert_accesses, decode_record, encode_record, write_binary_header, BINARY_MAGIC, BINARY_VERSION, RECORD_SIZE};
+pub use cache::{parse_address_range, parse_address_ranges, parse_way_locks, split_address, AccessResult, BlockStats, Cache, CacheBuilder, Insertion, LifetimeStats, LineState, OobPolicy, Op, Replacement, Roi, SetState, SetStats, Stats, ThreadStats, TieBreak, WayLock, WayPredictor, CSV_HEADER, DEFAULT_DRAIN_INTERVAL, DEFAULT_SEED, HOT_BLOCK_CAPACITY};
+pub use checkpoint::CHECKPOINT_VERSION;
+pub use completions::{completion_script, CommandLine, OptionValue, Shell};
+pub use config::{parse_bytes, parse_toml, ByteSize, DramConfig, HierarchyConfig, LevelConfig, MemoryControllerConfig};
+pub use controller::{ControllerStats, MemoryController};
+pub use dashboard::{simulate_accesses_live, Dashboard, HistoryPoint, Progress, PUBLISH_INTERVAL};
+pub use debug::{debug_accesses, tui_accesses, DEFAULT_UNDO_DEPTH};
+pub use demo::{locality_demo, DEFAULT_DEMO_ELEMENT_SIZE, DEFAULT_DEMO_N};
+pub use dram::{Dram, DramStats, RowLatencies, RowOutcome, RowPolicy};
+pub use dueling::{DuelRole, SetDueling, DUEL_LEADERS, PSEL_BITS};
+pub use error::SimError;
+pub use generate::{Generator, Kernel, MatrixKernel, Pattern, DEFAULT_GEN_ACCESS_SIZE};
+pub use hawkeye::{Hawkeye, OptGen, HAWKEYE_COUNTER_MAX, HAWKEYE_SAMPLED_SETS, HAWKEYE_SIGNATURE_BITS, OPTGEN_WINDOW_FACTOR};
+pub use heatmap::{set_heatmap_svg, write_set_heatmap};
+pub use hierarchy::{Hierarchy, Level};
+pub use lirs::{Lirs, LirsStatus, LIRS_HIR_DIVISOR, LIRS_STACK_FACTOR};
+pub use logging::{log_enabled, log_event, log_level, set_log_level, set_log_output, LogLevel};
+pub use multicore::{Core, Interleave, MultiCore};
+pub use nuca::{SliceHash, SliceStats, Slices};
+pub use paging::{simulate_paging, simulate_paging_tracefile, PageReplacement, PagingStats, PhysicalMemory, DEFAULT_PAGE_SIZE};
+pub use policies::{compare_policies, PolicyComparison, PolicyDivergence, DEFAULT_DIVERGENCES};
+pub use progress::{format_progress, ProgressReporter, PROGRESS_INTERVAL, PROGRESS_LOG_INTERVAL};
+pub use reference::{compare_with_program, compare_with_reference, verbose_line, Comparison, Divergence};
+pub use remap::{parse_address_offset, parse_remap_regions, AddressRemap};
+pub use repl::{decompose_address, describe_address, repl};
+pub use reuse::{reuse_histogram, reuse_histogram_tracefile, ReuseHistogram};
+pub use sampling::{simulate_accesses_sampled, simulate_tracefile_sampled, SampleStats, Sampling};
+pub use service::{listen, serve, Service, DEFAULT_SERVICE_ADDRESS, MAX_SERVICE_LINES};
+pub use sharing::{detect_false_sharing, detect_false_sharing_tracefile, BlockSharing, SharingReport, DEFAULT_SHARING_TOP};
+pub use ship::{fold_signature, SignatureTable, SHIP_COUNTER_MAX, SHIP_REGION_BITS, SHIP_SIGNATURE_BITS};
+pub use snoop::SnoopFilter;
+pub use sweep::{format_sweep, sweep_accesses, sweep_tracefile};
+pub use tinylfu::{TinyLfu, TINYLFU_COUNTER_MAX, TINYLFU_ROWS, TINYLFU_SAMPLE_FACTOR};
+pub use trace::{parse_din_record, parse_memory_access, read_accesses, read_tracefile, simulate_accesses, simulate_tracefile, simulate_tracefile_with_warmup, trace_accesses, Column, GenericFormat, MemoryAccess, ReadProgress, TraceAccesses, TraceFormat, STDIN_TRACEFILE};
+pub use two_queue::{TwoQueue, A1IN_WAYS_DIVISOR, A1OUT_TAGS_DIVISOR};
+pub use web::WebSimulator;
+pub use working_set::{format_working_set, working_set_curve, working_set_tracefile, WorkingSetPoint};
+pub use writer::TraceWriter;
+
+// Golden-output snapshot tests over the bundled traces
+#[cfg(test)]
+const SNAPSHOT_TRACES: &[&str] = &[
+    "traces/ibm.trace",
+    "traces/yi.trace",
+    "traces/yi2.trace",
+    "traces/trans.trace",
+    "traces/long.trace",
+];
+
+// Configurations covered by the snapshots: (name, s, E, b, extra setup)
+#[cfg(test)]
+type SnapshotConfig = (&'static str, usize, usize, usize, fn(CacheBuilder) -> CacheBuilder);
+
+#[cfg(test)]
+const SNAPSHOT_CONFIGS: &[SnapshotConfig] = &[
+    ("s1_E1_b1", 1, 1, 1, |builder| builder),
+    ("s4_E2_b4", 4, 2, 4, |builder| builder),
+    ("s2_E4_b3", 2, 4, 3, |builder| builder),
+    ("s5_E1_b5", 5, 1, 5, |builder| builder),
+    ("s4_E2_b4_skewed", 4, 2, 4, |builder| builder.skewed(true)),
+    ("s2_E4_b3_mru", 2, 4, 3, |builder| builder.way_predictor(Some(WayPredictor::Mru))),
+];
+
+// Simulate a snapshot trace under a configuration
+#[cfg(test)]
+fn run_snapshot_trace(config: &SnapshotConfig, trace: &str) -> Cache {
+    let (_, s, e, b, setup) = *config;
+    let mut cache = setup(CacheBuilder::new().set_index_bits(s).associativity(e).block_bits(b)).build().unwrap();
+    simulate_tracefile(&mut cache, &format!("../{}", trace)).unwrap();
+    cache
+}
+
+// Run every snapshot trace under a configuration and join the outputs produced by `render`
+#[cfg(test)]
+fn render_snapshot_runs(config: &SnapshotConfig, render: fn(&Cache) -> String) -> String {
+    let mut output = String::new();
+    for trace in SNAPSHOT_TRACES {
+        output.push_str(&format!("== {} ==\n{}\n", trace, render(&run_snapshot_trace(config, trace))));
+    }
+    output
+}
+
+#[test]
+fn test_snapshot_human_report() {
+    for config in SNAPSHOT_CONFIGS {
+        insta::assert_snapshot!(format!("report_{}", config.0), render_snapshot_runs(config, Cache::format_stats));
+    }
+}
+
+#[test]
+fn test_snapshot_csv() {
+    // One table of every configuration and trace, as --sweep would print it, so column changes show up in review
+    let mut output = format!("{}\n", CSV_HEADER);
+    for config in SNAPSHOT_CONFIGS {
+        for trace in SNAPSHOT_TRACES {
+            output.push_str(&format!("{}\n", run_snapshot_trace(config, trace).csv_row(trace, 0)));
+        }
+    }
+    insta::assert_snapshot!("csv", output);
+}
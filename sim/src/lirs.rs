@@ -0,0 +1,327 @@
+//! Low inter-reference recency set (LIRS) replacement (Jiang and Zhang): blocks are ranked by the recency of their
+//! last two accesses rather than of their last one. Blocks reused within a short distance form the LIR set, which
+//! holds most of the lines, and the others are HIR blocks, of which only a few stay resident, in a queue evicted
+//! first in first out.
+//!
+//! Each set keeps a recency stack of the blocks accessed since its least recently used LIR block: LIR blocks,
+//! resident HIR blocks, and non-resident HIR blocks remembered as ghosts. An HIR block accessed while it is still in
+//! the stack has been reused more recently than that LIR block, so it becomes LIR and the bottom LIR block becomes
+//! HIR. Blocks falling below the last LIR block of the stack are pruned, ghosts being forgotten.
+
+use std::collections::{HashMap, VecDeque};
+
+use serde::{Deserialize, Serialize};
+
+/// Lines of each set kept for resident HIR blocks, as a divisor of the ways, at least one line being kept.
+pub const LIRS_HIR_DIVISOR: usize = 8;
+
+/// Blocks the recency stack of a set holds, as a multiple of the ways, before its oldest ghosts are forgotten.
+pub const LIRS_STACK_FACTOR: usize = 4;
+
+/// Status of a block a set of a LIRS cache knows about.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum LirsStatus {
+    /// Resident, reused within a short distance
+    Lir,
+    /// Resident, in the queue of HIR blocks
+    Hir,
+    /// Evicted HIR block still in the recency stack
+    Ghost,
+}
+
+// Recency stack, HIR queue and status of the blocks of one set
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+struct LirsSet {
+    stack: VecDeque<u64>, // Most recently accessed block first, an LIR block last
+    queue: VecDeque<u64>, // Resident HIR blocks, the next to be evicted first
+    blocks: HashMap<u64, LirsStatus>,
+}
+
+impl LirsSet {
+    fn lir_blocks(&self) -> usize {
+        self.blocks.values().filter(|&&status| status == LirsStatus::Lir).count()
+    }
+
+    fn move_to_top(&mut self, tag: u64) {
+        self.stack.retain(|&other| other != tag);
+        self.stack.push_front(tag);
+    }
+
+    // Make the LIR block at the bottom of the stack a resident HIR block at the end of the queue
+    fn demote_bottom(&mut self) {
+        if let Some(bottom) = self.stack.pop_back() {
+            self.blocks.insert(bottom, LirsStatus::Hir);
+            self.queue.push_back(bottom);
+        }
+        self.prune();
+    }
+
+    // Drop the HIR blocks below the last LIR block, forgetting the ghosts among them
+    fn prune(&mut self) {
+        while let Some(&bottom) = self.stack.back() {
+            match self.blocks.get(&bottom) {
+                Some(LirsStatus::Lir) => break,
+                Some(LirsStatus::Ghost) | None => {
+                    self.blocks.remove(&bottom);
+                }
+                Some(LirsStatus::Hir) => {}
+            }
+            self.stack.pop_back();
+        }
+    }
+
+    // Forget the oldest ghosts while the stack holds more than capacity blocks
+    fn bound(&mut self, capacity: usize) {
+        while self.stack.len() > capacity {
+            let Some(position) = self.stack.iter().rposition(|tag| self.blocks.get(tag) == Some(&LirsStatus::Ghost)) else {
+                break;
+            };
+            if let Some(tag) = self.stack.remove(position) {
+                self.blocks.remove(&tag);
+            }
+        }
+    }
+}
+
+/// LIRS state of the sets of a cache, with how its accesses went.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Lirs {
+    lir_ways: usize,
+    stack_capacity: usize,
+    sets: Vec<LirsSet>,
+    lir_hits: usize,
+    hir_hits: usize,
+    ghost_hits: usize, // Misses on a ghost, which become LIR blocks
+}
+
+/// State of some sets of a LIRS cache and its counts, saved so that the accesses to those sets can be undone.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct LirsUndo {
+    sets: Vec<(usize, LirsSet)>,
+    counts: [usize; 3], // LIR, HIR and ghost hits
+}
+
+impl Lirs {
+    /// State of sets of ways lines, all but ways / LIRS_HIR_DIVISOR of them, and at least one, holding LIR blocks.
+    pub fn new(sets: usize, ways: usize) -> Lirs {
+        let hir_ways = (ways / LIRS_HIR_DIVISOR).max(1);
+        Lirs {
+            lir_ways: ways.saturating_sub(hir_ways),
+            stack_capacity: LIRS_STACK_FACTOR * ways,
+            sets: vec![LirsSet::default(); sets],
+            lir_hits: 0,
+            hir_hits: 0,
+            ghost_hits: 0,
+        }
+    }
+
+    /// Lines of a set holding LIR blocks once it is warm.
+    pub fn lir_ways(&self) -> usize {
+        self.lir_ways
+    }
+
+    /// Number of sets.
+    pub fn sets(&self) -> usize {
+        self.sets.len()
+    }
+
+    /// Status of a block of a set, or None for a block the set doesn't know about.
+    pub fn status(&self, set: usize, tag: u64) -> Option<LirsStatus> {
+        self.sets[set].blocks.get(&tag).copied()
+    }
+
+    /// Recency stack of a set, most recently accessed block first.
+    pub fn stack(&self, set: usize) -> &VecDeque<u64> {
+        &self.sets[set].stack
+    }
+
+    /// Resident blocks of a set, LIR or HIR.
+    pub fn resident(&self, set: usize) -> impl Iterator<Item = u64> + '_ {
+        self.sets[set].blocks.iter().filter(|(_, &status)| status != LirsStatus::Ghost).map(|(&tag, _)| tag)
+    }
+
+    /// Resident blocks of a set in the order they should be evicted: the queue of HIR blocks, then the LIR blocks
+    /// from the bottom of the stack up.
+    pub fn victims(&self, set: usize) -> impl Iterator<Item = u64> + '_ {
+        let lirs = &self.sets[set];
+        let lir = lirs.stack.iter().rev().filter(|tag| lirs.blocks.get(tag) == Some(&LirsStatus::Lir));
+        lirs.queue.iter().chain(lir).copied()
+    }
+
+    /// Record a hit on a resident block of a set.
+    pub fn hit(&mut self, set: usize, tag: u64) {
+        let lir_ways = self.lir_ways;
+        let lirs = &mut self.sets[set];
+        match lirs.blocks.get(&tag) {
+            Some(LirsStatus::Lir) => {
+                self.lir_hits += 1;
+                lirs.move_to_top(tag);
+                lirs.prune();
+            }
+            Some(LirsStatus::Hir) => {
+                self.hir_hits += 1;
+                // Reused while still in the stack, more recently than the bottom LIR block
+                if lir_ways > 0 && lirs.stack.contains(&tag) {
+                    lirs.queue.retain(|&other| other != tag);
+                    lirs.blocks.insert(tag, LirsStatus::Lir);
+                    lirs.move_to_top(tag);
+                    lirs.demote_bottom();
+                } else {
+                    lirs.move_to_top(tag);
+                    lirs.queue.retain(|&other| other != tag);
+                    lirs.queue.push_back(tag);
+                }
+            }
+            Some(LirsStatus::Ghost) | None => {}
+        }
+        lirs.bound(self.stack_capacity);
+    }
+
+    /// Record the fill of a missing block into a set, after the eviction that made room for it if any.
+    pub fn fill(&mut self, set: usize, tag: u64) {
+        let lir_ways = self.lir_ways;
+        let lirs = &mut self.sets[set];
+        let ghost = lirs.blocks.get(&tag) == Some(&LirsStatus::Ghost);
+        if ghost {
+            self.ghost_hits += 1;
+        }
+        if lirs.lir_blocks() < lir_ways {
+            // Until the LIR blocks fill their share of the set, every block is one
+            lirs.blocks.insert(tag, LirsStatus::Lir);
+            lirs.move_to_top(tag);
+        } else if ghost && lir_ways > 0 {
+            lirs.blocks.insert(tag, LirsStatus::Lir);
+            lirs.move_to_top(tag);
+            lirs.demote_bottom();
+        } else {
+            lirs.blocks.insert(tag, LirsStatus::Hir);
+            lirs.move_to_top(tag);
+            lirs.queue.push_back(tag);
+        }
+        lirs.bound(self.stack_capacity);
+    }
+
+    /// Record that a resident block left a set, evicted or invalidated. An HIR block stays in the stack as a ghost,
+    /// while an LIR block, only evicted when no HIR one could be, is forgotten.
+    pub fn evict(&mut self, set: usize, tag: u64) {
+        let lirs = &mut self.sets[set];
+        match lirs.blocks.get(&tag) {
+            Some(LirsStatus::Hir) => {
+                lirs.queue.retain(|&other| other != tag);
+                if lirs.stack.contains(&tag) {
+                    lirs.blocks.insert(tag, LirsStatus::Ghost);
+                } else {
+                    lirs.blocks.remove(&tag);
+                }
+            }
+            Some(LirsStatus::Lir) => {
+                lirs.blocks.remove(&tag);
+                lirs.stack.retain(|&other| other != tag);
+                lirs.prune();
+            }
+            Some(LirsStatus::Ghost) | None => {}
+        }
+    }
+
+    /// Hits counted so far on LIR blocks.
+    pub fn lir_hits(&self) -> usize {
+        self.lir_hits
+    }
+
+    /// Hits counted so far on resident HIR blocks.
+    pub fn hir_hits(&self) -> usize {
+        self.hir_hits
+    }
+
+    /// Misses counted so far on ghosts.
+    pub fn ghost_hits(&self) -> usize {
+        self.ghost_hits
+    }
+
+    /// Number of ghosts the sets remember.
+    pub fn ghosts(&self) -> usize {
+        self.sets.iter().flat_map(|lirs| lirs.blocks.values()).filter(|&&status| status == LirsStatus::Ghost).count()
+    }
+
+    /// Zero the counts, keeping the stacks and queues.
+    pub fn reset_stats(&mut self) {
+        (self.lir_hits, self.hir_hits, self.ghost_hits) = (0, 0, 0);
+    }
+
+    /// Save the state of some sets and the counts, for [`Lirs::restore`].
+    pub(crate) fn save(&self, sets: &[usize]) -> LirsUndo {
+        LirsUndo {
+            sets: sets.iter().map(|&set| (set, self.sets[set].clone())).collect(),
+            counts: [self.lir_hits, self.hir_hits, self.ghost_hits],
+        }
+    }
+
+    /// Put back the state saved by [`Lirs::save`], undoing the accesses to its sets since.
+    pub(crate) fn restore(&mut self, undo: LirsUndo) {
+        for (set, lirs) in undo.sets {
+            self.sets[set] = lirs;
+        }
+        [self.lir_hits, self.hir_hits, self.ghost_hits] = undo.counts;
+    }
+}
+
+// Tests for Lirs::new
+#[test]
+fn test_lirs_sizes() {
+    assert_eq!((Lirs::new(2, 4).lir_ways(), Lirs::new(2, 16).lir_ways(), Lirs::new(2, 1).lir_ways()), (3, 14, 0));
+    assert_eq!(Lirs::new(2, 4).sets(), 2);
+}
+
+// Tests for Lirs::fill, Lirs::hit and Lirs::evict
+#[test]
+fn test_lirs_transitions() {
+    // Three LIR lines and one HIR line: A, B and C become LIR while the set warms up
+    let mut lirs = Lirs::new(1, 4);
+    for tag in [0xa, 0xb, 0xc, 0xd] {
+        lirs.fill(0, tag);
+    }
+    assert_eq!(lirs.victims(0).collect::<Vec<_>>(), [0xd, 0xa, 0xb, 0xc]);
+    assert_eq!(lirs.stack(0), &[0xd, 0xc, 0xb, 0xa]);
+
+    // D is evicted for E but stays in the stack as a ghost, so its next miss makes it LIR, demoting A at the bottom
+    lirs.evict(0, 0xd);
+    lirs.fill(0, 0xe);
+    assert_eq!((lirs.status(0, 0xd), lirs.status(0, 0xe)), (Some(LirsStatus::Ghost), Some(LirsStatus::Hir)));
+    lirs.evict(0, 0xe);
+    lirs.fill(0, 0xd);
+    assert_eq!((lirs.status(0, 0xd), lirs.status(0, 0xa), lirs.ghost_hits()), (Some(LirsStatus::Lir), Some(LirsStatus::Hir), 1));
+    // A fell out of the stack, B being its new bottom, while E stays a ghost until it falls out too
+    assert_eq!(lirs.stack(0), &[0xd, 0xe, 0xc, 0xb]);
+    lirs.hit(0, 0xb);
+    assert_eq!((lirs.stack(0), lirs.status(0, 0xe)), (&VecDeque::from([0xb, 0xd, 0xe, 0xc]), Some(LirsStatus::Ghost)));
+    lirs.hit(0, 0xc);
+    assert_eq!((lirs.stack(0), lirs.status(0, 0xe)), (&VecDeque::from([0xc, 0xb, 0xd]), None));
+
+    // A is a resident HIR block out of the stack, so a hit keeps it HIR, at the end of the queue
+    lirs.hit(0, 0xa);
+    assert_eq!((lirs.status(0, 0xa), lirs.lir_hits(), lirs.hir_hits()), (Some(LirsStatus::Hir), 2, 1));
+    // Hit again while in the stack, it becomes LIR and D, the bottom LIR block, becomes HIR
+    lirs.hit(0, 0xa);
+    assert_eq!((lirs.status(0, 0xa), lirs.status(0, 0xd)), (Some(LirsStatus::Lir), Some(LirsStatus::Hir)));
+    assert_eq!(lirs.victims(0).collect::<Vec<_>>(), [0xd, 0xb, 0xc, 0xa]);
+    let mut resident: Vec<u64> = lirs.resident(0).collect();
+    resident.sort_unstable();
+    assert_eq!(resident, [0xa, 0xb, 0xc, 0xd]);
+    lirs.reset_stats();
+    assert_eq!((lirs.lir_hits(), lirs.hir_hits(), lirs.ghost_hits()), (0, 0, 0));
+}
+
+// Tests for Lirs::save and Lirs::restore
+#[test]
+fn test_lirs_restore() {
+    let mut lirs = Lirs::new(2, 4);
+    lirs.fill(0, 0xa);
+    lirs.fill(1, 0xb);
+    let undo = lirs.save(&[0]);
+    lirs.hit(0, 0xa);
+    lirs.fill(0, 0xc);
+    lirs.evict(0, 0xa);
+    lirs.restore(undo);
+    assert_eq!((lirs.stack(0), lirs.status(0, 0xa), lirs.status(0, 0xc)), (&VecDeque::from([0xa]), Some(LirsStatus::Lir), None));
+    assert_eq!((lirs.stack(1), lirs.lir_hits()), (&VecDeque::from([0xb]), 0));
+}
@@ -0,0 +1,143 @@
+//! Logging of internal events, such as fills, evictions, replacement decisions and skipped trace lines, filtered by
+//! a level set once for the whole program (`--log-level`). Events are written to stderr as `[debug cache] ...` lines
+//! unless another output is set.
+
+use std::fmt;
+use std::io::{self, Write};
+use std::sync::atomic::{AtomicU8, Ordering};
+use std::sync::Mutex;
+
+use crate::error::SimError;
+
+/// Severity of an event, from the most to the least severe. A level lets through the events of its severity and
+/// the more severe ones.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default)]
+pub enum LogLevel {
+    Off,
+    Error,
+    #[default]
+    Warn,
+    Info,
+    /// Fills, evictions and skipped lines
+    Debug,
+    /// Hits and replacement decisions
+    Trace,
+}
+
+impl LogLevel {
+    const ALL: [LogLevel; 6] = [LogLevel::Off, LogLevel::Error, LogLevel::Warn, LogLevel::Info, LogLevel::Debug, LogLevel::Trace];
+}
+
+impl std::str::FromStr for LogLevel {
+    type Err = SimError;
+
+    fn from_str(value: &str) -> Result<LogLevel, SimError> {
+        LogLevel::ALL.into_iter().find(|level| level.to_string() == value).ok_or(SimError::Config(format!("unknown log level: {}", value)))
+    }
+}
+
+impl fmt::Display for LogLevel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            LogLevel::Off => "off",
+            LogLevel::Error => "error",
+            LogLevel::Warn => "warn",
+            LogLevel::Info => "info",
+            LogLevel::Debug => "debug",
+            LogLevel::Trace => "trace",
+        })
+    }
+}
+
+// Most verbose level let through
+static MAX_LEVEL: AtomicU8 = AtomicU8::new(LogLevel::Warn as u8);
+
+// Where events go, stderr if None
+static OUTPUT: Mutex<Option<Box<dyn Write + Send>>> = Mutex::new(None);
+
+/// Let through the events of a level and the more severe ones.
+pub fn set_log_level(level: LogLevel) {
+    MAX_LEVEL.store(level as u8, Ordering::Relaxed);
+}
+
+/// Most verbose level let through.
+pub fn log_level() -> LogLevel {
+    LogLevel::ALL[MAX_LEVEL.load(Ordering::Relaxed) as usize]
+}
+
+/// Whether events of a level are let through, to skip the work of preparing them otherwise.
+pub fn log_enabled(level: LogLevel) -> bool {
+    level != LogLevel::Off && level as u8 <= MAX_LEVEL.load(Ordering::Relaxed)
+}
+
+/// Write events to an output instead of stderr, or back to stderr for None.
+pub fn set_log_output(output: Option<Box<dyn Write + Send>>) {
+    *OUTPUT.lock().unwrap_or_else(|poisoned| poisoned.into_inner()) = output;
+}
+
+/// Log an event of a part of the simulator, such as "cache" or "trace", if its level is let through.
+pub fn log_event(level: LogLevel, target: &str, message: fmt::Arguments) {
+    if !log_enabled(level) {
+        return;
+    }
+    let mut output = OUTPUT.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+    // Losing an event isn't worth failing the run
+    let _ = match output.as_mut() {
+        Some(output) => writeln!(output, "[{} {}] {}", level, target, message),
+        None => writeln!(io::stderr(), "[{} {}] {}", level, target, message),
+    };
+}
+
+// An output the test can read back
+#[cfg(test)]
+#[derive(Clone, Default)]
+struct SharedOutput(std::sync::Arc<Mutex<Vec<u8>>>);
+
+#[cfg(test)]
+impl Write for SharedOutput {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.lock().unwrap().write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+// Tests for LogLevel
+#[test]
+fn test_log_level() {
+    for level in LogLevel::ALL {
+        assert_eq!(level.to_string().parse::<LogLevel>(), Ok(level));
+    }
+    assert_eq!("verbose".parse::<LogLevel>(), Err(SimError::Config("unknown log level: verbose".to_string())));
+    assert!(LogLevel::Error < LogLevel::Trace);
+}
+
+// Tests for log_event function, the only test changing the global level
+#[test]
+fn test_log_event() {
+    use crate::cache::CacheBuilder;
+
+    let output = SharedOutput::default();
+    set_log_output(Some(Box::new(output.clone())));
+    set_log_level(LogLevel::Debug);
+    assert_eq!(log_level(), LogLevel::Debug);
+    assert!(log_enabled(LogLevel::Info) && !log_enabled(LogLevel::Trace) && !log_enabled(LogLevel::Off));
+
+    log_event(LogLevel::Info, "test", format_args!("event {}", 1));
+    log_event(LogLevel::Trace, "test", format_args!("event {}", 2));
+    let mut cache = CacheBuilder::new().set_index_bits(1).associativity(1).block_bits(4).build().unwrap();
+    cache.simulate_address('L', 0x1240).unwrap();
+    cache.simulate_address('S', 0x4560).unwrap();
+
+    set_log_level(LogLevel::Warn);
+    set_log_output(None);
+    // Other tests may log at the same time, so only look for these events
+    let logged = String::from_utf8(output.0.lock().unwrap().clone()).unwrap();
+    assert!(logged.contains("[info test] event 1\n"));
+    assert!(!logged.contains("event 2"));
+    assert!(logged.contains("[debug cache] fill set:0 way:0 tag:0x92\n"));
+    assert!(logged.contains("[debug cache] evict set:0 way:0 tag:0x92\n"));
+    assert!(logged.contains("[debug cache] fill set:0 way:0 tag:0x22b dirty\n"));
+}
@@ -1,300 +1,2107 @@
 use std::env;
 extern crate getopt;
 use getopt::Opt;
-use std::collections::{HashMap, VecDeque};
-use std::fs::File;
-use std::io::{BufRead, BufReader};
-
-struct Line {
-    tag: Option<usize>,
-    is_valid: bool,
-}
-
-struct Set {
-    lines: Vec<Line>,
-    access_order: VecDeque<usize>,
-}
-
-struct Cache {
-    sets: Vec<Set>,
-    hits: usize,
-    misses: usize,
-    evictions: usize,
-}
-
-impl Cache {
-    // Constructor for Cache struct
-    fn new(s: usize, e: usize, b: usize) -> Result<Cache, String> {
-        // Calculate total cache size: 2^s * 2^b * E
-        match usize::checked_pow(2, s.try_into().unwrap()).and_then(|sets| {
-            usize::checked_pow(2, b.try_into().unwrap()).and_then(|blocks| {
-                sets.checked_mul(blocks).and_then(|sets_blocks| sets_blocks.checked_mul(e))})
-        }) {
-            Some(_size) => {
-                let mut sets = Vec::with_capacity(2usize.pow(s as u32));
-                for _ in 0..2usize.pow(s as u32) {
-                    let mut lines = Vec::with_capacity(e);
-                    for _ in 0..e {
-                        lines.push(Line { 
-                            tag: None, 
-                            is_valid: false 
-                        });
-                    }
-                    sets.push(Set { 
-                        lines: lines, 
-                        access_order: VecDeque::new() 
-                    });
-                }
-                Ok(Cache { 
-                    sets, 
-                    hits: 0, 
-                    misses: 0, 
-                    evictions: 0 
-                })
-            }
-            None => {
-                return Err("cache size exceeds available space (overflow)".to_string());
-            }
+use std::collections::HashMap;
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufWriter, Write};
+use std::ops::RangeInclusive;
+use std::process::ExitCode;
+use std::thread;
+
+use sim::{parse_address_range, parse_address_ranges, parse_way_locks, parse_bytes, parse_address_offset, parse_remap_regions, AddressRemap, format_bench, run_bench, DEFAULT_BENCH_LENGTH, compare_policies, compare_with_program, compare_with_reference, completion_script, decompose_address, set_log_level, LogLevel, ProgressReporter, CommandLine, OptionValue, Shell, convert_accesses, Hierarchy, HierarchyConfig, Interleave, MultiCore, debug_accesses, listen, serve, DEFAULT_SERVICE_ADDRESS, simulate_accesses_live, Dashboard, tui_accesses, format_working_set, simulate_paging, PageReplacement, DEFAULT_PAGE_SIZE, detect_false_sharing, DEFAULT_SHARING_TOP, repl, reuse_histogram, working_set_curve, write_set_heatmap, Generator, Kernel, MatrixKernel, MemoryAccess, Pattern, TraceWriter, DEFAULT_GEN_ACCESS_SIZE, format_sweep, locality_demo, simulate_accesses, simulate_accesses_sampled, sweep_accesses, Cache, CacheBuilder, Insertion, OobPolicy, Replacement, Sampling, SimError, Stats, TieBreak, WayLock, WayPredictor, CSV_HEADER, DEFAULT_DEMO_ELEMENT_SIZE, DEFAULT_DEMO_N, DEFAULT_DIVERGENCES, DEFAULT_DRAIN_INTERVAL, DEFAULT_SEED, DEFAULT_UNDO_DEPTH, HOT_BLOCK_CAPACITY, STDIN_TRACEFILE, TraceAccesses, TraceFormat};
+
+const USAGE: &str = "Usage: -- -s <set index bits> -E <lines in set> -b <block bits> -t <tracefile> [-t <tracefile>...] [options]
+       -- --sweep <s:E:b,...> -t <tracefile> [options]
+       -- --config <file> -t <tracefile> [options]
+       -- -s <set index bits> -E <lines in set> -b <block bits> -t <tracefile> --compare-ref <program>
+       -- -s <set index bits> -E <lines in set> -b <block bits> -t <tracefile> --compare-policies <p,p,...> [options]
+       -- convert -t <tracefile> -o <file> [--format <f>] [--lenient]
+       -- reuse -t <tracefile> -b <block bits> [--format <f>] [--lenient]
+       -- working-set -t <tracefile> -b <block bits> --window <n> [--step <n>] [--format <f>] [--lenient]
+       -- paging -t <tracefile> --frames <n> [--page-size <bytes>] [--page-policy <p>] [--format <f>] [--lenient]
+       -- false-sharing -t <tracefile> -b <block bits> [--top <n>] [--format <f>] [--lenient]
+       -- gen --pattern <pattern> --footprint <bytes> --length <n> [-o <file>] [options]
+       -- gen --kernel <kernel> --n <n> [--block <b>] [--element-size <bytes>] [-o <file>] [--format <f>]
+       -- serve [--listen <addr>]
+       -- repl -s <set index bits> -E <lines in set> -b <block bits> [cache options]
+       -- decode -s <set index bits> -b <block bits> <address>...
+       -- bench -s <set index bits> -E <lines in set> -b <block bits> [--length <n>] [cache options]
+       -- completions <bash|zsh|fish>
+       -- demo locality -s <set index bits> -E <lines in set> -b <block bits> [--n <n>] [--element-size <bytes>] [options]
+Options:
+  --config <file>     Read the cache, or a hierarchy of cache levels with their latencies, from a TOML or JSON file
+                      instead of -s, -E, -b and the cache options
+  --interleave <order>
+                      Replay one -t tracefile per core through private copies of the first --config levels in front
+                      of the shared ones, interleaved round-robin or by timestamp (each core's simulated cycles)
+  --policy <p>        Replacement policy: lru (default), fifo, mru, slru (segmented LRU), 2q (A1in, A1out and Am
+                      queues), lirs (low inter-reference recency set), random, plru (tree pseudo-LRU, power-of-two
+                      E), srrip, brrip or drrip (RRIP, the last dueling between the first two), ship-pc or ship-mem
+                      (SRRIP predicting dead fills from their instruction or 16 KiB region), or hawkeye (learning
+                      from OPT replayed on sampled sets which instructions' lines to keep)
+  --insertion <p>     Position of filled lines in the LRU or plru recency order: mru (default), lip (least recently
+                      used) or bip (lip but mru once every 32 fills)
+  --protected-ways <n>
+                      Lines of each set in the protected segment of slru, fewer than E (default E/2)
+  --adaptive <p,q>    Switch between two of lru, fifo, mru, random, srrip and brrip at runtime instead of --policy:
+                      leader sets duel for them, the others follow whichever missed less, and switches are logged
+                      at info level
+  --tie-break <rule>  Choice between equally eligible lines: lowest (default), random, round-robin
+  --seed <n>          Seed for random choices
+  --skewed            Skewed-associative cache: each way indexes the sets with its own hash
+  --way-predict <p>   Simulate a way predictor and report fast/slow hits: mru, partial-tag
+  --addr-bits <n>     Modeled address width in bits (default 64)
+  --oob-addr <p>      Handling of addresses above the address width: mask, skip, error (default)
+  --partition <spec>  Ways each thread may fill, e.g. 0:0-1,1:2-3 (thread ids come from an optional third trace field)
+  --uncacheable <ranges>
+                      Hexadecimal address ranges whose accesses bypass the cache and are only counted, such as
+                      memory-mapped devices, e.g. 0xfe000000-0xfeffffff,0xa0000-0xbffff
+  --lock-ways <spec>  Ways locked against replacement once filled, in all sets or in a range of sets,
+                      e.g. 0-1 or 3@0-15
+  --scratchpad <range>
+                      Hexadecimal address range served by a scratchpad that always hits, taking its size from
+                      the ways of the cache, e.g. 0x10000-0x17fff
+  --banks <n>         Split the cache into n banks by low block bits and count bank conflicts
+  --issue-width <n>   Accesses issued per cycle when counting bank conflicts (default 1)
+  --write-buffer <n>  Hold up to n evicted dirty blocks in a write-back buffer, refilling misses from it
+  --drain-interval <n>
+                      Lookups between two blocks the write-back buffer drains to memory (default 10)
+  --store-buffer <n>  Put a store buffer of n blocks in front of the cache, coalescing stores and forwarding loads
+  --bypass <size>     Bypass the fills predicted dead with a reuse counter per region of size bytes, e.g. 4K
+  --tinylfu           Only let a miss evict a line when a TinyLFU frequency sketch estimates its block to be accessed
+                      more often than the line's
+  --compress <p>      Compressed cache where a fraction p of the blocks take half a line
+  --classify-misses   Classify misses as compulsory, capacity or conflict misses
+  --lifetimes         Report how many lookups evicted lines survived: min, mean, percentiles and max
+  --debug             Step through the trace interactively, reading commands from stdin
+  --tui               Step through the trace like --debug, redrawing the contents of the sets after every access
+  --undo-depth <n>    Accesses the debugger can step back over (default 100)
+  --warmup <n>        Simulate the first n accesses without counting them in the statistics
+  --sample-period <n> Sampled simulation: only simulate windows at the start of every n accesses and extrapolate
+  --sample-length <n> Accesses in each sampled window
+  --sweep <configs>   Simulate several configurations in one pass over the trace, e.g. 4:2:4,5:1:5 (s:E:b), and compare them
+  --checkpoint <file> Save the cache state to a file after the run
+  --restore <file>    Start from a saved cache state instead of an empty cache (other cache options come from the file)
+  --format <f>        Tracefile format: lackey (default), din (DineroIV), or generic:<columns>[:<separator>] naming
+                      the columns op, addr, size, pc, tid or - in order, separated by ws (default), csv or tab
+  --output <f>        Results format: text (default), or csv for a header and one row per run with the configuration
+                      and every statistic
+  --append <file>     Append the csv rows to a file instead of printing them, writing the header only to a new file
+  --per-set           Also print the hits, misses and evictions of every set that was accessed
+  --top <n>           Also print the n most looked-up blocks with their hit rates (false-sharing: the n most falsely
+                      shared blocks, default 10)
+  --heatmap <file>    Write an SVG heatmap of the misses of every set to a file
+  --serve <addr>      Serve live statistics and a chart at an address such as 127.0.0.1:8080 during the run, and
+                      keep serving the final results until interrupted
+  --compare-ref <program>
+                      Run a reference simulator taking csim's options, such as csim-ref, verbosely on the tracefile and
+                      report the first access whose outcome differs, exiting with 1 if one does
+  --compare-expected <file>
+                      Compare with a saved verbose output of a reference, or only its hits:X misses:Y evictions:Z line
+  --compare-policies <p,p,...>
+                      Simulate several replacement policies side by side, printing the statistics of each one and the
+                      first accesses whose outcomes differ between them
+  --divergences <n>   Diverging accesses --compare-policies prints (default 10)
+  --keep-cache        With several -t tracefiles, simulate each one on the cache the previous one left instead of an
+                      empty cache
+  --quiet             Don't report the progress of long runs on stderr
+  --log-level <level> Log internal events on stderr up to a level: off, error, warn (default), info, debug for fills,
+                      evictions and skipped lines, trace for hits and tie-breaks; also taken by the subcommands
+  --errors <format>   Report errors on stderr as text (default) or as one JSON object each: json; also taken by the
+                      subcommands
+  --lenient           Skip and count trace lines that can't be parsed instead of stopping at the first one
+  --addr-offset <n>   Hexadecimal offset added to every trace address, e.g. -7ff000000000, to line up traces captured
+                      under different ASLR layouts
+  --remap <regions>   Move address ranges of the trace to new start addresses before indexing, e.g.
+                      7ffd0000-7ffdffff=7ff000000000; --addr-offset applies to the addresses outside them
+  --icache            Simulate the instruction fetches of the trace (Lackey I lines, din label 2 or a generic I/ifetch
+                      operation) as loads instead of its data accesses, to study an instruction cache
+Serve options:
+  --listen <addr>     Address the JSON-RPC service listens on (default 127.0.0.1:7878)
+Working-set options:
+  --window <n>        Accesses in the sliding window whose distinct blocks are counted
+  --step <n>          Accesses between two points of the working-set curve (default: the window length)
+Paging options:
+  --frames <n>        Frames of physical memory the pages of the trace are loaded into
+  --page-size <n>     Bytes per page, a power of two with an optional K, M or G suffix (default 4K)
+  --page-policy <p>   Page replacement policy: lru (default), fifo, clock, second-chance
+Convert, gen and bench options:
+  -o <file>           Trace written by convert (binary) or gen (in the --format format, stdout by default), - for stdout
+  --pattern <p>       Access pattern of gen: sequential, random, strided, pointer-chase
+  --footprint <n>     Bytes touched by gen, with an optional K, M or G suffix
+  --length <n>        Accesses generated by gen, or simulated per workload by bench (default 2000000)
+  --access-size <n>   Bytes per generated access (default 8)
+  --stride <n>        Bytes between accesses of the strided pattern, or between pointer-chase nodes (default: access size)
+  --write-ratio <p>   Share of generated accesses that are stores (default 0)
+  --kernel <k>        Matrix kernel of gen over n x n matrices (--n, --element-size): transpose, multiply
+  --block <b>         Block factor of a tiled matrix kernel (default: naive, untiled)
+Demo options:
+  --n <n>             Matrix size of the locality demo (default 512) or of a gen kernel
+  --element-size <n>  Element size in bytes of the locality demo or of a gen kernel (default 8)
+Environment:
+  CACHESIM_S, CACHESIM_E, CACHESIM_B, CACHESIM_T, CACHESIM_CONFIG, CACHESIM_POLICY, CACHESIM_TIE_BREAK, CACHESIM_SEED,
+  CACHESIM_ADDR_BITS, CACHESIM_OOB_ADDR, CACHESIM_WARMUP, CACHESIM_FORMAT, CACHESIM_OUTPUT, CACHESIM_APPEND, CACHESIM_LENIENT (1 or 0) and
+  CACHESIM_LOG_LEVEL give defaults for the matching options of a simulation; options on the command line take precedence
+Exit status:
+  0 on success, 2 for invalid arguments, 3 for a tracefile that can't be opened or read, 4 for a malformed tracefile and
+  1 for any other error";
+
+// Parsed command-line parameters
+#[derive(Debug, PartialEq, Default)]
+struct Args {
+    s: usize,
+    e: usize,
+    b: usize,
+    t: String,
+    extra_traces: Vec<String>, // Tracefiles of further -t flags, simulated after the first one
+    keep_cache: bool, // Carry the cache contents over from one tracefile to the next
+    compare_ref: Option<String>, // Reference simulator run on the tracefile to compare with
+    compare_expected: Option<String>, // Saved output of a reference to compare with
+    config: Option<HierarchyConfig>, // Cache levels read from --config instead of -s, -E, -b and the cache options
+    interleave: Option<Interleave>, // Replay the tracefiles together, one per core, through the --config levels
+    replacement: Replacement,
+    insertion: Insertion, // Position of filled lines in the recency order
+    protected_ways: Option<usize>, // Lines of each set in the protected segment of SLRU
+    adaptive: Option<[Replacement; 2]>, // Policies switched between at runtime by set dueling
+    compare_policies: Vec<Replacement>, // Policies simulated side by side
+    divergences: Option<usize>, // Diverging accesses printed when comparing policies
+    tie_break: TieBreak,
+    seed: Option<u64>,
+    skewed: bool,
+    way_predictor: Option<WayPredictor>,
+    address_bits: Option<usize>,
+    oob_policy: OobPolicy,
+    way_partitions: HashMap<usize, Vec<usize>>,
+    uncacheable: Vec<RangeInclusive<u64>>, // Address ranges that bypass the cache
+    locked_ways: Vec<WayLock>,
+    scratchpad: Option<RangeInclusive<u64>>,
+    banks: Option<usize>,
+    issue_width: Option<usize>,
+    write_buffer: Option<usize>, // Entries of the write-back buffer
+    drain_interval: Option<u64>,
+    store_buffer: Option<usize>, // Entries of the store buffer
+    bypass_region: Option<u64>, // Region size of the bypass predictor
+    tinylfu: bool, // Gate the fills that would evict with a TinyLFU admission filter
+    compressibility: Option<f64>,
+    classify_misses: bool,
+    lifetimes: bool,
+    debug: bool,
+    tui: bool, // Debug in a redrawn view of the sets
+    undo_depth: Option<usize>,
+    warmup: Option<usize>,
+    sweep: Vec<(usize, usize, usize)>, // (s, E, b) of each configuration of a sweep
+    sample_period: Option<usize>,
+    sample_length: Option<usize>,
+    checkpoint: Option<String>,
+    restore: Option<String>,
+    format: TraceFormat,
+    icache: bool, // Simulate the instruction fetches instead of the data accesses
+    remap: AddressRemap, // Rewriting of the trace addresses given by --addr-offset and --remap
+    lenient: bool,
+    quiet: bool, // Don't report progress
+    log_level: Option<LogLevel>, // Most verbose internal events logged, for every mode
+    csv: bool, // Print results as CSV rows
+    append: Option<String>, // File the CSV rows are appended to
+    per_set: bool,
+    top: Option<usize>, // Number of hottest blocks to report
+    heatmap: Option<String>, // SVG file the per-set miss heatmap is written to
+    serve: Option<String>, // Address the live dashboard listens on
+    convert: bool, // Convert the tracefile to a binary trace instead of simulating it
+    reuse: bool, // Print the reuse-distance histogram of the tracefile instead of simulating it
+    working_set: bool, // Print the working-set curve of the tracefile instead of simulating it
+    paging: bool, // Simulate the page faults of the tracefile instead of its cache accesses
+    false_sharing: bool, // Report the false sharing between the threads of the tracefile instead of simulating it
+    frames: Option<usize>,
+    page_size: Option<u64>,
+    page_policy: Option<PageReplacement>,
+    decode: bool, // Print the tag, set index and offset of addresses instead of simulating
+    addresses: Vec<u64>, // Addresses given to decode
+    window: Option<usize>,
+    step: Option<usize>,
+    output: Option<String>, // Trace written by convert or gen
+    gen: bool, // Write a synthetic trace instead of simulating one
+    pattern: Option<Pattern>,
+    footprint: Option<u64>,
+    length: Option<usize>,
+    access_size: Option<u32>,
+    stride: Option<u64>,
+    write_ratio: Option<f64>,
+    kernel: Option<Kernel>,
+    block: Option<usize>,
+    repl: bool, // Simulate accesses typed on stdin instead of a tracefile
+    bench: bool, // Time the simulation of synthetic workloads instead of a tracefile
+    service: bool, // Serve caches to JSON-RPC clients instead of simulating a tracefile
+    listen: Option<String>,
+    completions: Option<Shell>, // Print the completion script of a shell instead of simulating a tracefile
+    demo: bool, // Run the locality demo instead of a tracefile
+    demo_n: Option<usize>,
+    element_size: Option<usize>,
+}
+
+// Long options accepted alongside the short getopt flags: (name, takes a value)
+const LONG_OPTIONS: &[(&str, bool)] = &[
+    ("config", true),
+    ("interleave", true),
+    ("policy", true),
+    ("insertion", true),
+    ("protected-ways", true),
+    ("adaptive", true),
+    ("tie-break", true),
+    ("seed", true),
+    ("skewed", false),
+    ("way-predict", true),
+    ("addr-bits", true),
+    ("oob-addr", true),
+    ("partition", true),
+    ("uncacheable", true),
+    ("lock-ways", true),
+    ("scratchpad", true),
+    ("banks", true),
+    ("issue-width", true),
+    ("write-buffer", true),
+    ("drain-interval", true),
+    ("store-buffer", true),
+    ("bypass", true),
+    ("tinylfu", false),
+    ("compress", true),
+    ("classify-misses", false),
+    ("lifetimes", false),
+    ("debug", false),
+    ("tui", false),
+    ("undo-depth", true),
+    ("warmup", true),
+    ("sweep", true),
+    ("sample-period", true),
+    ("sample-length", true),
+    ("checkpoint", true),
+    ("restore", true),
+    ("format", true),
+    ("lenient", false),
+    ("icache", false),
+    ("addr-offset", true),
+    ("remap", true),
+    ("keep-cache", false),
+    ("compare-ref", true),
+    ("compare-expected", true),
+    ("compare-policies", true),
+    ("divergences", true),
+    ("quiet", false),
+    ("log-level", true),
+    ("errors", true),
+    ("output", true),
+    ("append", true),
+    ("per-set", false),
+    ("top", true),
+    ("heatmap", true),
+    ("serve", true),
+    ("listen", true),
+    ("window", true),
+    ("step", true),
+    ("frames", true),
+    ("page-size", true),
+    ("page-policy", true),
+    ("pattern", true),
+    ("footprint", true),
+    ("length", true),
+    ("access-size", true),
+    ("stride", true),
+    ("write-ratio", true),
+    ("kernel", true),
+    ("block", true),
+    ("n", true),
+    ("element-size", true),
+];
+
+// Exit codes telling failures apart, for scripts
+const EXIT_FAILURE: u8 = 1; // Any other error, such as an invalid cache or results that can't be written
+const EXIT_USAGE: u8 = 2;
+const EXIT_TRACE_READ: u8 = 3;
+const EXIT_TRACE_PARSE: u8 = 4;
+
+// Environment variables giving defaults for the options of a simulation, and the option each one stands for
+const ENV_OPTIONS: &[(&str, &str)] = &[
+    ("CACHESIM_S", "-s"),
+    ("CACHESIM_E", "-E"),
+    ("CACHESIM_B", "-b"),
+    ("CACHESIM_T", "-t"),
+    ("CACHESIM_CONFIG", "--config"),
+    ("CACHESIM_POLICY", "--policy"),
+    ("CACHESIM_TIE_BREAK", "--tie-break"),
+    ("CACHESIM_SEED", "--seed"),
+    ("CACHESIM_ADDR_BITS", "--addr-bits"),
+    ("CACHESIM_OOB_ADDR", "--oob-addr"),
+    ("CACHESIM_WARMUP", "--warmup"),
+    ("CACHESIM_FORMAT", "--format"),
+    ("CACHESIM_OUTPUT", "--output"),
+    ("CACHESIM_APPEND", "--append"),
+    ("CACHESIM_LENIENT", "--lenient"),
+    ("CACHESIM_LOG_LEVEL", "--log-level"),
+];
+
+// Flags giving the cache geometry, which --config and --sweep replace
+const GEOMETRY_FLAGS: &[&str] = &["-s", "-E", "-b"];
+
+// Options a --config file replaces
+const CONFIG_CACHE_OPTIONS: &[&str] = &["--policy", "--tie-break", "--seed", "--addr-bits", "--oob-addr"];
+
+// Subcommands, which don't take defaults from the environment
+const SUBCOMMANDS: &[&str] = &["convert", "reuse", "working-set", "paging", "false-sharing", "gen", "serve", "repl", "decode", "completions", "demo", "bench"];
+
+// Values completion scripts offer for options, other than the numbers and names they can't guess
+const OPTION_VALUES: &[(&str, OptionValue)] = &[
+    ("-t", OptionValue::File),
+    ("-o", OptionValue::File),
+    ("--config", OptionValue::File),
+    ("--checkpoint", OptionValue::File),
+    ("--restore", OptionValue::File),
+    ("--append", OptionValue::File),
+    ("--heatmap", OptionValue::File),
+    ("--compare-ref", OptionValue::File),
+    ("--compare-expected", OptionValue::File),
+    ("--policy", OptionValue::OneOf(&["lru", "fifo", "mru", "slru", "2q", "lirs", "random", "plru", "srrip", "brrip", "drrip", "ship-pc", "ship-mem", "hawkeye"])),
+    ("--insertion", OptionValue::OneOf(&["mru", "lip", "bip"])),
+    ("--tie-break", OptionValue::OneOf(&["lowest", "random", "round-robin"])),
+    ("--way-predict", OptionValue::OneOf(&["mru", "partial-tag"])),
+    ("--oob-addr", OptionValue::OneOf(&["mask", "skip", "error"])),
+    ("--format", OptionValue::OneOf(&["lackey", "din", "generic:"])),
+    ("--output", OptionValue::OneOf(&["text", "csv"])),
+    ("--log-level", OptionValue::OneOf(&["off", "error", "warn", "info", "debug", "trace"])),
+    ("--errors", OptionValue::OneOf(&["text", "json"])),
+    ("--pattern", OptionValue::OneOf(&["sequential", "random", "strided", "pointer-chase"])),
+    ("--kernel", OptionValue::OneOf(&["transpose", "multiply"])),
+    ("--page-policy", OptionValue::OneOf(&["lru", "fifo", "clock", "second-chance"])),
+    ("--interleave", OptionValue::OneOf(&["round-robin", "timestamp"])),
+];
+
+// Short flags that take a value, so a following "--..." argument is treated as that value
+const SHORT_FLAGS_WITH_VALUE: &[&str] = &["-s", "-E", "-b", "-t"];
+
+// A long option name with its value, if it takes one
+type LongOption = (String, Option<String>);
+
+// Separate long options from the arguments handed over to getopt
+fn split_long_options(args: &[String]) -> Result<(Vec<String>, Vec<LongOption>), SimError> {
+    let mut short_args = Vec::new();
+    let mut long_opts = Vec::new();
+    let mut iter = args.iter().enumerate();
+
+    while let Some((index, arg)) = iter.next() {
+        let follows_short_flag = index > 0 && SHORT_FLAGS_WITH_VALUE.contains(&args[index - 1].as_str());
+        if index == 0 || follows_short_flag || !arg.starts_with("--") || arg == "--" {
+            short_args.push(arg.clone());
+            continue;
         }
-    }
 
-    // Apply cache simulation logic based on operation and update cache and statistics
-    fn simulate_memory_access(&mut self, operation: char, set_index: usize, tag: usize) -> Result<(), String> {
-        match operation {
-            'L' | 'S' => {
-                if set_index >= self.sets.len() {
-                    return Err("failed to access cache set".to_string());
-                }
+        let (name, inline_value) = match arg[2..].split_once('=') {
+            Some((name, value)) => (name, Some(value.to_string())),
+            None => (&arg[2..], None),
+        };
+        let takes_value = match LONG_OPTIONS.iter().find(|(option, _)| *option == name) {
+            Some((_, takes_value)) => *takes_value,
+            None => return Err(SimError::ParseArgs(format!("unknown option: --{}", name))),
+        };
+        let value = match (takes_value, inline_value) {
+            (true, Some(value)) => Some(value),
+            (true, None) => match iter.next() {
+                Some((_, value)) => Some(value.clone()),
+                None => return Err(SimError::ParseArgs(format!("missing value for --{}", name))),
+            },
+            (false, Some(_)) => return Err(SimError::ParseArgs(format!("--{} does not take a value", name))),
+            (false, None) => None,
+        };
+        long_opts.push((name.to_string(), value));
+    }
 
-                let mut found_empty_line = false;
+    Ok((short_args, long_opts))
+}
 
-                for index in 0..self.sets[set_index].lines.len() { 
-                    if index >= self.sets[set_index].lines.len() {
-                        return Err("failed to access cache line".to_string());
-                    }
+// Parse a way partitioning such as "0:0-1,1:2-3" into the ways assigned to each thread
+fn parse_way_partitions(value: &str) -> Result<HashMap<usize, Vec<usize>>, SimError> {
+    let mut partitions = HashMap::new();
+    for partition in value.split(',') {
+        let (thread, ways) = partition.split_once(':').ok_or(SimError::ParseArgs(format!("invalid partition: {}", partition)))?;
+        let thread: usize = thread.parse().map_err(|e| SimError::ParseArgs(format!("invalid thread in partition {} ({})", partition, e)))?;
+        let (first, last) = ways.split_once('-').unwrap_or((ways, ways));
+        let first: usize = first.parse().map_err(|e| SimError::ParseArgs(format!("invalid ways in partition {} ({})", partition, e)))?;
+        let last: usize = last.parse().map_err(|e| SimError::ParseArgs(format!("invalid ways in partition {} ({})", partition, e)))?;
+        if first > last {
+            return Err(SimError::ParseArgs(format!("invalid ways in partition {}", partition)));
+        }
+        if partitions.insert(thread, (first..=last).collect()).is_some() {
+            return Err(SimError::ParseArgs(format!("duplicate partition for thread {}", thread)));
+        }
+    }
+    Ok(partitions)
+}
 
-                    if self.sets[set_index].lines[index].is_valid {
-                        // If the line is not empty, compare the tags - if they match, it's a hit
-                        if self.sets[set_index].lines[index].tag.unwrap() == tag {
-                            self.record_hit();
-                            self.update_access_order(set_index, index);
-                            return Ok(());
-                        }
-                    } else {
-                        // If the line is empty, the tag has not been found - it's a miss and update the line properties
-                        found_empty_line = true;
-                        self.sets[set_index].lines[index].tag = Some(tag);
-                        self.sets[set_index].lines[index].is_valid = true;
-                        self.record_miss();
-                        self.update_access_order(set_index, index);
-                        break;
-                    }
-                }
+// Report an invalid option value as a command-line error
+fn invalid_option(err: SimError) -> SimError {
+    SimError::ParseArgs(err.message().to_string())
+}
 
-                // If no hit happened and no empty line was found, evict the LRU line - it's an eviction and update the line tag
-                if !found_empty_line {
-                    if let Some(evict_index) = self.sets[set_index].access_order.pop_back() {
-                        self.sets[set_index].lines[evict_index].tag = Some(tag);
-                        self.record_miss();
-                        self.record_eviction();
-                        self.update_access_order(set_index, evict_index);
-                        return Ok(());
-                    }
-                    return Err("eviction failed".to_string());
-                }
-                return Ok(());
-            }
-            'M' => {
-                // Simulate Load operation followed by Store operation
-                self.simulate_memory_access('L', set_index, tag)?;
-                self.simulate_memory_access('S', set_index, tag)?;
-                return Ok(());
-            }
-            _ => {
-                return Err(format!("unknown operation: {}", operation));
-            }
-        }
+// Generator given by the gen options
+fn generator(params: &Args) -> Result<Generator, SimError> {
+    match (params.pattern, params.footprint, params.length) {
+        (Some(pattern), Some(footprint), Some(length)) => Ok(Generator {
+            pattern,
+            footprint,
+            length,
+            access_size: params.access_size.unwrap_or(DEFAULT_GEN_ACCESS_SIZE),
+            stride: params.stride.unwrap_or(0),
+            write_ratio: params.write_ratio.unwrap_or(0.0),
+            seed: params.seed.unwrap_or(DEFAULT_SEED),
+        }),
+        _ => Err(SimError::ParseArgs("gen requires --pattern, --footprint and --length, or --kernel and --n".to_string())),
     }
+}
 
-    // Update the LRU order based on the accessed line
-    fn update_access_order(&mut self, set_index: usize, accessed_index: usize) {
-        let access_order = &mut self.sets[set_index].access_order;
+// Matrix kernel given by the gen options
+fn matrix_kernel(params: &Args) -> Result<MatrixKernel, SimError> {
+    match (params.kernel, params.demo_n) {
+        (Some(kernel), Some(n)) => Ok(MatrixKernel {
+            kernel,
+            n,
+            block: params.block,
+            element_size: params.element_size.unwrap_or(DEFAULT_DEMO_ELEMENT_SIZE) as u32,
+        }),
+        _ => Err(SimError::ParseArgs("gen --kernel requires --n".to_string())),
+    }
+}
 
-        if let Some(position) = access_order.iter().position(|&i| i == accessed_index) { 
-            access_order.remove(position); // Remove accessed_index if it exists
+// Parse sweep configurations such as "4:2:4,5:1:5" into (s, E, b) triples
+fn parse_sweep(value: &str) -> Result<Vec<(usize, usize, usize)>, SimError> {
+    value.split(',').map(|config| {
+        let parts = config.split(':').map(|part| part.parse::<usize>()).collect::<Result<Vec<usize>, _>>()
+            .map_err(|e| SimError::ParseArgs(format!("invalid sweep configuration {} ({})", config, e)))?;
+        match parts[..] {
+            [s, e, b] => Ok((s, e, b)),
+            _ => Err(SimError::ParseArgs(format!("invalid sweep configuration {} (expected s:E:b)", config))),
         }
-        access_order.push_front(accessed_index); // Add accessed_index at the back
-    }
+    }).collect()
+}
 
-    // Increase cache hits count
-    fn record_hit(&mut self) {
-        self.hits += 1;
+// Sampling windows given by --sample-period and --sample-length, which go together
+fn sampling(params: &Args) -> Result<Option<Sampling>, SimError> {
+    match (params.sample_period, params.sample_length) {
+        (Some(period), Some(length)) => Ok(Some(Sampling { period, length })),
+        (None, None) => Ok(None),
+        _ => Err(SimError::ParseArgs("--sample-period and --sample-length must be given together".to_string())),
     }
+}
 
-    // Increase cache misses count
-    fn record_miss(&mut self) {
-        self.misses += 1;
+// Append the options the environment gives defaults for to the command-line arguments of a simulation, unless the
+// command line already gives them. `var` looks up an environment variable
+fn with_env_defaults(args: &[String], var: impl Fn(&str) -> Option<String>) -> Result<Vec<String>, SimError> {
+    let mut args = args.to_vec();
+    if args.get(1).is_some_and(|arg| SUBCOMMANDS.contains(&arg.as_str())) {
+        return Ok(args);
     }
+    let (short_args, long_opts) = split_long_options(&args)?;
+    let short_flags: Vec<char> = getopt::Parser::new(&short_args, "s:E:b:t:o:").filter_map(|opt| opt.ok()).map(|Opt(flag, _)| flag).collect();
+    let given = |option: &str| match option.strip_prefix("--") {
+        Some(name) => long_opts.iter().any(|(long_name, _)| long_name == name),
+        None => option.chars().nth(1).is_some_and(|flag| short_flags.contains(&flag)),
+    };
+    // Options of the environment that options of the command line replace
+    let replaced = |option: &str| match option {
+        "-s" | "-E" | "-b" => given("--config") || given("--sweep"),
+        "--config" => given("--sweep") || GEOMETRY_FLAGS.iter().any(|flag| given(flag)),
+        _ => given("--config") && CONFIG_CACHE_OPTIONS.contains(&option),
+    };
 
-    // Increase cache evictions count
-    fn record_eviction(&mut self) {
-        self.evictions += 1;
+    let mut env_args = Vec::new();
+    for &(name, option) in ENV_OPTIONS {
+        let value = match var(name) {
+            Some(value) => value,
+            None => continue,
+        };
+        if given(option) || replaced(option) {
+            continue;
+        }
+        if option == "--lenient" {
+            match value.as_str() {
+                "1" | "true" => env_args.push(option.to_string()),
+                "0" | "false" | "" => {}
+                _ => return Err(SimError::ParseArgs(format!("invalid value for {} ({}), expected 1 or 0", name, value))),
+            }
+        } else {
+            env_args.push(option.to_string());
+            env_args.push(value);
+        }
     }
+    args.extend(env_args);
+    Ok(args)
+}
 
-    // Print cache statistics
-    fn print_stats(&self) {
-        println!("hits:{} misses:{} evictions:{}", self.hits, self.misses, self.evictions);
+// Take the addresses out of the arguments of decode: the arguments that are neither options nor their values, in
+// hex with an optional 0x
+fn take_addresses(args: &mut Vec<String>) -> Result<Vec<u64>, SimError> {
+    let mut addresses = Vec::new();
+    let mut index = 1;
+    while index < args.len() {
+        let arg = args[index].as_str();
+        let takes_value = SHORT_FLAGS_WITH_VALUE.contains(&arg)
+            || arg.strip_prefix("--").is_some_and(|name| LONG_OPTIONS.iter().any(|(option, takes_value)| *option == name && *takes_value));
+        if takes_value {
+            index += 2;
+        } else if arg.starts_with('-') {
+            index += 1;
+        } else {
+            let arg = args.remove(index);
+            let digits = arg.strip_prefix("0x").or_else(|| arg.strip_prefix("0X")).unwrap_or(&arg);
+            addresses.push(u64::from_str_radix(digits, 16).map_err(|e| SimError::ParseArgs(format!("invalid address {} ({})", arg, e)))?);
+        }
     }
+    Ok(addresses)
 }
 
 // Parse command-line arguments and return parameters
-fn parse_args(args: &[String]) -> Result<(usize, usize, usize, String), String> {
-    let mut s = 0;
-    let mut e = 0;
-    let mut b = 0;
-    let mut t = String::new();
+fn parse_args(args: &[String]) -> Result<Args, SimError> {
+    let mut params = Args::default();
+    // "demo locality" runs a built-in experiment instead of a tracefile, and "convert" writes it as a binary trace
+    let mut args = args.to_vec();
+    if args.get(1).map(String::as_str) == Some("convert") {
+        params.convert = true;
+        args.remove(1);
+    } else if args.get(1).map(String::as_str) == Some("reuse") {
+        params.reuse = true;
+        args.remove(1);
+    } else if args.get(1).map(String::as_str) == Some("working-set") {
+        params.working_set = true;
+        args.remove(1);
+    } else if args.get(1).map(String::as_str) == Some("paging") {
+        params.paging = true;
+        args.remove(1);
+    } else if args.get(1).map(String::as_str) == Some("false-sharing") {
+        params.false_sharing = true;
+        args.remove(1);
+    } else if args.get(1).map(String::as_str) == Some("gen") {
+        params.gen = true;
+        args.remove(1);
+    } else if args.get(1).map(String::as_str) == Some("serve") {
+        params.service = true;
+        args.remove(1);
+    } else if args.get(1).map(String::as_str) == Some("repl") {
+        params.repl = true;
+        args.remove(1);
+    } else if args.get(1).map(String::as_str) == Some("bench") {
+        params.bench = true;
+        args.remove(1);
+    } else if args.get(1).map(String::as_str) == Some("decode") {
+        params.decode = true;
+        args.remove(1);
+        params.addresses = take_addresses(&mut args)?;
+    } else if args.get(1).map(String::as_str) == Some("completions") {
+        match args.get(2) {
+            Some(shell) => params.completions = Some(shell.parse().map_err(invalid_option)?),
+            None => return Err(SimError::ParseArgs("missing shell".to_string())),
+        }
+        args.drain(1..3);
+    } else if args.get(1).map(String::as_str) == Some("demo") {
+        match args.get(2).map(String::as_str) {
+            Some("locality") => params.demo = true,
+            Some(demo) => return Err(SimError::ParseArgs(format!("unknown demo: {}", demo))),
+            None => return Err(SimError::ParseArgs("missing demo name".to_string())),
+        }
+        args.drain(1..3);
+    }
+    let (short_args, long_opts) = split_long_options(&args)?;
 
     let mut counts = HashMap::new();
     counts.insert('s', 0);
     counts.insert('E', 0);
     counts.insert('b', 0);
     counts.insert('t', 0);
+    counts.insert('o', 0);
 
-    let mut opts = getopt::Parser::new(args, "s:E:b:t:"); // Use getopt crate
-    while let Some(opt) = opts.next() {
+    let opts = getopt::Parser::new(&short_args, "s:E:b:t:o:"); // Use getopt crate
+    for opt in opts {
         match opt {
             Ok(Opt(flag, Some(val))) => {
                 let count = counts.entry(flag).or_insert(0usize);
                 *count += 1;
-                if *count > 1 {
-                    return Err(format!("duplicate flag -{}", flag));
+                // -t can be repeated to simulate several tracefiles
+                if *count > 1 && flag != 't' {
+                    return Err(SimError::ParseArgs(format!("duplicate flag -{}", flag)));
                 }
                 match flag {
+                    't' if *count > 1 => {
+                        params.extra_traces.push(val);
+                    }
                     't' => {
-                        t = val;
+                        params.t = val;
+                    }
+                    'o' => {
+                        params.output = Some(val);
                     }
                     's' | 'E' | 'b' => {
-                        let param = val.parse().map_err(|e| format!("invalid value for -{} flag ({})", flag, e))?;
+                        let param = val.parse().map_err(|e| SimError::ParseArgs(format!("invalid value for -{} flag ({})", flag, e)))?;
                         if flag == 's' {
-                            s = param;
+                            params.s = param;
                         } else if flag == 'E' {
-                            e = param;
+                            params.e = param;
                         } else {
-                            b = param;
+                            params.b = param;
                         }
                     }
-                    _ => return Err(format!("unknown flag: -{}", flag)),
+                    _ => return Err(SimError::ParseArgs(format!("unknown flag: -{}", flag))),
+                }
+            }
+            Ok(Opt(_, None)) => {
+                return Err(SimError::ParseArgs("unexpected option".to_string()));
+            }
+            Err(err) => {
+                return Err(SimError::ParseArgs(err.to_string()));
+            }
+        }
+    }
+
+    let mut seen = Vec::new();
+    for (name, value) in long_opts {
+        if seen.contains(&name) {
+            return Err(SimError::ParseArgs(format!("duplicate option --{}", name)));
+        }
+        let value = value.unwrap_or_default();
+        match name.as_str() {
+            "config" => {
+                params.config = Some(HierarchyConfig::load(&value).map_err(|e| SimError::ParseArgs(format!("invalid --config file ({})", e)))?);
+            }
+            "policy" => {
+                params.replacement = value.parse().map_err(invalid_option)?;
+            }
+            "insertion" => {
+                params.insertion = value.parse().map_err(invalid_option)?;
+            }
+            "protected-ways" => {
+                let ways = value.parse().map_err(|e| SimError::ParseArgs(format!("invalid value for --protected-ways option ({})", e)))?;
+                params.protected_ways = Some(ways);
+            }
+            "adaptive" => {
+                let policies = value.split(',').map(str::parse).collect::<Result<Vec<_>, _>>().map_err(invalid_option)?;
+                let [first, second] = policies[..] else {
+                    return Err(SimError::ParseArgs("--adaptive takes two policies".to_string()));
+                };
+                params.adaptive = Some([first, second]);
+            }
+            "tie-break" => {
+                params.tie_break = value.parse().map_err(invalid_option)?;
+            }
+            "seed" => {
+                params.seed = Some(value.parse().map_err(|e| SimError::ParseArgs(format!("invalid value for --seed option ({})", e)))?);
+            }
+            "skewed" => {
+                params.skewed = true;
+            }
+            "way-predict" => {
+                params.way_predictor = Some(value.parse().map_err(invalid_option)?);
+            }
+            "addr-bits" => {
+                let address_bits = value.parse().map_err(|e| SimError::ParseArgs(format!("invalid value for --addr-bits option ({})", e)))?;
+                if !(1..=64).contains(&address_bits) {
+                    return Err(SimError::ParseArgs("--addr-bits must be between 1 and 64".to_string()));
+                }
+                params.address_bits = Some(address_bits);
+            }
+            "oob-addr" => {
+                params.oob_policy = value.parse().map_err(invalid_option)?;
+            }
+            "partition" => {
+                params.way_partitions = parse_way_partitions(&value)?;
+            }
+            "uncacheable" => {
+                params.uncacheable = parse_address_ranges(&value).map_err(invalid_option)?;
+            }
+            "lock-ways" => {
+                params.locked_ways = parse_way_locks(&value).map_err(invalid_option)?;
+            }
+            "scratchpad" => {
+                params.scratchpad = Some(parse_address_range(&value).map_err(invalid_option)?);
+            }
+            "banks" => {
+                let banks: usize = value.parse().map_err(|e| SimError::ParseArgs(format!("invalid value for --banks option ({})", e)))?;
+                if !banks.is_power_of_two() {
+                    return Err(SimError::ParseArgs("--banks must be a power of two".to_string()));
+                }
+                params.banks = Some(banks);
+            }
+            "issue-width" => {
+                let issue_width = value.parse().map_err(|e| SimError::ParseArgs(format!("invalid value for --issue-width option ({})", e)))?;
+                if issue_width == 0 {
+                    return Err(SimError::ParseArgs("--issue-width must be at least 1".to_string()));
+                }
+                params.issue_width = Some(issue_width);
+            }
+            "write-buffer" => {
+                let entries = value.parse().map_err(|e| SimError::ParseArgs(format!("invalid value for --write-buffer option ({})", e)))?;
+                if entries == 0 {
+                    return Err(SimError::ParseArgs("--write-buffer must be at least 1".to_string()));
+                }
+                params.write_buffer = Some(entries);
+            }
+            "drain-interval" => {
+                let drain_interval = value.parse().map_err(|e| SimError::ParseArgs(format!("invalid value for --drain-interval option ({})", e)))?;
+                if drain_interval == 0 {
+                    return Err(SimError::ParseArgs("--drain-interval must be at least 1".to_string()));
+                }
+                params.drain_interval = Some(drain_interval);
+            }
+            "store-buffer" => {
+                let entries = value.parse().map_err(|e| SimError::ParseArgs(format!("invalid value for --store-buffer option ({})", e)))?;
+                if entries == 0 {
+                    return Err(SimError::ParseArgs("--store-buffer must be at least 1".to_string()));
+                }
+                params.store_buffer = Some(entries);
+            }
+            "bypass" => {
+                let region_size = parse_bytes(&value).map_err(|e| SimError::ParseArgs(format!("invalid value for --bypass option ({})", e)))?;
+                if !region_size.is_power_of_two() {
+                    return Err(SimError::ParseArgs("--bypass must be a power of two".to_string()));
+                }
+                params.bypass_region = Some(region_size);
+            }
+            "tinylfu" => {
+                params.tinylfu = true;
+            }
+            "compress" => {
+                let compressibility: f64 = value.parse().map_err(|e| SimError::ParseArgs(format!("invalid value for --compress option ({})", e)))?;
+                if !(0.0..=1.0).contains(&compressibility) {
+                    return Err(SimError::ParseArgs("--compress must be between 0 and 1".to_string()));
+                }
+                params.compressibility = Some(compressibility);
+            }
+            "icache" => {
+                params.icache = true;
+            }
+            "classify-misses" => {
+                params.classify_misses = true;
+            }
+            "lifetimes" => {
+                params.lifetimes = true;
+            }
+            "debug" => {
+                params.debug = true;
+            }
+            "tui" => {
+                params.debug = true;
+                params.tui = true;
+            }
+            "undo-depth" => {
+                params.undo_depth = Some(value.parse().map_err(|e| SimError::ParseArgs(format!("invalid value for --undo-depth option ({})", e)))?);
+            }
+            "warmup" => {
+                params.warmup = Some(value.parse().map_err(|e| SimError::ParseArgs(format!("invalid value for --warmup option ({})", e)))?);
+            }
+            "sweep" => {
+                params.sweep = parse_sweep(&value)?;
+            }
+            "sample-period" => {
+                params.sample_period = Some(value.parse().map_err(|e| SimError::ParseArgs(format!("invalid value for --sample-period option ({})", e)))?);
+            }
+            "sample-length" => {
+                params.sample_length = Some(value.parse().map_err(|e| SimError::ParseArgs(format!("invalid value for --sample-length option ({})", e)))?);
+            }
+            "checkpoint" => {
+                params.checkpoint = Some(value);
+            }
+            "restore" => {
+                params.restore = Some(value);
+            }
+            "format" => {
+                params.format = value.parse().map_err(invalid_option)?;
+            }
+            "lenient" => {
+                params.lenient = true;
+            }
+            "addr-offset" => {
+                params.remap.offset = parse_address_offset(&value).map_err(invalid_option)?;
+            }
+            "remap" => {
+                params.remap.regions = parse_remap_regions(&value).map_err(invalid_option)?;
+            }
+            "keep-cache" => {
+                params.keep_cache = true;
+            }
+            "compare-ref" => {
+                params.compare_ref = Some(value);
+            }
+            "compare-expected" => {
+                params.compare_expected = Some(value);
+            }
+            "compare-policies" => {
+                params.compare_policies = value.split(',').map(str::parse).collect::<Result<_, _>>().map_err(invalid_option)?;
+                if params.compare_policies.len() < 2 {
+                    return Err(SimError::ParseArgs("--compare-policies takes at least two policies".to_string()));
+                }
+            }
+            "divergences" => {
+                params.divergences = Some(value.parse().map_err(|e| SimError::ParseArgs(format!("invalid value for --divergences option ({})", e)))?);
+            }
+            "quiet" => {
+                params.quiet = true;
+            }
+            "log-level" => {
+                params.log_level = Some(value.parse().map_err(invalid_option)?);
+            }
+            // Read by main before parsing, see json_errors
+            "errors" => {
+                if value != "text" && value != "json" {
+                    return Err(SimError::ParseArgs(format!("unknown error format: {}", value)));
+                }
+            }
+            "output" => {
+                params.csv = match value.as_str() {
+                    "text" => false,
+                    "csv" => true,
+                    _ => return Err(SimError::ParseArgs(format!("unknown output format: {}", value))),
+                };
+            }
+            "append" => {
+                params.append = Some(value);
+            }
+            "per-set" => {
+                params.per_set = true;
+            }
+            "top" => {
+                let top = value.parse().map_err(|e| SimError::ParseArgs(format!("invalid value for --top option ({})", e)))?;
+                if top == 0 {
+                    return Err(SimError::ParseArgs("--top must be at least 1".to_string()));
+                }
+                params.top = Some(top);
+            }
+            "heatmap" => {
+                params.heatmap = Some(value);
+            }
+            "serve" => {
+                params.serve = Some(value);
+            }
+            "listen" => {
+                params.listen = Some(value);
+            }
+            "window" => {
+                params.window = Some(value.parse().map_err(|e| SimError::ParseArgs(format!("invalid value for --window option ({})", e)))?);
+            }
+            "step" => {
+                params.step = Some(value.parse().map_err(|e| SimError::ParseArgs(format!("invalid value for --step option ({})", e)))?);
+            }
+            "frames" => {
+                params.frames = Some(value.parse().map_err(|e| SimError::ParseArgs(format!("invalid value for --frames option ({})", e)))?);
+            }
+            "page-size" => {
+                params.page_size = Some(parse_bytes(&value).map_err(|e| SimError::ParseArgs(format!("invalid value for --page-size option ({})", e)))?);
+            }
+            "page-policy" => {
+                params.page_policy = Some(value.parse().map_err(invalid_option)?);
+            }
+            "interleave" => {
+                params.interleave = Some(value.parse().map_err(invalid_option)?);
+            }
+            "pattern" => {
+                params.pattern = Some(value.parse().map_err(invalid_option)?);
+            }
+            "footprint" => {
+                params.footprint = Some(parse_bytes(&value).map_err(|e| SimError::ParseArgs(format!("invalid value for --footprint option ({})", e)))?);
+            }
+            "length" => {
+                params.length = Some(value.parse().map_err(|e| SimError::ParseArgs(format!("invalid value for --length option ({})", e)))?);
+            }
+            "access-size" => {
+                params.access_size = Some(value.parse().map_err(|e| SimError::ParseArgs(format!("invalid value for --access-size option ({})", e)))?);
+            }
+            "stride" => {
+                params.stride = Some(parse_bytes(&value).map_err(|e| SimError::ParseArgs(format!("invalid value for --stride option ({})", e)))?);
+            }
+            "kernel" => {
+                params.kernel = Some(value.parse().map_err(invalid_option)?);
+            }
+            "block" => {
+                params.block = Some(value.parse().map_err(|e| SimError::ParseArgs(format!("invalid value for --block option ({})", e)))?);
+            }
+            "write-ratio" => {
+                params.write_ratio = Some(value.parse().map_err(|e| SimError::ParseArgs(format!("invalid value for --write-ratio option ({})", e)))?);
+            }
+            "n" => {
+                params.demo_n = Some(value.parse().map_err(|e| SimError::ParseArgs(format!("invalid value for --n option ({})", e)))?);
+            }
+            "element-size" => {
+                params.element_size = Some(value.parse().map_err(|e| SimError::ParseArgs(format!("invalid value for --element-size option ({})", e)))?);
+            }
+            _ => return Err(SimError::ParseArgs(format!("unknown option: --{}", name))),
+        }
+        seen.push(name);
+    }
+
+    if params.convert {
+        if params.t.is_empty() || params.output.is_none() {
+            return Err(SimError::ParseArgs("convert requires -t and -o".to_string()));
+        }
+        let convert_options = Args {
+            convert: true,
+            t: params.t.clone(),
+            output: params.output.clone(),
+            format: params.format.clone(),
+            lenient: params.lenient,
+            remap: params.remap.clone(),
+            log_level: params.log_level,
+            ..Default::default()
+        };
+        if params != convert_options {
+            return Err(SimError::ParseArgs("convert only takes -t, -o, --format, --lenient, --addr-offset and --remap".to_string()));
+        }
+        return Ok(params);
+    }
+    if params.reuse {
+        if params.t.is_empty() {
+            return Err(SimError::ParseArgs("reuse requires -t".to_string()));
+        }
+        let reuse_options = Args {
+            reuse: true,
+            t: params.t.clone(),
+            b: params.b,
+            format: params.format.clone(),
+            lenient: params.lenient,
+            remap: params.remap.clone(),
+            log_level: params.log_level,
+            ..Default::default()
+        };
+        if params != reuse_options {
+            return Err(SimError::ParseArgs("reuse only takes -t, -b, --format, --lenient, --addr-offset and --remap".to_string()));
+        }
+        return Ok(params);
+    }
+    if params.working_set {
+        let window = match (params.t.is_empty(), params.window) {
+            (false, Some(window)) => window,
+            _ => return Err(SimError::ParseArgs("working-set requires -t and --window".to_string())),
+        };
+        let working_set_options = Args {
+            working_set: true,
+            t: params.t.clone(),
+            b: params.b,
+            window: params.window,
+            step: params.step,
+            format: params.format.clone(),
+            lenient: params.lenient,
+            remap: params.remap.clone(),
+            log_level: params.log_level,
+            ..Default::default()
+        };
+        if params != working_set_options {
+            return Err(SimError::ParseArgs("working-set only takes -t, -b, --window, --step, --format, --lenient, --addr-offset and --remap".to_string()));
+        }
+        if window == 0 || params.step == Some(0) {
+            return Err(SimError::ParseArgs("--window and --step must be at least 1".to_string()));
+        }
+        return Ok(params);
+    }
+    if params.paging {
+        let frames = match (params.t.is_empty(), params.frames) {
+            (false, Some(frames)) => frames,
+            _ => return Err(SimError::ParseArgs("paging requires -t and --frames".to_string())),
+        };
+        let paging_options = Args {
+            paging: true,
+            t: params.t.clone(),
+            frames: params.frames,
+            page_size: params.page_size,
+            page_policy: params.page_policy,
+            format: params.format.clone(),
+            lenient: params.lenient,
+            remap: params.remap.clone(),
+            log_level: params.log_level,
+            ..Default::default()
+        };
+        if params != paging_options {
+            return Err(SimError::ParseArgs("paging only takes -t, --frames, --page-size, --page-policy, --format, --lenient, --addr-offset and --remap".to_string()));
+        }
+        if frames == 0 {
+            return Err(SimError::ParseArgs("--frames must be at least 1".to_string()));
+        }
+        if params.page_size.is_some_and(|page_size| !page_size.is_power_of_two()) {
+            return Err(SimError::ParseArgs("--page-size must be a power of two".to_string()));
+        }
+        return Ok(params);
+    }
+    if params.false_sharing {
+        if params.t.is_empty() {
+            return Err(SimError::ParseArgs("false-sharing requires -t".to_string()));
+        }
+        let false_sharing_options = Args {
+            false_sharing: true,
+            t: params.t.clone(),
+            b: params.b,
+            top: params.top,
+            format: params.format.clone(),
+            lenient: params.lenient,
+            remap: params.remap.clone(),
+            log_level: params.log_level,
+            ..Default::default()
+        };
+        if params != false_sharing_options {
+            return Err(SimError::ParseArgs("false-sharing only takes -t, -b, --top, --format, --lenient, --addr-offset and --remap".to_string()));
+        }
+        return Ok(params);
+    }
+    if params.decode {
+        if counts[&'s'] == 0 || counts[&'b'] == 0 || params.addresses.is_empty() {
+            return Err(SimError::ParseArgs("decode requires -s, -b and at least one address".to_string()));
+        }
+        let decode_options = Args { decode: true, s: params.s, b: params.b, addresses: params.addresses.clone(), log_level: params.log_level, ..Default::default() };
+        if params != decode_options {
+            return Err(SimError::ParseArgs("decode only takes -s, -b and addresses".to_string()));
+        }
+        // The tag needs at least one bit
+        if params.s + params.b >= 64 {
+            return Err(SimError::ParseArgs("-s plus -b must be less than 64".to_string()));
+        }
+        return Ok(params);
+    }
+    if params.completions.is_some() {
+        if params != (Args { completions: params.completions, log_level: params.log_level, ..Default::default() }) {
+            return Err(SimError::ParseArgs("completions only takes a shell".to_string()));
+        }
+        return Ok(params);
+    }
+    if params.service {
+        if params != (Args { service: true, listen: params.listen.clone(), log_level: params.log_level, ..Default::default() }) {
+            return Err(SimError::ParseArgs("serve only takes --listen".to_string()));
+        }
+        return Ok(params);
+    }
+    if params.listen.is_some() {
+        return Err(SimError::ParseArgs("--listen is only used by serve".to_string()));
+    }
+    if params.gen && params.kernel.is_some() {
+        let kernel_options = Args {
+            gen: true,
+            kernel: params.kernel,
+            demo_n: params.demo_n,
+            block: params.block,
+            element_size: params.element_size,
+            output: params.output.clone(),
+            format: params.format.clone(),
+            log_level: params.log_level,
+            ..Default::default()
+        };
+        if params != kernel_options {
+            return Err(SimError::ParseArgs("gen --kernel only takes --n, --block, --element-size, -o and --format".to_string()));
+        }
+        matrix_kernel(&params)?.validate().map_err(invalid_option)?;
+        return Ok(params);
+    }
+    if params.gen {
+        let gen_options = Args {
+            gen: true,
+            pattern: params.pattern,
+            footprint: params.footprint,
+            length: params.length,
+            access_size: params.access_size,
+            stride: params.stride,
+            write_ratio: params.write_ratio,
+            seed: params.seed,
+            output: params.output.clone(),
+            format: params.format.clone(),
+            log_level: params.log_level,
+            ..Default::default()
+        };
+        if params != gen_options {
+            return Err(SimError::ParseArgs("gen --pattern only takes --footprint, --length, --access-size, --stride, --write-ratio, --seed, -o and --format".to_string()));
+        }
+        if params.pattern == Some(Pattern::Strided) && params.stride.is_none() {
+            return Err(SimError::ParseArgs("the strided pattern requires --stride".to_string()));
+        }
+        if params.stride.is_some() && !matches!(params.pattern, Some(Pattern::Strided | Pattern::PointerChase)) {
+            return Err(SimError::ParseArgs("--stride is only used by the strided and pointer-chase patterns".to_string()));
+        }
+        generator(&params)?.validate().map_err(invalid_option)?;
+        return Ok(params);
+    }
+    if params.repl {
+        let repl_options = Args {
+            repl: true,
+            s: params.s,
+            e: params.e,
+            b: params.b,
+            replacement: params.replacement,
+            insertion: params.insertion,
+            protected_ways: params.protected_ways,
+            adaptive: params.adaptive,
+            tie_break: params.tie_break,
+            seed: params.seed,
+            skewed: params.skewed,
+            way_predictor: params.way_predictor,
+            address_bits: params.address_bits,
+            oob_policy: params.oob_policy,
+            way_partitions: params.way_partitions.clone(),
+            uncacheable: params.uncacheable.clone(),
+            locked_ways: params.locked_ways.clone(),
+            scratchpad: params.scratchpad.clone(),
+            banks: params.banks,
+            issue_width: params.issue_width,
+            write_buffer: params.write_buffer,
+            drain_interval: params.drain_interval,
+            store_buffer: params.store_buffer,
+            bypass_region: params.bypass_region,
+            tinylfu: params.tinylfu,
+            compressibility: params.compressibility,
+            classify_misses: params.classify_misses,
+            lifetimes: params.lifetimes,
+            config: params.config.clone(),
+            log_level: params.log_level,
+            ..Default::default()
+        };
+        if params != repl_options {
+            return Err(SimError::ParseArgs("repl only takes -s, -E, -b and cache options".to_string()));
+        }
+    }
+    if params.bench {
+        let bench_options = Args {
+            bench: true,
+            s: params.s,
+            e: params.e,
+            b: params.b,
+            length: params.length,
+            replacement: params.replacement,
+            insertion: params.insertion,
+            protected_ways: params.protected_ways,
+            adaptive: params.adaptive,
+            tie_break: params.tie_break,
+            seed: params.seed,
+            skewed: params.skewed,
+            way_predictor: params.way_predictor,
+            address_bits: params.address_bits,
+            oob_policy: params.oob_policy,
+            way_partitions: params.way_partitions.clone(),
+            uncacheable: params.uncacheable.clone(),
+            locked_ways: params.locked_ways.clone(),
+            scratchpad: params.scratchpad.clone(),
+            banks: params.banks,
+            issue_width: params.issue_width,
+            write_buffer: params.write_buffer,
+            drain_interval: params.drain_interval,
+            store_buffer: params.store_buffer,
+            bypass_region: params.bypass_region,
+            tinylfu: params.tinylfu,
+            compressibility: params.compressibility,
+            classify_misses: params.classify_misses,
+            lifetimes: params.lifetimes,
+            config: params.config.clone(),
+            log_level: params.log_level,
+            ..Default::default()
+        };
+        if params != bench_options {
+            return Err(SimError::ParseArgs("bench only takes -s, -E, -b, --length and cache options".to_string()));
+        }
+        if params.length == Some(0) {
+            return Err(SimError::ParseArgs("--length must be at least 1".to_string()));
+        }
+    }
+    if let Some(config) = &params.config {
+        let cache_options = params.s != 0 || params.e != 0 || params.b != 0 || !params.sweep.is_empty()
+            || params.replacement != Replacement::default() || params.insertion != Insertion::default() || params.protected_ways.is_some() || params.adaptive.is_some() || params.tie_break != TieBreak::default() || params.seed.is_some() || params.skewed || params.way_predictor.is_some()
+            || params.address_bits.is_some() || params.oob_policy != OobPolicy::default() || !params.way_partitions.is_empty() || !params.uncacheable.is_empty() || !params.locked_ways.is_empty() || params.scratchpad.is_some()
+            || params.banks.is_some() || params.issue_width.is_some() || params.write_buffer.is_some() || params.drain_interval.is_some() || params.store_buffer.is_some() || params.bypass_region.is_some() || params.tinylfu || params.compressibility.is_some();
+        if cache_options || params.restore.is_some() {
+            return Err(SimError::ParseArgs("--config replaces -s, -E, -b, --sweep, --restore and the cache options".to_string()));
+        }
+        if !config.is_single_cache() {
+            let hierarchy_options = Args {
+                config: params.config.clone(),
+                t: params.t.clone(),
+                extra_traces: if params.interleave.is_some() { params.extra_traces.clone() } else { Vec::new() },
+                interleave: params.interleave,
+                warmup: params.warmup,
+                format: params.format.clone(),
+                lenient: params.lenient,
+                remap: params.remap.clone(),
+                log_level: params.log_level,
+                ..Default::default()
+            };
+            if params.t.is_empty() || params != hierarchy_options {
+                return Err(SimError::ParseArgs("a --config file with several levels, cores or a DRAM requires -t and only takes --warmup, --format, --lenient, --addr-offset, --remap and, with --interleave, several -t tracefiles".to_string()));
+            }
+            if params.interleave.is_some() && config.private_levels() == 0 {
+                return Err(SimError::ParseArgs("--interleave requires a --config file with a private first level".to_string()));
+            }
+            if config.snoop_filter && config.cores.is_none() && params.interleave.is_none() {
+                return Err(SimError::ParseArgs("snoop_filter in a --config file requires cores or --interleave".to_string()));
+            }
+            if let (Some(cores), Some(_)) = (config.cores, params.interleave) {
+                if params.extra_traces.len() + 1 != cores {
+                    return Err(SimError::ParseArgs(format!("--interleave with cores = {} in the --config file requires {} -t tracefiles", cores, cores)));
+                }
+            }
+            if params.extra_traces.iter().chain([&params.t]).filter(|&trace| trace == STDIN_TRACEFILE).count() > 1 {
+                return Err(SimError::ParseArgs("stdin can only be read as one of the tracefiles".to_string()));
+            }
+            return Ok(params);
+        }
+    }
+    if params.interleave.is_some() {
+        return Err(SimError::ParseArgs("--interleave requires a --config file with several levels or cores".to_string()));
+    }
+    if params.output.is_some() {
+        return Err(SimError::ParseArgs("-o is only used by convert and gen".to_string()));
+    }
+    if params.compare_ref.is_some() || params.compare_expected.is_some() {
+        let compare_options = Args {
+            s: params.s,
+            e: params.e,
+            b: params.b,
+            t: params.t.clone(),
+            compare_ref: params.compare_ref.clone(),
+            compare_expected: params.compare_expected.clone(),
+            log_level: params.log_level,
+            ..Default::default()
+        };
+        if params != compare_options || (params.compare_ref.is_some() && params.compare_expected.is_some()) {
+            return Err(SimError::ParseArgs("--compare-ref and --compare-expected only take -s, -E, -b and -t, and not each other".to_string()));
+        }
+    }
+    if !params.compare_policies.is_empty() {
+        if params.debug || params.demo || params.repl || !params.sweep.is_empty() || params.sample_period.is_some() || params.serve.is_some()
+            || params.csv || params.checkpoint.is_some() || params.restore.is_some() || params.per_set || params.top.is_some()
+            || params.heatmap.is_some() || !params.extra_traces.is_empty() || params.replacement != Replacement::default() || params.adaptive.is_some() {
+            return Err(SimError::ParseArgs(
+                "--compare-policies is not supported with --policy, --adaptive, --debug, --sweep, sampling, --serve, --output csv, --checkpoint, --restore, --per-set, --top, --heatmap, several -t tracefiles or a subcommand".to_string()
+            ));
+        }
+        for &policy in &params.compare_policies {
+            cache_builder(&params).replacement(policy).validate().map_err(invalid_option)?;
+        }
+    } else if params.divergences.is_some() {
+        return Err(SimError::ParseArgs("--divergences requires --compare-policies".to_string()));
+    }
+    if !params.extra_traces.is_empty() {
+        if params.debug || params.demo || params.repl || !params.sweep.is_empty() || params.sample_period.is_some() || params.serve.is_some()
+            || params.checkpoint.is_some() || params.restore.is_some() || params.per_set || params.top.is_some() || params.heatmap.is_some() {
+            return Err(SimError::ParseArgs(
+                "several -t tracefiles are not supported with --debug, --sweep, sampling, --serve, --checkpoint, --restore, --per-set, --top, --heatmap or a subcommand".to_string()
+            ));
+        }
+        if params.extra_traces.iter().chain([&params.t]).filter(|&trace| trace == STDIN_TRACEFILE).count() > 1 {
+            return Err(SimError::ParseArgs("stdin can only be read as one of the tracefiles".to_string()));
+        }
+    } else if params.keep_cache {
+        return Err(SimError::ParseArgs("--keep-cache requires several -t tracefiles".to_string()));
+    }
+    if params.append.is_some() && !params.csv {
+        return Err(SimError::ParseArgs("--append requires --output csv".to_string()));
+    }
+    if (params.per_set || params.top.is_some()) && (params.csv || params.demo || !params.sweep.is_empty()) {
+        return Err(SimError::ParseArgs("--per-set and --top are not supported with --output csv, --sweep or demo locality".to_string()));
+    }
+    if params.heatmap.is_some() && (params.demo || !params.sweep.is_empty()) {
+        return Err(SimError::ParseArgs("--heatmap is not supported with --sweep or demo locality".to_string()));
+    }
+    if params.serve.is_some() && (params.debug || params.demo || !params.sweep.is_empty() || params.sample_period.is_some()) {
+        return Err(SimError::ParseArgs("--serve is not supported with --debug, --sweep, demo locality or sampling".to_string()));
+    }
+    if params.csv && (params.debug || params.demo || params.sample_period.is_some()) {
+        return Err(SimError::ParseArgs("--output csv is not supported with --debug, demo locality or sampling".to_string()));
+    }
+    if params.window.is_some() || params.step.is_some() {
+        return Err(SimError::ParseArgs("--window and --step are only used by working-set".to_string()));
+    }
+    if params.frames.is_some() || params.page_size.is_some() || params.page_policy.is_some() {
+        return Err(SimError::ParseArgs("--frames, --page-size and --page-policy are only used by paging".to_string()));
+    }
+    if params.pattern.is_some() || params.footprint.is_some() || params.length.is_some() && !params.bench || params.access_size.is_some()
+        || params.stride.is_some() || params.write_ratio.is_some() || params.kernel.is_some() || params.block.is_some() {
+        return Err(SimError::ParseArgs("--pattern, --footprint, --length, --access-size, --stride, --write-ratio, --kernel and --block are only used by gen".to_string()));
+    }
+    if params.sweep.is_empty() && params.config.is_none() && (params.s == 0 || params.e == 0 || params.b == 0) || params.t.is_empty() != (params.demo || params.repl || params.bench) {
+        return Err(SimError::ParseArgs("missing required arguments, incorrect command-line format".to_string()));
+    }
+    if params.sweep.is_empty() && params.config.is_none() {
+        // A geometry too large for the address width is as invalid an argument as a missing one
+        CacheBuilder::new().set_index_bits(params.s).associativity(params.e).block_bits(params.b)
+            .address_bits(params.address_bits.unwrap_or(64)).validate().map_err(invalid_option)?;
+    }
+    if params.icache && params.demo {
+        return Err(SimError::ParseArgs("--icache simulates the instruction fetches of a tracefile, not demo locality".to_string()));
+    }
+    if params.remap != AddressRemap::default() && params.demo {
+        return Err(SimError::ParseArgs("--addr-offset and --remap rewrite the addresses of a tracefile, not demo locality".to_string()));
+    }
+    if !params.sweep.is_empty() {
+        if params.s != 0 || params.e != 0 || params.b != 0 {
+            return Err(SimError::ParseArgs("--sweep replaces -s, -E and -b".to_string()));
+        }
+        if params.debug || params.demo || params.restore.is_some() || params.checkpoint.is_some() || params.sample_period.is_some() {
+            return Err(SimError::ParseArgs("--sweep is not supported with --debug, demo locality, --restore, --checkpoint or sampling".to_string()));
+        }
+        for builder in sweep_builders(&params) {
+            builder.validate().map_err(invalid_option)?;
+        }
+    }
+    if (params.demo_n.is_some() || params.element_size.is_some()) && !params.demo {
+        return Err(SimError::ParseArgs("--n and --element-size are only used by demo locality and gen --kernel".to_string()));
+    }
+    if params.debug && params.t == STDIN_TRACEFILE {
+        return Err(SimError::ParseArgs("--debug reads commands from stdin, so it can't read the trace from there too".to_string()));
+    }
+    if params.demo && params.debug {
+        return Err(SimError::ParseArgs("--debug is not supported with demo locality".to_string()));
+    }
+    if params.demo && (params.checkpoint.is_some() || params.restore.is_some() || params.warmup.is_some()) {
+        return Err(SimError::ParseArgs("--checkpoint, --restore and --warmup are not supported with demo locality".to_string()));
+    }
+    if let Some(sampling) = sampling(&params)? {
+        sampling.validate().map_err(invalid_option)?;
+        if params.debug || params.warmup.is_some() || params.demo {
+            return Err(SimError::ParseArgs("sampling is not supported with --debug, --warmup or demo locality".to_string()));
+        }
+    }
+    if params.restore.is_some() && (params.debug || cache_builder(&params) != CacheBuilder::new().set_index_bits(params.s).associativity(params.e).block_bits(params.b)) {
+        return Err(SimError::ParseArgs("--restore takes the cache configuration from the checkpoint and is not supported with other cache options or --debug".to_string()));
+    }
+    if params.way_partitions.values().flatten().any(|&way| way >= params.e) {
+        return Err(SimError::ParseArgs("--partition assigns a way beyond the lines in a set".to_string()));
+    }
+    if params.sweep.is_empty() && params.locked_ways.iter().any(|lock| *lock.ways.end() >= params.e) {
+        return Err(SimError::ParseArgs("--lock-ways locks a way beyond the lines in a set".to_string()));
+    }
+    if params.sweep.is_empty() && params.locked_ways.iter().flat_map(|lock| lock.sets.as_ref()).any(|sets| sets.end().checked_shr(params.s as u32).unwrap_or(0) != 0) {
+        return Err(SimError::ParseArgs("--lock-ways locks a set beyond the sets of the cache".to_string()));
+    }
+    if params.sweep.is_empty() && (params.scratchpad.is_some() || params.bypass_region.is_some() || params.tinylfu || params.insertion != Insertion::default() || params.protected_ways.is_some() || params.adaptive.is_some()) {
+        cache_builder(&params).validate().map_err(invalid_option)?;
+    }
+    if params.issue_width.is_some() && params.banks.is_none() {
+        return Err(SimError::ParseArgs("--issue-width requires --banks".to_string()));
+    }
+    if params.drain_interval.is_some() && params.write_buffer.is_none() {
+        return Err(SimError::ParseArgs("--drain-interval requires --write-buffer".to_string()));
+    }
+    if params.compressibility.is_some() && (params.skewed || !params.way_partitions.is_empty() || !params.locked_ways.is_empty()) {
+        return Err(SimError::ParseArgs("--compress is not supported with --skewed, --partition or --lock-ways".to_string()));
+    }
+    if params.undo_depth.is_some() && !params.debug {
+        return Err(SimError::ParseArgs("--undo-depth requires --debug".to_string()));
+    }
+    if params.skewed && params.way_predictor.is_some() {
+        return Err(SimError::ParseArgs("--way-predict is not supported with --skewed".to_string()));
+    }
+
+    Ok(params)
+}
+
+// The subcommands and options of the command line, for completion scripts
+fn command_line_options() -> Vec<(String, OptionValue)> {
+    let short_flags = ["-s", "-E", "-b", "-t", "-o"].iter().map(|flag| (flag.to_string(), true));
+    let long_options = LONG_OPTIONS.iter().map(|&(name, takes_value)| (format!("--{}", name), takes_value));
+    short_flags.chain(long_options)
+        .map(|(option, takes_value)| {
+            let value = match OPTION_VALUES.iter().find(|(name, _)| *name == option) {
+                Some(&(_, value)) => value,
+                None if takes_value => OptionValue::Any,
+                None => OptionValue::None,
+            };
+            (option, value)
+        })
+        .collect()
+}
+
+// Build the cache configuration described by the command-line arguments, or by the single level of --config
+fn cache_builder(params: &Args) -> CacheBuilder {
+    let mut builder = match &params.config {
+        // The level was checked when the file was loaded
+        Some(config) => config.levels[0].builder().expect("invalid cache level"),
+        None => {
+            let mut builder = CacheBuilder::new()
+                .set_index_bits(params.s)
+                .associativity(params.e)
+                .block_bits(params.b)
+                .replacement(params.replacement)
+                .insertion(params.insertion)
+                .tie_break(params.tie_break)
+                .seed(params.seed.unwrap_or(DEFAULT_SEED))
+                .skewed(params.skewed)
+                .way_predictor(params.way_predictor)
+                .address_bits(params.address_bits.unwrap_or(64))
+                .oob_policy(params.oob_policy)
+                .way_partitions(params.way_partitions.clone())
+                .uncacheable(params.uncacheable.clone())
+                .locked_ways(params.locked_ways.clone());
+            if let Some(scratchpad) = &params.scratchpad {
+                builder = builder.scratchpad(scratchpad.clone());
+            }
+            if let Some(banks) = params.banks {
+                builder = builder.banks(banks, params.issue_width.unwrap_or(1));
+            }
+            if let Some(entries) = params.write_buffer {
+                builder = builder.write_buffer(entries, params.drain_interval.unwrap_or(DEFAULT_DRAIN_INTERVAL));
+            }
+            if let Some(entries) = params.store_buffer {
+                builder = builder.store_buffer(entries);
+            }
+            if let Some(ways) = params.protected_ways {
+                builder = builder.protected_ways(ways);
+            }
+            if let Some([first, second]) = params.adaptive {
+                builder = builder.adaptive(first, second);
+            }
+            if let Some(region_size) = params.bypass_region {
+                builder = builder.bypass(region_size);
+            }
+            builder = builder.tinylfu(params.tinylfu);
+            if let Some(compressibility) = params.compressibility {
+                builder = builder.compression(compressibility);
+            }
+            builder
+        }
+    };
+    builder = builder.classify_misses(params.classify_misses).track_lifetimes(params.lifetimes);
+    if params.debug {
+        builder = builder.undo_depth(params.undo_depth.unwrap_or(DEFAULT_UNDO_DEPTH));
+    }
+    if let Some(top) = params.top {
+        builder = builder.track_hot_blocks(top.max(HOT_BLOCK_CAPACITY));
+    }
+    builder
+}
+
+// Exit code of a run stopped by an error
+fn exit_code(err: &SimError) -> u8 {
+    match err {
+        SimError::ParseArgs(_) => EXIT_USAGE,
+        SimError::TraceRead(_) => EXIT_TRACE_READ,
+        SimError::Trace(_) => EXIT_TRACE_PARSE,
+        _ => EXIT_FAILURE,
+    }
+}
+
+// Whether --errors json is given, looked up before parsing so that argument errors follow it too
+fn json_errors(args: &[String]) -> bool {
+    args.iter().zip(args.iter().skip(1).map(Some).chain([None])).any(|(arg, next)| {
+        arg == "--errors=json" || (arg == "--errors" && next.is_some_and(|value| value == "json"))
+    })
+}
+
+// One error as a JSON object, with the tracefile it happened in if any
+fn format_error_json(err: &SimError, tracefile: Option<&str>) -> String {
+    let mut object = serde_json::json!({ "error": err.kind(), "message": err.message(), "exit_code": exit_code(err) });
+    if let Some(tracefile) = tracefile {
+        object["tracefile"] = tracefile.into();
+    }
+    object.to_string()
+}
+
+// Print an error on stderr after what failed, or as a JSON object with --errors json, and return its exit code
+fn report_error(json: bool, context: &str, tracefile: Option<&str>, err: &SimError) -> ExitCode {
+    if json {
+        eprintln!("{}", format_error_json(err, tracefile));
+    } else {
+        eprintln!("{}: {}", context, err);
+    }
+    ExitCode::from(exit_code(err))
+}
+
+// Print an error stopping the simulation of a tracefile
+fn report_run_error(json: bool, err: &SimError, tracefile: &str) -> ExitCode {
+    let context = match err {
+        SimError::TraceRead(_) | SimError::Trace(_) => format!("Error reading tracefile {}", tracefile),
+        SimError::Access(_) => "Error simulating cache access".to_string(),
+        _ => "Error".to_string(),
+    };
+    report_error(json, &context, Some(tracefile), err)
+}
+
+// Open the tracefile in the format given by --format, skipping unparseable lines if --lenient is given
+fn open_trace(params: &Args) -> Result<TraceAccesses, SimError> {
+    open_tracefile(params, &params.t)
+}
+
+// Open one of the tracefiles like open_trace, parsing it in a thread of its own when there is a spare CPU to overlap
+// the parsing with the simulation
+fn open_tracefile(params: &Args, path: &str) -> Result<TraceAccesses, SimError> {
+    let accesses = TraceAccesses::open(path)?.format(params.format.clone()).lenient(params.lenient).instruction_fetches(params.icache).remap(params.remap.clone());
+    match thread::available_parallelism() {
+        Ok(cpus) if cpus.get() > 1 => Ok(accesses.pipelined()),
+        _ => Ok(accesses),
+    }
+}
+
+// Report the lines a lenient run skipped
+fn print_skipped_lines(params: &Args, accesses: &TraceAccesses) {
+    match (params.lenient, params.csv) {
+        (true, false) => println!("skipped-lines:{}", accesses.skipped_lines()),
+        // Keep CSV output parseable
+        (true, true) => eprintln!("skipped-lines:{}", accesses.skipped_lines()),
+        _ => {}
+    }
+}
+
+// Print the per-set statistics and hottest blocks and write the heatmap if they were asked for
+fn print_details(params: &Args, cache: &Cache) -> Result<(), SimError> {
+    if params.per_set {
+        println!("{}", cache.format_set_stats());
+    }
+    if let Some(top) = params.top {
+        println!("{}", cache.format_hot_blocks(top));
+    }
+    if let Some(path) = &params.heatmap {
+        write_set_heatmap(cache, path)?;
+    }
+    Ok(())
+}
+
+// Print the results of a run, as text or as CSV rows printed or appended to a file
+fn print_results(params: &Args, rows: impl IntoIterator<Item = String>, text: String) -> Result<(), SimError> {
+    if !params.csv {
+        print!("{}", text);
+        return Ok(());
+    }
+    let rows: Vec<String> = rows.into_iter().collect();
+    match &params.append {
+        Some(path) => append_csv(path, &rows),
+        None => {
+            println!("{}", CSV_HEADER);
+            rows.iter().for_each(|row| println!("{}", row));
+            Ok(())
+        }
+    }
+}
+
+// Append CSV rows to a file, starting it with the header if it's new or empty
+fn append_csv(path: &str, rows: &[String]) -> Result<(), SimError> {
+    let append_error = |err: io::Error| SimError::Io(format!("failed to append to {} ({})", path, err));
+    let mut file = OpenOptions::new().create(true).append(true).open(path).map_err(append_error)?;
+    let mut text = String::new();
+    if file.metadata().map_err(append_error)?.len() == 0 {
+        text.push_str(CSV_HEADER);
+        text.push('\n');
+    }
+    for row in rows {
+        text.push_str(row);
+        text.push('\n');
+    }
+    file.write_all(text.as_bytes()).map_err(append_error)
+}
+
+// Reporter of the progress of reading a tracefile, unless --quiet is given
+fn progress_reporter(params: &Args, accesses: &TraceAccesses) -> Option<ProgressReporter<io::Stderr>> {
+    (!params.quiet).then(|| ProgressReporter::stderr(accesses.read_progress()))
+}
+
+// Simulate a tracefile after its warm-up accesses, publishing the statistics to the dashboard if there is one and
+// reporting progress if there is a reporter, and return the instruction fetches read after the warm-up
+fn simulate_trace(
+    cache: &mut Cache,
+    accesses: &mut TraceAccesses,
+    warmup: usize,
+    dashboard: Option<&Dashboard>,
+    mut progress: Option<ProgressReporter<io::Stderr>>,
+) -> Result<usize, SimError> {
+    if warmup > 0 {
+        simulate_accesses(cache, accesses.by_ref().take(warmup).inspect(|_| progress.iter_mut().for_each(ProgressReporter::tick)), 0)?;
+        cache.reset_stats();
+    }
+    let warmup_instructions = accesses.instructions();
+    let remaining = accesses.by_ref().inspect(|_| progress.iter_mut().for_each(ProgressReporter::tick));
+    if let Some(dashboard) = dashboard {
+        simulate_accesses_live(cache, remaining, dashboard)?;
+    } else {
+        simulate_accesses(cache, remaining, 0)?;
+    }
+    Ok(accesses.instructions() - warmup_instructions)
+}
+
+// Format the hits, misses and evictions of several runs together, with their miss rate
+fn format_totals(total: &Stats) -> String {
+    let accesses = total.hits + total.misses;
+    let miss_rate = if accesses == 0 { 0.0 } else { 100.0 * total.misses as f64 / accesses as f64 };
+    format!("hits:{} misses:{} evictions:{} miss-rate:{:.2}%", total.hits, total.misses, total.evictions, miss_rate)
+}
+
+// Format the instruction count and the misses per thousand instructions
+fn format_mpki(misses: usize, instructions: usize) -> String {
+    format!("instructions:{} mpki:{:.2}", instructions, 1000.0 * misses as f64 / instructions as f64)
+}
+
+// Step through a trace in the debugger, or its redrawn view with --tui, after simulating its warm-up accesses
+fn debug_trace(cache: &mut Cache, accesses: &mut TraceAccesses, warmup: usize, tui: bool) -> Result<(), SimError> {
+    let accesses = accesses.collect::<Result<Vec<_>, SimError>>()?;
+    let warmup = warmup.min(accesses.len());
+    if warmup > 0 {
+        for result in cache.drive(accesses[..warmup].iter().copied()) {
+            result?;
+        }
+        cache.reset_stats();
+    }
+    if tui {
+        tui_accesses(cache, &accesses[warmup..], io::stdin().lock(), &mut io::stdout())
+    } else {
+        debug_accesses(cache, &accesses[warmup..], io::stdin().lock(), &mut io::stdout())
+    }
+}
+
+// Write the tracefile as a binary trace and return how many accesses it holds
+fn convert_trace(accesses: &mut TraceAccesses, output: &str) -> Result<usize, SimError> {
+    convert_accesses(accesses, &mut create_output(output)?)
+}
+
+// Open a file to write a trace to, or stdout for "-"
+fn create_output(output: &str) -> Result<BufWriter<Box<dyn Write>>, SimError> {
+    let writer: Box<dyn Write> = if output == STDIN_TRACEFILE {
+        Box::new(io::stdout().lock())
+    } else {
+        Box::new(File::create(output).map_err(|err| SimError::Io(format!("failed to create {} ({})", output, err)))?)
+    };
+    Ok(BufWriter::new(writer))
+}
+
+// Write the accesses of a generator as a trace and return how many there are
+fn generate_trace(params: &Args, output: &str) -> Result<usize, SimError> {
+    let accesses: Box<dyn Iterator<Item = MemoryAccess>> = match params.kernel {
+        Some(_) => matrix_kernel(params)?.accesses()?,
+        None => Box::new(generator(params)?.accesses()?),
+    };
+    let mut writer = TraceWriter::new(create_output(output)?, params.format.clone());
+    for access in accesses {
+        writer.write_access(&access)?;
+    }
+    let count = writer.accesses();
+    writer.finish()?;
+    Ok(count)
+}
+
+// Cache configurations of a sweep, sharing the options other than s, E and b
+fn sweep_builders(params: &Args) -> Vec<CacheBuilder> {
+    let builder = cache_builder(params);
+    params.sweep.iter()
+        .map(|&(s, e, b)| builder.clone().set_index_bits(s).associativity(e).block_bits(b))
+        .collect()
+}
+
+// Load a saved cache, checking it has the geometry given on the command line
+fn restore_cache(path: &str, params: &Args) -> Result<Cache, SimError> {
+    let cache = Cache::load_checkpoint(path)?;
+    if cache.set_count() != 1 << params.s || cache.associativity() != params.e || cache.block_size() != 1 << params.b {
+        return Err(SimError::Config(format!(
+            "checkpoint {} holds a cache of {} sets x {} lines of {} bytes, not the one given by -s, -E and -b",
+            path, cache.set_count(), cache.associativity(), cache.block_size()
+        )));
+    }
+    Ok(cache)
+}
+
+pub fn main() -> ExitCode {
+    // Collect command line arguments and parse them
+    let args: Vec<String> = env::args().collect();
+    let json = json_errors(&args);
+    let fail = |context: &str, err: &SimError| report_error(json, context, None, err);
+    let params = match with_env_defaults(&args, |name| env::var(name).ok()).and_then(|args| parse_args(&args)) {
+        Ok(params) => params,
+        Err(err) => {
+            let code = fail("Error parsing command-line arguments", &err);
+            if !json {
+                eprintln!("{}", USAGE);
+            }
+            return code;
+        }
+    };
+    if let Some(level) = params.log_level {
+        set_log_level(level);
+    }
+
+    // Convert the tracefile to a binary trace, reporting to stderr if the trace goes to stdout
+    if params.convert {
+        let mut accesses = match open_trace(&params) {
+            Ok(accesses) => accesses,
+            Err(err) => return report_run_error(json, &err, &params.t),
+        };
+        let output = params.output.as_deref().unwrap_or_default();
+        match convert_trace(&mut accesses, output) {
+            Ok(count) if output == STDIN_TRACEFILE => eprintln!("accesses:{}", count),
+            Ok(count) => println!("accesses:{}", count),
+            Err(err) => return report_run_error(json, &err, &params.t),
+        }
+        if params.lenient {
+            eprintln!("skipped-lines:{}", accesses.skipped_lines());
+        }
+        return ExitCode::SUCCESS;
+    }
+
+    // Print the reuse-distance histogram of the tracefile
+    if params.reuse {
+        let mut accesses = match open_trace(&params) {
+            Ok(accesses) => accesses,
+            Err(err) => return report_run_error(json, &err, &params.t),
+        };
+        match reuse_histogram(&mut accesses, params.b) {
+            Ok(histogram) => println!("{}", histogram.format()),
+            Err(err) => return report_run_error(json, &err, &params.t),
+        }
+        print_skipped_lines(&params, &accesses);
+        return ExitCode::SUCCESS;
+    }
+
+    // Print the working-set curve of the tracefile
+    if params.working_set {
+        let mut accesses = match open_trace(&params) {
+            Ok(accesses) => accesses,
+            Err(err) => return report_run_error(json, &err, &params.t),
+        };
+        let window = params.window.unwrap_or_default();
+        match working_set_curve(&mut accesses, params.b, window, params.step.unwrap_or(window)) {
+            Ok(points) => println!("{}", format_working_set(&points, params.b)),
+            Err(err) => return report_run_error(json, &err, &params.t),
+        }
+        print_skipped_lines(&params, &accesses);
+        return ExitCode::SUCCESS;
+    }
+
+    // Report the blocks the threads of the tracefile falsely share
+    if params.false_sharing {
+        let mut accesses = match open_trace(&params) {
+            Ok(accesses) => accesses,
+            Err(err) => return report_run_error(json, &err, &params.t),
+        };
+        match detect_false_sharing(&mut accesses, params.b) {
+            Ok(report) => println!("{}", report.format(params.top.unwrap_or(DEFAULT_SHARING_TOP))),
+            Err(err) => return report_run_error(json, &err, &params.t),
+        }
+        print_skipped_lines(&params, &accesses);
+        return ExitCode::SUCCESS;
+    }
+
+    // Simulate the page faults of the tracefile
+    if params.paging {
+        let mut accesses = match open_trace(&params) {
+            Ok(accesses) => accesses,
+            Err(err) => return report_run_error(json, &err, &params.t),
+        };
+        let (frames, page_size) = (params.frames.unwrap_or_default(), params.page_size.unwrap_or(DEFAULT_PAGE_SIZE));
+        let policy = params.page_policy.unwrap_or_default();
+        match simulate_paging(&mut accesses, frames, page_size, policy) {
+            Ok(memory) => println!("frames:{} page-size:{} policy:{}\n{}", frames, page_size, policy, memory.stats().format()),
+            Err(err) => return report_run_error(json, &err, &params.t),
+        }
+        print_skipped_lines(&params, &accesses);
+        return ExitCode::SUCCESS;
+    }
+
+    // Print where each address lands in the cache
+    if params.decode {
+        for &address in &params.addresses {
+            match decompose_address(address, params.s, params.b) {
+                Ok(parts) => println!("address:{:x} {}", address, parts),
+                Err(err) => return fail("Error", &err),
+            }
+        }
+        return ExitCode::SUCCESS;
+    }
+
+    // Print the completion script of a shell
+    if let Some(shell) = params.completions {
+        let options = command_line_options();
+        print!("{}", completion_script(shell, &CommandLine { command: "sim", subcommands: SUBCOMMANDS, options: &options }));
+        return ExitCode::SUCCESS;
+    }
+
+    // Serve caches to JSON-RPC clients until interrupted
+    if params.service {
+        let address = params.listen.as_deref().unwrap_or(DEFAULT_SERVICE_ADDRESS);
+        match listen(address) {
+            Ok(listener) => {
+                eprintln!("serving JSON-RPC on {}", address);
+                serve(listener);
+            }
+            Err(err) => return fail("Error", &err),
+        }
+        return ExitCode::SUCCESS;
+    }
+
+    // Write a synthetic trace, reporting its length unless the trace goes to stdout
+    if params.gen {
+        let output = params.output.as_deref().unwrap_or(STDIN_TRACEFILE);
+        match generate_trace(&params, output) {
+            Ok(count) if output != STDIN_TRACEFILE => println!("accesses:{}", count),
+            Ok(_) => {}
+            Err(err) => return fail("Error generating trace", &err),
+        }
+        return ExitCode::SUCCESS;
+    }
+
+    // Simulate every configuration of a sweep in one pass and compare them
+    if !params.sweep.is_empty() {
+        let mut accesses = match open_trace(&params) {
+            Ok(accesses) => accesses,
+            Err(err) => return report_run_error(json, &err, &params.t),
+        };
+        let mut progress = progress_reporter(&params, &accesses);
+        let result = sweep_accesses(&sweep_builders(&params), accesses.by_ref().inspect(|_| progress.iter_mut().for_each(ProgressReporter::tick)), params.warmup.unwrap_or(0));
+        drop(progress);
+        match result {
+            Ok(caches) => {
+                if let Err(err) = print_results(&params, caches.iter().map(|cache| cache.csv_row(&params.t, params.warmup.unwrap_or(0))), format_sweep(&caches)) {
+                    return fail("Error writing results", &err);
                 }
+                print_skipped_lines(&params, &accesses);
             }
-            Ok(Opt(_, None)) => {
-                return Err("unexpected option".to_string());
+            Err(err @ SimError::Config(_)) => return fail("Error initializing cache", &err),
+            Err(err) => return report_run_error(json, &err, &params.t),
+        }
+        return ExitCode::SUCCESS;
+    }
+
+    // Replay the tracefiles together, one per core, or the threads of one tracefile on the cores of the
+    // configuration, through private levels in front of the shared ones
+    if let Some(config) = params.config.as_ref().filter(|config| params.interleave.is_some() || config.cores.is_some()) {
+        let traces: Vec<&String> = [&params.t].into_iter().chain(&params.extra_traces).collect();
+        let mut multicore = match MultiCore::new(config, config.cores.unwrap_or(traces.len())) {
+            Ok(multicore) => multicore,
+            Err(err) => return fail("Error initializing cache", &err),
+        };
+        let mut readers = Vec::new();
+        for trace in &traces {
+            match open_tracefile(&params, trace) {
+                Ok(accesses) => readers.push(accesses),
+                Err(err) => return report_run_error(json, &err, trace),
             }
-            Err(err) => {
-                return Err(format!("{}", err));
+        }
+        // Name the tracefile of a malformed line, since the traces are read together
+        let mut accesses: Vec<_> = readers.iter_mut().zip(&traces).map(|(accesses, trace)| {
+            accesses.map(move |access| access.map_err(|err| match err {
+                SimError::Trace(message) => SimError::Trace(format!("{}: {}", trace, message)),
+                SimError::TraceRead(message) => SimError::TraceRead(format!("{}: {}", trace, message)),
+                err => err,
+            }))
+        }).collect();
+        let result = match params.interleave {
+            Some(interleave) => multicore.simulate_interleaved(accesses, interleave, params.warmup.unwrap_or(0)),
+            None => multicore.simulate_threads(accesses.remove(0), params.warmup.unwrap_or(0)),
+        };
+        match result {
+            Ok(()) => {
+                println!("{}", multicore.format_stats());
+                for accesses in &readers {
+                    print_skipped_lines(&params, accesses);
+                }
             }
+            Err(err) => return report_run_error(json, &err, &traces.iter().map(|trace| trace.as_str()).collect::<Vec<_>>().join(",")),
         }
+        return ExitCode::SUCCESS;
     }
 
-    if s == 0 || e == 0 || b == 0 || t.is_empty() {
-        return Err("missing required arguments, incorrect command-line format".to_string());
+    // Simulate the levels of a hierarchy, each one looked up on the misses of the one before, and the DRAM behind them
+    if let Some(config) = params.config.as_ref().filter(|config| !config.is_single_cache()) {
+        let mut hierarchy = match Hierarchy::new(config) {
+            Ok(hierarchy) => hierarchy,
+            Err(err) => return fail("Error initializing cache", &err),
+        };
+        let mut accesses = match open_trace(&params) {
+            Ok(accesses) => accesses,
+            Err(err) => return report_run_error(json, &err, &params.t),
+        };
+        let mut progress = progress_reporter(&params, &accesses);
+        let result = hierarchy.simulate_accesses(accesses.by_ref().inspect(|_| progress.iter_mut().for_each(ProgressReporter::tick)), params.warmup.unwrap_or(0));
+        drop(progress);
+        match result {
+            Ok(()) => {
+                println!("{}", hierarchy.format_stats());
+                print_skipped_lines(&params, &accesses);
+            }
+            Err(err) => return report_run_error(json, &err, &params.t),
+        }
+        return ExitCode::SUCCESS;
     }
 
-    Ok((s, e, b, t))
-}
+    // Initialize the cache, or restore a saved one
+    let builder = cache_builder(&params);
+    let cache = match &params.restore {
+        Some(path) => restore_cache(path, &params),
+        None => builder.build(),
+    };
+    let mut cache = match cache {
+        Ok(c) => c,
+        Err(err) => return fail("Error initializing cache", &err),
+    };
 
-// Read memory access trace file and return memory accesses
-fn read_tracefile(filename: &str) -> Result<Vec<String>, std::io::Error> {
-    let file_path = format!("../{}", filename);
-    let file = File::open(&file_path)?;
-    let reader = BufReader::new(file);
-    reader.lines().collect()
-}
+    // Run the locality demo on the configured cache instead of a tracefile
+    if params.demo {
+        match locality_demo(&builder, params.demo_n.unwrap_or(DEFAULT_DEMO_N), params.element_size.unwrap_or(DEFAULT_DEMO_ELEMENT_SIZE)) {
+            Ok(report) => print!("{}", report),
+            Err(err) => return fail("Error running demo", &err),
+        }
+        return ExitCode::SUCCESS;
+    }
 
-// Parse memory access string and return set index, tag, and operation
-fn parse_memory_access(memory_access: &str, s: usize, b: usize) -> Result<Option<(char, usize, usize)>, String> {
-    if memory_access.is_empty() {
-        return Ok(None);
+    // Time the simulation of synthetic workloads on the configured cache
+    if params.bench {
+        match run_bench(&builder, params.length.unwrap_or(DEFAULT_BENCH_LENGTH)) {
+            Ok(results) => print!("{}", format_bench(&results)),
+            Err(err) => return fail("Error running benchmark", &err),
+        }
+        return ExitCode::SUCCESS;
     }
-    let memory_access_parts: Vec<&str> = memory_access.split_whitespace().collect();
 
-    if memory_access_parts.len() >= 2 {
-        if memory_access_parts[0] == "I" { // Skip instruction cache accesses
-            return Ok(None); 
+    // Simulate accesses as they are typed
+    if params.repl {
+        if let Err(err) = repl(&mut cache, io::stdin().lock(), &mut io::stdout()) {
+            return fail("Error", &err);
         }
+        return ExitCode::SUCCESS;
+    }
 
-        let operation = match memory_access_parts[0] {
-            "S" | "M" | "L" => memory_access_parts[0].chars().next().unwrap(),
-            _ => return Err("invalid operation encountered".to_string()),
+    // Compare the outcome of every access with a reference simulator or its saved output
+    if params.compare_ref.is_some() || params.compare_expected.is_some() {
+        let accesses = match open_trace(&params) {
+            Ok(accesses) => accesses,
+            Err(err) => return report_run_error(json, &err, &params.t),
+        };
+        let comparison = match (&params.compare_ref, &params.compare_expected) {
+            (Some(program), _) => compare_with_program(&mut cache, accesses, program, &params.t),
+            (None, Some(path)) => File::open(path)
+                .map_err(|err| SimError::Io(format!("failed to open {} ({})", path, err)))
+                .and_then(|file| compare_with_reference(&mut cache, accesses, io::BufReader::new(file))),
+            (None, None) => unreachable!(),
+        };
+        return match comparison {
+            Ok(comparison) => {
+                println!("{}", comparison.format());
+                match comparison.divergence {
+                    Some(_) => ExitCode::from(EXIT_FAILURE),
+                    None => ExitCode::SUCCESS,
+                }
+            }
+            Err(err) => report_run_error(json, &err, &params.t),
         };
-        let address_size_parts: Vec<&str> = memory_access_parts[1].split(',').collect();
-        if address_size_parts.len() >= 2 {
-            let hexadecimal_address = u64::from_str_radix(address_size_parts[0], 16).map_err(|e| format!("failed to parse address ({})", e))?;
-            let binary_address = format!("{:0>64b}", hexadecimal_address);
-            let set_index_start = 64 - b;
-            let tag_start = set_index_start - s;
-            let tag = usize::from_str_radix(&binary_address[..tag_start], 2).map_err(|e| format!("failed to parse tag ({})", e))?;
-            let set_index = usize::from_str_radix(&binary_address[tag_start..set_index_start], 2).map_err(|e| format!("failed to parse set index ({})", e))?;
-            return Ok(Some((operation, set_index, tag)));
-        }
     }
-    Err("invalid memory access format".to_string())
-}
 
-pub fn main() {
-    // Collect command line arguments and parse them
-    let args: Vec<String> = env::args().collect();
-    let (s, e, b, t) = match parse_args(&args) {
-        Ok(params) => params,
-        Err(err) => {
-            eprintln!("Error parsing command-line arguments: {}", err);
-            eprintln!("Usage: -- -s <set index bits> -E <lines in set> -b <block bits> -t <tracefile>");
-            return;
+    // Simulate the replacement policies side by side and list the accesses where their outcomes differ
+    if !params.compare_policies.is_empty() {
+        let mut accesses = match open_trace(&params) {
+            Ok(accesses) => accesses,
+            Err(err) => return report_run_error(json, &err, &params.t),
+        };
+        let mut progress = progress_reporter(&params, &accesses);
+        let limit = params.divergences.unwrap_or(DEFAULT_DIVERGENCES);
+        let result = compare_policies(&builder, &params.compare_policies, accesses.by_ref().inspect(|_| progress.iter_mut().for_each(ProgressReporter::tick)), params.warmup.unwrap_or(0), limit);
+        drop(progress);
+        match result {
+            Ok(comparison) => {
+                print!("{}", comparison.format());
+                print_skipped_lines(&params, &accesses);
+            }
+            Err(err @ SimError::Config(_)) => return fail("Error initializing cache", &err),
+            Err(err) => return report_run_error(json, &err, &params.t),
         }
-    };
+        return ExitCode::SUCCESS;
+    }
 
-    // Initialize the cache
-    let mut cache = match Cache::new(s, e, b) {
-        Ok(c) => c,
-        Err(err) => {
-            eprintln!("Error initializing cache: {}", err);
-            return;
+    // Simulate the tracefiles in turn, each one on an empty cache unless --keep-cache is given
+    if !params.extra_traces.is_empty() {
+        let warmup = params.warmup.unwrap_or(0);
+        let (mut rows, mut total, mut total_instructions) = (Vec::new(), Stats::default(), 0);
+        for (index, trace) in [&params.t].into_iter().chain(&params.extra_traces).enumerate() {
+            if index > 0 && params.keep_cache {
+                cache.reset_stats();
+            } else if index > 0 {
+                cache = match builder.build() {
+                    Ok(cache) => cache,
+                    Err(err) => return fail("Error initializing cache", &err),
+                };
+            }
+            let mut accesses = match open_tracefile(&params, trace) {
+                Ok(accesses) => accesses,
+                Err(err) => return report_run_error(json, &err, trace),
+            };
+            let progress = progress_reporter(&params, &accesses);
+            let instructions = match simulate_trace(&mut cache, &mut accesses, warmup, None, progress) {
+                Ok(instructions) => instructions,
+                Err(err) => return report_run_error(json, &err, trace),
+            };
+            if params.csv {
+                rows.push(cache.csv_row(trace, warmup));
+            } else {
+                println!("trace:{}\n{}", trace, cache.format_stats());
+                if instructions > 0 {
+                    println!("{}", format_mpki(cache.misses(), instructions));
+                }
+            }
+            print_skipped_lines(&params, &accesses);
+            total.add(&cache.snapshot_stats());
+            total_instructions += instructions;
         }
+        let mut text = format!("total: {}\n", format_totals(&total));
+        if total_instructions > 0 {
+            text.push_str(&format!("{}\n", format_mpki(total.misses, total_instructions)));
+        }
+        if let Err(err) = print_results(&params, rows, text) {
+            return fail("Error writing results", &err);
+        }
+        return ExitCode::SUCCESS;
+    }
+
+    let mut accesses = match open_trace(&params) {
+        Ok(accesses) => accesses,
+        Err(err) => return report_run_error(json, &err, &params.t),
     };
 
-    // Read tracefile and loop through memory accesses
-    match read_tracefile(&t) {
-        Ok(memory_accesses) => {
-            for memory_access in &memory_accesses {
-
-                // Parse memory accesses
-                match parse_memory_access(memory_access, s, b) {
-                    Ok(Some((operation, set_index, tag))) => {
-
-                        // Simulate cache behaviour using memory access data
-                        match cache.simulate_memory_access(operation, set_index, tag) {
-                            Ok(_) => {}
-                            Err(err) => {
-                                eprintln!("Error simulating cache access: {}", err);
-                                return;
-                            }
-                        }
-                    }
-                    Ok(None) => continue,
-                    Err(err) => {
-                        eprintln!("Error parsing memory access: {}", err);
-                        return;
-                    }
+    // Simulate the sampled windows of the tracefile and extrapolate
+    if let Ok(Some(sampling)) = sampling(&params) {
+        match simulate_accesses_sampled(&mut cache, accesses.by_ref(), sampling) {
+            Ok(sample_stats) => {
+                println!("{}", sample_stats.format_stats(&cache));
+                let details = print_details(&params, &cache);
+                print_skipped_lines(&params, &accesses);
+                if let Err(err) = details {
+                    return fail("Error writing heatmap", &err);
                 }
             }
+            Err(err) => return report_run_error(json, &err, &params.t),
         }
-        Err(err) => {
-            eprintln!("Error reading tracefile {}: {}", t, err);
-            return;
+        return ExitCode::SUCCESS;
+    }
+
+    // Start the live dashboard before the run it shows
+    let dashboard = match params.serve.as_deref().map(Dashboard::serve).transpose() {
+        Ok(dashboard) => dashboard,
+        Err(err) => return fail("Error starting the dashboard", &err),
+    };
+    if let Some(dashboard) = &dashboard {
+        eprintln!("dashboard: http://{}/", dashboard.address());
+    }
+
+    // Read tracefile and simulate its memory accesses, interactively in debug mode
+    let result = if params.debug {
+        debug_trace(&mut cache, &mut accesses, params.warmup.unwrap_or(0), params.tui).map(|_| 0)
+    } else {
+        let progress = progress_reporter(&params, &accesses);
+        simulate_trace(&mut cache, &mut accesses, params.warmup.unwrap_or(0), dashboard.as_ref(), progress)
+    };
+    let instructions = match result {
+        Ok(instructions) => instructions,
+        Err(err) => return report_run_error(json, &err, &params.t),
+    };
+
+    // Print results, normalized by the instruction count if the trace records instruction fetches
+    let mut text = format!("{}\n", cache.format_stats());
+    if instructions > 0 {
+        text.push_str(&format!("{}\n", format_mpki(cache.misses(), instructions)));
+    }
+    if let Err(err) = print_results(&params, [cache.csv_row(&params.t, params.warmup.unwrap_or(0))], text) {
+        return fail("Error writing results", &err);
+    }
+    let details = print_details(&params, &cache);
+    print_skipped_lines(&params, &accesses);
+    if let Err(err) = details {
+        return fail("Error writing heatmap", &err);
+    }
+
+    if let Some(path) = &params.checkpoint {
+        if let Err(err) = cache.save_checkpoint(path) {
+            return fail("Error saving checkpoint", &err);
         }
     }
 
-    // Print results
-    cache.print_stats();
+    if let Some(dashboard) = dashboard {
+        eprintln!("serving the final results at http://{}/ until interrupted", dashboard.address());
+        dashboard.wait();
+    }
+    ExitCode::SUCCESS
 }
 
 
@@ -313,7 +2120,7 @@ fn test_parse_args_valid_input() {
         "-t".to_string(),
         "test_tracefile".to_string(),
     ];
-    assert_eq!(parse_args(&args), Ok((4, 2, 4, "test_tracefile".to_string())));
+    assert_eq!(parse_args(&args), Ok(Args { s: 4, e: 2, b: 4, t: "test_tracefile".to_string(), ..Default::default() }));
 }
 
 #[test]
@@ -329,7 +2136,7 @@ fn test_parse_args_different_order() {
         "-b".to_string(),
         "4".to_string(),
     ];
-    assert_eq!(parse_args(&args), Ok((4, 2, 4, "test_tracefile".to_string())));
+    assert_eq!(parse_args(&args), Ok(Args { s: 4, e: 2, b: 4, t: "test_tracefile".to_string(), ..Default::default() }));
 }
 
 #[test]
@@ -344,7 +2151,7 @@ fn test_parse_args_missing_whitespace() {
         "-b".to_string(),
         "4".to_string(),
     ];
-    assert_eq!(parse_args(&args), Ok((4, 2, 4, "test_tracefile".to_string())));
+    assert_eq!(parse_args(&args), Ok(Args { s: 4, e: 2, b: 4, t: "test_tracefile".to_string(), ..Default::default() }));
 }
 
 #[test]
@@ -465,275 +2272,1122 @@ fn test_parse_args_case_sensitivity_to_lower() {
     assert!(parse_args(&args).is_err());
 }
 
-// Tests for read_tracefile function
 #[test]
-fn test_read_tracefile_ibm() {
-    let expected_contents = vec![
-       " L 10,4 ", 
-       " S 18,4",
-       " L 20,4",
-       " S 28,4",
-       " S 50,4",
+fn test_parse_args_tie_break() {
+    let args = vec![
+        "program".to_string(),
+        "-s".to_string(),
+        "4".to_string(),
+        "--tie-break".to_string(),
+        "round-robin".to_string(),
+        "-E".to_string(),
+        "2".to_string(),
+        "-b".to_string(),
+        "4".to_string(),
+        "-t".to_string(),
+        "test_tracefile".to_string(),
+        "--seed=7".to_string(),
+    ];
+    let params = parse_args(&args).unwrap();
+    assert_eq!(params.tie_break, TieBreak::RoundRobin);
+    assert_eq!(params.seed, Some(7));
+    assert_eq!(params.t, "test_tracefile");
+}
+
+#[test]
+fn test_parse_args_skewed() {
+    let args = vec![
+        "program".to_string(),
+        "-s".to_string(),
+        "4".to_string(),
+        "-E".to_string(),
+        "2".to_string(),
+        "-b".to_string(),
+        "4".to_string(),
+        "-t".to_string(),
+        "test_tracefile".to_string(),
+        "--skewed".to_string(),
+    ];
+    assert!(parse_args(&args).unwrap().skewed);
+    assert!(!parse_args(&args[..9]).unwrap().skewed);
+}
+
+#[test]
+fn test_parse_args_address_width() {
+    let args = vec![
+        "program".to_string(),
+        "-s".to_string(),
+        "4".to_string(),
+        "-E".to_string(),
+        "2".to_string(),
+        "-b".to_string(),
+        "4".to_string(),
+        "-t".to_string(),
+        "test_tracefile".to_string(),
+        "--addr-bits".to_string(),
+        "48".to_string(),
+        "--oob-addr".to_string(),
+        "skip".to_string(),
+    ];
+    let params = parse_args(&args).unwrap();
+    assert_eq!(params.address_bits, Some(48));
+    assert_eq!(params.oob_policy, OobPolicy::Skip);
+}
+
+#[test]
+fn test_parse_args_invalid_long_options() {
+    let invalid_options = vec![
+        vec!["--tie-break", "oldest"],
+        vec!["--tie-break"],
+        vec!["--seed", "x"],
+        vec!["--verbose"],
+        vec!["--skewed=yes"],
+        vec!["--way-predict", "perfect"],
+        vec!["--skewed", "--way-predict", "mru"],
+        vec!["--addr-bits", "0"],
+        vec!["--addr-bits", "65"],
+        vec!["--oob-addr", "wrap"],
+        vec!["--partition", "0:0-1,1:2"],
+        vec!["--uncacheable", "100-ff"],
+        vec!["--uncacheable", "mmio"],
+        vec!["--restore", "state.json", "--uncacheable", "100-1ff"],
+        vec!["--lock-ways", "1-0"],
+        vec!["--lock-ways", "all"],
+        vec!["--lock-ways", "2"],
+        vec!["--lock-ways", "0@16"],
+        vec!["--lock-ways", "0", "--compress", "0.5"],
+        vec!["--restore", "state.json", "--lock-ways", "0"],
+        vec!["--scratchpad", "0-ff,200-2ff"],
+        vec!["--scratchpad", "0-7f"],
+        vec!["--scratchpad", "0-1ff"],
+        vec!["--banks", "3"],
+        vec!["--banks", "0"],
+        vec!["--banks", "4", "--issue-width", "0"],
+        vec!["--issue-width", "2"],
+        vec!["--write-buffer", "0"],
+        vec!["--write-buffer", "many"],
+        vec!["--write-buffer", "4", "--drain-interval", "0"],
+        vec!["--drain-interval", "5"],
+        vec!["--store-buffer", "0"],
+        vec!["--bypass", "3K"],
+        vec!["--bypass", "8"],
+        vec!["--bypass", "4K", "--skewed"],
+        vec!["--tinylfu", "--compress", "0.5"],
+        vec!["--tinylfu=yes"],
+        vec!["--compress", "1.5"],
+        vec!["--compress", "half"],
+        vec!["--compress", "0.5", "--skewed"],
+        vec!["--undo-depth", "5"],
+        vec!["--debug", "--undo-depth", "-1"],
+        vec!["--seed=1", "--seed=2"],
+        vec!["--restore", "state.json", "--banks", "4"],
+        vec!["--restore", "state.json", "--debug"],
+        vec!["--warmup", "-1"],
+        vec!["--sample-period", "10"],
+        vec!["--sample-length", "10"],
+        vec!["--sample-period", "10", "--sample-length", "20"],
+        vec!["--sample-period", "10", "--sample-length", "0"],
+        vec!["--sample-period", "10", "--sample-length", "5", "--warmup", "5"],
+        vec!["--sample-period", "10", "--sample-length", "5", "--debug"],
+        vec!["--sample-period", "10", "--sample-length", "5", "--tui"],
+        vec!["--output", "csv", "--tui"],
+        vec!["--serve", "127.0.0.1:8080", "--debug"],
+        vec!["--serve", "127.0.0.1:8080", "--sample-period", "10", "--sample-length", "5"],
+        vec!["--n", "16"],
+        vec!["--element-size", "4"],
+    ];
+    for invalid_option in invalid_options {
+        let mut args = vec![
+            "program".to_string(),
+            "-s".to_string(),
+            "4".to_string(),
+            "-E".to_string(),
+            "2".to_string(),
+            "-b".to_string(),
+            "4".to_string(),
+            "-t".to_string(),
+            "test_tracefile".to_string(),
+        ];
+        args.extend(invalid_option.iter().map(|arg| arg.to_string()));
+        assert!(matches!(parse_args(&args), Err(SimError::ParseArgs(_))));
+    }
+}
+
+#[test]
+fn test_parse_args_long_option_as_short_flag_value() {
+    let args = vec![
+        "program".to_string(),
+        "-s".to_string(),
+        "4".to_string(),
+        "-E".to_string(),
+        "2".to_string(),
+        "-b".to_string(),
+        "4".to_string(),
+        "-t".to_string(),
+        "--seed".to_string(),
+    ];
+    assert_eq!(parse_args(&args).unwrap().t, "--seed");
+}
+
+#[test]
+fn test_parse_args_demo() {
+    let args: Vec<String> = ["program", "demo", "locality", "-s", "4", "-E", "2", "-b", "4", "--n", "64", "--element-size", "4"].iter().map(|arg| arg.to_string()).collect();
+    let params = parse_args(&args).unwrap();
+    assert_eq!(params, Args { s: 4, e: 2, b: 4, demo: true, demo_n: Some(64), element_size: Some(4), ..Default::default() });
+
+    let invalid_demos = vec![
+        vec!["program", "demo"],
+        vec!["program", "demo", "strides", "-s", "4", "-E", "2", "-b", "4"],
+        vec!["program", "demo", "locality", "-s", "4", "-E", "2", "-b", "4", "-t", "test_tracefile"],
+        vec!["program", "demo", "locality", "-s", "4", "-E", "2", "-b", "4", "--debug"],
+        vec!["program", "demo", "locality", "-s", "4", "-E", "2", "-b", "4", "--n", "x"],
+        vec!["program", "demo", "locality", "-s", "4", "-E", "2", "-b", "4", "--checkpoint", "state.json"],
+        vec!["program", "demo", "locality", "-s", "4", "-E", "2", "-b", "4", "--warmup", "10"],
+        vec!["program", "demo", "locality", "-s", "4", "-E", "2"],
     ];
-    let result = read_tracefile("traces/ibm.trace");
-    assert!(result.is_ok());
+    for invalid_demo in invalid_demos {
+        let args: Vec<String> = invalid_demo.iter().map(|arg| arg.to_string()).collect();
+        assert!(parse_args(&args).is_err());
+    }
+}
 
-    let actual_contents = result.unwrap();
-    assert_eq!(actual_contents, expected_contents);
+#[test]
+fn test_parse_args_convert() {
+    let args: Vec<String> = ["program", "convert", "-t", "test_tracefile", "-o", "trace.bin", "--format", "din", "--lenient"].iter().map(|arg| arg.to_string()).collect();
+    let params = parse_args(&args).unwrap();
+    assert_eq!(params, Args {
+        t: "test_tracefile".to_string(),
+        convert: true,
+        output: Some("trace.bin".to_string()),
+        format: TraceFormat::Dinero,
+        lenient: true,
+        ..Default::default()
+    });
+
+    let invalid_converts = vec![
+        vec!["program", "convert", "-t", "test_tracefile"],
+        vec!["program", "convert", "-o", "trace.bin"],
+        vec!["program", "convert", "-t", "test_tracefile", "-o", "trace.bin", "-s", "4"],
+        vec!["program", "convert", "-t", "test_tracefile", "-o", "trace.bin", "--warmup", "10"],
+        vec!["program", "convert", "-t", "test_tracefile", "-o", "trace.bin", "--output", "csv"],
+        vec!["program", "-s", "4", "-E", "2", "-b", "4", "-t", "test_tracefile", "-o", "trace.bin"],
+    ];
+    for invalid_convert in invalid_converts {
+        let args: Vec<String> = invalid_convert.iter().map(|arg| arg.to_string()).collect();
+        assert!(parse_args(&args).is_err());
+    }
 }
 
 #[test]
-fn test_read_tracefile_yi() {
-    let expected_contents = vec![
-        " L 10,1",
-        " M 20,1",
-        " L 22,1",
-        " S 18,1",
-        " L 110,1",
-        " L 210,1",
-        " M 12,1",
+fn test_parse_args_gen() {
+    let args: Vec<String> = ["program", "gen", "--pattern", "strided", "--footprint", "32K", "--length", "1000", "--stride", "64", "--write-ratio", "0.25", "--seed", "7"].iter().map(|arg| arg.to_string()).collect();
+    let params = parse_args(&args).unwrap();
+    assert_eq!(params, Args {
+        gen: true,
+        pattern: Some(Pattern::Strided),
+        footprint: Some(32 << 10),
+        length: Some(1000),
+        stride: Some(64),
+        write_ratio: Some(0.25),
+        seed: Some(7),
+        ..Default::default()
+    });
+    assert_eq!(generator(&params), Ok(Generator {
+        pattern: Pattern::Strided,
+        footprint: 32 << 10,
+        length: 1000,
+        access_size: DEFAULT_GEN_ACCESS_SIZE,
+        stride: 64,
+        write_ratio: 0.25,
+        seed: 7,
+    }));
+
+    let invalid_gens = vec![
+        vec!["program", "gen", "--pattern", "random", "--footprint", "1M"],
+        vec!["program", "gen", "--pattern", "zigzag", "--footprint", "1M", "--length", "10"],
+        vec!["program", "gen", "--pattern", "random", "--footprint", "1X", "--length", "10"],
+        vec!["program", "gen", "--pattern", "random", "--footprint", "1M", "--length", "10", "--stride", "8"],
+        vec!["program", "gen", "--pattern", "strided", "--footprint", "1M", "--length", "10"],
+        vec!["program", "gen", "--pattern", "random", "--footprint", "4", "--length", "10"],
+        vec!["program", "gen", "--pattern", "random", "--footprint", "1M", "--length", "10", "--write-ratio", "2"],
+        vec!["program", "gen", "--pattern", "random", "--footprint", "1M", "--length", "10", "-s", "4"],
+        vec!["program", "-s", "4", "-E", "2", "-b", "4", "-t", "test_tracefile", "--pattern", "random"],
     ];
+    for invalid_gen in invalid_gens {
+        let args: Vec<String> = invalid_gen.iter().map(|arg| arg.to_string()).collect();
+        assert!(parse_args(&args).is_err());
+    }
+}
+
+#[test]
+fn test_parse_args_gen_pointer_chase() {
+    let args: Vec<String> = ["program", "gen", "--pattern", "pointer-chase", "--footprint", "1M", "--length", "10", "--stride", "64"].iter().map(|arg| arg.to_string()).collect();
+    assert_eq!(generator(&parse_args(&args).unwrap()).unwrap().stride, 64);
+    let args: Vec<String> = ["program", "gen", "--pattern", "pointer-chase", "--footprint", "1M", "--length", "10"].iter().map(|arg| arg.to_string()).collect();
+    assert_eq!(generator(&parse_args(&args).unwrap()).unwrap().stride, 0);
+    let args: Vec<String> = ["program", "gen", "--pattern", "pointer-chase", "--footprint", "1K", "--length", "10", "--stride", "2K"].iter().map(|arg| arg.to_string()).collect();
+    assert!(parse_args(&args).is_err());
+}
 
-    let result = read_tracefile("traces/yi.trace");
-    assert!(result.is_ok());
-
-    let actual_contents = result.unwrap();
-    assert_eq!(actual_contents, expected_contents);
-}
-
-#[test]
-fn test_read_tracefile_yi2() {
-    let expected_contents = vec![
-        " L 0,1",
-        " L 1,1",
-        " L 2,1",
-        " L 3,1",
-        " S 4,1",
-        " L 5,1",
-        " S 6,1",
-        " L 7,1",
-        " S 8,1",
-        " L 9,1",
-        " S a,1",
-        " L b,1",
-        " S c,1",
-        " L d,1",
-        " S e,1",
-        " M f,1",
+#[test]
+fn test_parse_args_gen_kernel() {
+    let args: Vec<String> = ["program", "gen", "--kernel", "transpose", "--n", "32", "--block", "8", "--element-size", "4"].iter().map(|arg| arg.to_string()).collect();
+    let params = parse_args(&args).unwrap();
+    assert_eq!(matrix_kernel(&params), Ok(MatrixKernel { kernel: Kernel::Transpose, n: 32, block: Some(8), element_size: 4 }));
+    let args: Vec<String> = ["program", "gen", "--kernel", "multiply", "--n", "16"].iter().map(|arg| arg.to_string()).collect();
+    assert_eq!(matrix_kernel(&parse_args(&args).unwrap()), Ok(MatrixKernel { kernel: Kernel::Multiply, n: 16, block: None, element_size: 8 }));
+
+    let invalid_kernels = vec![
+        vec!["program", "gen", "--kernel", "transpose"],
+        vec!["program", "gen", "--kernel", "lu", "--n", "16"],
+        vec!["program", "gen", "--kernel", "transpose", "--n", "16", "--block", "32"],
+        vec!["program", "gen", "--kernel", "transpose", "--n", "16", "--length", "10"],
+        vec!["program", "gen", "--kernel", "transpose", "--n", "16", "--pattern", "random"],
+        vec!["program", "gen", "--pattern", "random", "--footprint", "1M", "--length", "10", "--block", "8"],
+        vec!["program", "-s", "4", "-E", "2", "-b", "4", "-t", "test_tracefile", "--kernel", "transpose"],
     ];
+    for invalid_kernel in invalid_kernels {
+        let args: Vec<String> = invalid_kernel.iter().map(|arg| arg.to_string()).collect();
+        assert!(parse_args(&args).is_err());
+    }
+}
 
-    let result = read_tracefile("traces/yi2.trace");
-    assert!(result.is_ok());
+// Tests for the reuse subcommand
+#[test]
+fn test_parse_args_reuse() {
+    let args: Vec<String> = ["program", "reuse", "-t", "test_tracefile", "-b", "6", "--format", "din"].iter().map(|arg| arg.to_string()).collect();
+    let params = parse_args(&args).unwrap();
+    assert!(params.reuse);
+    assert_eq!((params.t.as_str(), params.b, params.format), ("test_tracefile", 6, TraceFormat::Dinero));
+
+    for extra in [vec!["-s", "4"], vec!["--per-set"], vec!["-o", "out.bin"], vec!["--classify-misses"]] {
+        let mut args: Vec<String> = ["program", "reuse", "-t", "test_tracefile", "-b", "6"].iter().map(|arg| arg.to_string()).collect();
+        args.extend(extra.iter().map(|arg| arg.to_string()));
+        assert!(parse_args(&args).is_err());
+    }
+    let args: Vec<String> = ["program", "reuse", "-b", "6"].iter().map(|arg| arg.to_string()).collect();
+    assert!(parse_args(&args).is_err());
+}
 
-    let actual_contents = result.unwrap();
-    assert_eq!(actual_contents, expected_contents);
+// Tests for the working-set subcommand
+#[test]
+fn test_parse_args_working_set() {
+    let args: Vec<String> = ["program", "working-set", "-t", "test_tracefile", "-b", "6", "--window", "1000", "--step", "100"].iter().map(|arg| arg.to_string()).collect();
+    let params = parse_args(&args).unwrap();
+    assert!(params.working_set);
+    assert_eq!((params.b, params.window, params.step), (6, Some(1000), Some(100)));
+
+    for args in [
+        vec!["program", "working-set", "-t", "test_tracefile", "-b", "6"],
+        vec!["program", "working-set", "-t", "test_tracefile", "--window", "0"],
+        vec!["program", "working-set", "-t", "test_tracefile", "--window", "10", "--step", "0"],
+        vec!["program", "working-set", "-t", "test_tracefile", "--window", "10", "-s", "4"],
+        vec!["program", "-s", "4", "-E", "2", "-b", "4", "-t", "test_tracefile", "--window", "10"],
+    ] {
+        let args: Vec<String> = args.iter().map(|arg| arg.to_string()).collect();
+        assert!(parse_args(&args).is_err());
+    }
 }
 
+// Tests for the false-sharing subcommand
 #[test]
-fn test_read_tracefile_long() {
-    assert!(read_tracefile("traces/long.trace").is_ok());
+fn test_parse_args_false_sharing() {
+    let args: Vec<String> = ["program", "false-sharing", "-t", "test_tracefile", "-b", "6", "--top", "5"].iter().map(|arg| arg.to_string()).collect();
+    let params = parse_args(&args).unwrap();
+    assert!(params.false_sharing);
+    assert_eq!((params.t.as_str(), params.b, params.top), ("test_tracefile", 6, Some(5)));
+
+    for args in [
+        vec!["program", "false-sharing", "-b", "6"],
+        vec!["program", "false-sharing", "-t", "test_tracefile", "-b", "6", "--top", "0"],
+        vec!["program", "false-sharing", "-t", "test_tracefile", "-b", "6", "-s", "4"],
+        vec!["program", "false-sharing", "-t", "test_tracefile", "-b", "6", "--window", "10"],
+    ] {
+        let args: Vec<String> = args.iter().map(|arg| arg.to_string()).collect();
+        assert!(parse_args(&args).is_err());
+    }
 }
 
+// Tests for the paging subcommand
 #[test]
-fn test_read_tracefile_trance() {
-    assert!(read_tracefile("traces/trans.trace").is_ok());
+fn test_parse_args_paging() {
+    let args: Vec<String> = ["program", "paging", "-t", "test_tracefile", "--frames", "64", "--page-size", "2M", "--page-policy", "clock"].iter().map(|arg| arg.to_string()).collect();
+    let params = parse_args(&args).unwrap();
+    assert!(params.paging);
+    assert_eq!((params.frames, params.page_size, params.page_policy), (Some(64), Some(2 << 20), Some(PageReplacement::Clock)));
+
+    let args: Vec<String> = ["program", "paging", "-t", "test_tracefile", "--frames", "4", "--addr-offset", "1000"].iter().map(|arg| arg.to_string()).collect();
+    assert_eq!(parse_args(&args).unwrap().page_size, None);
+
+    for args in [
+        vec!["program", "paging", "-t", "test_tracefile"],
+        vec!["program", "paging", "--frames", "4"],
+        vec!["program", "paging", "-t", "test_tracefile", "--frames", "0"],
+        vec!["program", "paging", "-t", "test_tracefile", "--frames", "4", "--page-size", "3000"],
+        vec!["program", "paging", "-t", "test_tracefile", "--frames", "4", "--page-policy", "plru"],
+        vec!["program", "paging", "-t", "test_tracefile", "--frames", "4", "-b", "6"],
+        vec!["program", "paging", "-t", "test_tracefile", "--frames", "4", "--policy", "fifo"],
+        vec!["program", "-s", "4", "-E", "2", "-b", "4", "-t", "test_tracefile", "--frames", "4"],
+        vec!["program", "working-set", "-t", "test_tracefile", "--window", "10", "--page-policy", "lru"],
+    ] {
+        let args: Vec<String> = args.iter().map(|arg| arg.to_string()).collect();
+        assert!(parse_args(&args).is_err());
+    }
 }
 
+// Tests for the serve subcommand
 #[test]
-fn test_read_tracefile_test_tracefile() {
-    assert!(read_tracefile("test_tracefile").is_err());
+fn test_parse_args_serve() {
+    let args: Vec<String> = ["program", "serve"].iter().map(|arg| arg.to_string()).collect();
+    assert_eq!(parse_args(&args), Ok(Args { service: true, ..Default::default() }));
+    let args: Vec<String> = ["program", "serve", "--listen", "0.0.0.0:9000"].iter().map(|arg| arg.to_string()).collect();
+    assert_eq!(parse_args(&args).unwrap().listen.as_deref(), Some("0.0.0.0:9000"));
+
+    for args in [
+        vec!["program", "serve", "-s", "4"],
+        vec!["program", "serve", "-t", "test_tracefile"],
+        vec!["program", "-s", "4", "-E", "2", "-b", "4", "-t", "test_tracefile", "--listen", "0.0.0.0:9000"],
+    ] {
+        let args: Vec<String> = args.iter().map(|arg| arg.to_string()).collect();
+        assert!(parse_args(&args).is_err());
+    }
 }
 
-// Tests for parse_memory_access function
+// Tests for the bench subcommand
 #[test]
-fn test_parse_memory_access_valid_input() {
-    let memory_access = "S 10,1";
-    let s = 4;
-    let b = 4;
-    assert_eq!(parse_memory_access(memory_access, s, b), Ok(Some(('S', 1, 0))));
+fn test_parse_args_bench() {
+    let args: Vec<String> = ["program", "bench", "-s", "4", "-E", "2", "-b", "4", "--length", "1000", "--policy", "plru"].iter().map(|arg| arg.to_string()).collect();
+    let params = parse_args(&args).unwrap();
+    assert_eq!(params, Args { bench: true, s: 4, e: 2, b: 4, length: Some(1000), replacement: Replacement::Plru, ..Default::default() });
+    assert_eq!(cache_builder(&params), CacheBuilder::new().set_index_bits(4).associativity(2).block_bits(4).replacement(Replacement::Plru));
+
+    for args in [
+        vec!["program", "bench", "-s", "4", "-E", "2"],
+        vec!["program", "bench", "-s", "4", "-E", "2", "-b", "4", "-t", "test_tracefile"],
+        vec!["program", "bench", "-s", "4", "-E", "2", "-b", "4", "--length", "0"],
+        vec!["program", "bench", "-s", "4", "-E", "2", "-b", "4", "--warmup", "10"],
+        vec!["program", "bench", "-s", "4", "-E", "2", "-b", "4", "--pattern", "random"],
+        vec!["program", "bench", "-s", "4", "-E", "2", "-b", "4", "--output", "csv"],
+    ] {
+        let args: Vec<String> = args.iter().map(|arg| arg.to_string()).collect();
+        assert!(parse_args(&args).is_err(), "{:?}", args);
+    }
 }
 
+// Tests for the repl subcommand
 #[test]
-fn test_parse_memory_access_extra_whitespace() {
-    let memory_accesses = vec!["S      10,1", "   S 10,1", "S 10,1    "];
-    for memory_access in memory_accesses {
-        let s = 4;
-        let b = 4;
-        assert_eq!(parse_memory_access(memory_access, s, b), Ok(Some(('S', 1, 0))));
+fn test_parse_args_repl() {
+    let args: Vec<String> = ["program", "repl", "-s", "4", "-E", "2", "-b", "4", "--skewed", "--classify-misses"].iter().map(|arg| arg.to_string()).collect();
+    let params = parse_args(&args).unwrap();
+    assert!(params.repl);
+    assert_eq!(cache_builder(&params), CacheBuilder::new().set_index_bits(4).associativity(2).block_bits(4).skewed(true).classify_misses(true));
+
+    for args in [
+        vec!["program", "repl", "-s", "4", "-E", "2"],
+        vec!["program", "repl", "-s", "4", "-E", "2", "-b", "4", "-t", "test_tracefile"],
+        vec!["program", "repl", "-s", "4", "-E", "2", "-b", "4", "--debug"],
+        vec!["program", "repl", "-s", "4", "-E", "2", "-b", "4", "--warmup", "10"],
+        vec!["program", "repl", "-s", "4", "-E", "2", "-b", "4", "--issue-width", "2"],
+    ] {
+        let args: Vec<String> = args.iter().map(|arg| arg.to_string()).collect();
+        assert!(parse_args(&args).is_err());
     }
 }
 
+// Tests for the completions subcommand
 #[test]
-fn test_parse_memory_access_instruction_access() {
-    let memory_access = "I 10,1";
-    let s = 4;
-    let b = 4;
-    assert_eq!(parse_memory_access(memory_access, s, b), Ok(None));
+fn test_parse_args_completions() {
+    let args: Vec<String> = ["program", "completions", "zsh"].iter().map(|arg| arg.to_string()).collect();
+    assert_eq!(parse_args(&args), Ok(Args { completions: Some(Shell::Zsh), ..Default::default() }));
+    for args in [vec!["program", "completions"], vec!["program", "completions", "tcsh"], vec!["program", "completions", "bash", "-s", "4"]] {
+        let args: Vec<String> = args.iter().map(|arg| arg.to_string()).collect();
+        assert!(parse_args(&args).is_err());
+    }
 }
 
+// Tests for command_line_options function
 #[test]
-fn test_parse_memory_access_invalid_operation() {
-    let memory_access = "X 10,1";
-    let s = 4;
-    let b = 4;
-    assert!(parse_memory_access(memory_access, s, b).is_err());
+fn test_command_line_options() {
+    let options = command_line_options();
+    assert_eq!(options.len(), 5 + LONG_OPTIONS.len());
+    assert!(options.contains(&("-E".to_string(), OptionValue::Any)));
+    assert!(options.contains(&("--skewed".to_string(), OptionValue::None)));
+    assert!(options.contains(&("--config".to_string(), OptionValue::File)));
+    // Every value offered is one the parser accepts
+    for (option, value) in OPTION_VALUES {
+        assert!(options.iter().any(|(name, _)| name == option), "{} is not an option", option);
+        let values = match value {
+            OptionValue::OneOf(values) => values,
+            _ => continue,
+        };
+        for value in values.iter() {
+            let valid = match *option {
+                "--policy" => value.parse::<Replacement>().is_ok(),
+                "--insertion" => value.parse::<Insertion>().is_ok(),
+                "--tie-break" => value.parse::<TieBreak>().is_ok(),
+                "--way-predict" => value.parse::<WayPredictor>().is_ok(),
+                "--oob-addr" => value.parse::<OobPolicy>().is_ok(),
+                "--format" => value.parse::<TraceFormat>().is_ok() || *value == "generic:",
+                "--output" => ["text", "csv"].contains(value),
+                "--log-level" => value.parse::<LogLevel>().is_ok(),
+                "--errors" => ["text", "json"].contains(value),
+                "--pattern" => value.parse::<Pattern>().is_ok(),
+                "--kernel" => value.parse::<Kernel>().is_ok(),
+                "--page-policy" => value.parse::<PageReplacement>().is_ok(),
+                "--interleave" => value.parse::<Interleave>().is_ok(),
+                _ => false,
+            };
+            assert!(valid, "invalid value {} for {}", value, option);
+        }
+    }
 }
 
+// Tests for with_env_defaults function
 #[test]
-fn test_parse_memory_access_invalid_format_no_whitespace() {
-    let memory_access = "S10,1";
-    let s = 4;
-    let b = 4;
-    assert!(parse_memory_access(memory_access, s, b).is_err());
+fn test_with_env_defaults() {
+    let vars: HashMap<&str, &str> = [("CACHESIM_S", "4"), ("CACHESIM_E", "2"), ("CACHESIM_B", "4"), ("CACHESIM_SEED", "7"), ("CACHESIM_LENIENT", "1")].into_iter().collect();
+    let var = |name: &str| vars.get(name).map(|value| value.to_string());
+    let parse = |args: &[&str]| {
+        let args: Vec<String> = args.iter().map(|arg| arg.to_string()).collect();
+        with_env_defaults(&args, var).and_then(|args| parse_args(&args))
+    };
+    assert_eq!(
+        parse(&["program", "-t", "test_tracefile"]),
+        Ok(Args { s: 4, e: 2, b: 4, t: "test_tracefile".to_string(), seed: Some(7), lenient: true, ..Default::default() })
+    );
+    // The command line takes precedence
+    let params = parse(&["program", "-s5", "-t", "test_tracefile", "--seed=9"]).unwrap();
+    assert_eq!((params.s, params.e, params.seed), (5, 2, Some(9)));
+    let params = parse(&["program", "--sweep", "4:2:4,5:1:5", "-t", "test_tracefile"]).unwrap();
+    assert_eq!((params.s, params.sweep.len()), (0, 2));
+    // Subcommands don't take defaults from the environment
+    let params = parse(&["program", "reuse", "-b", "6", "-t", "test_tracefile"]).unwrap();
+    assert_eq!((params.b, params.lenient), (6, false));
+
+    let var = |name: &str| (name == "CACHESIM_LENIENT").then(|| "yes".to_string());
+    let args: Vec<String> = ["program", "-t", "test_tracefile"].iter().map(|arg| arg.to_string()).collect();
+    assert!(with_env_defaults(&args, var).is_err());
+    let var = |name: &str| (name == "CACHESIM_S").then(|| "four".to_string());
+    assert!(parse_args(&with_env_defaults(&args, var).unwrap()).is_err());
 }
 
+// Tests for several -t tracefiles
 #[test]
-fn test_parse_memory_access_invalid_format_no_size() {
-    let memory_access = "S 10";
-    let s = 4;
-    let b = 4;
-    assert!(parse_memory_access(memory_access, s, b).is_err());
+fn test_parse_args_several_traces() {
+    let args: Vec<String> = ["program", "-s", "4", "-E", "2", "-b", "4", "-t", "first", "-t", "second", "-t", "third", "--keep-cache"].iter().map(|arg| arg.to_string()).collect();
+    let params = parse_args(&args).unwrap();
+    assert_eq!((params.t.as_str(), params.extra_traces, params.keep_cache), ("first", vec!["second".to_string(), "third".to_string()], true));
+    let args: Vec<String> = ["program", "-s", "4", "-E", "2", "-b", "4", "-t", "first", "-t", "-", "--output", "csv"].iter().map(|arg| arg.to_string()).collect();
+    assert!(parse_args(&args).is_ok());
+
+    for args in [
+        vec!["program", "-s", "4", "-E", "2", "-b", "4", "-t", "first", "--keep-cache"],
+        vec!["program", "-s", "4", "-E", "2", "-b", "4", "-t", "-", "-t", "-"],
+        vec!["program", "-s", "4", "-E", "2", "-b", "4", "-t", "first", "-t", "second", "--debug"],
+        vec!["program", "-s", "4", "-E", "2", "-b", "4", "-t", "first", "-t", "second", "--per-set"],
+        vec!["program", "-s", "4", "-E", "2", "-b", "4", "-t", "first", "-t", "second", "--checkpoint", "state.json"],
+        vec!["program", "--sweep", "4:2:4", "-t", "first", "-t", "second"],
+        vec!["program", "reuse", "-b", "4", "-t", "first", "-t", "second"],
+    ] {
+        let args: Vec<String> = args.iter().map(|arg| arg.to_string()).collect();
+        assert!(parse_args(&args).is_err());
+    }
 }
 
+// Tests for --config
 #[test]
-fn test_parse_memory_access_invalid_format_no_comma() {
-    let memory_access = "S 10:1";
-    let s = 4;
-    let b = 4;
-    assert!(parse_memory_access(memory_access, s, b).is_err());
+fn test_parse_args_config() {
+    let single = env::temp_dir().join(format!("sim-config-single-{}.toml", std::process::id()));
+    let levels = env::temp_dir().join(format!("sim-config-levels-{}.json", std::process::id()));
+    let dram = env::temp_dir().join(format!("sim-config-dram-{}.toml", std::process::id()));
+    std::fs::write(&single, "[[level]]\ns = 4\nE = 2\nb = 4\nskewed = true\n").unwrap();
+    std::fs::write(&levels, r#"{"level": [{"s": 1, "E": 1, "b": 4}, {"s": 4, "E": 2, "b": 4, "latency": 10}]}"#).unwrap();
+    let cores = env::temp_dir().join(format!("sim-config-cores-{}.toml", std::process::id()));
+    let snoop = env::temp_dir().join(format!("sim-config-snoop-{}.toml", std::process::id()));
+    std::fs::write(&dram, "[[level]]\ns = 4\nE = 2\nb = 4\n[dram]\nbanks = 8\nrow_size = 8192\n").unwrap();
+    std::fs::write(&cores, "cores = 2\n[[level]]\nsize = \"1K\"\nE = 2\nb = 4\n[[level]]\nsize = 16384\nE = 4\nb = 4\n").unwrap();
+    std::fs::write(&snoop, "snoop_filter = true\n[[level]]\ns = 1\nE = 1\nb = 4\n[[level]]\ns = 4\nE = 2\nb = 4\n").unwrap();
+    let (single, levels, dram) = (single.to_str().unwrap(), levels.to_str().unwrap(), dram.to_str().unwrap());
+    let (cores, snoop) = (cores.to_str().unwrap(), snoop.to_str().unwrap());
+
+    // A single level replaces -s, -E, -b and the cache options
+    let args: Vec<String> = ["program", "--config", single, "-t", "test_tracefile", "--classify-misses"].iter().map(|arg| arg.to_string()).collect();
+    let params = parse_args(&args).unwrap();
+    assert_eq!(cache_builder(&params), CacheBuilder::new().set_index_bits(4).associativity(2).block_bits(4).skewed(true).classify_misses(true));
+    let args: Vec<String> = ["program", "repl", "--config", single].iter().map(|arg| arg.to_string()).collect();
+    assert!(parse_args(&args).unwrap().repl);
+
+    let args: Vec<String> = ["program", "--config", levels, "-t", "test_tracefile", "--warmup", "10", "--lenient"].iter().map(|arg| arg.to_string()).collect();
+    let params = parse_args(&args).unwrap();
+    assert_eq!(params.config.map(|config| config.levels.len()), Some(2));
+    // A single level with a DRAM behind it runs as a hierarchy
+    let args: Vec<String> = ["program", "--config", dram, "-t", "test_tracefile", "--warmup", "10"].iter().map(|arg| arg.to_string()).collect();
+    assert!(parse_args(&args).unwrap().config.is_some_and(|config| !config.is_single_cache()));
+    // Several tracefiles run one per core through a private first level
+    let args: Vec<String> = ["program", "--config", levels, "-t", "first", "-t", "second", "--interleave", "timestamp"].iter().map(|arg| arg.to_string()).collect();
+    let params = parse_args(&args).unwrap();
+    assert_eq!((params.interleave, params.extra_traces.len()), (Some(Interleave::Timestamp), 1));
+    // A configuration with cores spreads the threads of one tracefile over them, or takes one tracefile per core
+    let args: Vec<String> = ["program", "--config", cores, "-t", "test_tracefile"].iter().map(|arg| arg.to_string()).collect();
+    assert_eq!(parse_args(&args).unwrap().config.and_then(|config| config.cores), Some(2));
+    let args: Vec<String> = ["program", "--config", cores, "-t", "first", "-t", "second", "--interleave", "round-robin"].iter().map(|arg| arg.to_string()).collect();
+    assert!(parse_args(&args).is_ok());
+    let args: Vec<String> = ["program", "--config", snoop, "-t", "first", "-t", "second", "--interleave", "round-robin"].iter().map(|arg| arg.to_string()).collect();
+    assert!(parse_args(&args).unwrap().config.is_some_and(|config| config.snoop_filter));
+
+    for args in [
+        vec!["program", "--config", single, "-s", "4", "-t", "test_tracefile"],
+        vec!["program", "--config", single, "--seed", "1", "-t", "test_tracefile"],
+        vec!["program", "--config", single, "--sweep", "4:2:4", "-t", "test_tracefile"],
+        vec!["program", "--config", single, "--restore", "state.json", "-t", "test_tracefile"],
+        vec!["program", "--config", single],
+        vec!["program", "--config", levels, "-t", "test_tracefile", "--debug"],
+        vec!["program", "--config", levels, "-t", "test_tracefile", "--output", "csv"],
+        vec!["program", "--config", levels],
+        vec!["program", "repl", "--config", levels],
+        vec!["program", "--config", dram, "-t", "test_tracefile", "--classify-misses"],
+        vec!["program", "bench", "--config", dram],
+        vec!["program", "--config", "missing.toml", "-t", "test_tracefile"],
+        vec!["program", "--config", levels, "-t", "first", "-t", "second"],
+        vec!["program", "--config", levels, "-t", "-", "-t", "-", "--interleave", "round-robin"],
+        vec!["program", "--config", levels, "-t", "first", "--interleave", "random"],
+        vec!["program", "--config", cores, "-t", "first", "--interleave", "round-robin"],
+        vec!["program", "--config", cores, "-t", "first", "-t", "second"],
+        vec!["program", "--config", snoop, "-t", "first"],
+        vec!["program", "--config", single, "-t", "first", "-t", "second", "--interleave", "round-robin"],
+        vec!["program", "-s", "4", "-E", "2", "-b", "4", "-t", "first", "--interleave", "round-robin"],
+    ] {
+        let args: Vec<String> = args.iter().map(|arg| arg.to_string()).collect();
+        assert!(parse_args(&args).is_err());
+    }
+    std::fs::remove_file(single).unwrap();
+    std::fs::remove_file(levels).unwrap();
+    std::fs::remove_file(dram).unwrap();
+    std::fs::remove_file(cores).unwrap();
+    std::fs::remove_file(snoop).unwrap();
 }
 
+// Tests for simulate_trace and format_mpki functions
 #[test]
-fn test_parse_memory_access_invalid_address_value() {
-    let memory_access = "S xyz,1";
-    let s = 4;
-    let b = 4;
-    assert!(parse_memory_access(memory_access, s, b).is_err());
+fn test_simulate_trace_instructions() {
+    let mut cache = Cache::new(4, 1, 4).unwrap();
+    let mut accesses = TraceAccesses::open("../traces/trans.trace").unwrap();
+    assert_eq!(simulate_trace(&mut cache, &mut accesses, 0, None, None), Ok(378));
+    assert_eq!(format_mpki(cache.misses(), 378), "instructions:378 mpki:71.43");
+
+    // Instructions read during the warm-up aren't counted
+    let mut cache = Cache::new(4, 1, 4).unwrap();
+    let mut accesses = TraceAccesses::open("../traces/trans.trace").unwrap();
+    let instructions = simulate_trace(&mut cache, &mut accesses, 100, None, None).unwrap();
+    assert!(instructions > 0 && instructions < 378);
+    let mut reference = Cache::new(4, 1, 4).unwrap();
+    simulate_accesses(&mut reference, TraceAccesses::open("../traces/trans.trace").unwrap(), 100).unwrap();
+    assert_eq!(cache.format_stats(), reference.format_stats());
+
+    let mut accesses = TraceAccesses::open("../traces/yi.trace").unwrap();
+    assert_eq!(simulate_trace(&mut cache, &mut accesses, 0, None, None), Ok(0));
+
+    // The dashboard shows the statistics after the warm-up
+    let dashboard = Dashboard::serve("127.0.0.1:0").unwrap();
+    let mut cache = Cache::new(4, 1, 4).unwrap();
+    let mut accesses = TraceAccesses::open("../traces/trans.trace").unwrap();
+    assert_eq!(simulate_trace(&mut cache, &mut accesses, 100, Some(&dashboard), None), Ok(instructions));
+    assert_eq!(cache.format_stats(), reference.format_stats());
+    let progress = dashboard.progress();
+    assert!(progress.done);
+    assert_eq!((progress.accesses, progress.stats), (118, cache.snapshot_stats()));
 }
 
-// Test cache initilisation
+// Tests for format_totals function
 #[test]
-fn test_cache_new_valid_parameters() {
-    let s = 6;
-    let e = 2;
-    let b = 4;
+fn test_format_totals() {
+    let mut total = Stats::default();
+    assert_eq!(format_totals(&total), "hits:0 misses:0 evictions:0 miss-rate:0.00%");
+    for trace in ["../traces/yi.trace", "../traces/trans.trace"] {
+        let mut cache = Cache::new(4, 1, 4).unwrap();
+        simulate_accesses(&mut cache, TraceAccesses::open(trace).unwrap(), 0).unwrap();
+        total.add(&cache.snapshot_stats());
+    }
+    assert_eq!(format_totals(&total), "hits:215 misses:32 evictions:21 miss-rate:12.96%");
+}
 
-    match Cache::new(s, e, b) {
-        Ok(cache) => {
-            assert_eq!(cache.sets.len(), 64); 
-            for set in &cache.sets {
-                assert_eq!(set.lines.len(), e);
+#[test]
+fn test_parse_args_stdin_tracefile() {
+    let args: Vec<String> = ["program", "-s", "4", "-E", "2", "-b", "4", "-t", "-"].iter().map(|arg| arg.to_string()).collect();
+    assert_eq!(parse_args(&args).unwrap().t, "-");
+    let args: Vec<String> = ["program", "-s", "4", "-E", "2", "-b", "4", "-t", "-", "--debug"].iter().map(|arg| arg.to_string()).collect();
+    assert!(parse_args(&args).is_err());
+    let args: Vec<String> = ["program", "-s", "4", "-E", "2", "-b", "4", "-t", "-", "--tui"].iter().map(|arg| arg.to_string()).collect();
+    assert!(parse_args(&args).is_err());
+}
 
-                for line in &set.lines {
-                    assert!(!line.is_valid);
-                    assert_eq!(line.tag, None); 
-                }
+#[test]
+fn test_parse_args_format() {
+    let args: Vec<String> = ["program", "-s", "4", "-E", "2", "-b", "4", "-t", "test_tracefile"].iter().map(|arg| arg.to_string()).collect();
+    assert_eq!(parse_args(&args).unwrap().format, TraceFormat::Lackey);
+    let args: Vec<String> = ["program", "-s", "4", "-E", "2", "-b", "4", "-t", "test_tracefile", "--format", "din"].iter().map(|arg| arg.to_string()).collect();
+    assert_eq!(parse_args(&args).unwrap().format, TraceFormat::Dinero);
+    let args: Vec<String> = ["program", "-s", "4", "-E", "2", "-b", "4", "-t", "test_tracefile", "--format", "pin"].iter().map(|arg| arg.to_string()).collect();
+    assert_eq!(parse_args(&args), Err(SimError::ParseArgs("unknown trace format: pin".to_string())));
+    let args: Vec<String> = ["program", "-s", "4", "-E", "2", "-b", "4", "-t", "test_tracefile", "--format", "generic:pc,op,addr:csv"].iter().map(|arg| arg.to_string()).collect();
+    assert_eq!(parse_args(&args).unwrap().format, "generic:pc,op,addr:csv".parse().unwrap());
+}
 
-                assert_eq!(set.access_order.len(), 0); 
-            }
-        }
-        Err(err) => panic!("Error testing cache: {}", err),
+#[test]
+fn test_parse_args_csv() {
+    let args: Vec<String> = ["program", "-s", "4", "-E", "2", "-b", "4", "-t", "test_tracefile", "--output", "csv", "--append", "results.csv"].iter().map(|arg| arg.to_string()).collect();
+    let params = parse_args(&args).unwrap();
+    assert!(params.csv);
+    assert_eq!(params.append, Some("results.csv".to_string()));
+    let args: Vec<String> = ["program", "--sweep", "4:2:4,5:1:5", "-t", "test_tracefile", "--output", "csv"].iter().map(|arg| arg.to_string()).collect();
+    assert!(parse_args(&args).unwrap().csv);
+    let args: Vec<String> = ["program", "-s", "4", "-E", "2", "-b", "4", "-t", "test_tracefile", "--output", "text"].iter().map(|arg| arg.to_string()).collect();
+    assert!(!parse_args(&args).unwrap().csv);
+
+    let invalid_csvs = vec![
+        vec!["--output", "json"],
+        vec!["--append", "results.csv"],
+        vec!["--output", "text", "--append", "results.csv"],
+        vec!["--output", "csv", "--debug"],
+        vec!["--output", "csv", "--sample-period", "10", "--sample-length", "5"],
+    ];
+    for invalid_csv in invalid_csvs {
+        let args: Vec<String> = ["program", "-s", "4", "-E", "2", "-b", "4", "-t", "test_tracefile"].iter().chain(invalid_csv.iter()).map(|arg| arg.to_string()).collect();
+        assert!(parse_args(&args).is_err());
+    }
+}
+
+#[test]
+fn test_parse_args_per_set() {
+    let args: Vec<String> = ["program", "-s", "4", "-E", "2", "-b", "4", "-t", "test_tracefile", "--per-set"].iter().map(|arg| arg.to_string()).collect();
+    assert!(parse_args(&args).unwrap().per_set);
+    let args: Vec<String> = ["program", "-s", "4", "-E", "2", "-b", "4", "-t", "test_tracefile", "--per-set", "--output", "csv"].iter().map(|arg| arg.to_string()).collect();
+    assert!(parse_args(&args).is_err());
+    let args: Vec<String> = ["program", "--sweep", "4:2:4", "-t", "test_tracefile", "--per-set"].iter().map(|arg| arg.to_string()).collect();
+    assert!(parse_args(&args).is_err());
+
+    let args: Vec<String> = ["program", "-s", "4", "-E", "2", "-b", "4", "-t", "test_tracefile", "--heatmap", "sets.svg", "--output", "csv"].iter().map(|arg| arg.to_string()).collect();
+    assert_eq!(parse_args(&args).unwrap().heatmap, Some("sets.svg".to_string()));
+    let args: Vec<String> = ["program", "--sweep", "4:2:4", "-t", "test_tracefile", "--heatmap", "sets.svg"].iter().map(|arg| arg.to_string()).collect();
+    assert!(parse_args(&args).is_err());
+}
+
+// Tests for append_csv function
+#[test]
+fn test_append_csv() {
+    let path = env::temp_dir().join(format!("sim-results-{}.csv", std::process::id()));
+    let path = path.to_str().unwrap();
+    append_csv(path, &["a".to_string()]).unwrap();
+    append_csv(path, &["b".to_string(), "c".to_string()]).unwrap();
+    assert_eq!(std::fs::read_to_string(path).unwrap(), format!("{}\na\nb\nc\n", CSV_HEADER));
+    std::fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn test_parse_args_lenient() {
+    let args: Vec<String> = ["program", "-s", "4", "-E", "2", "-b", "4", "-t", "test_tracefile", "--lenient"].iter().map(|arg| arg.to_string()).collect();
+    assert!(parse_args(&args).unwrap().lenient);
+    let args: Vec<String> = ["program", "-s", "4", "-E", "2", "-b", "4", "-t", "test_tracefile", "--lenient", "--lenient"].iter().map(|arg| arg.to_string()).collect();
+    assert!(parse_args(&args).is_err());
+}
+
+#[test]
+fn test_parse_args_icache() {
+    let args: Vec<String> = ["program", "-s", "4", "-E", "1", "-b", "4", "-t", "../traces/trans.trace", "--icache"].iter().map(|arg| arg.to_string()).collect();
+    let params = parse_args(&args).unwrap();
+    assert!(params.icache);
+    let mut cache = cache_builder(&params).build().unwrap();
+    assert_eq!(simulate_trace(&mut cache, &mut open_trace(&params).unwrap(), 0, None, None), Ok(378));
+    assert_eq!(cache.format_stats(), "hits:368 misses:10 evictions:0");
+
+    for args in [
+        vec!["program", "demo", "locality", "-s", "4", "-E", "2", "-b", "4", "--icache"],
+        vec!["program", "repl", "-s", "4", "-E", "2", "-b", "4", "--icache"],
+        vec!["program", "reuse", "-t", "test_tracefile", "-b", "4", "--icache"],
+        vec!["program", "-s", "4", "-E", "2", "-b", "4", "-t", "test_tracefile", "--compare-ref", "./csim-ref", "--icache"],
+    ] {
+        let args: Vec<String> = args.iter().map(|arg| arg.to_string()).collect();
+        assert!(parse_args(&args).is_err(), "{:?}", args);
     }
 }
 
 #[test]
-fn test_cache_new_invalid_size() {
-    let s = 1000;
-    let e = 16;
-    let b = 64;
-    assert!(Cache::new(s, e, b).is_err());
+fn test_parse_args_uncacheable() {
+    let args: Vec<String> = ["program", "-s", "4", "-E", "1", "-b", "4", "-t", "../traces/yi.trace", "--uncacheable", "0x10-0x1f,0x200-0x2ff"]
+        .iter().map(|arg| arg.to_string()).collect();
+    let params = parse_args(&args).unwrap();
+    assert_eq!(params.uncacheable, vec![0x10..=0x1f, 0x200..=0x2ff]);
+    let mut cache = cache_builder(&params).build().unwrap();
+    simulate_trace(&mut cache, &mut open_trace(&params).unwrap(), 0, None, None).unwrap();
+    assert_eq!(cache.format_stats(), "hits:2 misses:2 evictions:0\nuncached-accesses:4");
 }
 
-// Test for simulate_memory_access function
 #[test]
-fn test_simulate_memory_access_cache_hits() {
-    let mut cache = Cache::new(6, 2, 4).unwrap();
+fn test_parse_args_lock_ways() {
+    let args: Vec<String> = ["program", "-s", "1", "-E", "2", "-b", "4", "-t", "../traces/yi.trace", "--lock-ways", "0@1"]
+        .iter().map(|arg| arg.to_string()).collect();
+    let params = parse_args(&args).unwrap();
+    assert_eq!(params.locked_ways, vec![WayLock { ways: 0..=0, sets: Some(1..=1) }]);
+    let mut cache = cache_builder(&params).build().unwrap();
+    simulate_trace(&mut cache, &mut open_trace(&params).unwrap(), 0, None, None).unwrap();
+    assert_eq!(cache.pinned_lines(), 1);
+}
 
-    cache.sets[0].lines[0].is_valid = true;
-    cache.sets[0].lines[0].tag = Some(100);
-    cache.sets[0].access_order.push_back(0);
+#[test]
+fn test_parse_args_scratchpad() {
+    let args: Vec<String> = ["program", "-s", "4", "-E", "2", "-b", "4", "-t", "../traces/yi.trace", "--scratchpad", "0x10-0x10f"]
+        .iter().map(|arg| arg.to_string()).collect();
+    let params = parse_args(&args).unwrap();
+    assert_eq!(params.scratchpad, Some(0x10..=0x10f));
+    let mut cache = cache_builder(&params).build().unwrap();
+    assert_eq!(cache.associativity(), 1);
+    simulate_trace(&mut cache, &mut open_trace(&params).unwrap(), 0, None, None).unwrap();
+    assert_eq!((cache.hits() + cache.misses(), cache.scratchpad_accesses()), (2, 7));
+}
 
-    assert_eq!(cache.simulate_memory_access('L', 0, 100), Ok(()));
-    assert_eq!(cache.hits, 1);
-    assert_eq!(cache.misses, 0);
-    assert_eq!(cache.evictions, 0);
+#[test]
+fn test_parse_args_remap() {
+    // The same accesses captured at another base address simulate alike once the offset is taken back off
+    let path = std::env::temp_dir().join(format!("sim-remap-{}.trace", std::process::id()));
+    let shifted: String = std::fs::read_to_string("../traces/yi.trace").unwrap().lines()
+        .map(|line| {
+            let access = sim::parse_memory_access(line).unwrap().unwrap();
+            format!(" {} {:x},{}\n", access.operation, access.address + 0x7ff0_0000_000e, access.size)
+        })
+        .collect();
+    std::fs::write(&path, shifted).unwrap();
+    let run = |extra: &[&str]| {
+        let mut args: Vec<String> = ["program", "-s", "4", "-E", "1", "-b", "4", "-t", path.to_str().unwrap()].iter().map(|arg| arg.to_string()).collect();
+        args.extend(extra.iter().map(|arg| arg.to_string()));
+        let params = parse_args(&args).unwrap();
+        let mut cache = cache_builder(&params).build().unwrap();
+        simulate_trace(&mut cache, &mut open_trace(&params).unwrap(), 0, None, None).unwrap();
+        cache.format_stats()
+    };
+    let mut cache = CacheBuilder::new().set_index_bits(4).associativity(1).block_bits(4).build().unwrap();
+    simulate_accesses(&mut cache, TraceAccesses::open("../traces/yi.trace").unwrap(), 0).unwrap();
+    assert_ne!(run(&[]), cache.format_stats());
+    assert_eq!(run(&["--addr-offset", "-7ff00000000e"]), cache.format_stats());
+    assert_eq!(run(&["--remap", "7ff000000000-7ff0ffffffff=0", "--addr-offset", "1"]), run(&["--addr-offset=-0x7ff000000000"]));
+    std::fs::remove_file(&path).unwrap();
+
+    for args in [
+        vec!["program", "convert", "-t", "test_tracefile", "-o", "out.bin", "--addr-offset", "100"],
+        vec!["program", "reuse", "-t", "test_tracefile", "-b", "4", "--remap", "0-ff=1000"],
+        vec!["program", "working-set", "-t", "test_tracefile", "--window", "10", "--addr-offset", "-100"],
+    ] {
+        let args: Vec<String> = args.iter().map(|arg| arg.to_string()).collect();
+        assert!(parse_args(&args).is_ok(), "{:?}", args);
+    }
+    for args in [
+        vec!["program", "-s", "4", "-E", "2", "-b", "4", "-t", "test_tracefile", "--addr-offset", "0xg"],
+        vec!["program", "-s", "4", "-E", "2", "-b", "4", "-t", "test_tracefile", "--remap", "0-ff=1000,80-17f=2000"],
+        vec!["program", "demo", "locality", "-s", "4", "-E", "2", "-b", "4", "--addr-offset", "100"],
+        vec!["program", "repl", "-s", "4", "-E", "2", "-b", "4", "--remap", "0-ff=1000"],
+        vec!["program", "-s", "4", "-E", "2", "-b", "4", "-t", "test_tracefile", "--compare-ref", "./csim-ref", "--addr-offset", "100"],
+    ] {
+        let args: Vec<String> = args.iter().map(|arg| arg.to_string()).collect();
+        assert!(parse_args(&args).is_err(), "{:?}", args);
+    }
+}
 
-    assert_eq!(cache.simulate_memory_access('S', 0, 100), Ok(()));
-    assert_eq!(cache.hits, 2);
-    assert_eq!(cache.misses, 0);
-    assert_eq!(cache.evictions, 0);
+#[test]
+fn test_parse_args_warmup() {
+    let args: Vec<String> = ["program", "-s", "4", "-E", "2", "-b", "4", "-t", "test_tracefile", "--warmup", "1000"].iter().map(|arg| arg.to_string()).collect();
+    assert_eq!(parse_args(&args).unwrap().warmup, Some(1000));
+}
 
-    assert_eq!(cache.simulate_memory_access('M', 0, 100), Ok(()));
-    assert_eq!(cache.hits, 4);
-    assert_eq!(cache.misses, 0);
-    assert_eq!(cache.evictions, 0);
+#[test]
+fn test_parse_args_sampling() {
+    let args: Vec<String> = ["program", "-s", "4", "-E", "2", "-b", "4", "-t", "test_tracefile", "--sample-period", "1000", "--sample-length", "100"].iter().map(|arg| arg.to_string()).collect();
+    let params = parse_args(&args).unwrap();
+    assert_eq!(sampling(&params), Ok(Some(Sampling { period: 1000, length: 100 })));
 }
 
 #[test]
-fn test_simulate_memory_access_cache_misses() {
-    let mut cache = Cache::new(6, 4, 4).unwrap();
+fn test_parse_args_sweep() {
+    let args: Vec<String> = ["program", "--sweep", "4:2:4,5:1:5", "-t", "test_tracefile", "--tie-break", "random"].iter().map(|arg| arg.to_string()).collect();
+    let params = parse_args(&args).unwrap();
+    assert_eq!(params.sweep, vec![(4, 2, 4), (5, 1, 5)]);
+    assert_eq!(sweep_builders(&params), vec![
+        CacheBuilder::new().set_index_bits(4).associativity(2).block_bits(4).tie_break(TieBreak::Random),
+        CacheBuilder::new().set_index_bits(5).associativity(1).block_bits(5).tie_break(TieBreak::Random),
+    ]);
+
+    let invalid_sweeps = vec![
+        vec!["--sweep", "4:2"],
+        vec!["--sweep", "4:2:x"],
+        vec!["--sweep", "4:2:4,"],
+        vec!["--sweep", "4:2:4", "-s", "4"],
+        vec!["--sweep", "4:2:4", "--debug"],
+        vec!["--sweep", "4:0:4"],
+        vec!["--sweep", "4:2:4,4:1:4", "--partition", "0:1"],
+    ];
+    for invalid_sweep in invalid_sweeps {
+        let mut args = vec!["program".to_string(), "-t".to_string(), "test_tracefile".to_string()];
+        args.extend(invalid_sweep.iter().map(|arg| arg.to_string()));
+        assert!(parse_args(&args).is_err());
+    }
+}
 
-    assert_eq!(cache.simulate_memory_access('L', 0, 100), Ok(()));
-    assert_eq!(cache.hits, 0);
-    assert_eq!(cache.misses, 1);
-    assert_eq!(cache.evictions, 0);
+// Tests for parse_sweep function
+#[test]
+fn test_parse_sweep() {
+    assert_eq!(parse_sweep("1:1:1"), Ok(vec![(1, 1, 1)]));
+    assert_eq!(parse_sweep("4:2:4,0:8:6"), Ok(vec![(4, 2, 4), (0, 8, 6)]));
+    assert!(parse_sweep("").is_err());
+    assert!(parse_sweep("4:2:4:1").is_err());
+}
 
-    assert_eq!(cache.simulate_memory_access('S', 0, 200), Ok(()));
-    assert_eq!(cache.hits, 0);
-    assert_eq!(cache.misses, 2);
-    assert_eq!(cache.evictions, 0);
+#[test]
+fn test_parse_args_checkpoint_and_restore() {
+    let args: Vec<String> = ["program", "-s", "4", "-E", "2", "-b", "4", "-t", "test_tracefile", "--restore", "in.json", "--checkpoint", "out.json"].iter().map(|arg| arg.to_string()).collect();
+    let params = parse_args(&args).unwrap();
+    assert_eq!((params.restore.as_deref(), params.checkpoint.as_deref()), (Some("in.json"), Some("out.json")));
+}
 
-    assert_eq!(cache.simulate_memory_access('M', 0, 300), Ok(()));
-    assert_eq!(cache.hits, 1);
-    assert_eq!(cache.misses, 3);
-    assert_eq!(cache.evictions, 0);
+// Tests for restore_cache function
+#[test]
+fn test_restore_cache() {
+    let path = env::temp_dir().join(format!("sim-restore-{}.json", std::process::id()));
+    let path = path.to_str().unwrap();
+    CacheBuilder::new().set_index_bits(4).associativity(2).block_bits(4).build().unwrap().save_checkpoint(path).unwrap();
+
+    let args: Vec<String> = ["program", "-s", "4", "-E", "2", "-b", "4", "-t", "test_tracefile", "--restore", path].iter().map(|arg| arg.to_string()).collect();
+    assert!(restore_cache(path, &parse_args(&args).unwrap()).is_ok());
+    let args: Vec<String> = ["program", "-s", "4", "-E", "1", "-b", "4", "-t", "test_tracefile", "--restore", path].iter().map(|arg| arg.to_string()).collect();
+    assert!(matches!(restore_cache(path, &parse_args(&args).unwrap()), Err(SimError::Config(_))));
+    std::fs::remove_file(path).unwrap();
 }
 
+// Tests for cache_builder function
 #[test]
-fn test_simulate_memory_access_cache_evictions() {
-    let mut cache = Cache::new(6, 1, 4).unwrap();
+fn test_cache_builder() {
+    let args: Vec<String> = ["program", "-s", "4", "-E", "2", "-b", "4", "-t", "test_tracefile", "--banks", "4"].iter().map(|arg| arg.to_string()).collect();
+    let params = parse_args(&args).unwrap();
+    assert_eq!(cache_builder(&params), CacheBuilder::new().set_index_bits(4).associativity(2).block_bits(4).banks(4, 1));
+
+    let args: Vec<String> = ["program", "-s", "4", "-E", "2", "-b", "4", "-t", "test_tracefile", "--write-buffer", "8"].iter().map(|arg| arg.to_string()).collect();
+    let params = parse_args(&args).unwrap();
+    assert_eq!(cache_builder(&params), CacheBuilder::new().set_index_bits(4).associativity(2).block_bits(4).write_buffer(8, DEFAULT_DRAIN_INTERVAL));
+    let args: Vec<String> = ["program", "-s", "4", "-E", "2", "-b", "4", "-t", "test_tracefile", "--write-buffer", "8", "--drain-interval", "3"].iter().map(|arg| arg.to_string()).collect();
+    let params = parse_args(&args).unwrap();
+    assert_eq!(cache_builder(&params), CacheBuilder::new().set_index_bits(4).associativity(2).block_bits(4).write_buffer(8, 3));
+    let args: Vec<String> = ["program", "-s", "4", "-E", "2", "-b", "4", "-t", "test_tracefile", "--store-buffer", "4"].iter().map(|arg| arg.to_string()).collect();
+    let params = parse_args(&args).unwrap();
+    assert_eq!(cache_builder(&params), CacheBuilder::new().set_index_bits(4).associativity(2).block_bits(4).store_buffer(4));
+    let args: Vec<String> = ["program", "-s", "4", "-E", "2", "-b", "4", "-t", "test_tracefile", "--bypass", "4K"].iter().map(|arg| arg.to_string()).collect();
+    let params = parse_args(&args).unwrap();
+    assert_eq!(cache_builder(&params), CacheBuilder::new().set_index_bits(4).associativity(2).block_bits(4).bypass(4096));
+    let args: Vec<String> = ["program", "-s", "4", "-E", "2", "-b", "4", "-t", "test_tracefile", "--tinylfu"].iter().map(|arg| arg.to_string()).collect();
+    let params = parse_args(&args).unwrap();
+    assert_eq!(cache_builder(&params), CacheBuilder::new().set_index_bits(4).associativity(2).block_bits(4).tinylfu(true));
+
+    let args: Vec<String> = ["program", "-s", "4", "-E", "2", "-b", "4", "-t", "test_tracefile", "--debug"].iter().map(|arg| arg.to_string()).collect();
+    let params = parse_args(&args).unwrap();
+    assert_eq!(cache_builder(&params), CacheBuilder::new().set_index_bits(4).associativity(2).block_bits(4).undo_depth(DEFAULT_UNDO_DEPTH));
+    let args: Vec<String> = ["program", "-s", "4", "-E", "2", "-b", "4", "-t", "test_tracefile", "--tui", "--undo-depth", "5"].iter().map(|arg| arg.to_string()).collect();
+    let params = parse_args(&args).unwrap();
+    assert!(params.debug && params.tui);
+    assert_eq!(cache_builder(&params), CacheBuilder::new().set_index_bits(4).associativity(2).block_bits(4).undo_depth(5));
+
+    let args: Vec<String> = ["program", "-s", "4", "-E", "2", "-b", "4", "-t", "test_tracefile", "--classify-misses"].iter().map(|arg| arg.to_string()).collect();
+    let params = parse_args(&args).unwrap();
+    assert_eq!(cache_builder(&params), CacheBuilder::new().set_index_bits(4).associativity(2).block_bits(4).classify_misses(true));
+
+    let args: Vec<String> = ["program", "-s", "4", "-E", "2", "-b", "4", "-t", "test_tracefile", "--top", "10"].iter().map(|arg| arg.to_string()).collect();
+    let params = parse_args(&args).unwrap();
+    assert_eq!(cache_builder(&params), CacheBuilder::new().set_index_bits(4).associativity(2).block_bits(4).track_hot_blocks(HOT_BLOCK_CAPACITY));
+    let args: Vec<String> = ["program", "-s", "4", "-E", "2", "-b", "4", "-t", "test_tracefile", "--top", "0"].iter().map(|arg| arg.to_string()).collect();
+    assert!(parse_args(&args).is_err());
 
-    cache.sets[0].lines[0].is_valid = true;
-    cache.sets[0].lines[0].tag = Some(100);
-    cache.sets[0].access_order.push_back(0);
+    let args: Vec<String> = ["program", "-s", "4", "-E", "2", "-b", "4", "-t", "test_tracefile", "--lifetimes"].iter().map(|arg| arg.to_string()).collect();
+    let params = parse_args(&args).unwrap();
+    assert_eq!(cache_builder(&params), CacheBuilder::new().set_index_bits(4).associativity(2).block_bits(4).track_lifetimes(true));
+}
 
-    assert_eq!(cache.simulate_memory_access('L', 0, 200), Ok(()));
-    assert_eq!(cache.hits, 0);
-    assert_eq!(cache.misses, 1);
-    assert_eq!(cache.evictions, 1);
+// Tests for parse_way_partitions function
+#[test]
+fn test_parse_way_partitions_valid() {
+    let partitions = parse_way_partitions("0:0-1,1:2-3,7:4").unwrap();
+    assert_eq!(partitions.len(), 3);
+    assert_eq!(partitions[&0], vec![0, 1]);
+    assert_eq!(partitions[&1], vec![2, 3]);
+    assert_eq!(partitions[&7], vec![4]);
+}
 
-    assert_eq!(cache.simulate_memory_access('S', 0, 300), Ok(()));
-    assert_eq!(cache.hits, 0);
-    assert_eq!(cache.misses, 2);
-    assert_eq!(cache.evictions, 2);
+#[test]
+fn test_parse_way_partitions_invalid() {
+    let invalid_values = vec!["", "0", "0:", "x:1", "0:1-x", "0:3-1", "0:0,0:1", "0:0;1:1"];
+    for invalid_value in invalid_values {
+        assert!(parse_way_partitions(invalid_value).is_err());
+    }
+}
 
-    assert_eq!(cache.simulate_memory_access('M', 0, 400), Ok(()));
-    assert_eq!(cache.hits, 1);
-    assert_eq!(cache.misses, 3);
-    assert_eq!(cache.evictions, 3);
+// Tests for --log-level option
+#[test]
+fn test_parse_args_log_level() {
+    let args: Vec<String> = ["program", "-s", "4", "-E", "2", "-b", "4", "-t", "test_tracefile", "--log-level", "debug"].iter().map(|arg| arg.to_string()).collect();
+    assert_eq!(
+        parse_args(&args),
+        Ok(Args { s: 4, e: 2, b: 4, t: "test_tracefile".to_string(), log_level: Some(LogLevel::Debug), ..Default::default() })
+    );
+    // Subcommands take it too
+    let args: Vec<String> = ["program", "convert", "-t", "in.trace", "-o", "out.bin", "--log-level", "trace"].iter().map(|arg| arg.to_string()).collect();
+    assert_eq!(parse_args(&args).unwrap().log_level, Some(LogLevel::Trace));
+    let args: Vec<String> = ["program", "-s", "4", "-E", "2", "-b", "4", "-t", "test_tracefile", "--log-level", "verbose"].iter().map(|arg| arg.to_string()).collect();
+    assert!(parse_args(&args).is_err());
 }
 
+// Tests for error reporting and exit codes
 #[test]
-fn test_simulate_memory_access_unknown_operation() {
-    let mut cache = Cache::new(6, 1, 4).unwrap();
+fn test_exit_code() {
+    assert_eq!(exit_code(&SimError::ParseArgs("missing -t".to_string())), EXIT_USAGE);
+    assert_eq!(exit_code(&SimError::TraceRead("No such file or directory".to_string())), EXIT_TRACE_READ);
+    assert_eq!(exit_code(&SimError::Trace("line 1: invalid memory access format".to_string())), EXIT_TRACE_PARSE);
+    assert_eq!(exit_code(&SimError::Config("block size too large".to_string())), EXIT_FAILURE);
+    let err = TraceAccesses::open("test_tracefile").err().unwrap();
+    assert_eq!(exit_code(&err), EXIT_TRACE_READ);
+
+    // Set index and block bits exceeding the address width are a command-line error
+    let args = |args: &[&str]| args.iter().map(|arg| arg.to_string()).collect::<Vec<String>>();
+    let err = parse_args(&args(&["program", "-s", "40", "-E", "1", "-b", "30", "-t", "test_tracefile"])).unwrap_err();
+    assert_eq!((exit_code(&err), err.message()), (EXIT_USAGE, "40 set index bits and 30 block bits exceed the 64-bit address width"));
+    let err = parse_args(&args(&["program", "-s", "20", "-E", "1", "-b", "16", "--addr-bits", "32", "-t", "test_tracefile"])).unwrap_err();
+    assert_eq!(exit_code(&err), EXIT_USAGE);
+}
 
-    assert_eq!(cache.simulate_memory_access('X', 0, 100), Err("unknown operation: X".to_string()));
+#[test]
+fn test_json_errors() {
+    let args = |args: &[&str]| args.iter().map(|arg| arg.to_string()).collect::<Vec<String>>();
+    assert!(json_errors(&args(&["program", "-t", "x.trace", "--errors", "json"])));
+    assert!(json_errors(&args(&["program", "--errors=json", "-s", "4"])));
+    assert!(!json_errors(&args(&["program", "--errors", "text"])));
+    assert!(!json_errors(&args(&["program", "--errors"])));
+    assert!(!json_errors(&args(&["program", "-t", "json"])));
+
+    let all = ["program", "-s", "4", "-E", "2", "-b", "4", "-t", "test_tracefile", "--errors", "json"];
+    assert_eq!(parse_args(&args(&all)), Ok(Args { s: 4, e: 2, b: 4, t: "test_tracefile".to_string(), ..Default::default() }));
+    assert!(parse_args(&args(&["program", "-s", "4", "-E", "2", "-b", "4", "-t", "test_tracefile", "--errors", "xml"])).is_err());
 }
 
-// Test for update_access_order function
 #[test]
-fn test_update_access_order() {
-    let mut cache = Cache::new(6, 2, 4).unwrap();
+fn test_format_error_json() {
+    let err = SimError::Trace("line 3: invalid memory access format".to_string());
+    assert_eq!(
+        format_error_json(&err, Some("x.trace")),
+        r#"{"error":"trace-parse","exit_code":4,"message":"line 3: invalid memory access format","tracefile":"x.trace"}"#
+    );
+    let err = SimError::ParseArgs("unknown option: --fast".to_string());
+    assert_eq!(format_error_json(&err, None), r#"{"error":"args","exit_code":2,"message":"unknown option: --fast"}"#);
+}
 
-    cache.update_access_order(0, 1);
-    assert_eq!(cache.sets[0].access_order, vec![1]);
+// Tests for the decode subcommand
+#[test]
+fn test_parse_args_decode() {
+    let args = |args: &[&str]| args.iter().map(|arg| arg.to_string()).collect::<Vec<String>>();
+    assert_eq!(
+        parse_args(&args(&["program", "decode", "-s", "6", "0x7ff0", "-b", "6", "1234"])),
+        Ok(Args { decode: true, s: 6, b: 6, addresses: vec![0x7ff0, 0x1234], ..Default::default() })
+    );
+    for invalid in [
+        vec!["program", "decode", "-s", "6", "-b", "6"],
+        vec!["program", "decode", "-s", "6", "7ff0"],
+        vec!["program", "decode", "-s", "6", "-b", "6", "0xzz"],
+        vec!["program", "decode", "-s", "6", "-E", "2", "-b", "6", "7ff0"],
+        vec!["program", "decode", "-s", "6", "-b", "6", "-t", "test_tracefile", "7ff0"],
+        vec!["program", "decode", "-s", "32", "-b", "32", "7ff0"],
+    ] {
+        assert!(matches!(parse_args(&args(&invalid)), Err(SimError::ParseArgs(_))), "{:?}", invalid);
+    }
+}
 
-    cache.update_access_order(0, 2);
-    assert_eq!(cache.sets[0].access_order, vec![2, 1]);
- 
-    cache.update_access_order(0, 1);
-    assert_eq!(cache.sets[0].access_order, vec![1, 2]);
+// Tests for --compare-ref and --compare-expected options
+#[test]
+fn test_parse_args_compare() {
+    let args = |args: &[&str]| args.iter().map(|arg| arg.to_string()).collect::<Vec<String>>();
+    let base = ["program", "-s", "4", "-E", "2", "-b", "4", "-t", "test_tracefile"];
+    assert_eq!(
+        parse_args(&args(&[&base[..], &["--compare-ref", "./csim-ref"]].concat())),
+        Ok(Args { s: 4, e: 2, b: 4, t: "test_tracefile".to_string(), compare_ref: Some("./csim-ref".to_string()), ..Default::default() })
+    );
+    assert_eq!(parse_args(&args(&[&base[..], &["--compare-expected", "yi.out"]].concat())).unwrap().compare_expected, Some("yi.out".to_string()));
+    for extra in [
+        vec!["--compare-ref", "./csim-ref", "--compare-expected", "yi.out"],
+        vec!["--compare-ref", "./csim-ref", "--warmup", "10"],
+        vec!["--compare-expected", "yi.out", "--skewed"],
+        vec!["--compare-ref", "./csim-ref", "-t", "other_tracefile"],
+    ] {
+        assert!(parse_args(&args(&[&base[..], &extra].concat())).is_err(), "{:?}", extra);
+    }
+}
 
-    cache.update_access_order(0, 3);
-    assert_eq!(cache.sets[0].access_order, vec![3, 1, 2]);
+#[test]
+fn test_parse_args_policies() {
+    let args = |args: &[&str]| args.iter().map(|arg| arg.to_string()).collect::<Vec<String>>();
+    let base = ["program", "-s", "4", "-E", "2", "-b", "4", "-t", "test_tracefile"];
+    assert_eq!(parse_args(&args(&[&base[..], &["--policy", "fifo"]].concat())).unwrap().replacement, Replacement::Fifo);
+    assert_eq!(parse_args(&args(&[&base[..], &["--policy", "mru"]].concat())).unwrap().replacement, Replacement::Mru);
+    assert_eq!(parse_args(&args(&[&base[..], &["--policy", "2q"]].concat())).unwrap().replacement, Replacement::TwoQ);
+    assert_eq!(parse_args(&args(&[&base[..], &["--policy", "lirs"]].concat())).unwrap().replacement, Replacement::Lirs);
+    assert_eq!(
+        cache_builder(&parse_args(&args(&[&base[..], &["--policy", "slru", "--protected-ways", "1"]].concat())).unwrap()),
+        CacheBuilder::new().set_index_bits(4).associativity(2).block_bits(4).replacement(Replacement::Slru).protected_ways(1)
+    );
+    assert_eq!(cache_builder(&parse_args(&args(&[&base[..], &["--policy", "plru"]].concat())).unwrap()).build().unwrap().replacement(), Replacement::Plru);
+    assert_eq!(
+        cache_builder(&parse_args(&args(&[&base[..], &["--adaptive", "lru,brrip"]].concat())).unwrap()),
+        CacheBuilder::new().set_index_bits(4).associativity(2).block_bits(4).adaptive(Replacement::Lru, Replacement::Brrip)
+    );
+    assert_eq!(parse_args(&args(&[&base[..], &["--policy", "drrip"]].concat())).unwrap().replacement, Replacement::Drrip);
+    assert_eq!(parse_args(&args(&[&base[..], &["--policy", "ship-pc"]].concat())).unwrap().replacement, Replacement::ShipPc);
+    assert_eq!(parse_args(&args(&[&base[..], &["--policy", "hawkeye"]].concat())).unwrap().replacement, Replacement::Hawkeye);
+    assert_eq!(
+        cache_builder(&parse_args(&args(&[&base[..], &["--policy", "plru", "--insertion", "bip"]].concat())).unwrap()),
+        CacheBuilder::new().set_index_bits(4).associativity(2).block_bits(4).replacement(Replacement::Plru).insertion(Insertion::Bip)
+    );
+    assert_eq!(
+        parse_args(&args(&[&base[..], &["--compare-policies", "lru,plru", "--divergences", "3", "--warmup", "5", "--tie-break", "random"]].concat())),
+        Ok(Args {
+            s: 4,
+            e: 2,
+            b: 4,
+            t: "test_tracefile".to_string(),
+            compare_policies: vec![Replacement::Lru, Replacement::Plru],
+            divergences: Some(3),
+            warmup: Some(5),
+            tie_break: TieBreak::Random,
+            ..Default::default()
+        })
+    );
+    for extra in [
+        vec!["--policy", "lfu"],
+        vec!["--insertion", "lru"],
+        vec!["--insertion", "lip", "--policy", "srrip"],
+        vec!["--compare-policies", "lru,fifo", "--insertion", "bip"],
+        vec!["--protected-ways", "1"],
+        vec!["--policy", "slru", "--protected-ways", "2"],
+        vec!["--adaptive", "lru"],
+        vec!["--adaptive", "lru,mru,fifo"],
+        vec!["--adaptive", "lru,lfu"],
+        vec!["--adaptive", "lru,lru"],
+        vec!["--adaptive", "lru,drrip"],
+        vec!["--adaptive", "lru,mru", "--policy", "fifo"],
+        vec!["--adaptive", "lru,mru", "--insertion", "lip"],
+        vec!["--compare-policies", "lru,fifo", "--adaptive", "lru,mru"],
+        vec!["--compare-policies", "lru"],
+        vec!["--compare-policies", "lru,lfu"],
+        vec!["--compare-policies", "lru,fifo", "--policy", "random"],
+        vec!["--compare-policies", "lru,fifo", "--output", "csv"],
+        vec!["--compare-policies", "lru,fifo", "--compare-ref", "./csim-ref"],
+        vec!["--compare-policies", "lru,fifo", "--skewed"],
+        vec!["--divergences", "3"],
+        vec!["--compare-policies", "lru,fifo", "--divergences", "x"],
+    ] {
+        assert!(parse_args(&args(&[&base[..], &extra].concat())).is_err(), "{:?}", extra);
+    }
+    // PLRU needs a power-of-two associativity
+    let base = ["program", "-s", "4", "-E", "3", "-b", "4", "-t", "test_tracefile"];
+    assert!(parse_args(&args(&[&base[..], &["--compare-policies", "lru,plru"]].concat())).is_err());
+    assert!(parse_args(&args(&[&base[..], &["--compare-policies", "lru,fifo"]].concat())).is_ok());
 }
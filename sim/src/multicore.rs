@@ -0,0 +1,542 @@
+//! Several traces replayed together, one per core, through private caches in front of shared levels.
+//!
+//! Each core looks its accesses up in private copies of the first levels of a configuration, by default only the
+//! first one; its misses are fetched from the levels after them, which every core shares, and from the memory behind
+//! them. Accesses of the shared levels
+//! carry the index of their core as thread id, so their per-thread counts show how the cores interfere. Caches aren't
+//! kept coherent: a block written by one core isn't invalidated in the private caches of the others.
+
+use std::fmt;
+
+use crate::cache::split_address;
+use crate::config::HierarchyConfig;
+use crate::error::SimError;
+use crate::hierarchy::{fetch_levels, Hierarchy, Level};
+use crate::snoop::SnoopFilter;
+use crate::trace::MemoryAccess;
+
+/// Order the accesses of the cores are interleaved in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Interleave {
+    /// One access of each core in turn, skipping the cores whose trace has ended
+    #[default]
+    RoundRobin,
+    /// The next access of the core with the earliest clock, each core's clock advancing by the cycles its accesses
+    /// took, so a core that misses often falls behind the others
+    Timestamp,
+}
+
+impl std::str::FromStr for Interleave {
+    type Err = SimError;
+
+    fn from_str(value: &str) -> Result<Interleave, SimError> {
+        match value {
+            "round-robin" => Ok(Interleave::RoundRobin),
+            "timestamp" => Ok(Interleave::Timestamp),
+            _ => Err(SimError::Config(format!("unknown interleaving: {}", value))),
+        }
+    }
+}
+
+impl fmt::Display for Interleave {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Interleave::RoundRobin => "round-robin",
+            Interleave::Timestamp => "timestamp",
+        })
+    }
+}
+
+/// A core: its private caches, and the accesses and cycles it simulated so far.
+pub struct Core {
+    pub private: Vec<Level>,
+    pub accesses: usize,
+    pub cycles: u64,
+}
+
+/// Cores with private caches in front of the shared levels of a configuration, and optionally a snoop filter
+/// tracking the blocks of the private caches.
+pub struct MultiCore {
+    pub cores: Vec<Core>,
+    pub shared: Hierarchy,
+    pub snoop_filter: Option<SnoopFilter>,
+}
+
+impl MultiCore {
+    /// Build the empty caches of a configuration for the given number of cores, each with its own copies of the
+    /// configuration's private levels.
+    pub fn new(config: &HierarchyConfig, cores: usize) -> Result<MultiCore, SimError> {
+        let private = config.private_levels();
+        if private == 0 {
+            return Err(SimError::Config("several cores need a private first level".to_string()));
+        }
+        if cores == 0 {
+            return Err(SimError::Config("at least one core is needed".to_string()));
+        }
+        let mut shared = Hierarchy::new(config)?;
+        let first: Vec<Level> = shared.levels.drain(..private).collect();
+        let cores = (0..cores).map(|_| {
+            let private = first.iter().zip(&config.levels).map(|(level, level_config)| {
+                Ok(Level { name: level.name.clone(), cache: level_config.builder()?.build()?, latency: level.latency, slices: level_config.slices()? })
+            }).collect::<Result<Vec<_>, SimError>>()?;
+            Ok(Core { private, accesses: 0, cycles: 0 })
+        }).collect::<Result<Vec<_>, SimError>>()?;
+        if config.snoop_filter && config.levels[..private].iter().any(|level| level.skewed) {
+            return Err(SimError::Config("the snoop filter doesn't support skewed private levels".to_string()));
+        }
+        let snoop_filter = config.snoop_filter.then(|| SnoopFilter::new(cores.len()));
+        Ok(MultiCore { cores, shared, snoop_filter })
+    }
+
+    /// Simulate an access of a core on its private caches, fetching the blocks they miss from the shared levels.
+    /// The core's clock advances by the latencies the access met, and at least a cycle.
+    pub fn simulate_access(&mut self, core: usize, access: MemoryAccess) -> Result<(), SimError> {
+        let access = MemoryAccess { thread: core, ..access };
+        if matches!(access.operation, 'F' | 'W') {
+            return self.flush(core, access);
+        }
+        if matches!(access.operation, 'K' | 'U') {
+            return self.pin(core, access);
+        }
+        let before = self.snoop_filter.as_ref().map(|_| private_sets(&self.cores[core].private, access.address));
+        let (first, rest) = self.cores[core].private.split_first_mut().expect("cores have a private level");
+        let (misses, hop_cycles) = first.look_up(access)?;
+        let latency = first.latency.unwrap_or(0);
+        let (misses, private_cycles) = fetch_levels(rest, access, misses)?;
+        if let (Some(filter), Some(before)) = (&mut self.snoop_filter, before) {
+            track_private_blocks(filter, &self.cores[core].private, core, access, misses, before);
+        }
+        let cycles = latency + hop_cycles + private_cycles + self.shared.fetch(0, access, misses)?;
+        self.shared.tick();
+        let core = &mut self.cores[core];
+        core.accesses += 1;
+        core.cycles += cycles.max(1);
+        Ok(())
+    }
+
+    // Flush a line, or everything for 'W', from the private levels of every core and the shared levels, as a flush
+    // reaches every copy of a block. A line flush is a request the snoop filter sends to the cores holding the block.
+    // The flush takes the core a cycle
+    fn flush(&mut self, core: usize, access: MemoryAccess) -> Result<(), SimError> {
+        for level in self.cores.iter_mut().flat_map(|core| &mut core.private).chain(&mut self.shared.levels) {
+            level.maintain(access)?;
+        }
+        if let Some(filter) = &mut self.snoop_filter {
+            let block = access.address >> level_bits(&self.cores[0].private[0]).1;
+            match access.operation {
+                'W' => filter.clear(),
+                _ => {
+                    filter.snoop(block, core);
+                    (0..self.cores.len()).for_each(|holder| filter.evict(block, holder));
+                }
+            }
+        }
+        let core = &mut self.cores[core];
+        core.accesses += 1;
+        core.cycles += 1;
+        Ok(())
+    }
+
+    // Pin or unpin a line in the core's private levels and the shared levels. The blocks a pin brings in or evicts
+    // update the snoop filter like an access. The pin takes the core a cycle
+    fn pin(&mut self, core: usize, access: MemoryAccess) -> Result<(), SimError> {
+        let before = self.snoop_filter.as_ref().map(|_| private_sets(&self.cores[core].private, access.address));
+        for level in self.cores[core].private.iter_mut().chain(&mut self.shared.levels) {
+            level.maintain(access)?;
+        }
+        if let (Some(filter), Some(before)) = (&mut self.snoop_filter, before) {
+            track_private_blocks(filter, &self.cores[core].private, core, access, 0, before);
+        }
+        let core = &mut self.cores[core];
+        core.accesses += 1;
+        core.cycles += 1;
+        Ok(())
+    }
+
+    /// Simulate the accesses of a multithreaded trace in order, each one on the core its thread id picks modulo the
+    /// number of cores, resetting the statistics after the first warmup accesses. Stops at the first error.
+    pub fn simulate_threads<I>(&mut self, accesses: I, warmup: usize) -> Result<(), SimError>
+    where
+        I: IntoIterator<Item = Result<MemoryAccess, SimError>>,
+    {
+        for (index, access) in accesses.into_iter().enumerate() {
+            let access = access?;
+            self.simulate_access(access.thread % self.cores.len(), access)?;
+            if index + 1 == warmup {
+                self.reset_stats();
+            }
+        }
+        Ok(())
+    }
+
+    /// Simulate one trace per core, interleaving their accesses in the given order, and resetting the statistics
+    /// after the first warmup accesses of all the cores together. Stops at the first error.
+    pub fn simulate_interleaved<I>(&mut self, traces: Vec<I>, interleave: Interleave, warmup: usize) -> Result<(), SimError>
+    where
+        I: IntoIterator<Item = Result<MemoryAccess, SimError>>,
+    {
+        if traces.len() != self.cores.len() {
+            return Err(SimError::Config(format!("{} traces for {} cores", traces.len(), self.cores.len())));
+        }
+        let mut traces: Vec<_> = traces.into_iter().map(IntoIterator::into_iter).collect();
+        let mut active: Vec<usize> = (0..traces.len()).collect();
+        let (mut next, mut simulated) = (0, 0);
+        while !active.is_empty() {
+            let position = match interleave {
+                Interleave::RoundRobin => next % active.len(),
+                Interleave::Timestamp => (0..active.len()).min_by_key(|&position| (self.cores[active[position]].cycles, active[position])).unwrap(),
+            };
+            let core = active[position];
+            match traces[core].next() {
+                Some(access) => {
+                    self.simulate_access(core, access?)?;
+                    simulated += 1;
+                    if simulated == warmup {
+                        self.reset_stats();
+                    }
+                    next = position + 1;
+                }
+                None => {
+                    active.remove(position);
+                    next = position;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Clear the statistics of every cache, the shared memory and the cores' counts, keeping the caches' contents
+    /// and the cores' clocks.
+    pub fn reset_stats(&mut self) {
+        for core in &mut self.cores {
+            for level in &mut core.private {
+                level.reset_stats();
+            }
+            core.accesses = 0;
+        }
+        self.shared.reset_stats();
+        if let Some(filter) = &mut self.snoop_filter {
+            filter.reset_stats();
+        }
+    }
+
+    /// One line per private cache of each core, the first one with the core's accesses and cycles, then the shared
+    /// levels' lines, each followed by the hits, misses and evictions of every core in it and by its slices' lines,
+    /// and the DRAM's and the memory controller's.
+    pub fn format_stats(&self) -> String {
+        let mut lines = Vec::new();
+        for (index, core) in self.cores.iter().enumerate() {
+            for (position, level) in core.private.iter().enumerate() {
+                let cache = &level.cache;
+                let mut line = format!(
+                    "core {} {}: hits:{} misses:{} evictions:{} miss-rate:{:.2}%",
+                    index, level.name, cache.hits(), cache.misses(), cache.evictions(), miss_rate(cache.hits(), cache.misses())
+                );
+                if position == 0 {
+                    line.push_str(&format!(" accesses:{} cycles:{}", core.accesses, core.cycles));
+                }
+                lines.push(line);
+                if let Some(slices) = &level.slices {
+                    lines.extend(slices.format_stats(&format!("core {} {}", index, level.name)));
+                }
+            }
+        }
+        for level in &self.shared.levels {
+            let cache = &level.cache;
+            lines.push(format!(
+                "{}: hits:{} misses:{} evictions:{} miss-rate:{:.2}%",
+                level.name, cache.hits(), cache.misses(), cache.evictions(), miss_rate(cache.hits(), cache.misses())
+            ));
+            for (core, stats) in cache.thread_stats() {
+                lines.push(format!(
+                    "{} core {}: hits:{} misses:{} evictions:{} miss-rate:{:.2}%",
+                    level.name, core, stats.hits, stats.misses, stats.evictions, miss_rate(stats.hits, stats.misses)
+                ));
+            }
+            if let Some(slices) = &level.slices {
+                lines.extend(slices.format_stats(&level.name));
+            }
+        }
+        if let Some(filter) = &self.snoop_filter {
+            lines.push(format!("snoop-filter: {}", filter.format_stats()));
+        }
+        if let Some(dram) = &self.shared.dram {
+            lines.push(format!("DRAM: {}", dram.format_stats()));
+        }
+        if let Some(controller) = &self.shared.controller {
+            lines.push(format!("memory-controller: {}", controller.format_stats()));
+        }
+        lines.join("\n")
+    }
+}
+
+// Address a level's cache looks up for an address, in the slice of a sliced level
+fn level_address(level: &Level, address: u64) -> u64 {
+    level.slices.as_ref().map_or(address, |slices| slices.map(address))
+}
+
+// Set bits and block bits of a level's cache
+fn level_bits(level: &Level) -> (usize, usize) {
+    (level.cache.set_count().trailing_zeros() as usize, level.cache.block_size().trailing_zeros() as usize)
+}
+
+// Set of each private level an address falls in, with the tags it holds
+fn private_sets(levels: &[Level], address: u64) -> Vec<Option<(usize, Vec<usize>)>> {
+    levels.iter().map(|level| {
+        let (set_bits, block_bits) = level_bits(level);
+        let (set_index, _) = split_address(level_address(level, address), set_bits, block_bits).ok()?;
+        let state = level.cache.set_state(set_index)?;
+        Some((set_index, state.lines.iter().filter_map(|line| line.tag).collect()))
+    }).collect()
+}
+
+// Update a snoop filter after an access of a core: count the request if it left the private levels or wrote, drop
+// the blocks the access evicted from every private level, and add the block it brought in. Blocks are counted in
+// blocks of the first private level
+fn track_private_blocks(filter: &mut SnoopFilter, levels: &[Level], core: usize, access: MemoryAccess, misses: usize, before: Vec<Option<(usize, Vec<usize>)>>) {
+    let block_bits = level_bits(&levels[0]).1;
+    let holds = |address: u64| levels.iter().any(|level| level.cache.locate(level_address(level, address)).is_some());
+    if misses > 0 || matches!(access.operation, 'S' | 'M' | 'A') {
+        filter.snoop(access.address >> block_bits, core);
+    }
+    for (level, before) in levels.iter().zip(before) {
+        let Some((set_index, tags)) = before else { continue };
+        let (set_bits, level_block_bits) = level_bits(level);
+        let after = level.cache.set_state(set_index).map_or_else(Vec::new, |state| state.lines.iter().filter_map(|line| line.tag).collect());
+        for tag in tags.into_iter().filter(|tag| !after.contains(tag)) {
+            let mapped = ((tag as u64) << set_bits | set_index as u64) << level_block_bits;
+            let address = level_address(level, mapped);
+            if !holds(address) {
+                filter.evict(address >> block_bits, core);
+            }
+        }
+    }
+    if holds(access.address) {
+        filter.fill(access.address >> block_bits, core);
+    }
+}
+
+fn miss_rate(hits: usize, misses: usize) -> f64 {
+    if hits + misses == 0 { 0.0 } else { 100.0 * misses as f64 / (hits + misses) as f64 }
+}
+
+#[cfg(test)]
+use std::collections::BTreeSet;
+#[cfg(test)]
+use crate::trace::trace_accesses;
+
+#[cfg(test)]
+const PRIVATE_L1_SHARED_L2: &str = "memory_latency = 100\n\
+    [[level]]\ns = 1\nE = 1\nb = 4\nlatency = 1\n\
+    [[level]]\ns = 4\nE = 2\nb = 4\nlatency = 10\n";
+
+#[cfg(test)]
+fn loads(addresses: &[u64]) -> Vec<Result<MemoryAccess, SimError>> {
+    addresses.iter().map(|&address| Ok(MemoryAccess { operation: 'L', address, thread: 0, size: 4, pc: 0 })).collect()
+}
+
+// Tests for Interleave
+#[test]
+fn test_parse_interleave() {
+    for interleave in [Interleave::RoundRobin, Interleave::Timestamp] {
+        assert_eq!(interleave.to_string().parse(), Ok(interleave));
+    }
+    assert!("random".parse::<Interleave>().is_err());
+}
+
+// Tests for MultiCore
+#[test]
+fn test_multicore_shared_level() {
+    let mut multicore = MultiCore::new(&HierarchyConfig::from_toml(PRIVATE_L1_SHARED_L2).unwrap(), 2).unwrap();
+    assert_eq!((multicore.cores[0].private[0].name.as_str(), multicore.shared.levels[0].name.as_str()), ("L1", "L2"));
+    // Both cores load the same blocks: the second core misses in its own L1 but hits what the first brought into L2
+    multicore.simulate_interleaved(vec![loads(&[0x0, 0x100]), loads(&[0x0, 0x100, 0x200])], Interleave::RoundRobin, 0).unwrap();
+    let shared = &multicore.shared.levels[0].cache;
+    assert_eq!((shared.hits(), shared.misses()), (2, 3));
+    assert_eq!(shared.thread_stats()[&1].hits, 2);
+    assert_eq!((multicore.cores[0].accesses, multicore.cores[1].accesses), (2, 3));
+    // An L1 miss takes 1 + 10 cycles, and 100 more when L2 misses too
+    assert_eq!((multicore.cores[0].cycles, multicore.cores[1].cycles), (222, 133));
+    assert_eq!(multicore.format_stats(), "core 0 L1: hits:0 misses:2 evictions:1 miss-rate:100.00% accesses:2 cycles:222\n\
+        core 1 L1: hits:0 misses:3 evictions:2 miss-rate:100.00% accesses:3 cycles:133\n\
+        L2: hits:2 misses:3 evictions:1 miss-rate:60.00%\n\
+        L2 core 0: hits:0 misses:2 evictions:0 miss-rate:100.00%\n\
+        L2 core 1: hits:2 misses:1 evictions:1 miss-rate:33.33%");
+
+    multicore.reset_stats();
+    assert_eq!(multicore.shared.levels[0].cache.hits() + multicore.cores[1].accesses, 0);
+}
+
+#[test]
+fn test_multicore_timestamp() {
+    // The first core misses to memory on every access, so the second one runs ahead of it
+    let mut multicore = MultiCore::new(&HierarchyConfig::from_toml(PRIVATE_L1_SHARED_L2).unwrap(), 2).unwrap();
+    let (slow, fast) = (loads(&[0x0, 0x100, 0x200, 0x300]), loads(&[0x400, 0x400, 0x400, 0x400, 0x400, 0x400]));
+    // The cores start with a miss each, then the second one's hits all come before the first one's next miss: after
+    // six accesses, two of each core are left
+    multicore.simulate_interleaved(vec![slow.clone(), fast.clone()], Interleave::Timestamp, 6).unwrap();
+    assert_eq!((multicore.cores[0].accesses, multicore.cores[1].accesses), (2, 2));
+    assert_eq!((multicore.cores[0].cycles, multicore.cores[1].cycles), (444, 111 + 5));
+
+    // Taking turns, one access of the first core and three of the second are left
+    let mut multicore = MultiCore::new(&HierarchyConfig::from_toml(PRIVATE_L1_SHARED_L2).unwrap(), 2).unwrap();
+    multicore.simulate_interleaved(vec![slow, fast], Interleave::RoundRobin, 6).unwrap();
+    assert_eq!((multicore.cores[0].accesses, multicore.cores[1].accesses), (1, 3));
+}
+
+#[test]
+fn test_multicore_traces() {
+    // Replaying one trace on a single core gives the same counts as the hierarchy
+    let config = HierarchyConfig::from_toml(PRIVATE_L1_SHARED_L2).unwrap();
+    let mut multicore = MultiCore::new(&config, 1).unwrap();
+    multicore.simulate_interleaved(vec![trace_accesses("../traces/long.trace").unwrap()], Interleave::RoundRobin, 0).unwrap();
+    let mut hierarchy = Hierarchy::new(&config).unwrap();
+    hierarchy.simulate_accesses(trace_accesses("../traces/long.trace").unwrap(), 0).unwrap();
+    assert_eq!(multicore.cores[0].private[0].cache.format_stats(), hierarchy.levels[0].cache.format_stats());
+    assert_eq!(multicore.shared.levels[0].cache.misses(), hierarchy.levels[1].cache.misses());
+
+    // Two copies of a trace share their blocks in L2
+    let mut multicore = MultiCore::new(&config, 2).unwrap();
+    let traces = vec![trace_accesses("../traces/long.trace").unwrap(), trace_accesses("../traces/long.trace").unwrap()];
+    multicore.simulate_interleaved(traces, Interleave::Timestamp, 0).unwrap();
+    assert!(multicore.shared.levels[0].cache.thread_stats()[&1].hits > hierarchy.levels[1].cache.hits());
+}
+
+#[test]
+fn test_multicore_private_levels() {
+    // Private L1 and L2 for each core in front of a shared L3
+    let config = HierarchyConfig::from_toml("memory_latency = 100\n\
+        [[level]]\ns = 1\nE = 1\nb = 4\nlatency = 1\n\
+        [[level]]\ns = 2\nE = 1\nb = 4\nlatency = 4\nprivate = true\n\
+        [[level]]\ns = 4\nE = 2\nb = 4\nlatency = 10\n").unwrap();
+    let mut multicore = MultiCore::new(&config, 2).unwrap();
+    assert_eq!(multicore.cores[1].private.iter().map(|level| level.name.as_str()).collect::<Vec<_>>(), vec!["L1", "L2"]);
+    assert_eq!(multicore.shared.levels.len(), 1);
+    // The first core's second load of 0x0 hits its L2 after 0x20 evicted it from its L1
+    multicore.simulate_interleaved(vec![loads(&[0x0, 0x20, 0x0]), loads(&[0x0])], Interleave::RoundRobin, 0).unwrap();
+    assert_eq!((multicore.cores[0].cycles, multicore.cores[1].cycles), (115 + 115 + 5, 15));
+    assert_eq!(multicore.format_stats(), "core 0 L1: hits:0 misses:3 evictions:2 miss-rate:100.00% accesses:3 cycles:235\n\
+        core 0 L2: hits:1 misses:2 evictions:0 miss-rate:66.67%\n\
+        core 1 L1: hits:0 misses:1 evictions:0 miss-rate:100.00% accesses:1 cycles:15\n\
+        core 1 L2: hits:0 misses:1 evictions:0 miss-rate:100.00%\n\
+        L3: hits:1 misses:2 evictions:0 miss-rate:66.67%\n\
+        L3 core 0: hits:0 misses:2 evictions:0 miss-rate:100.00%\n\
+        L3 core 1: hits:1 misses:0 evictions:0 miss-rate:0.00%");
+
+    // A core's accesses take longer the farther their slice of the shared level is
+    let sliced = HierarchyConfig::from_toml(&format!("{}slices = 2\nslice_latency = 5\n", PRIVATE_L1_SHARED_L2)).unwrap();
+    let mut multicore = MultiCore::new(&sliced, 2).unwrap();
+    multicore.simulate_interleaved(vec![loads(&[0x80]), loads(&[0x80])], Interleave::RoundRobin, 0).unwrap();
+    assert_eq!((multicore.cores[0].cycles, multicore.cores[1].cycles), (111 + 5, 11));
+    assert!(multicore.format_stats().ends_with("L2 slices:2 hash:xor imbalance:2.00 hop-cycles:5\n\
+        L2 slice 0: hits:0 misses:0 miss-rate:0.00% hops:0\n\
+        L2 slice 1: hits:1 misses:1 miss-rate:50.00% hops:1"));
+
+    // Without shared levels, the cores only share memory
+    let private = HierarchyConfig::from_toml("memory_latency = 100\ncores = 2\n[[level]]\ns = 1\nE = 1\nb = 4\n").unwrap();
+    let mut multicore = MultiCore::new(&private, 2).unwrap();
+    multicore.simulate_interleaved(vec![loads(&[0x0]), loads(&[0x0])], Interleave::RoundRobin, 0).unwrap();
+    assert_eq!((multicore.cores[0].cycles, multicore.cores[1].cycles), (100, 100));
+}
+
+#[test]
+fn test_multicore_threads() {
+    // Threads 0 and 2 run on the first of two cores, thread 1 on the second
+    let mut multicore = MultiCore::new(&HierarchyConfig::from_toml(PRIVATE_L1_SHARED_L2).unwrap(), 2).unwrap();
+    let accesses = [(0x0, 0), (0x0, 1), (0x0, 2), (0x100, 1)].map(|(address, thread)| Ok(MemoryAccess { operation: 'L', address, thread, size: 4, pc: 0 }));
+    multicore.simulate_threads(accesses, 0).unwrap();
+    assert_eq!((multicore.cores[0].accesses, multicore.cores[1].accesses), (2, 2));
+    assert_eq!(multicore.cores[0].private[0].cache.hits(), 1);
+    assert_eq!(multicore.shared.levels[0].cache.thread_stats()[&1].hits, 1);
+
+    let mut multicore = MultiCore::new(&HierarchyConfig::from_toml(PRIVATE_L1_SHARED_L2).unwrap(), 2).unwrap();
+    multicore.simulate_threads(trace_accesses("../traces/long.trace").unwrap(), 10).unwrap();
+    assert_eq!(multicore.cores.iter().map(|core| core.accesses).sum::<usize>() + 10, trace_accesses("../traces/long.trace").unwrap().count());
+    assert!(multicore.simulate_threads(vec![Err(SimError::Trace("bad line".to_string()))], 0).is_err());
+}
+
+#[test]
+fn test_multicore_snoop_filter() {
+    let config = HierarchyConfig::from_toml(&format!("snoop_filter = true\n{}", PRIVATE_L1_SHARED_L2)).unwrap();
+    let mut multicore = MultiCore::new(&config, 2).unwrap();
+    let access = |operation, address| MemoryAccess { operation, address, thread: 0, size: 4, pc: 0 };
+    // Misses and writes are requests, snooping the other core only when it holds the block; 0x20 evicts 0x0 from
+    // the first core's L1
+    for (core, operation, address) in [(0, 'L', 0x0), (1, 'L', 0x0), (0, 'S', 0x0), (0, 'L', 0x20), (1, 'S', 0x0), (1, 'L', 0x0)] {
+        multicore.simulate_access(core, access(operation, address)).unwrap();
+    }
+    let filter = multicore.snoop_filter.as_ref().unwrap();
+    assert_eq!((filter.holders(0x0), filter.holders(0x2)), (vec![1], vec![0]));
+    assert_eq!(filter.format_stats(), "requests:5 snoops:2 filtered:3 filter-rate:60.00% entries:2 peak-entries:2");
+    assert!(multicore.format_stats().contains("\nsnoop-filter: requests:5 snoops:2"));
+    multicore.reset_stats();
+    assert_eq!(multicore.snoop_filter.as_ref().unwrap().requests(), 0);
+
+    // The directory keeps track of every block the private caches hold
+    let mut multicore = MultiCore::new(&config, 2).unwrap();
+    let traces = vec![trace_accesses("../traces/long.trace").unwrap(), trace_accesses("../traces/trans.trace").unwrap()];
+    multicore.simulate_interleaved(traces, Interleave::RoundRobin, 0).unwrap();
+    let filter = multicore.snoop_filter.as_ref().unwrap();
+    let mut held = BTreeSet::new();
+    for (index, core) in multicore.cores.iter().enumerate() {
+        for state in core.private[0].cache.set_states() {
+            for tag in state.lines.iter().filter_map(|line| line.tag) {
+                let block = (tag << 1 | state.index) as u64;
+                assert!(filter.holders(block).contains(&index));
+                held.insert(block);
+            }
+        }
+    }
+    assert_eq!(filter.entries(), held.len());
+    assert!(filter.filtered() > 0 && filter.entries() <= 4);
+}
+
+#[test]
+fn test_multicore_flush() {
+    let config = HierarchyConfig::from_toml(&format!("snoop_filter = true\n{}", PRIVATE_L1_SHARED_L2)).unwrap();
+    let mut multicore = MultiCore::new(&config, 2).unwrap();
+    let access = |operation, address| MemoryAccess { operation, address, thread: 0, size: 4, pc: 0 };
+    // A flush by the first core empties the line in the second core's L1 and in L2 too
+    for (core, operation, address) in [(0, 'L', 0x0), (1, 'S', 0x0), (1, 'L', 0x10), (0, 'F', 0x0)] {
+        multicore.simulate_access(core, access(operation, address)).unwrap();
+    }
+    assert!(multicore.cores.iter().all(|core| core.private[0].cache.locate(0x0).is_none()));
+    assert_eq!(multicore.shared.levels[0].cache.locate(0x0), None);
+    assert_eq!(multicore.cores[1].private[0].cache.snapshot_stats().flush_write_backs, 1);
+    assert_eq!((multicore.cores[0].accesses, multicore.cores[0].cycles), (2, 112));
+    let filter = multicore.snoop_filter.as_ref().unwrap();
+    assert_eq!((filter.holders(0x0), filter.holders(0x1), filter.snoops()), (vec![], vec![1], 2));
+
+    multicore.simulate_access(0, access('W', 0)).unwrap();
+    assert!(multicore.cores[1].private[0].cache.locate(0x10).is_none());
+    assert_eq!(multicore.snoop_filter.as_ref().unwrap().entries(), 0);
+}
+
+#[test]
+fn test_multicore_pin() {
+    let config = HierarchyConfig::from_toml(&format!("snoop_filter = true\n{}", PRIVATE_L1_SHARED_L2)).unwrap();
+    let mut multicore = MultiCore::new(&config, 2).unwrap();
+    let access = |operation, address| MemoryAccess { operation, address, thread: 0, size: 4, pc: 0 };
+    // The second core's pin only reaches its own L1, and the shared L2
+    for (core, operation, address) in [(0, 'L', 0x0), (1, 'K', 0x0), (1, 'L', 0x20), (1, 'L', 0x0)] {
+        multicore.simulate_access(core, access(operation, address)).unwrap();
+    }
+    assert_eq!((multicore.cores[0].private[0].cache.pinned_lines(), multicore.cores[1].private[0].cache.pinned_lines()), (0, 1));
+    assert_eq!((multicore.cores[1].private[0].cache.hits(), multicore.shared.levels[0].cache.pinned_lines()), (1, 1));
+    let filter = multicore.snoop_filter.as_ref().unwrap();
+    assert_eq!((filter.holders(0x0), filter.holders(0x2)), (vec![0, 1], vec![]));
+}
+
+#[test]
+fn test_multicore_errors() {
+    let shared = HierarchyConfig::from_toml("[[level]]\ns = 1\nE = 1\nb = 4\nprivate = false\n").unwrap();
+    assert!(matches!(MultiCore::new(&shared, 2), Err(SimError::Config(_))));
+    let skewed = HierarchyConfig::from_toml("snoop_filter = true\n[[level]]\ns = 1\nE = 2\nb = 4\nskewed = true\n").unwrap();
+    assert_eq!(MultiCore::new(&skewed, 2).err(), Some(SimError::Config("the snoop filter doesn't support skewed private levels".to_string())));
+    let config = HierarchyConfig::from_toml(PRIVATE_L1_SHARED_L2).unwrap();
+    assert!(matches!(MultiCore::new(&config, 0), Err(SimError::Config(_))));
+    let mut multicore = MultiCore::new(&config, 2).unwrap();
+    assert!(multicore.simulate_interleaved(vec![loads(&[0x0])], Interleave::RoundRobin, 0).is_err());
+    let broken = vec![loads(&[0x0]), vec![Err(SimError::Trace("bad line".to_string()))]];
+    assert_eq!(multicore.simulate_interleaved(broken, Interleave::RoundRobin, 0), Err(SimError::Trace("bad line".to_string())));
+}
@@ -0,0 +1,219 @@
+//! Cache levels split into address-hashed slices, as the shared last-level caches of multi-core chips are: a
+//! non-uniform cache architecture (NUCA), where an access takes longer the farther its slice is from the core.
+//!
+//! A level of 2^s sets split into 2^k slices gives each slice 2^(s-k) sets. The slice of a block is a hash of its
+//! address, so that blocks spread over the slices even when their low bits repeat; the set within the slice comes
+//! from the low bits of the set index as usual. Slices sit on a ring, core c next to slice c modulo the slices, and
+//! each hop to the slice of an access can cost extra cycles.
+
+use std::fmt;
+
+use crate::error::SimError;
+
+/// How the slice of a block is picked.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SliceHash {
+    /// The top k bits of the set index XORed with every k-bit chunk of the tag
+    #[default]
+    Xor,
+    /// The top k bits of the set index, as an unsliced cache indexes its sets
+    Bits,
+}
+
+impl std::str::FromStr for SliceHash {
+    type Err = SimError;
+
+    fn from_str(value: &str) -> Result<SliceHash, SimError> {
+        match value {
+            "xor" => Ok(SliceHash::Xor),
+            "bits" => Ok(SliceHash::Bits),
+            _ => Err(SimError::Config(format!("unknown slice hash: {}", value))),
+        }
+    }
+}
+
+impl fmt::Display for SliceHash {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            SliceHash::Xor => "xor",
+            SliceHash::Bits => "bits",
+        })
+    }
+}
+
+/// Lookups of one slice.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct SliceStats {
+    pub hits: usize,
+    pub misses: usize,
+    pub hops: u64, // Ring hops from the cores to the slice, over all its lookups
+}
+
+/// The slices of a cache level and their statistics.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Slices {
+    hash: SliceHash,
+    slice_bits: usize,
+    set_bits: usize,
+    block_bits: usize,
+    hop_latency: Option<u64>,
+    stats: Vec<SliceStats>,
+}
+
+impl Slices {
+    /// Split a level of 2^set_bits sets of 2^block_bits-byte blocks into count slices, a power of two no larger
+    /// than the sets, each hop to a slice taking hop_latency cycles if given.
+    pub fn new(count: usize, hash: SliceHash, set_bits: usize, block_bits: usize, hop_latency: Option<u64>) -> Result<Slices, SimError> {
+        if !count.is_power_of_two() || count.trailing_zeros() as usize > set_bits {
+            return Err(SimError::Config(format!("slices must be a power of two no larger than the {} sets, not {}", 1u64 << set_bits.min(63), count)));
+        }
+        Ok(Slices { hash, slice_bits: count.trailing_zeros() as usize, set_bits, block_bits, hop_latency, stats: vec![SliceStats::default(); count] })
+    }
+
+    pub fn count(&self) -> usize {
+        self.stats.len()
+    }
+
+    pub fn hash(&self) -> SliceHash {
+        self.hash
+    }
+
+    /// Lookups of each slice so far, by slice.
+    pub fn stats(&self) -> &[SliceStats] {
+        &self.stats
+    }
+
+    // Bits XORed into the top of the set index: every slice_bits-bit chunk of the tag folded together
+    fn fold(&self, address: u64) -> u64 {
+        let shift = self.block_bits + self.set_bits;
+        if self.hash == SliceHash::Bits || self.slice_bits == 0 || shift >= 64 {
+            return 0;
+        }
+        let (mut tag, mut folded) = (address >> shift, 0);
+        while tag != 0 {
+            folded ^= tag & ((1 << self.slice_bits) - 1);
+            tag >>= self.slice_bits;
+        }
+        folded
+    }
+
+    /// Slice holding the block of an address.
+    pub fn slice(&self, address: u64) -> usize {
+        let shift = (self.block_bits + self.set_bits - self.slice_bits) as u32;
+        self.map(address).checked_shr(shift).unwrap_or(0) as usize & (self.count() - 1)
+    }
+
+    /// Address the level's cache looks up for an address: the top bits of its set index replaced by its slice. The
+    /// tag is unchanged, so blocks that differ keep differing.
+    pub fn map(&self, address: u64) -> u64 {
+        address ^ self.fold(address).checked_shl((self.block_bits + self.set_bits - self.slice_bits) as u32).unwrap_or(0)
+    }
+
+    /// Ring hops between a slice and the one next to a core.
+    pub fn distance(&self, slice: usize, core: usize) -> u64 {
+        let home = core % self.count();
+        let hops = slice.abs_diff(home);
+        hops.min(self.count() - hops) as u64
+    }
+
+    /// Cycles an access of a core takes to reach a slice, if hops have a latency.
+    pub fn hop_cycles(&self, slice: usize, core: usize) -> u64 {
+        self.hop_latency.unwrap_or(0) * self.distance(slice, core)
+    }
+
+    /// Count a lookup of a slice by a core.
+    pub fn record(&mut self, slice: usize, core: usize, hits: usize, misses: usize) {
+        let hops = self.distance(slice, core);
+        let stats = &mut self.stats[slice];
+        stats.hits += hits;
+        stats.misses += misses;
+        stats.hops += hops;
+    }
+
+    pub fn reset_stats(&mut self) {
+        self.stats = vec![SliceStats::default(); self.count()];
+    }
+
+    /// Cycles all the lookups so far spent reaching their slices.
+    pub fn total_hop_cycles(&self) -> u64 {
+        self.hop_latency.unwrap_or(0) * self.stats.iter().map(|stats| stats.hops).sum::<u64>()
+    }
+
+    /// Lookups of the busiest slice over the mean lookups per slice: 1 when the hash spreads the lookups evenly, and
+    /// the number of slices when they all go to one.
+    pub fn imbalance(&self) -> f64 {
+        let lookups: Vec<usize> = self.stats.iter().map(|stats| stats.hits + stats.misses).collect();
+        let total: usize = lookups.iter().sum();
+        if total == 0 {
+            return 1.0;
+        }
+        *lookups.iter().max().unwrap() as f64 * self.count() as f64 / total as f64
+    }
+
+    /// A summary line for the level of the given name, then one line per slice.
+    pub fn format_stats(&self, name: &str) -> Vec<String> {
+        let mut lines = vec![format!("{} slices:{} hash:{} imbalance:{:.2} hop-cycles:{}", name, self.count(), self.hash, self.imbalance(), self.total_hop_cycles())];
+        for (slice, stats) in self.stats.iter().enumerate() {
+            let lookups = stats.hits + stats.misses;
+            let miss_rate = if lookups == 0 { 0.0 } else { 100.0 * stats.misses as f64 / lookups as f64 };
+            lines.push(format!("{} slice {}: hits:{} misses:{} miss-rate:{:.2}% hops:{}", name, slice, stats.hits, stats.misses, miss_rate, stats.hops));
+        }
+        lines
+    }
+}
+
+// Tests for SliceHash
+#[test]
+fn test_parse_slice_hash() {
+    for hash in [SliceHash::Xor, SliceHash::Bits] {
+        assert_eq!(hash.to_string().parse(), Ok(hash));
+    }
+    assert!("crc".parse::<SliceHash>().is_err());
+}
+
+// Tests for Slices
+#[test]
+fn test_slices_map() {
+    // 16 sets of 16-byte blocks in 4 slices: the slice is bits 6-7 of the address, XORed with the tag's 2-bit chunks
+    let slices = Slices::new(4, SliceHash::Xor, 4, 4, None).unwrap();
+    assert_eq!(slices.slice(0x40), 1);
+    assert_eq!(slices.slice(0x100), 1);
+    assert_eq!(slices.map(0x100), 0x140);
+    assert_eq!(slices.slice(0x340), 1 ^ 3);
+    // Mapping twice gives the address back, so no two blocks map to the same one
+    for address in [0x0, 0x1234, 0xdead_beef, u64::MAX] {
+        assert_eq!(slices.map(slices.map(address)), address);
+    }
+    // Blocks a power-of-two stride apart all share a slice with plain bits, and spread with the XOR hash
+    let bits = Slices::new(4, SliceHash::Bits, 4, 4, None).unwrap();
+    let addresses: Vec<u64> = (0..4).map(|index| index << 8).collect();
+    assert!(addresses.iter().all(|&address| bits.slice(address) == 0 && bits.map(address) == address));
+    assert_eq!(addresses.iter().map(|&address| slices.slice(address)).collect::<Vec<_>>(), vec![0, 1, 2, 3]);
+}
+
+#[test]
+fn test_slices_distance() {
+    let mut slices = Slices::new(8, SliceHash::Xor, 6, 6, Some(3)).unwrap();
+    assert_eq!((slices.distance(0, 0), slices.distance(3, 0), slices.distance(7, 0), slices.distance(2, 13)), (0, 3, 1, 3));
+    assert_eq!(slices.hop_cycles(4, 1), 9);
+    slices.record(4, 1, 1, 0);
+    slices.record(4, 0, 0, 1);
+    slices.record(0, 0, 1, 0);
+    assert_eq!(slices.stats()[4], SliceStats { hits: 1, misses: 1, hops: 7 });
+    assert_eq!(slices.total_hop_cycles(), 21);
+    assert_eq!(slices.imbalance(), 2.0 * 8.0 / 3.0);
+    assert_eq!(slices.format_stats("L3")[..2], ["L3 slices:8 hash:xor imbalance:5.33 hop-cycles:21".to_string(), "L3 slice 0: hits:1 misses:0 miss-rate:0.00% hops:0".to_string()]);
+    slices.reset_stats();
+    assert_eq!((slices.total_hop_cycles(), slices.imbalance()), (0, 1.0));
+}
+
+#[test]
+fn test_slices_errors() {
+    assert!(Slices::new(3, SliceHash::Xor, 4, 4, None).is_err());
+    assert!(Slices::new(0, SliceHash::Xor, 4, 4, None).is_err());
+    assert_eq!(
+        Slices::new(32, SliceHash::Xor, 4, 4, None),
+        Err(SimError::Config("slices must be a power of two no larger than the 16 sets, not 32".to_string()))
+    );
+    assert!(Slices::new(1, SliceHash::Xor, 0, 4, None).is_ok());
+}
@@ -0,0 +1,382 @@
+//! Paging: the page faults of a physical memory of a fixed number of frames, replacing pages as an operating system
+//! would, to carry the simulation from the caches down to main memory and the disk behind it.
+
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::fmt;
+
+use crate::error::SimError;
+use crate::trace::{trace_accesses, MemoryAccess};
+
+/// Page size used unless another one is set, in bytes.
+pub const DEFAULT_PAGE_SIZE: u64 = 4096;
+
+/// Policy choosing the page a fault evicts from a full memory.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum PageReplacement {
+    /// The least recently referenced page
+    #[default]
+    Lru,
+    /// The page loaded first, whatever its references since
+    Fifo,
+    /// The first page without its reference bit set that a hand sweeping the frames in a circle finds, clearing the
+    /// bits it passes over
+    Clock,
+    /// The oldest page without its reference bit set, pages with the bit set being cleared and moved to the back of
+    /// the queue. It evicts the same pages as the clock, which implements it without moving pages
+    SecondChance,
+}
+
+impl std::str::FromStr for PageReplacement {
+    type Err = SimError;
+
+    fn from_str(value: &str) -> Result<PageReplacement, SimError> {
+        match value {
+            "lru" => Ok(PageReplacement::Lru),
+            "fifo" => Ok(PageReplacement::Fifo),
+            "clock" => Ok(PageReplacement::Clock),
+            "second-chance" => Ok(PageReplacement::SecondChance),
+            _ => Err(SimError::Config(format!("unknown page replacement policy: {}", value))),
+        }
+    }
+}
+
+impl fmt::Display for PageReplacement {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            PageReplacement::Lru => "lru",
+            PageReplacement::Fifo => "fifo",
+            PageReplacement::Clock => "clock",
+            PageReplacement::SecondChance => "second-chance",
+        })
+    }
+}
+
+/// Counts of a paging simulation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct PagingStats {
+    pub references: usize, // Page references, one per page an access covers
+    pub hits: usize, // References to resident pages
+    pub major_faults: usize, // References to pages that had to be loaded into a frame
+    pub evictions: usize, // Pages evicted to make room for a faulting page
+    pub write_backs: usize, // Evicted pages that had been written, and so had to be saved first
+}
+
+impl PagingStats {
+    /// Format the counts on one line, with the share of references that faulted.
+    pub fn format(&self) -> String {
+        let fault_rate = if self.references == 0 { 0.0 } else { 100.0 * self.major_faults as f64 / self.references as f64 };
+        format!(
+            "references:{} hits:{} major-faults:{} evictions:{} write-backs:{} fault-rate:{:.2}%",
+            self.references, self.hits, self.major_faults, self.evictions, self.write_backs, fault_rate
+        )
+    }
+}
+
+// A frame of physical memory and the page it holds
+#[derive(Debug, Clone, Copy)]
+struct Frame {
+    page: u64,
+    referenced: bool, // Reference bit of the clock and second-chance policies
+    dirty: bool,
+    last_used: u64, // Time of the last reference, for LRU
+}
+
+/// A physical memory of a fixed number of frames holding the pages of a trace, which starts empty.
+///
+/// ```
+/// use sim::{PageReplacement, PhysicalMemory};
+///
+/// let mut memory = PhysicalMemory::new(2, 4096, PageReplacement::Lru).unwrap();
+/// for address in [0x0, 0x1000, 0x0, 0x2000, 0x1000] {
+///     memory.reference(address >> 12, false);
+/// }
+/// assert_eq!(memory.stats().major_faults, 4);
+/// ```
+#[derive(Debug, Clone)]
+pub struct PhysicalMemory {
+    page_bits: u32,
+    capacity: usize,
+    policy: PageReplacement,
+    frames: Vec<Frame>,
+    resident: HashMap<u64, usize>, // Frame holding each resident page
+    recency: BTreeMap<u64, usize>, // Frames by the time of their last reference, for LRU
+    queue: VecDeque<usize>, // Frames in the order the FIFO and second-chance policies evict them
+    hand: usize, // Next frame the clock looks at
+    time: u64,
+    stats: PagingStats,
+}
+
+impl PhysicalMemory {
+    /// Create an empty memory of the given number of frames, with pages of page_size bytes, a power of two.
+    pub fn new(frames: usize, page_size: u64, policy: PageReplacement) -> Result<PhysicalMemory, SimError> {
+        if frames == 0 {
+            return Err(SimError::Config("physical memory needs at least one frame".to_string()));
+        }
+        if !page_size.is_power_of_two() {
+            return Err(SimError::Config(format!("page size must be a power of two, not {}", page_size)));
+        }
+        Ok(PhysicalMemory {
+            page_bits: page_size.trailing_zeros(),
+            capacity: frames,
+            policy,
+            frames: Vec::with_capacity(frames.min(1 << 16)),
+            resident: HashMap::new(),
+            recency: BTreeMap::new(),
+            queue: VecDeque::new(),
+            hand: 0,
+            time: 0,
+            stats: PagingStats::default(),
+        })
+    }
+
+    /// Page size in bytes.
+    pub fn page_size(&self) -> u64 {
+        1 << self.page_bits
+    }
+
+    /// Number of frames.
+    pub fn frames(&self) -> usize {
+        self.capacity
+    }
+
+    /// Replacement policy.
+    pub fn policy(&self) -> PageReplacement {
+        self.policy
+    }
+
+    /// Counts so far.
+    pub fn stats(&self) -> PagingStats {
+        self.stats
+    }
+
+    /// Whether a page is held in a frame.
+    pub fn is_resident(&self, page: u64) -> bool {
+        self.resident.contains_key(&page)
+    }
+
+    /// Reference a page, writing it or not, and return whether it faulted.
+    pub fn reference(&mut self, page: u64, write: bool) -> bool {
+        self.time += 1;
+        self.stats.references += 1;
+        if let Some(&index) = self.resident.get(&page) {
+            self.stats.hits += 1;
+            let frame = &mut self.frames[index];
+            self.recency.remove(&frame.last_used);
+            self.recency.insert(self.time, index);
+            frame.referenced = true;
+            frame.dirty |= write;
+            frame.last_used = self.time;
+            return false;
+        }
+
+        self.stats.major_faults += 1;
+        let frame = Frame { page, referenced: true, dirty: write, last_used: self.time };
+        let index = if self.frames.len() < self.capacity {
+            self.frames.push(frame);
+            self.frames.len() - 1
+        } else {
+            let index = self.victim();
+            let evicted = self.frames[index];
+            self.resident.remove(&evicted.page);
+            self.recency.remove(&evicted.last_used);
+            self.stats.evictions += 1;
+            if evicted.dirty {
+                self.stats.write_backs += 1;
+            }
+            self.frames[index] = frame;
+            index
+        };
+        self.resident.insert(page, index);
+        self.recency.insert(self.time, index);
+        match self.policy {
+            PageReplacement::Fifo | PageReplacement::SecondChance => self.queue.push_back(index),
+            PageReplacement::Clock => self.hand = (index + 1) % self.capacity,
+            PageReplacement::Lru => {}
+        }
+        true
+    }
+
+    // Choose the frame a fault evicts from a full memory, taking it out of the FIFO queue
+    fn victim(&mut self) -> usize {
+        match self.policy {
+            PageReplacement::Lru => *self.recency.values().next().expect("full memory without frames"),
+            PageReplacement::Fifo => self.queue.pop_front().expect("full memory without frames"),
+            PageReplacement::Clock => loop {
+                let frame = &mut self.frames[self.hand];
+                if !frame.referenced {
+                    return self.hand;
+                }
+                frame.referenced = false;
+                self.hand = (self.hand + 1) % self.capacity;
+            },
+            PageReplacement::SecondChance => loop {
+                let index = self.queue.pop_front().expect("full memory without frames");
+                let frame = &mut self.frames[index];
+                if !frame.referenced {
+                    return index;
+                }
+                frame.referenced = false;
+                self.queue.push_back(index);
+            },
+        }
+    }
+
+    /// Reference the pages an access covers, a store, modify or atomic writing them. Software prefetches are left
+    /// out, as processors drop the ones that would fault, and so are cache flushes and pins.
+    pub fn access(&mut self, access: &MemoryAccess) {
+        if matches!(access.operation, 'P' | 'F' | 'W' | 'K' | 'U') {
+            return;
+        }
+        let write = matches!(access.operation, 'S' | 'M' | 'A');
+        let first = access.address >> self.page_bits;
+        let last = access.address.saturating_add(u64::from(access.size.max(1)) - 1) >> self.page_bits;
+        for page in first..=last {
+            self.reference(page, write);
+        }
+    }
+}
+
+/// Simulate a sequence of accesses on an empty memory of the given number of frames, with pages of page_size bytes.
+pub fn simulate_paging<I>(accesses: I, frames: usize, page_size: u64, policy: PageReplacement) -> Result<PhysicalMemory, SimError>
+where
+    I: IntoIterator<Item = Result<MemoryAccess, SimError>>,
+{
+    let mut memory = PhysicalMemory::new(frames, page_size, policy)?;
+    for access in accesses {
+        memory.access(&access?);
+    }
+    Ok(memory)
+}
+
+/// Simulate the paging of a tracefile.
+pub fn simulate_paging_tracefile(filename: &str, frames: usize, page_size: u64, policy: PageReplacement) -> Result<PhysicalMemory, SimError> {
+    simulate_paging(trace_accesses(filename)?, frames, page_size, policy)
+}
+
+#[cfg(test)]
+use crate::cache::CacheBuilder;
+#[cfg(test)]
+use crate::trace::simulate_tracefile;
+
+// Reference pages without writing them, returning which faulted
+#[cfg(test)]
+fn references(memory: &mut PhysicalMemory, pages: &[u64]) -> Vec<bool> {
+    pages.iter().map(|&page| memory.reference(page, false)).collect()
+}
+
+// Tests for PageReplacement
+#[test]
+fn test_parse_page_replacement() {
+    for policy in [PageReplacement::Lru, PageReplacement::Fifo, PageReplacement::Clock, PageReplacement::SecondChance] {
+        assert_eq!(policy.to_string().parse(), Ok(policy));
+    }
+    assert_eq!("plru".parse::<PageReplacement>(), Err(SimError::Config("unknown page replacement policy: plru".to_string())));
+}
+
+// Tests for PhysicalMemory
+#[test]
+fn test_physical_memory_lru() {
+    let mut memory = PhysicalMemory::new(3, 4096, PageReplacement::Lru).unwrap();
+    // 1 is referenced again before 4 faults, so 2 is the least recently used
+    assert_eq!(references(&mut memory, &[1, 2, 3, 1, 4, 2]), vec![true, true, true, false, true, true]);
+    assert!(memory.is_resident(1) && memory.is_resident(4) && !memory.is_resident(3));
+    assert_eq!(memory.stats(), PagingStats { references: 6, hits: 1, major_faults: 5, evictions: 2, write_backs: 0 });
+}
+
+#[test]
+fn test_physical_memory_fifo() {
+    let mut memory = PhysicalMemory::new(3, 4096, PageReplacement::Fifo).unwrap();
+    // 1 was loaded first, so 4 evicts it despite its reference
+    assert_eq!(references(&mut memory, &[1, 2, 3, 1, 4, 1]), vec![true, true, true, false, true, true]);
+
+    // Belady's anomaly: FIFO faults more with four frames than with three on this string
+    let string = [1, 2, 3, 4, 1, 2, 5, 1, 2, 3, 4, 5];
+    for (frames, faults) in [(3, 9), (4, 10)] {
+        let mut memory = PhysicalMemory::new(frames, 4096, PageReplacement::Fifo).unwrap();
+        references(&mut memory, &string);
+        assert_eq!(memory.stats().major_faults, faults);
+    }
+}
+
+#[test]
+fn test_physical_memory_clock() {
+    let mut memory = PhysicalMemory::new(3, 4096, PageReplacement::Clock).unwrap();
+    references(&mut memory, &[1, 2, 3]);
+    // Every bit is set: the hand clears them all and comes back to 1
+    assert!(memory.reference(4, false));
+    assert!(!memory.is_resident(1));
+    // 2 is referenced again, so the hand passes over it to 3
+    assert!(!memory.reference(2, false));
+    assert!(memory.reference(5, false));
+    assert!(memory.is_resident(2) && !memory.is_resident(3));
+}
+
+#[test]
+fn test_physical_memory_second_chance_matches_clock() {
+    let string: Vec<u64> = (0..2000u64).map(|i| (i * 7 + i / 13) % 23).collect();
+    let mut clock = PhysicalMemory::new(8, 4096, PageReplacement::Clock).unwrap();
+    let mut second_chance = PhysicalMemory::new(8, 4096, PageReplacement::SecondChance).unwrap();
+    assert_eq!(references(&mut clock, &string), references(&mut second_chance, &string));
+    assert_eq!(clock.stats(), second_chance.stats());
+    assert!(clock.stats().evictions > 0);
+}
+
+#[test]
+fn test_physical_memory_write_backs() {
+    let mut memory = PhysicalMemory::new(1, 4096, PageReplacement::Lru).unwrap();
+    memory.reference(1, false);
+    memory.reference(1, true);
+    memory.reference(2, false);
+    memory.reference(3, false);
+    assert_eq!(memory.stats(), PagingStats { references: 4, hits: 1, major_faults: 3, evictions: 2, write_backs: 1 });
+}
+
+#[test]
+fn test_physical_memory_access() {
+    let mut memory = PhysicalMemory::new(4, 4096, PageReplacement::Lru).unwrap();
+    // An access across a page boundary references both pages
+    memory.access(&MemoryAccess { operation: 'L', address: 0xffe, thread: 0, size: 4, pc: 0 });
+    assert!(memory.is_resident(0) && memory.is_resident(1));
+    // Accesses of unknown size reference their first byte's page
+    memory.access(&MemoryAccess { operation: 'S', address: 0x2fff, thread: 0, size: 0, pc: 0 });
+    assert!(memory.is_resident(2) && !memory.is_resident(3));
+    memory.access(&MemoryAccess { operation: 'P', address: 0x5000, thread: 0, size: 8, pc: 0 });
+    assert!(!memory.is_resident(5));
+    memory.access(&MemoryAccess { operation: 'F', address: 0x6000, thread: 0, size: 8, pc: 0 });
+    assert!(!memory.is_resident(6));
+    memory.access(&MemoryAccess { operation: 'L', address: u64::MAX, thread: 0, size: 8, pc: 0 });
+    assert_eq!(memory.stats().references, 4);
+}
+
+#[test]
+fn test_physical_memory_errors() {
+    assert!(matches!(PhysicalMemory::new(0, 4096, PageReplacement::Lru), Err(SimError::Config(_))));
+    assert!(matches!(PhysicalMemory::new(4, 3000, PageReplacement::Lru), Err(SimError::Config(_))));
+    assert!(matches!(PhysicalMemory::new(4, 0, PageReplacement::Lru), Err(SimError::Config(_))));
+    assert_eq!(PhysicalMemory::new(4, 1 << 21, PageReplacement::Clock).unwrap().page_size(), 1 << 21);
+}
+
+// Tests for simulate_paging_tracefile function
+#[test]
+fn test_simulate_paging_tracefile() {
+    // The accesses of yi.trace all fall in page 0
+    let memory = simulate_paging_tracefile("../traces/yi.trace", 1, 4096, PageReplacement::Lru).unwrap();
+    assert_eq!(memory.stats(), PagingStats { references: 7, hits: 6, major_faults: 1, evictions: 0, write_backs: 0 });
+
+    // With a page per block, LRU paging faults where a fully-associative LRU cache of as many lines misses
+    for frames in [1, 4, 16] {
+        let memory = simulate_paging_tracefile("../traces/trans.trace", frames, 8, PageReplacement::Lru).unwrap();
+        let mut cache = CacheBuilder::new().associativity(frames).block_bits(3).skewed(true).build().unwrap();
+        simulate_tracefile(&mut cache, "../traces/trans.trace").unwrap();
+        assert_eq!(memory.stats().major_faults, cache.misses());
+    }
+    assert!(simulate_paging_tracefile("../traces/missing.trace", 1, 4096, PageReplacement::Lru).is_err());
+}
+
+// Tests for PagingStats::format
+#[test]
+fn test_format_paging_stats() {
+    let stats = PagingStats { references: 8, hits: 5, major_faults: 3, evictions: 1, write_backs: 1 };
+    assert_eq!(stats.format(), "references:8 hits:5 major-faults:3 evictions:1 write-backs:1 fault-rate:37.50%");
+    assert_eq!(PagingStats::default().format(), "references:0 hits:0 major-faults:0 evictions:0 write-backs:0 fault-rate:0.00%");
+}
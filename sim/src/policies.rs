@@ -0,0 +1,158 @@
+//! Simulating several replacement policies side by side over one pass of a trace, and listing the accesses whose
+//! outcomes differ between them.
+
+use crate::cache::{AccessResult, Cache, CacheBuilder, Replacement};
+use crate::error::SimError;
+use crate::trace::MemoryAccess;
+
+/// Diverging accesses listed unless another number is asked for.
+pub const DEFAULT_DIVERGENCES: usize = 10;
+
+/// An access whose hits, misses or evictions differ between policies, with its outcome under each policy in the
+/// order they were given. Accesses are numbered from 1, counting the warm-up.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PolicyDivergence {
+    pub number: usize,
+    pub results: Vec<AccessResult>,
+}
+
+/// Caches simulated with each policy, in the order they were given, and the accesses where they diverged after
+/// the warm-up.
+pub struct PolicyComparison {
+    pub policies: Vec<Replacement>,
+    pub caches: Vec<Cache>,
+    pub diverging: usize, // Accesses after the warm-up with different outcomes
+    pub divergences: Vec<PolicyDivergence>, // The first ones, up to the limit
+}
+
+impl PolicyComparison {
+    /// A table of the statistics of each policy, the number of diverging accesses and the first of them, e.g.
+    /// `access 5 L 110,1: lru miss eviction | plru miss`.
+    pub fn format(&self) -> String {
+        let mut text = format!("{:>8}{:>12}{:>12}{:>12}{:>11}\n", "policy", "hits", "misses", "evictions", "miss-rate");
+        for (policy, cache) in self.policies.iter().zip(&self.caches) {
+            let lookups = cache.hits() + cache.misses();
+            let miss_rate = if lookups == 0 { 0.0 } else { 100.0 * cache.misses() as f64 / lookups as f64 };
+            text.push_str(&format!(
+                "{:>8}{:>12}{:>12}{:>12}{:>10.2}%\n",
+                policy.to_string(), cache.hits(), cache.misses(), cache.evictions(), miss_rate
+            ));
+        }
+        text.push_str(&format!("diverging-accesses:{}\n", self.diverging));
+        for divergence in &self.divergences {
+            let access = divergence.results[0].access;
+            let outcomes: Vec<String> = self.policies.iter().zip(&divergence.results)
+                .map(|(policy, result)| format!("{} {}", policy, outcome(result)))
+                .collect();
+            text.push_str(&format!("access {} {} {:x},{}: {}\n", divergence.number, access.operation, access.address, access.size, outcomes.join(" | ")));
+        }
+        text
+    }
+}
+
+// The misses, evictions and hits of an access, e.g. "miss eviction hit"
+fn outcome(result: &AccessResult) -> String {
+    let outcomes = [("miss", result.misses), ("eviction", result.evictions), ("hit", result.hits)];
+    let words: Vec<&str> = outcomes.iter().flat_map(|&(word, count)| std::iter::repeat_n(word, count)).collect();
+    if words.is_empty() { "skipped".to_string() } else { words.join(" ") }
+}
+
+/// Simulate one cache per policy, each configured by the builder otherwise, over one pass of the accesses,
+/// resetting the statistics after the first warmup accesses. Accesses after the warm-up whose hits, misses or
+/// evictions differ between the policies are counted, and the first limit of them are kept.
+pub fn compare_policies<I>(builder: &CacheBuilder, policies: &[Replacement], accesses: I, warmup: usize, limit: usize) -> Result<PolicyComparison, SimError>
+where
+    I: IntoIterator<Item = Result<MemoryAccess, SimError>>,
+{
+    if policies.len() < 2 {
+        return Err(SimError::Config("comparing policies needs at least two of them".to_string()));
+    }
+    if let Some((index, policy)) = policies.iter().enumerate().find(|(index, policy)| policies[..*index].contains(policy)) {
+        return Err(SimError::Config(format!("policy {} is given twice (position {})", policy, index + 1)));
+    }
+    let mut caches = policies.iter()
+        .map(|&policy| builder.clone().replacement(policy).build())
+        .collect::<Result<Vec<Cache>, SimError>>()?;
+    let (mut number, mut diverging, mut divergences) = (0, 0, Vec::new());
+    for access in accesses {
+        let access = access?;
+        let results = caches.iter_mut().map(|cache| cache.simulate_access(access)).collect::<Result<Vec<AccessResult>, SimError>>()?;
+        number += 1;
+        if number <= warmup {
+            if number == warmup {
+                caches.iter_mut().for_each(Cache::reset_stats);
+            }
+            continue;
+        }
+        if results.iter().any(|result| (result.hits, result.misses, result.evictions) != (results[0].hits, results[0].misses, results[0].evictions)) {
+            diverging += 1;
+            if divergences.len() < limit {
+                divergences.push(PolicyDivergence { number, results });
+            }
+        }
+    }
+    // A trace shorter than the warm-up only warms the caches up
+    if number < warmup {
+        caches.iter_mut().for_each(Cache::reset_stats);
+    }
+    Ok(PolicyComparison { policies: policies.to_vec(), caches, diverging, divergences })
+}
+
+#[cfg(test)]
+use crate::trace::{trace_accesses, simulate_tracefile_with_warmup};
+
+// Tests for compare_policies function
+#[test]
+fn test_compare_policies_matches_single_runs() {
+    let policies = [Replacement::Lru, Replacement::Fifo, Replacement::Random, Replacement::Plru];
+    let builder = CacheBuilder::new().set_index_bits(2).associativity(4).block_bits(3).seed(11);
+    for warmup in [0, 100] {
+        let comparison = compare_policies(&builder, &policies, trace_accesses("../traces/trans.trace").unwrap(), warmup, 3).unwrap();
+        for (&policy, cache) in policies.iter().zip(&comparison.caches) {
+            let mut single = builder.clone().replacement(policy).build().unwrap();
+            simulate_tracefile_with_warmup(&mut single, "../traces/trans.trace", warmup).unwrap();
+            assert_eq!(cache.format_stats(), single.format_stats(), "{} with a warm-up of {}", policy, warmup);
+        }
+        assert!(comparison.diverging > 3);
+        assert_eq!(comparison.divergences.len(), 3);
+        assert!(comparison.divergences.iter().all(|divergence| divergence.number > warmup && divergence.results.len() == 4));
+    }
+}
+
+#[test]
+fn test_compare_policies_divergences() {
+    // Blocks A B C D of set 0, then B and E: PLRU evicts C, LRU evicts A, and only LRU misses on A again
+    let accesses = [0x00, 0x20, 0x40, 0x60, 0x20, 0x80, 0x00].map(|address| Ok(MemoryAccess { operation: 'L', address, thread: 0, size: 1, pc: 0 }));
+    let builder = CacheBuilder::new().set_index_bits(1).associativity(4).block_bits(4);
+    let comparison = compare_policies(&builder, &[Replacement::Lru, Replacement::Plru], accesses.clone(), 0, 10).unwrap();
+    assert_eq!(comparison.diverging, 1);
+    assert_eq!(comparison.divergences[0].number, 7);
+    assert_eq!(
+        comparison.format(),
+        "  policy        hits      misses   evictions  miss-rate\n\
+         \x20    lru           1           6           2     85.71%\n\
+         \x20   plru           2           5           1     71.43%\n\
+         diverging-accesses:1\n\
+         access 7 L 0,1: lru miss eviction | plru hit\n"
+    );
+
+    // Accesses of the warm-up aren't compared
+    for warmup in [7, 100] {
+        let comparison = compare_policies(&builder, &[Replacement::Lru, Replacement::Plru], accesses.clone(), warmup, 10).unwrap();
+        assert_eq!((comparison.diverging, comparison.caches[0].hits() + comparison.caches[0].misses()), (0, 0));
+    }
+}
+
+#[test]
+fn test_compare_policies_errors() {
+    let builder = CacheBuilder::new().set_index_bits(1).associativity(3);
+    let accesses = || trace_accesses("../traces/yi.trace").unwrap();
+    assert!(matches!(compare_policies(&builder, &[Replacement::Lru], accesses(), 0, 10), Err(SimError::Config(_))));
+    assert_eq!(
+        compare_policies(&builder, &[Replacement::Lru, Replacement::Fifo, Replacement::Lru], accesses(), 0, 10).err(),
+        Some(SimError::Config("policy lru is given twice (position 3)".to_string()))
+    );
+    assert!(matches!(compare_policies(&builder, &[Replacement::Lru, Replacement::Plru], accesses(), 0, 10), Err(SimError::Config(_))));
+    let bad = vec![Ok(MemoryAccess { operation: 'L', address: 0, thread: 0, size: 1, pc: 0 }), Err(SimError::Trace("line 2: bad".to_string()))];
+    assert!(matches!(compare_policies(&builder, &[Replacement::Lru, Replacement::Fifo], bad, 0, 10), Err(SimError::Trace(_))));
+}
@@ -0,0 +1,163 @@
+//! Progress of long runs on stderr: the accesses simulated, their rate and, when the size of the trace is known, the
+//! share read and the time left. Short runs finish before the first report, so they stay silent.
+
+use std::io::{self, IsTerminal, Write};
+use std::time::{Duration, Instant};
+
+use crate::trace::ReadProgress;
+
+/// Time between two redraws of the progress bar on a terminal.
+pub const PROGRESS_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Time between two progress lines when stderr isn't a terminal, such as a batch job's log.
+pub const PROGRESS_LOG_INTERVAL: Duration = Duration::from_secs(10);
+
+// Accesses between two looks at the clock
+const CLOCK_CHECK_PERIOD: usize = 4096;
+
+// Characters of the progress bar
+const BAR_WIDTH: usize = 20;
+
+/// Reports the progress of a run at regular intervals, either redrawing one line or printing one line per report.
+pub struct ProgressReporter<W: Write> {
+    output: W,
+    read: ReadProgress,
+    redraw: bool,
+    interval: Duration,
+    start: Instant,
+    last_report: Instant,
+    accesses: usize,
+    drawn: usize, // Length of the redrawn line, 0 if none is shown
+}
+
+impl ProgressReporter<io::Stderr> {
+    /// Report on stderr, redrawing a bar if it is a terminal and printing a line every
+    /// [`PROGRESS_LOG_INTERVAL`] otherwise.
+    pub fn stderr(read: ReadProgress) -> ProgressReporter<io::Stderr> {
+        let redraw = io::stderr().is_terminal();
+        let interval = if redraw { PROGRESS_INTERVAL } else { PROGRESS_LOG_INTERVAL };
+        ProgressReporter::new(io::stderr(), read, redraw, interval)
+    }
+}
+
+impl<W: Write> ProgressReporter<W> {
+    pub fn new(output: W, read: ReadProgress, redraw: bool, interval: Duration) -> ProgressReporter<W> {
+        let now = Instant::now();
+        ProgressReporter { output, read, redraw, interval, start: now, last_report: now, accesses: 0, drawn: 0 }
+    }
+
+    /// Count one more access, and report if the interval has passed since the last report.
+    pub fn tick(&mut self) {
+        self.accesses += 1;
+        if self.accesses.is_multiple_of(CLOCK_CHECK_PERIOD) && self.last_report.elapsed() >= self.interval {
+            self.report();
+        }
+    }
+
+    /// Erase the redrawn line, if one is shown, so the results start on a clean line. Dropping the reporter also
+    /// erases it.
+    pub fn finish(&mut self) {
+        if self.drawn > 0 {
+            // A failing stderr only loses the report
+            let _ = write!(self.output, "\r{}\r", " ".repeat(self.drawn));
+            let _ = self.output.flush();
+            self.drawn = 0;
+        }
+    }
+
+    fn report(&mut self) {
+        self.last_report = Instant::now();
+        let line = format_progress(self.accesses, self.start.elapsed(), self.read.fraction());
+        let _ = if self.redraw {
+            // Pad over the end of a longer previous line
+            let width = self.drawn.max(line.len());
+            self.drawn = line.len();
+            write!(self.output, "\r{:<width$}", line, width = width)
+        } else {
+            writeln!(self.output, "{}", line)
+        };
+        let _ = self.output.flush();
+    }
+}
+
+impl<W: Write> Drop for ProgressReporter<W> {
+    fn drop(&mut self) {
+        self.finish();
+    }
+}
+
+/// One progress report, such as `progress: [#####...............] 25.0% accesses:1200000 rate:400000/s eta:9s`.
+/// The bar, share and time left need the share of the trace read.
+pub fn format_progress(accesses: usize, elapsed: Duration, fraction: Option<f64>) -> String {
+    let seconds = elapsed.as_secs_f64();
+    let rate = if seconds > 0.0 { (accesses as f64 / seconds) as u64 } else { 0 };
+    let counts = format!("accesses:{} rate:{}/s", accesses, rate);
+    match fraction {
+        Some(fraction) => {
+            let filled = (fraction * BAR_WIDTH as f64).round() as usize;
+            let bar = format!("{}{}", "#".repeat(filled), ".".repeat(BAR_WIDTH - filled));
+            let eta = if fraction > 0.0 { format_duration(seconds * (1.0 - fraction) / fraction) } else { "?".to_string() };
+            format!("progress: [{}] {:.1}% {} eta:{}", bar, 100.0 * fraction, counts, eta)
+        }
+        None => format!("progress: {}", counts),
+    }
+}
+
+// Format seconds as 1h02m03s, 2m03s or 3s
+fn format_duration(seconds: f64) -> String {
+    let seconds = seconds.round() as u64;
+    match (seconds / 3600, seconds / 60 % 60, seconds % 60) {
+        (0, 0, s) => format!("{}s", s),
+        (0, m, s) => format!("{}m{:02}s", m, s),
+        (h, m, s) => format!("{}h{:02}m{:02}s", h, m, s),
+    }
+}
+
+// Tests for format_progress function
+#[test]
+fn test_format_progress() {
+    assert_eq!(
+        format_progress(1_200_000, Duration::from_secs(3), Some(0.25)),
+        "progress: [#####...............] 25.0% accesses:1200000 rate:400000/s eta:9s"
+    );
+    assert_eq!(format_progress(1_200_000, Duration::from_secs(3), None), "progress: accesses:1200000 rate:400000/s");
+    assert_eq!(format_progress(0, Duration::ZERO, Some(0.0)), "progress: [....................] 0.0% accesses:0 rate:0/s eta:?");
+    assert_eq!(format_duration(125.4), "2m05s");
+    assert_eq!(format_duration(3723.0), "1h02m03s");
+}
+
+// Tests for ProgressReporter
+#[test]
+fn test_progress_reporter() {
+    let mut output = Vec::new();
+    let mut reporter = ProgressReporter::new(&mut output, ReadProgress::default(), false, Duration::ZERO);
+    for _ in 0..2 * CLOCK_CHECK_PERIOD + 1 {
+        reporter.tick();
+    }
+    drop(reporter);
+    let output = String::from_utf8(output).unwrap();
+    let lines: Vec<&str> = output.lines().collect();
+    assert_eq!(lines.len(), 2);
+    assert!(lines[0].starts_with("progress: accesses:4096 rate:"));
+    assert!(lines[1].starts_with("progress: accesses:8192 rate:"));
+
+    // A redrawn line is erased at the end
+    let mut output = Vec::new();
+    let mut reporter = ProgressReporter::new(&mut output, ReadProgress::default(), true, Duration::ZERO);
+    for _ in 0..CLOCK_CHECK_PERIOD {
+        reporter.tick();
+    }
+    drop(reporter);
+    let output = String::from_utf8(output).unwrap();
+    assert!(output.starts_with("\rprogress: accesses:4096 "));
+    assert!(output.ends_with(" \r") && !output.contains('\n'));
+
+    // Nothing is reported before the interval passes
+    let mut output = Vec::new();
+    let mut reporter = ProgressReporter::new(&mut output, ReadProgress::default(), true, Duration::from_secs(3600));
+    for _ in 0..CLOCK_CHECK_PERIOD {
+        reporter.tick();
+    }
+    drop(reporter);
+    assert!(output.is_empty());
+}
@@ -0,0 +1,213 @@
+//! Comparison with a reference simulator, such as cachelab's csim-ref, access by access: both outcomes are written in
+//! the reference's verbose format (`L 10,1 miss eviction`) and the first access where they differ is reported.
+
+use std::fmt;
+use std::io::{BufRead, BufReader};
+use std::process::{Command, Stdio};
+
+use crate::cache::{AccessResult, Cache};
+use crate::error::SimError;
+use crate::trace::MemoryAccess;
+
+/// The first difference between the simulator and a reference. Accesses are numbered from 1.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Divergence {
+    /// An access with a different outcome, or a different access if the two read the trace differently
+    Access { number: usize, expected: String, actual: String },
+    /// An access the reference has after the end of the trace
+    Missing { number: usize, expected: String },
+    /// An access of the trace after the end of the reference's accesses
+    Extra { number: usize, actual: String },
+    /// Different totals, such as when the reference only gives its totals
+    Totals { expected: String, actual: String },
+}
+
+impl fmt::Display for Divergence {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Divergence::Access { number, expected, actual } => {
+                write!(f, "access {} differs\n  reference: {}\n  sim:       {}", number, expected, actual)
+            }
+            Divergence::Missing { number, expected } => write!(f, "the trace ends before access {} of the reference: {}", number, expected),
+            Divergence::Extra { number, actual } => write!(f, "the reference ends before access {}: {}", number, actual),
+            Divergence::Totals { expected, actual } => write!(f, "totals differ\n  reference: {}\n  sim:       {}", expected, actual),
+        }
+    }
+}
+
+/// Outcome of a comparison: the accesses compared and the first divergence, if any.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Comparison {
+    pub accesses: usize,
+    pub divergence: Option<Divergence>,
+}
+
+impl Comparison {
+    /// `matches reference: N accesses`, or the divergence.
+    pub fn format(&self) -> String {
+        match &self.divergence {
+            Some(divergence) => format!("first divergence: {}", divergence),
+            None => format!("matches reference: {} accesses", self.accesses),
+        }
+    }
+}
+
+/// An access and its outcome in the verbose format of csim, e.g. "M 20,1 miss eviction hit".
+pub fn verbose_line(result: &AccessResult) -> String {
+    let access = result.access;
+    let outcomes = [("miss", result.misses), ("eviction", result.evictions), ("hit", result.hits)];
+    let mut line = format!("{} {:x},{}", access.operation, access.address, access.size);
+    for (outcome, count) in outcomes {
+        for _ in 0..count {
+            line.push(' ');
+            line.push_str(outcome);
+        }
+    }
+    line
+}
+
+// The totals line of csim
+fn totals_line(cache: &Cache) -> String {
+    format!("hits:{} misses:{} evictions:{}", cache.hits(), cache.misses(), cache.evictions())
+}
+
+/// Simulate accesses on a cache and compare each outcome with the next access line of a reference's verbose output,
+/// stopping at the first divergence, then compare the totals with its `hits:X misses:Y evictions:Z` line. A
+/// reference that only gives its totals, such as an expected stats file, is only compared on them.
+pub fn compare_with_reference<I, R>(cache: &mut Cache, accesses: I, reference: R) -> Result<Comparison, SimError>
+where
+    I: IntoIterator<Item = Result<MemoryAccess, SimError>>,
+    R: BufRead,
+{
+    let read_error = |err: std::io::Error| SimError::Io(format!("failed to read the reference output ({})", err));
+    // Lines are compared with their spacing normalized, as csim ends them with a space
+    let mut lines = reference.lines()
+        .map(|line| line.map(|line| line.split_whitespace().collect::<Vec<_>>().join(" ")))
+        .filter(|line| !matches!(line, Ok(line) if line.is_empty()));
+    let (mut number, mut expected_totals, mut per_access) = (0, None, true);
+    for access in accesses {
+        let actual = verbose_line(&cache.simulate_access(access?)?);
+        number += 1;
+        if !per_access {
+            continue;
+        }
+        match lines.next().transpose().map_err(read_error)? {
+            Some(line) if line.starts_with("hits:") && number == 1 => {
+                expected_totals = Some(line);
+                per_access = false;
+            }
+            Some(line) if line.starts_with("hits:") => {
+                return Ok(Comparison { accesses: number - 1, divergence: Some(Divergence::Extra { number, actual }) });
+            }
+            Some(expected) if expected != actual => {
+                return Ok(Comparison { accesses: number - 1, divergence: Some(Divergence::Access { number, expected, actual }) });
+            }
+            Some(_) => {}
+            None => return Ok(Comparison { accesses: number - 1, divergence: Some(Divergence::Extra { number, actual }) }),
+        }
+    }
+    if per_access {
+        match lines.next().transpose().map_err(read_error)? {
+            Some(line) if line.starts_with("hits:") => expected_totals = Some(line),
+            Some(expected) => return Ok(Comparison { accesses: number, divergence: Some(Divergence::Missing { number: number + 1, expected }) }),
+            None => {}
+        }
+    }
+    let divergence = expected_totals.filter(|expected| *expected != totals_line(cache)).map(|expected| Divergence::Totals { expected, actual: totals_line(cache) });
+    Ok(Comparison { accesses: number, divergence })
+}
+
+/// Run a reference simulator taking csim's options (`-v -s <s> -E <E> -b <b> -t <tracefile>`) on a tracefile with the
+/// geometry of a cache, and compare its verbose output with the simulation of the same accesses as they are read.
+pub fn compare_with_program<I>(cache: &mut Cache, accesses: I, program: &str, tracefile: &str) -> Result<Comparison, SimError>
+where
+    I: IntoIterator<Item = Result<MemoryAccess, SimError>>,
+{
+    let run_error = |err: std::io::Error| SimError::Io(format!("failed to run the reference {} ({})", program, err));
+    let (s, e, b) = (cache.set_count().trailing_zeros(), cache.associativity(), cache.block_size().trailing_zeros());
+    let mut child = Command::new(program)
+        .args(["-v", "-s", &s.to_string(), "-E", &e.to_string(), "-b", &b.to_string(), "-t", tracefile])
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .spawn()
+        .map_err(run_error)?;
+    let output = BufReader::new(child.stdout.take().expect("piped stdout"));
+    let comparison = compare_with_reference(cache, accesses, output);
+    // Stop a reference whose output wasn't read to the end; otherwise its failure explains a short output
+    let unread = match &comparison {
+        Ok(comparison) => matches!(comparison.divergence, Some(Divergence::Access { .. } | Divergence::Missing { .. })),
+        Err(_) => true,
+    };
+    if unread && child.try_wait().map_err(run_error)?.is_none() {
+        let _ = child.kill();
+        child.wait().map_err(run_error)?;
+        return comparison;
+    }
+    let status = child.wait().map_err(run_error)?;
+    if !status.success() {
+        return Err(SimError::Io(format!("the reference {} failed ({})", program, status)));
+    }
+    comparison
+}
+
+#[cfg(test)]
+use crate::trace::trace_accesses;
+
+// Tests for verbose_line function
+#[test]
+fn test_verbose_line() {
+    let access = MemoryAccess { operation: 'M', address: 0x12, thread: 0, size: 1, pc: 0 };
+    assert_eq!(verbose_line(&AccessResult { access, hits: 1, misses: 1, evictions: 1, prefetch_hits: 0, prefetch_fills: 0 }), "M 12,1 miss eviction hit");
+    let access = MemoryAccess { operation: 'L', address: 0x7ff000398, thread: 0, size: 8, pc: 0 };
+    assert_eq!(verbose_line(&AccessResult { access, hits: 1, misses: 0, evictions: 0, prefetch_hits: 0, prefetch_fills: 0 }), "L 7ff000398,8 hit");
+}
+
+// Tests for compare_with_reference function
+#[test]
+fn test_compare_with_reference() {
+    // csim's verbose output of yi.trace on s=1, E=1, b=4, with its trailing spaces
+    let reference = "L 10,1 miss \nM 20,1 miss hit \nL 22,1 hit \nS 18,1 hit \nL 110,1 miss eviction \n\
+        L 210,1 miss eviction \nM 12,1 miss eviction hit \nhits:4 misses:5 evictions:3\n";
+    let mut cache = Cache::new(1, 1, 4).unwrap();
+    let comparison = compare_with_reference(&mut cache, trace_accesses("../traces/yi.trace").unwrap(), reference.as_bytes()).unwrap();
+    assert_eq!(comparison, Comparison { accesses: 7, divergence: None });
+    assert_eq!(comparison.format(), "matches reference: 7 accesses");
+
+    // A reference with one more way diverges at the first conflict
+    let mut cache = Cache::new(1, 2, 4).unwrap();
+    let comparison = compare_with_reference(&mut cache, trace_accesses("../traces/yi.trace").unwrap(), reference.as_bytes()).unwrap();
+    let divergence = Divergence::Access { number: 5, expected: "L 110,1 miss eviction".to_string(), actual: "L 110,1 miss".to_string() };
+    assert_eq!(comparison, Comparison { accesses: 4, divergence: Some(divergence) });
+    assert_eq!(comparison.format(), "first divergence: access 5 differs\n  reference: L 110,1 miss eviction\n  sim:       L 110,1 miss");
+}
+
+#[test]
+fn test_compare_with_reference_lengths_and_totals() {
+    let yi = || trace_accesses("../traces/yi.trace").unwrap();
+    let mut cache = Cache::new(1, 1, 4).unwrap();
+    let comparison = compare_with_reference(&mut cache, yi(), "L 10,1 miss\nM 20,1 miss hit\n".as_bytes()).unwrap();
+    assert_eq!(comparison.divergence, Some(Divergence::Extra { number: 3, actual: "L 22,1 hit".to_string() }));
+
+    let mut cache = Cache::new(1, 1, 4).unwrap();
+    let comparison = compare_with_reference(&mut cache, yi().take(1), "L 10,1 miss\nM 20,1 miss hit\n".as_bytes()).unwrap();
+    assert_eq!(comparison.divergence, Some(Divergence::Missing { number: 2, expected: "M 20,1 miss hit".to_string() }));
+
+    // An expected stats file only has the totals
+    let mut cache = Cache::new(1, 1, 4).unwrap();
+    assert_eq!(compare_with_reference(&mut cache, yi(), "hits:4 misses:5 evictions:3\n".as_bytes()).unwrap(), Comparison { accesses: 7, divergence: None });
+    let mut cache = Cache::new(1, 1, 4).unwrap();
+    let comparison = compare_with_reference(&mut cache, yi(), "hits:5 misses:4 evictions:3\n".as_bytes()).unwrap();
+    assert_eq!(comparison.divergence, Some(Divergence::Totals { expected: "hits:5 misses:4 evictions:3".to_string(), actual: "hits:4 misses:5 evictions:3".to_string() }));
+}
+
+// Tests for compare_with_program function, against the reference simulator of the repository
+#[test]
+fn test_compare_with_program() {
+    for (s, e, b, trace) in [(1, 1, 1, "../traces/yi.trace"), (4, 2, 4, "../traces/trans.trace"), (2, 4, 3, "../traces/long.trace")] {
+        let mut cache = Cache::new(s, e, b).unwrap();
+        let comparison = compare_with_program(&mut cache, trace_accesses(trace).unwrap(), "../sim-ref", trace).unwrap();
+        assert_eq!(comparison.divergence, None, "{} with s={} E={} b={}", trace, s, e, b);
+    }
+    let mut cache = Cache::new(1, 1, 4).unwrap();
+    assert!(matches!(compare_with_program(&mut cache, trace_accesses("../traces/yi.trace").unwrap(), "../no-such-sim", "../traces/yi.trace"), Err(SimError::Io(_))));
+}
@@ -0,0 +1,117 @@
+//! Rewriting the addresses of a trace, so that runs of a program captured under different address-space layouts,
+//! as ASLR gives them, index the cache the same way.
+
+use std::ops::RangeInclusive;
+
+use crate::cache::parse_address_ranges;
+use crate::error::SimError;
+
+/// A fixed offset and per-region moves applied to every address of a trace before it is simulated.
+///
+/// ```
+/// use sim::AddressRemap;
+///
+/// let remap = AddressRemap { offset: 0x1000, regions: vec![(0x7ffd_0000..=0x7ffd_ffff, 0x7ff0_0000)] };
+/// assert_eq!(remap.apply(0x7ffd_0010), 0x7ff0_0010);
+/// assert_eq!(remap.apply(0x400), 0x1400);
+/// ```
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct AddressRemap {
+    /// Added to the addresses outside the regions, modulo 2^64, so that a negative offset subtracts
+    pub offset: u64,
+    /// Address ranges moved to start at a new address, keeping the offsets within them
+    pub regions: Vec<(RangeInclusive<u64>, u64)>,
+}
+
+impl AddressRemap {
+    /// Whether the remapping leaves every address unchanged.
+    pub fn is_identity(&self) -> bool {
+        self.offset == 0 && self.regions.iter().all(|(range, start)| range.start() == start)
+    }
+
+    /// The address an access to the given address is simulated at.
+    pub fn apply(&self, address: u64) -> u64 {
+        match self.regions.iter().find(|(range, _)| range.contains(&address)) {
+            Some((range, start)) => start.wrapping_add(address - range.start()),
+            None => address.wrapping_add(self.offset),
+        }
+    }
+}
+
+/// Parse an address offset such as "0x1000" or "-7ff000000000", hexadecimal with an optional sign, into the value
+/// added to addresses modulo 2^64.
+pub fn parse_address_offset(value: &str) -> Result<u64, SimError> {
+    let (negative, magnitude) = match value.strip_prefix('-') {
+        Some(magnitude) => (true, magnitude),
+        None => (false, value.strip_prefix('+').unwrap_or(value)),
+    };
+    let digits = magnitude.strip_prefix("0x").or_else(|| magnitude.strip_prefix("0X")).unwrap_or(magnitude);
+    let offset = u64::from_str_radix(digits, 16).map_err(|e| SimError::Config(format!("invalid address offset {} ({})", value, e)))?;
+    Ok(if negative { offset.wrapping_neg() } else { offset })
+}
+
+/// Parse comma-separated region moves such as "7ffd0000-7ffdffff=7ff000000000", each an address range as
+/// [`parse_address_ranges`] reads it and the hexadecimal address its first byte moves to. Regions may not overlap.
+pub fn parse_remap_regions(value: &str) -> Result<Vec<(RangeInclusive<u64>, u64)>, SimError> {
+    let regions = value.split(',').map(|region| {
+        let (range, start) = region.split_once('=').ok_or(SimError::Config(format!("invalid remapped region {} (expected <first>-<last>=<start>)", region)))?;
+        let range = parse_address_ranges(range)?.remove(0);
+        let start = start.trim();
+        let digits = start.strip_prefix("0x").or_else(|| start.strip_prefix("0X")).unwrap_or(start);
+        let start = u64::from_str_radix(digits, 16).map_err(|e| SimError::Config(format!("invalid start address in remapped region {} ({})", region, e)))?;
+        Ok((range, start))
+    }).collect::<Result<Vec<_>, SimError>>()?;
+
+    let mut ranges: Vec<&RangeInclusive<u64>> = regions.iter().map(|(range, _)| range).collect();
+    ranges.sort_by_key(|range| range.start());
+    if let Some(overlap) = ranges.windows(2).find(|pair| pair[1].start() <= pair[0].end()) {
+        return Err(SimError::Config(format!(
+            "remapped regions {:x}-{:x} and {:x}-{:x} overlap",
+            overlap[0].start(), overlap[0].end(), overlap[1].start(), overlap[1].end()
+        )));
+    }
+    Ok(regions)
+}
+
+// Tests for AddressRemap
+#[test]
+fn test_address_remap() {
+    let remap = AddressRemap { offset: 0x10u64.wrapping_neg(), regions: vec![(0x1000..=0x1fff, 0x8000), (0x4000..=0x4fff, 0x4000)] };
+    assert_eq!(remap.apply(0x1000), 0x8000);
+    assert_eq!(remap.apply(0x1fff), 0x8fff);
+    assert_eq!(remap.apply(0x4010), 0x4010);
+    assert_eq!(remap.apply(0x2000), 0x1ff0);
+    // The offset wraps around the address space
+    assert_eq!(remap.apply(0x8), u64::MAX - 7);
+    assert!(!remap.is_identity());
+
+    assert!(AddressRemap::default().is_identity());
+    assert!(AddressRemap { offset: 0, regions: vec![(0x4000..=0x4fff, 0x4000)] }.is_identity());
+    assert_eq!(AddressRemap { offset: 1, regions: vec![(0..=u64::MAX, u64::MAX)] }.apply(1), 0);
+}
+
+// Tests for parse_address_offset function
+#[test]
+fn test_parse_address_offset() {
+    assert_eq!(parse_address_offset("0x1000"), Ok(0x1000));
+    assert_eq!(parse_address_offset("+1000"), Ok(0x1000));
+    assert_eq!(parse_address_offset("-0x1000"), Ok(0x1000u64.wrapping_neg()));
+    assert_eq!(parse_address_offset("-7ff000000000").map(|offset| 0x7ff0_0000_0010u64.wrapping_add(offset)), Ok(0x10));
+    for invalid in ["", "-", "0x", "--1", "12g", "1 0"] {
+        assert!(matches!(parse_address_offset(invalid), Err(SimError::Config(_))), "{}", invalid);
+    }
+}
+
+// Tests for parse_remap_regions function
+#[test]
+fn test_parse_remap_regions() {
+    assert_eq!(parse_remap_regions("7ffd0000-7ffdffff=7ff000000000"), Ok(vec![(0x7ffd_0000..=0x7ffd_ffff, 0x7ff0_0000_0000)]));
+    assert_eq!(parse_remap_regions("0x10-0x1f=0x100, 0x20=0x200"), Ok(vec![(0x10..=0x1f, 0x100), (0x20..=0x20, 0x200)]));
+    assert_eq!(
+        parse_remap_regions("10-1f=100,18-2f=200"),
+        Err(SimError::Config("remapped regions 10-1f and 18-2f overlap".to_string()))
+    );
+    for invalid in ["", "10-1f", "10-1f=", "10-1f=-100", "1f-10=100", "10-1f=100,", "x-1f=100"] {
+        assert!(matches!(parse_remap_regions(invalid), Err(SimError::Config(_))), "{}", invalid);
+    }
+}
@@ -0,0 +1,111 @@
+//! Interactive simulation of accesses typed one at a time, without a tracefile.
+
+use std::io::{BufRead, Write};
+
+use crate::cache::{split_address, Cache};
+use crate::error::SimError;
+use crate::trace::parse_memory_access;
+#[cfg(test)]
+use crate::cache::CacheBuilder;
+
+const REPL_HELP: &str = "Type an access such as L 7ff0,4 (S stores, M modifies), or s(tats), h(elp), q(uit)";
+
+// Write REPL output
+fn emit<W: Write>(output: &mut W, text: &str) -> Result<(), SimError> {
+    output.write_all(text.as_bytes()).and_then(|_| output.flush()).map_err(|err| SimError::Io(format!("failed to write output ({})", err)))
+}
+
+/// Describe where an address lands in a cache: its tag, set index and offset within the block. The lines
+/// of a skewed cache keep the whole block address, and each way indexes its own set.
+pub fn describe_address(cache: &Cache, address: u64) -> Result<String, SimError> {
+    let block_bits = cache.block_size().trailing_zeros() as usize;
+    if cache.is_skewed() {
+        let offset = address & (cache.block_size() as u64 - 1);
+        return Ok(format!("block:{:x} offset:{} (skewed: each way indexes its own set)", address >> block_bits, offset));
+    }
+    decompose_address(address, cache.set_count().trailing_zeros() as usize, block_bits)
+}
+
+/// Split an address into its tag, set index and block offset for 2^s sets of 2^b-byte blocks, as in
+/// `tag:7f set:15 offset:4`, without building a cache.
+pub fn decompose_address(address: u64, s: usize, b: usize) -> Result<String, SimError> {
+    let (set_index, tag) = split_address(address, s, b)?;
+    Ok(format!("tag:{:x} set:{} offset:{}", tag, set_index, address & ((1 << b) - 1)))
+}
+
+// Simulate one typed access and describe its decomposition, outcome and the statistics so far
+fn evaluate(cache: &mut Cache, line: &str) -> Result<String, SimError> {
+    let access = match parse_memory_access(line)? {
+        Some(access) => access,
+        None => return Ok("instruction fetches aren't simulated".to_string()),
+    };
+    let decomposition = describe_address(cache, access.address)?;
+    let result = cache.simulate_access(access)?;
+    Ok(format!("{}\n{}\n{}", decomposition, result.describe(), cache.format_stats()))
+}
+
+/// Read accesses from input one line at a time, simulating each on the cache as soon as it is typed. Lines
+/// that can't be parsed or simulated report their error and leave the cache as it was.
+pub fn repl<R: BufRead, W: Write>(cache: &mut Cache, input: R, output: &mut W) -> Result<(), SimError> {
+    emit(output, &format!("{}\n", REPL_HELP))?;
+    let mut lines = input.lines();
+    loop {
+        emit(output, "> ")?;
+        let line = match lines.next() {
+            Some(Ok(line)) => line,
+            _ => break,
+        };
+        let response = match line.trim() {
+            "" => continue,
+            "s" | "stats" => cache.format_stats(),
+            "h" | "help" => REPL_HELP.to_string(),
+            "q" | "quit" => break,
+            line => evaluate(cache, line).unwrap_or_else(|err| format!("error: {}", err)),
+        };
+        emit(output, &format!("{}\n", response))?;
+    }
+    emit(output, "\n")
+}
+
+// Tests for describe_address function
+#[test]
+fn test_describe_address() {
+    let cache = Cache::new(4, 1, 4).unwrap();
+    assert_eq!(describe_address(&cache, 0x7ff4), Ok("tag:7f set:15 offset:4".to_string()));
+    let cache = CacheBuilder::new().set_index_bits(2).associativity(2).block_bits(6).skewed(true).build().unwrap();
+    assert_eq!(describe_address(&cache, 0x1041), Ok("block:41 offset:1 (skewed: each way indexes its own set)".to_string()));
+}
+
+// Tests for decompose_address function
+#[test]
+fn test_decompose_address() {
+    assert_eq!(decompose_address(0x7ff4, 4, 4), Ok("tag:7f set:15 offset:4".to_string()));
+    assert_eq!(decompose_address(0x1234_5678, 6, 6), Ok("tag:12345 set:25 offset:56".to_string()));
+    assert_eq!(decompose_address(0xffff_ffff_ffff_ffff, 8, 8), Ok("tag:ffffffffffff set:255 offset:255".to_string()));
+}
+
+// Tests for repl function
+#[test]
+fn test_repl() {
+    let mut cache = Cache::new(4, 1, 4).unwrap();
+    let input = "L 0x7ff0,4\n\nL 7ff8,4\nS 10\nI 400,2\nM 8ff0,4\ns\nq\nL 0,1\n";
+    let mut output = Vec::new();
+    assert_eq!(repl(&mut cache, input.as_bytes(), &mut output), Ok(()));
+    let output = String::from_utf8(output).unwrap();
+    assert_eq!(output.lines().skip(1).collect::<Vec<_>>(), vec![
+        "> tag:7f set:15 offset:0",
+        "L 7ff0 miss",
+        "hits:0 misses:1 evictions:0",
+        "> > tag:7f set:15 offset:8",
+        "L 7ff8 hit",
+        "hits:1 misses:1 evictions:0",
+        "> error: invalid memory access format",
+        "> instruction fetches aren't simulated",
+        "> tag:8f set:15 offset:0",
+        "M 8ff0 miss eviction hit",
+        "hits:2 misses:2 evictions:1",
+        "> hits:2 misses:2 evictions:1",
+        "> ",
+    ]);
+    assert_eq!(cache.hits() + cache.misses(), 4);
+}
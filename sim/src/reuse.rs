@@ -0,0 +1,220 @@
+//! Reuse distances: how many distinct blocks a trace touches between two accesses to the same block.
+//!
+//! The reuse (LRU stack) distance of an access is the position its block holds in an LRU stack of all the
+//! blocks seen so far, so a fully-associative LRU cache of L lines hits exactly the accesses at distances
+//! below L. One histogram of a trace predicts the misses of every such cache size.
+
+use std::collections::HashMap;
+
+use crate::error::SimError;
+use crate::trace::{trace_accesses, MemoryAccess};
+
+/// Histogram of the reuse distances of a trace at the granularity of 2^b-byte blocks.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ReuseHistogram {
+    pub block_bits: usize,
+    pub counts: Vec<usize>, // Accesses at each distance, indexed by distance
+    pub cold: usize, // First accesses to a block, which have no reuse distance
+}
+
+impl ReuseHistogram {
+    /// Number of accesses counted, modifies counting as a load and a store.
+    pub fn accesses(&self) -> usize {
+        self.cold + self.counts.iter().sum::<usize>()
+    }
+
+    /// Misses a fully-associative LRU cache of the given number of lines would have on the trace.
+    pub fn predicted_misses(&self, lines: usize) -> usize {
+        self.cold + self.counts.iter().skip(lines).sum::<usize>()
+    }
+
+    /// Format the histogram in power-of-two buckets of distances, followed by the predicted misses of
+    /// fully-associative caches of power-of-two sizes, up to the first size that only has cold misses.
+    pub fn format(&self) -> String {
+        let mut lines = vec![format!("accesses:{} cold:{}", self.accesses(), self.cold)];
+        let mut start = 0;
+        while start < self.counts.len() {
+            let end = (2 * start).max(1).min(self.counts.len());
+            let count: usize = self.counts[start..end].iter().sum();
+            let bucket = if start < 2 { start.to_string() } else { format!("{}-{}", start, 2 * start - 1) };
+            lines.push(format!("distance:{} count:{}", bucket, count));
+            start = (2 * start).max(1);
+        }
+        let accesses = self.accesses();
+        let mut cache_lines = 1;
+        loop {
+            let misses = self.predicted_misses(cache_lines);
+            let miss_rate = if accesses == 0 { 0.0 } else { 100.0 * misses as f64 / accesses as f64 };
+            lines.push(format!("lines:{} predicted-misses:{} miss-rate:{:.2}%", cache_lines, misses, miss_rate));
+            if cache_lines >= self.counts.len() {
+                break;
+            }
+            cache_lines *= 2;
+        }
+        lines.join("\n")
+    }
+}
+
+// Reuse distances of a stream of blocks: a Fenwick tree over access times marks the last access of every
+// block, so the distance of an access is the number of marks since the previous access to its block
+struct StackDistances {
+    last_access: HashMap<u64, usize>,
+    marks: Vec<usize>, // Fenwick tree, 1-based
+    time: usize,
+}
+
+impl StackDistances {
+    fn new() -> StackDistances {
+        StackDistances { last_access: HashMap::new(), marks: vec![0; 1 << 10], time: 0 }
+    }
+
+    // Access a block and return its reuse distance, or None on its first access
+    fn access(&mut self, block: u64) -> Option<usize> {
+        if self.time + 1 >= self.marks.len() {
+            self.grow();
+        }
+        let distance = self.last_access.insert(block, self.time).map(|last| {
+            let distance = self.prefix(self.time) - self.prefix(last + 1);
+            self.update(last, false);
+            distance
+        });
+        self.update(self.time, true);
+        self.time += 1;
+        distance
+    }
+
+    // Double the times the tree covers, re-marking the last access of every block
+    fn grow(&mut self) {
+        self.marks = vec![0; 2 * self.marks.len()];
+        let times: Vec<usize> = self.last_access.values().copied().collect();
+        for time in times {
+            self.update(time, true);
+        }
+    }
+
+    // Mark or unmark the access at a time
+    fn update(&mut self, time: usize, mark: bool) {
+        let mut index = time + 1;
+        while index < self.marks.len() {
+            if mark {
+                self.marks[index] += 1;
+            } else {
+                self.marks[index] -= 1;
+            }
+            index += index & index.wrapping_neg();
+        }
+    }
+
+    // Number of marked accesses before a time
+    fn prefix(&self, time: usize) -> usize {
+        let mut index = time;
+        let mut sum = 0;
+        while index > 0 {
+            sum += self.marks[index];
+            index -= index & index.wrapping_neg();
+        }
+        sum
+    }
+}
+
+/// Compute the reuse-distance histogram of a sequence of accesses with 2^block_bits-byte blocks. Each access
+/// counts its first block, as the simulator does, and a modify or atomic counts as a load followed by a store.
+/// Flushes and pins are skipped.
+pub fn reuse_histogram<I>(accesses: I, block_bits: usize) -> Result<ReuseHistogram, SimError>
+where
+    I: IntoIterator<Item = Result<MemoryAccess, SimError>>,
+{
+    if block_bits >= 64 {
+        return Err(SimError::Config("block bits must be below 64".to_string()));
+    }
+    let mut histogram = ReuseHistogram { block_bits, counts: Vec::new(), cold: 0 };
+    let mut distances = StackDistances::new();
+    for access in accesses {
+        let access = access?;
+        if matches!(access.operation, 'F' | 'W' | 'K' | 'U') {
+            continue; // Flushes and pins touch no data
+        }
+        let block = access.address >> block_bits;
+        let times = if matches!(access.operation, 'M' | 'A') { 2 } else { 1 };
+        for _ in 0..times {
+            match distances.access(block) {
+                Some(distance) => {
+                    if distance >= histogram.counts.len() {
+                        histogram.counts.resize(distance + 1, 0);
+                    }
+                    histogram.counts[distance] += 1;
+                }
+                None => histogram.cold += 1,
+            }
+        }
+    }
+    Ok(histogram)
+}
+
+/// Compute the reuse-distance histogram of a tracefile.
+pub fn reuse_histogram_tracefile(filename: &str, block_bits: usize) -> Result<ReuseHistogram, SimError> {
+    reuse_histogram(trace_accesses(filename)?, block_bits)
+}
+
+#[cfg(test)]
+use crate::cache::CacheBuilder;
+#[cfg(test)]
+use crate::trace::simulate_tracefile;
+
+// Tests for reuse_histogram function
+#[test]
+fn test_reuse_histogram() {
+    // Blocks a b c a a b: a is reused at distances 2 and 0, b at distance 2
+    let accesses = [0x0, 0x10, 0x20, 0x8, 0x0, 0x18].map(|address| Ok(MemoryAccess { operation: 'L', address, thread: 0, size: 4, pc: 0 }));
+    let histogram = reuse_histogram(accesses, 4).unwrap();
+    assert_eq!(histogram.counts, vec![1, 0, 2]);
+    assert_eq!(histogram.cold, 3);
+    assert_eq!(histogram.accesses(), 6);
+    assert_eq!(histogram.predicted_misses(1), 5);
+    assert_eq!(histogram.predicted_misses(3), 3);
+
+    let modify = [Ok(MemoryAccess { operation: 'M', address: 0x0, thread: 0, size: 4, pc: 0 })];
+    assert_eq!(reuse_histogram(modify, 4).unwrap().counts, vec![1]);
+    let atomic = [Ok(MemoryAccess { operation: 'A', address: 0x0, thread: 0, size: 4, pc: 0 })];
+    assert_eq!(reuse_histogram(atomic, 4).unwrap().counts, vec![1]);
+    let flushes = ['L', 'F', 'W', 'L'].map(|operation| Ok(MemoryAccess { operation, address: 0x0, thread: 0, size: 4, pc: 0 }));
+    assert_eq!(reuse_histogram(flushes, 4).unwrap().accesses(), 2);
+    assert!(reuse_histogram(Vec::new(), 64).is_err());
+}
+
+#[test]
+fn test_reuse_histogram_grows() {
+    // Enough accesses to grow the Fenwick tree several times
+    let accesses = (0..10_000u64).map(|i| Ok(MemoryAccess { operation: 'S', address: (i % 300) * 64, thread: 0, size: 8, pc: 0 }));
+    let histogram = reuse_histogram(accesses, 6).unwrap();
+    assert_eq!(histogram.cold, 300);
+    assert_eq!(histogram.counts.len(), 300);
+    assert_eq!(histogram.counts[299], 9_700);
+}
+
+#[test]
+fn test_predicted_misses_match_fully_associative_cache() {
+    // A skewed cache with a single set is a fully-associative LRU cache
+    let histogram = reuse_histogram_tracefile("../traces/trans.trace", 3).unwrap();
+    for lines in [1, 2, 4, 8, 16, 32] {
+        let mut cache = CacheBuilder::new().associativity(lines).block_bits(3).skewed(true).build().unwrap();
+        simulate_tracefile(&mut cache, "../traces/trans.trace").unwrap();
+        assert_eq!(histogram.predicted_misses(lines), cache.misses());
+        assert_eq!(histogram.accesses(), cache.hits() + cache.misses());
+    }
+}
+
+// Tests for ReuseHistogram::format
+#[test]
+fn test_format_reuse_histogram() {
+    let histogram = ReuseHistogram { block_bits: 4, counts: vec![5, 1, 2, 0, 3], cold: 4 };
+    assert_eq!(histogram.format(), "accesses:15 cold:4\n\
+        distance:0 count:5\n\
+        distance:1 count:1\n\
+        distance:2-3 count:2\n\
+        distance:4-7 count:3\n\
+        lines:1 predicted-misses:10 miss-rate:66.67%\n\
+        lines:2 predicted-misses:9 miss-rate:60.00%\n\
+        lines:4 predicted-misses:7 miss-rate:46.67%\n\
+        lines:8 predicted-misses:4 miss-rate:26.67%");
+}
@@ -0,0 +1,30 @@
+//! Deterministic random numbers for the simulator's random choices.
+
+use serde::{Deserialize, Serialize};
+
+use crate::cache::DEFAULT_SEED;
+
+// Small xorshift64* generator, so random runs are reproducible for a given seed
+#[derive(Serialize, Deserialize)]
+pub(crate) struct Rng {
+    pub(crate) state: u64,
+}
+
+impl Rng {
+    pub(crate) fn new(seed: u64) -> Rng {
+        // A zero state would make xorshift return zeros forever
+        Rng { state: if seed == 0 { DEFAULT_SEED } else { seed } }
+    }
+
+    pub(crate) fn next_u64(&mut self) -> u64 {
+        self.state ^= self.state >> 12;
+        self.state ^= self.state << 25;
+        self.state ^= self.state >> 27;
+        self.state.wrapping_mul(0x2545_f491_4f6c_dd1d)
+    }
+
+    // Random number in the range 0..bound
+    pub(crate) fn next_below(&mut self, bound: usize) -> usize {
+        (self.next_u64() % bound as u64) as usize
+    }
+}
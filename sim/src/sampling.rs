@@ -0,0 +1,114 @@
+//! Systematic sampling: simulating periodic windows of a trace and extrapolating to the whole of it.
+
+use crate::cache::Cache;
+use crate::error::SimError;
+use crate::trace::{trace_accesses, MemoryAccess};
+
+/// Windows of a trace to simulate: the first length accesses of every period accesses.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Sampling {
+    pub period: usize,
+    pub length: usize,
+}
+
+impl Sampling {
+    /// Check that the windows fit in the period.
+    pub fn validate(&self) -> Result<(), SimError> {
+        if self.length == 0 || self.length > self.period {
+            return Err(SimError::Config("sample length must be between 1 and the sample period".to_string()));
+        }
+        Ok(())
+    }
+
+    // Whether the access at a position of the trace falls in a window
+    fn samples(&self, position: usize) -> bool {
+        position % self.period < self.length
+    }
+}
+
+/// Accesses seen by a sampled run, to extrapolate its statistics to the whole trace.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SampleStats {
+    pub total_accesses: usize,
+    pub sampled_accesses: usize,
+}
+
+impl SampleStats {
+    /// Scale a count measured over the sampled accesses to the whole trace.
+    pub fn extrapolate(&self, count: usize) -> usize {
+        if self.sampled_accesses == 0 {
+            return 0;
+        }
+        (count as f64 * self.total_accesses as f64 / self.sampled_accesses as f64).round() as usize
+    }
+
+    /// Format the cache's statistics with extrapolated hits, misses and evictions, followed by the measured ones.
+    pub fn format_stats(&self, cache: &Cache) -> String {
+        let stats = cache.format_stats();
+        let details = stats.split_once('\n').map(|(_, details)| format!("\n{}", details)).unwrap_or_default();
+        let share = if self.total_accesses == 0 { 0.0 } else { 100.0 * self.sampled_accesses as f64 / self.total_accesses as f64 };
+        format!(
+            "hits:{} misses:{} evictions:{}\nsampling: sampled-accesses:{}/{} ({:.2}%) measured-hits:{} measured-misses:{} measured-evictions:{}{}",
+            self.extrapolate(cache.hits()), self.extrapolate(cache.misses()), self.extrapolate(cache.evictions()),
+            self.sampled_accesses, self.total_accesses, share, cache.hits(), cache.misses(), cache.evictions(), details
+        )
+    }
+}
+
+/// Simulate only the sampled windows of a tracefile, skipping the accesses between them.
+pub fn simulate_tracefile_sampled(cache: &mut Cache, filename: &str, sampling: Sampling) -> Result<SampleStats, SimError> {
+    simulate_accesses_sampled(cache, trace_accesses(filename)?, sampling)
+}
+
+/// Simulate only the sampled windows of a sequence of accesses, skipping the accesses between them.
+pub fn simulate_accesses_sampled<I>(cache: &mut Cache, accesses: I, sampling: Sampling) -> Result<SampleStats, SimError>
+where
+    I: IntoIterator<Item = Result<MemoryAccess, SimError>>,
+{
+    sampling.validate()?;
+    let mut sample_stats = SampleStats { total_accesses: 0, sampled_accesses: 0 };
+    for access in accesses {
+        let access = access?;
+        if sampling.samples(sample_stats.total_accesses) {
+            cache.simulate_access(access)?;
+            sample_stats.sampled_accesses += 1;
+        }
+        sample_stats.total_accesses += 1;
+    }
+    Ok(sample_stats)
+}
+
+// Tests for sampled simulation
+#[test]
+fn test_sampling_validate() {
+    assert!(Sampling { period: 10, length: 1 }.validate().is_ok());
+    assert!(Sampling { period: 10, length: 10 }.validate().is_ok());
+    assert!(Sampling { period: 10, length: 0 }.validate().is_err());
+    assert!(Sampling { period: 10, length: 11 }.validate().is_err());
+}
+
+#[test]
+fn test_simulate_tracefile_sampled() {
+    // yi.trace has 7 data accesses; windows of 2 in every 3 keep accesses 0, 1, 3, 4 and 6
+    let mut cache = Cache::new(4, 1, 4).unwrap();
+    let sample_stats = simulate_tracefile_sampled(&mut cache, "../traces/yi.trace", Sampling { period: 3, length: 2 }).unwrap();
+    assert_eq!(sample_stats, SampleStats { total_accesses: 7, sampled_accesses: 5 });
+    // L 10 miss, M 20 miss hit, S 18 hit, L 110 miss eviction, M 12 miss eviction hit
+    assert_eq!((cache.hits(), cache.misses(), cache.evictions()), (3, 4, 2));
+    assert_eq!(sample_stats.extrapolate(4), 6);
+    assert_eq!(
+        sample_stats.format_stats(&cache),
+        "hits:4 misses:6 evictions:3\nsampling: sampled-accesses:5/7 (71.43%) measured-hits:3 measured-misses:4 measured-evictions:2"
+    );
+
+    // Sampling everything matches a full run
+    let mut cache = Cache::new(4, 1, 4).unwrap();
+    let sample_stats = simulate_tracefile_sampled(&mut cache, "../traces/yi.trace", Sampling { period: 1, length: 1 }).unwrap();
+    assert_eq!(sample_stats.format_stats(&cache).lines().next(), Some("hits:4 misses:5 evictions:3"));
+}
+
+#[test]
+fn test_sample_stats_empty() {
+    let sample_stats = SampleStats { total_accesses: 0, sampled_accesses: 0 };
+    assert_eq!(sample_stats.extrapolate(5), 0);
+}
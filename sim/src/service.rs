@@ -0,0 +1,233 @@
+//! The simulator as a long-lived service: JSON-RPC 2.0 requests, one per line over TCP, create caches, stream
+//! accesses to them and query their statistics. Every connection shares the same caches, so several tools can
+//! use one warmed-up cache.
+//!
+//! Methods, with their params:
+//! * `create` `{"s": 4, "E": 2, "b": 4}` returns `{"cache": id}`, for caches of up to MAX_SERVICE_LINES lines
+//! * `access` `{"cache": id, "accesses": ["L 7ff0,4", "S 10,1"]}` returns the hits, misses and evictions they caused
+//! * `stats` `{"cache": id}` returns the statistics so far
+//! * `reset_stats` `{"cache": id}` clears the statistics, keeping the contents
+//! * `destroy` `{"cache": id}` releases the cache
+
+use std::collections::HashMap;
+use std::io::{BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use serde_json::{json, Value};
+
+use crate::cache::{Cache, CacheBuilder};
+use crate::error::SimError;
+use crate::trace::parse_memory_access;
+
+/// Address `sim serve` listens on unless another is given.
+pub const DEFAULT_SERVICE_ADDRESS: &str = "127.0.0.1:7878";
+
+/// Most lines a cache created by a client may have, so that one request can't exhaust the memory of the process
+/// holding every client's caches.
+pub const MAX_SERVICE_LINES: usize = 1 << 20;
+
+// JSON-RPC error codes
+const PARSE_ERROR: i64 = -32700;
+const INVALID_REQUEST: i64 = -32600;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+const SIMULATION_ERROR: i64 = -32000;
+
+/// Caches created by the clients of a service, by id.
+#[derive(Default)]
+pub struct Service {
+    caches: HashMap<u64, Cache>,
+    next_id: u64,
+}
+
+// A JSON-RPC error: code and message
+type RpcError = (i64, String);
+
+impl Service {
+    pub fn new() -> Service {
+        Service::default()
+    }
+
+    /// Answer one JSON-RPC request, or return None for a notification, which gets no response.
+    pub fn handle(&mut self, request: &str) -> Option<String> {
+        let request: Value = match serde_json::from_str(request) {
+            Ok(request) => request,
+            Err(err) => return Some(error_response(Value::Null, (PARSE_ERROR, format!("parse error ({})", err)))),
+        };
+        let id = request.get("id").cloned();
+        let result = match (request.get("jsonrpc").and_then(Value::as_str), request.get("method").and_then(Value::as_str)) {
+            (Some("2.0"), Some(method)) => self.call(method, request.get("params").unwrap_or(&Value::Null)),
+            _ => Err((INVALID_REQUEST, "invalid request".to_string())),
+        };
+        let id = id?;
+        Some(match result {
+            Ok(result) => json!({ "jsonrpc": "2.0", "result": result, "id": id }).to_string(),
+            Err(err) => error_response(id, err),
+        })
+    }
+
+    // Run a method
+    fn call(&mut self, method: &str, params: &Value) -> Result<Value, RpcError> {
+        match method {
+            "create" => {
+                let (s, e) = (usize_param(params, "s")?, usize_param(params, "E")?);
+                let lines = u32::try_from(s).ok().and_then(|s| 1usize.checked_shl(s)).and_then(|sets| sets.checked_mul(e));
+                if lines.is_none_or(|lines| lines > MAX_SERVICE_LINES) {
+                    return Err((INVALID_PARAMS, format!("caches are limited to {} lines", MAX_SERVICE_LINES)));
+                }
+                let builder = CacheBuilder::new().set_index_bits(s).associativity(e).block_bits(usize_param(params, "b")?);
+                let cache = builder.build().map_err(|err| (INVALID_PARAMS, err.to_string()))?;
+                self.next_id += 1;
+                self.caches.insert(self.next_id, cache);
+                Ok(json!({ "cache": self.next_id }))
+            }
+            "access" => {
+                let lines = params.get("accesses").and_then(Value::as_array).ok_or((INVALID_PARAMS, "missing accesses".to_string()))?;
+                let cache = self.cache(params)?;
+                let (hits, misses, evictions) = (cache.hits(), cache.misses(), cache.evictions());
+                for (index, line) in lines.iter().enumerate() {
+                    let line = line.as_str().ok_or((INVALID_PARAMS, format!("access {} isn't a string", index)))?;
+                    let access = parse_memory_access(line).map_err(|err| (INVALID_PARAMS, format!("access {}: {}", index, err)))?;
+                    if let Some(access) = access {
+                        cache.simulate_access(access).map_err(|err| (SIMULATION_ERROR, format!("access {}: {}", index, err)))?;
+                    }
+                }
+                Ok(json!({ "hits": cache.hits() - hits, "misses": cache.misses() - misses, "evictions": cache.evictions() - evictions }))
+            }
+            "stats" => serde_json::to_value(self.cache(params)?.snapshot_stats()).map_err(|err| (SIMULATION_ERROR, err.to_string())),
+            "reset_stats" => {
+                self.cache(params)?.reset_stats();
+                Ok(Value::Null)
+            }
+            "destroy" => {
+                let id = cache_id(params)?;
+                self.caches.remove(&id).ok_or((INVALID_PARAMS, format!("no cache {}", id)))?;
+                Ok(Value::Null)
+            }
+            _ => Err((METHOD_NOT_FOUND, format!("unknown method: {}", method))),
+        }
+    }
+
+    // The cache named by the params
+    fn cache(&mut self, params: &Value) -> Result<&mut Cache, RpcError> {
+        let id = cache_id(params)?;
+        self.caches.get_mut(&id).ok_or((INVALID_PARAMS, format!("no cache {}", id)))
+    }
+}
+
+// A non-negative integer parameter
+fn usize_param(params: &Value, name: &str) -> Result<usize, RpcError> {
+    params.get(name).and_then(Value::as_u64).map(|value| value as usize).ok_or((INVALID_PARAMS, format!("missing or invalid {}", name)))
+}
+
+fn cache_id(params: &Value) -> Result<u64, RpcError> {
+    params.get("cache").and_then(Value::as_u64).ok_or((INVALID_PARAMS, "missing or invalid cache".to_string()))
+}
+
+fn error_response(id: Value, (code, message): RpcError) -> String {
+    json!({ "jsonrpc": "2.0", "error": { "code": code, "message": message }, "id": id }).to_string()
+}
+
+// Answer the requests of one connection until it closes
+fn serve_connection(stream: TcpStream, service: &Mutex<Service>) -> std::io::Result<()> {
+    let mut writer = &stream;
+    for line in BufReader::new(&stream).lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let response = service.lock().unwrap_or_else(|poisoned| poisoned.into_inner()).handle(&line);
+        if let Some(response) = response {
+            writeln!(writer, "{}", response)?;
+        }
+    }
+    Ok(())
+}
+
+/// Serve requests from a listener, one thread per connection, until the process is interrupted.
+pub fn serve(listener: TcpListener) {
+    let service = Arc::new(Mutex::new(Service::new()));
+    for stream in listener.incoming().flatten() {
+        let service = Arc::clone(&service);
+        thread::spawn(move || {
+            // A connection that fails only ends itself
+            let _ = serve_connection(stream, &service);
+        });
+    }
+}
+
+/// Listen on an address such as 127.0.0.1:7878.
+pub fn listen(address: &str) -> Result<TcpListener, SimError> {
+    TcpListener::bind(address).map_err(|err| SimError::Io(format!("failed to listen on {} ({})", address, err)))
+}
+
+// Tests for Service
+#[test]
+fn test_service() {
+    let mut service = Service::new();
+    let create = r#"{"jsonrpc": "2.0", "method": "create", "params": {"s": 4, "E": 1, "b": 4}, "id": 1}"#;
+    assert_eq!(service.handle(create), Some(r#"{"id":1,"jsonrpc":"2.0","result":{"cache":1}}"#.to_string()));
+    let access = r#"{"jsonrpc": "2.0", "method": "access", "params": {"cache": 1, "accesses": ["L 10,1", "M 0x20,1", "I 400,4"]}, "id": 2}"#;
+    assert_eq!(service.handle(access), Some(r#"{"id":2,"jsonrpc":"2.0","result":{"evictions":0,"hits":1,"misses":2}}"#.to_string()));
+    let access = r#"{"jsonrpc": "2.0", "method": "access", "params": {"cache": 1, "accesses": ["L 110,1"]}, "id": 3}"#;
+    assert_eq!(service.handle(access), Some(r#"{"id":3,"jsonrpc":"2.0","result":{"evictions":1,"hits":0,"misses":1}}"#.to_string()));
+
+    let stats: Value = serde_json::from_str(&service.handle(r#"{"jsonrpc": "2.0", "method": "stats", "params": {"cache": 1}, "id": 4}"#).unwrap()).unwrap();
+    assert_eq!((stats["result"]["hits"].as_u64(), stats["result"]["misses"].as_u64()), (Some(1), Some(3)));
+    // Notifications get no response
+    assert_eq!(service.handle(r#"{"jsonrpc": "2.0", "method": "reset_stats", "params": {"cache": 1}}"#), None);
+    let stats: Value = serde_json::from_str(&service.handle(r#"{"jsonrpc": "2.0", "method": "stats", "params": {"cache": 1}, "id": 5}"#).unwrap()).unwrap();
+    assert_eq!(stats["result"]["misses"], 0);
+
+    assert_eq!(service.handle(r#"{"jsonrpc": "2.0", "method": "destroy", "params": {"cache": 1}, "id": 6}"#), Some(r#"{"id":6,"jsonrpc":"2.0","result":null}"#.to_string()));
+    assert_eq!(
+        service.handle(r#"{"jsonrpc": "2.0", "method": "stats", "params": {"cache": 1}, "id": 7}"#),
+        Some(r#"{"error":{"code":-32602,"message":"no cache 1"},"id":7,"jsonrpc":"2.0"}"#.to_string())
+    );
+}
+
+#[test]
+fn test_service_errors() {
+    let mut service = Service::new();
+    let code = |response: Option<String>| serde_json::from_str::<Value>(&response.unwrap()).unwrap()["error"]["code"].as_i64();
+    assert_eq!(code(service.handle("{")), Some(PARSE_ERROR));
+    assert_eq!(code(service.handle(r#"{"method": "stats", "id": 1}"#)), Some(INVALID_REQUEST));
+    assert_eq!(code(service.handle(r#"{"jsonrpc": "2.0", "method": "flush", "id": 1}"#)), Some(METHOD_NOT_FOUND));
+    assert_eq!(code(service.handle(r#"{"jsonrpc": "2.0", "method": "create", "params": {"s": 4, "E": 0, "b": 4}, "id": 1}"#)), Some(INVALID_PARAMS));
+    assert_eq!(code(service.handle(r#"{"jsonrpc": "2.0", "method": "create", "params": {"s": 4}, "id": 1}"#)), Some(INVALID_PARAMS));
+    // Caches too large to allocate are refused rather than aborting the process
+    for params in [r#"{"s": 40, "E": 1, "b": 4}"#, r#"{"s": 10, "E": 2048, "b": 4}"#, r#"{"s": 64, "E": 1, "b": 0}"#, r#"{"s": 4, "E": 18446744073709551615, "b": 4}"#] {
+        let request = format!(r#"{{"jsonrpc": "2.0", "method": "create", "params": {}, "id": 1}}"#, params);
+        assert_eq!(code(service.handle(&request)), Some(INVALID_PARAMS));
+    }
+    service.handle(r#"{"jsonrpc": "2.0", "method": "create", "params": {"s": 4, "E": 1, "b": 4}, "id": 1}"#);
+    let response = service.handle(r#"{"jsonrpc": "2.0", "method": "access", "params": {"cache": 1, "accesses": ["L 10,1", "X 10,1"]}, "id": 1}"#);
+    assert_eq!(
+        response,
+        Some(r#"{"error":{"code":-32602,"message":"access 1: invalid operation encountered"},"id":1,"jsonrpc":"2.0"}"#.to_string())
+    );
+}
+
+// Tests for serve function
+#[test]
+fn test_serve() {
+    let listener = listen("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap();
+    thread::spawn(move || serve(listener));
+
+    // Two connections share the caches
+    let mut first = TcpStream::connect(address).unwrap();
+    writeln!(first, r#"{{"jsonrpc": "2.0", "method": "create", "params": {{"s": 2, "E": 2, "b": 4}}, "id": 1}}"#).unwrap();
+    let mut responses = BufReader::new(first.try_clone().unwrap()).lines();
+    assert_eq!(responses.next().unwrap().unwrap(), r#"{"id":1,"jsonrpc":"2.0","result":{"cache":1}}"#);
+    writeln!(first, r#"{{"jsonrpc": "2.0", "method": "access", "params": {{"cache": 1, "accesses": ["L 10,1"]}}, "id": 2}}"#).unwrap();
+    assert!(responses.next().unwrap().unwrap().contains(r#""misses":1"#));
+
+    let mut second = TcpStream::connect(address).unwrap();
+    writeln!(second, r#"{{"jsonrpc": "2.0", "method": "access", "params": {{"cache": 1, "accesses": ["L 10,1"]}}, "id": 1}}"#).unwrap();
+    let response = BufReader::new(second).lines().next().unwrap().unwrap();
+    assert_eq!(response, r#"{"id":1,"jsonrpc":"2.0","result":{"evictions":0,"hits":1,"misses":0}}"#);
+    assert!(listen(&address.to_string()).is_err());
+}
@@ -0,0 +1,238 @@
+//! False-sharing detection: blocks whose copies in the private caches of other threads a write invalidates although
+//! those threads only touched other bytes of the block.
+//!
+//! Each thread of a trace is taken to run on a core of its own with a private cache large enough to keep every block,
+//! so the only misses between threads are the coherence ones: a write invalidates the copies of every other thread
+//! holding the block. The invalidation is true sharing if the other thread accessed a byte the write covers since it
+//! got its copy, and false sharing if their bytes are disjoint, the traffic a different data layout would avoid.
+
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+
+use crate::error::SimError;
+use crate::trace::{trace_accesses, MemoryAccess};
+
+/// Blocks listed by default when reporting false sharing.
+pub const DEFAULT_SHARING_TOP: usize = 10;
+
+// Parts of a block tracked per copy: every byte of blocks up to 64 bytes, and 64 equal parts of larger blocks
+const MASK_BITS: usize = 64;
+
+/// Invalidations of one block.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct BlockSharing {
+    pub address: u64, // First byte of the block
+    pub true_sharing: usize,
+    pub false_sharing: usize,
+    pub threads: BTreeSet<usize>, // Threads whose writes or copies took part in false sharing
+}
+
+// Copies of a block in the private caches of the threads, with the parts of the block each thread accessed since it
+// got its copy
+#[derive(Default)]
+struct BlockCopies {
+    copies: BTreeMap<usize, u64>,
+    sharing: BlockSharing,
+}
+
+/// Invalidations of a trace's blocks, by kind.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SharingReport {
+    pub block_bits: usize,
+    pub writes: usize, // Writes looked at, modifies and atomic accesses counting once
+    pub blocks: Vec<BlockSharing>, // Blocks invalidated at least once, by address
+}
+
+impl SharingReport {
+    /// Invalidations where the other thread had accessed a byte of the write.
+    pub fn true_sharing(&self) -> usize {
+        self.blocks.iter().map(|block| block.true_sharing).sum()
+    }
+
+    /// Invalidations where the other thread had only accessed other bytes of the block.
+    pub fn false_sharing(&self) -> usize {
+        self.blocks.iter().map(|block| block.false_sharing).sum()
+    }
+
+    /// The n blocks with the most false-sharing invalidations, most first and by address among equals.
+    pub fn top_blocks(&self, n: usize) -> Vec<&BlockSharing> {
+        let mut blocks: Vec<&BlockSharing> = self.blocks.iter().filter(|block| block.false_sharing > 0).collect();
+        blocks.sort_by_key(|block| (std::cmp::Reverse(block.false_sharing), block.address));
+        blocks.truncate(n);
+        blocks
+    }
+
+    /// Format the totals, followed by the n blocks with the most false sharing, one per line.
+    pub fn format(&self, n: usize) -> String {
+        let (true_sharing, false_sharing) = (self.true_sharing(), self.false_sharing());
+        let invalidations = true_sharing + false_sharing;
+        let false_rate = if invalidations == 0 { 0.0 } else { 100.0 * false_sharing as f64 / invalidations as f64 };
+        let mut lines = vec![format!(
+            "writes:{} invalidations:{} true-sharing:{} false-sharing:{} false-sharing-rate:{:.2}%",
+            self.writes, invalidations, true_sharing, false_sharing, false_rate
+        )];
+        for block in self.top_blocks(n) {
+            let threads: Vec<String> = block.threads.iter().map(|thread| thread.to_string()).collect();
+            lines.push(format!(
+                "block:{:#x} false-sharing:{} true-sharing:{} threads:{}",
+                block.address, block.false_sharing, block.true_sharing, threads.join(",")
+            ));
+        }
+        lines.join("\n")
+    }
+}
+
+// Parts of a block of 2^block_bits bytes covered by the bytes first..=last of it
+fn byte_mask(block_bits: usize, first: u64, last: u64) -> u64 {
+    let part_bits = block_bits.saturating_sub(MASK_BITS.trailing_zeros() as usize);
+    let (first, last) = (first >> part_bits, last >> part_bits);
+    let upper = if last >= 63 { u64::MAX } else { (1 << (last + 1)) - 1 };
+    upper & !((1 << first) - 1)
+}
+
+/// Detect the false sharing of a sequence of accesses with 2^block_bits-byte blocks. Accesses that straddle a block
+/// boundary touch every block they cover; prefetches are skipped, as they bring in no copy a write must invalidate,
+/// and so are pins. Flushes drop the copies of their block, or of every block.
+pub fn detect_false_sharing<I>(accesses: I, block_bits: usize) -> Result<SharingReport, SimError>
+where
+    I: IntoIterator<Item = Result<MemoryAccess, SimError>>,
+{
+    if block_bits >= 64 {
+        return Err(SimError::Config("block bits must be below 64".to_string()));
+    }
+    let mut blocks: HashMap<u64, BlockCopies> = HashMap::new();
+    let mut writes = 0;
+    for access in accesses {
+        let access = access?;
+        match access.operation {
+            'P' | 'K' | 'U' => continue,
+            // Flushes empty the private caches of the block, or of everything, without invalidating a copy by a write
+            'F' => {
+                if let Some(entry) = blocks.get_mut(&(access.address >> block_bits)) {
+                    entry.copies.clear();
+                }
+                continue;
+            }
+            'W' => {
+                blocks.values_mut().for_each(|entry| entry.copies.clear());
+                continue;
+            }
+            _ => {}
+        }
+        let write = matches!(access.operation, 'S' | 'M' | 'A');
+        if write {
+            writes += 1;
+        }
+        let offset_mask = (1u64 << block_bits) - 1;
+        let end = access.address.saturating_add(access.size.max(1) as u64 - 1);
+        for block in (access.address >> block_bits)..=(end >> block_bits) {
+            let first = if block == access.address >> block_bits { access.address & offset_mask } else { 0 };
+            let last = if block == end >> block_bits { end & offset_mask } else { offset_mask };
+            let mask = byte_mask(block_bits, first, last);
+            let entry = blocks.entry(block).or_default();
+            if write {
+                let others: Vec<(usize, u64)> = entry.copies.iter().filter(|&(&thread, _)| thread != access.thread).map(|(&thread, &mask)| (thread, mask)).collect();
+                for (thread, accessed) in others {
+                    if accessed & mask == 0 {
+                        entry.sharing.false_sharing += 1;
+                        entry.sharing.threads.extend([thread, access.thread]);
+                    } else {
+                        entry.sharing.true_sharing += 1;
+                    }
+                    entry.copies.remove(&thread);
+                }
+            }
+            *entry.copies.entry(access.thread).or_default() |= mask;
+        }
+    }
+    let mut blocks: Vec<BlockSharing> = blocks.into_iter()
+        .filter(|(_, entry)| entry.sharing.true_sharing + entry.sharing.false_sharing > 0)
+        .map(|(block, entry)| BlockSharing { address: block << block_bits, ..entry.sharing })
+        .collect();
+    blocks.sort_by_key(|block| block.address);
+    Ok(SharingReport { block_bits, writes, blocks })
+}
+
+/// Detect the false sharing of a tracefile.
+pub fn detect_false_sharing_tracefile(filename: &str, block_bits: usize) -> Result<SharingReport, SimError> {
+    detect_false_sharing(trace_accesses(filename)?, block_bits)
+}
+
+#[cfg(test)]
+fn access(operation: char, address: u64, thread: usize, size: u32) -> Result<MemoryAccess, SimError> {
+    Ok(MemoryAccess { operation, address, thread, size, pc: 0 })
+}
+
+// Tests for byte_mask function
+#[test]
+fn test_byte_mask() {
+    assert_eq!(byte_mask(4, 0, 3), 0xf);
+    assert_eq!(byte_mask(4, 8, 15), 0xff00);
+    assert_eq!(byte_mask(6, 0, 63), u64::MAX);
+    // 128-byte blocks track pairs of bytes
+    assert_eq!(byte_mask(7, 2, 5), 0b110);
+    assert_eq!(byte_mask(0, 0, 0), 1);
+}
+
+// Tests for detect_false_sharing function
+#[test]
+fn test_detect_false_sharing() {
+    // Two threads updating their own counter in the same 64-byte block ping-pong it
+    let accesses = vec![
+        access('L', 0x100, 0, 8),
+        access('L', 0x108, 1, 8),
+        access('S', 0x100, 0, 8),
+        access('S', 0x108, 1, 8),
+        access('M', 0x100, 0, 8),
+    ];
+    let report = detect_false_sharing(accesses, 6).unwrap();
+    assert_eq!((report.writes, report.true_sharing(), report.false_sharing()), (3, 0, 3));
+    assert_eq!(report.blocks, vec![BlockSharing { address: 0x100, true_sharing: 0, false_sharing: 3, threads: BTreeSet::from([0, 1]) }]);
+
+    // Threads reading a byte the write covers share it truly, and a thread's own writes invalidate nothing
+    let accesses = vec![
+        access('L', 0x0, 1, 4),
+        access('L', 0x0, 2, 1),
+        access('S', 0x2, 0, 4),
+        access('S', 0x2, 0, 4),
+        access('A', 0x40, 0, 4),
+    ];
+    let report = detect_false_sharing(accesses, 6).unwrap();
+    assert_eq!((report.true_sharing(), report.false_sharing()), (1, 1));
+    assert_eq!(report.blocks[0].threads, BTreeSet::from([0, 2]));
+}
+
+#[test]
+fn test_detect_false_sharing_spanning() {
+    // A store straddling two blocks invalidates the copies of both
+    let accesses = vec![access('L', 0x38, 1, 8), access('L', 0x48, 1, 8), access('S', 0x3c, 0, 8), access('P', 0x0, 1, 1)];
+    let report = detect_false_sharing(accesses, 6).unwrap();
+    assert_eq!(report.blocks.iter().map(|block| (block.address, block.true_sharing, block.false_sharing)).collect::<Vec<_>>(), vec![(0x0, 1, 0), (0x40, 0, 1)]);
+    assert!(detect_false_sharing(Vec::new(), 64).is_err());
+}
+
+#[test]
+fn test_detect_false_sharing_flush() {
+    // Once flushed, the other threads' copies are gone before the store
+    let accesses = vec![access('L', 0x0, 1, 4), access('F', 0x0, 1, 0), access('S', 0x8, 0, 4), access('L', 0x4, 2, 4), access('W', 0x0, 2, 0), access('S', 0x8, 0, 4)];
+    let report = detect_false_sharing(accesses, 6).unwrap();
+    assert_eq!((report.writes, report.true_sharing(), report.false_sharing()), (2, 0, 0));
+}
+
+#[test]
+fn test_detect_false_sharing_single_thread() {
+    let report = detect_false_sharing_tracefile("../traces/trans.trace", 4).unwrap();
+    assert!(report.writes > 0);
+    assert!(report.blocks.is_empty());
+}
+
+// Tests for SharingReport::format
+#[test]
+fn test_format_sharing_report() {
+    let block = |address, false_sharing| BlockSharing { address, true_sharing: 1, false_sharing, threads: BTreeSet::from([0, 3]) };
+    let report = SharingReport { block_bits: 6, writes: 12, blocks: vec![block(0x0, 0), block(0x40, 2), block(0x80, 5), block(0xc0, 2)] };
+    assert_eq!(report.format(2), "writes:12 invalidations:13 true-sharing:4 false-sharing:9 false-sharing-rate:69.23%\n\
+        block:0x80 false-sharing:5 true-sharing:1 threads:0,3\n\
+        block:0x40 false-sharing:2 true-sharing:1 threads:0,3");
+    let empty = SharingReport { block_bits: 6, writes: 0, blocks: Vec::new() };
+    assert_eq!(empty.format(10), "writes:0 invalidations:0 true-sharing:0 false-sharing:0 false-sharing-rate:0.00%");
+}
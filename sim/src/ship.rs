@@ -0,0 +1,189 @@
+//! Signature-based hit prediction (SHiP): a table of saturating counters, indexed by a signature of the instruction
+//! or memory region behind a fill, learns which signatures fill lines that are hit again before being evicted.
+//!
+//! A line remembers the signature it was filled with. A hit on it raises the signature's counter, and its eviction
+//! without a hit lowers it, so a counter at zero predicts that the next line the signature fills won't be reused. An
+//! insertion policy can then fill those lines where they are evicted first.
+
+use serde::{Deserialize, Serialize};
+
+/// Bits of the signatures, the table holding one counter per signature.
+pub const SHIP_SIGNATURE_BITS: u32 = 14;
+
+/// Highest value of the 3-bit counters of the table.
+pub const SHIP_COUNTER_MAX: u8 = 7;
+
+/// Bits of the memory regions whose addresses share a signature when signatures come from addresses: 16 KiB regions.
+pub const SHIP_REGION_BITS: u32 = 14;
+
+/// Fold the bits of a program counter or region number onto a signature of up to 16 bits, XORing its bits-wide
+/// chunks together.
+pub fn fold_signature(key: u64, bits: u32) -> u16 {
+    let mut folded = 0;
+    let mut key = key;
+    while key != 0 {
+        folded ^= key;
+        key = key.checked_shr(bits).unwrap_or(0);
+    }
+    (folded & ((1 << bits) - 1)) as u16
+}
+
+/// Counters of some signatures and the fill counts of a signature table, saved so that the accesses training them
+/// can be undone.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct SignatureTableUndo {
+    counters: Vec<(u16, u8)>,
+    fills: [usize; 2], // Fills and distant fills
+}
+
+/// Signature history counter table, with the fills it predicted.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SignatureTable {
+    counters: Vec<u8>,
+    fills: usize,
+    distant_fills: usize, // Fills whose signature's counter was zero, predicted not to be reused
+}
+
+impl SignatureTable {
+    /// Table of 2^signature_bits counters, each starting halfway so that a signature only predicts no reuse once more
+    /// lines it filled were evicted unused than were hit.
+    pub fn new(signature_bits: u32) -> SignatureTable {
+        SignatureTable { counters: vec![SHIP_COUNTER_MAX / 2 + 1; 1 << signature_bits], fills: 0, distant_fills: 0 }
+    }
+
+    /// Signature of a program counter or region number, folding its bits onto the width of the table.
+    pub fn signature(&self, key: u64) -> u16 {
+        fold_signature(key, self.counters.len().trailing_zeros())
+    }
+
+    /// Whether lines filled by a signature are predicted to be reused.
+    pub fn predicts_reuse(&self, signature: u16) -> bool {
+        self.counters[usize::from(signature)] != 0
+    }
+
+    /// Count a fill by a signature, returning whether it is predicted to be reused.
+    pub fn record_fill(&mut self, signature: u16) -> bool {
+        let reuse = self.predicts_reuse(signature);
+        self.fills += 1;
+        if !reuse {
+            self.distant_fills += 1;
+        }
+        reuse
+    }
+
+    /// Train on a hit on a line the signature filled.
+    pub fn record_hit(&mut self, signature: u16) {
+        let counter = &mut self.counters[usize::from(signature)];
+        *counter = (*counter + 1).min(SHIP_COUNTER_MAX);
+    }
+
+    /// Train on the eviction of a line the signature filled that was never hit.
+    pub fn record_dead_eviction(&mut self, signature: u16) {
+        let counter = &mut self.counters[usize::from(signature)];
+        *counter = counter.saturating_sub(1);
+    }
+
+    /// Number of signatures in the table.
+    pub fn signatures(&self) -> usize {
+        self.counters.len()
+    }
+
+    /// Number of signatures currently predicting no reuse.
+    pub fn dead_signatures(&self) -> usize {
+        self.counters.iter().filter(|&&counter| counter == 0).count()
+    }
+
+    /// Fills counted so far.
+    pub fn fills(&self) -> usize {
+        self.fills
+    }
+
+    /// Fills counted so far that were predicted not to be reused.
+    pub fn distant_fills(&self) -> usize {
+        self.distant_fills
+    }
+
+    /// Zero the fill counts, keeping what the table learnt.
+    pub fn reset_stats(&mut self) {
+        (self.fills, self.distant_fills) = (0, 0);
+    }
+
+    /// Save the counters of some signatures and the fill counts, for [`SignatureTable::restore`].
+    pub(crate) fn save(&self, signatures: &[u16]) -> SignatureTableUndo {
+        let mut signatures = signatures.to_vec();
+        signatures.sort();
+        signatures.dedup();
+        SignatureTableUndo {
+            counters: signatures.into_iter().map(|signature| (signature, self.counters[usize::from(signature)])).collect(),
+            fills: [self.fills, self.distant_fills],
+        }
+    }
+
+    /// Put back the state saved by [`SignatureTable::save`], undoing the training of its signatures since.
+    pub(crate) fn restore(&mut self, undo: SignatureTableUndo) {
+        for (signature, counter) in undo.counters {
+            self.counters[usize::from(signature)] = counter;
+        }
+        [self.fills, self.distant_fills] = undo.fills;
+    }
+}
+
+// Tests for SignatureTable::signature
+#[test]
+fn test_signature_table_signature() {
+    let table = SignatureTable::new(SHIP_SIGNATURE_BITS);
+    assert_eq!(table.signatures(), 1 << 14);
+    assert_eq!(table.signature(0x1234), 0x1234);
+    // Higher bits fold onto the low ones
+    assert_eq!(table.signature(0x4000), 1);
+    assert_eq!(table.signature(0x400a10), 0xa10 ^ 0x100);
+    assert_eq!(table.signature(u64::MAX), 0xff);
+    assert_eq!(SignatureTable::new(4).signature(0xab), 0x1);
+    assert_eq!((fold_signature(0x400a10, 11), fold_signature(0, 11)), (0x210, 0));
+}
+
+// Tests for SignatureTable::record_fill
+#[test]
+fn test_signature_table_training() {
+    let mut table = SignatureTable::new(4);
+    assert!(table.record_fill(3));
+    // Signatures start halfway, predicting no reuse after four dead evictions, and one hit brings them back
+    for _ in 0..4 {
+        table.record_dead_eviction(3);
+    }
+    assert_eq!((table.predicts_reuse(3), table.dead_signatures()), (false, 1));
+    assert!(!table.record_fill(3));
+    table.record_dead_eviction(3);
+    table.record_hit(3);
+    assert!(table.record_fill(3));
+    assert_eq!((table.fills(), table.distant_fills()), (3, 1));
+
+    // Counters saturate
+    for _ in 0..10 {
+        table.record_hit(5);
+    }
+    for _ in 0..SHIP_COUNTER_MAX - 1 {
+        table.record_dead_eviction(5);
+    }
+    assert!(table.predicts_reuse(5));
+    table.record_dead_eviction(5);
+    assert!(!table.predicts_reuse(5));
+
+    table.reset_stats();
+    assert_eq!((table.fills(), table.distant_fills(), table.dead_signatures()), (0, 0, 1));
+}
+
+// Tests for SignatureTable::save and SignatureTable::restore
+#[test]
+fn test_signature_table_restore() {
+    let mut table = SignatureTable::new(4);
+    table.record_hit(3);
+    let undo = table.save(&[3, 5, 3]);
+    let saved = table.clone();
+    table.record_hit(3);
+    table.record_dead_eviction(5);
+    table.record_fill(5);
+    assert_ne!(table, saved);
+    table.restore(undo);
+    assert_eq!(table, saved);
+}
@@ -0,0 +1,135 @@
+//! A snoop filter in front of the private caches of the cores: a directory duplicating the tags of the blocks each
+//! core's private levels hold, so that a request only snoops the cores that may have the block instead of all of
+//! them.
+//!
+//! A core's access that misses its private levels, or writes, is a request a snooping protocol broadcasts to every
+//! other core. The filter counts those snoops and the ones it lets through, to the cores its directory lists for the
+//! block. The directory follows the private levels' fills and evictions. As the caches aren't kept coherent, snoops
+//! don't invalidate anything: they are only counted.
+
+use std::collections::{BTreeSet, HashMap};
+
+/// The directory of a snoop filter and the snoops it saw.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct SnoopFilter {
+    cores: usize,
+    directory: HashMap<u64, BTreeSet<usize>>, // Cores whose private levels hold each block
+    requests: usize,
+    snoops: usize, // Snoops sent to the cores holding the block
+    peak_entries: usize,
+}
+
+impl SnoopFilter {
+    /// An empty filter for the given number of cores.
+    pub fn new(cores: usize) -> SnoopFilter {
+        SnoopFilter { cores, ..Default::default() }
+    }
+
+    /// Count a request of a core for a block, returning the other cores it snoops.
+    pub fn snoop(&mut self, block: u64, core: usize) -> usize {
+        let snoops = self.directory.get(&block).map_or(0, |holders| holders.iter().filter(|&&holder| holder != core).count());
+        self.requests += 1;
+        self.snoops += snoops;
+        snoops
+    }
+
+    /// Record that a core's private levels hold a block.
+    pub fn fill(&mut self, block: u64, core: usize) {
+        self.directory.entry(block).or_default().insert(core);
+        self.peak_entries = self.peak_entries.max(self.directory.len());
+    }
+
+    /// Record that a core's private levels no longer hold a block.
+    pub fn evict(&mut self, block: u64, core: usize) {
+        if let Some(holders) = self.directory.get_mut(&block) {
+            holders.remove(&core);
+            if holders.is_empty() {
+                self.directory.remove(&block);
+            }
+        }
+    }
+
+    /// Record that the private levels of every core are empty.
+    pub fn clear(&mut self) {
+        self.directory.clear();
+    }
+
+    /// Cores whose private levels hold a block, by the directory.
+    pub fn holders(&self, block: u64) -> Vec<usize> {
+        self.directory.get(&block).map_or_else(Vec::new, |holders| holders.iter().copied().collect())
+    }
+
+    /// Blocks the directory tracks.
+    pub fn entries(&self) -> usize {
+        self.directory.len()
+    }
+
+    pub fn requests(&self) -> usize {
+        self.requests
+    }
+
+    /// Snoops the requests sent through the filter.
+    pub fn snoops(&self) -> usize {
+        self.snoops
+    }
+
+    /// Snoops the requests would have broadcast to every other core without the filter.
+    pub fn broadcast_snoops(&self) -> usize {
+        self.requests * self.cores.saturating_sub(1)
+    }
+
+    /// Snoops the filter spared.
+    pub fn filtered(&self) -> usize {
+        self.broadcast_snoops() - self.snoops
+    }
+
+    /// Clear the counts, keeping the directory.
+    pub fn reset_stats(&mut self) {
+        self.requests = 0;
+        self.snoops = 0;
+        self.peak_entries = self.directory.len();
+    }
+
+    pub fn format_stats(&self) -> String {
+        let broadcast = self.broadcast_snoops();
+        let filter_rate = if broadcast == 0 { 0.0 } else { 100.0 * self.filtered() as f64 / broadcast as f64 };
+        format!(
+            "requests:{} snoops:{} filtered:{} filter-rate:{:.2}% entries:{} peak-entries:{}",
+            self.requests, self.snoops, self.filtered(), filter_rate, self.entries(), self.peak_entries
+        )
+    }
+}
+
+// Tests for SnoopFilter
+#[test]
+fn test_snoop_filter() {
+    let mut filter = SnoopFilter::new(4);
+    // Nobody holds the block yet: the request snoops no core instead of three
+    assert_eq!(filter.snoop(0x10, 0), 0);
+    filter.fill(0x10, 0);
+    filter.fill(0x10, 2);
+    filter.fill(0x20, 1);
+    assert_eq!(filter.holders(0x10), vec![0, 2]);
+    // A core's own copy isn't snooped
+    assert_eq!(filter.snoop(0x10, 2), 1);
+    assert_eq!(filter.snoop(0x10, 3), 2);
+    assert_eq!((filter.requests(), filter.snoops(), filter.broadcast_snoops(), filter.filtered()), (3, 3, 9, 6));
+    assert_eq!(filter.format_stats(), "requests:3 snoops:3 filtered:6 filter-rate:66.67% entries:2 peak-entries:2");
+
+    filter.evict(0x10, 0);
+    filter.evict(0x20, 1);
+    filter.evict(0x30, 1);
+    assert_eq!((filter.holders(0x10), filter.entries()), (vec![2], 1));
+    filter.reset_stats();
+    assert_eq!(filter.format_stats(), "requests:0 snoops:0 filtered:0 filter-rate:0.00% entries:1 peak-entries:1");
+    filter.clear();
+    assert_eq!((filter.holders(0x10), filter.entries()), (vec![], 0));
+}
+
+#[test]
+fn test_snoop_filter_single_core() {
+    let mut filter = SnoopFilter::new(1);
+    filter.fill(0x10, 0);
+    assert_eq!(filter.snoop(0x10, 0), 0);
+    assert_eq!((filter.broadcast_snoops(), filter.filtered()), (0, 0));
+}
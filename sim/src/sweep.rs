@@ -0,0 +1,114 @@
+//! Simulating several cache configurations in one pass over a trace.
+
+use rayon::prelude::*;
+
+use crate::cache::{Cache, CacheBuilder};
+use crate::error::SimError;
+use crate::trace::{trace_accesses, MemoryAccess};
+
+// Accesses read at a time and shared by the configurations of a sweep
+const SWEEP_CHUNK: usize = 1 << 16;
+
+/// Simulate every configuration over one read of a tracefile, resetting the statistics after the first warmup
+/// accesses, and return the caches in the order of the builders. The trace is read in chunks, and the
+/// configurations simulate each chunk in parallel.
+pub fn sweep_tracefile(builders: &[CacheBuilder], filename: &str, warmup: usize) -> Result<Vec<Cache>, SimError> {
+    sweep_accesses(builders, trace_accesses(filename)?, warmup)
+}
+
+/// Simulate every configuration over one pass of a sequence of accesses, as [`sweep_tracefile`] does for a file.
+pub fn sweep_accesses<I>(builders: &[CacheBuilder], accesses: I, warmup: usize) -> Result<Vec<Cache>, SimError>
+where
+    I: IntoIterator<Item = Result<MemoryAccess, SimError>>,
+{
+    let mut caches = builders.iter().map(CacheBuilder::build).collect::<Result<Vec<Cache>, SimError>>()?;
+    let mut accesses = accesses.into_iter();
+    let mut position = 0;
+    loop {
+        // Chunks end where the warm-up does, so the statistics can be reset between them
+        let chunk_size = if position < warmup { (warmup - position).min(SWEEP_CHUNK) } else { SWEEP_CHUNK };
+        let chunk = accesses.by_ref().take(chunk_size).collect::<Result<Vec<MemoryAccess>, SimError>>()?;
+        caches.par_iter_mut().try_for_each(|cache| {
+            for result in cache.drive(chunk.iter().copied()) {
+                result?;
+            }
+            Ok::<(), SimError>(())
+        })?;
+        position += chunk.len();
+        if position == warmup && warmup > 0 || chunk.len() < chunk_size && position < warmup {
+            caches.iter_mut().for_each(Cache::reset_stats);
+        }
+        if chunk.len() < chunk_size {
+            return Ok(caches);
+        }
+    }
+}
+
+/// Format a table comparing the statistics of the caches of a sweep.
+pub fn format_sweep(caches: &[Cache]) -> String {
+    let mut table = format!("{:>4}{:>4}{:>4}{:>12}{:>12}{:>12}{:>11}\n", "s", "E", "b", "hits", "misses", "evictions", "miss-rate");
+    for cache in caches {
+        let lookups = cache.hits() + cache.misses();
+        let miss_rate = if lookups == 0 { 0.0 } else { 100.0 * cache.misses() as f64 / lookups as f64 };
+        table.push_str(&format!(
+            "{:>4}{:>4}{:>4}{:>12}{:>12}{:>12}{:>10.2}%\n",
+            cache.set_count().trailing_zeros(), cache.associativity(), cache.block_size().trailing_zeros(),
+            cache.hits(), cache.misses(), cache.evictions(), miss_rate
+        ));
+    }
+    table
+}
+
+// Tests for sweep_tracefile and format_sweep functions
+#[cfg(test)]
+use crate::trace::simulate_tracefile_with_warmup;
+
+#[test]
+fn test_sweep_tracefile_matches_single_runs() {
+    let configs = [(1, 1, 1), (4, 2, 4), (2, 4, 3), (5, 1, 5), (3, 8, 2), (6, 2, 6), (1, 16, 4), (8, 1, 3)];
+    let builders: Vec<CacheBuilder> = configs.iter()
+        .map(|&(s, e, b)| CacheBuilder::new().set_index_bits(s).associativity(e).block_bits(b))
+        .collect();
+    for warmup in [0, 100, 1_000_000] {
+        let caches = sweep_tracefile(&builders, "../traces/trans.trace", warmup).unwrap();
+        for (builder, cache) in builders.iter().zip(&caches) {
+            let mut single = builder.build().unwrap();
+            simulate_tracefile_with_warmup(&mut single, "../traces/trans.trace", warmup).unwrap();
+            assert_eq!(cache.format_stats(), single.format_stats());
+        }
+    }
+}
+
+#[test]
+fn test_sweep_tracefile_chunks() {
+    // long.trace spans several chunks, and the warm-up ends inside one of them
+    let builders = vec![CacheBuilder::new().set_index_bits(4).associativity(2).block_bits(4)];
+    for warmup in [0, SWEEP_CHUNK, 100_000] {
+        let caches = sweep_tracefile(&builders, "../traces/long.trace", warmup).unwrap();
+        let mut single = builders[0].build().unwrap();
+        simulate_tracefile_with_warmup(&mut single, "../traces/long.trace", warmup).unwrap();
+        assert_eq!(caches[0].format_stats(), single.format_stats());
+    }
+}
+
+#[test]
+fn test_sweep_tracefile_errors() {
+    let builders = vec![CacheBuilder::new().set_index_bits(4).associativity(1).block_bits(4), CacheBuilder::new().associativity(0)];
+    assert!(matches!(sweep_tracefile(&builders, "../traces/yi.trace", 0), Err(SimError::Config(_))));
+    assert!(matches!(sweep_tracefile(&builders[..1], "test_tracefile", 0), Err(SimError::TraceRead(_))));
+}
+
+#[test]
+fn test_format_sweep() {
+    let builders = vec![
+        CacheBuilder::new().set_index_bits(4).associativity(1).block_bits(4),
+        CacheBuilder::new().set_index_bits(1).associativity(1).block_bits(1),
+    ];
+    let caches = sweep_tracefile(&builders, "../traces/yi.trace", 0).unwrap();
+    assert_eq!(
+        format_sweep(&caches),
+        "   s   E   b        hits      misses   evictions  miss-rate\n\
+         \x20  4   1   4           4           5           3     55.56%\n\
+         \x20  1   1   1           2           7           5     77.78%\n"
+    );
+}
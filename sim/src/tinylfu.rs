@@ -0,0 +1,229 @@
+//! TinyLFU admission filter (Einziger, Friedman and Manes): a count-min sketch estimates how often each block was
+//! accessed recently, and a miss only replaces the line the policy chose when its block is estimated to be accessed
+//! more often than the block of that line.
+//!
+//! The sketch has TINYLFU_ROWS rows of 4-bit counters, each indexed by its own hash of the block. An access raises the
+//! block's counter in every row, and the estimate is the smallest of them, which hash collisions can only inflate.
+//! After as many accesses as the sample size, every counter is halved, so the estimates follow recent frequency.
+
+use serde::{Deserialize, Serialize};
+
+/// Rows of the sketch, each hashing blocks with its own seed.
+pub const TINYLFU_ROWS: usize = 4;
+
+/// Highest value of the 4-bit counters of the sketch.
+pub const TINYLFU_COUNTER_MAX: u8 = 15;
+
+/// Accesses between two halvings of the counters, as a multiple of the lines of the cache.
+pub const TINYLFU_SAMPLE_FACTOR: usize = 10;
+
+// Seeds of the hash of each row
+const ROW_SEEDS: [u64; TINYLFU_ROWS] = [0x9e37_79b9_7f4a_7c15, 0xc2b2_ae3d_27d4_eb4f, 0x1656_67b1_9e37_79f9, 0x27d4_eb2f_1656_67c5];
+
+/// Frequency sketch deciding which misses are admitted, with the decisions it made.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TinyLfu {
+    counters: Vec<Vec<u8>>, // One row of counters per seed, a power of two long
+    sample_size: usize,
+    accesses: usize, // Accesses since the last halving
+    resets: usize, // Halvings so far
+    admitted: usize,
+    rejected: usize,
+}
+
+/// Counters some blocks index in a TinyLFU sketch and the counts of the filter, saved so that accesses to those
+/// blocks can be undone, or the whole filter when the accesses may complete the sample and halve every counter.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum TinyLfuUndo {
+    Counters { counters: Vec<(usize, usize, u8)>, accesses: usize, admitted: usize, rejected: usize },
+    Filter(Box<TinyLfu>),
+}
+
+impl TinyLfu {
+    /// Sketch for a cache of lines lines: rows of as many counters, rounded up to a power of two and at least 16,
+    /// halved every TINYLFU_SAMPLE_FACTOR times as many accesses.
+    pub fn new(lines: usize) -> TinyLfu {
+        let width = lines.next_power_of_two().max(16);
+        TinyLfu {
+            counters: vec![vec![0; width]; TINYLFU_ROWS],
+            sample_size: TINYLFU_SAMPLE_FACTOR * lines.max(1),
+            accesses: 0,
+            resets: 0,
+            admitted: 0,
+            rejected: 0,
+        }
+    }
+
+    // Counter of a block in a row: the high bits of a multiplicative hash of the block mixed with the row's seed
+    fn index(&self, row: usize, block: u64) -> usize {
+        let hash = (block ^ ROW_SEEDS[row]).wrapping_mul(0x2545_f491_4f6c_dd1d);
+        let hash = hash ^ (hash >> 29);
+        (hash as usize) & (self.counters[row].len() - 1)
+    }
+
+    /// Count an access to a block, halving every counter once the sample is complete.
+    pub fn record(&mut self, block: u64) {
+        for row in 0..TINYLFU_ROWS {
+            let index = self.index(row, block);
+            let counter = &mut self.counters[row][index];
+            *counter = (*counter + 1).min(TINYLFU_COUNTER_MAX);
+        }
+        self.accesses += 1;
+        if self.accesses >= self.sample_size {
+            self.counters.iter_mut().flatten().for_each(|counter| *counter /= 2);
+            self.accesses /= 2;
+            self.resets += 1;
+        }
+    }
+
+    /// Estimated number of recent accesses to a block.
+    pub fn estimate(&self, block: u64) -> u8 {
+        (0..TINYLFU_ROWS).map(|row| self.counters[row][self.index(row, block)]).min().unwrap_or(0)
+    }
+
+    /// Whether a missing block should replace a victim block, counting the decision: only if it is estimated to be
+    /// accessed more often.
+    pub fn admit(&mut self, candidate: u64, victim: u64) -> bool {
+        let admit = self.estimate(candidate) > self.estimate(victim);
+        if admit {
+            self.admitted += 1;
+        } else {
+            self.rejected += 1;
+        }
+        admit
+    }
+
+    /// Counters in each row of the sketch.
+    pub fn width(&self) -> usize {
+        self.counters[0].len()
+    }
+
+    /// Accesses between two halvings of the counters.
+    pub fn sample_size(&self) -> usize {
+        self.sample_size
+    }
+
+    /// Halvings of the counters so far.
+    pub fn resets(&self) -> usize {
+        self.resets
+    }
+
+    /// Misses admitted so far, replacing their victim.
+    pub fn admitted(&self) -> usize {
+        self.admitted
+    }
+
+    /// Misses rejected so far, leaving their victim in place.
+    pub fn rejected(&self) -> usize {
+        self.rejected
+    }
+
+    /// Zero the decision counts, keeping the counters.
+    pub fn reset_stats(&mut self) {
+        (self.admitted, self.rejected) = (0, 0);
+    }
+
+    /// Save what up to accesses records of some blocks may change, for [`TinyLfu::restore`]: the counters of the
+    /// blocks in each row, unless the records may complete the sample.
+    pub(crate) fn save(&self, blocks: &[u64], accesses: usize) -> TinyLfuUndo {
+        if self.accesses + accesses >= self.sample_size {
+            return TinyLfuUndo::Filter(Box::new(self.clone()));
+        }
+        let counters = blocks.iter()
+            .flat_map(|&block| (0..TINYLFU_ROWS).map(move |row| (row, self.index(row, block))))
+            .map(|(row, index)| (row, index, self.counters[row][index]))
+            .collect();
+        TinyLfuUndo::Counters { counters, accesses: self.accesses, admitted: self.admitted, rejected: self.rejected }
+    }
+
+    /// Put back the state saved by [`TinyLfu::save`], undoing the records and decisions since.
+    pub(crate) fn restore(&mut self, undo: TinyLfuUndo) {
+        match undo {
+            TinyLfuUndo::Counters { counters, accesses, admitted, rejected } => {
+                for (row, index, counter) in counters {
+                    self.counters[row][index] = counter;
+                }
+                (self.accesses, self.admitted, self.rejected) = (accesses, admitted, rejected);
+            }
+            TinyLfuUndo::Filter(filter) => *self = *filter,
+        }
+    }
+}
+
+// Tests for TinyLfu::new
+#[test]
+fn test_tinylfu_sizes() {
+    let filter = TinyLfu::new(64);
+    assert_eq!((filter.width(), filter.sample_size()), (64, 640));
+    let filter = TinyLfu::new(6);
+    assert_eq!((filter.width(), filter.sample_size()), (16, 60));
+}
+
+// Tests for TinyLfu::record and TinyLfu::estimate
+#[test]
+fn test_tinylfu_estimate() {
+    let mut filter = TinyLfu::new(1024);
+    for _ in 0..5 {
+        filter.record(0xa);
+    }
+    filter.record(0xb);
+    assert_eq!((filter.estimate(0xa), filter.estimate(0xb), filter.estimate(0xc)), (5, 1, 0));
+    // Counters saturate at 15
+    for _ in 0..20 {
+        filter.record(0xd);
+    }
+    assert_eq!(filter.estimate(0xd), TINYLFU_COUNTER_MAX);
+
+    // Completing the sample halves every counter
+    let mut filter = TinyLfu::new(1);
+    for _ in 0..7 {
+        filter.record(0xa);
+    }
+    assert_eq!((filter.estimate(0xa), filter.resets()), (7, 0));
+    for _ in 0..3 {
+        filter.record(0xb);
+    }
+    assert_eq!((filter.estimate(0xa), filter.estimate(0xb), filter.resets()), (3, 1, 1));
+}
+
+// Tests for TinyLfu::admit
+#[test]
+fn test_tinylfu_admit() {
+    let mut filter = TinyLfu::new(1024);
+    filter.record(0xa);
+    filter.record(0xa);
+    filter.record(0xb);
+    assert!(filter.admit(0xa, 0xb));
+    // Ties keep the victim
+    assert!(!filter.admit(0xb, 0xb));
+    assert!(!filter.admit(0xc, 0xa));
+    assert_eq!((filter.admitted(), filter.rejected()), (1, 2));
+    filter.reset_stats();
+    assert_eq!((filter.admitted(), filter.rejected(), filter.estimate(0xa)), (0, 0, 2));
+}
+
+// Tests for TinyLfu::save and TinyLfu::restore
+#[test]
+fn test_tinylfu_restore() {
+    let mut filter = TinyLfu::new(1);
+    filter.record(0xa);
+    let undo = filter.save(&[0xa, 0xb], 2);
+    assert!(matches!(undo, TinyLfuUndo::Counters { .. }));
+    filter.record(0xa);
+    filter.record(0xb);
+    assert!(filter.admit(0xa, 0xb));
+    filter.restore(undo);
+    assert_eq!((filter.estimate(0xa), filter.estimate(0xb), filter.admitted()), (1, 0, 0));
+
+    // Records that may complete the sample save the whole filter, the halving being undone with them
+    for _ in 0..7 {
+        filter.record(0xa);
+    }
+    let undo = filter.save(&[0xb], 2);
+    assert!(matches!(undo, TinyLfuUndo::Filter(_)));
+    filter.record(0xb);
+    filter.record(0xb);
+    assert_eq!((filter.estimate(0xa), filter.resets()), (4, 1));
+    filter.restore(undo);
+    assert_eq!((filter.estimate(0xa), filter.estimate(0xb), filter.resets()), (8, 0, 0));
+}
@@ -0,0 +1,1199 @@
+//! Reading Valgrind-Lackey, DineroIV and custom text tracefiles and feeding them to a cache.
+
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Read};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::{self, Receiver};
+use std::sync::Arc;
+use std::{thread, vec};
+
+#[cfg(feature = "compressed-traces")]
+use flate2::bufread::MultiGzDecoder;
+
+use crate::binary::{decode_record, read_binary_header, read_record};
+use crate::cache::Cache;
+use crate::error::SimError;
+use crate::logging::{log_event, LogLevel};
+use crate::remap::AddressRemap;
+
+/// A data access read from a tracefile.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MemoryAccess {
+    /// 'L', 'S', 'M', 'A' for an atomic read-modify-write, 'P' for a software prefetch, 'F' for a flush of the
+    /// address's line, 'W' for a flush of the whole cache, or 'K' and 'U' to pin and unpin the lines of the bytes
+    /// accessed
+    pub operation: char,
+    pub address: u64,
+    /// Optional third field of a trace line, 0 if absent
+    pub thread: usize,
+    /// Bytes accessed, 0 if the trace doesn't record it
+    pub size: u32,
+    /// Address of the instruction making the access, 0 if the trace doesn't record it
+    pub pc: u64,
+}
+
+/// Layout of the lines of a tracefile.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub enum TraceFormat {
+    /// Valgrind Lackey lines such as " L 10,4", with an optional thread id. Flushes and pins such as " F 10" may
+    /// leave the size out, and " W" the address too
+    #[default]
+    Lackey,
+    /// DineroIV "din" records such as "0 10": a label (0 read, 1 write, 2 instruction fetch, 3 escape,
+    /// 4 flush) and a hexadecimal address
+    Dinero,
+    /// Delimited text with the fields in the given columns, e.g. from a Pin tool
+    Generic(GenericFormat),
+}
+
+impl TraceFormat {
+    /// Parse one line of a trace in this format.
+    pub fn parse_line(&self, line: &str) -> Result<Option<MemoryAccess>, SimError> {
+        match self {
+            TraceFormat::Lackey => parse_memory_access(line),
+            TraceFormat::Dinero => parse_din_record(line),
+            TraceFormat::Generic(format) => format.parse_line(line),
+        }
+    }
+
+    /// Parse one line of a trace given as raw bytes, which are malformed if they aren't valid UTF-8. Lackey lines
+    /// are parsed from the bytes directly.
+    pub fn parse_bytes(&self, line: &[u8]) -> Result<Option<MemoryAccess>, SimError> {
+        self.parse(line, false)
+    }
+
+    // Parse raw bytes like parse_bytes, keeping instruction fetches as accesses with operation 'I' if asked to
+    fn parse(&self, line: &[u8], fetches: bool) -> Result<Option<MemoryAccess>, SimError> {
+        // ASCII, as almost every trace line is, needs no decoding
+        if matches!(self, TraceFormat::Lackey) && line.is_ascii() {
+            return parse_lackey(line, fetches);
+        }
+        let line = std::str::from_utf8(line).map_err(|e| SimError::Trace(format!("invalid UTF-8 ({})", e)))?;
+        match self {
+            TraceFormat::Lackey => parse_lackey(line.as_bytes(), fetches),
+            TraceFormat::Dinero => parse_din(line, fetches),
+            TraceFormat::Generic(format) => format.parse_fields(line, fetches),
+        }
+    }
+
+    // Whether a line without a data access is an instruction fetch
+    fn is_instruction_fetch(&self, line: &[u8]) -> bool {
+        match self {
+            TraceFormat::Lackey => fields(line).next() == Some(b"I"),
+            TraceFormat::Dinero => fields(line).next() == Some(b"2"),
+            TraceFormat::Generic(format) => std::str::from_utf8(line).is_ok_and(|line| format.is_instruction_fetch(line)),
+        }
+    }
+}
+
+/// Field held by a column of a generic text trace.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Column {
+    /// Operation: L/R/load/read, S/W/store/write, M/modify, or I/ifetch for instruction fetches, which are skipped
+    /// unless an instruction cache is simulated
+    Op,
+    /// Hexadecimal address, with or without 0x
+    Address,
+    /// Access size in bytes
+    Size,
+    /// Hexadecimal program counter
+    Pc,
+    /// Decimal thread id
+    Thread,
+    /// Column that isn't read
+    Skip,
+}
+
+/// Column layout of a generic text trace, written as `generic:<columns>[:<separator>]` with the columns named
+/// op, addr, size, pc, tid or - in order and a separator of ws (default), csv or tab, e.g. `generic:pc,op,addr:csv`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct GenericFormat {
+    pub(crate) columns: Vec<Column>,
+    pub(crate) separator: Option<char>, // None splits on whitespace
+}
+
+impl GenericFormat {
+    /// Parse one line of a trace with this layout. Lines starting with # are comments.
+    pub fn parse_line(&self, line: &str) -> Result<Option<MemoryAccess>, SimError> {
+        self.parse_fields(line, false)
+    }
+
+    // Parse a line, keeping instruction fetches as accesses with operation 'I' if asked to
+    fn parse_fields(&self, line: &str, fetches: bool) -> Result<Option<MemoryAccess>, SimError> {
+        if line.trim().is_empty() || line.trim_start().starts_with('#') {
+            return Ok(None);
+        }
+        let fields: Vec<&str> = match self.separator {
+            Some(separator) => line.split(separator).map(str::trim).collect(),
+            None => line.split_whitespace().collect(),
+        };
+        if fields.len() < self.columns.len() {
+            return Err(SimError::Trace(format!("expected {} fields, found {}", self.columns.len(), fields.len())));
+        }
+
+        let mut access = MemoryAccess { operation: 'L', address: 0, thread: 0, size: 0, pc: 0 };
+        for (column, field) in self.columns.iter().zip(fields) {
+            match column {
+                Column::Op => {
+                    access.operation = match field.to_ascii_lowercase().as_str() {
+                        "l" | "r" | "load" | "read" => 'L',
+                        "s" | "w" | "store" | "write" => 'S',
+                        "m" | "modify" => 'M',
+                        "a" | "atomic" => 'A',
+                        "p" | "prefetch" => 'P',
+                        "f" | "flush" | "clflush" => 'F',
+                        "wbinvd" | "flush-all" => 'W',
+                        "k" | "pin" | "lock" => 'K',
+                        "u" | "unpin" | "unlock" => 'U',
+                        "i" | "ifetch" if fetches => 'I',
+                        "i" | "ifetch" => return Ok(None),
+                        _ => return Err(SimError::Trace(format!("invalid operation {}", field))),
+                    };
+                }
+                Column::Address => access.address = parse_hex(field, "address")?,
+                Column::Size => {
+                    access.size = field.parse().map_err(|e| SimError::Trace(format!("failed to parse size ({})", e)))?;
+                }
+                Column::Pc => access.pc = parse_hex(field, "pc")?,
+                Column::Thread => {
+                    access.thread = field.parse().map_err(|e| SimError::Trace(format!("failed to parse thread id ({})", e)))?;
+                }
+                Column::Skip => {}
+            }
+        }
+        Ok(Some(access))
+    }
+}
+
+impl GenericFormat {
+    // Whether the operation column of a line holds an instruction fetch
+    fn is_instruction_fetch(&self, line: &str) -> bool {
+        let position = match self.columns.iter().position(|&column| column == Column::Op) {
+            Some(position) => position,
+            None => return false,
+        };
+        let field = match self.separator {
+            Some(separator) => line.split(separator).nth(position).map(str::trim),
+            None => line.split_whitespace().nth(position),
+        };
+        field.is_some_and(|field| matches!(field.to_ascii_lowercase().as_str(), "i" | "ifetch"))
+    }
+}
+
+impl std::str::FromStr for GenericFormat {
+    type Err = SimError;
+
+    fn from_str(value: &str) -> Result<GenericFormat, SimError> {
+        let (columns, separator) = value.split_once(':').unwrap_or((value, "ws"));
+        let separator = match separator {
+            "ws" => None,
+            "csv" => Some(','),
+            "tab" => Some('\t'),
+            _ => return Err(SimError::Trace(format!("unknown trace separator: {}", separator))),
+        };
+        let columns = columns.split(',').map(|column| match column {
+            "op" => Ok(Column::Op),
+            "addr" => Ok(Column::Address),
+            "size" => Ok(Column::Size),
+            "pc" => Ok(Column::Pc),
+            "tid" => Ok(Column::Thread),
+            "-" => Ok(Column::Skip),
+            _ => Err(SimError::Trace(format!("unknown trace column: {}", column))),
+        }).collect::<Result<Vec<Column>, SimError>>()?;
+        for required in [Column::Op, Column::Address] {
+            if !columns.contains(&required) {
+                return Err(SimError::Trace("generic trace format needs op and addr columns".to_string()));
+            }
+        }
+        for (i, column) in columns.iter().enumerate() {
+            if *column != Column::Skip && columns[..i].contains(column) {
+                return Err(SimError::Trace(format!("trace column {:?} given twice", column)));
+            }
+        }
+        Ok(GenericFormat { columns, separator })
+    }
+}
+
+// Parse a hexadecimal field, with or without 0x
+fn parse_hex(field: &str, name: &str) -> Result<u64, SimError> {
+    let digits = field.strip_prefix("0x").or_else(|| field.strip_prefix("0X")).unwrap_or(field);
+    u64::from_str_radix(digits, 16).map_err(|e| SimError::Trace(format!("failed to parse {} ({})", name, e)))
+}
+
+// Fields of a line separated by whitespace, as str::split_whitespace splits ASCII text, without allocating
+fn fields(line: &[u8]) -> impl Iterator<Item = &[u8]> {
+    line.split(|byte| matches!(byte, b'\t'..=b'\r' | b' ')).filter(|field| !field.is_empty())
+}
+
+// A field of a line as text, for the number parsers
+fn field_text<'a>(field: &'a [u8], name: &str) -> Result<&'a str, SimError> {
+    std::str::from_utf8(field).map_err(|e| SimError::Trace(format!("failed to parse {} ({})", name, e)))
+}
+
+impl std::str::FromStr for TraceFormat {
+    type Err = SimError;
+
+    fn from_str(value: &str) -> Result<TraceFormat, SimError> {
+        match value {
+            "lackey" => Ok(TraceFormat::Lackey),
+            "din" => Ok(TraceFormat::Dinero),
+            _ if value.starts_with("generic:") => Ok(TraceFormat::Generic(value["generic:".len()..].parse()?)),
+            _ => Err(SimError::Trace(format!("unknown trace format: {}", value))),
+        }
+    }
+}
+
+/// Filename that reads the trace from standard input.
+pub const STDIN_TRACEFILE: &str = "-";
+
+/// Read memory access trace file and return memory accesses.
+pub fn read_tracefile(filename: &str) -> Result<Vec<String>, std::io::Error> {
+    open_tracefile(filename)?.lines().collect()
+}
+
+// Leading bytes of gzip and zstd streams
+const GZIP_MAGIC: &[u8] = &[0x1f, 0x8b];
+const ZSTD_MAGIC: &[u8] = &[0x28, 0xb5, 0x2f, 0xfd];
+
+// Open a tracefile for buffered reading, or standard input for "-"
+fn open_tracefile(filename: &str) -> Result<Box<dyn BufRead + Send>, std::io::Error> {
+    if filename == STDIN_TRACEFILE {
+        return decompress(Box::new(BufReader::new(io::stdin())));
+    }
+    let file = File::open(filename)?;
+    decompress(Box::new(BufReader::new(file)))
+}
+
+// Decompress gzip and zstd traces on the fly, recognizing them by their leading bytes
+#[cfg(feature = "compressed-traces")]
+fn decompress(mut reader: Box<dyn BufRead + Send>) -> Result<Box<dyn BufRead + Send>, std::io::Error> {
+    let start = reader.fill_buf()?;
+    if start.starts_with(GZIP_MAGIC) {
+        Ok(Box::new(BufReader::new(MultiGzDecoder::new(reader))))
+    } else if start.starts_with(ZSTD_MAGIC) {
+        Ok(Box::new(BufReader::new(zstd::stream::read::Decoder::with_buffer(reader)?)))
+    } else {
+        Ok(reader)
+    }
+}
+
+// Without the compressed-traces feature, such as on wasm, compressed traces are reported instead of misparsed
+#[cfg(not(feature = "compressed-traces"))]
+fn decompress(mut reader: Box<dyn BufRead + Send>) -> Result<Box<dyn BufRead + Send>, std::io::Error> {
+    let start = reader.fill_buf()?;
+    if start.starts_with(GZIP_MAGIC) || start.starts_with(ZSTD_MAGIC) {
+        Err(io::Error::new(io::ErrorKind::Unsupported, "compressed traces need the compressed-traces feature"))
+    } else {
+        Ok(reader)
+    }
+}
+
+/// How much of a tracefile has been read, shared with whatever reports the progress of a run. Compressed traces
+/// count their compressed bytes.
+#[derive(Debug, Clone, Default)]
+pub struct ReadProgress {
+    bytes_read: Arc<AtomicU64>,
+    total_bytes: Option<u64>,
+}
+
+impl ReadProgress {
+    /// Bytes of the file read so far, a buffer ahead of the accesses parsed.
+    pub fn bytes_read(&self) -> u64 {
+        self.bytes_read.load(Ordering::Relaxed)
+    }
+
+    /// Size of the file, unless the trace comes from stdin or another reader.
+    pub fn total_bytes(&self) -> Option<u64> {
+        self.total_bytes
+    }
+
+    /// Share of the file read so far, if its size is known.
+    pub fn fraction(&self) -> Option<f64> {
+        self.total_bytes.filter(|&total| total > 0).map(|total| (self.bytes_read() as f64 / total as f64).min(1.0))
+    }
+}
+
+// A reader counting the bytes read through it
+struct CountingReader<R> {
+    inner: R,
+    count: Arc<AtomicU64>,
+}
+
+impl<R: Read> Read for CountingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = self.inner.read(buf)?;
+        self.count.fetch_add(read as u64, Ordering::Relaxed);
+        Ok(read)
+    }
+}
+
+/// Lazily read and parse the data accesses of a tracefile one line at a time, so memory use doesn't grow with the trace.
+pub fn trace_accesses(filename: &str) -> Result<TraceAccesses, SimError> {
+    TraceAccesses::open(filename)
+}
+
+/// Iterator over the data accesses of a tracefile, read and parsed one line at a time, or one record at a time
+/// for binary traces. Errors name the line or record they were found on and end the iteration, unless the reader
+/// is lenient.
+pub struct TraceAccesses {
+    source: Source,
+    line: Vec<u8>, // The last line read, reused from one line to the next
+    progress: ReadProgress,
+    format: TraceFormat,
+    line_number: usize,
+    lenient: bool,
+    instruction_fetches: bool, // Yield the instruction fetches instead of the data accesses
+    remap: Option<AddressRemap>, // Rewriting of the addresses, None to keep them
+    skipped_lines: usize,
+    instructions: usize,
+    failed: bool,
+}
+
+// Text lines, the records of a binary trace, or batches of accesses parsed ahead by another thread
+enum Source {
+    Text(Box<dyn BufRead + Send>),
+    Binary(Box<dyn BufRead + Send>),
+    Pipelined { batches: Receiver<Vec<Parsed>>, batch: vec::IntoIter<Parsed> },
+}
+
+// Accesses a pipelined trace sends at a time, and batches its parsing thread may get ahead by
+const PIPELINE_BATCH: usize = 1024;
+const PIPELINE_DEPTH: usize = 16;
+
+// An access parsed ahead, None at the end of the trace, with the lines skipped and instruction fetches read up to it
+struct Parsed {
+    access: Option<Result<MemoryAccess, SimError>>,
+    skipped_lines: usize,
+    instructions: usize,
+}
+
+impl TraceAccesses {
+    /// Open a tracefile, or standard input for "-". Binary traces are recognised by their header.
+    pub fn open(filename: &str) -> Result<TraceAccesses, SimError> {
+        let trace_error = |err: io::Error| read_error(&err, err.to_string());
+        if filename == STDIN_TRACEFILE {
+            return TraceAccesses::read(open_tracefile(filename).map_err(trace_error)?);
+        }
+        let file = File::open(filename).map_err(trace_error)?;
+        let progress = ReadProgress { bytes_read: Arc::default(), total_bytes: file.metadata().ok().map(|metadata| metadata.len()) };
+        let reader = BufReader::new(CountingReader { inner: file, count: Arc::clone(&progress.bytes_read) });
+        let accesses = TraceAccesses::read(decompress(Box::new(reader)).map_err(trace_error)?)?;
+        Ok(TraceAccesses { progress, ..accesses })
+    }
+
+    /// Read a trace from any reader, such as a trace held in memory, decompressing and recognising binary
+    /// traces as [`TraceAccesses::open`] does.
+    pub fn from_reader<R: BufRead + Send + 'static>(reader: R) -> Result<TraceAccesses, SimError> {
+        TraceAccesses::read(decompress(Box::new(reader)).map_err(|err| read_error(&err, err.to_string()))?)
+    }
+
+    /// Read a trace held in a byte slice, which may be compressed or binary as with [`TraceAccesses::from_reader`].
+    pub fn from_bytes(data: &[u8]) -> Result<TraceAccesses, SimError> {
+        TraceAccesses::from_reader(io::Cursor::new(data.to_vec()))
+    }
+
+    // Read a decompressed trace
+    fn read(mut reader: Box<dyn BufRead + Send>) -> Result<TraceAccesses, SimError> {
+        let source = match read_binary_header(&mut reader).map_err(|err| read_error(&err, err.to_string()))? {
+            true => Source::Binary(reader),
+            false => Source::Text(reader),
+        };
+        Ok(TraceAccesses { source, line: Vec::new(), progress: ReadProgress::default(), format: TraceFormat::default(), line_number: 0, lenient: false, instruction_fetches: false, remap: None, skipped_lines: 0, instructions: 0, failed: false })
+    }
+
+    /// Read lines in the given format instead of the Lackey one. Binary traces ignore it.
+    pub fn format(mut self, format: TraceFormat) -> TraceAccesses {
+        self.format = format;
+        self
+    }
+
+    /// Skip and count lines that can't be parsed, such as Valgrind's ==PID== banners, instead of failing on them.
+    pub fn lenient(mut self, lenient: bool) -> TraceAccesses {
+        self.lenient = lenient;
+        self
+    }
+
+    /// Yield the instruction fetches of the trace, as loads of the instruction addresses, instead of its data
+    /// accesses, to simulate an instruction cache. Binary traces don't keep instruction fetches, so reading one
+    /// fails.
+    pub fn instruction_fetches(mut self, instruction_fetches: bool) -> TraceAccesses {
+        self.instruction_fetches = instruction_fetches;
+        self
+    }
+
+    /// Rewrite the address of every access, or instruction fetch, with the given offset and region moves.
+    pub fn remap(mut self, remap: AddressRemap) -> TraceAccesses {
+        self.remap = (!remap.is_identity()).then_some(remap);
+        self
+    }
+
+    /// Number of lines, or binary records, skipped so far in lenient mode.
+    pub fn skipped_lines(&self) -> usize {
+        self.skipped_lines
+    }
+
+    /// Number of instruction fetches read so far. Binary traces don't record them.
+    pub fn instructions(&self) -> usize {
+        self.instructions
+    }
+
+    /// Handle on how much of the tracefile has been read, which stays valid while the accesses are consumed.
+    pub fn read_progress(&self) -> ReadProgress {
+        self.progress.clone()
+    }
+
+    /// Read and parse the trace in a thread of its own, up to a bounded number of batches ahead, so that decoding
+    /// overlaps with whatever consumes the accesses. The accesses and errors come in the same order, and the skipped
+    /// lines and instruction fetches are counted up to the last access taken, as without a pipeline.
+    pub fn pipelined(self) -> TraceAccesses {
+        if matches!(self.source, Source::Pipelined { .. }) {
+            return self;
+        }
+        let (sender, batches) = mpsc::sync_channel(PIPELINE_DEPTH);
+        let accesses = TraceAccesses {
+            source: Source::Pipelined { batches, batch: Vec::new().into_iter() },
+            line: Vec::new(),
+            progress: self.progress.clone(),
+            format: self.format.clone(),
+            line_number: self.line_number,
+            lenient: self.lenient,
+            instruction_fetches: self.instruction_fetches,
+            remap: self.remap.clone(),
+            skipped_lines: self.skipped_lines,
+            instructions: self.instructions,
+            failed: self.failed,
+        };
+        let mut parser = self;
+        thread::spawn(move || loop {
+            let mut batch = Vec::with_capacity(PIPELINE_BATCH);
+            let mut done = false;
+            while !done && batch.len() < PIPELINE_BATCH {
+                let access = parser.next();
+                done = access.is_none();
+                batch.push(Parsed { access, skipped_lines: parser.skipped_lines, instructions: parser.instructions });
+            }
+            // Stop at the end of the trace, or when the accesses are dropped
+            if sender.send(batch).is_err() || done {
+                return;
+            }
+        });
+        accesses
+    }
+
+    // Take the next access parsed ahead by the thread of a pipelined trace
+    fn next_pipelined(&mut self) -> Option<Result<MemoryAccess, SimError>> {
+        let Source::Pipelined { batches, batch } = &mut self.source else {
+            unreachable!("trace is not pipelined")
+        };
+        loop {
+            if let Some(parsed) = batch.next() {
+                self.skipped_lines = parsed.skipped_lines;
+                self.instructions = parsed.instructions;
+                self.failed = !matches!(parsed.access, Some(Ok(_)));
+                return parsed.access;
+            }
+            match batches.recv() {
+                Ok(next) => *batch = next.into_iter(),
+                Err(_) => {
+                    self.failed = true;
+                    return Some(Err(SimError::TraceRead("the trace parsing thread stopped".to_string())));
+                }
+            }
+        }
+    }
+}
+
+// Read the next line into a reused buffer, without its \n or \r\n ending, false at the end of the input
+fn read_line(reader: &mut dyn BufRead, line: &mut Vec<u8>) -> io::Result<bool> {
+    line.clear();
+    if reader.read_until(b'\n', line)? == 0 {
+        return Ok(false);
+    }
+    if line.last() == Some(&b'\n') {
+        line.pop();
+        if line.last() == Some(&b'\r') {
+            line.pop();
+        }
+    }
+    Ok(true)
+}
+
+// An error reading a trace: a malformed trace if the data is invalid or cut short, a failed read otherwise
+fn read_error(err: &io::Error, message: String) -> SimError {
+    match err.kind() {
+        io::ErrorKind::InvalidData | io::ErrorKind::UnexpectedEof => SimError::Trace(message),
+        _ => SimError::TraceRead(message),
+    }
+}
+
+impl Iterator for TraceAccesses {
+    type Item = Result<MemoryAccess, SimError>;
+
+    fn next(&mut self) -> Option<Result<MemoryAccess, SimError>> {
+        if matches!(self.source, Source::Pipelined { .. }) && !self.failed {
+            return self.next_pipelined();
+        }
+        while !self.failed {
+            // The outer error is a read error, which can't be skipped
+            let (position, read) = match &mut self.source {
+                Source::Text(reader) => ("line", match read_line(reader, &mut self.line) {
+                    Ok(true) if self.instruction_fetches => Ok(match self.format.parse(&self.line, true) {
+                        Ok(Some(access)) if access.operation == 'I' => {
+                            self.instructions += 1;
+                            Ok(Some(MemoryAccess { operation: 'L', ..access }))
+                        }
+                        Ok(Some(_)) => Ok(None),
+                        parsed => parsed,
+                    }),
+                    Ok(true) => {
+                        let parsed = self.format.parse_bytes(&self.line);
+                        if matches!(parsed, Ok(None)) && self.format.is_instruction_fetch(&self.line) {
+                            self.instructions += 1;
+                        }
+                        Ok(parsed)
+                    }
+                    Ok(false) => return None,
+                    Err(err) => Err(err),
+                }),
+                Source::Binary(_) if self.instruction_fetches => {
+                    self.failed = true;
+                    return Some(Err(SimError::Trace("binary traces don't keep instruction fetches".to_string())));
+                }
+                Source::Binary(reader) => ("record", match read_record(reader) {
+                    Ok(Some(record)) => Ok(decode_record(&record).map(Some)),
+                    Ok(None) => return None,
+                    Err(err) => Err(err),
+                }),
+                Source::Pipelined { .. } => unreachable!("pipelined traces are parsed by their thread"),
+            };
+            self.line_number += 1;
+            match read {
+                Ok(Ok(Some(access))) => match &self.remap {
+                    Some(remap) => return Some(Ok(MemoryAccess { address: remap.apply(access.address), ..access })),
+                    None => return Some(Ok(access)),
+                },
+                Ok(Ok(None)) => continue,
+                Ok(Err(err)) if self.lenient => {
+                    log_event(LogLevel::Debug, "trace", format_args!("skipped {} {}: {}", position, self.line_number, err));
+                    self.skipped_lines += 1;
+                }
+                Ok(Err(err)) => {
+                    self.failed = true;
+                    return Some(Err(SimError::Trace(format!("{} {}: {}", position, self.line_number, err))));
+                }
+                Err(err) => {
+                    self.failed = true;
+                    return Some(Err(read_error(&err, format!("{} {}: {}", position, self.line_number, err))));
+                }
+            }
+        }
+        None
+    }
+}
+
+/// Parse memory access line, as text or raw bytes, and return operation, address and thread, or None for lines
+/// without a data access. Fields are separated by ASCII whitespace and sliced out of the line without allocating.
+pub fn parse_memory_access<T: AsRef<[u8]> + ?Sized>(memory_access: &T) -> Result<Option<MemoryAccess>, SimError> {
+    parse_lackey(memory_access.as_ref(), false)
+}
+
+// Parse a Lackey line, keeping instruction fetches as accesses with operation 'I' if asked to
+fn parse_lackey(memory_access: &[u8], fetches: bool) -> Result<Option<MemoryAccess>, SimError> {
+    if memory_access.is_empty() {
+        return Ok(None);
+    }
+    let mut memory_access_parts = fields(memory_access);
+
+    let (operation, address_size) = (memory_access_parts.next(), memory_access_parts.next());
+    if let (Some(b"W"), None) = (operation, address_size) {
+        return Ok(Some(MemoryAccess { operation: 'W', address: 0, thread: 0, size: 0, pc: 0 })); // A full flush needs no address
+    }
+    if let (Some(operation), Some(address_size)) = (operation, address_size) {
+        let operation = match operation {
+            b"I" if !fetches => return Ok(None), // Skip instruction cache accesses
+            b"I" | b"S" | b"M" | b"L" | b"A" | b"P" | b"F" | b"W" | b"K" | b"U" => operation[0] as char,
+            _ => return Err(SimError::Trace("invalid operation encountered".to_string())),
+        };
+        let mut address_size_parts = address_size.split(|&byte| byte == b',');
+        // Flushes and pins may leave the size out
+        let (address, size) = (address_size_parts.next(), address_size_parts.next().or(matches!(operation, 'F' | 'W' | 'K' | 'U').then_some(b"0")));
+        if let (Some(address), Some(size)) = (address, size) {
+            let hexadecimal_address = parse_hex(field_text(address, "address")?, "address")?;
+            let size = field_text(size, "size")?.parse().map_err(|e| SimError::Trace(format!("failed to parse size ({})", e)))?;
+            let thread = match memory_access_parts.next() {
+                Some(thread) => field_text(thread, "thread id")?.parse().map_err(|e| SimError::Trace(format!("failed to parse thread id ({})", e)))?,
+                None => 0,
+            };
+            return Ok(Some(MemoryAccess { operation, address: hexadecimal_address, thread, size, pc: 0 }));
+        }
+    }
+    Err(SimError::Trace("invalid memory access format".to_string()))
+}
+
+/// Parse a DineroIV "din" record. Instruction fetches and escape records carry no data access, and flush records
+/// flush the whole cache.
+pub fn parse_din_record(record: &str) -> Result<Option<MemoryAccess>, SimError> {
+    parse_din(record, false)
+}
+
+// Parse a din record, keeping instruction fetches as accesses with operation 'I' if asked to
+fn parse_din(record: &str, fetches: bool) -> Result<Option<MemoryAccess>, SimError> {
+    let mut fields = record.split_whitespace();
+    let (label, address) = match (fields.next(), fields.next()) {
+        (None, _) => return Ok(None),
+        (Some(label), Some(address)) => (label, address),
+        (Some(_), None) => return Err(SimError::Trace("invalid din record format".to_string())),
+    };
+    let operation = match label {
+        "0" => 'L',
+        "1" => 'S',
+        "2" if fetches => 'I',
+        "4" => return Ok(Some(MemoryAccess { operation: 'W', address: 0, thread: 0, size: 0, pc: 0 })),
+        "2" | "3" => return Ok(None),
+        _ => return Err(SimError::Trace(format!("invalid din label {}", label))),
+    };
+    Ok(Some(MemoryAccess { operation, address: parse_hex(address, "address")?, thread: 0, size: 0, pc: 0 }))
+}
+
+/// Read tracefile and loop through memory accesses, simulating each of them.
+pub fn simulate_tracefile(cache: &mut Cache, filename: &str) -> Result<(), SimError> {
+    simulate_tracefile_with_warmup(cache, filename, 0)
+}
+
+/// Simulate a tracefile, resetting the statistics after the first warmup accesses so that they only warm the cache up.
+pub fn simulate_tracefile_with_warmup(cache: &mut Cache, filename: &str, warmup: usize) -> Result<(), SimError> {
+    simulate_accesses(cache, trace_accesses(filename)?, warmup)
+}
+
+/// Simulate accesses as they are read, resetting the statistics after the first warmup accesses and stopping at
+/// the first error.
+pub fn simulate_accesses<I>(cache: &mut Cache, accesses: I, warmup: usize) -> Result<(), SimError>
+where
+    I: IntoIterator<Item = Result<MemoryAccess, SimError>>,
+{
+    let mut read_error = None;
+    let mut accesses = accesses.into_iter()
+        .map_while(|access| access.map_err(|err| read_error = Some(err)).ok());
+
+    // Simulate cache behaviour using memory access data
+    if warmup > 0 {
+        for result in cache.drive(accesses.by_ref().take(warmup)) {
+            result?;
+        }
+        cache.reset_stats();
+    }
+    for result in cache.drive(accesses) {
+        result?;
+    }
+    match read_error {
+        Some(err) => Err(err),
+        None => Ok(()),
+    }
+}
+
+/// Read and parse every memory access of a tracefile.
+pub fn read_accesses(filename: &str) -> Result<Vec<MemoryAccess>, SimError> {
+    trace_accesses(filename)?.collect()
+}
+
+#[cfg(test)]
+use crate::rng::Rng;
+
+// Tests for read_tracefile function
+#[test]
+fn test_read_tracefile_ibm() {
+    let expected_contents = vec![
+       " L 10,4 ", 
+       " S 18,4",
+       " L 20,4",
+       " S 28,4",
+       " S 50,4",
+    ];
+    let result = read_tracefile("../traces/ibm.trace");
+    assert!(result.is_ok());
+
+    let actual_contents = result.unwrap();
+    assert_eq!(actual_contents, expected_contents);
+}
+
+#[test]
+fn test_read_tracefile_yi() {
+    let expected_contents = vec![
+        " L 10,1",
+        " M 20,1",
+        " L 22,1",
+        " S 18,1",
+        " L 110,1",
+        " L 210,1",
+        " M 12,1",
+    ];
+
+    let result = read_tracefile("../traces/yi.trace");
+    assert!(result.is_ok());
+
+    let actual_contents = result.unwrap();
+    assert_eq!(actual_contents, expected_contents);
+}
+
+#[test]
+fn test_read_tracefile_yi2() {
+    let expected_contents = vec![
+        " L 0,1",
+        " L 1,1",
+        " L 2,1",
+        " L 3,1",
+        " S 4,1",
+        " L 5,1",
+        " S 6,1",
+        " L 7,1",
+        " S 8,1",
+        " L 9,1",
+        " S a,1",
+        " L b,1",
+        " S c,1",
+        " L d,1",
+        " S e,1",
+        " M f,1",
+    ];
+
+    let result = read_tracefile("../traces/yi2.trace");
+    assert!(result.is_ok());
+
+    let actual_contents = result.unwrap();
+    assert_eq!(actual_contents, expected_contents);
+}
+
+#[test]
+fn test_read_tracefile_long() {
+    assert!(read_tracefile("../traces/long.trace").is_ok());
+}
+
+#[test]
+fn test_read_tracefile_trance() {
+    assert!(read_tracefile("../traces/trans.trace").is_ok());
+}
+
+#[test]
+fn test_read_tracefile_test_tracefile() {
+    assert!(read_tracefile("test_tracefile").is_err());
+}
+
+// Tests for trace_accesses function
+#[test]
+fn test_trace_accesses() {
+    let mut accesses = trace_accesses("../traces/yi.trace").unwrap();
+    assert_eq!(accesses.next(), Some(Ok(MemoryAccess { operation: 'L', address: 0x10, thread: 0, size: 1, pc: 0 })));
+    assert_eq!(accesses.count(), 6);
+    assert_eq!(trace_accesses("../traces/long.trace").unwrap().count(), 267_988);
+    assert!(trace_accesses("test_tracefile").is_err());
+
+    // Paths are used as given, including absolute ones
+    let absolute = format!("{}/../traces/yi.trace", env!("CARGO_MANIFEST_DIR"));
+    assert_eq!(trace_accesses(&absolute).unwrap().count(), 7);
+}
+
+#[test]
+fn test_trace_accesses_from_reader() {
+    let trace = std::fs::read("../traces/yi.trace").unwrap();
+    let accesses: Vec<MemoryAccess> = TraceAccesses::from_reader(io::Cursor::new(trace)).unwrap().map(Result::unwrap).collect();
+    assert_eq!(accesses, read_accesses("../traces/yi.trace").unwrap());
+    let mut accesses = TraceAccesses::from_reader(&b" L 10,1\n X 20,1\n"[..]).unwrap();
+    assert_eq!(accesses.next(), Some(Ok(MemoryAccess { operation: 'L', address: 0x10, thread: 0, size: 1, pc: 0 })));
+    assert!(accesses.next().unwrap().is_err());
+}
+
+#[test]
+fn test_trace_accesses_from_bytes() {
+    let trace = std::fs::read("../traces/yi.trace").unwrap();
+    assert_eq!(TraceAccesses::from_bytes(&trace).unwrap().count(), 7);
+    // Windows line endings, and a last line without one
+    let addresses: Vec<u64> = TraceAccesses::from_bytes(b" L 10,1\r\n I 400,4\r\n\r\n S 20,1 3\r\n M 30,1").unwrap().map(|access| access.unwrap().address).collect();
+    assert_eq!(addresses, vec![0x10, 0x20, 0x30]);
+    let mut accesses = TraceAccesses::from_bytes(b" L 10,1\n S \xff,1\n").unwrap();
+    assert_eq!(accesses.next(), Some(Ok(MemoryAccess { operation: 'L', address: 0x10, thread: 0, size: 1, pc: 0 })));
+    assert!(matches!(accesses.next(), Some(Err(SimError::Trace(_)))));
+}
+
+// Arbitrary bytes, biased towards trace-like lines, must give errors rather than panics
+#[test]
+fn test_trace_accesses_arbitrary_bytes() {
+    let alphabet = b"LSMI 0x,123456789abcdefz\t#-\n\xff\xc3";
+    let formats: Vec<TraceFormat> = ["lackey", "din", "generic:op,addr,size,tid", "generic:-,op,addr:csv"].iter().map(|format| format.parse().unwrap()).collect();
+    let mut rng = Rng::new(1093);
+    for _ in 0..2000 {
+        let length = rng.next_below(40);
+        let data: Vec<u8> = (0..length).map(|_| alphabet[rng.next_below(alphabet.len())]).collect();
+        for format in &formats {
+            let _ = format.parse_bytes(&data);
+            let mut cache = Cache::new(2, 2, 3).unwrap();
+            for access in TraceAccesses::from_bytes(&data).unwrap().format(format.clone()).lenient(true).flatten() {
+                let _ = cache.simulate_access(access);
+            }
+        }
+    }
+    for line in ["S ,4", "L ,", "M 10,", "S 10,4 x", "0", "L 10,-1"] {
+        assert!(TraceFormat::Lackey.parse_bytes(line.as_bytes()).is_err(), "{}", line);
+    }
+}
+
+#[cfg(feature = "compressed-traces")]
+#[test]
+fn test_trace_accesses_compressed() {
+    use std::io::Write;
+
+    let trace = std::fs::read("../traces/yi.trace").unwrap();
+    let directory = std::env::temp_dir();
+    let gzip_path = directory.join(format!("sim-yi-{}.trace.gz", std::process::id()));
+    let mut encoder = flate2::write::GzEncoder::new(File::create(&gzip_path).unwrap(), flate2::Compression::default());
+    encoder.write_all(&trace).unwrap();
+    encoder.finish().unwrap();
+    // The extension doesn't matter, only the leading bytes
+    let zstd_path = directory.join(format!("sim-yi-{}.trace", std::process::id()));
+    std::fs::write(&zstd_path, zstd::encode_all(&trace[..], 0).unwrap()).unwrap();
+
+    let expected = read_accesses("../traces/yi.trace").unwrap();
+    for path in [&gzip_path, &zstd_path] {
+        assert_eq!(read_accesses(path.to_str().unwrap()), Ok(expected.clone()));
+        std::fs::remove_file(path).unwrap();
+    }
+}
+
+#[test]
+fn test_trace_accesses_errors() {
+    let path = std::env::temp_dir().join(format!("sim-garbage-{}.trace", std::process::id()));
+    std::fs::write(&path, b"==123== Lackey, an example Valgrind tool\n L 10,1\n\n   \n\xff\xfe\n M 20,1\nI  0400d7d4,8\n").unwrap();
+    let path = path.to_str().unwrap();
+
+    let mut accesses = trace_accesses(path).unwrap();
+    assert_eq!(accesses.next(), Some(Err(SimError::Trace("line 1: invalid operation encountered".to_string()))));
+    assert_eq!(accesses.next(), None);
+
+    let mut accesses = trace_accesses(path).unwrap().lenient(true);
+    let addresses: Vec<u64> = accesses.by_ref().map(|access| access.unwrap().address).collect();
+    assert_eq!(addresses, vec![0x10, 0x20]);
+    assert_eq!(accesses.skipped_lines(), 3);
+    assert_eq!(accesses.instructions(), 1);
+    std::fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn test_trace_accesses_instructions() {
+    let mut accesses = trace_accesses("../traces/trans.trace").unwrap();
+    assert_eq!(accesses.instructions(), 0);
+    assert_eq!(accesses.by_ref().count(), 218);
+    assert_eq!(accesses.instructions(), 378);
+
+    let path = std::env::temp_dir().join(format!("sim-ifetch-{}.trace", std::process::id()));
+    std::fs::write(&path, "2 400100\n0 10\n2 400104\n3 0\n").unwrap();
+    let mut accesses = trace_accesses(path.to_str().unwrap()).unwrap().format(TraceFormat::Dinero);
+    assert_eq!(accesses.by_ref().count(), 1);
+    assert_eq!(accesses.instructions(), 2);
+
+    std::fs::write(&path, "# pc,op,addr\n400a10,I,0\n400a14, ifetch ,0\n400a18,W,10\n").unwrap();
+    let mut accesses = trace_accesses(path.to_str().unwrap()).unwrap().format("generic:pc,op,addr:csv".parse().unwrap());
+    assert_eq!(accesses.by_ref().count(), 1);
+    assert_eq!(accesses.instructions(), 2);
+    std::fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn test_trace_accesses_instruction_fetches() {
+    let mut accesses = trace_accesses("../traces/trans.trace").unwrap().instruction_fetches(true);
+    assert_eq!(accesses.next(), Some(Ok(MemoryAccess { operation: 'L', address: 0x4005b6, thread: 0, size: 5, pc: 0 })));
+    assert_eq!(accesses.by_ref().count(), 377);
+    assert_eq!(accesses.instructions(), 378);
+
+    // Every format marks its fetches, and malformed ones are errors like malformed data accesses
+    let path = std::env::temp_dir().join(format!("sim-fetches-{}.trace", std::process::id()));
+    for (format, trace) in [
+        ("lackey", "I  0400100,4\n L 10,4\n S 20,1\nI  0400104,2\n"),
+        ("din", "2 400100\n0 10\n1 20\n2 0x400104\n"),
+        ("generic:pc,op,addr:csv", "0,ifetch,400100\n1,R,10\n2,W,20\n3,I,400104\n"),
+    ] {
+        std::fs::write(&path, trace).unwrap();
+        let mut accesses = trace_accesses(path.to_str().unwrap()).unwrap().format(format.parse().unwrap()).instruction_fetches(true);
+        let addresses: Vec<u64> = accesses.by_ref().map(|access| access.unwrap().address).collect();
+        assert_eq!((addresses, accesses.instructions()), (vec![0x400100, 0x400104], 2), "{}", format);
+    }
+    std::fs::write(&path, "I  0400100,4\nI  zz,4\n L 10,4\n").unwrap();
+    let mut accesses = trace_accesses(path.to_str().unwrap()).unwrap().instruction_fetches(true);
+    assert!(accesses.next().unwrap().is_ok());
+    assert!(matches!(accesses.next(), Some(Err(SimError::Trace(message))) if message.starts_with("line 2: failed to parse address")));
+    let mut accesses = trace_accesses(path.to_str().unwrap()).unwrap().instruction_fetches(true).lenient(true);
+    assert_eq!((accesses.by_ref().count(), accesses.skipped_lines()), (1, 1));
+    std::fs::remove_file(&path).unwrap();
+
+    let mut accesses = TraceAccesses::from_bytes(b"SIMTRACE\x01").unwrap().instruction_fetches(true);
+    assert_eq!(accesses.next(), Some(Err(SimError::Trace("binary traces don't keep instruction fetches".to_string()))));
+    assert_eq!(accesses.next(), None);
+}
+
+#[test]
+fn test_trace_accesses_remap() {
+    let remap = AddressRemap { offset: 0x100, regions: vec![(0x20..=0x2f, 0x1000)] };
+    let addresses = |accesses: TraceAccesses| accesses.map(|access| access.unwrap().address).collect::<Vec<u64>>();
+    let remapped = vec![0x110, 0x1000, 0x1002, 0x118, 0x210, 0x310, 0x112];
+    assert_eq!(addresses(trace_accesses("../traces/yi.trace").unwrap().remap(remap.clone())), remapped);
+    assert_eq!(addresses(trace_accesses("../traces/yi.trace").unwrap().remap(remap.clone()).pipelined()), remapped);
+
+    // Binary traces and instruction fetches are remapped too, and the identity keeps the addresses as they are
+    let binary = TraceAccesses::from_bytes(b"SIMTRACE\x01L\x24\0\0\0\0\0\0\0\x04\0").unwrap();
+    assert_eq!(addresses(binary.remap(remap.clone())), vec![0x1004]);
+    let fetches = TraceAccesses::from_bytes(b"I  0400100,4\n L 10,4\n").unwrap().instruction_fetches(true);
+    assert_eq!(addresses(fetches.remap(remap)), vec![0x400200]);
+    assert_eq!(addresses(trace_accesses("../traces/yi.trace").unwrap().remap(AddressRemap::default()))[..2], [0x10, 0x20]);
+}
+
+#[test]
+fn test_trace_accesses_read_progress() {
+    let size = std::fs::metadata("../traces/long.trace").unwrap().len();
+    let mut accesses = trace_accesses("../traces/long.trace").unwrap();
+    let progress = accesses.read_progress();
+    assert_eq!(progress.total_bytes(), Some(size));
+    accesses.by_ref().take(1000).for_each(drop);
+    let fraction = progress.fraction().unwrap();
+    assert!(fraction > 0.0 && fraction < 0.1);
+    accesses.for_each(drop);
+    assert_eq!((progress.bytes_read(), progress.fraction()), (size, Some(1.0)));
+
+    // The size of a trace read from memory isn't known
+    let progress = TraceAccesses::from_reader(&b" L 10,1\n"[..]).unwrap().read_progress();
+    assert_eq!((progress.total_bytes(), progress.fraction()), (None, None));
+}
+
+#[test]
+fn test_trace_accesses_pipelined() {
+    // The same accesses in the same order, across many batches
+    let expected = read_accesses("../traces/long.trace").unwrap();
+    let accesses: Vec<MemoryAccess> = trace_accesses("../traces/long.trace").unwrap().pipelined().pipelined().map(Result::unwrap).collect();
+    assert_eq!(accesses, expected);
+
+    // Counts are those of the accesses taken so far, not of the lines the thread has parsed ahead
+    let mut accesses = trace_accesses("../traces/trans.trace").unwrap().pipelined();
+    let mut plain = trace_accesses("../traces/trans.trace").unwrap();
+    for _ in 0..100 {
+        assert_eq!(accesses.next(), plain.next());
+        assert_eq!(accesses.instructions(), plain.instructions());
+    }
+    accesses.by_ref().for_each(drop);
+    assert_eq!((accesses.instructions(), accesses.next()), (378, None));
+
+    let trace = b"==123== Lackey\n L 10,1\n\xff\n I 400,4\n M 20,1\nx\n";
+    let mut accesses = TraceAccesses::from_bytes(trace).unwrap().lenient(true).pipelined();
+    assert_eq!(accesses.next().map(|access| access.unwrap().address), Some(0x10));
+    assert_eq!((accesses.skipped_lines(), accesses.instructions()), (1, 0));
+    assert_eq!(accesses.next().map(|access| access.unwrap().address), Some(0x20));
+    assert_eq!((accesses.skipped_lines(), accesses.instructions()), (2, 1));
+    assert_eq!(accesses.next(), None);
+    assert_eq!(accesses.skipped_lines(), 3);
+
+    // Errors end the accesses as they do without a pipeline
+    let mut accesses = TraceAccesses::from_bytes(trace).unwrap().pipelined();
+    assert_eq!(accesses.next(), Some(Err(SimError::Trace("line 1: invalid operation encountered".to_string()))));
+    assert_eq!(accesses.next(), None);
+
+    // Dropping the accesses early stops the thread instead of leaving it blocked
+    let mut accesses = trace_accesses("../traces/long.trace").unwrap().pipelined();
+    accesses.by_ref().take(10).for_each(drop);
+    drop(accesses);
+}
+
+#[test]
+fn test_trace_accesses_din() {
+    let path = std::env::temp_dir().join(format!("sim-din-{}.din", std::process::id()));
+    std::fs::write(&path, "2 400100\n0 10\n1 0x20\n\n3 0\n0 7ffc\n").unwrap();
+    let path = path.to_str().unwrap();
+    let accesses: Vec<MemoryAccess> = trace_accesses(path).unwrap().format(TraceFormat::Dinero).map(Result::unwrap).collect();
+    assert_eq!(accesses, vec![
+        MemoryAccess { operation: 'L', address: 0x10, thread: 0, size: 0, pc: 0 },
+        MemoryAccess { operation: 'S', address: 0x20, thread: 0, size: 0, pc: 0 },
+        MemoryAccess { operation: 'L', address: 0x7ffc, thread: 0, size: 0, pc: 0 },
+    ]);
+    let mut accesses = trace_accesses(path).unwrap();
+    assert_eq!(accesses.next(), Some(Err(SimError::Trace("line 1: invalid operation encountered".to_string()))));
+    std::fs::remove_file(path).unwrap();
+}
+
+// Tests for parse_din_record function
+#[test]
+fn test_parse_din_record() {
+    assert_eq!(parse_din_record("0 10"), Ok(Some(MemoryAccess { operation: 'L', address: 0x10, thread: 0, size: 0, pc: 0 })));
+    assert_eq!(parse_din_record("1 7ff000"), Ok(Some(MemoryAccess { operation: 'S', address: 0x7ff000, thread: 0, size: 0, pc: 0 })));
+    assert_eq!(parse_din_record("1 0xabc extra"), Ok(Some(MemoryAccess { operation: 'S', address: 0xabc, thread: 0, size: 0, pc: 0 })));
+    assert_eq!(parse_din_record("2 400100"), Ok(None));
+    assert_eq!(parse_din_record("4 0"), Ok(Some(MemoryAccess { operation: 'W', address: 0, thread: 0, size: 0, pc: 0 })));
+    assert_eq!(parse_din_record(""), Ok(None));
+    assert_eq!(parse_din_record("5 10"), Err(SimError::Trace("invalid din label 5".to_string())));
+    assert_eq!(parse_din_record("0"), Err(SimError::Trace("invalid din record format".to_string())));
+    assert!(parse_din_record("0 xyz").is_err());
+}
+
+#[test]
+fn test_trace_format_from_str() {
+    assert_eq!("lackey".parse(), Ok(TraceFormat::Lackey));
+    assert_eq!("din".parse(), Ok(TraceFormat::Dinero));
+    assert_eq!("pin".parse::<TraceFormat>(), Err(SimError::Trace("unknown trace format: pin".to_string())));
+    assert_eq!("generic:op,addr".parse(), Ok(TraceFormat::Generic(GenericFormat { columns: vec![Column::Op, Column::Address], separator: None })));
+    assert!("generic:op".parse::<TraceFormat>().is_err());
+}
+
+// Tests for GenericFormat
+#[test]
+fn test_generic_format_from_str() {
+    assert_eq!("pc,op,addr,size,tid:csv".parse(), Ok(GenericFormat {
+        columns: vec![Column::Pc, Column::Op, Column::Address, Column::Size, Column::Thread],
+        separator: Some(','),
+    }));
+    assert_eq!("-,addr,-,op:tab".parse(), Ok(GenericFormat {
+        columns: vec![Column::Skip, Column::Address, Column::Skip, Column::Op],
+        separator: Some('\t'),
+    }));
+    assert_eq!("op,addr:;".parse::<GenericFormat>(), Err(SimError::Trace("unknown trace separator: ;".to_string())));
+    assert_eq!("op,address".parse::<GenericFormat>(), Err(SimError::Trace("unknown trace column: address".to_string())));
+    assert_eq!("addr,size".parse::<GenericFormat>(), Err(SimError::Trace("generic trace format needs op and addr columns".to_string())));
+    assert_eq!("op,addr,addr".parse::<GenericFormat>(), Err(SimError::Trace("trace column Address given twice".to_string())));
+}
+
+#[test]
+fn test_generic_format_parse_line() {
+    let csv: GenericFormat = "pc,op,addr,size,tid:csv".parse().unwrap();
+    assert_eq!(csv.parse_line("0x400a10, W, 0x7ffd1000, 8, 2"), Ok(Some(MemoryAccess { operation: 'S', address: 0x7ffd1000, thread: 2, size: 8, pc: 0x400a10 })));
+    assert_eq!(csv.parse_line("400a10,read,10,4,0"), Ok(Some(MemoryAccess { operation: 'L', address: 0x10, thread: 0, size: 4, pc: 0x400a10 })));
+    assert_eq!(csv.parse_line("400a10,I,10,4,0"), Ok(None));
+    assert_eq!(csv.parse_line("400a10,prefetch,40,64,0"), Ok(Some(MemoryAccess { operation: 'P', address: 0x40, thread: 0, size: 64, pc: 0x400a10 })));
+    assert_eq!(csv.parse_line("400a10,atomic,40,8,1"), Ok(Some(MemoryAccess { operation: 'A', address: 0x40, thread: 1, size: 8, pc: 0x400a10 })));
+    assert_eq!(csv.parse_line("400a10,clflush,40,64,1"), Ok(Some(MemoryAccess { operation: 'F', address: 0x40, thread: 1, size: 64, pc: 0x400a10 })));
+    assert_eq!(csv.parse_line("400a10,wbinvd,0,0,0"), Ok(Some(MemoryAccess { operation: 'W', address: 0, thread: 0, size: 0, pc: 0x400a10 })));
+    assert_eq!(csv.parse_line("400a10,lock,40,64,0"), Ok(Some(MemoryAccess { operation: 'K', address: 0x40, thread: 0, size: 64, pc: 0x400a10 })));
+    assert_eq!(csv.parse_line("# pc,op,addr,size,tid"), Ok(None));
+    assert_eq!(csv.parse_line(""), Ok(None));
+    assert_eq!(csv.parse_line("400a10,X,10,4,0"), Err(SimError::Trace("invalid operation X".to_string())));
+    assert_eq!(csv.parse_line("400a10,R,10"), Err(SimError::Trace("expected 5 fields, found 3".to_string())));
+    assert!(csv.parse_line("400a10,R,10,big,0").is_err());
+    assert!(csv.parse_line("pc,R,10,4,0").is_err());
+
+    let whitespace: GenericFormat = "addr,-,op".parse().unwrap();
+    assert_eq!(whitespace.parse_line("  ff  anything  M extra"), Ok(Some(MemoryAccess { operation: 'M', address: 0xff, thread: 0, size: 0, pc: 0 })));
+}
+
+// Tests for parse_memory_access function
+#[test]
+fn test_parse_memory_access_valid_input() {
+    let memory_access = "S 10,1";
+    assert_eq!(parse_memory_access(memory_access), Ok(Some(MemoryAccess { operation: 'S', address: 0x10, thread: 0, size: 1, pc: 0 })));
+    assert_eq!(parse_memory_access("L 0x7ff0,4"), Ok(Some(MemoryAccess { operation: 'L', address: 0x7ff0, thread: 0, size: 4, pc: 0 })));
+    assert_eq!(parse_memory_access(" P 40,64"), Ok(Some(MemoryAccess { operation: 'P', address: 0x40, thread: 0, size: 64, pc: 0 })));
+    assert_eq!(parse_memory_access(" A 7ff0,8 1"), Ok(Some(MemoryAccess { operation: 'A', address: 0x7ff0, thread: 1, size: 8, pc: 0 })));
+}
+
+#[test]
+fn test_parse_memory_access_flush() {
+    assert_eq!(parse_memory_access(" F 7ff0,64"), Ok(Some(MemoryAccess { operation: 'F', address: 0x7ff0, thread: 0, size: 64, pc: 0 })));
+    assert_eq!(parse_memory_access(" F 7ff0"), Ok(Some(MemoryAccess { operation: 'F', address: 0x7ff0, thread: 0, size: 0, pc: 0 })));
+    assert_eq!(parse_memory_access(" W"), Ok(Some(MemoryAccess { operation: 'W', address: 0, thread: 0, size: 0, pc: 0 })));
+    assert_eq!(parse_memory_access(" W 0,0 2"), Ok(Some(MemoryAccess { operation: 'W', address: 0, thread: 2, size: 0, pc: 0 })));
+    // Other operations still need a size
+    assert_eq!(parse_memory_access(" L 7ff0"), Err(SimError::Trace("invalid memory access format".to_string())));
+    assert_eq!(parse_memory_access(" F"), Err(SimError::Trace("invalid memory access format".to_string())));
+    assert_eq!(parse_memory_access(" K 7ff0,256"), Ok(Some(MemoryAccess { operation: 'K', address: 0x7ff0, thread: 0, size: 256, pc: 0 })));
+    assert_eq!(parse_memory_access(" U 7ff0"), Ok(Some(MemoryAccess { operation: 'U', address: 0x7ff0, thread: 0, size: 0, pc: 0 })));
+}
+
+#[test]
+fn test_parse_memory_access_extra_whitespace() {
+    let memory_accesses = vec!["S      10,1", "   S 10,1", "S 10,1    "];
+    for memory_access in memory_accesses {
+        assert_eq!(parse_memory_access(memory_access), Ok(Some(MemoryAccess { operation: 'S', address: 0x10, thread: 0, size: 1, pc: 0 })));
+    }
+}
+
+#[test]
+fn test_parse_memory_access_thread() {
+    assert_eq!(parse_memory_access(" L 10,4 3"), Ok(Some(MemoryAccess { operation: 'L', address: 0x10, thread: 3, size: 4, pc: 0 })));
+    assert!(parse_memory_access(" L 10,4 x").is_err());
+    assert_eq!(parse_memory_access(" L 10,x"), Err(SimError::Trace("failed to parse size (invalid digit found in string)".to_string())));
+}
+
+#[test]
+fn test_parse_memory_access_bytes() {
+    let expected = Ok(Some(MemoryAccess { operation: 'M', address: 0x7ff0, thread: 2, size: 8, pc: 0 }));
+    for line in [&b" M 7ff0,8 2"[..], b"M\t0x7ff0,8\t2\r", b"\x0bM 7ff0,8,16 2 extra"] {
+        assert_eq!(parse_memory_access(line), expected, "{:?}", line);
+    }
+    assert_eq!(parse_memory_access(&b" I 400,\xff"[..]), Ok(None));
+    assert!(matches!(parse_memory_access(&b" L \xff,4"[..]), Err(SimError::Trace(message)) if message.starts_with("failed to parse address")));
+    assert_eq!(parse_memory_access(&b" L 10,4 \xff"[..]), Err(SimError::Trace("failed to parse thread id (invalid utf-8 sequence of 1 bytes from index 0)".to_string())));
+    assert_eq!(parse_memory_access("   "), Err(SimError::Trace("invalid memory access format".to_string())));
+    assert_eq!(parse_memory_access("I"), Err(SimError::Trace("invalid memory access format".to_string())));
+}
+
+#[test]
+fn test_parse_memory_access_instruction_access() {
+    let memory_access = "I 10,1";
+    assert_eq!(parse_memory_access(memory_access), Ok(None));
+}
+
+#[test]
+fn test_parse_memory_access_invalid_operation() {
+    let memory_access = "X 10,1";
+    assert_eq!(parse_memory_access(memory_access), Err(SimError::Trace("invalid operation encountered".to_string())));
+}
+
+#[test]
+fn test_parse_memory_access_invalid_format_no_whitespace() {
+    let memory_access = "S10,1";
+    assert!(parse_memory_access(memory_access).is_err());
+}
+
+#[test]
+fn test_parse_memory_access_invalid_format_no_size() {
+    let memory_access = "S 10";
+    assert!(parse_memory_access(memory_access).is_err());
+}
+
+#[test]
+fn test_parse_memory_access_invalid_format_no_comma() {
+    let memory_access = "S 10:1";
+    assert!(parse_memory_access(memory_access).is_err());
+}
+
+#[test]
+fn test_parse_memory_access_invalid_address_value() {
+    let memory_access = "S xyz,1";
+    assert!(parse_memory_access(memory_access).is_err());
+}
+
+// Tests for TraceFormat::parse_bytes function
+#[test]
+fn test_trace_format_parse_bytes() {
+    // Lines that aren't ASCII are still checked as a whole, even in fields the format ignores
+    assert!(matches!(TraceFormat::Lackey.parse_bytes(b" L 10,4 1 \xff"), Err(SimError::Trace(message)) if message.starts_with("invalid UTF-8")));
+    assert_eq!(TraceFormat::Lackey.parse_bytes(" L 10,4 1 é".as_bytes()), Ok(Some(MemoryAccess { operation: 'L', address: 0x10, thread: 1, size: 4, pc: 0 })));
+    assert_eq!(TraceFormat::Lackey.parse_bytes(b" L 10,4"), Ok(Some(MemoryAccess { operation: 'L', address: 0x10, thread: 0, size: 4, pc: 0 })));
+    assert_eq!(TraceFormat::Dinero.parse_bytes(b"1 20"), Ok(Some(MemoryAccess { operation: 'S', address: 0x20, thread: 0, size: 0, pc: 0 })));
+    assert!(matches!(TraceFormat::Lackey.parse_bytes(b" L \xff,4"), Err(SimError::Trace(_))));
+}
+
+// Tests for simulate_tracefile function
+#[test]
+fn test_simulate_tracefile_errors() {
+    let mut cache = Cache::new(4, 1, 4).unwrap();
+    assert!(matches!(simulate_tracefile(&mut cache, "test_tracefile"), Err(SimError::TraceRead(_))));
+    assert_eq!(simulate_tracefile(&mut cache, "../traces/yi.trace"), Ok(()));
+    assert_eq!((cache.hits(), cache.misses(), cache.evictions()), (4, 5, 3));
+
+    let mut cache = crate::cache::CacheBuilder::new().set_index_bits(4).associativity(1).block_bits(4).address_bits(8).build().unwrap();
+    assert_eq!(simulate_tracefile(&mut cache, "../traces/yi.trace"), Err(SimError::Access("address 110 exceeds the 8-bit address width".to_string())));
+}
+
+#[test]
+fn test_simulate_tracefile_with_warmup() {
+    // yi.trace: L 10 miss, M 20 miss hit, L 22 hit, S 18 hit, L 110 miss eviction, L 210 miss eviction, M 12 miss eviction hit
+    let mut cache = Cache::new(4, 1, 4).unwrap();
+    assert_eq!(simulate_tracefile_with_warmup(&mut cache, "../traces/yi.trace", 4), Ok(()));
+    assert_eq!((cache.hits(), cache.misses(), cache.evictions()), (1, 3, 3));
+
+    let mut cache = Cache::new(4, 1, 4).unwrap();
+    assert_eq!(simulate_tracefile_with_warmup(&mut cache, "../traces/yi.trace", 100), Ok(()));
+    assert_eq!((cache.hits(), cache.misses(), cache.evictions()), (0, 0, 0));
+}
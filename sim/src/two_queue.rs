@@ -0,0 +1,168 @@
+//! 2Q replacement (Johnson and Shasha): blocks missing for the first time are filled into the A1in queue, which
+//! gives up its lines first in first out, and the tags of the lines it evicts are remembered in the A1out ghost
+//! queue. A block missing again while A1out still remembers it was reused beyond A1in, so it is filled into the Am
+//! queue, kept in LRU order.
+//!
+//! Each set keeps its own queues: A1in holds a quarter of its lines before evicting from itself rather than from Am,
+//! and A1out remembers as many tags as half its lines. A scan of blocks used once only cycles through A1in, leaving
+//! the lines of Am alone.
+
+use std::collections::VecDeque;
+
+use serde::{Deserialize, Serialize};
+
+/// Lines of each set A1in holds before its oldest line is evicted instead of the least recently used line of Am, as a
+/// divisor of the ways.
+pub const A1IN_WAYS_DIVISOR: usize = 4;
+
+/// Tags of each set the A1out ghost queue remembers, as a divisor of the ways.
+pub const A1OUT_TAGS_DIVISOR: usize = 2;
+
+/// A1out ghost queues of the sets of a 2Q cache, with the share of each set A1in may hold.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TwoQueue {
+    in_ways: usize,
+    out_tags: usize,
+    ghosts: Vec<VecDeque<u64>>, // A1out of each set, the tag evicted last at the back
+    ghost_hits: usize, // Misses on a tag A1out remembered, filled into Am
+    remembered: usize, // Tags of lines evicted from A1in put in A1out
+}
+
+/// A1out queues of some sets of a 2Q cache and its counts, saved so that the accesses to those sets can be undone.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct TwoQueueUndo {
+    ghosts: Vec<(usize, VecDeque<u64>)>,
+    ghost_hits: usize,
+    remembered: usize,
+}
+
+impl TwoQueue {
+    /// Queues for sets of ways lines, A1in holding at least one line and A1out remembering at least one tag.
+    pub fn new(sets: usize, ways: usize) -> TwoQueue {
+        let out_tags = (ways / A1OUT_TAGS_DIVISOR).max(1);
+        TwoQueue {
+            in_ways: (ways / A1IN_WAYS_DIVISOR).max(1),
+            out_tags,
+            ghosts: vec![VecDeque::with_capacity(out_tags + 1); sets],
+            ghost_hits: 0,
+            remembered: 0,
+        }
+    }
+
+    /// Lines of a set A1in holds before evicting its own oldest line.
+    pub fn in_ways(&self) -> usize {
+        self.in_ways
+    }
+
+    /// Tags of a set A1out remembers.
+    pub fn out_tags(&self) -> usize {
+        self.out_tags
+    }
+
+    /// Number of sets with an A1out queue.
+    pub fn sets(&self) -> usize {
+        self.ghosts.len()
+    }
+
+    /// Tags a set's A1out remembers, the tag evicted first at the front.
+    pub fn ghosts(&self, set: usize) -> &VecDeque<u64> {
+        &self.ghosts[set]
+    }
+
+    /// Remember the tag of a line evicted from A1in, forgetting the oldest tag of the set when A1out is full.
+    pub fn remember(&mut self, set: usize, tag: u64) {
+        let ghosts = &mut self.ghosts[set];
+        ghosts.retain(|&ghost| ghost != tag);
+        ghosts.push_back(tag);
+        if ghosts.len() > self.out_tags {
+            ghosts.pop_front();
+        }
+        self.remembered += 1;
+    }
+
+    /// Look up a missing tag in A1out, forgetting it if found: whether its fill goes into Am.
+    pub fn recall(&mut self, set: usize, tag: u64) -> bool {
+        let ghosts = &mut self.ghosts[set];
+        let Some(position) = ghosts.iter().position(|&ghost| ghost == tag) else {
+            return false;
+        };
+        ghosts.remove(position);
+        self.ghost_hits += 1;
+        true
+    }
+
+    /// Misses counted so far on a tag A1out remembered.
+    pub fn ghost_hits(&self) -> usize {
+        self.ghost_hits
+    }
+
+    /// Tags put in A1out so far.
+    pub fn remembered(&self) -> usize {
+        self.remembered
+    }
+
+    /// Zero the counts, keeping the tags A1out remembers.
+    pub fn reset_stats(&mut self) {
+        (self.ghost_hits, self.remembered) = (0, 0);
+    }
+
+    /// Save the A1out queues of some sets and the counts, for [`TwoQueue::restore`].
+    pub(crate) fn save(&self, sets: &[usize]) -> TwoQueueUndo {
+        TwoQueueUndo {
+            ghosts: sets.iter().map(|&set| (set, self.ghosts[set].clone())).collect(),
+            ghost_hits: self.ghost_hits,
+            remembered: self.remembered,
+        }
+    }
+
+    /// Put back the state saved by [`TwoQueue::save`], undoing the accesses to its sets since.
+    pub(crate) fn restore(&mut self, undo: TwoQueueUndo) {
+        for (set, ghosts) in undo.ghosts {
+            self.ghosts[set] = ghosts;
+        }
+        (self.ghost_hits, self.remembered) = (undo.ghost_hits, undo.remembered);
+    }
+}
+
+// Tests for TwoQueue::new
+#[test]
+fn test_two_queue_sizes() {
+    let queues = TwoQueue::new(4, 8);
+    assert_eq!((queues.sets(), queues.in_ways(), queues.out_tags()), (4, 2, 4));
+    let queues = TwoQueue::new(1, 1);
+    assert_eq!((queues.in_ways(), queues.out_tags()), (1, 1));
+}
+
+// Tests for TwoQueue::remember and TwoQueue::recall
+#[test]
+fn test_two_queue_ghosts() {
+    let mut queues = TwoQueue::new(2, 4);
+    queues.remember(0, 0xa);
+    queues.remember(0, 0xb);
+    // A1out of a 4-way set remembers two tags, the oldest being forgotten first
+    queues.remember(0, 0xc);
+    assert_eq!(queues.ghosts(0), &[0xb, 0xc]);
+    assert!(queues.ghosts(1).is_empty());
+    assert!(!queues.recall(0, 0xa));
+    assert!(!queues.recall(1, 0xb));
+    assert!(queues.recall(0, 0xb));
+    // A recalled tag is forgotten, its block now being in Am
+    assert!(!queues.recall(0, 0xb));
+    assert_eq!((queues.ghosts(0), queues.ghost_hits(), queues.remembered()), (&VecDeque::from([0xc]), 1, 3));
+    queues.reset_stats();
+    assert_eq!((queues.ghost_hits(), queues.remembered(), queues.ghosts(0).len()), (0, 0, 1));
+}
+
+// Tests for TwoQueue::save and TwoQueue::restore
+#[test]
+fn test_two_queue_restore() {
+    let mut queues = TwoQueue::new(2, 4);
+    queues.remember(0, 0xa);
+    queues.remember(1, 0xb);
+    let undo = queues.save(&[0]);
+    queues.remember(0, 0xc);
+    assert!(queues.recall(0, 0xa));
+    queues.restore(undo);
+    assert_eq!((queues.ghosts(0), queues.ghosts(1)), (&VecDeque::from([0xa]), &VecDeque::from([0xb])));
+    assert_eq!((queues.ghost_hits(), queues.remembered()), (0, 2));
+}
@@ -0,0 +1,86 @@
+//! A simulator API for JavaScript callers, such as a browser-based teaching tool running the library as wasm.
+//!
+//! Every argument and result is a number, a string or a JSON string, and errors are messages, so the methods
+//! map directly onto wasm-bindgen exports. Traces are passed as text rather than read from files.
+
+use std::io::Cursor;
+
+use serde_json::json;
+
+use crate::cache::{Cache, CacheBuilder, Op};
+use crate::trace::TraceAccesses;
+
+/// A cache driven through strings and numbers.
+pub struct WebSimulator {
+    cache: Cache,
+}
+
+impl WebSimulator {
+    /// Create an empty cache of 2^s sets of e lines of 2^b bytes.
+    pub fn new(s: usize, e: usize, b: usize) -> Result<WebSimulator, String> {
+        let cache = CacheBuilder::new().set_index_bits(s).associativity(e).block_bits(b).build().map_err(|err| err.to_string())?;
+        Ok(WebSimulator { cache })
+    }
+
+    /// Simulate one access, an operation of "L", "S", "M", "A", "P", "F", "W", "K" or "U", and return its hits, misses and evictions as JSON.
+    pub fn access(&mut self, operation: &str, address: u64, size: u32) -> Result<String, String> {
+        let op = operation.parse::<char>()
+            .map_err(|_| format!("unknown operation: {}", operation))
+            .and_then(|operation| Op::try_from(operation).map_err(|err| err.to_string()))?;
+        let result = self.cache.access(op, address, size).map_err(|err| err.to_string())?;
+        Ok(json!({ "hits": result.hits, "misses": result.misses, "evictions": result.evictions }).to_string())
+    }
+
+    /// Simulate every access of a trace in the Lackey text format and return how many were simulated.
+    pub fn simulate_trace(&mut self, trace: &str) -> Result<usize, String> {
+        let accesses = TraceAccesses::from_reader(Cursor::new(trace.as_bytes().to_vec())).map_err(|err| err.to_string())?;
+        let mut count = 0;
+        for access in accesses {
+            self.cache.simulate_access(access.map_err(|err| err.to_string())?).map_err(|err| err.to_string())?;
+            count += 1;
+        }
+        Ok(count)
+    }
+
+    /// Statistics so far as JSON.
+    pub fn stats(&self) -> String {
+        serde_json::to_string(&self.cache.snapshot_stats()).unwrap_or_default()
+    }
+
+    /// Contents of every set as JSON: the tag of each line (null when empty) and the LRU order of its ways.
+    pub fn sets(&self) -> String {
+        let sets: Vec<_> = self.cache.set_states().map(|state| json!({
+            "index": state.index,
+            "tags": state.lines.iter().map(|line| line.tag).collect::<Vec<_>>(),
+            "lru_order": state.lru_order,
+        })).collect();
+        serde_json::Value::from(sets).to_string()
+    }
+}
+
+// Tests for WebSimulator
+#[test]
+fn test_web_simulator() {
+    let mut simulator = WebSimulator::new(4, 1, 4).unwrap();
+    assert_eq!(simulator.access("L", 0x10, 1), Ok(r#"{"evictions":0,"hits":0,"misses":1}"#.to_string()));
+    assert_eq!(simulator.access("M", 0x10, 1), Ok(r#"{"evictions":0,"hits":2,"misses":0}"#.to_string()));
+    assert_eq!(simulator.access("X", 0x10, 1), Err("unknown operation: X".to_string()));
+    assert_eq!(simulator.access("LS", 0x10, 1), Err("unknown operation: LS".to_string()));
+
+    let stats: serde_json::Value = serde_json::from_str(&simulator.stats()).unwrap();
+    assert_eq!((stats["hits"].as_u64(), stats["misses"].as_u64()), (Some(2), Some(1)));
+    let sets: serde_json::Value = serde_json::from_str(&simulator.sets()).unwrap();
+    assert_eq!(sets.as_array().unwrap().len(), 16);
+    assert_eq!(sets[1], json!({ "index": 1, "tags": [0], "lru_order": [0] }));
+    assert!(WebSimulator::new(4, 0, 4).is_err());
+}
+
+#[test]
+fn test_web_simulator_trace() {
+    let trace = std::fs::read_to_string("../traces/yi.trace").unwrap();
+    let mut simulator = WebSimulator::new(4, 1, 4).unwrap();
+    assert_eq!(simulator.simulate_trace(&trace), Ok(7));
+    let stats: serde_json::Value = serde_json::from_str(&simulator.stats()).unwrap();
+    assert_eq!((stats["hits"].as_u64(), stats["misses"].as_u64(), stats["evictions"].as_u64()), (Some(4), Some(5), Some(3)));
+    assert_eq!(simulator.simulate_trace(" L 10,1\n X 20,1\n"), Err("line 2: invalid operation encountered".to_string()));
+}
@@ -0,0 +1,120 @@
+//! Working-set size over time: the number of distinct blocks a trace touches within a sliding window.
+
+use std::collections::{HashMap, VecDeque};
+
+use crate::error::SimError;
+use crate::trace::{trace_accesses, MemoryAccess};
+
+/// Working-set size at one point of a trace.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct WorkingSetPoint {
+    pub position: usize, // Accesses of the trace read so far
+    pub blocks: usize, // Distinct blocks among the last window accesses
+}
+
+/// Distinct 2^block_bits-byte blocks within the last window accesses, measured after every step accesses
+/// and at the end of the trace. Windows at the start of the trace hold fewer accesses. Flushes and pins touch no
+/// block and aren't counted.
+pub fn working_set_curve<I>(accesses: I, block_bits: usize, window: usize, step: usize) -> Result<Vec<WorkingSetPoint>, SimError>
+where
+    I: IntoIterator<Item = Result<MemoryAccess, SimError>>,
+{
+    if block_bits >= 64 {
+        return Err(SimError::Config("block bits must be below 64".to_string()));
+    }
+    if window == 0 || step == 0 {
+        return Err(SimError::Config("working-set window and step must be at least 1".to_string()));
+    }
+    let mut recent = VecDeque::with_capacity(window);
+    let mut counts: HashMap<u64, usize> = HashMap::new();
+    let mut points = Vec::new();
+    let mut position = 0;
+    for access in accesses {
+        let access = access?;
+        if matches!(access.operation, 'F' | 'W' | 'K' | 'U') {
+            continue;
+        }
+        let block = access.address >> block_bits;
+        if recent.len() == window {
+            let oldest = recent.pop_front().unwrap();
+            if let Some(count) = counts.get_mut(&oldest) {
+                *count -= 1;
+                if *count == 0 {
+                    counts.remove(&oldest);
+                }
+            }
+        }
+        recent.push_back(block);
+        *counts.entry(block).or_default() += 1;
+        position += 1;
+        if position % step == 0 {
+            points.push(WorkingSetPoint { position, blocks: counts.len() });
+        }
+    }
+    if position % step != 0 {
+        points.push(WorkingSetPoint { position, blocks: counts.len() });
+    }
+    Ok(points)
+}
+
+/// Measure the working-set curve of a tracefile.
+pub fn working_set_tracefile(filename: &str, block_bits: usize, window: usize, step: usize) -> Result<Vec<WorkingSetPoint>, SimError> {
+    working_set_curve(trace_accesses(filename)?, block_bits, window, step)
+}
+
+/// Format a working-set curve one point per line, with sizes in blocks and bytes, followed by the largest
+/// and mean working set.
+pub fn format_working_set(points: &[WorkingSetPoint], block_bits: usize) -> String {
+    let mut lines: Vec<String> = points.iter()
+        .map(|point| format!("accesses:{} blocks:{} bytes:{}", point.position, point.blocks, (point.blocks as u64) << block_bits))
+        .collect();
+    let max = points.iter().map(|point| point.blocks).max().unwrap_or(0);
+    let mean = if points.is_empty() { 0.0 } else { points.iter().map(|point| point.blocks).sum::<usize>() as f64 / points.len() as f64 };
+    lines.push(format!("max-blocks:{} mean-blocks:{:.2}", max, mean));
+    lines.join("\n")
+}
+
+// Tests for working_set_curve function
+#[test]
+fn test_working_set_curve() {
+    // Blocks a a b c a d
+    let accesses = [0x0, 0x4, 0x10, 0x20, 0x0, 0x30].map(|address| Ok(MemoryAccess { operation: 'L', address, thread: 0, size: 4, pc: 0 }));
+    let points = working_set_curve(accesses, 4, 3, 2).unwrap();
+    assert_eq!(points, vec![
+        WorkingSetPoint { position: 2, blocks: 1 },
+        WorkingSetPoint { position: 4, blocks: 3 },
+        WorkingSetPoint { position: 6, blocks: 3 },
+    ]);
+
+    let accesses = [0x0, 0x10, 0x20].map(|address| Ok(MemoryAccess { operation: 'S', address, thread: 0, size: 4, pc: 0 }));
+    assert_eq!(working_set_curve(accesses, 4, 2, 2).unwrap(), vec![
+        WorkingSetPoint { position: 2, blocks: 2 },
+        WorkingSetPoint { position: 3, blocks: 2 },
+    ]);
+
+    let accesses = [('L', 0x0), ('F', 0x0), ('W', 0x0), ('L', 0x10)].map(|(operation, address)| Ok(MemoryAccess { operation, address, thread: 0, size: 4, pc: 0 }));
+    assert_eq!(working_set_curve(accesses, 4, 2, 2).unwrap(), vec![WorkingSetPoint { position: 2, blocks: 2 }]);
+}
+
+#[test]
+fn test_working_set_curve_errors() {
+    assert!(working_set_curve(Vec::new(), 4, 0, 1).is_err());
+    assert!(working_set_curve(Vec::new(), 4, 1, 0).is_err());
+    assert!(working_set_curve(Vec::new(), 64, 1, 1).is_err());
+    assert_eq!(working_set_curve(Vec::new(), 4, 1, 1), Ok(Vec::new()));
+}
+
+#[test]
+fn test_working_set_tracefile() {
+    // A window as long as the trace holds every block it touches
+    let points = working_set_tracefile("../traces/yi.trace", 4, 100, 100).unwrap();
+    assert_eq!(points, vec![WorkingSetPoint { position: 7, blocks: 4 }]);
+}
+
+// Tests for format_working_set function
+#[test]
+fn test_format_working_set() {
+    let points = [WorkingSetPoint { position: 100, blocks: 3 }, WorkingSetPoint { position: 200, blocks: 6 }];
+    assert_eq!(format_working_set(&points, 6), "accesses:100 blocks:3 bytes:192\naccesses:200 blocks:6 bytes:384\nmax-blocks:6 mean-blocks:4.50");
+    assert_eq!(format_working_set(&[], 6), "max-blocks:0 mean-blocks:0.00");
+}
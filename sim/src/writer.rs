@@ -0,0 +1,183 @@
+//! Recording memory accesses as tracefiles the simulator reads.
+
+use std::io::{self, Write};
+
+use crate::binary::{encode_record, write_binary_header};
+use crate::cache::Op;
+use crate::error::SimError;
+use crate::trace::{Column, MemoryAccess, TraceFormat};
+
+/// Writes accesses as a Lackey, DineroIV, generic text or binary trace, for programs that record their own
+/// memory accesses.
+///
+/// ```
+/// use sim::{Op, TraceFormat, TraceWriter};
+///
+/// let mut writer = TraceWriter::new(Vec::new(), TraceFormat::Lackey);
+/// writer.record(Op::Load, 0x10, 4).unwrap();
+/// writer.record(Op::Store, 0x20, 8).unwrap();
+/// assert_eq!(writer.finish().unwrap(), b" L 10,4\n S 20,8\n");
+/// ```
+pub struct TraceWriter<W: Write> {
+    writer: W,
+    format: Option<TraceFormat>, // None writes binary records
+    header_written: bool,
+    accesses: usize,
+}
+
+impl<W: Write> TraceWriter<W> {
+    /// Writer of a text trace in the given format.
+    pub fn new(writer: W, format: TraceFormat) -> TraceWriter<W> {
+        TraceWriter { writer, format: Some(format), header_written: true, accesses: 0 }
+    }
+
+    /// Writer of a binary trace.
+    pub fn binary(writer: W) -> TraceWriter<W> {
+        TraceWriter { writer, format: None, header_written: false, accesses: 0 }
+    }
+
+    /// Record a data access by thread 0.
+    pub fn record(&mut self, op: Op, address: u64, size: u32) -> Result<(), SimError> {
+        self.write_access(&MemoryAccess { operation: op.into(), address, thread: 0, size, pc: 0 })
+    }
+
+    /// Record a data access. DineroIV traces write modifies and atomics as a read and a write, and drop thread ids, as
+    /// binary traces do.
+    pub fn write_access(&mut self, access: &MemoryAccess) -> Result<(), SimError> {
+        let op = Op::try_from(access.operation).map_err(|err| SimError::Trace(err.message().to_string()))?;
+        let result = match &self.format {
+            None => {
+                let record = encode_record(access)?;
+                self.write_header().and_then(|_| self.writer.write_all(&record))
+            }
+            Some(TraceFormat::Lackey) => match access.thread {
+                0 => writeln!(self.writer, " {} {:x},{}", access.operation, access.address, access.size),
+                thread => writeln!(self.writer, " {} {:x},{} {}", access.operation, access.address, access.size, thread),
+            },
+            Some(TraceFormat::Dinero) => match op {
+                Op::Load => writeln!(self.writer, "0 {:x}", access.address),
+                Op::Store => writeln!(self.writer, "1 {:x}", access.address),
+                Op::Modify | Op::Atomic => writeln!(self.writer, "0 {:x}\n1 {:x}", access.address, access.address),
+                Op::Prefetch => return Err(SimError::Trace("DineroIV traces have no prefetch records".to_string())),
+                Op::Flush => return Err(SimError::Trace("DineroIV traces have no line flush records".to_string())),
+                Op::Pin | Op::Unpin => return Err(SimError::Trace("DineroIV traces have no pin records".to_string())),
+                Op::FlushAll => writeln!(self.writer, "4 0"),
+            },
+            Some(TraceFormat::Generic(format)) => {
+                let fields: Vec<String> = format.columns.iter().map(|column| match column {
+                    // A W column reads back as a write
+                    Column::Op if op == Op::FlushAll => "wbinvd".to_string(),
+                    Column::Op => access.operation.to_string(),
+                    Column::Address => format!("0x{:x}", access.address),
+                    Column::Size => access.size.to_string(),
+                    Column::Thread => access.thread.to_string(),
+                    Column::Pc | Column::Skip => "0".to_string(),
+                }).collect();
+                let separator = format.separator.map_or(" ".to_string(), String::from);
+                writeln!(self.writer, "{}", fields.join(&separator))
+            }
+        };
+        result.map_err(write_error)?;
+        self.accesses += 1;
+        Ok(())
+    }
+
+    /// Accesses recorded so far.
+    pub fn accesses(&self) -> usize {
+        self.accesses
+    }
+
+    /// Flush the trace and return the underlying writer. An empty binary trace still gets its header.
+    pub fn finish(mut self) -> Result<W, SimError> {
+        self.write_header().and_then(|_| self.writer.flush()).map_err(write_error)?;
+        Ok(self.writer)
+    }
+
+    // Binary traces start with a header, written before the first record
+    fn write_header(&mut self) -> io::Result<()> {
+        if !self.header_written {
+            write_binary_header(&mut self.writer)?;
+            self.header_written = true;
+        }
+        Ok(())
+    }
+}
+
+fn write_error(err: io::Error) -> SimError {
+    SimError::Io(format!("failed to write trace ({})", err))
+}
+
+#[cfg(test)]
+use crate::{binary::BINARY_MAGIC, trace::{parse_din_record, parse_memory_access, trace_accesses}};
+
+// Tests for TraceWriter
+#[test]
+fn test_trace_writer_lackey() {
+    let mut writer = TraceWriter::new(Vec::new(), TraceFormat::Lackey);
+    let access = MemoryAccess { operation: 'M', address: 0x7ff0, thread: 2, size: 8, pc: 0 };
+    writer.write_access(&access).unwrap();
+    writer.record(Op::Load, 0x10, 1).unwrap();
+    assert_eq!(writer.accesses(), 2);
+    let output = String::from_utf8(writer.finish().unwrap()).unwrap();
+    assert_eq!(output, " M 7ff0,8 2\n L 10,1\n");
+    assert_eq!(parse_memory_access(output.lines().next().unwrap()), Ok(Some(access)));
+}
+
+#[test]
+fn test_trace_writer_din() {
+    let mut writer = TraceWriter::new(Vec::new(), TraceFormat::Dinero);
+    writer.record(Op::Store, 0x20, 4).unwrap();
+    writer.record(Op::Modify, 0x30, 4).unwrap();
+    writer.record(Op::Atomic, 0x40, 8).unwrap();
+    writer.record(Op::FlushAll, 0, 0).unwrap();
+    let output = String::from_utf8(writer.finish().unwrap()).unwrap();
+    assert_eq!(output, "1 20\n0 30\n1 30\n0 40\n1 40\n4 0\n");
+    assert_eq!(parse_din_record("1 20"), Ok(Some(MemoryAccess { operation: 'S', address: 0x20, thread: 0, size: 0, pc: 0 })));
+}
+
+#[test]
+fn test_trace_writer_generic() {
+    let format: TraceFormat = "generic:pc,op,addr,size,tid:csv".parse().unwrap();
+    let mut writer = TraceWriter::new(Vec::new(), format.clone());
+    let access = MemoryAccess { operation: 'S', address: 0xabc, thread: 1, size: 2, pc: 0 };
+    writer.write_access(&access).unwrap();
+    let output = String::from_utf8(writer.finish().unwrap()).unwrap();
+    assert_eq!(output, "0,S,0xabc,2,1\n");
+    assert_eq!(format.parse_line(output.trim_end()), Ok(Some(access)));
+
+    let mut writer = TraceWriter::new(Vec::new(), format.clone());
+    let flush = MemoryAccess { operation: 'W', address: 0, thread: 0, size: 0, pc: 0 };
+    writer.write_access(&flush).unwrap();
+    let output = String::from_utf8(writer.finish().unwrap()).unwrap();
+    assert_eq!(format.parse_line(output.trim_end()), Ok(Some(flush)));
+}
+
+#[test]
+fn test_trace_writer_binary() {
+    let path = std::env::temp_dir().join(format!("sim-writer-{}.bin", std::process::id()));
+    let accesses: Vec<MemoryAccess> = trace_accesses("../traces/yi.trace").unwrap().map(Result::unwrap).collect();
+    let mut writer = TraceWriter::binary(Vec::new());
+    for access in &accesses {
+        writer.write_access(access).unwrap();
+    }
+    std::fs::write(&path, writer.finish().unwrap()).unwrap();
+    let read: Vec<MemoryAccess> = trace_accesses(path.to_str().unwrap()).unwrap().map(Result::unwrap).collect();
+    assert_eq!(read, accesses);
+    std::fs::remove_file(path).unwrap();
+
+    let empty = TraceWriter::binary(Vec::new()).finish().unwrap();
+    assert!(empty.starts_with(BINARY_MAGIC));
+}
+
+#[test]
+fn test_trace_writer_errors() {
+    let mut writer = TraceWriter::new(Vec::new(), TraceFormat::Lackey);
+    assert!(writer.write_access(&MemoryAccess { operation: 'X', address: 0, thread: 0, size: 1, pc: 0 }).is_err());
+    let mut writer = TraceWriter::new(Vec::new(), TraceFormat::Dinero);
+    assert_eq!(writer.record(Op::Prefetch, 0, 1), Err(SimError::Trace("DineroIV traces have no prefetch records".to_string())));
+    assert_eq!(writer.record(Op::Flush, 0, 1), Err(SimError::Trace("DineroIV traces have no line flush records".to_string())));
+    assert_eq!(writer.record(Op::Unpin, 0, 1), Err(SimError::Trace("DineroIV traces have no pin records".to_string())));
+    let mut writer = TraceWriter::binary(Vec::new());
+    assert!(writer.record(Op::Load, 0, 0x1_0000).is_err());
+    assert_eq!(writer.accesses(), 0);
+}